@@ -0,0 +1,218 @@
+//! User-defined command aliases from the config's `[alias]` table.
+//!
+//! With `up = "apply --filesets desktop --force"`, `janus up -v` runs
+//! `janus apply --filesets desktop --force -v`. The command line is
+//! rewritten before clap parses it, so an alias may expand to another
+//! alias, but a chain that leads back to an alias already expanded is an
+//! error. Built-in commands always win over an alias of the same name.
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+use crate::platform::Fs;
+
+/// Just the part of the config aliases need, so expanding one doesn't
+/// depend on the rest of the config being valid.
+#[derive(Deserialize)]
+struct AliasTable {
+    #[serde(default)]
+    alias: BTreeMap<String, String>,
+}
+
+/// Read the `[alias]` table from the config at `path`. A missing config has
+/// no aliases.
+pub fn load(path: &Path, fs: &impl Fs) -> Result<BTreeMap<String, String>> {
+    if !fs.exists(path) {
+        return Ok(BTreeMap::new());
+    }
+    let contents = fs
+        .read_to_string(path)
+        .with_context(|| format!("Failed to read config: {}", path.display()))?;
+    let table: AliasTable = toml::from_str(&contents)
+        .with_context(|| format!("Failed to parse [alias] in {}", path.display()))?;
+    Ok(table.alias)
+}
+
+/// Where the subcommand is in `args` (program name first): the first
+/// argument that isn't an option or the value of one of `value_flags`.
+pub fn command_position(args: &[OsString], value_flags: &[String]) -> Option<usize> {
+    let mut i = 1;
+    while let Some(arg) = args.get(i) {
+        let arg = arg.to_string_lossy();
+        if arg == "--" {
+            return args.get(i + 1).map(|_| i + 1);
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            return Some(i);
+        }
+        // `--config path` takes the next argument; `--config=path` doesn't
+        i += if value_flags.iter().any(|f| *f == arg) {
+            2
+        } else {
+            1
+        };
+    }
+    None
+}
+
+/// The `--config` path given in `args`, if any.
+pub fn config_flag(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let arg = arg.to_string_lossy();
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+/// Replace the alias at `args[position]` with its expansion, following
+/// aliases that expand to other aliases. Names that are built-in commands
+/// or not aliases are left for clap to handle.
+pub fn expand(
+    mut args: Vec<OsString>,
+    position: usize,
+    aliases: &BTreeMap<String, String>,
+    is_builtin: impl Fn(&str) -> bool,
+) -> Result<Vec<OsString>> {
+    let mut expanded: Vec<String> = Vec::new();
+    while let Some(name) = args.get(position).and_then(|a| a.to_str()) {
+        if is_builtin(name) {
+            break;
+        }
+        let Some(expansion) = aliases.get(name) else {
+            break;
+        };
+        let name = name.to_string();
+        if expanded.contains(&name) {
+            expanded.push(name);
+            bail!("Alias loop: {}", expanded.join(" -> "));
+        }
+        let words = split_words(expansion).with_context(|| format!("Invalid alias '{name}'"))?;
+        if words.is_empty() {
+            bail!("Alias '{name}' is empty");
+        }
+        debug!("Expanding alias '{name}' to: {expansion}");
+        expanded.push(name);
+        args.splice(position..=position, words.into_iter().map(OsString::from));
+    }
+    Ok(args)
+}
+
+/// Aliases that expand back to themselves, each as the chain that loops
+/// (e.g. `["up", "deploy-all", "up"]`), for `janus check`.
+pub fn loops(aliases: &BTreeMap<String, String>) -> Vec<Vec<String>> {
+    let mut found = Vec::new();
+    for start in aliases.keys() {
+        let mut chain = vec![start.clone()];
+        let mut current = start;
+        while let Some(next) = aliases
+            .get(current)
+            .and_then(|e| split_words(e).ok())
+            .and_then(|words| words.into_iter().next())
+        {
+            if chain.contains(&next) {
+                // Report each loop once, from its alphabetically first alias
+                if next == *start {
+                    chain.push(next);
+                    found.push(chain);
+                }
+                break;
+            }
+            let Some((key, _)) = aliases.get_key_value(&next) else {
+                break;
+            };
+            chain.push(next);
+            current = key;
+        }
+    }
+    found.retain(|chain| chain.iter().min() == Some(&chain[0]));
+    found
+}
+
+/// Split an alias into words like a shell would, keeping single- and
+/// double-quoted strings together.
+fn split_words(text: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' | '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some(ch) if ch == c => break,
+                        Some('\\') if c == '"' => word.extend(chars.next()),
+                        Some(ch) => word.push(ch),
+                        None => bail!("unterminated {c} quote"),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<OsString> {
+        line.split(' ').map(OsString::from).collect()
+    }
+
+    fn aliases(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn expands_after_global_options() {
+        let value_flags = vec!["--config".to_string()];
+        let line = args("janus --config /tmp/c.toml -v up --dry-run");
+        let position = command_position(&line, &value_flags).unwrap();
+        assert_eq!(position, 4);
+        assert_eq!(config_flag(&line), Some(PathBuf::from("/tmp/c.toml")));
+
+        let aliases = aliases(&[
+            ("up", "ship --force"),
+            ("ship", "apply --filesets 'my desktop'"),
+            ("status", "deploy --all"),
+        ]);
+        let expanded = expand(line, position, &aliases, |n| n == "apply").unwrap();
+        let mut expected = args("janus --config /tmp/c.toml -v apply --filesets");
+        expected.extend(["my desktop", "--force", "--dry-run"].map(OsString::from));
+        assert_eq!(expanded, expected);
+
+        // Built-ins are never replaced
+        let line = args("janus status");
+        let expanded = expand(line.clone(), 1, &aliases, |n| n == "status").unwrap();
+        assert_eq!(expanded, line);
+    }
+
+    #[test]
+    fn loops_are_rejected() {
+        let aliases = aliases(&[("a", "b --x"), ("b", "a"), ("c", "a")]);
+        let err = expand(args("janus c"), 1, &aliases, |_| false).unwrap_err();
+        assert_eq!(format!("{err:#}"), "Alias loop: c -> a -> b -> a");
+        assert_eq!(
+            loops(&aliases),
+            vec![vec!["a".to_string(), "b".to_string(), "a".to_string()]]
+        );
+    }
+}
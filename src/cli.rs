@@ -5,6 +5,14 @@
 
 use clap::{Parser, Subcommand};
 use clap_complete::Shell;
+
+use janus::ops::cat::CatStage;
+use janus::ops::config::SchemaKind;
+use janus::ops::diff::Stage;
+use janus::ops::import::ByteSize;
+use janus::ops::migrate::MigrateFrom;
+use janus::ops::shell_init::InitShell;
+use janus::plan::Format as PlanFormat;
 use std::path::PathBuf;
 
 /// Top-level CLI arguments shared across all subcommands.
@@ -31,6 +39,49 @@ pub struct Cli {
     #[arg(long, global = true, default_value = "5")]
     pub lock_timeout: u64,
 
+    /// Write diff and status output straight to stdout instead of a pager
+    #[arg(long, global = true)]
+    pub no_pager: bool,
+
+    /// Skip the `confirm_threshold` prompt for commands touching many files
+    #[arg(short, long, global = true)]
+    pub yes: bool,
+
+    /// Refuse every filesystem change (also set by JANUS_READONLY=1), for
+    /// inspecting untrusted dotfiles
+    #[arg(long, global = true)]
+    pub readonly: bool,
+
+    /// Render secrets as placeholders instead of resolving them (offline work)
+    #[arg(long, global = true)]
+    pub skip_secrets: bool,
+
+    /// Also write full trace-level logs to this file
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Print how long each phase took (vars, secrets, render, writes)
+    #[arg(long, global = true)]
+    pub timings: bool,
+
+    /// Never draw progress bars (they're only drawn on a terminal anyway)
+    #[arg(long, global = true)]
+    pub no_progress: bool,
+
+    /// Make templates' now() report this time (epoch seconds or RFC 3339)
+    /// instead of the current one; SOURCE_DATE_EPOCH does the same
+    #[arg(long, global = true, value_name = "TIME", value_parser = janus::template::parse_time)]
+    pub frozen_time: Option<i64>,
+
+    /// Let deploy write under sensitive paths such as ~/.ssh and ~/.gnupg
+    #[arg(long, global = true)]
+    pub allow_sensitive_targets: bool,
+
+    /// How --dry-run reports what it would do: log lines, or a JSON plan on
+    /// stdout
+    #[arg(long, global = true, value_enum, default_value_t = PlanFormat::Text)]
+    pub format: PlanFormat,
+
     #[command(subcommand)]
     pub command: Command,
 }
@@ -57,6 +108,19 @@ pub enum Command {
         /// Filesets to operate on (comma-separated)
         #[arg(long, value_delimiter = ',')]
         filesets: Vec<String>,
+
+        /// Render one file without writing it and report where each
+        /// expression's value came from (to stderr)
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["files", "all", "filesets"])]
+        explain: Option<String>,
+
+        /// Stage the files that generated cleanly
+        #[arg(long, conflicts_with = "explain")]
+        and_stage: bool,
+
+        /// Also deploy the files that staged cleanly
+        #[arg(long, requires = "and_stage")]
+        and_deploy: bool,
     },
 
     /// Copy generated files into .staged/
@@ -86,6 +150,15 @@ pub enum Command {
         #[arg(long)]
         force: bool,
 
+        /// Show a diff and ask how to resolve each existing non-janus file
+        #[arg(short, long, conflicts_with = "force")]
+        interactive: bool,
+
+        /// Deploy the remaining files when one fails, and report every
+        /// failure at the end
+        #[arg(long)]
+        keep_going: bool,
+
         /// Filesets to operate on (comma-separated)
         #[arg(long, value_delimiter = ',')]
         filesets: Vec<String>,
@@ -103,6 +176,62 @@ pub enum Command {
         /// Filesets to operate on (comma-separated)
         #[arg(long, value_delimiter = ',')]
         filesets: Vec<String>,
+
+        /// Old side: source, generated, staged, git:<rev> (the source at
+        /// a revision, rendered), or remote:<host> (the target over ssh)
+        #[arg(long, default_value = "generated")]
+        from: Stage,
+
+        /// New side: source, generated, staged, git:<rev>, or remote:<host>
+        #[arg(long, default_value = "staged")]
+        to: Stage,
+
+        /// Compare against what's deployed on another host (user@host);
+        /// shorthand for `--to remote:<host>`
+        #[arg(long, conflicts_with = "to")]
+        remote: Option<String>,
+
+        /// Print resolved secret values instead of masking them
+        #[arg(long)]
+        show_secrets: bool,
+    },
+
+    /// Print a managed file as one pipeline stage has it
+    Cat {
+        /// The file's src, its target, or one of its pipeline copies
+        file: String,
+
+        /// Which copy to print
+        #[arg(long, value_enum, default_value_t = CatStage::Generated)]
+        stage: CatStage,
+
+        /// Print resolved secret values instead of masking them
+        #[arg(long)]
+        show_secrets: bool,
+    },
+
+    /// Render files into a directory or tarball for sharing, optionally
+    /// with secrets and machine-specific paths redacted
+    Export {
+        /// Files/globs to export
+        files: Vec<String>,
+
+        /// Export all configured files
+        #[arg(long)]
+        all: bool,
+
+        /// Filesets to export (comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        filesets: Vec<String>,
+
+        /// Directory to write to, or a `.tar`, `.tar.gz`, or `.tgz` to pack
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+
+        /// Render secrets as `<secret NAME>` without resolving them, and
+        /// replace the home directory and hostname
+        #[arg(long)]
+        redact_secrets: bool,
     },
 
     /// Remove generated files or clean up orphans
@@ -114,13 +243,26 @@ pub enum Command {
         /// Remove orphan files from .generated/ and .staged/
         #[arg(long)]
         orphans: bool,
+
+        /// Only clean these filesets' generated and staged files
+        /// (comma-separated)
+        #[arg(long, value_delimiter = ',', requires = "generated")]
+        filesets: Vec<String>,
     },
 
     /// Import existing config files into management
     Import {
-        /// Path to import (file or directory)
+        /// Path to import (file or directory), or an http(s) URL to download
         path: String,
 
+        /// Source path for a downloaded URL (default: the URL's file name)
+        #[arg(long = "as", value_name = "SRC")]
+        as_src: Option<String>,
+
+        /// Expected SHA-256 of a downloaded URL; the import fails on mismatch
+        #[arg(long, value_name = "HEX")]
+        sha256: Option<String>,
+
         /// Skip interactive prompts, import all files
         #[arg(long)]
         all: bool,
@@ -128,6 +270,58 @@ pub enum Command {
         /// Maximum directory traversal depth
         #[arg(long, default_value = "10")]
         max_depth: usize,
+
+        /// Don't follow symlinks while walking a directory
+        #[arg(long)]
+        no_follow_links: bool,
+
+        /// Offer files .gitignore rules ignore (and files under .git/) too
+        #[arg(long)]
+        no_gitignore: bool,
+
+        /// Skip files larger than this (e.g. 512K, 4M)
+        #[arg(long, value_name = "SIZE", default_value = "1M")]
+        max_file_size: ByteSize,
+
+        /// Ask before importing more than this in total
+        #[arg(long, value_name = "SIZE", default_value = "20M")]
+        max_total_size: ByteSize,
+
+        /// Ask before importing more files than this
+        #[arg(long, value_name = "N", default_value = "200")]
+        max_files: usize,
+
+        /// Offer files again that were ignored or unimported with --tombstone
+        #[arg(long)]
+        forget: bool,
+    },
+
+    /// Create a new managed file from scratch
+    New {
+        /// Path of the new source file within the dotfiles directory
+        src: String,
+
+        /// Deploy target (default: ~/.config/{src})
+        #[arg(long)]
+        target: Option<String>,
+
+        /// Fill the new file from stdin instead of leaving it empty
+        #[arg(long)]
+        stdin: bool,
+
+        /// Open the new file in $VISUAL or $EDITOR
+        #[arg(long)]
+        edit: bool,
+    },
+
+    /// Bring dotfiles over from another manager (stow, chezmoi, yadm)
+    Migrate {
+        /// Manager the dotfiles are currently in
+        #[arg(long, value_enum)]
+        from: MigrateFrom,
+
+        /// Its source: the stow directory, chezmoi source directory, or yadm repo
+        path: String,
     },
 
     /// Run generate + stage + deploy in one shot
@@ -143,9 +337,17 @@ pub enum Command {
         #[arg(long)]
         force: bool,
 
+        /// Show a diff and ask how to resolve each existing non-janus file
+        #[arg(short, long, conflicts_with = "force")]
+        interactive: bool,
+
         /// Filesets to operate on (comma-separated)
         #[arg(long, value_delimiter = ',')]
         filesets: Vec<String>,
+
+        /// Also write the closing summary to this file as JSON
+        #[arg(long, value_name = "PATH")]
+        report: Option<PathBuf>,
     },
 
     /// Remove deployed symlinks
@@ -166,6 +368,25 @@ pub enum Command {
         filesets: Vec<String>,
     },
 
+    /// Park files: undeploy them and set `enabled = false`, so every
+    /// command skips them until `janus enable`
+    Disable {
+        /// Source files/globs to disable
+        #[arg(required = true)]
+        files: Vec<String>,
+
+        /// Remove the deployed symlink without leaving a copy of the file
+        #[arg(long)]
+        remove_file: bool,
+    },
+
+    /// Clear `enabled = false` from files and apply them again
+    Enable {
+        /// Source files/globs to enable
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+
     /// Fully reverse an import: undeploy, remove config entry, clean up source files
     Unimport {
         /// Source files to unimport (matched against src paths in config)
@@ -175,6 +396,15 @@ pub enum Command {
         #[arg(long)]
         remove_file: bool,
 
+        /// Remember the target so `janus import` doesn't offer it again
+        #[arg(long)]
+        tombstone: bool,
+
+        /// Move the source, generated, and staged copies and the config entry
+        /// to `archive/` in the dotfiles directory instead of deleting them
+        #[arg(long)]
+        archive: bool,
+
         /// Filesets to operate on (comma-separated)
         #[arg(long, value_delimiter = ',')]
         filesets: Vec<String>,
@@ -192,6 +422,14 @@ pub enum Command {
         /// Filesets to operate on (comma-separated)
         #[arg(long, value_delimiter = ',')]
         filesets: Vec<String>,
+
+        /// Print resolved secret values instead of masking them
+        #[arg(long)]
+        show_secrets: bool,
+
+        /// Ask again about hunks skipped at earlier syncs
+        #[arg(long)]
+        reset_decisions: bool,
     },
 
     /// Generate shell completions
@@ -200,6 +438,13 @@ pub enum Command {
         shell: Shell,
     },
 
+    /// Print shell functions to eval at shell start (configured by `[shell]`)
+    ShellInit {
+        /// Shell to print functions for
+        #[arg(value_enum)]
+        shell: InitShell,
+    },
+
     /// Show pipeline status for managed files
     Status {
         /// Files/globs to check
@@ -221,8 +466,339 @@ pub enum Command {
         #[arg(long)]
         undeployed: bool,
 
+        /// Only show stale files: source edited since the last generate, or
+        /// staged drift older than `stale_days`
+        #[arg(long)]
+        stale: bool,
+
+        /// Show columns: pipeline copies, changed lines, source and staged
+        /// ages, and target
+        #[arg(short, long)]
+        long: bool,
+
         /// Filesets to operate on (comma-separated)
         #[arg(long, value_delimiter = ',')]
         filesets: Vec<String>,
     },
+
+    /// Manage filesets in the config
+    Fileset {
+        #[command(subcommand)]
+        action: FilesetCommand,
+    },
+
+    /// Check the config for problems without changing anything
+    Check,
+
+    /// Revert the most recent command that changed files
+    Undo {
+        /// Restore paths even if they changed after that command ran
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// List or restore backups deploy made under `[backup] dir`
+    Backups {
+        #[command(subcommand)]
+        action: BackupsCommand,
+    },
+
+    /// Bring back sources archived with `unimport --archive`
+    RestoreArchived {
+        /// Source paths to restore, as they were in the config
+        #[arg(required = true)]
+        srcs: Vec<String>,
+    },
+
+    /// List or restore files janus moved to the trash (`use_trash = true`)
+    Trash {
+        #[command(subcommand)]
+        action: TrashCommand,
+    },
+
+    /// Restore pipeline files janus expects to look a certain way
+    Repair {
+        /// Files/globs to repair
+        files: Vec<String>,
+
+        /// Process all configured files
+        #[arg(long)]
+        all: bool,
+
+        /// Filesets to operate on (comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        filesets: Vec<String>,
+
+        /// Restore the permissions each file was staged with
+        #[arg(long)]
+        modes: bool,
+    },
+
+    /// Show which managed file a path belongs to
+    Which {
+        /// A target, a symlink to one, or a source, generated, or staged copy
+        path: String,
+    },
+
+    /// Report repo metrics: files per fileset and mode, secret usage,
+    /// generated size, undeployed files, and the largest diffs
+    Stats,
+
+    /// Run the `[[tests]]` template checks against their fixture vars
+    Test {
+        /// Test names to run (default: all)
+        names: Vec<String>,
+    },
+
+    /// Repair the state file
+    State {
+        #[command(subcommand)]
+        action: StateCommand,
+    },
+
+    /// Inspect template variables
+    Vars {
+        #[command(subcommand)]
+        action: VarsCommand,
+    },
+
+    /// Work with template secrets
+    Secrets {
+        #[command(subcommand)]
+        action: SecretsCommand,
+    },
+
+    /// Inspect the config file format, or edit the config
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+}
+
+/// Actions for `janus fileset`.
+#[derive(Subcommand)]
+pub enum FilesetCommand {
+    /// Create a new fileset
+    Create {
+        /// Fileset name
+        name: String,
+
+        /// Glob patterns matching `src` paths
+        #[arg(required = true)]
+        patterns: Vec<String>,
+    },
+
+    /// Add a pattern to an existing fileset
+    Add {
+        /// Fileset name
+        name: String,
+
+        /// Glob pattern matching `src` paths
+        pattern: String,
+    },
+
+    /// Remove a fileset
+    Rm {
+        /// Fileset name
+        name: String,
+    },
+
+    /// List filesets and their patterns
+    List {
+        /// Also show the files each fileset resolves to
+        #[arg(long)]
+        files: bool,
+    },
+}
+
+/// Actions for `janus vars`.
+#[derive(Subcommand)]
+pub enum VarsCommand {
+    /// List every template variable with where it's used and defined, as a
+    /// Markdown table, flagging variables a template uses but no vars file
+    /// defines
+    Docs,
+    /// Probe monitors, GPU vendor, battery, and dark/light preference, and
+    /// write them to a vars file, keeping any other keys in it
+    Detect {
+        /// Vars file to write, relative to dotfiles_dir [default:
+        /// vars.detected.toml]
+        #[arg(long)]
+        output: Option<String>,
+    },
+}
+
+/// Actions for `janus state`.
+#[derive(Subcommand)]
+pub enum StateCommand {
+    /// Record targets that are already janus symlinks (e.g. after losing
+    /// the state file) as deployed
+    ImportExisting,
+
+    /// Replace many ignored paths under one directory with a single
+    /// `dir/**` pattern
+    CompactIgnored {
+        /// Collapse directories directly holding at least this many
+        /// ignored paths
+        #[arg(long, default_value_t = 3)]
+        min: usize,
+    },
+}
+
+/// Actions for `janus backups`.
+#[derive(Subcommand)]
+pub enum BackupsCommand {
+    /// List recorded backups, oldest first
+    List,
+
+    /// Move backups back to the paths they were taken from
+    Restore {
+        /// Target paths (as shown by `janus backups list`) or source paths
+        #[arg(required = true)]
+        paths: Vec<String>,
+
+        /// Restore the first backup instead of the newest
+        #[arg(long)]
+        original: bool,
+    },
+}
+
+/// Actions for `janus trash`.
+#[derive(Subcommand)]
+pub enum TrashCommand {
+    /// List files janus moved to the trash
+    List,
+
+    /// Move trashed files back to where they were
+    Restore {
+        /// Original paths, as shown by `janus trash list` (relative paths
+        /// are taken from the dotfiles directory)
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SecretsCommand {
+    /// Regenerate, restage, and redeploy every file using a secret, after
+    /// rotating it in the secret manager
+    Rotate {
+        /// Secret name, as used in templates
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Print a JSON Schema for editors to validate and autocomplete with
+    Schema {
+        /// File the schema describes
+        #[arg(value_enum, default_value_t = SchemaKind::Config)]
+        kind: SchemaKind,
+    },
+
+    /// Print a config value by dotted key (e.g. `files.hypr/hypr.conf.target`)
+    Get {
+        /// Dotted key; `[[files]]` entries are named by src or index
+        key: String,
+    },
+
+    /// Set a config value, keeping the file's comments and formatting
+    Set {
+        /// Dotted key; `[[files]]` entries are named by src or index
+        key: String,
+
+        /// A TOML value (`12`, `true`, `["a", "b"]`); anything else is taken
+        /// as a string
+        value: String,
+    },
+
+    /// Remove a config value
+    Unset {
+        /// Dotted key; `[[files]]` entries are named by src or index
+        key: String,
+    },
+
+    /// Add a `[[files]]` entry
+    AddFile {
+        /// Path relative to the dotfiles directory
+        src: String,
+
+        /// Deploy target (default: ~/.config/<src>)
+        #[arg(long)]
+        target: Option<String>,
+    },
+}
+
+impl Command {
+    /// The subcommand name as typed on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Init { .. } => "init",
+            Command::Generate { .. } => "generate",
+            Command::Stage { .. } => "stage",
+            Command::Deploy { .. } => "deploy",
+            Command::Diff { .. } => "diff",
+            Command::Clean { .. } => "clean",
+            Command::Import { .. } => "import",
+            Command::New { .. } => "new",
+            Command::Migrate { .. } => "migrate",
+            Command::Apply { .. } => "apply",
+            Command::Undeploy { .. } => "undeploy",
+            Command::Disable { .. } => "disable",
+            Command::Enable { .. } => "enable",
+            Command::Unimport { .. } => "unimport",
+            Command::Sync { .. } => "sync",
+            Command::Completions { .. } => "completions",
+            Command::ShellInit { .. } => "shell-init",
+            Command::Status { .. } => "status",
+            Command::Fileset { .. } => "fileset",
+            Command::Check => "check",
+            Command::Undo { .. } => "undo",
+            Command::Backups { .. } => "backups",
+            Command::RestoreArchived { .. } => "restore-archived",
+            Command::Trash { .. } => "trash",
+            Command::Repair { .. } => "repair",
+            Command::Which { .. } => "which",
+            Command::Cat { .. } => "cat",
+            Command::Export { .. } => "export",
+            Command::Stats => "stats",
+            Command::Test { .. } => "test",
+            Command::State { .. } => "state",
+            Command::Vars { .. } => "vars",
+            Command::Secrets { .. } => "secrets",
+            Command::Config { .. } => "config",
+        }
+    }
+
+    /// Whether this command's filesystem changes are recorded for `janus
+    /// undo`. Read-only commands never change anything, so they'd record
+    /// nothing anyway; undo itself must not replace the record it consumes.
+    pub fn records_undo(&self) -> bool {
+        !matches!(self, Command::Undo { .. }) && !self.is_read_only()
+    }
+
+    /// Whether this command only reads the dotfiles and state, so it
+    /// leaves an interrupted deploy's journal for the next one that writes.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Command::Diff { .. }
+                | Command::Cat { .. }
+                | Command::Config {
+                    action: ConfigCommand::Get { .. }
+                }
+                | Command::Status { .. }
+                | Command::Which { .. }
+                | Command::Backups {
+                    action: BackupsCommand::List
+                }
+                | Command::Stats
+                | Command::ShellInit { .. }
+                | Command::Vars {
+                    action: VarsCommand::Docs
+                }
+                | Command::Test { .. }
+        )
+    }
 }
@@ -3,27 +3,50 @@
 //! The [`Config`] struct represents the top-level config, and [`FileEntry`]
 //! represents a single managed file with its source path, target path,
 //! template flag, and optional per-file variable overrides.
+//!
+//! `dotfiles_dir`, `dotfiles_dirs`, `generated_dir`, `staged_dir`, and file
+//! targets may use `{home}`, `{hostname}`, `{xdg_config}`, and
+//! `{xdg_state}`, filled in for this machine at load.
 
 use anyhow::{Context, Result, bail};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::{Path, PathBuf};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
 use strsim::jaro_winkler;
 
 use crate::paths::expand_tilde;
-use crate::platform::Fs;
+use crate::platform::{Fs, WalkOptions};
 
 /// Top-level janus configuration, loaded from a TOML file.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Config {
-    /// Path to the dotfiles directory (may contain `~`).
+    /// Path to the dotfiles directory (may contain `~`, `{home}`,
+    /// `{hostname}`, or `{xdg_config}`).
     pub dotfiles_dir: String,
+    /// Extra source layers beneath `dotfiles_dir`, lowest priority first
+    /// (may contain `~`). Each file's source comes from the highest layer
+    /// that has it; `dotfiles_dir` is always the top layer and the only one
+    /// holding `.generated/`, `.staged/`, and state.
+    #[serde(default)]
+    pub dotfiles_dirs: Vec<String>,
+    /// Where generate writes its output, relative to `dotfiles_dir` unless
+    /// absolute (e.g. `{xdg_state}/janus/generated`). Defaults to
+    /// `.generated`.
+    pub generated_dir: Option<String>,
+    /// Where stage copies generated output and deployed symlinks point,
+    /// like `generated_dir`. Defaults to `.staged`.
+    pub staged_dir: Option<String>,
     /// Global template variable files, relative to `dotfiles_dir`.
     #[serde(default)]
     pub vars: Vec<String>,
     /// Global secret config files, relative to `dotfiles_dir`.
     #[serde(default)]
     pub secrets: Vec<String>,
+    /// Rhai script computing derived vars, relative to `dotfiles_dir`.
+    /// Runs after the global `vars` files; see [`crate::vars_script`].
+    pub vars_script: Option<String>,
     /// Managed file entries.
     #[serde(default)]
     pub files: Vec<FileEntry>,
@@ -34,10 +57,255 @@ pub struct Config {
     /// Named groups of file patterns for batch operations.
     #[serde(default)]
     pub filesets: HashMap<String, FilesetEntry>,
+    /// Command to run when a janus command fails (for unattended runs).
+    pub on_failure: Option<OnFailureConfig>,
+    /// Mutating commands that would touch more files than this ask for
+    /// confirmation first (or need `--yes`). Unset disables the check.
+    pub confirm_threshold: Option<usize>,
+    /// Days staged may differ from generated without a stage or sync before
+    /// `janus status` flags the file as stale. Defaults to 7.
+    pub stale_days: Option<u64>,
+    /// Backups deploy keeps per target after the first: the original stays
+    /// at `<name>.janus.bak` and later ones are numbered `.janus.bak.1`,
+    /// `.2`, and so on, oldest deleted first. Defaults to 5; at least 1.
+    pub keep_backups: Option<usize>,
+    /// Where deploy puts backups.
+    #[serde(default)]
+    pub backup: BackupConfig,
+    /// Move files removed by `clean` and `unimport` to the trash instead of
+    /// deleting them; `janus trash` lists and restores them.
+    #[serde(default)]
+    pub use_trash: bool,
+    /// Terminal output settings.
+    #[serde(default)]
+    pub ui: UiConfig,
+    /// Log file settings.
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    /// What `janus shell-init` defines.
+    #[serde(default)]
+    pub shell: ShellConfig,
+    /// Command aliases, like git's: `up = "apply --filesets desktop"` makes
+    /// `janus up` run that. Built-in commands take precedence.
+    #[serde(default)]
+    pub alias: BTreeMap<String, String>,
+    /// Host groups: label -> hostnames. Labels (and the hostnames listed
+    /// here) can key a file's `targets`.
+    #[serde(default)]
+    pub host_groups: BTreeMap<String, Vec<String>>,
+    /// Whether deploy may create missing parent directories of targets.
+    /// With `false`, a target whose directory doesn't exist (often a typo)
+    /// fails to deploy instead. Entries can override it.
+    #[serde(default = "default_true")]
+    pub create_parents: bool,
+    /// Directories whose files are also deployed elsewhere: directory ->
+    /// extra directories (all may contain `~`). A target under a key is
+    /// also linked at the same relative path under each of its values,
+    /// e.g. into a Flatpak app's `~/.var/app/<id>/config`.
+    #[serde(default)]
+    pub path_maps: BTreeMap<String, Vec<String>>,
+    /// How vars layers combine: `shallow` (the default) replaces a whole
+    /// top-level value, `deep` merges tables key by key.
+    #[serde(default)]
+    pub merge_strategy: MergeStrategy,
+    /// What rendering does with a variable no vars layer defines: `error`
+    /// (the default), `empty`, or `warn`. Entries can override it.
+    #[serde(default)]
+    pub undefined_vars: UndefinedVars,
+    /// Fallback values for vars, beneath every vars file and the vars
+    /// script, so an optional var can go unset on some machines.
+    #[serde(default)]
+    #[schemars(with = "HashMap<String, serde_json::Value>")]
+    pub var_defaults: HashMap<String, toml::Value>,
+    /// Directory, relative to `dotfiles_dir`, whose files are managed by
+    /// their names rather than `[[files]]` entries (see
+    /// `Config::add_attribute_entries`).
+    pub attributes_dir: Option<String>,
+    /// Let templates run commands with `has_command`, `pkg_installed`, and
+    /// `command_output` (see [`crate::template`]).
+    #[serde(default)]
+    pub allow_exec: bool,
+    /// Where targets may go (see [`crate::policy`]).
+    #[serde(default)]
+    pub policy: PolicyConfig,
+    /// Whether deploy may write under sensitive paths such as `~/.ssh`.
+    /// Set from `--allow-sensitive-targets`, never from the config file.
+    #[serde(skip)]
+    pub allow_sensitive_targets: bool,
+    /// Whether this run may write nothing, so best-effort writes like the
+    /// status cache are skipped. Set from `--readonly` or `JANUS_READONLY`.
+    #[serde(skip)]
+    pub readonly: bool,
+    /// Keep janus's own files out of `dotfiles_dir`: state, the lock, the
+    /// journal, the undo record, and the trash log go under
+    /// `$XDG_STATE_HOME/janus/`, and the status cache under
+    /// `$XDG_CACHE_HOME/janus/` (see [`Config::state_dir`]).
+    #[serde(default)]
+    pub xdg_state: bool,
+    /// Stage files as hard links into a content-addressed store under
+    /// the staged directory, so identical renders share one copy (see
+    /// [`crate::store`]).
+    #[serde(default)]
+    pub staged_store: bool,
+    /// Keep the generated directory in a single pack file and index beside
+    /// it rather than a tree of small files (see
+    /// [`crate::platform::PackedFs`]).
+    #[serde(default)]
+    pub packed_store: bool,
+    /// Template tests run by `janus test`.
+    #[serde(default)]
+    pub tests: Vec<TemplateTest>,
+}
+
+/// A `[[tests]]` entry: render a managed template with fixture vars and
+/// check the output.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct TemplateTest {
+    /// Name in the report. Defaults to `src`.
+    pub name: Option<String>,
+    /// The `[[files]]` entry whose source template is rendered.
+    pub src: String,
+    /// Vars file, relative to `dotfiles_dir`, that replaces every other
+    /// vars source for this render. Secrets aren't looked up, so give them
+    /// values here too.
+    pub vars: String,
+    /// Golden file, relative to `dotfiles_dir`, the output must equal.
+    pub expected: Option<String>,
+    /// Regexes the output must each match somewhere.
+    #[serde(default)]
+    pub matches: Vec<String>,
+}
+
+impl TemplateTest {
+    /// The name shown in reports.
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.src)
+    }
+}
+
+/// The `[ui]` block: terminal output settings.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct UiConfig {
+    /// Pager for diff and status output. Falls back to `$PAGER`, then
+    /// `less -R`; `""` or `"cat"` disables paging.
+    pub pager: Option<String>,
+}
+
+/// An entry's `reload` setting, as a bare command or a table.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ReloadHook {
+    /// `auto`, `never`, or a shell command.
+    Command(String),
+    Table {
+        /// `auto`, `never`, or a shell command.
+        command: String,
+        /// Run the hook once at the end of the run however many deployed
+        /// files trigger it, after the other reloads. For `auto`, the
+        /// recipe runs once for the last matching target.
+        #[serde(default)]
+        debounce: bool,
+    },
+}
+
+impl ReloadHook {
+    /// The command (or `auto`/`never`).
+    pub fn command(&self) -> &str {
+        match self {
+            Self::Command(command) | Self::Table { command, .. } => command,
+        }
+    }
+
+    /// Whether the hook is debounced.
+    pub fn debounce(&self) -> bool {
+        matches!(self, Self::Table { debounce: true, .. })
+    }
+}
+
+impl From<&str> for ReloadHook {
+    fn from(command: &str) -> Self {
+        Self::Command(command.to_string())
+    }
+}
+
+/// The `[backup]` block: where deploy puts backups.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct BackupConfig {
+    /// Directory for backups (may start with `~`). Each goes to the
+    /// target's absolute path mirrored under it, suffixed with the time it
+    /// was made, and is recorded in state for `janus backups restore`.
+    /// Unset puts backups next to the target as `<name>.janus.bak`.
+    pub dir: Option<String>,
+}
+
+/// The `[logging]` block: a log file written alongside console output.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct LoggingConfig {
+    /// File to append logs to (may start with `~`). `--log-file` overrides it.
+    pub file: Option<String>,
+    /// Level for the log file (`trace`, `debug`, `info`, ...). Defaults to
+    /// `trace`, independent of `-v`/`-q`.
+    pub level: Option<String>,
+}
+
+/// The `[shell]` block: the functions `janus shell-init` emits.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct ShellConfig {
+    /// Name of the function that opens a managed source in `$EDITOR`,
+    /// completing over `src` paths. `""` leaves it out.
+    #[serde(default = "default_edit_function")]
+    pub edit: String,
+    /// Name of the function that changes to `dotfiles_dir`. `""` leaves it
+    /// out.
+    #[serde(default = "default_cd_function")]
+    pub cd: String,
+    /// Run `janus status --only-diffs` when the shell starts if the status
+    /// cache shows drift.
+    #[serde(default = "default_true")]
+    pub status_on_start: bool,
+}
+
+impl Default for ShellConfig {
+    fn default() -> Self {
+        Self {
+            edit: default_edit_function(),
+            cd: default_cd_function(),
+            status_on_start: true,
+        }
+    }
+}
+
+fn default_edit_function() -> String {
+    "jedit".to_string()
+}
+
+fn default_cd_function() -> String {
+    "jcd".to_string()
+}
+
+/// The `[policy]` block: limits on where targets may go.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct PolicyConfig {
+    /// Directories (may start with `~`) every target must be under. Empty
+    /// allows any target.
+    #[serde(default)]
+    pub allowed_targets: Vec<String>,
+    /// Paths, beyond the built-in ones like `~/.ssh`, that deploying under
+    /// needs `--allow-sensitive-targets`.
+    #[serde(default)]
+    pub sensitive_targets: Vec<String>,
+}
+
+/// The `[on_failure]` block: a hook run when a command fails.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct OnFailureConfig {
+    /// Shell command, rendered as a Tera template and run via `sh -c`.
+    /// See [`crate::on_failure`] for the available context.
+    pub command: String,
 }
 
 /// A named fileset: file patterns with optional vars and secrets overrides.
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct FilesetEntry {
     /// Glob patterns that select files in this set.
     pub patterns: Vec<String>,
@@ -49,35 +317,180 @@ pub struct FilesetEntry {
     pub secrets: Vec<String>,
 }
 
+/// Prefix selecting a file entry by its `name` on the command line
+/// (`janus deploy name:work-gitconfig`).
+pub const NAME_SELECTOR: &str = "name:";
+
 /// A single managed file entry in the janus config.
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct FileEntry {
     /// Relative path within the dotfiles directory (e.g. `hypr/hypr.conf`).
     pub src: String,
-    /// Deployment target path (may contain `~`). Defaults to `~/.config/{src}`.
+    /// Stable name for the entry, unique in the config. Keys its deploy
+    /// state instead of `src` and selects it as `name:<name>` on the
+    /// command line, so entries sharing a `src` can be told apart.
+    pub name: Option<String>,
+    /// Deployment target path (may contain `~`, `{home}`, `{hostname}`, or
+    /// `{xdg_config}`). Defaults to `~/.config/{src}`.
     pub target: Option<String>,
+    /// Per-machine targets keyed by hostname, host group label, or
+    /// `default`. Mutually exclusive with `target`.
+    #[serde(default)]
+    pub targets: BTreeMap<String, String>,
+    /// The entry of `targets` that applies to this machine, picked at load.
+    #[serde(skip)]
+    pub host_target: Option<String>,
     /// Whether to render this file as a Tera template. Defaults to `true`.
     #[serde(default = "default_true")]
     pub template: bool,
+    /// Commands the output is piped through, in order, before generate
+    /// writes it to `.generated/` (e.g. `["jq ."]`).
+    #[serde(default)]
+    pub postprocess: Vec<String>,
     /// Per-file variable files that override globals, relative to `dotfiles_dir`.
     #[serde(default)]
     pub vars: Vec<String>,
     /// Per-file secret config files that override globals, relative to `dotfiles_dir`.
     #[serde(default)]
     pub secrets: Vec<String>,
+    /// Placeholders generate replaces with secret values in a file that's
+    /// otherwise copied verbatim (`template = false`).
+    #[serde(default)]
+    pub inject: Vec<Injection>,
+    /// Line endings generate writes: `lf`, `crlf`, or `preserve` (the
+    /// default) to keep whatever the source and template produced.
+    #[serde(default)]
+    pub newline: Newline,
+    /// Character encoding generate writes: `utf8` (the default) or
+    /// `latin1`. Sources are always UTF-8.
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// Permissions for the generated and staged copies (e.g. `0o600`).
+    /// Defaults to the source file's.
+    pub mode: Option<u32>,
     /// Whether to symlink directly from dotfiles source (skip generate/stage).
     #[serde(default)]
     pub direct: bool,
+    /// For a direct file, a template in the dotfiles directory whose managed
+    /// regions `generate` renders into the matching regions of the source,
+    /// in place. Everything outside the regions is left alone.
+    pub regions_template: Option<String>,
     /// Whether to exclude this file when `--all` or `default_targets = "all"` is used.
     #[serde(default)]
     pub exclude_from_all: bool,
+    /// Whether janus manages this file at all. Disabled entries are skipped
+    /// by every pipeline command, even when named explicitly. Defaults to
+    /// `true`; `janus disable` and `janus enable` flip it.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Whether this file is a systemd unit; deploy and undeploy then run
+    /// `systemctl daemon-reload`.
+    #[serde(default)]
+    pub systemd_unit: bool,
+    /// systemd units to `try-restart` after this file is deployed.
+    #[serde(default)]
+    pub reload_units: Vec<String>,
+    /// Whether deploy may create the target's missing parent directories.
+    /// Overrides the global `create_parents`.
+    pub create_parents: Option<bool>,
+    /// What to run after deploying so the program picks up the change:
+    /// `auto` for the built-in recipe matching the target, `never`, or a
+    /// shell command. A table `{ command = "...", debounce = true }` also
+    /// runs it only once per run, after every other reload.
+    pub reload: Option<ReloadHook>,
+    /// Sources (or globs) of other managed files this one needs deployed
+    /// too, e.g. a shared colors file. `apply` on a selection including
+    /// this file brings them in.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Overrides the global `undefined_vars` for this file.
+    pub undefined_vars: Option<UndefinedVars>,
+}
+
+/// How a later vars layer combines with an earlier one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeStrategy {
+    /// A later top-level key replaces the earlier value outright.
+    #[default]
+    Shallow,
+    /// Tables present in both layers are merged recursively; anything else
+    /// is replaced.
+    Deep,
+}
+
+/// What rendering does with a template variable that isn't defined.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum UndefinedVars {
+    /// Fail the render.
+    #[default]
+    Error,
+    /// Render it as an empty string.
+    Empty,
+    /// Render it as an empty string and log a warning.
+    Warn,
+}
+
+/// Line endings for a file's generated output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Newline {
+    /// Leave line endings as rendered.
+    #[default]
+    Preserve,
+    /// `\n`.
+    Lf,
+    /// `\r\n`.
+    Crlf,
+}
+
+/// Character encoding for a file's generated output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// ISO-8859-1: one byte per character, U+0000 to U+00FF only.
+    Latin1,
+}
+
+/// A placeholder in a non-template file and the secret that replaces it.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct Injection {
+    /// Exact bytes to replace wherever they appear (e.g. `@@TOKEN@@`).
+    pub placeholder: String,
+    /// Name of a secret from the secrets files that apply to the entry.
+    pub secret: String,
 }
 
 impl FileEntry {
-    /// Return the target path string, defaulting to `~/.config/{src}` when unset.
+    /// Whether generate rewrites this file's line endings or encoding, so a
+    /// plain copy won't do.
+    pub fn converts_output(&self) -> bool {
+        self.newline != Newline::Preserve || self.encoding != Encoding::Utf8
+    }
+
+    /// The entry's key in the state file: its `name`, else its `src`.
+    pub fn key(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.src)
+    }
+
+    /// How to refer to the entry on the command line: `name:<name>` for a
+    /// named entry, else its `src`.
+    pub fn selector(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{NAME_SELECTOR}{name}"),
+            None => self.src.clone(),
+        }
+    }
+
+    /// Return the target path string: this machine's entry of `targets`,
+    /// else `target`, defaulting to `~/.config/{src}` when unset.
     pub fn target(&self) -> String {
-        self.target
+        self.host_target
             .clone()
+            .or_else(|| self.target.clone())
             .unwrap_or_else(|| format!("~/.config/{}", self.src))
     }
 }
@@ -86,17 +499,499 @@ fn default_true() -> bool {
     true
 }
 
+/// Attributes encoded in an `attributes_dir` file name.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct FileAttributes {
+    private: bool,
+    executable: bool,
+    template: bool,
+}
+
+/// Decode one path component under `attributes_dir` into its target name
+/// and attributes. `.tmpl` is only a suffix on file names (`is_file`).
+fn decode_attributes(component: &str, is_file: bool) -> (String, FileAttributes) {
+    let mut attrs = FileAttributes::default();
+    let mut name = component;
+    if is_file && let Some(stripped) = name.strip_suffix(".tmpl") {
+        attrs.template = true;
+        name = stripped;
+    }
+    loop {
+        if let Some(rest) = name.strip_prefix("literal_") {
+            return (rest.to_string(), attrs);
+        } else if let Some(rest) = name.strip_prefix("private_") {
+            attrs.private = true;
+            name = rest;
+        } else if let Some(rest) = name.strip_prefix("executable_") {
+            attrs.executable = true;
+            name = rest;
+        } else {
+            break;
+        }
+    }
+    let name = match name.strip_prefix("dot_") {
+        Some(rest) => format!(".{rest}"),
+        None => name.to_string(),
+    };
+    (name, attrs)
+}
+
+/// Lexically normalize a path: drop `.` components, resolve `..` against
+/// preceding components, and collapse repeated or trailing separators.
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Replace the `{home}`, `{hostname}`, `{xdg_config}`, and `{xdg_state}`
+/// placeholders in `value` with this machine's values.
+///
+/// Braces around anything but a lowercase name are left alone; an unknown
+/// name, or one this machine has no value for, is an error.
+pub(crate) fn interpolate(value: &str, fs: &impl Fs) -> Result<String> {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let name = after.find('}').map(|end| &after[..end]).filter(|name| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+        });
+        let Some(name) = name else {
+            out.push('{');
+            rest = after;
+            continue;
+        };
+        let replacement = match name {
+            "home" => fs.home_dir().map(|p| p.display().to_string()),
+            "hostname" => fs.hostname(),
+            "xdg_config" => fs.config_dir().map(|p| p.display().to_string()),
+            "xdg_state" => fs.state_dir().map(|p| p.display().to_string()),
+            other => bail!(
+                "unknown variable {{{other}}} \
+                 (expected {{home}}, {{hostname}}, {{xdg_config}}, or {{xdg_state}})"
+            ),
+        };
+        let Some(replacement) = replacement else {
+            bail!("{{{name}}} can't be determined on this machine");
+        };
+        out.push_str(&replacement);
+        rest = &after[name.len() + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
 impl Config {
-    /// Load and parse a config file from the given path.
+    /// Load and parse a config file from the given path, then validate it.
     pub fn load(path: &Path, fs: &impl Fs) -> Result<Self> {
+        let config = Self::load_unchecked(path, fs)?;
+        config.validate(fs)?;
+        Ok(config)
+    }
+
+    /// Load and parse a config file without validating it.
+    ///
+    /// Used by `janus check`, which reports every problem instead of
+    /// stopping at the first.
+    pub fn load_unchecked(path: &Path, fs: &impl Fs) -> Result<Self> {
         let contents = fs
             .read_to_string(path)
             .with_context(|| format!("Failed to read config file: {}", path.display()))?;
-        let config: Config =
+        let mut config: Config =
             toml::from_str(&contents).with_context(|| "Failed to parse config file")?;
+        config.interpolate_paths(fs)?;
+        config.add_attribute_entries(fs)?;
+        config.resolve_host_targets(fs.hostname().as_deref());
         Ok(config)
     }
 
+    /// Fill in the [`interpolate`] placeholders in the dotfiles and
+    /// pipeline directories and file targets.
+    fn interpolate_paths(&mut self, fs: &impl Fs) -> Result<()> {
+        self.dotfiles_dir = interpolate(&self.dotfiles_dir, fs).context("in dotfiles_dir")?;
+        for dir in &mut self.dotfiles_dirs {
+            *dir = interpolate(dir, fs).context("in dotfiles_dirs")?;
+        }
+        if let Some(dir) = &mut self.generated_dir {
+            *dir = interpolate(dir, fs).context("in generated_dir")?;
+        }
+        if let Some(dir) = &mut self.staged_dir {
+            *dir = interpolate(dir, fs).context("in staged_dir")?;
+        }
+        for entry in &mut self.files {
+            let src = &entry.src;
+            if let Some(target) = &mut entry.target {
+                *target = interpolate(target, fs).with_context(|| format!("in target of {src}"))?;
+            }
+            for (key, target) in &mut entry.targets {
+                *target = interpolate(target, fs)
+                    .with_context(|| format!("in targets.{key} of {src}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add an entry for each file under `attributes_dir` that no `[[files]]`
+    /// entry already manages (by source or target).
+    ///
+    /// A file's target is its path under the directory, relative to home,
+    /// with each component decoded chezmoi-style: `dot_` becomes a leading
+    /// `.` and `literal_` stops decoding. On the file name itself,
+    /// `private_` and `executable_` set `mode` (`0o600`, `0o755`, or `0o700`
+    /// for both) and a `.tmpl` suffix makes it a template; other files are
+    /// copied verbatim. Names starting with `.` are skipped.
+    fn add_attribute_entries(&mut self, fs: &impl Fs) -> Result<()> {
+        let Some(dir) = &self.attributes_dir else {
+            return Ok(());
+        };
+        let root = self.dotfiles_dir(fs).join(dir);
+        if !fs.is_dir(&root) {
+            bail!("attributes_dir {} is not a directory", root.display());
+        }
+        let explicit_targets: Vec<PathBuf> = self
+            .files
+            .iter()
+            .map(|e| expand_tilde(&e.target(), fs))
+            .collect();
+
+        let mut walked: Vec<PathBuf> = fs
+            .walk_dir(
+                &root,
+                &WalkOptions {
+                    min_depth: 1,
+                    follow_links: true,
+                    ..Default::default()
+                },
+            )?
+            .into_iter()
+            .filter(|e| e.is_file)
+            .map(|e| e.path)
+            .collect();
+        walked.sort();
+
+        let mut added = Vec::new();
+        'files: for path in walked {
+            let Ok(relative) = path.strip_prefix(&root) else {
+                continue;
+            };
+            let components: Vec<String> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            let mut target = String::from("~");
+            let mut attrs = FileAttributes::default();
+            for (i, component) in components.iter().enumerate() {
+                if component.starts_with('.') {
+                    continue 'files;
+                }
+                let (name, component_attrs) =
+                    decode_attributes(component, i == components.len() - 1);
+                target.push('/');
+                target.push_str(&name);
+                attrs = component_attrs;
+            }
+
+            let src = Path::new(dir).join(relative).display().to_string();
+            if self.files.iter().any(|e| e.src == src)
+                || explicit_targets.contains(&expand_tilde(&target, fs))
+            {
+                continue;
+            }
+            let mode = match (attrs.private, attrs.executable) {
+                (true, true) => Some(0o700),
+                (true, false) => Some(0o600),
+                (false, true) => Some(0o755),
+                (false, false) => None,
+            };
+            added.push(FileEntry {
+                src,
+                name: None,
+                target: Some(target),
+                targets: BTreeMap::new(),
+                host_target: None,
+                template: attrs.template,
+                postprocess: vec![],
+                vars: vec![],
+                secrets: vec![],
+                inject: vec![],
+                newline: Newline::Preserve,
+                encoding: Encoding::Utf8,
+                mode,
+                direct: false,
+                regions_template: None,
+                exclude_from_all: false,
+                enabled: true,
+                systemd_unit: false,
+                reload_units: vec![],
+                create_parents: None,
+                reload: None,
+                depends_on: vec![],
+                undefined_vars: None,
+            });
+        }
+        self.files.extend(added);
+        Ok(())
+    }
+
+    /// Pick each entry's `targets` entry for `hostname`: the hostname itself,
+    /// else the first host group (alphabetically) that lists it, else
+    /// `default`. Entries with no match fall back to `~/.config/{src}`.
+    fn resolve_host_targets(&mut self, hostname: Option<&str>) {
+        let groups: Vec<&str> = match hostname {
+            Some(hostname) => self
+                .host_groups
+                .iter()
+                .filter(|(_, hosts)| hosts.iter().any(|h| h.eq_ignore_ascii_case(hostname)))
+                .map(|(label, _)| label.as_str())
+                .collect(),
+            None => Vec::new(),
+        };
+        for entry in &mut self.files {
+            if entry.targets.is_empty() {
+                continue;
+            }
+            let by_host = hostname.and_then(|hostname| {
+                entry
+                    .targets
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(hostname))
+                    .map(|(_, target)| target)
+            });
+            entry.host_target = by_host
+                .or_else(|| groups.iter().find_map(|label| entry.targets.get(*label)))
+                .or_else(|| entry.targets.get("default"))
+                .cloned();
+        }
+    }
+
+    /// Problems with per-machine `targets`: entries that also set `target`,
+    /// and keys that are neither `default`, a `[host_groups]` label, nor a
+    /// hostname listed in one.
+    pub fn host_target_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for entry in &self.files {
+            if entry.targets.is_empty() {
+                continue;
+            }
+            if entry.target.is_some() {
+                problems.push(format!(
+                    "{}: target and targets are mutually exclusive",
+                    entry.src
+                ));
+            }
+            for key in entry.targets.keys() {
+                let known = key == "default"
+                    || self.host_groups.contains_key(key)
+                    || self
+                        .host_groups
+                        .values()
+                        .flatten()
+                        .any(|h| h.eq_ignore_ascii_case(key));
+                if known {
+                    continue;
+                }
+                let mut msg = format!("{}: unknown host or host group {key} in targets", entry.src);
+                match self.suggest_host_group(key) {
+                    Some(suggestion) => msg.push_str(&format!(" (did you mean {suggestion}?)")),
+                    None => msg.push_str(" (list hostnames under [host_groups])"),
+                }
+                problems.push(msg);
+            }
+        }
+        problems
+    }
+
+    /// Find the closest host group label to `name`.
+    fn suggest_host_group(&self, name: &str) -> Option<&str> {
+        self.host_groups
+            .keys()
+            .map(|label| (label.as_str(), jaro_winkler(name, label)))
+            .filter(|(_, score)| *score > 0.8)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(label, _)| label)
+    }
+
+    /// Check invariants that serde can't express.
+    ///
+    /// Currently: names must be unique, per-machine `targets` must use known
+    /// hosts and host groups,
+    /// `inject` only goes on generated non-template files, and no two entries
+    /// may resolve to the same target, since deploy would flip the symlink
+    /// between them.
+    pub fn validate(&self, fs: &impl Fs) -> Result<()> {
+        let src_problems = self.src_problems();
+        if !src_problems.is_empty() {
+            bail!("Invalid src:\n  {}", src_problems.join("\n  "));
+        }
+        let name_problems = self.name_problems();
+        if !name_problems.is_empty() {
+            bail!("Invalid name:\n  {}", name_problems.join("\n  "));
+        }
+        let host_problems = self.host_target_problems();
+        if !host_problems.is_empty() {
+            bail!("Invalid targets:\n  {}", host_problems.join("\n  "));
+        }
+        let inject_problems = self.inject_problems();
+        if !inject_problems.is_empty() {
+            bail!("Invalid inject:\n  {}", inject_problems.join("\n  "));
+        }
+        let dependency_problems = self.dependency_problems();
+        if !dependency_problems.is_empty() {
+            bail!(
+                "Invalid depends_on:\n  {}",
+                dependency_problems.join("\n  ")
+            );
+        }
+        let dir_problems = self.pipeline_dir_problems(fs);
+        if !dir_problems.is_empty() {
+            bail!(
+                "Invalid pipeline directories:\n  {}",
+                dir_problems.join("\n  ")
+            );
+        }
+        let duplicates = self.duplicate_targets(fs);
+        if duplicates.is_empty() {
+            return Ok(());
+        }
+        let mut msg = String::from("Multiple files share a target:");
+        for (target, srcs) in &duplicates {
+            msg.push_str(&format!("\n  {}: {}", target.display(), srcs.join(", ")));
+        }
+        bail!(msg);
+    }
+
+    /// Entries whose `src` or `regions_template` is absolute or leaves
+    /// `dotfiles_dir` (see [`crate::policy::src_problem`]).
+    pub fn src_problems(&self) -> Vec<String> {
+        let regions_templates = self.files.iter().filter_map(|e| {
+            let problem = crate::policy::src_problem(e.regions_template.as_deref()?)?;
+            Some(format!("{}: regions_template {problem}", e.src))
+        });
+        self.files
+            .iter()
+            .filter_map(|e| crate::policy::src_problem(&e.src))
+            .chain(regions_templates)
+            .collect()
+    }
+
+    /// Entries whose `name` is empty or clashes with another entry's key.
+    pub fn name_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (i, entry) in self.files.iter().enumerate() {
+            let Some(name) = &entry.name else {
+                continue;
+            };
+            if name.trim().is_empty() {
+                problems.push(format!("{}: name is empty", entry.src));
+            } else if let Some(other) = self.files[..i].iter().find(|e| e.key() == name) {
+                problems.push(format!(
+                    "{}: name {name} is already the key of {}",
+                    entry.src, other.src
+                ));
+            } else if let Some(other) = self.files[i + 1..]
+                .iter()
+                .find(|e| e.name.is_none() && e.src == *name)
+            {
+                problems.push(format!(
+                    "{}: name {name} is already the key of {}",
+                    entry.src, other.src
+                ));
+            }
+        }
+        problems
+    }
+
+    /// `src`s shared by more than one unnamed entry. Those entries have
+    /// the same key, so their deploy state overwrites each other's until
+    /// all but one get a `name`.
+    pub fn unnamed_duplicate_srcs(&self) -> Vec<&str> {
+        let mut duplicates: Vec<&str> = Vec::new();
+        for (i, entry) in self.files.iter().enumerate() {
+            let clashes = entry.name.is_none()
+                && self.files[..i]
+                    .iter()
+                    .any(|e| e.name.is_none() && e.src == entry.src);
+            if clashes && !duplicates.contains(&entry.src.as_str()) {
+                duplicates.push(&entry.src);
+            }
+        }
+        duplicates
+    }
+
+    /// `depends_on` entries matching no managed file.
+    fn dependency_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for entry in &self.files {
+            for dep in &entry.depends_on {
+                if self.find_files(std::slice::from_ref(dep)).is_empty() {
+                    problems.push(format!(
+                        "{}: depends_on {dep} matches no managed file",
+                        entry.src
+                    ));
+                }
+            }
+        }
+        problems
+    }
+
+    /// Entries whose `inject` can't work: templates (use the secret as a
+    /// variable instead), direct files (never generated), and empty
+    /// placeholders.
+    fn inject_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for entry in self.files.iter().filter(|e| !e.inject.is_empty()) {
+            if entry.direct {
+                problems.push(format!(
+                    "{}: direct files are never generated, so nothing is injected",
+                    entry.src
+                ));
+            } else if entry.template {
+                problems.push(format!(
+                    "{}: inject needs template = false; templates can use the secret directly",
+                    entry.src
+                ));
+            }
+            if entry.inject.iter().any(|i| i.placeholder.is_empty()) {
+                problems.push(format!("{}: inject placeholder is empty", entry.src));
+            }
+        }
+        problems
+    }
+
+    /// Entries whose targets resolve to the same path, grouped by that path.
+    ///
+    /// Targets are compared after tilde expansion and lexical normalization,
+    /// so `~/.config/a`, `/home/me/.config/a`, and an unset target for `src =
+    /// "a"` all collide. Groups are in config order.
+    pub fn duplicate_targets(&self, fs: &impl Fs) -> Vec<(PathBuf, Vec<&str>)> {
+        let mut groups: Vec<(PathBuf, Vec<&str>)> = Vec::new();
+        let mut index: HashMap<PathBuf, usize> = HashMap::new();
+        for entry in &self.files {
+            let target = normalize_path(&expand_tilde(&entry.target(), fs));
+            match index.get(&target) {
+                Some(&i) => groups[i].1.push(&entry.src),
+                None => {
+                    index.insert(target.clone(), groups.len());
+                    groups.push((target, vec![&entry.src]));
+                }
+            }
+        }
+        groups.retain(|(_, srcs)| srcs.len() > 1);
+        groups
+    }
+
     /// Return the default config file path.
     pub fn default_path(fs: &impl Fs) -> PathBuf {
         fs.config_dir()
@@ -110,14 +1005,142 @@ impl Config {
         expand_tilde(&self.dotfiles_dir, fs)
     }
 
-    /// Return the .generated directory path.
+    /// Directory holding the state file, lock, journal, undo record, and
+    /// trash log: `dotfiles_dir`, or with `xdg_state`, a directory under
+    /// `$XDG_STATE_HOME/janus/` named for this `dotfiles_dir`.
+    pub fn state_dir(&self, fs: &impl Fs) -> PathBuf {
+        if self.xdg_state {
+            self.xdg_state_dir(fs)
+        } else {
+            self.dotfiles_dir(fs)
+        }
+    }
+
+    /// Directory holding the status cache: `dotfiles_dir`, or with
+    /// `xdg_state`, a directory under `$XDG_CACHE_HOME/janus/` named for
+    /// this `dotfiles_dir`.
+    pub fn cache_dir(&self, fs: &impl Fs) -> PathBuf {
+        if self.xdg_state {
+            self.xdg_cache_dir(fs)
+        } else {
+            self.dotfiles_dir(fs)
+        }
+    }
+
+    /// [`Config::state_dir`] with `xdg_state` set, whether or not it is.
+    pub fn xdg_state_dir(&self, fs: &impl Fs) -> PathBuf {
+        let base = fs
+            .state_dir()
+            .unwrap_or_else(|| expand_tilde("~/.local/state", fs));
+        base.join("janus").join(self.checkout_key(fs))
+    }
+
+    /// [`Config::cache_dir`] with `xdg_state` set, whether or not it is.
+    pub fn xdg_cache_dir(&self, fs: &impl Fs) -> PathBuf {
+        let base = fs
+            .cache_dir()
+            .unwrap_or_else(|| expand_tilde("~/.cache", fs));
+        base.join("janus").join(self.checkout_key(fs))
+    }
+
+    /// Name for this checkout's XDG directories: the `dotfiles_dir` name
+    /// for readability, plus a hash of its full path so two checkouts with
+    /// the same name don't share state.
+    fn checkout_key(&self, fs: &impl Fs) -> String {
+        let dotfiles_dir = self.dotfiles_dir(fs);
+        let name = dotfiles_dir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "dotfiles".to_string());
+        let digest = Sha256::digest(dotfiles_dir.to_string_lossy().as_bytes());
+        format!("{name}-{}", &format!("{digest:x}")[..16])
+    }
+
+    /// Every source layer, highest priority first: `dotfiles_dir`, then
+    /// `dotfiles_dirs` from last to first.
+    pub fn layers(&self, fs: &impl Fs) -> Vec<PathBuf> {
+        std::iter::once(self.dotfiles_dir(fs))
+            .chain(self.dotfiles_dirs.iter().rev().map(|d| expand_tilde(d, fs)))
+            .collect()
+    }
+
+    /// The layer providing `src`: the highest-priority one that has it.
+    pub fn source_layer(&self, src: &str, fs: &impl Fs) -> Option<PathBuf> {
+        self.layers(fs).into_iter().find(|layer| {
+            let path = layer.join(src);
+            fs.exists(&path) || fs.is_symlink(&path)
+        })
+    }
+
+    /// The extra paths `target` is deployed to under `path_maps`, in key
+    /// order. Empty when no mapped directory contains it.
+    pub fn mapped_targets(&self, target: &str, fs: &impl Fs) -> Vec<PathBuf> {
+        let target = expand_tilde(target, fs);
+        let mut mapped = Vec::new();
+        for (from, to) in &self.path_maps {
+            if let Ok(rest) = target.strip_prefix(expand_tilde(from, fs)) {
+                mapped.extend(to.iter().map(|dir| expand_tilde(dir, fs).join(rest)));
+            }
+        }
+        mapped
+    }
+
+    /// Whether deploy may create `entry`'s missing target directories.
+    pub fn creates_parents(&self, entry: &FileEntry) -> bool {
+        entry.create_parents.unwrap_or(self.create_parents)
+    }
+
+    /// What rendering `entry` does with an undefined variable.
+    pub fn undefined_vars_for(&self, entry: &FileEntry) -> UndefinedVars {
+        entry.undefined_vars.unwrap_or(self.undefined_vars)
+    }
+
+    /// Path of `src`'s source file in the layer providing it. When no layer
+    /// has it, the path in `dotfiles_dir`, so "not found" errors point there.
+    pub fn source_path(&self, src: &str, fs: &impl Fs) -> PathBuf {
+        self.source_layer(src, fs)
+            .unwrap_or_else(|| self.dotfiles_dir(fs))
+            .join(src)
+    }
+
+    /// The `[backup] dir`, expanded, if set.
+    pub fn backup_dir(&self, fs: &impl Fs) -> Option<PathBuf> {
+        self.backup.dir.as_deref().map(|dir| expand_tilde(dir, fs))
+    }
+
+    /// Return the generated directory path (`.generated` unless
+    /// `generated_dir` moves it).
     pub fn generated_dir(&self, fs: &impl Fs) -> PathBuf {
-        self.dotfiles_dir(fs).join(".generated")
+        let dir = self.generated_dir.as_deref().unwrap_or(".generated");
+        self.dotfiles_dir(fs).join(expand_tilde(dir, fs))
     }
 
-    /// Return the .staged directory path.
+    /// Return the staged directory path (`.staged` unless `staged_dir`
+    /// moves it).
     pub fn staged_dir(&self, fs: &impl Fs) -> PathBuf {
-        self.dotfiles_dir(fs).join(".staged")
+        let dir = self.staged_dir.as_deref().unwrap_or(".staged");
+        self.dotfiles_dir(fs).join(expand_tilde(dir, fs))
+    }
+
+    /// Problems with `generated_dir` and `staged_dir`: the two can't be the
+    /// same directory, and neither can be the dotfiles directory itself.
+    pub fn pipeline_dir_problems(&self, fs: &impl Fs) -> Vec<String> {
+        let dotfiles_dir = normalize_path(&self.dotfiles_dir(fs));
+        let generated = normalize_path(&self.generated_dir(fs));
+        let staged = normalize_path(&self.staged_dir(fs));
+        let mut problems = Vec::new();
+        if generated == staged {
+            problems.push(format!(
+                "generated_dir and staged_dir are both {}",
+                generated.display()
+            ));
+        }
+        for (name, dir) in [("generated_dir", &generated), ("staged_dir", &staged)] {
+            if *dir == dotfiles_dir {
+                problems.push(format!("{name} can't be dotfiles_dir itself"));
+            }
+        }
+        problems
     }
 
     /// Resolve fileset names to their constituent file/glob patterns.
@@ -143,9 +1166,17 @@ impl Config {
     ///
     /// Used by generate to inherit fileset-level vars and secrets.
     pub fn matching_filesets(&self, src: &str) -> Vec<&FilesetEntry> {
+        self.matching_filesets_named(src)
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .collect()
+    }
+
+    /// Like [`Config::matching_filesets`], but paired with each fileset's name.
+    pub fn matching_filesets_named(&self, src: &str) -> Vec<(&str, &FilesetEntry)> {
         self.filesets
-            .values()
-            .filter(|entry| {
+            .iter()
+            .filter(|(_, entry)| {
                 entry.patterns.iter().any(|pattern| {
                     if let Ok(glob_pattern) = glob::Pattern::new(pattern) {
                         glob_pattern.matches(src)
@@ -154,6 +1185,7 @@ impl Config {
                     }
                 })
             })
+            .map(|(name, entry)| (name.as_str(), entry))
             .collect()
     }
 
@@ -161,19 +1193,41 @@ impl Config {
     ///
     /// `None` returns all entries. `Some` returns only entries whose `src`
     /// matches at least one pattern (glob syntax supported, falls back to
-    /// exact match if the pattern is not a valid glob).
+    /// exact match if the pattern is not a valid glob). Disabled entries are
+    /// never returned.
     pub fn filter_files(&self, patterns: Option<&[String]>) -> Vec<&FileEntry> {
         let Some(patterns) = patterns else {
-            return self.files.iter().filter(|e| !e.exclude_from_all).collect();
+            return self
+                .files
+                .iter()
+                .filter(|e| e.enabled && !e.exclude_from_all)
+                .collect();
         };
+        self.find_files(patterns)
+            .into_iter()
+            .filter(|e| e.enabled)
+            .collect()
+    }
+
+    /// Entries whose `src` matches at least one of `patterns`, as in
+    /// [`filter_files`](Self::filter_files), including disabled ones. A
+    /// pattern starting with `name:` matches entry names instead.
+    pub fn find_files(&self, patterns: &[String]) -> Vec<&FileEntry> {
         self.files
             .iter()
             .filter(|entry| {
                 patterns.iter().any(|pattern| {
+                    let (pattern, value) = match pattern.strip_prefix(NAME_SELECTOR) {
+                        Some(name) => match &entry.name {
+                            Some(entry_name) => (name, entry_name.as_str()),
+                            None => return false,
+                        },
+                        None => (pattern.as_str(), entry.src.as_str()),
+                    };
                     if let Ok(glob_pattern) = glob::Pattern::new(pattern) {
-                        glob_pattern.matches(&entry.src)
+                        glob_pattern.matches(value)
                     } else {
-                        entry.src == *pattern
+                        value == pattern
                     }
                 })
             })
@@ -221,12 +1275,24 @@ impl Config {
     /// Bail with fuzzy-match suggestions when explicit patterns matched no files.
     ///
     /// When `patterns` is `None` (`--all`), returns `Ok(())` — the caller handles
-    /// the info log for "no configured files". When `Some`, bails with suggestions
-    /// if any are close enough, or a plain "no matching files" error otherwise.
+    /// the info log for "no configured files". When `Some`, bails naming any
+    /// disabled entries the patterns match, else with suggestions if any are
+    /// close enough, or a plain "no matching files" error otherwise.
     pub fn bail_unmatched(&self, patterns: Option<&[String]>) -> Result<()> {
         let Some(patterns) = patterns else {
             return Ok(());
         };
+        let disabled: Vec<&str> = self
+            .find_files(patterns)
+            .iter()
+            .map(|e| e.src.as_str())
+            .collect();
+        if !disabled.is_empty() {
+            bail!(
+                "Disabled in config: {} (run `janus enable` to manage again)",
+                disabled.join(", ")
+            );
+        }
         let suggestions = self.suggest_files(patterns);
         if suggestions.is_empty() {
             bail!("No matching files found in config");
@@ -311,16 +1377,121 @@ secrets = ["desktop-secrets.toml"]
         );
     }
 
+    #[test]
+    fn inject_needs_copied_file() {
+        let fs = setup_fs();
+        let toml = format!(
+            r#"dotfiles_dir = "{DOTFILES}"
+
+[[files]]
+src = "a.conf"
+inject = [{{ placeholder = "@@A@@", secret = "a" }}]
+
+[[files]]
+src = "b.conf"
+template = false
+direct = true
+inject = [{{ placeholder = "@@B@@", secret = "b" }}]
+
+[[files]]
+src = "c.conf"
+template = false
+inject = [{{ placeholder = "", secret = "c" }}]
+"#
+        );
+        fs.add_file(CONFIG_PATH, toml);
+        let msg = format!(
+            "{:#}",
+            Config::load(Path::new(CONFIG_PATH), &fs).unwrap_err()
+        );
+        assert!(
+            msg.contains("a.conf: inject needs template = false"),
+            "got: {msg}"
+        );
+        assert!(msg.contains("b.conf: direct files"), "got: {msg}");
+        assert!(
+            msg.contains("c.conf: inject placeholder is empty"),
+            "got: {msg}"
+        );
+    }
+
+    #[test]
+    fn duplicate_targets_rejected() {
+        let fs = setup_fs();
+        let toml = format!(
+            r#"dotfiles_dir = "{DOTFILES}"
+
+[[files]]
+src = "a.conf"
+
+[[files]]
+src = "b.conf"
+target = "/home/test/.config/./a.conf"
+
+[[files]]
+src = "c.conf"
+target = "~/.config/c.conf"
+"#
+        );
+        fs.add_file(CONFIG_PATH, toml);
+        let result = Config::load(Path::new(CONFIG_PATH), &fs);
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(
+            msg.contains("/home/test/.config/a.conf: a.conf, b.conf"),
+            "got: {msg}"
+        );
+        assert!(!msg.contains("c.conf"), "got: {msg}");
+
+        // Still loadable for `janus check`
+        let config = Config::load_unchecked(Path::new(CONFIG_PATH), &fs).unwrap();
+        assert_eq!(config.duplicate_targets(&fs).len(), 1);
+    }
+
+    #[test]
+    fn distinct_targets_accepted() {
+        let fs = setup_fs();
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[("a.conf", None), ("b.conf", Some("~/.config/a.conf.bak"))]),
+        );
+        assert!(config.duplicate_targets(&fs).is_empty());
+    }
+
+    #[test]
+    fn normalize_path_lexical() {
+        assert_eq!(
+            normalize_path(Path::new("/a/./b/../c/")),
+            PathBuf::from("/a/c")
+        );
+        assert_eq!(normalize_path(Path::new("../x")), PathBuf::from("../x"));
+    }
+
     #[test]
     fn file_entry_target_default() {
         let entry = FileEntry {
             src: "hypr/hypr.conf".to_string(),
+            name: None,
             target: None,
+            targets: BTreeMap::new(),
+            host_target: None,
             template: true,
+            postprocess: vec![],
             vars: vec![],
             secrets: vec![],
+            inject: vec![],
+            newline: Newline::Preserve,
+            encoding: Encoding::Utf8,
+            mode: None,
             direct: false,
+            regions_template: None,
             exclude_from_all: false,
+            enabled: true,
+            systemd_unit: false,
+            reload_units: vec![],
+            create_parents: None,
+            reload: None,
+            depends_on: vec![],
+            undefined_vars: None,
         };
         assert_eq!(entry.target(), "~/.config/hypr/hypr.conf");
     }
@@ -329,16 +1500,161 @@ secrets = ["desktop-secrets.toml"]
     fn file_entry_target_explicit() {
         let entry = FileEntry {
             src: "bashrc".to_string(),
+            name: None,
             target: Some("~/.bashrc".to_string()),
+            targets: BTreeMap::new(),
+            host_target: None,
             template: true,
+            postprocess: vec![],
             vars: vec![],
             secrets: vec![],
+            inject: vec![],
+            newline: Newline::Preserve,
+            encoding: Encoding::Utf8,
+            mode: None,
             direct: false,
+            regions_template: None,
             exclude_from_all: false,
+            enabled: true,
+            systemd_unit: false,
+            reload_units: vec![],
+            create_parents: None,
+            reload: None,
+            depends_on: vec![],
+            undefined_vars: None,
         };
         assert_eq!(entry.target(), "~/.bashrc");
     }
 
+    fn host_targets_toml() -> String {
+        format!(
+            "dotfiles_dir = \"{DOTFILES}\"\n\n[host_groups]\nwork = [\"work-laptop\", \"build\"]\n\n\
+             [[files]]\nsrc = \"foo.conf\"\n\
+             targets = {{ default = \"~/.config/foo\", work = \"~/company/foo\", build = \"~/ci/foo\" }}\n"
+        )
+    }
+
+    #[test]
+    fn attribute_entries_decoded_from_names() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/home/dot_config/kitty/kitty.conf"), "");
+        fs.add_file(
+            format!("{DOTFILES}/home/private_dot_ssh/private_config.tmpl"),
+            "",
+        );
+        fs.add_file(
+            format!("{DOTFILES}/home/dot_local/bin/executable_backup"),
+            "",
+        );
+        fs.add_file(format!("{DOTFILES}/home/literal_dot_x"), "");
+        fs.add_file(format!("{DOTFILES}/home/.git/HEAD"), "");
+        let toml = format!("dotfiles_dir = \"{DOTFILES}\"\nattributes_dir = \"home\"\n");
+        let config = write_and_load_config(&fs, &toml);
+
+        let entries: Vec<(&str, String, bool, Option<u32>)> = config
+            .files
+            .iter()
+            .map(|e| (e.src.as_str(), e.target(), e.template, e.mode))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    "home/dot_config/kitty/kitty.conf",
+                    "~/.config/kitty/kitty.conf".to_string(),
+                    false,
+                    None
+                ),
+                (
+                    "home/dot_local/bin/executable_backup",
+                    "~/.local/bin/backup".to_string(),
+                    false,
+                    Some(0o755)
+                ),
+                ("home/literal_dot_x", "~/dot_x".to_string(), false, None),
+                (
+                    "home/private_dot_ssh/private_config.tmpl",
+                    "~/.ssh/config".to_string(),
+                    true,
+                    Some(0o600)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn explicit_entries_take_precedence_over_attributes() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/home/dot_bashrc"), "");
+        fs.add_file(format!("{DOTFILES}/home/dot_profile"), "");
+        fs.add_file(format!("{DOTFILES}/profile"), "");
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nattributes_dir = \"home\"\n\n\
+             [[files]]\nsrc = \"home/dot_bashrc\"\ntarget = \"~/.bashrc-custom\"\n\n\
+             [[files]]\nsrc = \"profile\"\ntarget = \"~/.profile\"\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let targets: Vec<(&str, String)> = config
+            .files
+            .iter()
+            .map(|e| (e.src.as_str(), e.target()))
+            .collect();
+        assert_eq!(
+            targets,
+            vec![
+                ("home/dot_bashrc", "~/.bashrc-custom".to_string()),
+                ("profile", "~/.profile".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn host_targets_resolve_by_host_then_group_then_default() {
+        let fs = setup_fs();
+        let config = write_and_load_config(&fs, &host_targets_toml());
+        assert_eq!(config.files[0].target(), "~/.config/foo");
+
+        fs.set_hostname("work-laptop");
+        let config = write_and_load_config(&fs, &host_targets_toml());
+        assert_eq!(config.files[0].target(), "~/company/foo");
+
+        fs.set_hostname("build");
+        let config = write_and_load_config(&fs, &host_targets_toml());
+        assert_eq!(config.files[0].target(), "~/ci/foo");
+    }
+
+    #[test]
+    fn host_targets_reject_unknown_groups() {
+        let fs = setup_fs();
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\n\n[host_groups]\nwork = [\"work-laptop\"]\n\n\
+             [[files]]\nsrc = \"foo.conf\"\ntargets = {{ wrok = \"~/company/foo\" }}\n"
+        );
+        fs.add_file(CONFIG_PATH, toml.as_str());
+        let err = Config::load(Path::new(CONFIG_PATH), &fs).unwrap_err();
+        let msg = format!("{err:#}");
+        assert!(
+            msg.contains("unknown host or host group wrok"),
+            "got: {msg}"
+        );
+        assert!(msg.contains("did you mean work?"), "got: {msg}");
+    }
+
+    #[test]
+    fn host_targets_conflict_with_target() {
+        let fs = setup_fs();
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\n\n[[files]]\nsrc = \"foo.conf\"\n\
+             target = \"~/foo\"\ntargets = {{ default = \"~/bar\" }}\n"
+        );
+        fs.add_file(CONFIG_PATH, toml.as_str());
+        let msg = format!(
+            "{:#}",
+            Config::load(Path::new(CONFIG_PATH), &fs).unwrap_err()
+        );
+        assert!(msg.contains("mutually exclusive"), "got: {msg}");
+    }
+
     #[test]
     fn file_entry_template_defaults_true() {
         let fs = setup_fs();
@@ -384,6 +1700,52 @@ secrets = ["desktop-secrets.toml"]
         assert!(config.files[0].exclude_from_all);
     }
 
+    #[test]
+    fn systemd_fields_parse() {
+        let fs = setup_fs();
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\n\n[[files]]\nsrc = \"systemd/user/waybar.service\"\nsystemd_unit = true\nreload_units = [\"waybar.service\"]\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        assert!(config.files[0].systemd_unit);
+        assert_eq!(config.files[0].reload_units, vec!["waybar.service"]);
+    }
+
+    #[test]
+    fn on_failure_parses() {
+        let fs = setup_fs();
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\n\n[on_failure]\ncommand = \"notify-send janus\"\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        assert_eq!(config.on_failure.unwrap().command, "notify-send janus");
+    }
+
+    #[test]
+    fn ui_pager_parses() {
+        let fs = setup_fs();
+        let toml = format!("dotfiles_dir = \"{DOTFILES}\"\n\n[ui]\npager = \"bat\"\n");
+        let config = write_and_load_config(&fs, &toml);
+        assert_eq!(config.ui.pager.as_deref(), Some("bat"));
+
+        let config = write_and_load_config(&fs, &make_config_toml(&[]));
+        assert!(config.ui.pager.is_none());
+    }
+
+    #[test]
+    fn logging_parses() {
+        let fs = setup_fs();
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\n\n[logging]\nfile = \"~/janus.log\"\nlevel = \"debug\"\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        assert_eq!(config.logging.file.as_deref(), Some("~/janus.log"));
+        assert_eq!(config.logging.level.as_deref(), Some("debug"));
+
+        let config = write_and_load_config(&fs, &make_config_toml(&[]));
+        assert!(config.logging.file.is_none());
+    }
+
     #[test]
     fn filter_files_none_excludes_exclude_from_all() {
         let fs = setup_fs();
@@ -427,6 +1789,22 @@ exclude_from_all = true
         assert_eq!(filtered[0].src, "excluded.conf");
     }
 
+    #[test]
+    fn filter_files_skips_disabled() {
+        let fs = setup_fs();
+        let toml = format!(
+            "{}enabled = false\n",
+            make_config_toml(&[("a.conf", None), ("parked.conf", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        assert_eq!(config.filter_files(None).len(), 1);
+        let patterns = vec!["parked.conf".to_string()];
+        assert!(config.filter_files(Some(&patterns)).is_empty());
+        assert_eq!(config.find_files(&patterns).len(), 1);
+        let err = config.bail_unmatched(Some(&patterns)).unwrap_err();
+        assert!(err.to_string().contains("janus enable"), "got: {err}");
+    }
+
     #[test]
     fn default_path() {
         let fs = FakeFs::new("/home/test");
@@ -434,6 +1812,33 @@ exclude_from_all = true
         assert_eq!(path, PathBuf::from("/home/test/.config/janus/config.toml"));
     }
 
+    #[test]
+    fn xdg_state_dirs_are_keyed_by_checkout() {
+        let fs = setup_fs();
+        let config = write_and_load_config(&fs, "dotfiles_dir = \"~/dotfiles\"\n");
+        assert_eq!(config.state_dir(&fs), PathBuf::from(DOTFILES));
+        assert_eq!(config.cache_dir(&fs), PathBuf::from(DOTFILES));
+
+        let toml = "dotfiles_dir = \"~/dotfiles\"\nxdg_state = true\n";
+        let config = write_and_load_config(&fs, toml);
+        let state_dir = config.state_dir(&fs);
+        assert_eq!(
+            state_dir.parent().unwrap(),
+            Path::new("/home/test/.local/state/janus")
+        );
+        let key = state_dir.file_name().unwrap().to_str().unwrap();
+        assert!(key.starts_with("dotfiles-"), "got: {key}");
+        assert_eq!(
+            config.cache_dir(&fs),
+            Path::new("/home/test/.cache/janus").join(key)
+        );
+
+        // Another checkout with the same name gets its own directory
+        let toml = "dotfiles_dir = \"/srv/dotfiles\"\nxdg_state = true\n";
+        let other = write_and_load_config(&fs, toml);
+        assert_ne!(other.state_dir(&fs), state_dir);
+    }
+
     #[test]
     fn dotfiles_dir_expands_tilde() {
         let fs = setup_fs();
@@ -445,6 +1850,60 @@ exclude_from_all = true
         );
     }
 
+    #[test]
+    fn interpolates_builtin_vars() {
+        let fs = setup_fs();
+        fs.set_hostname("laptop");
+        let toml = "dotfiles_dir = \"{home}/dotfiles\"\n\n\
+                    [[files]]\nsrc = \"a.conf\"\ntarget = \"{xdg_config}/app/{hostname}.conf\"\n";
+        let config = write_and_load_config(&fs, toml);
+        assert_eq!(config.dotfiles_dir(&fs), PathBuf::from(DOTFILES));
+        assert_eq!(
+            config.files[0].target(),
+            format!("{}/app/laptop.conf", fs.config_dir().unwrap().display())
+        );
+    }
+
+    #[test]
+    fn interpolation_rejects_unknown_vars() {
+        let fs = setup_fs();
+        assert_eq!(interpolate("~/{a,b}/x", &fs).unwrap(), "~/{a,b}/x");
+        let err = interpolate("{user}/x", &fs).unwrap_err();
+        assert!(err.to_string().contains("{user}"), "got: {err}");
+        // FakeFs has no hostname until one is set
+        assert!(interpolate("{hostname}", &fs).is_err());
+    }
+
+    #[test]
+    fn pipeline_dirs_relocate() {
+        let fs = setup_fs();
+        let toml = format!(
+            "generated_dir = \"{{xdg_state}}/janus/generated\"\nstaged_dir = \"build/staged\"\n{}",
+            make_config_toml(&[])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        assert_eq!(
+            config.generated_dir(&fs),
+            PathBuf::from("/home/test/.local/state/janus/generated")
+        );
+        assert_eq!(
+            config.staged_dir(&fs),
+            PathBuf::from(format!("{DOTFILES}/build/staged"))
+        );
+    }
+
+    #[test]
+    fn pipeline_dirs_must_differ() {
+        let fs = setup_fs();
+        let toml = format!(
+            "generated_dir = \"out\"\nstaged_dir = \"./out\"\n{}",
+            make_config_toml(&[])
+        );
+        fs.add_file(CONFIG_PATH, toml);
+        let err = Config::load(Path::new(CONFIG_PATH), &fs).unwrap_err();
+        assert!(err.to_string().contains("both"), "got: {err}");
+    }
+
     #[test]
     fn generated_dir() {
         let fs = setup_fs();
@@ -696,4 +2155,77 @@ target = "~/.config/other/a.conf"
         // But different targets
         assert_ne!(entries[0].target(), entries[1].target());
     }
+
+    #[test]
+    fn named_entries_select_and_key() {
+        let fs = setup_fs();
+        let toml = format!(
+            r#"
+dotfiles_dir = "{DOTFILES}"
+
+[[files]]
+src = "git/config"
+target = "~/.gitconfig"
+
+[[files]]
+src = "git/config"
+name = "work-gitconfig"
+target = "~/work/.gitconfig"
+"#
+        );
+        let config = write_and_load_config(&fs, &toml);
+        assert_eq!(config.files[0].key(), "git/config");
+        assert_eq!(config.files[1].key(), "work-gitconfig");
+        assert_eq!(config.files[1].selector(), "name:work-gitconfig");
+        assert!(config.unnamed_duplicate_srcs().is_empty());
+
+        let named = config.filter_files(Some(&["name:work-*".to_string()]));
+        assert_eq!(named.len(), 1);
+        assert_eq!(named[0].target(), "~/work/.gitconfig");
+        assert_eq!(
+            config.filter_files(Some(&["git/config".to_string()])).len(),
+            2
+        );
+
+        let clash = toml.replace("work-gitconfig", "git/config");
+        fs.add_file(CONFIG_PATH, clash.as_str());
+        let err = Config::load(Path::new(CONFIG_PATH), &fs).unwrap_err();
+        assert!(format!("{err:#}").contains("Invalid name"), "got: {err:#}");
+    }
+
+    #[test]
+    fn source_path_prefers_later_layers() {
+        let fs = setup_fs();
+        fs.add_file("/srv/team/both.conf", "team");
+        fs.add_file("/srv/team/team.conf", "team");
+        fs.add_file("/srv/base/team.conf", "base");
+        fs.add_file(format!("{DOTFILES}/both.conf"), "mine");
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\ndotfiles_dirs = [\"/srv/base\", \"/srv/team\"]\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+
+        assert_eq!(
+            config.layers(&fs),
+            vec![
+                PathBuf::from(DOTFILES),
+                PathBuf::from("/srv/team"),
+                PathBuf::from("/srv/base")
+            ]
+        );
+        assert_eq!(
+            config.source_path("both.conf", &fs),
+            Path::new(DOTFILES).join("both.conf")
+        );
+        assert_eq!(
+            config.source_path("team.conf", &fs),
+            PathBuf::from("/srv/team/team.conf")
+        );
+        // Missing everywhere: point at the top layer
+        assert_eq!(config.source_layer("gone.conf", &fs), None);
+        assert_eq!(
+            config.source_path("gone.conf", &fs),
+            Path::new(DOTFILES).join("gone.conf")
+        );
+    }
 }
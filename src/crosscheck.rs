@@ -0,0 +1,390 @@
+//! Fake-vs-real filesystem cross-checks (the `real-fs-tests` feature).
+//!
+//! Each scenario runs twice: once against a [`FakeFs`], and once against
+//! the real filesystem in a fresh temporary directory, with the same home
+//! path in both. Afterwards the two home directories are walked and
+//! compared entry by entry (kind, contents, permissions, and symlink
+//! targets), so any place `FakeFs` models an operation differently from
+//! the kernel (temporary file names, renames over symlinks, hard links)
+//! shows up as a mismatch.
+//!
+//! Run with `cargo test --features real-fs-tests crosscheck`.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::ops::{clean, deploy, generate, stage, undeploy};
+use crate::platform::{
+    DirEntry, FakeCommandRunner, FakeFs, FakeSecretEngine, FileStamp, Fs, RealFs, WalkOptions,
+    Xattrs, xdg_trash,
+};
+
+/// Files whose contents carry timestamps, so only their presence is
+/// compared.
+const VOLATILE: &[&str] = &[".janus_state.toml"];
+
+/// The real filesystem with its system directories (home, config, state,
+/// cache, and trash) moved under a temporary home.
+struct SandboxFs {
+    home: PathBuf,
+}
+
+impl Fs for SandboxFs {
+    fn read_to_string(&self, path: &Path) -> Result<String> {
+        RealFs.read_to_string(path)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        RealFs.read(path)
+    }
+
+    fn hash_file(&self, path: &Path) -> Result<String> {
+        RealFs.hash_file(path)
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        RealFs.write(path, contents)
+    }
+
+    fn append(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        RealFs.append(path, contents)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        RealFs.copy(from, to)
+    }
+
+    fn copy_streaming(&self, from: &Path, to: &Path) -> Result<()> {
+        RealFs.copy_streaming(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        RealFs.remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<()> {
+        RealFs.remove_dir(path)
+    }
+
+    /// Trashes into `<home>/.local/share/Trash`, as [`FakeFs`] does.
+    fn trash(&self, path: &Path) -> Result<PathBuf> {
+        let trash_dir = self.home.join(".local/share/Trash");
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let (info_path, trashed) = (1..)
+            .map(|n| xdg_trash::slot(&trash_dir, &name, n))
+            .find(|(info, file)| !info.exists() && file.symlink_metadata().is_err())
+            .expect("some trash slot is free");
+        RealFs.create_dir_all(&trash_dir.join("files"))?;
+        RealFs.create_dir_all(&trash_dir.join("info"))?;
+        RealFs.write(&info_path, xdg_trash::trash_info(path, 0).as_bytes())?;
+        RealFs.rename(path, &trashed)?;
+        Ok(trashed)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        RealFs.rename(from, to)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        RealFs.create_dir_all(path)
+    }
+
+    fn file_mode(&self, path: &Path) -> Result<u32> {
+        RealFs.file_mode(path)
+    }
+
+    fn set_file_mode(&self, path: &Path, mode: u32) -> Result<()> {
+        RealFs.set_file_mode(path, mode)
+    }
+
+    fn stamp(&self, path: &Path) -> Result<FileStamp> {
+        RealFs.stamp(path)
+    }
+
+    fn xattrs(&self, path: &Path) -> Result<Xattrs> {
+        RealFs.xattrs(path)
+    }
+
+    fn set_xattrs(&self, path: &Path, attrs: &Xattrs) -> Result<()> {
+        RealFs.set_xattrs(path, attrs)
+    }
+
+    fn symlink(&self, original: &Path, link: &Path) -> Result<()> {
+        RealFs.symlink(original, link)
+    }
+
+    fn hard_link(&self, original: &Path, link: &Path) -> Result<()> {
+        RealFs.hard_link(original, link)
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf> {
+        RealFs.read_link(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        RealFs.canonicalize(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        RealFs.exists(path)
+    }
+
+    fn is_symlink(&self, path: &Path) -> bool {
+        RealFs.is_symlink(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        RealFs.is_file(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        RealFs.is_dir(path)
+    }
+
+    fn walk_dir(&self, path: &Path, opts: &WalkOptions) -> Result<Vec<DirEntry>> {
+        RealFs.walk_dir(path, opts)
+    }
+
+    fn home_dir(&self) -> Option<PathBuf> {
+        Some(self.home.clone())
+    }
+
+    fn config_dir(&self) -> Option<PathBuf> {
+        Some(self.home.join(".config"))
+    }
+
+    fn state_dir(&self) -> Option<PathBuf> {
+        Some(self.home.join(".local/state"))
+    }
+
+    fn cache_dir(&self) -> Option<PathBuf> {
+        Some(self.home.join(".cache"))
+    }
+
+    fn hostname(&self) -> Option<String> {
+        None
+    }
+}
+
+/// A temporary directory, removed on drop.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("janus-crosscheck-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("create temp dir");
+        // Symlink targets compare as written, so use the resolved path
+        Self(std::fs::canonicalize(&path).expect("resolve temp dir"))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// One entry of a filesystem snapshot.
+#[derive(Debug, PartialEq, Eq)]
+enum Node {
+    Dir,
+    /// Contents (`None` for [`VOLATILE`] files) and permission bits.
+    File(Option<String>, u32),
+    Symlink(PathBuf),
+}
+
+/// Everything under `home`, keyed by path relative to it.
+fn snapshot(fs: &impl Fs, home: &Path) -> BTreeMap<PathBuf, Node> {
+    let opts = WalkOptions {
+        min_depth: 1,
+        ..Default::default()
+    };
+    let mut nodes = BTreeMap::new();
+    for entry in fs.walk_dir(home, &opts).expect("walk home") {
+        let path = &entry.path;
+        let node = if entry.is_symlink {
+            Node::Symlink(fs.read_link(path).expect("read link"))
+        } else if entry.is_dir {
+            Node::Dir
+        } else {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let volatile = VOLATILE.iter().any(|suffix| name.ends_with(suffix));
+            let content = (!volatile)
+                .then(|| String::from_utf8_lossy(&fs.read(path).expect("read file")).into_owned());
+            Node::File(content, fs.file_mode(path).expect("file mode") & 0o7777)
+        };
+        let relative = path.strip_prefix(home).expect("under home").to_path_buf();
+        nodes.insert(relative, node);
+    }
+    nodes
+}
+
+/// Run `fake` on a [`FakeFs`] and `real` (the same scenario) in a temporary
+/// directory, then assert both leave identical home directories.
+fn crosscheck(
+    name: &str,
+    fake: fn(&FakeFs, &Path) -> Result<()>,
+    real: fn(&SandboxFs, &Path) -> Result<()>,
+) {
+    // Real file modes depend on the umask; fix it at the usual default
+    // SAFETY: umask has no preconditions and can't fail
+    unsafe { libc::umask(0o022) };
+
+    let dir = TempDir::new(name);
+    let home = dir.0.join("home");
+    let real_fs = SandboxFs { home: home.clone() };
+    real_fs.create_dir_all(&home.join(".config")).unwrap();
+    let fake_fs = FakeFs::new(home.clone());
+
+    let fake_result = fake(&fake_fs, &home).map_err(|e| format!("{e:#}"));
+    let real_result = real(&real_fs, &home).map_err(|e| format!("{e:#}"));
+    assert_eq!(
+        fake_result.is_ok(),
+        real_result.is_ok(),
+        "{name}: fake {fake_result:?}, real {real_result:?}"
+    );
+
+    let fake_nodes = snapshot(&fake_fs, &home);
+    let real_nodes = snapshot(&real_fs, &home);
+    let mut diffs = Vec::new();
+    for path in fake_nodes.keys().chain(real_nodes.keys()) {
+        let (f, r) = (fake_nodes.get(path), real_nodes.get(path));
+        if f != r && !diffs.iter().any(|(p, _, _)| p == path) {
+            diffs.push((path.clone(), f, r));
+        }
+    }
+    assert!(
+        diffs.is_empty(),
+        "{name}: fake and real filesystems diverged:\n{}",
+        diffs
+            .iter()
+            .map(|(p, f, r)| format!("  {}: fake {f:?}, real {r:?}", p.display()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+}
+
+/// Write `content` to `path`, creating its parents.
+fn put(fs: &impl Fs, path: &Path, content: &str) -> Result<()> {
+    fs.create_dir_all(path.parent().context("path has a parent")?)?;
+    fs.write(path, content.as_bytes())
+}
+
+/// Lay out a dotfiles directory under `home` with `files` as
+/// `(src, extra entry TOML, content)`, write a config with the `top` keys,
+/// and load it.
+fn dotfiles(fs: &impl Fs, home: &Path, top: &str, files: &[(&str, &str, &str)]) -> Result<Config> {
+    let dotfiles = home.join("dotfiles");
+    fs.create_dir_all(&dotfiles.join(".generated"))?;
+    fs.create_dir_all(&dotfiles.join(".staged"))?;
+    put(fs, &dotfiles.join(".janus_state.toml"), "")?;
+    put(fs, &dotfiles.join("vars.toml"), "size = 12\n")?;
+
+    let mut toml = format!(
+        "dotfiles_dir = \"{}\"\nvars = [\"vars.toml\"]\n{top}\n",
+        dotfiles.display()
+    );
+    for (src, extra, content) in files {
+        toml.push_str(&format!("\n[[files]]\nsrc = \"{src}\"\n{extra}\n"));
+        put(fs, &dotfiles.join(src), content)?;
+    }
+    let config_path = home.join(".config/janus/config.toml");
+    put(fs, &config_path, &toml)?;
+    Config::load(&config_path, fs)
+}
+
+/// A template, a verbatim copy with its own mode, and a direct file.
+fn three_files(fs: &impl Fs, home: &Path, top: &str) -> Result<Config> {
+    dotfiles(
+        fs,
+        home,
+        top,
+        &[
+            ("a.conf", "", "font = {{ size }}\n"),
+            ("b.sh", "template = false\nmode = 0o755", "#!/bin/sh\n"),
+            ("c.conf", "direct = true\ntemplate = false", "direct\n"),
+        ],
+    )
+}
+
+fn apply(config: &Config, force: bool, fs: &impl Fs) -> Result<()> {
+    let runner = FakeCommandRunner::new();
+    generate::run(config, None, false, fs, &FakeSecretEngine::new(), &runner)?;
+    stage::run(config, None, false, fs)?;
+    deploy::run(config, None, force, false, fs, &runner)
+}
+
+fn pipeline(fs: &impl Fs, home: &Path) -> Result<()> {
+    let config = three_files(fs, home, "")?;
+    apply(&config, false, fs)
+}
+
+fn undeploy_leaves_copies(fs: &impl Fs, home: &Path) -> Result<()> {
+    let config = three_files(fs, home, "")?;
+    apply(&config, false, fs)?;
+    undeploy::run(&config, None, false, false, fs, &FakeCommandRunner::new())
+}
+
+fn force_deploy_over_file(fs: &impl Fs, home: &Path) -> Result<()> {
+    let config = three_files(fs, home, "")?;
+    put(fs, &home.join(".config/a.conf"), "old\n")?;
+    apply(&config, true, fs)
+}
+
+fn staged_store_relinks(fs: &impl Fs, home: &Path) -> Result<()> {
+    let config = dotfiles(
+        fs,
+        home,
+        "staged_store = true",
+        &[("a.conf", "", "same\n"), ("b.conf", "", "same\n")],
+    )?;
+    apply(&config, false, fs)?;
+    // Restaging an unchanged file replaces its link in place
+    stage::run(&config, None, false, fs)
+}
+
+fn clean_orphans_to_trash(fs: &impl Fs, home: &Path) -> Result<()> {
+    let config = three_files(fs, home, "use_trash = true")?;
+    apply(&config, false, fs)?;
+    let dotfiles = home.join("dotfiles");
+    put(fs, &dotfiles.join(".generated/old/x.conf"), "x\n")?;
+    put(fs, &dotfiles.join(".staged/old/x.conf"), "x\n")?;
+    clean::run(&config, false, true, None, false, fs)
+}
+
+#[test]
+fn pipeline_matches() {
+    crosscheck("pipeline", pipeline, pipeline);
+}
+
+#[test]
+fn undeploy_matches() {
+    crosscheck("undeploy", undeploy_leaves_copies, undeploy_leaves_copies);
+}
+
+#[test]
+fn force_deploy_matches() {
+    crosscheck(
+        "force-deploy",
+        force_deploy_over_file,
+        force_deploy_over_file,
+    );
+}
+
+#[test]
+fn staged_store_matches() {
+    crosscheck("staged-store", staged_store_relinks, staged_store_relinks);
+}
+
+#[test]
+fn clean_orphans_matches() {
+    crosscheck(
+        "clean-orphans",
+        clean_orphans_to_trash,
+        clean_orphans_to_trash,
+    );
+}
@@ -0,0 +1,188 @@
+//! A minimal `.gitignore` matcher for import walks.
+//!
+//! Covers what build artifacts and plugin checkouts are usually ignored
+//! with: comments, blank lines, `!` negation, trailing `/` for directories
+//! only, patterns anchored by a `/` to the `.gitignore`'s directory, and
+//! `*`, `?`, `[...]`, and `**` globs. A pattern matching a directory
+//! ignores everything under it, and the last matching rule wins, with
+//! deeper `.gitignore` files read after shallower ones. Global excludes and
+//! `.git/info/exclude` aren't read.
+
+use anyhow::{Context, Result};
+use glob::{MatchOptions, Pattern};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::platform::Fs;
+
+/// Name of the files rules are read from.
+pub const GITIGNORE: &str = ".gitignore";
+
+/// `*` and `?` stay within one path component, as in git.
+const OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// One line of a `.gitignore`.
+struct Rule {
+    /// Directory of the `.gitignore` it came from.
+    base: PathBuf,
+    pattern: Pattern,
+    negated: bool,
+    dir_only: bool,
+    /// Matched against the path from `base` rather than just the name.
+    anchored: bool,
+}
+
+/// The rules of a set of `.gitignore` files.
+#[derive(Default)]
+pub struct Gitignore {
+    rules: Vec<Rule>,
+}
+
+impl Gitignore {
+    /// Read the `.gitignore` files for a walk of `root` that found `files`:
+    /// those among `files`, plus any in the directories between `root` and
+    /// the root of the git repository containing it.
+    pub fn load(root: &Path, files: &[PathBuf], fs: &impl Fs) -> Result<Self> {
+        let mut paths: Vec<PathBuf> = Vec::new();
+        let in_repo_root = fs.exists(&root.join(".git"));
+        for dir in root.ancestors().skip(1).take_while(|_| !in_repo_root) {
+            let candidate = dir.join(GITIGNORE);
+            if fs.is_file(&candidate) {
+                paths.push(candidate);
+            }
+            if fs.exists(&dir.join(".git")) {
+                break;
+            }
+            if dir.parent().is_none() {
+                // Not inside a repository, so ancestors' files don't apply
+                paths.clear();
+            }
+        }
+        paths.reverse();
+        let mut walked: Vec<&PathBuf> = files
+            .iter()
+            .filter(|f| f.file_name().is_some_and(|n| n == GITIGNORE))
+            .collect();
+        walked.sort_by_key(|f| f.components().count());
+        paths.extend(walked.into_iter().cloned());
+
+        let mut gitignore = Gitignore::default();
+        for path in &paths {
+            let content = fs
+                .read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let base = path.parent().unwrap_or(Path::new("/"));
+            gitignore.add(base, &content);
+        }
+        Ok(gitignore)
+    }
+
+    /// Add the rules in `content`, a `.gitignore` in `base`.
+    pub fn add(&mut self, base: &Path, content: &str) {
+        for line in content.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let line = line.strip_prefix("**/").unwrap_or(line);
+            let anchored = line.contains('/');
+            let line = line.strip_prefix('/').unwrap_or(line);
+            match Pattern::new(line) {
+                Ok(pattern) => self.rules.push(Rule {
+                    base: base.to_path_buf(),
+                    pattern,
+                    negated,
+                    dir_only,
+                    anchored,
+                }),
+                Err(e) => warn!(
+                    "Ignoring bad pattern {line} in {}: {e}",
+                    base.join(GITIGNORE).display()
+                ),
+            }
+        }
+    }
+
+    /// Whether the file at `path` is ignored.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let mut ignored = false;
+        for rule in &self.rules {
+            let Ok(relative) = path.strip_prefix(&rule.base) else {
+                continue;
+            };
+            // The file itself, then each directory above it
+            let matched = relative
+                .ancestors()
+                .filter(|p| !p.as_os_str().is_empty())
+                .any(|p| {
+                    if rule.dir_only && p == relative {
+                        return false;
+                    }
+                    if rule.anchored {
+                        rule.pattern.matches_path_with(p, OPTIONS)
+                    } else {
+                        p.file_name().is_some_and(|name| {
+                            rule.pattern.matches_with(&name.to_string_lossy(), OPTIONS)
+                        })
+                    }
+                });
+            if matched {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn matches_names_anchors_dirs_and_negation() {
+        let mut gitignore = Gitignore::default();
+        gitignore.add(
+            Path::new("/repo"),
+            "# build output\n*.log\n!keep.log\n/build\npack/\nlua/**/gen.lua\n",
+        );
+        let ignored = |p: &str| gitignore.is_ignored(Path::new(p));
+        assert!(ignored("/repo/a.log"));
+        assert!(ignored("/repo/deep/b.log"));
+        assert!(!ignored("/repo/deep/keep.log"));
+        assert!(ignored("/repo/build/out.txt"));
+        assert!(!ignored("/repo/src/build"));
+        assert!(ignored("/repo/src/pack/start/plugin.lua"));
+        assert!(!ignored("/repo/pack"));
+        assert!(ignored("/repo/lua/a/b/gen.lua"));
+        assert!(!ignored("/repo/init.lua"));
+        assert!(!ignored("/elsewhere/a.log"));
+    }
+
+    #[test]
+    fn loads_nested_and_repo_ancestor_files() {
+        let fs = setup_fs();
+        fs.add_file("/home/test/.config/.git/HEAD", "ref");
+        fs.add_file("/home/test/.config/.gitignore", "*.bak\n");
+        fs.add_file("/home/test/.config/nvim/lua/.gitignore", "cache/\n");
+        let root = Path::new("/home/test/.config/nvim");
+        let files = vec![PathBuf::from("/home/test/.config/nvim/lua/.gitignore")];
+
+        let gitignore = Gitignore::load(root, &files, &fs).unwrap();
+        assert!(gitignore.is_ignored(Path::new("/home/test/.config/nvim/init.bak")));
+        assert!(gitignore.is_ignored(Path::new("/home/test/.config/nvim/lua/cache/x.lua")));
+        assert!(!gitignore.is_ignored(Path::new("/home/test/.config/nvim/init.lua")));
+    }
+}
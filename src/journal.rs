@@ -0,0 +1,291 @@
+//! Write-ahead journal for deploy and undeploy.
+//!
+//! Deploy and undeploy change a target first and save state second, so a
+//! crash in between leaves state describing the old filesystem. To close that
+//! gap, each one records its intent in `.janus_journal.toml` before touching
+//! the target ([`begin`]) and clears it once state is saved ([`commit`]).
+//!
+//! Every command that changes anything first calls [`recover`], which
+//! replays whatever is left over: it looks at each journaled target and
+//! records what actually happened, so that command picks up where the
+//! crashed one stopped. Read-only commands and `--dry-run` leave the journal
+//! alone, so they never write state.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::paths::expand_tilde;
+use crate::platform::Fs;
+use crate::state::{BackupEntry, State, content_hash};
+
+/// File name of the journal within the state directory.
+pub const JOURNAL_FILE: &str = ".janus_journal.toml";
+
+/// The journal file: changes started but not yet reflected in state.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Journal {
+    #[serde(default)]
+    pending: Vec<Intent>,
+}
+
+/// What a journaled change does to its target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Point the target at `link`.
+    Deploy,
+    /// Replace or remove the target's symlink to `link`.
+    Undeploy,
+}
+
+/// One change to a deploy target, recorded before it's made.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Intent {
+    pub action: Action,
+    /// The file's key in state: its `name`, else its relative source path.
+    pub src: String,
+    /// Target path as state records it (may contain `~`).
+    pub target: String,
+    /// Where the target's symlink points when deployed.
+    pub link: PathBuf,
+    /// Whether `link` is the source itself (a direct file).
+    #[serde(default)]
+    pub direct: bool,
+    /// The original backup deploy makes of a conflicting target, as state
+    /// records it. Under `[backup] dir`, the backup it makes this time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<String>,
+    /// When a backup under `[backup] dir` was stamped, so replay can list
+    /// it for `janus backups`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_at: Option<u64>,
+}
+
+fn journal_path(state_dir: &Path) -> PathBuf {
+    state_dir.join(JOURNAL_FILE)
+}
+
+fn load(path: &Path, fs: &impl Fs) -> Result<Journal> {
+    if !fs.exists(path) {
+        return Ok(Journal::default());
+    }
+    let contents = fs
+        .read_to_string(path)
+        .with_context(|| format!("Failed to read journal: {}", path.display()))?;
+    toml::from_str(&contents)
+        .with_context(|| format!("Failed to parse journal: {}", path.display()))
+}
+
+fn save(path: &Path, journal: &Journal, fs: &impl Fs) -> Result<()> {
+    if journal.pending.is_empty() {
+        if fs.exists(path) {
+            fs.remove_file(path)
+                .with_context(|| format!("Failed to remove journal: {}", path.display()))?;
+        }
+        return Ok(());
+    }
+    let contents = toml::to_string_pretty(journal).context("Failed to serialize journal")?;
+    fs.write(path, contents.as_bytes())
+        .with_context(|| format!("Failed to write journal: {}", path.display()))
+}
+
+/// Record `intent` before making the change it describes.
+pub fn begin(state_dir: &Path, intent: Intent, fs: &impl Fs) -> Result<()> {
+    let path = journal_path(state_dir);
+    let mut journal = load(&path, fs)?;
+    journal.pending.retain(|p| p.src != intent.src);
+    journal.pending.push(intent);
+    save(&path, &journal, fs)
+}
+
+/// Clear the intent for `src` once state reflects it.
+pub fn commit(state_dir: &Path, src: &str, fs: &impl Fs) -> Result<()> {
+    let path = journal_path(state_dir);
+    let mut journal = load(&path, fs)?;
+    journal.pending.retain(|p| p.src != src);
+    save(&path, &journal, fs)
+}
+
+/// Replay the journal left by an interrupted deploy or undeploy into the
+/// saved state, then remove it. A no-op without a journal.
+pub fn recover(state_dir: &Path, fs: &impl Fs) -> Result<()> {
+    let path = journal_path(state_dir);
+    if !fs.exists(&path) {
+        return Ok(());
+    }
+    let mut state = State::load(state_dir, fs)?;
+    if replay(&mut state, state_dir, fs)? {
+        state
+            .save(fs)
+            .context("Failed to save state recovered from the deploy journal")?;
+    }
+    save(&path, &Journal::default(), fs)
+}
+
+/// Update `state` to match the filesystem for every intent left in the
+/// journal. Returns whether there were any.
+fn replay(state: &mut State, state_dir: &Path, fs: &impl Fs) -> Result<bool> {
+    let journal = load(&journal_path(state_dir), fs)?;
+    for intent in &journal.pending {
+        let target_path = expand_tilde(&intent.target, fs);
+        let linked = fs
+            .read_link(&target_path)
+            .is_ok_and(|dest| dest == intent.link);
+        match intent.action {
+            Action::Deploy if linked => {
+                info!(
+                    "Recovered interrupted deploy: {} -> {}",
+                    intent.src, intent.target
+                );
+                state.add_deployed(intent.src.clone(), intent.target.clone());
+                if let Some(backup) = &intent.backup
+                    && fs.exists(&expand_tilde(backup, fs))
+                {
+                    state.record_backup(&intent.src, backup.clone());
+                    if let Some(at) = intent.backup_at
+                        && !state.backups.iter().any(|b| b.path == *backup)
+                    {
+                        state.backups.push(BackupEntry {
+                            src: intent.src.clone(),
+                            target: intent.target.clone(),
+                            path: backup.clone(),
+                            at,
+                        });
+                    }
+                }
+                if intent.direct
+                    && let Ok(bytes) = fs.read(&intent.link)
+                {
+                    state.set_source_hash(&intent.src, content_hash(&bytes));
+                }
+            }
+            Action::Undeploy if !linked && state.is_deployed(&intent.src) => {
+                info!(
+                    "Recovered interrupted undeploy: {} from {}",
+                    intent.src, intent.target
+                );
+                state.remove_deployed(&intent.src);
+            }
+            // The change never happened, or state already has it
+            _ => {}
+        }
+    }
+    Ok(!journal.pending.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    fn intent(action: Action) -> Intent {
+        Intent {
+            action,
+            src: "a.conf".to_string(),
+            target: "~/.config/a.conf".to_string(),
+            link: PathBuf::from(format!("{DOTFILES}/.staged/a.conf")),
+            direct: false,
+            backup: Some("~/.config/a.conf.janus.bak".to_string()),
+            backup_at: None,
+        }
+    }
+
+    #[test]
+    fn begin_and_commit_round_trip() {
+        let fs = setup_fs();
+        let dotfiles = Path::new(DOTFILES);
+        begin(dotfiles, intent(Action::Deploy), &fs).unwrap();
+        assert!(fs.exists(&journal_path(dotfiles)));
+        commit(dotfiles, "a.conf", &fs).unwrap();
+        assert!(!fs.exists(&journal_path(dotfiles)));
+    }
+
+    #[test]
+    fn interrupted_deploy_is_recorded_on_recover() {
+        let fs = setup_fs();
+        let dotfiles = Path::new(DOTFILES);
+        fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "a");
+        fs.add_file(format!("{HOME}/.config/a.conf.janus.bak"), "old");
+        begin(dotfiles, intent(Action::Deploy), &fs).unwrap();
+        // Crash after linking, before saving state
+        fs.add_symlink(
+            format!("{HOME}/.config/a.conf"),
+            format!("{DOTFILES}/.staged/a.conf"),
+        );
+
+        // Loading alone leaves the journal for a command that writes
+        assert!(!State::load(dotfiles, &fs).unwrap().is_deployed("a.conf"));
+        assert!(fs.exists(&journal_path(dotfiles)));
+
+        recover(dotfiles, &fs).unwrap();
+        let state = State::load(dotfiles, &fs).unwrap();
+        assert!(state.is_deployed("a.conf"));
+        assert_eq!(
+            state.deployed[0].backup.as_deref(),
+            Some("~/.config/a.conf.janus.bak")
+        );
+        assert!(!fs.exists(&journal_path(dotfiles)));
+        assert!(
+            fs.read_to_string(Path::new(&format!("{DOTFILES}/.janus_state.toml")))
+                .unwrap()
+                .contains("a.conf")
+        );
+    }
+
+    #[test]
+    fn interrupted_deploy_lists_its_dir_backup() {
+        let fs = setup_fs();
+        let dotfiles = Path::new(DOTFILES);
+        let backup = "~/backups/home/test/.config/a.conf.2024-05-01T12-00-00";
+        fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "a");
+        fs.add_file(expand_tilde(backup, &fs), "old");
+        let intent = Intent {
+            backup: Some(backup.to_string()),
+            backup_at: Some(1_714_564_800),
+            ..intent(Action::Deploy)
+        };
+        begin(dotfiles, intent, &fs).unwrap();
+        fs.add_symlink(
+            format!("{HOME}/.config/a.conf"),
+            format!("{DOTFILES}/.staged/a.conf"),
+        );
+
+        recover(dotfiles, &fs).unwrap();
+        let state = State::load(dotfiles, &fs).unwrap();
+        assert_eq!(state.deployed[0].backup.as_deref(), Some(backup));
+        assert_eq!(state.backups.len(), 1);
+        assert_eq!(state.backups[0].path, backup);
+        assert_eq!(state.backups[0].target, "~/.config/a.conf");
+        assert_eq!(state.backups[0].at, 1_714_564_800);
+    }
+
+    #[test]
+    fn deploy_that_never_happened_is_dropped() {
+        let fs = setup_fs();
+        let dotfiles = Path::new(DOTFILES);
+        begin(dotfiles, intent(Action::Deploy), &fs).unwrap();
+        recover(dotfiles, &fs).unwrap();
+        let state = State::load(dotfiles, &fs).unwrap();
+        assert!(!state.is_deployed("a.conf"));
+        assert!(!fs.exists(&journal_path(dotfiles)));
+    }
+
+    #[test]
+    fn interrupted_undeploy_is_recorded_on_recover() {
+        let fs = setup_fs();
+        let dotfiles = Path::new(DOTFILES);
+        fs.add_file(
+            format!("{DOTFILES}/.janus_state.toml"),
+            "[[deployed]]\nsrc = \"a.conf\"\ntarget = \"~/.config/a.conf\"\n",
+        );
+        begin(dotfiles, intent(Action::Undeploy), &fs).unwrap();
+        // Crash after replacing the symlink with a copy
+        fs.add_file(format!("{HOME}/.config/a.conf"), "a");
+
+        recover(dotfiles, &fs).unwrap();
+        let state = State::load(dotfiles, &fs).unwrap();
+        assert!(!state.is_deployed("a.conf"));
+    }
+}
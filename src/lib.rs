@@ -0,0 +1,92 @@
+//! Janus — a two-way dotfile manager, as a library.
+//!
+//! The `janus` binary is a thin CLI over this crate; other tools (GUIs,
+//! status bar widgets, editor plugins) can drive the same pipeline directly
+//! instead of shelling out and parsing its output.
+//!
+//! # Public API
+//!
+//! - [`config::Config`] loads and validates `config.toml` and selects files
+//!   ([`Config::filter_files`](config::Config::filter_files),
+//!   [`ops::resolve_file_selection`]).
+//! - [`ops`] has one module per command. Each exposes a `run()` taking the
+//!   config and the [`platform`] implementations it needs; read-only commands
+//!   also expose a `compute()` returning structured results, such as
+//!   [`ops::status::compute`] and [`ops::diff::compute`].
+//! - [`platform`] defines the traits every side effect goes through
+//!   ([`platform::Fs`], [`platform::SecretEngine`], [`platform::Prompter`],
+//!   [`platform::Locker`], [`platform::CommandRunner`]) and their real
+//!   implementations.
+//! - [`state::State`] reads `.janus_state.toml`: what's deployed, and when
+//!   each file was last generated and synced.
+//! - [`lock::acquire_lock`] takes the same process lock as the CLI, so a
+//!   tool and a running `janus` don't step on each other.
+//! - [`undo::RecordingFs`] wraps an [`platform::Fs`] to make a run undoable
+//!   with `janus undo`.
+//! - With the `test-util` feature, [`platform`] also exports in-memory fakes
+//!   of each trait (`FakeFs`, `FakePrompter`, ...) and `testing::Fixture`
+//!   builds a dotfiles directory on them in one call.
+//!
+//! Errors are [`anyhow::Error`]s. Ops that process files independently
+//! (generate, stage, sync, clean) fail with an [`ops::FileErrors`] listing
+//! each file that failed, which callers can downcast to.
+//!
+//! Progress is reported through [`tracing`] events under the `janus` target;
+//! install a subscriber to see them.
+//!
+//! The remaining modules are implementation details shared between ops and
+//! the CLI; they're public so the binary can use them, but hidden from the
+//! docs and may change without notice.
+
+pub mod config;
+pub mod lock;
+pub mod ops;
+pub mod paths;
+pub mod platform;
+pub mod state;
+pub mod undo;
+
+#[doc(hidden)]
+pub mod alias;
+#[doc(hidden)]
+pub mod gitignore;
+#[doc(hidden)]
+pub mod journal;
+#[doc(hidden)]
+pub mod on_failure;
+#[doc(hidden)]
+pub mod output;
+#[doc(hidden)]
+pub mod plan;
+#[doc(hidden)]
+pub mod policy;
+#[doc(hidden)]
+pub mod progress;
+#[doc(hidden)]
+pub mod regions;
+#[doc(hidden)]
+pub mod reload;
+#[doc(hidden)]
+pub mod secrets;
+mod shell;
+#[doc(hidden)]
+pub mod status_cache;
+#[doc(hidden)]
+pub mod store;
+#[doc(hidden)]
+pub mod systemd;
+#[doc(hidden)]
+pub mod template;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+#[doc(hidden)]
+pub mod timings;
+#[doc(hidden)]
+pub mod trash;
+#[doc(hidden)]
+pub mod vars_script;
+
+#[cfg(all(test, feature = "real-fs-tests"))]
+mod crosscheck;
+#[cfg(test)]
+mod test_helpers;
@@ -0,0 +1,209 @@
+//! Tracing setup: console output plus an optional log file.
+//!
+//! The console layer honors `-v`/`-q` (or `RUST_LOG`). The file layer has
+//! its own level, `trace` unless `[logging] level` says otherwise, so a log
+//! file can capture everything while the terminal stays quiet. The file comes
+//! from `--log-file`, which is opened before the config loads, or else from
+//! `[logging] file` once the config is available. Until one is opened the
+//! file layer's filter is `off`, so it enables nothing and trace-level
+//! events cost nothing without a log file. Read-only runs never open one.
+
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use tracing_subscriber::filter::EnvFilter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::reload;
+
+use janus::config::LoggingConfig;
+use janus::paths::expand_tilde;
+use janus::plan::PlanLayer;
+use janus::progress::ProgressLayer;
+use janus::timings::TimingsLayer;
+
+/// Default level for the log file.
+pub const DEFAULT_FILE_LEVEL: &str = "trace";
+
+/// Handle to the log file layer, used to open the file and set its level
+/// after tracing is initialized.
+pub struct LogFile {
+    file: Arc<Mutex<Option<File>>>,
+    filter: reload::Handle<EnvFilter, tracing_subscriber::Registry>,
+    readonly: bool,
+}
+
+impl LogFile {
+    /// Start appending logs to `path` at `level` (e.g. `debug`), creating the
+    /// file and its parent directories if needed. Does nothing in read-only
+    /// mode.
+    pub fn open(&self, path: &Path, level: &str) -> Result<()> {
+        // Read-only runs are for inspecting, so they write no log either
+        if self.readonly {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create log directory: {}", parent.display()))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open log file: {}", path.display()))?;
+        self.set_level(level)?;
+        *self.file.lock().unwrap_or_else(PoisonError::into_inner) = Some(file);
+        Ok(())
+    }
+
+    /// Change the log file's level.
+    pub fn set_level(&self, level: &str) -> Result<()> {
+        self.filter
+            .reload(file_filter(level)?)
+            .context("Failed to set log file level")
+    }
+
+    /// Apply the `[logging]` config. A `--log-file` given on the command
+    /// line is already open and wins over `[logging] file`; the configured
+    /// level applies either way, once a file is open.
+    pub fn configure(
+        &self,
+        config: &LoggingConfig,
+        cli_file: bool,
+        fs: &impl janus::platform::Fs,
+    ) -> Result<()> {
+        let level = config.level.as_deref().unwrap_or(DEFAULT_FILE_LEVEL);
+        match &config.file {
+            Some(file) if !cli_file => self.open(&expand_tilde(file, fs), level),
+            _ if self.is_open() => self.set_level(level),
+            _ => Ok(()),
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.file
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some()
+    }
+}
+
+/// Parse a level like `debug` into a filter for janus's own events.
+fn file_filter(level: &str) -> Result<EnvFilter> {
+    EnvFilter::try_new(format!("janus={level}"))
+        .with_context(|| format!("Invalid log level: {level}"))
+}
+
+/// Install the global subscriber with console output at `console_filter`
+/// and a log file layer that stays off until [`LogFile::open`], plus
+/// `timings` for `--timings` and `progress` for progress bars. With
+/// `readonly`, the log file is never opened.
+pub fn init(
+    console_filter: EnvFilter,
+    timings: Option<TimingsLayer>,
+    progress: Option<ProgressLayer>,
+    plan: Option<PlanLayer>,
+    readonly: bool,
+) -> LogFile {
+    let console = progress
+        .as_ref()
+        .map(ProgressLayer::writer)
+        .unwrap_or_default();
+    let file = Arc::new(Mutex::new(None));
+    let (filter, handle) = reload::Layer::new(file_filter("off").expect("off is a valid level"));
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(FileWriter(file.clone()))
+                .with_filter(filter),
+        )
+        .with(
+            tracing_subscriber::fmt::layer()
+                .without_time()
+                .with_writer(console)
+                .with_filter(console_filter),
+        )
+        .with(timings)
+        .with(progress)
+        .with(plan)
+        .init();
+    LogFile {
+        file,
+        filter: handle,
+        readonly,
+    }
+}
+
+/// Writes to the log file once it's open; discards output before that.
+#[derive(Clone)]
+struct FileWriter(Arc<Mutex<Option<File>>>);
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_mut()
+        {
+            Some(file) => file.write(buf),
+            None => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_mut()
+        {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for FileWriter {
+    type Writer = FileWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_filter_accepts_levels() {
+        assert!(file_filter("debug").is_ok());
+        assert!(file_filter(DEFAULT_FILE_LEVEL).is_ok());
+        assert!(file_filter("bogus").is_err());
+    }
+
+    #[test]
+    fn writer_discards_until_open() {
+        let mut writer = FileWriter(Arc::new(Mutex::new(None)));
+        assert_eq!(writer.write(b"dropped").unwrap(), 7);
+        writer.flush().unwrap();
+    }
+
+    #[test]
+    fn readonly_never_opens_a_file() {
+        let (_, filter) = reload::Layer::new(file_filter("off").unwrap());
+        let log_file = LogFile {
+            file: Arc::new(Mutex::new(None)),
+            filter,
+            readonly: true,
+        };
+        let path = std::env::temp_dir().join(format!("janus-readonly-{}.log", std::process::id()));
+        log_file.open(&path, DEFAULT_FILE_LEVEL).unwrap();
+        assert!(!log_file.is_open());
+        assert!(!path.exists());
+    }
+}
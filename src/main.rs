@@ -3,76 +3,650 @@
 //! Provides a three-stage pipeline (generate -> stage -> deploy) for managing
 //! dotfiles with template rendering, plus reverse operations (import, undeploy,
 //! unimport) for bringing existing configs under management or removing them.
+//!
+//! This binary parses the command line, sets up logging and the process
+//! lock, and hands off to the `janus` library crate, which does the work.
 
 mod cli;
-mod config;
-mod lock;
-mod ops;
-mod paths;
-mod platform;
-mod secrets;
-mod state;
-#[allow(clippy::items_after_test_module)]
-#[cfg(test)]
-mod test_helpers;
+mod logging;
 
+use std::ffi::OsString;
+use std::io::IsTerminal;
+use std::path::Path;
 use std::time::Duration;
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
 use clap::{CommandFactory, Parser};
+use tracing::warn;
 use tracing_subscriber::EnvFilter;
 
-use cli::{Cli, Command};
-use config::Config;
-use platform::{RealFs, RealLocker, RealPrompter, RealSecretEngine};
-
-/// Resolve file selection from explicit files, `--all`, or `--filesets`.
-///
-/// Exactly one source must be provided. Returns `None` for "all files",
-/// or `Some(patterns)` for explicit files or resolved filesets.
-fn resolve_file_selection(
-    files: Vec<String>,
-    all: bool,
-    filesets: Vec<String>,
+use cli::{
+    BackupsCommand, Cli, Command, ConfigCommand, FilesetCommand, SecretsCommand, StateCommand,
+    TrashCommand, VarsCommand,
+};
+use janus::config::Config;
+use janus::ops::{self, resolve_file_selection};
+use janus::output::{self, Output};
+use janus::plan::{self, Format as PlanFormat};
+use janus::platform::{
+    CommandRunner, Fs, PackedFs, Prompter, ReadonlyFs, RealCommandRunner, RealFs, RealHttpClient,
+    RealLocker, RealPrompter, RealSecretEngine, SecretEngine,
+};
+use janus::progress;
+use janus::secrets::{SecretMask, SkipSecrets};
+use janus::state::{self, State};
+use janus::timings::{self, Recorder};
+use janus::{alias, journal, lock, on_failure, template, undo};
+
+/// Run a command that needs a loaded, locked config (everything except
+/// `init`, `completions`, and `check`).
+#[allow(clippy::too_many_arguments)]
+fn dispatch(
+    command: Command,
     config: &Config,
-) -> Result<Option<Vec<String>>> {
-    let sources = [!files.is_empty(), all, !filesets.is_empty()]
-        .iter()
-        .filter(|&&b| b)
-        .count();
+    config_path: &Path,
+    dry_run: bool,
+    json_plan: bool,
+    no_pager: bool,
+    yes: bool,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    prompter: &impl Prompter,
+    runner: &impl CommandRunner,
+) -> Result<()> {
+    // Under --format json stdout holds only the plan, so summaries,
+    // listings, and prompts go to stderr
+    let console = || {
+        if json_plan {
+            Output::Stderr(std::io::stderr())
+        } else {
+            Output::Stdout(std::io::stdout())
+        }
+    };
+    let paged = || {
+        if json_plan {
+            Output::Stderr(std::io::stderr())
+        } else {
+            Output::paged(config.ui.pager.as_deref(), no_pager)
+        }
+    };
+    // Safe mode: ask before touching more than `confirm_threshold` files
+    let confirm = |action: &str, files: Option<&[String]>| {
+        let srcs: Vec<String> = config
+            .filter_files(files)
+            .into_iter()
+            .map(|e| e.src.clone())
+            .collect();
+        ops::confirm_bulk(config, action, &srcs, yes, dry_run, prompter)
+    };
+    // Secret values to hide from diff and sync output
+    let secret_mask = |files: Option<&[String]>, show_secrets: bool| {
+        if show_secrets {
+            SecretMask::default()
+        } else {
+            ops::generate::secret_mask(config, files, fs, engine)
+        }
+    };
 
-    if sources > 1 {
-        bail!("Cannot combine explicit files, --all, and --filesets");
+    match command {
+        Command::Generate {
+            files,
+            all,
+            filesets,
+            explain,
+            and_stage,
+            and_deploy,
+        } => {
+            if let Some(file) = explain {
+                ops::explain::run(config, &file, fs, engine, runner)?;
+            } else if and_stage {
+                let files = resolve_file_selection(files, all, filesets, config)?;
+                ops::generate::run_and_forward(
+                    config,
+                    files.as_deref(),
+                    and_deploy,
+                    dry_run,
+                    fs,
+                    engine,
+                    runner,
+                )?;
+            } else {
+                let files = resolve_file_selection(files, all, filesets, config)?;
+                ops::generate::run(config, files.as_deref(), dry_run, fs, engine, runner)?;
+            }
+        }
+        Command::Stage {
+            files,
+            all,
+            filesets,
+        } => {
+            let files = resolve_file_selection(files, all, filesets, config)?;
+            ops::stage::run(config, files.as_deref(), dry_run, fs)?;
+        }
+        Command::Deploy {
+            files,
+            all,
+            force,
+            interactive,
+            keep_going,
+            filesets,
+        } => {
+            let files = resolve_file_selection(files, all, filesets, config)?;
+            confirm("deploy", files.as_deref())?;
+            let files = files.as_deref();
+            match (interactive, keep_going) {
+                (true, false) => ops::deploy::run_interactive(
+                    config,
+                    files,
+                    dry_run,
+                    fs,
+                    prompter,
+                    runner,
+                    &mut console(),
+                )?,
+                (true, true) => ops::deploy::run_interactive_keep_going(
+                    config,
+                    files,
+                    dry_run,
+                    fs,
+                    prompter,
+                    runner,
+                    &mut console(),
+                )?,
+                (false, false) => ops::deploy::run(config, files, force, dry_run, fs, runner)?,
+                (false, true) => {
+                    ops::deploy::run_keep_going(config, files, force, dry_run, fs, runner)?
+                }
+            }
+        }
+        Command::Diff {
+            files,
+            all,
+            filesets,
+            from,
+            to,
+            remote,
+            show_secrets,
+        } => {
+            let files = resolve_file_selection(files, all, filesets, config)?;
+            let to = remote.map_or(to, ops::diff::Stage::Remote);
+            let mask = secret_mask(files.as_deref(), show_secrets);
+            let mut out = paged();
+            ops::diff::run(
+                config,
+                files.as_deref(),
+                &from,
+                &to,
+                fs,
+                engine,
+                runner,
+                &mask,
+                &mut out,
+            )?;
+        }
+        Command::Clean {
+            generated,
+            orphans,
+            filesets,
+        } => {
+            let patterns = if filesets.is_empty() {
+                None
+            } else {
+                Some(config.resolve_filesets(&filesets)?)
+            };
+            if generated {
+                let files = ops::clean::generated_files(config, patterns.as_deref(), fs)?;
+                ops::confirm_bulk(config, "clean", &files, yes, dry_run, prompter)?;
+            }
+            ops::clean::run(config, generated, orphans, patterns.as_deref(), dry_run, fs)?;
+        }
+        Command::Import {
+            path,
+            as_src,
+            sha256,
+            ..
+        } if ops::import::is_url(&path) => {
+            ops::import::run_url(
+                config,
+                config_path,
+                &path,
+                as_src.as_deref(),
+                sha256.as_deref(),
+                dry_run,
+                fs,
+                engine,
+                runner,
+                &RealHttpClient,
+            )?;
+        }
+        Command::Import {
+            path,
+            as_src,
+            sha256,
+            all,
+            max_depth,
+            no_follow_links,
+            no_gitignore,
+            max_file_size,
+            max_total_size,
+            max_files,
+            forget,
+        } => {
+            if as_src.is_some() || sha256.is_some() {
+                bail!("--as and --sha256 only apply when importing a URL");
+            }
+            ops::import::run(
+                config,
+                config_path,
+                &path,
+                &ops::import::ImportOptions {
+                    all,
+                    max_depth,
+                    follow_links: !no_follow_links,
+                    gitignore: !no_gitignore,
+                    limits: ops::import::Limits {
+                        max_file_size,
+                        max_total_size,
+                        max_files,
+                    },
+                    yes,
+                    forget,
+                },
+                dry_run,
+                fs,
+                engine,
+                prompter,
+                runner,
+            )?;
+        }
+        Command::New {
+            src,
+            target,
+            stdin,
+            edit,
+        } => {
+            let contents = if stdin {
+                std::io::read_to_string(std::io::stdin())?
+            } else {
+                String::new()
+            };
+            let path = ops::new::run(
+                config,
+                config_path,
+                &src,
+                target.as_deref(),
+                &contents,
+                dry_run,
+                fs,
+            )?;
+            if edit && !dry_run {
+                open_editor(&path)?;
+            }
+        }
+        Command::Migrate { from, path } => {
+            ops::migrate::run(config, config_path, from, &path, dry_run, fs, runner)?;
+        }
+        Command::Apply {
+            files,
+            all,
+            force,
+            interactive,
+            filesets,
+            report,
+        } => {
+            let files = resolve_file_selection(files, all, filesets, config)?;
+            confirm("apply", files.as_deref())?;
+            ops::apply::run_with_report(
+                config,
+                files.as_deref(),
+                force,
+                interactive,
+                dry_run,
+                report.as_deref(),
+                &mut console(),
+                fs,
+                engine,
+                prompter,
+                runner,
+            )?;
+        }
+        Command::Undeploy {
+            files,
+            all,
+            remove_file,
+            filesets,
+        } => {
+            let files = resolve_file_selection(files, all, filesets, config)?;
+            confirm("undeploy", files.as_deref())?;
+            ops::undeploy::run(config, files.as_deref(), remove_file, dry_run, fs, runner)?;
+        }
+        Command::Disable { files, remove_file } => {
+            ops::enable::disable(
+                config,
+                config_path,
+                &files,
+                remove_file,
+                dry_run,
+                fs,
+                runner,
+            )?;
+        }
+        Command::Enable { files } => {
+            ops::enable::enable(
+                config,
+                config_path,
+                &files,
+                dry_run,
+                fs,
+                engine,
+                prompter,
+                runner,
+            )?;
+        }
+        Command::Unimport {
+            files,
+            remove_file,
+            tombstone,
+            archive,
+            filesets,
+        } => {
+            let files = if !filesets.is_empty() {
+                if !files.is_empty() {
+                    bail!("Cannot combine explicit files and --filesets");
+                }
+                let files = config.resolve_filesets(&filesets)?;
+                ops::unimport::confirm_filesets(config, &filesets, &files, yes, dry_run, prompter)?;
+                files
+            } else {
+                if files.is_empty() {
+                    bail!("Specify files to unimport or use --filesets");
+                }
+                confirm("unimport", Some(files.as_slice()))?;
+                files
+            };
+            ops::unimport::run(
+                config,
+                config_path,
+                &files,
+                remove_file,
+                tombstone,
+                archive,
+                dry_run,
+                fs,
+                runner,
+            )?;
+        }
+        Command::Sync {
+            files,
+            all,
+            filesets,
+            show_secrets,
+            reset_decisions,
+        } => {
+            let files = resolve_file_selection(files, all, filesets, config)?;
+            let mask = secret_mask(files.as_deref(), show_secrets);
+            ops::sync::run(
+                config,
+                files.as_deref(),
+                reset_decisions,
+                dry_run,
+                fs,
+                prompter,
+                &mask,
+                &mut console(),
+            )?;
+        }
+        Command::Status {
+            files,
+            all,
+            only_diffs,
+            deployed,
+            undeployed,
+            stale,
+            long,
+            filesets,
+        } => {
+            let files = resolve_file_selection(files, all, filesets, config)?;
+            let format = if long {
+                ops::status::Format::Long {
+                    width: output::terminal_width(),
+                    color: std::io::stdout().is_terminal(),
+                }
+            } else {
+                ops::status::Format::Short
+            };
+            let mut out = paged();
+            ops::status::run(
+                config,
+                files.as_deref(),
+                ops::status::StatusFilters {
+                    only_diffs,
+                    deployed,
+                    undeployed,
+                    stale,
+                },
+                format,
+                fs,
+                &mut out,
+            )?;
+        }
+        Command::Fileset { action } => match action {
+            FilesetCommand::Create { name, patterns } => {
+                ops::fileset::create(config, config_path, &name, &patterns, dry_run, fs)?;
+            }
+            FilesetCommand::Add { name, pattern } => {
+                ops::fileset::add(config, config_path, &name, &pattern, dry_run, fs)?;
+            }
+            FilesetCommand::Rm { name } => {
+                ops::fileset::rm(config, config_path, &name, dry_run, fs)?;
+            }
+            FilesetCommand::List { files } => {
+                ops::fileset::list(config, files, &mut console())?;
+            }
+        },
+        Command::Undo { force } => {
+            ops::undo::run(config, force, dry_run, fs)?;
+        }
+        Command::State { action } => match action {
+            StateCommand::ImportExisting => {
+                ops::state::import_existing(config, yes, dry_run, fs, prompter, &mut console())?;
+            }
+            StateCommand::CompactIgnored { min } => {
+                ops::state::compact_ignored(
+                    config,
+                    min,
+                    yes,
+                    dry_run,
+                    fs,
+                    prompter,
+                    &mut console(),
+                )?;
+            }
+        },
+        Command::Backups { action } => match action {
+            BackupsCommand::List => {
+                ops::backups::list(config, fs, &mut console())?;
+            }
+            BackupsCommand::Restore { paths, original } => {
+                ops::backups::restore(config, &paths, original, dry_run, fs)?;
+            }
+        },
+        Command::RestoreArchived { srcs } => {
+            ops::archive::restore(config, config_path, &srcs, dry_run, fs)?;
+        }
+        Command::Trash { action } => match action {
+            TrashCommand::List => {
+                ops::trash::list(config, fs, &mut console())?;
+            }
+            TrashCommand::Restore { paths } => {
+                ops::trash::restore(config, &paths, dry_run, fs)?;
+            }
+        },
+        Command::Repair {
+            files,
+            all,
+            filesets,
+            modes,
+        } => {
+            let files = resolve_file_selection(files, all, filesets, config)?;
+            ops::repair::run(config, files.as_deref(), modes, dry_run, fs)?;
+        }
+        Command::Cat {
+            file,
+            stage,
+            show_secrets,
+        } => {
+            let entry = ops::cat::find_entry(config, &file, fs)?;
+            let mask = secret_mask(Some(std::slice::from_ref(&entry.src)), show_secrets);
+            ops::cat::run(config, entry, stage, fs, &mask, &mut console())?;
+        }
+        Command::Export {
+            files,
+            all,
+            filesets,
+            out,
+            redact_secrets,
+        } => {
+            let files = resolve_file_selection(files, all, filesets, config)?;
+            ops::export::run(
+                config,
+                files.as_deref(),
+                &out,
+                redact_secrets,
+                dry_run,
+                fs,
+                engine,
+                runner,
+            )?;
+        }
+        Command::Which { path } => {
+            ops::which::run(config, &path, fs, &mut console())?;
+        }
+        Command::Stats => {
+            ops::stats::run(config, fs, &mut console())?;
+        }
+        Command::Test { names } => {
+            ops::test::run(config, &names, fs, runner, &mut console())?;
+        }
+        Command::Vars { action } => match action {
+            VarsCommand::Docs => ops::vars::docs(config, fs, &mut console())?,
+            VarsCommand::Detect { output } => {
+                ops::vars::detect(config, output.as_deref(), dry_run, fs, runner)?;
+            }
+        },
+        Command::Secrets { action } => match action {
+            SecretsCommand::Rotate { name } => {
+                ops::secrets::rotate(config, &name, dry_run, fs, engine, runner, &mut console())?;
+            }
+        },
+        Command::Config { action } => match action {
+            ConfigCommand::Get { key } => {
+                ops::config::get(config_path, &key, fs, &mut console())?;
+            }
+            ConfigCommand::Set { key, value } => {
+                ops::config::set(config_path, &key, &value, dry_run, fs)?;
+            }
+            ConfigCommand::Unset { key } => {
+                ops::config::unset(config_path, &key, dry_run, fs)?;
+            }
+            ConfigCommand::AddFile { src, target } => {
+                ops::config::add_file(config, config_path, &src, target.as_deref(), dry_run, fs)?;
+            }
+            ConfigCommand::Schema { .. } => unreachable!(),
+        },
+        Command::Init { .. }
+        | Command::Completions { .. }
+        | Command::Check
+        | Command::ShellInit { .. } => unreachable!(),
     }
 
-    if all {
-        return Ok(None);
-    }
-    if !filesets.is_empty() {
-        return Ok(Some(config.resolve_filesets(&filesets)?));
+    Ok(())
+}
+
+/// Print `--timings` for this run next to the last recorded run of the same
+/// command, then record this one in the state file if `save`.
+fn report_timings(recorder: &Recorder, command: &str, config: &Config, save: bool, fs: &impl Fs) {
+    let timings = recorder.finish(command);
+    let mut state = match State::load(&config.state_dir(fs), fs) {
+        Ok(state) => Some(state),
+        Err(e) => {
+            warn!("Failed to load previous timings: {e:#}");
+            None
+        }
+    };
+    let previous = state.as_ref().and_then(|s| s.timings.as_ref());
+    // Nothing useful to do if stderr itself is gone
+    let _ = timings.write(previous, &mut std::io::stderr());
+    if save && let Some(state) = &mut state {
+        state.timings = Some(timings);
+        if let Err(e) = state.save(fs) {
+            warn!("Failed to save timings: {e:#}");
+        }
     }
-    if !files.is_empty() {
-        return Ok(Some(files));
+}
+
+/// Open `path` in `$VISUAL`, else `$EDITOR`, else `vi`, and wait for it.
+fn open_editor(path: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // Through the shell so editors configured with arguments ("code -w") work
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$1\""))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to run editor `{editor}`"))?;
+    if !status.success() {
+        bail!("Editor `{editor}` exited with {status}");
     }
+    Ok(())
+}
 
-    // No explicit source — fall back to default_targets from config
-    match config.default_targets.as_deref() {
-        Some("all") => Ok(None),
-        Some(filesets) => {
-            let names: Vec<String> = filesets.split(',').map(|s| s.trim().to_string()).collect();
-            Ok(Some(config.resolve_filesets(&names)?))
-        }
-        None => bail!("Specify files to process, --all, or --filesets"),
+/// The command line with any `[alias]` from the config expanded, ready for
+/// clap. The config is only read when the command isn't a built-in one.
+fn expand_aliases(fs: &impl Fs) -> Result<Vec<OsString>> {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let mut cmd = Cli::command();
+    // Adds the generated `help` subcommand, so it can't be aliased either
+    cmd.build();
+    let value_flags: Vec<String> = cmd
+        .get_arguments()
+        .filter(|arg| arg.get_action().takes_values())
+        .flat_map(|arg| {
+            let long = arg.get_long().map(|l| format!("--{l}"));
+            let short = arg.get_short().map(|s| format!("-{s}"));
+            long.into_iter().chain(short)
+        })
+        .collect();
+    let is_builtin = |name: &str| cmd.find_subcommand(name).is_some();
+    let Some(position) = alias::command_position(&args, &value_flags) else {
+        return Ok(args);
+    };
+    if args[position].to_str().is_none_or(is_builtin) {
+        return Ok(args);
     }
+    let config_path = alias::config_flag(&args).unwrap_or_else(|| Config::default_path(fs));
+    let aliases = alias::load(&config_path, fs)?;
+    alias::expand(args, position, &aliases, is_builtin)
 }
 
 fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let cli = Cli::parse_from(expand_aliases(&RealFs)?);
+    let json_plan = cli.format == PlanFormat::Json;
+    if json_plan && !cli.dry_run {
+        bail!("--format json only applies to --dry-run");
+    }
+    // The shell evaluates everything shell-init prints
+    let shell_script = matches!(cli.command, Command::ShellInit { .. });
 
     // Initialize tracing
     // verbosity is a signed level: positive = more verbose, negative = quieter
     let level = cli.verbose as i8 - cli.quiet as i8;
     let filter = match level {
+        // Console logs share stdout with the plan or script
+        _ if json_plan || shell_script => "janus=off",
         ..=-3 => "janus=off",
         -2 => "janus=error",
         -1 => "janus=warn",
@@ -80,290 +654,181 @@ fn main() -> Result<()> {
         1 => "janus=debug",
         2.. => "janus=trace",
     };
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(filter)),
-        )
-        .without_time()
-        .init();
+    let (timings_layer, recorder) = if cli.timings {
+        let (layer, recorder) = timings::layer();
+        (Some(layer), Some(recorder))
+    } else {
+        (None, None)
+    };
+    // Bars would garble piped output, and -q asks for silence
+    let progress_layer = (!cli.no_progress
+        && !json_plan
+        && !shell_script
+        && level >= 0
+        && std::io::stderr().is_terminal())
+    .then(progress::layer);
+    let (plan_layer, plan_recorder) = if json_plan {
+        let (layer, recorder) = plan::layer();
+        (Some(layer), Some(recorder))
+    } else {
+        (None, None)
+    };
+    let readonly =
+        cli.readonly || std::env::var("JANUS_READONLY").is_ok_and(|v| !v.is_empty() && v != "0");
+    let log_file = logging::init(
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(filter)),
+        timings_layer,
+        progress_layer,
+        plan_layer,
+        readonly,
+    );
+    if let Some(path) = &cli.log_file {
+        log_file.open(path, logging::DEFAULT_FILE_LEVEL)?;
+    }
 
-    let fs = RealFs;
-    let engine = RealSecretEngine;
+    if let Some(secs) = cli.frozen_time {
+        template::freeze_time(secs);
+    }
+
+    // Enforced under every command, so nothing can write even by mistake
+    let fs = ReadonlyFs::new(RealFs, readonly);
+    let engine = SkipSecrets::new(RealSecretEngine, cli.skip_secrets);
     let prompter = RealPrompter;
+    let runner = RealCommandRunner;
 
     match cli.command {
         Command::Init { dotfiles_dir } => {
             if cli.config.is_some() {
                 bail!("--config cannot be used with init (init creates the config)");
             }
-            ops::init::run(&dotfiles_dir, cli.dry_run, &fs, &engine)?;
+            ops::init::run(&dotfiles_dir, cli.dry_run, &fs, &engine, &prompter, &runner)?;
         }
         Command::Completions { shell } => {
             let mut cmd = Cli::command();
             clap_complete::generate(shell, &mut cmd, "janus", &mut std::io::stdout());
         }
-        command => {
+        Command::Config {
+            action: ConfigCommand::Schema { kind },
+        } => {
+            // Needs no config, so it works while writing the first one
+            ops::config::print_schema(kind)?;
+        }
+        Command::Check => {
+            // Read-only, and must work on configs that fail validation
+            let config_path = cli.config.unwrap_or_else(|| Config::default_path(&fs));
+            let mut config = Config::load_unchecked(&config_path, &fs)?;
+            log_file.configure(&config.logging, cli.log_file.is_some(), &fs)?;
+            config.allow_sensitive_targets = cli.allow_sensitive_targets;
+            ops::check::run(&config, &fs)?;
+        }
+        Command::ShellInit { shell } => {
+            // Runs on every shell start, so it never waits for the lock or
+            // migrates state; it only reads
             let config_path = cli.config.unwrap_or_else(|| Config::default_path(&fs));
             let config = Config::load(&config_path, &fs)?;
-
-            // Acquire process lock
-            let lock_path = config.dotfiles_dir(&fs).join(".janus.lock");
-            let mut locker = RealLocker::new(lock_path)?;
-            lock::acquire_lock(&mut locker, Duration::from_secs(cli.lock_timeout))?;
-
-            // Reload config under lock for consistency
+            log_file.configure(&config.logging, cli.log_file.is_some(), &fs)?;
+            let fs = PackedFs::new(&fs, config.packed_store.then(|| config.generated_dir(&fs)));
+            ops::shell_init::run(&config, shell, &fs, &mut std::io::stdout().lock())?;
+        }
+        command => {
+            let config_path = cli.config.unwrap_or_else(|| Config::default_path(&fs));
             let config = Config::load(&config_path, &fs)?;
+            log_file.configure(&config.logging, cli.log_file.is_some(), &fs)?;
+
+            // Everything from here on reports failures through the hook,
+            // including waiting for the lock and migrating state
+            let name = command.name();
+            on_failure::guard(
+                config.on_failure.as_ref(),
+                name,
+                cli.dry_run,
+                &runner,
+                || {
+                    // Acquire process lock (read-only runs can't create the
+                    // lock file, and can't race a writer into inconsistency
+                    // anyway)
+                    let _locker = if readonly {
+                        None
+                    } else {
+                        let state_dir = config.state_dir(&fs);
+                        fs.create_dir_all(&state_dir).with_context(|| {
+                            format!("Failed to create state directory: {}", state_dir.display())
+                        })?;
+                        let mut locker = RealLocker::new(state_dir.join(".janus.lock"))?;
+                        lock::acquire_lock(&mut locker, Duration::from_secs(cli.lock_timeout))?;
+                        Some(locker)
+                    };
 
-            match command {
-                Command::Generate {
-                    files,
-                    all,
-                    filesets,
-                } => {
-                    let files = resolve_file_selection(files, all, filesets, &config)?;
-                    ops::generate::run(&config, files.as_deref(), cli.dry_run, &fs, &engine)?;
-                }
-                Command::Stage {
-                    files,
-                    all,
-                    filesets,
-                } => {
-                    let files = resolve_file_selection(files, all, filesets, &config)?;
-                    ops::stage::run(&config, files.as_deref(), cli.dry_run, &fs)?;
-                }
-                Command::Deploy {
-                    files,
-                    all,
-                    force,
-                    filesets,
-                } => {
-                    let files = resolve_file_selection(files, all, filesets, &config)?;
-                    ops::deploy::run(&config, files.as_deref(), force, cli.dry_run, &fs)?;
-                }
-                Command::Diff {
-                    files,
-                    all,
-                    filesets,
-                } => {
-                    let files = resolve_file_selection(files, all, filesets, &config)?;
-                    ops::diff::run(&config, files.as_deref(), &fs)?;
-                }
-                Command::Clean { generated, orphans } => {
-                    ops::clean::run(&config, generated, orphans, cli.dry_run, &fs)?;
-                }
-                Command::Import {
-                    path,
-                    all,
-                    max_depth,
-                } => {
-                    ops::import::run(
-                        &config,
-                        &config_path,
-                        &path,
-                        all,
-                        max_depth,
-                        cli.dry_run,
-                        &fs,
-                        &engine,
-                        &prompter,
-                    )?;
-                }
-                Command::Apply {
-                    files,
-                    all,
-                    force,
-                    filesets,
-                } => {
-                    let files = resolve_file_selection(files, all, filesets, &config)?;
-                    ops::apply::run(&config, files.as_deref(), force, cli.dry_run, &fs, &engine)?;
-                }
-                Command::Undeploy {
-                    files,
-                    all,
-                    remove_file,
-                    filesets,
-                } => {
-                    let files = resolve_file_selection(files, all, filesets, &config)?;
-                    ops::undeploy::run(&config, files.as_deref(), remove_file, cli.dry_run, &fs)?;
-                }
-                Command::Unimport {
-                    files,
-                    remove_file,
-                    filesets,
-                } => {
-                    let files = if !filesets.is_empty() {
-                        if !files.is_empty() {
-                            bail!("Cannot combine explicit files and --filesets");
+                    // Reload config under lock for consistency
+                    let mut config = Config::load(&config_path, &fs)?;
+                    config.allow_sensitive_targets = cli.allow_sensitive_targets;
+                    config.readonly = readonly;
+                    let fs =
+                        PackedFs::new(&fs, config.packed_store.then(|| config.generated_dir(&fs)));
+                    if !readonly {
+                        // Follow `xdg_state` if it changed since the last run
+                        state::migrate(&config, &fs)?;
+                        if !cli.dry_run && !command.is_read_only() {
+                            journal::recover(&config.state_dir(&fs), &fs)?;
                         }
-                        config.resolve_filesets(&filesets)?
+                    }
+
+                    let result = if cli.dry_run || readonly || !command.records_undo() {
+                        dispatch(
+                            command,
+                            &config,
+                            &config_path,
+                            cli.dry_run,
+                            json_plan,
+                            cli.no_pager,
+                            cli.yes,
+                            &fs,
+                            &engine,
+                            &prompter,
+                            &runner,
+                        )
                     } else {
-                        if files.is_empty() {
-                            bail!("Specify files to unimport or use --filesets");
+                        // Record every change so `janus undo` can revert this
+                        // command, including the changes made before a failure
+                        let state_dir = config.state_dir(&fs);
+                        let recording = undo::RecordingFs::new(&fs, &state_dir);
+                        let result = dispatch(
+                            command,
+                            &config,
+                            &config_path,
+                            cli.dry_run,
+                            json_plan,
+                            cli.no_pager,
+                            cli.yes,
+                            &recording,
+                            &engine,
+                            &prompter,
+                            &runner,
+                        );
+                        let command_line = std::iter::once("janus".to_string())
+                            .chain(std::env::args().skip(1))
+                            .collect::<Vec<_>>()
+                            .join(" ");
+                        if let Err(e) = recording.save(&command_line) {
+                            warn!("Failed to save undo record: {e:#}");
                         }
-                        files
+                        result
                     };
-                    ops::unimport::run(
-                        &config,
-                        &config_path,
-                        &files,
-                        remove_file,
-                        cli.dry_run,
-                        &fs,
-                    )?;
-                }
-                Command::Sync {
-                    files,
-                    all,
-                    filesets,
-                } => {
-                    let files = resolve_file_selection(files, all, filesets, &config)?;
-                    ops::sync::run(&config, files.as_deref(), cli.dry_run, &fs, &prompter)?;
-                }
-                Command::Status {
-                    files,
-                    all,
-                    only_diffs,
-                    deployed,
-                    undeployed,
-                    filesets,
-                } => {
-                    let files = resolve_file_selection(files, all, filesets, &config)?;
-                    ops::status::run(
-                        &config,
-                        files.as_deref(),
-                        ops::status::StatusFilters {
-                            only_diffs,
-                            deployed,
-                            undeployed,
-                        },
-                        &fs,
-                    )?;
-                }
-                Command::Init { .. } | Command::Completions { .. } => unreachable!(),
-            }
+                    // Write out the pack even after a failure, like any files
+                    // generated before it
+                    let result = result.and(fs.flush());
+
+                    if let Some(recorder) = &recorder {
+                        report_timings(recorder, name, &config, !cli.dry_run && !readonly, &fs);
+                    }
+                    result
+                },
+            )?;
         }
     }
 
-    Ok(())
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::test_helpers::*;
-
-    fn test_config() -> Config {
-        let fs = setup_fs();
-        let toml = format!(
-            r#"
-dotfiles_dir = "{DOTFILES}"
-
-[[files]]
-src = "a.conf"
-
-[[files]]
-src = "hypr/hypr.conf"
-
-[filesets.desktop]
-patterns = ["hypr/*"]
-"#
-        );
-        write_and_load_config(&fs, &toml)
-    }
-
-    #[test]
-    fn all_returns_none() {
-        let config = test_config();
-        let result = resolve_file_selection(vec![], true, vec![], &config).unwrap();
-        assert!(result.is_none());
-    }
-
-    #[test]
-    fn explicit_files() {
-        let config = test_config();
-        let result =
-            resolve_file_selection(vec!["a.conf".to_string()], false, vec![], &config).unwrap();
-        assert_eq!(result, Some(vec!["a.conf".to_string()]));
-    }
-
-    #[test]
-    fn filesets_resolved() {
-        let config = test_config();
-        let result =
-            resolve_file_selection(vec![], false, vec!["desktop".to_string()], &config).unwrap();
-        assert_eq!(result, Some(vec!["hypr/*".to_string()]));
-    }
-
-    #[test]
-    fn no_source_errors() {
-        let config = test_config();
-        let result = resolve_file_selection(vec![], false, vec![], &config);
-        let msg = format!("{:#}", result.unwrap_err());
-        assert!(msg.contains("Specify"), "got: {msg}");
-    }
-
-    #[test]
-    fn multiple_sources_errors() {
-        let config = test_config();
-        let result = resolve_file_selection(vec!["a.conf".to_string()], true, vec![], &config);
-        let msg = format!("{:#}", result.unwrap_err());
-        assert!(msg.contains("Cannot combine"), "got: {msg}");
-    }
-
-    fn test_config_with_default_targets(default_targets: &str) -> Config {
-        let fs = setup_fs();
-        let toml = format!(
-            r#"
-dotfiles_dir = "{DOTFILES}"
-default_targets = "{default_targets}"
-
-[[files]]
-src = "a.conf"
-
-[[files]]
-src = "hypr/hypr.conf"
-
-[filesets.desktop]
-patterns = ["hypr/*"]
-"#
-        );
-        write_and_load_config(&fs, &toml)
-    }
-
-    #[test]
-    fn default_targets_all() {
-        let config = test_config_with_default_targets("all");
-        let result = resolve_file_selection(vec![], false, vec![], &config).unwrap();
-        assert!(result.is_none());
-    }
-
-    #[test]
-    fn default_targets_fileset() {
-        let config = test_config_with_default_targets("desktop");
-        let result = resolve_file_selection(vec![], false, vec![], &config).unwrap();
-        assert_eq!(result, Some(vec!["hypr/*".to_string()]));
-    }
-
-    #[test]
-    fn default_targets_overridden_by_explicit() {
-        let config = test_config_with_default_targets("all");
-        let result =
-            resolve_file_selection(vec!["a.conf".to_string()], false, vec![], &config).unwrap();
-        assert_eq!(result, Some(vec!["a.conf".to_string()]));
-    }
-
-    #[test]
-    fn default_targets_overridden_by_all() {
-        let config = test_config_with_default_targets("desktop");
-        let result = resolve_file_selection(vec![], true, vec![], &config).unwrap();
-        assert!(result.is_none());
-    }
-
-    #[test]
-    fn default_targets_overridden_by_filesets() {
-        let config = test_config_with_default_targets("all");
-        let result =
-            resolve_file_selection(vec![], false, vec!["desktop".to_string()], &config).unwrap();
-        assert_eq!(result, Some(vec!["hypr/*".to_string()]));
+    if let Some(recorder) = &plan_recorder {
+        recorder.write(&mut std::io::stdout())?;
     }
+    Ok(())
 }
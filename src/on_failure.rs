@@ -0,0 +1,269 @@
+//! The `[on_failure]` hook for unattended runs.
+//!
+//! When a command fails, the configured command is rendered as a Tera
+//! template and run through the [`CommandRunner`]. [`guard`] covers
+//! everything after the config loads, waiting for the lock included. The
+//! template context has:
+//!
+//! - `command` — the janus subcommand that failed (e.g. `"apply"`)
+//! - `summary` — a human-readable description of the failure
+//! - `files` — the files that failed, when the op reports per-file errors
+//! - `errors` — a list of `{ file, error }` objects for those files
+//!
+//! The summary is also passed on stdin. Values are interpolated as they
+//! are, so one that reaches `sh -c` without the `shell_quote` filter (as in
+//! `{{ summary | shell_quote }}`) can break the command or inject into it.
+
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::collections::HashMap;
+use tera::Tera;
+use tracing::{debug, info, warn};
+
+use crate::config::OnFailureConfig;
+use crate::ops::FileErrors;
+use crate::plan;
+use crate::platform::CommandRunner;
+use crate::shell;
+
+/// Template context describing a failed command.
+#[derive(Debug, Serialize)]
+struct FailureReport {
+    command: String,
+    summary: String,
+    files: Vec<String>,
+    errors: Vec<FailedFile>,
+}
+
+/// A single file that failed, as exposed to the template.
+#[derive(Debug, Serialize)]
+struct FailedFile {
+    file: String,
+    error: String,
+}
+
+impl FailureReport {
+    fn new(command: &str, error: &anyhow::Error) -> Self {
+        let errors: Vec<FailedFile> = error
+            .downcast_ref::<FileErrors>()
+            .map(|fe| {
+                fe.errors
+                    .iter()
+                    .map(|(file, error)| FailedFile {
+                        file: file.clone(),
+                        error: error.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            command: command.to_string(),
+            summary: format!("janus {command} failed: {error:#}"),
+            files: errors.iter().map(|e| e.file.clone()).collect(),
+            errors,
+        }
+    }
+}
+
+/// Run the `[on_failure]` hook for a failed `command`.
+///
+/// Errors if the template can't be rendered, the command can't be spawned,
+/// or it exits non-zero.
+pub fn run(
+    hook: &OnFailureConfig,
+    command: &str,
+    error: &anyhow::Error,
+    runner: &impl CommandRunner,
+) -> Result<()> {
+    let report = FailureReport::new(command, error);
+    let rendered = render_command(&hook.command, &report)?;
+
+    debug!("Running on_failure hook: {rendered}");
+    let output = runner.run(&rendered, Some(&report.summary))?;
+    if !output.success {
+        bail!("on_failure hook failed: {}", output.stderr.trim());
+    }
+    if !output.stdout.trim().is_empty() {
+        debug!("on_failure hook output: {}", output.stdout.trim());
+    }
+    Ok(())
+}
+
+/// Run `body` for `command`, then `hook` if it fails.
+///
+/// A failing hook is logged, and `body`'s error is returned either way. On
+/// a dry run the hook is only reported.
+pub fn guard<T>(
+    hook: Option<&OnFailureConfig>,
+    command: &str,
+    dry_run: bool,
+    runner: &impl CommandRunner,
+    body: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    let result = body();
+    if let Err(e) = &result
+        && let Some(hook) = hook
+    {
+        if dry_run {
+            info!("[dry-run] Would run on_failure hook");
+            plan::action("run", "", &hook.command, "on_failure hook");
+        } else if let Err(hook_err) = run(hook, command, e, runner) {
+            warn!("{hook_err:#}");
+        }
+    }
+    result
+}
+
+/// Render the hook's command template against the failure report.
+fn render_command(template: &str, report: &FailureReport) -> Result<String> {
+    let mut tera = Tera::default();
+    tera.register_filter("shell_quote", shell_quote);
+    tera.add_raw_template("on_failure", template)
+        .context("Failed to parse on_failure command")?;
+    let context = tera::Context::from_serialize(report)?;
+    tera.render("on_failure", &context)
+        .context("Failed to render on_failure command")
+}
+
+/// Tera filter: quote a value for use as one shell word.
+fn shell_quote(
+    value: &tera::Value,
+    _args: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let quoted = match value {
+        tera::Value::String(s) => shell::quote(s),
+        other => shell::quote(&other.to_string()),
+    };
+    Ok(tera::Value::String(quoted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock;
+    use crate::platform::{CommandOutput, FakeCommandRunner, FakeLocker};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn hook(command: &str) -> OnFailureConfig {
+        OnFailureConfig {
+            command: command.to_string(),
+        }
+    }
+
+    #[test]
+    fn renders_command_and_passes_summary_on_stdin() {
+        let runner = FakeCommandRunner::new();
+        let error = anyhow::anyhow!("lock timed out");
+        run(
+            &hook("notify-send janus-{{ command }}"),
+            "apply",
+            &error,
+            &runner,
+        )
+        .unwrap();
+
+        let calls = runner.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0, "notify-send janus-apply");
+        assert_eq!(
+            calls[0].1.as_deref(),
+            Some("janus apply failed: lock timed out")
+        );
+    }
+
+    #[test]
+    fn file_errors_exposed_to_template() {
+        let runner = FakeCommandRunner::new();
+        let errors = vec![
+            ("a.conf".to_string(), anyhow::anyhow!("boom")),
+            ("b.conf".to_string(), anyhow::anyhow!("bang")),
+        ];
+        let error: anyhow::Error = FileErrors::new("generate", &errors).into();
+        let template = "echo {{ files | join(sep=\",\") }}{% for e in errors %} {{ e.file }}={{ e.error }}{% endfor %}";
+        run(&hook(template), "generate", &error, &runner).unwrap();
+
+        assert_eq!(
+            runner.commands(),
+            vec!["echo a.conf,b.conf a.conf=boom b.conf=bang"]
+        );
+    }
+
+    #[test]
+    fn shell_quote_escapes_single_quotes() {
+        let runner = FakeCommandRunner::new();
+        let error = anyhow::anyhow!("it's broken");
+        run(
+            &hook("echo {{ summary | shell_quote }}"),
+            "stage",
+            &error,
+            &runner,
+        )
+        .unwrap();
+        assert_eq!(
+            runner.commands(),
+            vec![r#"echo 'janus stage failed: it'\''s broken'"#]
+        );
+    }
+
+    #[test]
+    fn failing_hook_errors() {
+        let mut runner = FakeCommandRunner::new();
+        runner.add_response(
+            "false",
+            CommandOutput {
+                success: false,
+                stdout: String::new(),
+                stderr: "nope\n".to_string(),
+            },
+        );
+        let error = anyhow::anyhow!("boom");
+        let result = run(&hook("false"), "deploy", &error, &runner);
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(msg.contains("nope"), "got: {msg}");
+    }
+
+    #[test]
+    fn guard_reports_lock_timeout() {
+        let runner = FakeCommandRunner::new();
+        let mut locker = FakeLocker::new_contended(PathBuf::from("/state/.janus.lock"), 42);
+        let result = guard(
+            Some(&hook("notify-send janus-{{ command }}")),
+            "apply",
+            false,
+            &runner,
+            || lock::acquire_lock(&mut locker, Duration::ZERO),
+        );
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(msg.contains("Could not acquire lock"), "got: {msg}");
+
+        let calls = runner.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0, "notify-send janus-apply");
+        assert!(
+            calls[0].1.as_deref().unwrap().contains("PID: 42"),
+            "got: {:?}",
+            calls[0].1
+        );
+    }
+
+    #[test]
+    fn guard_skips_hook_on_success_and_dry_run() {
+        let runner = FakeCommandRunner::new();
+        guard(Some(&hook("notify")), "apply", false, &runner, || Ok(())).unwrap();
+        let result: Result<()> = guard(Some(&hook("notify")), "apply", true, &runner, || {
+            bail!("boom")
+        });
+        assert!(result.is_err());
+        assert!(runner.calls().is_empty());
+    }
+
+    #[test]
+    fn invalid_template_errors() {
+        let runner = FakeCommandRunner::new();
+        let error = anyhow::anyhow!("boom");
+        let result = run(&hook("echo {{ unclosed"), "deploy", &error, &runner);
+        assert!(result.is_err());
+        assert!(runner.calls().is_empty());
+    }
+}
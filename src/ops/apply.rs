@@ -1,42 +1,322 @@
 //! Compound command: run generate -> stage -> deploy in one shot.
 //!
 //! Bails between steps if any step fails — won't deploy if generation or
-//! staging produced errors.
+//! staging produced errors. Ends with a summary of what each step did to
+//! which files, which `--report` also writes as JSON (an [`ApplyReport`]).
+//!
+//! A partial apply (explicit files or `--filesets`) also takes in every file
+//! the selected ones name in `depends_on`, transitively, and warns about
+//! files a selected template lists through `fileset_files` or
+//! `managed_files` that are left out, since they may not be deployed.
 
-use anyhow::Result;
-use tracing::info;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::Path;
+use tracing::{info, warn};
 
+use super::FileErrors;
+use super::generate::{self, GenerateCache};
 use crate::config::Config;
-use crate::platform::{Fs, SecretEngine};
+use crate::platform::{CommandRunner, Fs, Prompter, SecretEngine};
+use crate::template;
+
+/// What one `apply` did, per step.
+#[derive(Debug, Default, Serialize)]
+pub struct ApplyReport {
+    pub dry_run: bool,
+    pub generated: Vec<String>,
+    pub staged: Vec<String>,
+    /// Files deployed (on a dry run, that would have been).
+    pub deployed: Vec<String>,
+    pub skipped: Vec<SkippedFile>,
+    pub failed: Vec<FailedFile>,
+}
+
+/// A file apply didn't finish, and why.
+#[derive(Debug, Serialize)]
+pub struct SkippedFile {
+    pub file: String,
+    pub reason: String,
+}
+
+/// A failure in one step, for one file or (with no `file`) the whole step.
+#[derive(Debug, Serialize)]
+pub struct FailedFile {
+    pub step: String,
+    pub file: Option<String>,
+    pub error: String,
+}
+
+impl ApplyReport {
+    fn fail(&mut self, step: &str, file: Option<&str>, error: String) {
+        self.failed.push(FailedFile {
+            step: step.to_string(),
+            file: file.map(str::to_string),
+            error,
+        });
+    }
+
+    /// Record the per-file failures of a step.
+    fn fail_files(&mut self, failure: &FileErrors) {
+        for (file, error) in &failure.errors {
+            self.fail(&failure.action, Some(file), error.clone());
+        }
+    }
+
+    /// Record an error that stopped `step` outright, passing it on.
+    fn stopped(&mut self, step: &str, e: anyhow::Error) -> anyhow::Error {
+        self.fail(step, None, format!("{e:#}"));
+        e
+    }
+
+    /// Mark every selected file that didn't get through, and didn't fail
+    /// itself, as skipped for `reason`.
+    fn skip_rest(&mut self, config: &Config, files: Option<&[String]>, reason: &str) {
+        for entry in config.filter_files(files) {
+            let src = entry.src.as_str();
+            let done = self.deployed.iter().any(|d| d == src)
+                || self.skipped.iter().any(|s| s.file == src)
+                || self.failed.iter().any(|f| f.file.as_deref() == Some(src));
+            if !done {
+                self.skipped.push(SkippedFile {
+                    file: src.to_string(),
+                    reason: reason.to_string(),
+                });
+            }
+        }
+    }
+
+    /// Write the closing summary: counts per step, then each skipped and
+    /// failed file with its reason.
+    pub fn write_summary(&self, out: &mut impl Write) -> io::Result<()> {
+        let dry_run = if self.dry_run { " (dry run)" } else { "" };
+        writeln!(out, "\nApply summary{dry_run}:")?;
+        writeln!(out, "  generated  {}", self.generated.len())?;
+        writeln!(out, "  staged     {}", self.staged.len())?;
+        writeln!(out, "  deployed   {}", self.deployed.len())?;
+        if !self.skipped.is_empty() {
+            writeln!(out, "  skipped    {}", self.skipped.len())?;
+            for skipped in &self.skipped {
+                writeln!(out, "    {}: {}", skipped.file, skipped.reason)?;
+            }
+        }
+        if !self.failed.is_empty() {
+            writeln!(out, "  failed     {}", self.failed.len())?;
+            for failed in &self.failed {
+                let file = failed.file.as_deref().unwrap_or("(all files)");
+                writeln!(out, "    {file} ({}): {}", failed.step, failed.error)?;
+            }
+        }
+        Ok(())
+    }
+}
 
 /// Run the full forward pipeline: generate, stage, then deploy.
 ///
 /// If any step fails, subsequent steps are skipped. The `force` and `dry_run`
-/// flags are passed through to each step.
+/// flags are passed through to each step; `interactive` makes deploy prompt
+/// on conflicting targets.
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     config: &Config,
     files: Option<&[String]>,
     force: bool,
+    interactive: bool,
     dry_run: bool,
     fs: &impl Fs,
     engine: &impl SecretEngine,
+    prompter: &impl Prompter,
+    runner: &impl CommandRunner,
+) -> Result<()> {
+    run_with_report(
+        config,
+        files,
+        force,
+        interactive,
+        dry_run,
+        None,
+        &mut io::sink(),
+        fs,
+        engine,
+        prompter,
+        runner,
+    )
+}
+
+/// [`run`], then write the summary to `out` and, with `report`, write the
+/// [`ApplyReport`] to that path as JSON, whether or not apply succeeded.
+#[allow(clippy::too_many_arguments)]
+pub fn run_with_report(
+    config: &Config,
+    files: Option<&[String]>,
+    force: bool,
+    interactive: bool,
+    dry_run: bool,
+    report_path: Option<&Path>,
+    out: &mut impl Write,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    prompter: &impl Prompter,
+    runner: &impl CommandRunner,
+) -> Result<()> {
+    let files = files.map(|files| with_dependencies(config, files));
+    if let Some(files) = &files {
+        warn_unlisted(config, files, fs);
+    }
+    let files = files.as_deref();
+    let mut report = ApplyReport {
+        dry_run,
+        ..ApplyReport::default()
+    };
+    let result = apply(
+        config,
+        files,
+        force,
+        interactive,
+        dry_run,
+        &mut report,
+        out,
+        fs,
+        engine,
+        prompter,
+        runner,
+    );
+    let summary = report
+        .write_summary(out)
+        .context("Failed to write apply summary");
+    if let Some(path) = report_path {
+        let written = serde_json::to_string_pretty(&report)
+            .context("Failed to serialize apply report")
+            .and_then(|json| {
+                fs.write(path, json.as_bytes())
+                    .with_context(|| format!("Failed to write apply report: {}", path.display()))
+            });
+        match (&result, written) {
+            (Ok(()), Err(e)) => return Err(e),
+            (Err(_), Err(e)) => warn!("{e:#}"),
+            (_, Ok(())) => info!("Wrote apply report to {}", path.display()),
+        }
+    }
+    result.and(summary)
+}
+
+/// `files` plus the `src` of each enabled file the selected ones need
+/// through `depends_on`, and those files' own dependencies.
+fn with_dependencies(config: &Config, files: &[String]) -> Vec<String> {
+    let mut files = files.to_vec();
+    let mut selected: Vec<&str> = config
+        .filter_files(Some(&files))
+        .iter()
+        .map(|e| e.src.as_str())
+        .collect();
+    let mut next = 0;
+    while let Some(&src) = selected.get(next) {
+        next += 1;
+        let Some(entry) = config.files.iter().find(|e| e.src == src) else {
+            continue;
+        };
+        for dep in config.filter_files(Some(&entry.depends_on)) {
+            if !selected.contains(&dep.src.as_str()) {
+                info!("Including {} (in depends_on of {src})", dep.src);
+                selected.push(&dep.src);
+                files.push(dep.src.clone());
+            }
+        }
+    }
+    files
+}
+
+/// Warn about managed files a selected template lists (through
+/// `fileset_files` or `managed_files`) that aren't selected, so won't be
+/// regenerated or deployed with it.
+fn warn_unlisted(config: &Config, files: &[String], fs: &impl Fs) {
+    let selected = config.filter_files(Some(files));
+    for entry in selected.iter().filter(|e| e.template && !e.direct) {
+        let Ok(content) = fs.read_to_string(&config.source_path(&entry.src, fs)) else {
+            continue;
+        };
+        for (call, listed) in template::listed_files(&content, config) {
+            if !selected.iter().any(|e| e.src == listed.src) {
+                warn!(
+                    "{} lists {} through {call}, but it isn't part of this apply; \
+                     add it to depends_on to apply them together",
+                    entry.src, listed.src
+                );
+            }
+        }
+    }
+}
+
+/// The three steps, filling in `report` as they go. Conflict prompts go to
+/// `out`.
+#[allow(clippy::too_many_arguments)]
+fn apply(
+    config: &Config,
+    files: Option<&[String]>,
+    force: bool,
+    interactive: bool,
+    dry_run: bool,
+    report: &mut ApplyReport,
+    out: &mut impl Write,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    prompter: &impl Prompter,
+    runner: &impl CommandRunner,
 ) -> Result<()> {
     info!("Running generate...");
-    crate::ops::generate::run(config, files, dry_run, fs, engine)?;
+    let mut cache = GenerateCache::default();
+    let generated = generate::generate(config, files, dry_run, &mut cache, fs, engine, runner)
+        .map_err(|e| report.stopped("generate", e))?;
+    report.generated = generated.succeeded.clone();
+    if let Some(failure) = &generated.failure {
+        report.fail_files(failure);
+        report.skip_rest(config, files, "not staged: generate failed");
+        return generated.into_result();
+    }
 
     info!("Running stage...");
-    crate::ops::stage::run(config, files, dry_run, fs)?;
+    let staged =
+        super::stage::stage(config, files, dry_run, fs).map_err(|e| report.stopped("stage", e))?;
+    report.staged = staged.succeeded.clone();
+    if let Some(failure) = &staged.failure {
+        report.fail_files(failure);
+        report.skip_rest(config, files, "not deployed: stage failed");
+        return staged.into_result();
+    }
 
     info!("Running deploy...");
-    crate::ops::deploy::run(config, files, force, dry_run, fs)?;
-
-    Ok(())
+    let deployed = if interactive {
+        super::deploy::deploy_interactive(config, files, false, dry_run, fs, prompter, runner, out)
+    } else {
+        super::deploy::deploy(config, files, force, false, dry_run, fs, runner)
+    }
+    .map_err(|e| report.stopped("deploy", e))?;
+    report.deployed = deployed.deployed.clone();
+    report
+        .skipped
+        .extend(deployed.skipped.iter().map(|file| SkippedFile {
+            file: file.clone(),
+            reason: "skipped at the conflict prompt".to_string(),
+        }));
+    if let Some((file, e)) = &deployed.failure {
+        report.fail("deploy", Some(file), format!("{e:#}"));
+        report.skip_rest(
+            config,
+            files,
+            &format!("not deployed: deploy stopped at {file}"),
+        );
+    }
+    if let Some(e) = &deployed.systemd {
+        report.fail("systemd", None, format!("{e:#}"));
+    }
+    deployed.into_result()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::platform::FakeSecretEngine;
+    use crate::platform::{FakeCommandRunner, FakePrompter, FakeSecretEngine};
     use crate::state::State;
     use crate::test_helpers::*;
     use std::path::Path;
@@ -51,7 +331,18 @@ mod tests {
             &make_config_toml(&[("a.conf", Some("~/.config/a.conf"))]),
         );
         let engine = FakeSecretEngine::new();
-        run(&config, None, false, false, &fs, &engine).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            false,
+            false,
+            &fs,
+            &engine,
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         // Should have generated, staged, and deployed
         assert!(fs.exists(Path::new(&format!("{DOTFILES}/.generated/a.conf"))));
         assert!(fs.exists(Path::new(&format!("{DOTFILES}/.staged/a.conf"))));
@@ -71,12 +362,158 @@ mod tests {
             &make_config_toml(&[("bad.conf", Some("~/.config/bad.conf"))]),
         );
         let engine = FakeSecretEngine::new();
-        let result = run(&config, None, false, false, &fs, &engine);
+        let result = run(
+            &config,
+            None,
+            false,
+            false,
+            false,
+            &fs,
+            &engine,
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        );
         assert!(result.is_err());
         // Should not have deployed
         assert!(!fs.exists(Path::new("/home/test/.config/bad.conf")));
     }
 
+    #[test]
+    fn report_lists_what_each_step_did() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file(format!("{DOTFILES}/good.conf"), "fine");
+        fs.add_file(format!("{DOTFILES}/bad.conf"), "{{ undefined_var }");
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[("good.conf", None), ("bad.conf", None)]),
+        );
+        let report_path = Path::new("/home/test/apply.json");
+        let mut out = Vec::new();
+        let result = run_with_report(
+            &config,
+            None,
+            false,
+            false,
+            false,
+            Some(report_path),
+            &mut out,
+            &fs,
+            &FakeSecretEngine::new(),
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        );
+        assert!(result.is_err());
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("failed     1"), "got: {out}");
+
+        let report: serde_json::Value =
+            serde_json::from_str(&fs.read_to_string(report_path).unwrap()).unwrap();
+        assert_eq!(report["generated"], serde_json::json!(["good.conf"]));
+        assert_eq!(report["deployed"], serde_json::json!([]));
+        assert_eq!(report["failed"][0]["step"], "generate");
+        assert_eq!(report["failed"][0]["file"], "bad.conf");
+        assert_eq!(report["skipped"][0]["file"], "good.conf");
+        assert_eq!(
+            report["skipped"][0]["reason"],
+            "not staged: generate failed"
+        );
+    }
+
+    #[test]
+    fn partial_apply_includes_dependencies() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file(format!("{DOTFILES}/waybar/config"), "bar");
+        fs.add_file(format!("{DOTFILES}/waybar/style.css"), "@import colors");
+        fs.add_file(format!("{DOTFILES}/colors.css"), "red");
+        fs.add_file(format!("{DOTFILES}/palette.css"), "blue");
+        fs.add_file(format!("{DOTFILES}/other.conf"), "other");
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[
+                ("waybar/config", None),
+                ("waybar/style.css", None),
+                ("colors.css", None),
+                ("palette.css", None),
+                ("other.conf", None),
+            ])
+            .replace(
+                "src = \"waybar/style.css\"\n",
+                "src = \"waybar/style.css\"\ndepends_on = [\"colors.css\"]\n",
+            )
+            .replace(
+                "src = \"colors.css\"\n",
+                "src = \"colors.css\"\ndepends_on = [\"palette.*\"]\n",
+            ),
+        );
+        let report_path = Path::new("/home/test/apply.json");
+        run_with_report(
+            &config,
+            Some(&["waybar/*".to_string()]),
+            false,
+            false,
+            false,
+            Some(report_path),
+            &mut io::sink(),
+            &fs,
+            &FakeSecretEngine::new(),
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        let report: serde_json::Value =
+            serde_json::from_str(&fs.read_to_string(report_path).unwrap()).unwrap();
+        assert_eq!(
+            report["deployed"],
+            serde_json::json!([
+                "waybar/config",
+                "waybar/style.css",
+                "colors.css",
+                "palette.css"
+            ])
+        );
+    }
+
+    #[test]
+    fn depends_on_must_match_a_file() {
+        let fs = setup_fs();
+        fs.add_file(
+            CONFIG_PATH,
+            format!(
+                "{}depends_on = [\"colours.css\"]\n",
+                make_config_toml(&[("a.conf", None)])
+            ),
+        );
+        let err = Config::load(Path::new(CONFIG_PATH), &fs).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("a.conf: depends_on colours.css matches no managed file"),
+            "got: {err:#}"
+        );
+    }
+
+    #[test]
+    fn summary_groups_by_outcome() {
+        let report = ApplyReport {
+            dry_run: false,
+            generated: vec!["a.conf".to_string(), "b.conf".to_string()],
+            staged: vec!["a.conf".to_string(), "b.conf".to_string()],
+            deployed: vec!["a.conf".to_string()],
+            skipped: vec![SkippedFile {
+                file: "b.conf".to_string(),
+                reason: "skipped at the conflict prompt".to_string(),
+            }],
+            failed: Vec::new(),
+        };
+        let mut out = Vec::new();
+        report.write_summary(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out,
+            "\nApply summary:\n  generated  2\n  staged     2\n  deployed   1\n  skipped    1\n    b.conf: skipped at the conflict prompt\n"
+        );
+    }
+
     #[test]
     fn full_pipeline_with_template_and_secrets() {
         let fs = setup_fs();
@@ -107,7 +544,18 @@ target = "~/.config/app.conf"
         let mut engine = FakeSecretEngine::new();
         engine.add_secret("1password", "op://vault/db/pw", "s3cret123");
 
-        run(&config, None, false, false, &fs, &engine).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            false,
+            false,
+            &fs,
+            &engine,
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
 
         // Verify the symlink exists and points to staged
         let target = Path::new("/home/test/.config/app.conf");
@@ -146,7 +594,18 @@ target = "~/.config/app.conf"
             &make_config_toml(&[("a.conf", Some("~/.config/a.conf"))]),
         );
         let engine = FakeSecretEngine::new();
-        run(&config, None, false, true, &fs, &engine).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            false,
+            true,
+            &fs,
+            &engine,
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         // No symlink should be created
         assert!(!fs.is_symlink(Path::new("/home/test/.config/a.conf")));
     }
@@ -0,0 +1,317 @@
+//! Archived sources: `janus unimport --archive` and
+//! `janus restore-archived`.
+//!
+//! Archiving moves a source out of management without deleting it: the
+//! source goes to `archive/<timestamp>/<src>` inside the dotfiles directory
+//! and its `[[files]]` entry, as written, to the [`ArchiveIndex`] at
+//! `archive/archive.toml`. Its generated and staged copies are removed
+//! rather than archived: they can hold rendered secrets, and `janus apply`
+//! rebuilds them after a restore. Committing the archive keeps the content
+//! in the repo, and restoring moves the source back and re-adds the entry.
+//!
+//! Archives made before that may still hold `.generated/` and `.staged/`
+//! copies; restoring moves those back too.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+use super::unimport::remove_empty_parents;
+use crate::config::Config;
+use crate::plan;
+use crate::platform::Fs;
+use crate::platform::xdg_trash::format_file_date;
+use crate::state::unix_now;
+use crate::trash;
+
+/// Directory within `dotfiles_dir` holding archived sources.
+pub const ARCHIVE_DIR: &str = "archive";
+
+/// File within [`ARCHIVE_DIR`] listing what's archived.
+pub const ARCHIVE_INDEX: &str = "archive.toml";
+
+/// One archived source.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Archived {
+    /// Its `src` when it was managed.
+    pub src: String,
+    /// When it was archived, in seconds since the Unix epoch.
+    pub at: u64,
+    /// The directory it was archived to, relative to `dotfiles_dir`.
+    pub dir: String,
+    /// Its `[[files]]` table, as it was written in the config.
+    pub entry: String,
+}
+
+/// Everything archived and not yet restored, oldest first.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ArchiveIndex {
+    #[serde(default)]
+    pub archived: Vec<Archived>,
+}
+
+impl ArchiveIndex {
+    /// Load the index from `dotfiles_dir`. A missing index is empty.
+    pub fn load(dotfiles_dir: &Path, fs: &impl Fs) -> Result<Self> {
+        let path = index_path(dotfiles_dir);
+        if !fs.exists(&path) {
+            return Ok(Self::default());
+        }
+        let contents = fs
+            .read_to_string(&path)
+            .with_context(|| format!("Failed to read archive index: {}", path.display()))?;
+        toml::from_str(&contents).context("Failed to parse archive index")
+    }
+
+    /// Write the index back to `dotfiles_dir`.
+    pub fn save(&self, dotfiles_dir: &Path, fs: &impl Fs) -> Result<()> {
+        let path = index_path(dotfiles_dir);
+        if let Some(parent) = path.parent() {
+            fs.create_dir_all(parent)?;
+        }
+        let contents = toml::to_string_pretty(self).context("Failed to serialize archive index")?;
+        fs.write(&path, contents.as_bytes())
+            .with_context(|| format!("Failed to write archive index: {}", path.display()))
+    }
+}
+
+fn index_path(dotfiles_dir: &Path) -> PathBuf {
+    dotfiles_dir.join(ARCHIVE_DIR).join(ARCHIVE_INDEX)
+}
+
+/// Where `src`'s source, generated, and staged copies are, and where they
+/// go in the archive directory `dir`.
+fn copies(
+    config: &Config,
+    src: &str,
+    dir: &Path,
+    fs: &impl Fs,
+) -> [(PathBuf, PathBuf, PathBuf); 3] {
+    let dotfiles_dir = config.dotfiles_dir(fs);
+    [
+        (dotfiles_dir.clone(), dir.to_path_buf()),
+        (config.generated_dir(fs), dir.join(".generated")),
+        (config.staged_dir(fs), dir.join(".staged")),
+    ]
+    .map(|(root, archived)| (root.join(src), archived.join(src), root))
+}
+
+/// Move `src`'s source into a new timestamped archive directory, remove
+/// its generated and staged copies, and record `entry` (its `[[files]]`
+/// table) in the index.
+pub(crate) fn archive(config: &Config, src: &str, entry: &str, fs: &impl Fs) -> Result<()> {
+    let dotfiles_dir = config.dotfiles_dir(fs);
+    let at = unix_now();
+    let relative = format!("{ARCHIVE_DIR}/{}", format_file_date(at));
+    let dir = dotfiles_dir.join(&relative);
+
+    let [(path, archived, root), rendered @ ..] = copies(config, src, &dir, fs);
+    if fs.exists(&path) {
+        if let Some(parent) = archived.parent() {
+            fs.create_dir_all(parent)?;
+        }
+        fs.rename(&path, &archived)
+            .with_context(|| format!("Failed to archive {}", path.display()))?;
+        remove_empty_parents(&path, &root, fs);
+        debug!("Archived {} to {}", path.display(), archived.display());
+    }
+    // Rendered copies can hold secrets, so they stay out of the archive
+    for (path, _, root) in rendered {
+        if !fs.exists(&path) {
+            continue;
+        }
+        trash::remove_file(config, &path, fs)
+            .with_context(|| format!("Failed to remove {}", path.display()))?;
+        remove_empty_parents(&path, &root, fs);
+        debug!("Removed {}", path.display());
+    }
+
+    let mut index = ArchiveIndex::load(&dotfiles_dir, fs)?;
+    index.archived.push(Archived {
+        src: src.to_string(),
+        at,
+        dir: relative,
+        entry: entry.to_string(),
+    });
+    index.save(&dotfiles_dir, fs)
+}
+
+/// Move archived sources back under management: their copies back into
+/// place and their `[[files]]` entries back into the config. The most
+/// recent archive of each `src` is restored. Nothing is deployed.
+pub fn restore(
+    config: &Config,
+    config_path: &Path,
+    srcs: &[String],
+    dry_run: bool,
+    fs: &impl Fs,
+) -> Result<()> {
+    let dotfiles_dir = config.dotfiles_dir(fs);
+    let archive_root = dotfiles_dir.join(ARCHIVE_DIR);
+    let mut index = ArchiveIndex::load(&dotfiles_dir, fs)?;
+
+    for src in srcs {
+        let Some(i) = index.archived.iter().rposition(|a| a.src == *src) else {
+            bail!("{src} isn't archived (see {ARCHIVE_DIR}/{ARCHIVE_INDEX})");
+        };
+        if config.files.iter().any(|e| e.src == *src) {
+            bail!("{src} is managed again; unimport it before restoring the archived copy");
+        }
+        let archived = index.archived[i].clone();
+        let dir = dotfiles_dir.join(&archived.dir);
+        let copies = copies(config, src, &dir, fs);
+        if let Some((path, _, _)) = copies
+            .iter()
+            .find(|(path, from, _)| fs.exists(from) && fs.exists(path))
+        {
+            bail!(
+                "{} already exists; move it aside to restore the archived copy",
+                path.display()
+            );
+        }
+
+        if dry_run {
+            info!("[dry-run] Would restore {src} from {}", archived.dir);
+            plan::action("restore", &archived.dir, src, "restore-archived");
+            continue;
+        }
+        for (path, from, _) in &copies {
+            if !fs.exists(from) {
+                continue;
+            }
+            if let Some(parent) = path.parent() {
+                fs.create_dir_all(parent)?;
+            }
+            fs.rename(from, path)
+                .with_context(|| format!("Failed to restore {}", path.display()))?;
+            remove_empty_parents(from, &archive_root, fs);
+        }
+        add_config_entry(config_path, &archived.entry, fs)?;
+        index.archived.remove(i);
+        index.save(&dotfiles_dir, fs)?;
+        info!("Restored {src}; run `janus apply {src}` to deploy it");
+    }
+    Ok(())
+}
+
+/// Append the `[[files]]` table written in `entry` to the config.
+fn add_config_entry(config_path: &Path, entry: &str, fs: &impl Fs) -> Result<()> {
+    let contents = fs
+        .read_to_string(config_path)
+        .with_context(|| format!("Failed to read config: {}", config_path.display()))?;
+    let mut doc = contents
+        .parse::<toml_edit::DocumentMut>()
+        .with_context(|| "Failed to parse config for editing")?;
+    let archived = entry
+        .parse::<toml_edit::DocumentMut>()
+        .context("Failed to parse the archived config entry")?;
+    let Some(table) = archived
+        .get("files")
+        .and_then(|f| f.as_array_of_tables())
+        .and_then(|a| a.get(0))
+    else {
+        bail!("Archived config entry is empty");
+    };
+
+    let files = doc
+        .entry("files")
+        .or_insert_with(|| toml_edit::Item::ArrayOfTables(toml_edit::ArrayOfTables::new()));
+    let Some(array) = files.as_array_of_tables_mut() else {
+        bail!("Config 'files' field is malformed");
+    };
+    array.push(table.clone());
+    fs.write(config_path, doc.to_string().as_bytes())
+        .with_context(|| format!("Failed to write config: {}", config_path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::unimport;
+    use crate::platform::FakeCommandRunner;
+    use crate::test_helpers::*;
+
+    /// Manage `hypr/hypr.conf` with a mode, then unimport it with
+    /// `--archive`.
+    fn archive_hypr(fs: &crate::platform::FakeFs) -> Config {
+        setup_pipeline_file(fs, "hypr/hypr.conf", "monitor=DP-1");
+        let toml = make_config_toml(&[("hypr/hypr.conf", None)]).replace(
+            "src = \"hypr/hypr.conf\"\n",
+            "src = \"hypr/hypr.conf\"\nmode = 0o600\n",
+        );
+        let config = write_and_load_config(fs, &toml);
+        unimport::run(
+            &config,
+            Path::new(CONFIG_PATH),
+            &["hypr/hypr.conf".to_string()],
+            false,
+            false,
+            true,
+            false,
+            fs,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        Config::load(Path::new(CONFIG_PATH), fs).unwrap()
+    }
+
+    #[test]
+    fn archive_and_restore_round_trip() {
+        let fs = setup_fs();
+        let config = archive_hypr(&fs);
+
+        let source = Path::new(DOTFILES).join("hypr/hypr.conf");
+        assert!(!fs.exists(&source));
+        let index = ArchiveIndex::load(Path::new(DOTFILES), &fs).unwrap();
+        assert_eq!(index.archived.len(), 1);
+        let dir = Path::new(DOTFILES).join(&index.archived[0].dir);
+        assert_eq!(
+            fs.read_to_string(&dir.join("hypr/hypr.conf")).unwrap(),
+            "monitor=DP-1"
+        );
+        // Rendered copies are removed, not archived
+        assert!(!fs.exists(&dir.join(".staged/hypr/hypr.conf")));
+        assert!(!fs.exists(&Path::new(DOTFILES).join(".staged/hypr/hypr.conf")));
+        assert!(config.files.is_empty());
+
+        restore(
+            &config,
+            Path::new(CONFIG_PATH),
+            &["hypr/hypr.conf".to_string()],
+            false,
+            &fs,
+        )
+        .unwrap();
+        assert_eq!(fs.read_to_string(&source).unwrap(), "monitor=DP-1");
+        assert!(!fs.exists(&dir));
+        let config = Config::load(Path::new(CONFIG_PATH), &fs).unwrap();
+        assert_eq!(config.files[0].src, "hypr/hypr.conf");
+        assert_eq!(config.files[0].mode, Some(0o600));
+        let index = ArchiveIndex::load(Path::new(DOTFILES), &fs).unwrap();
+        assert!(index.archived.is_empty());
+    }
+
+    #[test]
+    fn restore_refuses_to_overwrite() {
+        let fs = setup_fs();
+        let config = archive_hypr(&fs);
+        let restore_src = |src: &str| {
+            restore(
+                &config,
+                Path::new(CONFIG_PATH),
+                &[src.to_string()],
+                false,
+                &fs,
+            )
+        };
+
+        let err = restore_src("waybar/config").unwrap_err();
+        assert!(err.to_string().contains("isn't archived"), "got: {err}");
+        fs.add_file(format!("{DOTFILES}/hypr/hypr.conf"), "new");
+        let err = restore_src("hypr/hypr.conf").unwrap_err();
+        assert!(err.to_string().contains("already exists"), "got: {err}");
+        let index = ArchiveIndex::load(Path::new(DOTFILES), &fs).unwrap();
+        assert_eq!(index.archived.len(), 1);
+    }
+}
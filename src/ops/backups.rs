@@ -0,0 +1,204 @@
+//! `janus backups`: list and restore backups deploy made under
+//! `[backup] dir`.
+//!
+//! Each backup is recorded in state with the path it was taken from, so it
+//! can be put back there even after the file's `target` changes in the
+//! config. Sibling `<name>.janus.bak` backups aren't listed; they're already
+//! next to their target.
+
+use anyhow::{Context, Result, bail};
+use std::io::Write;
+use tracing::info;
+
+use crate::config::Config;
+use crate::paths::{collapse_tilde, expand_tilde};
+use crate::plan;
+use crate::platform::Fs;
+use crate::platform::xdg_trash::format_date;
+use crate::state::{State, move_file};
+
+/// Write the recorded backups to `out`, oldest first.
+pub fn list(config: &Config, fs: &impl Fs, out: &mut impl Write) -> Result<()> {
+    let state = State::load(&config.state_dir(fs), fs)?;
+    if state.backups.is_empty() {
+        info!("No backups recorded (backups are only recorded with [backup] dir)");
+        return Ok(());
+    }
+    for backup in &state.backups {
+        let path = expand_tilde(&backup.path, fs);
+        let gone = if fs.exists(&path) || fs.is_symlink(&path) {
+            ""
+        } else {
+            "  (missing)"
+        };
+        writeln!(
+            out,
+            "  {}  {}  ({})  {}{gone}",
+            format_date(backup.at),
+            backup.target,
+            backup.src,
+            backup.path,
+        )?;
+    }
+    Ok(())
+}
+
+/// Move backups back to the paths they were taken from.
+///
+/// Each path is matched against the recorded targets (`~` is expanded) or
+/// the `src` of the file deployed over them. The newest backup is restored,
+/// or the first with `original`. A symlink janus deployed at the target is
+/// removed and the file marked undeployed; anything else there is refused.
+pub fn restore(
+    config: &Config,
+    paths: &[String],
+    original: bool,
+    dry_run: bool,
+    fs: &impl Fs,
+) -> Result<()> {
+    let mut state = State::load(&config.state_dir(fs), fs)?;
+
+    for path in paths {
+        let target = collapse_tilde(&expand_tilde(path, fs), fs);
+        let matches = |i: &usize| {
+            let backup = &state.backups[*i];
+            backup.target == target || backup.src == *path
+        };
+        let mut indices = (0..state.backups.len()).filter(matches);
+        let index = if original {
+            indices.next()
+        } else {
+            indices.next_back()
+        };
+        let Some(index) = index else {
+            bail!("No backup of {path} recorded (see `janus backups list`)");
+        };
+        let backup = state.backups[index].clone();
+        let backup_path = expand_tilde(&backup.path, fs);
+        let target_path = expand_tilde(&backup.target, fs);
+        if !fs.exists(&backup_path) {
+            bail!("Backup of {} is missing: {}", backup.target, backup.path);
+        }
+
+        let deployed = state
+            .deployed
+            .iter()
+            .find(|e| expand_tilde(&e.target, fs) == target_path)
+            .map(|e| e.src.clone());
+        let occupied = fs.exists(&target_path) || fs.is_symlink(&target_path);
+        if occupied && (deployed.is_none() || !fs.is_symlink(&target_path)) {
+            bail!(
+                "{} already exists; move it aside to restore the backup",
+                backup.target
+            );
+        }
+
+        if dry_run {
+            info!(
+                "[dry-run] Would restore {} from {}",
+                backup.target, backup.path
+            );
+            plan::action("restore", &backup.path, &backup.target, "backups restore");
+            continue;
+        }
+        if let Some(src) = &deployed {
+            if occupied {
+                fs.remove_file(&target_path).with_context(|| {
+                    format!("Failed to remove symlink: {}", target_path.display())
+                })?;
+            }
+            state.remove_deployed(src);
+            info!("Undeployed {src}");
+        }
+        if let Some(parent) = target_path.parent() {
+            fs.create_dir_all(parent)?;
+        }
+        // Backups under `[backup] dir` may be on another filesystem
+        move_file(&backup_path, &target_path, fs)
+            .with_context(|| format!("Failed to restore {}", backup.target))?;
+        state.backups.remove(index);
+        state.save(fs)?;
+        info!("Restored {} from {}", backup.target, backup.path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::deploy;
+    use crate::platform::FakeCommandRunner;
+    use crate::test_helpers::*;
+    use std::path::Path;
+
+    const TARGET: &str = "/home/test/.config/a.conf";
+
+    /// Deploy `a.conf` over an existing file twice, with `[backup] dir`.
+    fn deploy_over(fs: &crate::platform::FakeFs) -> Config {
+        fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "staged");
+        fs.add_file(TARGET, "first");
+        let toml = format!(
+            "{}\n[backup]\ndir = \"~/backups\"\n",
+            make_config_toml(&[("a.conf", Some("~/.config/a.conf"))])
+        );
+        let config = write_and_load_config(fs, &toml);
+        let runner = FakeCommandRunner::new();
+        deploy::run(&config, None, false, false, fs, &runner).unwrap();
+        fs.remove_file(Path::new(TARGET)).unwrap();
+        fs.add_file(TARGET, "second");
+        deploy::run(&config, None, false, false, fs, &runner).unwrap();
+        config
+    }
+
+    #[test]
+    fn deploy_records_backups_under_dir() {
+        let fs = setup_fs();
+        let config = deploy_over(&fs);
+        assert!(!fs.exists(Path::new("/home/test/.config/a.conf.janus.bak")));
+
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert_eq!(state.backups.len(), 2);
+        assert_eq!(state.backups[0].target, "~/.config/a.conf");
+        assert!(
+            state.backups[0]
+                .path
+                .starts_with("~/backups/home/test/.config/a.conf."),
+            "got: {}",
+            state.backups[0].path
+        );
+        assert_eq!(
+            state.deployed[0].backup.as_deref(),
+            Some(state.backups[0].path.as_str())
+        );
+
+        let mut out = Vec::new();
+        list(&config, &fs, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("~/.config/a.conf  (a.conf)"), "got: {out}");
+    }
+
+    #[test]
+    fn restore_replaces_deployed_symlink() {
+        let fs = setup_fs();
+        let config = deploy_over(&fs);
+
+        restore(&config, &["a.conf".to_string()], true, false, &fs).unwrap();
+        let target = Path::new(TARGET);
+        assert!(!fs.is_symlink(target));
+        assert_eq!(fs.read_to_string(target).unwrap(), "first");
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert!(!state.is_deployed("a.conf"));
+        assert_eq!(state.backups.len(), 1);
+
+        // Now a regular file is in the way
+        let err = restore(
+            &config,
+            &["~/.config/a.conf".to_string()],
+            false,
+            false,
+            &fs,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("already exists"), "got: {err}");
+    }
+}
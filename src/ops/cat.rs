@@ -0,0 +1,182 @@
+//! `janus cat`: print one pipeline stage of a managed file.
+//!
+//! The file can be named by its `src` or by any path `janus which`
+//! resolves (its target, or its source, generated, or staged copy). The
+//! deployed stage reads the target through its symlink, so it shows what
+//! programs actually load, even if the link was replaced by a plain file.
+
+use anyhow::{Context, Result, bail};
+use std::fmt;
+use std::io::Write;
+
+use super::which;
+use crate::config::{Config, FileEntry};
+use crate::paths::expand_tilde;
+use crate::platform::Fs;
+use crate::secrets::SecretMask;
+
+/// Which copy of a file to print.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CatStage {
+    /// The source in the dotfiles directory, unrendered.
+    Source,
+    /// The `.generated/` copy.
+    Generated,
+    /// The `.staged/` copy.
+    Staged,
+    /// Whatever is at the target path.
+    Deployed,
+}
+
+impl fmt::Display for CatStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CatStage::Source => "source",
+            CatStage::Generated => "generated",
+            CatStage::Staged => "staged",
+            CatStage::Deployed => "deployed",
+        })
+    }
+}
+
+/// The entry `file` names: an exact `src`, else a path as `janus which`
+/// looks it up (starting with `~` or relative to the working directory).
+pub fn find_entry<'a>(config: &'a Config, file: &str, fs: &impl Fs) -> Result<&'a FileEntry> {
+    if let Some(entry) = config.files.iter().find(|e| e.src == file) {
+        return Ok(entry);
+    }
+    let absolute = std::path::absolute(expand_tilde(file, fs))?;
+    match which::lookup(config, &absolute, fs) {
+        Some((entry, _)) => Ok(entry),
+        None => bail!("{file} isn't managed by janus"),
+    }
+}
+
+/// Write `entry`'s `stage` copy to `out`, with the secret values in `mask`
+/// hidden. Files that aren't UTF-8 are written byte for byte.
+pub fn run(
+    config: &Config,
+    entry: &FileEntry,
+    stage: CatStage,
+    fs: &impl Fs,
+    mask: &SecretMask,
+    out: &mut impl Write,
+) -> Result<()> {
+    let path = match stage {
+        CatStage::Source => config.source_path(&entry.src, fs),
+        CatStage::Generated | CatStage::Staged if entry.direct => {
+            bail!(
+                "{} is a direct file, so it has no {stage} copy; use --stage source",
+                entry.src
+            )
+        }
+        CatStage::Generated => config.generated_dir(fs).join(&entry.src),
+        CatStage::Staged => config.staged_dir(fs).join(&entry.src),
+        CatStage::Deployed => expand_tilde(&entry.target(), fs),
+    };
+    if !fs.exists(&path) {
+        match stage {
+            CatStage::Generated => bail!(
+                "No generated copy of {} (run `janus generate` first)",
+                entry.src
+            ),
+            CatStage::Staged => bail!("No staged copy of {} (run `janus stage` first)", entry.src),
+            CatStage::Deployed => bail!(
+                "{} isn't deployed: {} doesn't exist",
+                entry.src,
+                path.display()
+            ),
+            CatStage::Source => bail!("Source file not found: {}", path.display()),
+        }
+    }
+
+    let bytes = fs
+        .read(&path)
+        .with_context(|| format!("Failed to read {stage} file: {}", path.display()))?;
+    match String::from_utf8(bytes) {
+        Ok(text) => out.write_all(mask.mask(&text).as_bytes())?,
+        Err(e) => out.write_all(e.as_bytes())?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    fn cat(
+        config: &Config,
+        file: &str,
+        stage: CatStage,
+        fs: &crate::platform::FakeFs,
+    ) -> Result<String> {
+        let entry = find_entry(config, file, fs)?;
+        let mut out = Vec::new();
+        run(config, entry, stage, fs, &SecretMask::default(), &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn prints_each_stage() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.conf"), "font = {{ size }}\n");
+        fs.add_file(format!("{DOTFILES}/.generated/a.conf"), "font = 12\n");
+        fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "font = 14\n");
+        fs.add_symlink(
+            "/home/test/.config/a.conf",
+            format!("{DOTFILES}/.staged/a.conf"),
+        );
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+
+        assert_eq!(
+            cat(&config, "a.conf", CatStage::Source, &fs).unwrap(),
+            "font = {{ size }}\n"
+        );
+        assert_eq!(
+            cat(&config, "a.conf", CatStage::Generated, &fs).unwrap(),
+            "font = 12\n"
+        );
+        // Named by its target, read through the symlink
+        assert_eq!(
+            cat(&config, "~/.config/a.conf", CatStage::Deployed, &fs).unwrap(),
+            "font = 14\n"
+        );
+    }
+
+    #[test]
+    fn missing_copies_are_explained() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.conf"), "x");
+        fs.add_file(format!("{DOTFILES}/b.conf"), "x");
+        let toml = format!(
+            "{}\n[[files]]\nsrc = \"b.conf\"\ndirect = true\ntemplate = false\n",
+            make_config_toml(&[("a.conf", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+
+        let err = cat(&config, "a.conf", CatStage::Staged, &fs).unwrap_err();
+        assert!(err.to_string().contains("janus stage"), "got: {err}");
+        let err = cat(&config, "a.conf", CatStage::Deployed, &fs).unwrap_err();
+        assert!(err.to_string().contains("isn't deployed"), "got: {err}");
+        let err = cat(&config, "b.conf", CatStage::Generated, &fs).unwrap_err();
+        assert!(err.to_string().contains("direct file"), "got: {err}");
+        assert!(cat(&config, "c.conf", CatStage::Source, &fs).is_err());
+    }
+
+    #[test]
+    fn masks_secrets() {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "a.conf", "token = hunter2hunter2\n");
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        let mut mask = SecretMask::default();
+        mask.add("api_token", "hunter2hunter2");
+        let mut out = Vec::new();
+        let entry = find_entry(&config, "a.conf", &fs).unwrap();
+        run(&config, entry, CatStage::Generated, &fs, &mask, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "token = <secret api_token>\n"
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! `janus check`: report config problems without changing anything.
+//!
+//! Loads the config without validation and runs every rule, so all problems
+//! are reported at once. Rules that [`Config::validate`] enforces (duplicate
+//! targets and names, per-machine targets, srcs leaving `dotfiles_dir`) fail
+//! every other command at load time; the rest would only fail later, partway
+//! through a pipeline run, or quietly mix up state (unnamed entries sharing
+//! a `src`).
+
+use anyhow::{Result, bail};
+use tracing::info;
+
+use crate::alias;
+use crate::config::Config;
+use crate::paths::{collapse_tilde, expand_tilde};
+use crate::platform::Fs;
+use crate::policy;
+use crate::template;
+
+/// A single problem found by a check rule.
+#[derive(Debug)]
+pub struct Problem {
+    /// Short rule name (e.g. `duplicate-target`).
+    pub rule: &'static str,
+    /// What's wrong and which entries are involved.
+    pub message: String,
+}
+
+/// Run every rule against the config and return the problems found, in rule
+/// order.
+pub fn compute(config: &Config, fs: &impl Fs) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    for (target, srcs) in config.duplicate_targets(fs) {
+        problems.push(Problem {
+            rule: "duplicate-target",
+            message: format!(
+                "{} is the target of {}",
+                collapse_tilde(&target, fs),
+                srcs.join(", ")
+            ),
+        });
+    }
+
+    for message in config.name_problems() {
+        problems.push(Problem {
+            rule: "names",
+            message,
+        });
+    }
+
+    for src in config.unnamed_duplicate_srcs() {
+        problems.push(Problem {
+            rule: "duplicate-src",
+            message: format!(
+                "{src} is managed by several entries without a name; give all but one a `name` so their deploy state stays apart"
+            ),
+        });
+    }
+
+    for chain in alias::loops(&config.alias) {
+        problems.push(Problem {
+            rule: "alias-loop",
+            message: format!("alias {} never reaches a command", chain.join(" -> ")),
+        });
+    }
+
+    for message in config.src_problems() {
+        problems.push(Problem {
+            rule: "src-outside-dotfiles",
+            message,
+        });
+    }
+
+    for entry in &config.files {
+        let target = expand_tilde(&entry.target(), fs);
+        let mapped = config.mapped_targets(&entry.target(), fs);
+        for path in std::iter::once(&target).chain(&mapped) {
+            if let Some(problem) = policy::target_problem(config, path, fs) {
+                problems.push(Problem {
+                    rule: "target-policy",
+                    message: format!("{}: {problem}", entry.src),
+                });
+            }
+        }
+    }
+
+    for message in config.host_target_problems() {
+        problems.push(Problem {
+            rule: "host-targets",
+            message,
+        });
+    }
+
+    for message in config.pipeline_dir_problems(fs) {
+        problems.push(Problem {
+            rule: "pipeline-dirs",
+            message,
+        });
+    }
+
+    for layer in config.layers(fs).iter().skip(1) {
+        if !fs.is_dir(layer) {
+            problems.push(Problem {
+                rule: "missing-layer",
+                message: format!(
+                    "dotfiles_dirs entry {} is not a directory",
+                    collapse_tilde(layer, fs)
+                ),
+            });
+        }
+    }
+
+    for entry in &config.files {
+        if config.source_layer(&entry.src, fs).is_none() {
+            problems.push(Problem {
+                rule: "missing-source",
+                message: format!("{} does not exist in the dotfiles directory", entry.src),
+            });
+        } else if entry.template
+            && !entry.direct
+            && let Ok(content) = fs.read_to_string(&config.source_path(&entry.src, fs))
+            && let Err(e) = template::check_syntax(&entry.src, &content)
+        {
+            problems.push(Problem {
+                rule: "template-syntax",
+                message: format!("{}: {}", entry.src, e.root_cause()),
+            });
+        }
+        if entry.direct && entry.template {
+            problems.push(Problem {
+                rule: "direct-template",
+                message: format!(
+                    "{}: direct = true and template = true are mutually exclusive",
+                    entry.src
+                ),
+            });
+        }
+        if !entry.direct && entry.regions_template.is_some() {
+            problems.push(Problem {
+                rule: "regions-template",
+                message: format!(
+                    "{}: regions_template only applies to direct files",
+                    entry.src
+                ),
+            });
+        }
+        if entry.direct && !entry.postprocess.is_empty() {
+            problems.push(Problem {
+                rule: "direct-postprocess",
+                message: format!(
+                    "{}: postprocess has no effect on direct files, which skip generate",
+                    entry.src
+                ),
+            });
+        }
+    }
+
+    if let Some(default_targets) = config.default_targets.as_deref()
+        && default_targets != "all"
+    {
+        for name in default_targets.split(',').map(str::trim) {
+            if !config.filesets.contains_key(name) {
+                problems.push(Problem {
+                    rule: "unknown-fileset",
+                    message: format!("default_targets references unknown fileset {name}"),
+                });
+            }
+        }
+    }
+
+    problems
+}
+
+/// Print every problem and fail if there were any.
+pub fn run(config: &Config, fs: &impl Fs) -> Result<()> {
+    let problems = compute(config, fs);
+    if problems.is_empty() {
+        info!("No problems found");
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("{}: {}", problem.rule, problem.message);
+    }
+    bail!("{} problem(s) found", problems.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+    use std::path::Path;
+
+    fn load_unchecked(fs: &crate::platform::FakeFs, toml: &str) -> Config {
+        fs.add_file(CONFIG_PATH, toml);
+        Config::load_unchecked(Path::new(CONFIG_PATH), fs).unwrap()
+    }
+
+    #[test]
+    fn clean_config_passes() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.conf"), "a");
+        let config = load_unchecked(&fs, &make_config_toml(&[("a.conf", None)]));
+        assert!(compute(&config, &fs).is_empty());
+        run(&config, &fs).unwrap();
+    }
+
+    #[test]
+    fn duplicate_target_lists_both_srcs() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.conf"), "a");
+        fs.add_file(format!("{DOTFILES}/b.conf"), "b");
+        let config = load_unchecked(
+            &fs,
+            &make_config_toml(&[
+                ("a.conf", Some("~/.bashrc")),
+                ("b.conf", Some("/home/test/.bashrc")),
+            ]),
+        );
+        let problems = compute(&config, &fs);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].rule, "duplicate-target");
+        assert_eq!(
+            problems[0].message,
+            "~/.bashrc is the target of a.conf, b.conf"
+        );
+    }
+
+    #[test]
+    fn reports_every_rule() {
+        let fs = setup_fs();
+        let toml = format!(
+            r#"dotfiles_dir = "{DOTFILES}"
+default_targets = "desktop"
+
+[[files]]
+src = "missing.conf"
+direct = true
+postprocess = ["jq ."]
+"#
+        );
+        let config = load_unchecked(&fs, &toml);
+        let rules: Vec<&str> = compute(&config, &fs).iter().map(|p| p.rule).collect();
+        assert_eq!(
+            rules,
+            vec![
+                "missing-source",
+                "direct-template",
+                "direct-postprocess",
+                "unknown-fileset"
+            ]
+        );
+        let msg = format!("{:#}", run(&config, &fs).unwrap_err());
+        assert_eq!(msg, "4 problem(s) found");
+    }
+
+    #[test]
+    fn missing_layer_reported() {
+        let fs = setup_fs();
+        fs.add_file("/srv/team/a.conf", "a");
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\ndotfiles_dirs = [\"/srv/team\", \"/srv/gone\"]\n\n\
+             [[files]]\nsrc = \"a.conf\"\n"
+        );
+        let config = load_unchecked(&fs, &toml);
+        let problems = compute(&config, &fs);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].rule, "missing-layer");
+        assert_eq!(
+            problems[0].message,
+            "dotfiles_dirs entry /srv/gone is not a directory"
+        );
+    }
+
+    #[test]
+    fn template_syntax_reported_with_excerpt() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.conf"), "ok\n{{ broken \n");
+        fs.add_file(format!("{DOTFILES}/b.conf"), "{{ fine }}\n");
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\n\n[[files]]\nsrc = \"a.conf\"\n\n[[files]]\nsrc = \"b.conf\"\n"
+        );
+        let config = load_unchecked(&fs, &toml);
+        let problems = compute(&config, &fs);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].rule, "template-syntax");
+        assert!(
+            problems[0].message.starts_with("a.conf: "),
+            "got: {}",
+            problems[0].message
+        );
+        assert!(
+            problems[0].message.contains("--> a.conf:"),
+            "got: {}",
+            problems[0].message
+        );
+    }
+}
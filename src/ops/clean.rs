@@ -2,8 +2,16 @@
 //!
 //! Two modes:
 //! - `--generated`: wipe everything in `.generated/` (files and empty dirs).
+//!   With `--filesets`, only the fileset's files are removed, from both
+//!   `.generated/` and `.staged/`; staged files that are still deployed are
+//!   preserved.
 //! - `--orphans`: remove files in `.generated/` and `.staged/` that are no longer
 //!   in the config. Staged orphans that are still actively deployed are preserved.
+//!   Blobs in the staged store (see [`crate::store`]) no staged file uses
+//!   any more are removed too.
+//!
+//! With `use_trash = true`, removed files go to the trash instead (see
+//! [`crate::trash`]).
 //!
 //! Uses error-collection strategy: continues processing remaining files after
 //! individual failures.
@@ -15,8 +23,10 @@ use tracing::{debug, info, warn};
 
 use crate::config::Config;
 use crate::paths::expand_tilde;
+use crate::plan;
 use crate::platform::{Fs, WalkOptions};
 use crate::state::State;
+use crate::{store, trash};
 
 /// Result of a clean operation: count of removed files and any errors encountered.
 struct CleanResult {
@@ -25,10 +35,14 @@ struct CleanResult {
 }
 
 /// Clean generated files, orphans, or both. Requires at least one flag.
+///
+/// `files` limits `generated` to the matching entries' artifacts; `None`
+/// wipes all of `.generated/`.
 pub fn run(
     config: &Config,
     generated: bool,
     orphans: bool,
+    files: Option<&[String]>,
     dry_run: bool,
     fs: &impl Fs,
 ) -> Result<()> {
@@ -39,7 +53,10 @@ pub fn run(
     let mut errors: Vec<(PathBuf, anyhow::Error)> = Vec::new();
 
     if generated {
-        let result = clean_generated(config, dry_run, fs)?;
+        let result = match files {
+            Some(files) => clean_selected(config, files, dry_run, fs)?,
+            None => clean_generated(config, dry_run, fs)?,
+        };
         errors.extend(result.errors);
     }
 
@@ -49,16 +66,50 @@ pub fn run(
     }
 
     if !errors.is_empty() {
-        let mut msg = format!("Failed to clean {} file(s):", errors.len());
-        for (path, e) in &errors {
-            msg.push_str(&format!("\n  {}: {e:#}", path.display()));
-        }
-        bail!(msg);
+        let errors: Vec<(String, anyhow::Error)> = errors
+            .into_iter()
+            .map(|(path, e)| (path.display().to_string(), e))
+            .collect();
+        return Err(super::FileErrors::new("clean", &errors).into());
     }
 
     Ok(())
 }
 
+/// Files `clean --generated` would remove, relative to `.generated/`. With
+/// `files`, the srcs of the matching entries that have something to remove.
+pub fn generated_files(
+    config: &Config,
+    files: Option<&[String]>,
+    fs: &impl Fs,
+) -> Result<Vec<String>> {
+    if let Some(files) = files {
+        let staged_dir = config.staged_dir(fs);
+        let deployed = deployed_staged_keys(config, &staged_dir, fs)?;
+        return Ok(selected_artifacts(config, files, &deployed, fs)
+            .into_iter()
+            .map(|(src, _)| src)
+            .collect());
+    }
+    let generated_dir = config.generated_dir(fs);
+    if !fs.exists(&generated_dir) {
+        return Ok(Vec::new());
+    }
+    let entries = fs.walk_dir(
+        &generated_dir,
+        &WalkOptions {
+            min_depth: 1,
+            ..Default::default()
+        },
+    )?;
+    Ok(entries
+        .iter()
+        .filter(|e| e.is_file)
+        .filter_map(|e| e.path.strip_prefix(&generated_dir).ok())
+        .map(|p| p.display().to_string())
+        .collect())
+}
+
 /// Delete everything in .generated/
 fn clean_generated(config: &Config, dry_run: bool, fs: &impl Fs) -> Result<CleanResult> {
     let generated_dir = config.generated_dir(fs);
@@ -85,12 +136,13 @@ fn clean_generated(config: &Config, dry_run: bool, fs: &impl Fs) -> Result<Clean
     for entry in &entries {
         if dry_run {
             info!("[dry-run] Would remove: {}", entry.path.display());
+            plan::action("remove", "", &entry.path.to_string_lossy(), "clean");
             count += entry.is_file as usize;
             continue;
         }
 
         if entry.is_file {
-            match fs.remove_file(&entry.path) {
+            match trash::remove_file(config, &entry.path, fs) {
                 Ok(()) => count += 1,
                 Err(e) => {
                     warn!("Failed to remove: {}", entry.path.display());
@@ -106,6 +158,106 @@ fn clean_generated(config: &Config, dry_run: bool, fs: &impl Fs) -> Result<Clean
     Ok(CleanResult { count, errors })
 }
 
+/// Remove the generated and staged copies of the entries matching `files`,
+/// keeping staged copies that are still deployed.
+fn clean_selected(
+    config: &Config,
+    files: &[String],
+    dry_run: bool,
+    fs: &impl Fs,
+) -> Result<CleanResult> {
+    if config.filter_files(Some(files)).is_empty() {
+        config.bail_unmatched(Some(files))?;
+    }
+    let generated_dir = config.generated_dir(fs);
+    let staged_dir = config.staged_dir(fs);
+    let deployed = deployed_staged_keys(config, &staged_dir, fs)?;
+
+    let mut count = 0usize;
+    let mut errors = Vec::new();
+    for (src, paths) in selected_artifacts(config, files, &deployed, fs) {
+        for path in paths {
+            if dry_run {
+                info!("[dry-run] Would remove: {}", path.display());
+                plan::action("remove", &src, &path.to_string_lossy(), "clean");
+                count += 1;
+                continue;
+            }
+            match trash::remove_file(config, &path, fs) {
+                Ok(()) => {
+                    count += 1;
+                    let stop_at = if path.starts_with(&generated_dir) {
+                        &generated_dir
+                    } else {
+                        &staged_dir
+                    };
+                    super::unimport::remove_empty_parents(&path, stop_at, fs);
+                }
+                Err(e) => {
+                    warn!("Failed to remove: {}", path.display());
+                    errors.push((path, e));
+                }
+            }
+        }
+        debug!("Cleaned {src}");
+    }
+
+    info!("Cleaned {} generated/staged file(s)", count);
+    Ok(CleanResult { count, errors })
+}
+
+/// The existing generated and staged copies of the non-direct entries
+/// matching `files`, by src. Staged copies of entries whose key is in
+/// `deployed` are left out.
+fn selected_artifacts(
+    config: &Config,
+    files: &[String],
+    deployed: &HashSet<String>,
+    fs: &impl Fs,
+) -> Vec<(String, Vec<PathBuf>)> {
+    let generated_dir = config.generated_dir(fs);
+    let staged_dir = config.staged_dir(fs);
+    config
+        .filter_files(Some(files))
+        .into_iter()
+        .filter(|entry| !entry.direct)
+        .filter_map(|entry| {
+            let mut paths = vec![generated_dir.join(&entry.src)];
+            if deployed.contains(entry.key()) {
+                debug!("Keeping staged copy (still deployed): {}", entry.src);
+            } else {
+                paths.push(staged_dir.join(&entry.src));
+            }
+            paths.retain(|p| fs.is_file(p));
+            (!paths.is_empty()).then(|| (entry.src.clone(), paths))
+        })
+        .collect()
+}
+
+/// State keys of the entries whose deployed target is still a symlink into
+/// `.staged/`. A key with no configured entry is taken to be its own src.
+fn deployed_staged_keys(
+    config: &Config,
+    staged_dir: &Path,
+    fs: &impl Fs,
+) -> Result<HashSet<String>> {
+    let state = State::load(&config.state_dir(fs), fs)?;
+    Ok(state
+        .deployed
+        .iter()
+        .filter(|d| {
+            let src = config
+                .files
+                .iter()
+                .find(|e| e.key() == d.src)
+                .map_or(d.src.as_str(), |e| e.src.as_str());
+            let target_path = expand_tilde(&d.target, fs);
+            is_symlink_to(&target_path, &staged_dir.join(src), fs)
+        })
+        .map(|d| d.src.clone())
+        .collect())
+}
+
 /// Remove orphan files from `.generated/` and `.staged/`.
 ///
 /// A file is an orphan if its relative path doesn't match any configured `src`.
@@ -121,6 +273,7 @@ fn clean_orphans(config: &Config, dry_run: bool, fs: &impl Fs) -> Result<CleanRe
         .collect();
 
     let gen_result = clean_orphans_in_dir(
+        config,
         &config.generated_dir(fs),
         "generated",
         &configured_srcs,
@@ -129,29 +282,26 @@ fn clean_orphans(config: &Config, dry_run: bool, fs: &impl Fs) -> Result<CleanRe
         fs,
     )?;
 
-    let dotfiles_dir = config.dotfiles_dir(fs);
-    let state = State::load(&dotfiles_dir, fs)?;
     let staged_dir = config.staged_dir(fs);
-
-    let deployed_srcs: HashSet<&str> = state
-        .deployed
-        .iter()
-        .filter(|d| {
-            let target_path = expand_tilde(&d.target, fs);
-            is_symlink_to(&target_path, &staged_dir.join(&d.src), fs)
-        })
-        .map(|d| d.src.as_str())
-        .collect();
+    // Orphans have no configured entry, so their keys are their srcs
+    let deployed_keys = deployed_staged_keys(config, &staged_dir, fs)?;
 
     let staged_result = clean_orphans_in_dir(
+        config,
         &staged_dir,
         "staged",
         &configured_srcs,
-        |relative| !deployed_srcs.contains(relative),
+        |relative| !deployed_keys.contains(relative),
         dry_run,
         fs,
     )?;
 
+    // Blobs only staged copies of files no longer configured were using
+    let pruned = store::prune(&staged_dir, dry_run, fs)?;
+    if pruned > 0 {
+        info!("Removed {pruned} unused blob(s) from the staged store");
+    }
+
     let total = gen_result.count + staged_result.count;
     if total == 0 {
         info!("No orphans found");
@@ -173,6 +323,7 @@ fn clean_orphans(config: &Config, dry_run: bool, fs: &impl Fs) -> Result<CleanRe
 /// Walk a directory, remove files whose relative path isn't in `configured_srcs`
 /// and for which `extra_check(relative_path)` returns true.
 fn clean_orphans_in_dir(
+    config: &Config,
     dir: &Path,
     label: &str,
     configured_srcs: &HashSet<&str>,
@@ -199,7 +350,11 @@ fn clean_orphans_in_dir(
     let mut errors = Vec::new();
     let mut dirs_to_check: Vec<PathBuf> = Vec::new();
 
+    let store_dir = store::store_dir(dir);
     for entry in &entries {
+        if entry.path.starts_with(&store_dir) {
+            continue;
+        }
         if entry.is_dir {
             dirs_to_check.push(entry.path.clone());
             continue;
@@ -222,8 +377,14 @@ fn clean_orphans_in_dir(
 
         if dry_run {
             info!("[dry-run] Would remove {} orphan: {}", label, relative);
+            plan::action(
+                "remove",
+                "",
+                &entry.path.to_string_lossy(),
+                &format!("{label} orphan"),
+            );
         } else {
-            match fs.remove_file(&entry.path) {
+            match trash::remove_file(config, &entry.path, fs) {
                 Ok(()) => {
                     info!("Removed {} orphan: {}", label, relative);
                 }
@@ -266,7 +427,7 @@ mod tests {
     fn requires_flag() {
         let fs = setup_fs();
         let config = write_and_load_config(&fs, &make_config_toml(&[]));
-        let result = run(&config, false, false, false, &fs);
+        let result = run(&config, false, false, None, false, &fs);
         assert!(result.is_err());
         let msg = format!("{:#}", result.unwrap_err());
         assert!(msg.contains("--generated"), "got: {msg}");
@@ -281,11 +442,22 @@ mod tests {
             &fs,
             &make_config_toml(&[("a.conf", None), ("b.conf", None)]),
         );
-        run(&config, true, false, false, &fs).unwrap();
+        run(&config, true, false, None, false, &fs).unwrap();
         assert!(!fs.exists(Path::new(&format!("{DOTFILES}/.generated/a.conf"))));
         assert!(!fs.exists(Path::new(&format!("{DOTFILES}/.generated/b.conf"))));
     }
 
+    #[test]
+    fn generated_files_lists_relative_paths() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/.generated/a.conf"), "a");
+        fs.add_file(format!("{DOTFILES}/.generated/hypr/b.conf"), "b");
+        let config = write_and_load_config(&fs, &make_config_toml(&[]));
+        let mut files = generated_files(&config, None, &fs).unwrap();
+        files.sort();
+        assert_eq!(files, vec!["a.conf", "hypr/b.conf"]);
+    }
+
     #[test]
     fn clean_generated_missing_dir_noop() {
         let fs = FakeFs::new(HOME);
@@ -293,7 +465,7 @@ mod tests {
         fs.add_file(format!("{DOTFILES}/.janus_state.toml"), "");
         // No .generated dir
         let config = write_and_load_config(&fs, &make_config_toml(&[]));
-        run(&config, true, false, false, &fs).unwrap();
+        run(&config, true, false, None, false, &fs).unwrap();
     }
 
     #[test]
@@ -301,18 +473,101 @@ mod tests {
         let fs = setup_fs();
         fs.add_file(format!("{DOTFILES}/.generated/a.conf"), "a");
         let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
-        run(&config, true, false, true, &fs).unwrap();
+        run(&config, true, false, None, true, &fs).unwrap();
         // File should still exist
         assert!(fs.exists(Path::new(&format!("{DOTFILES}/.generated/a.conf"))));
     }
 
+    fn fileset_config(fs: &FakeFs) -> Config {
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\n\n\
+             [filesets.desktop]\npatterns = [\"hypr/*\"]\n\n\
+             [[files]]\nsrc = \"hypr/a.conf\"\n\n\
+             [[files]]\nsrc = \"hypr/b.conf\"\n\n\
+             [[files]]\nsrc = \"zshrc\"\n"
+        );
+        write_and_load_config(fs, &toml)
+    }
+
+    #[test]
+    fn clean_selected_removes_only_matching_artifacts() {
+        let fs = setup_fs();
+        for src in ["hypr/a.conf", "hypr/b.conf", "zshrc"] {
+            fs.add_file(format!("{DOTFILES}/.generated/{src}"), "g");
+            fs.add_file(format!("{DOTFILES}/.staged/{src}"), "s");
+        }
+        // b.conf is deployed, so its staged copy must survive
+        fs.add_symlink(
+            format!("{HOME}/.config/hypr/b.conf"),
+            format!("{DOTFILES}/.staged/hypr/b.conf"),
+        );
+        fs.add_file(
+            format!("{DOTFILES}/.janus_state.toml"),
+            "[[deployed]]\nsrc = \"hypr/b.conf\"\ntarget = \"~/.config/hypr/b.conf\"\n",
+        );
+        let config = fileset_config(&fs);
+        let patterns = config.resolve_filesets(&["desktop".to_string()]).unwrap();
+
+        let mut listed = generated_files(&config, Some(&patterns), &fs).unwrap();
+        listed.sort();
+        assert_eq!(listed, vec!["hypr/a.conf", "hypr/b.conf"]);
+
+        run(&config, true, false, Some(&patterns), false, &fs).unwrap();
+        let exists = |p: &str| fs.exists(Path::new(&format!("{DOTFILES}/{p}")));
+        assert!(!exists(".generated/hypr/a.conf"));
+        assert!(!exists(".staged/hypr/a.conf"));
+        assert!(!exists(".generated/hypr/b.conf"));
+        assert!(exists(".staged/hypr/b.conf"));
+        assert!(exists(".generated/zshrc"));
+        assert!(exists(".staged/zshrc"));
+    }
+
+    #[test]
+    fn clean_selected_keeps_staged_copy_of_deployed_named_entry() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/.generated/git/config"), "g");
+        fs.add_file(format!("{DOTFILES}/.staged/git/config"), "s");
+        fs.add_symlink(
+            format!("{HOME}/work/.gitconfig"),
+            format!("{DOTFILES}/.staged/git/config"),
+        );
+        // Named entries are keyed by name in state
+        fs.add_file(
+            format!("{DOTFILES}/.janus_state.toml"),
+            "[[deployed]]\nsrc = \"work\"\ntarget = \"~/work/.gitconfig\"\n",
+        );
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\n\n\
+             [filesets.g]\npatterns = [\"git/*\"]\n\n\
+             [[files]]\nsrc = \"git/config\"\nname = \"work\"\n\
+             target = \"~/work/.gitconfig\"\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let patterns = config.resolve_filesets(&["g".to_string()]).unwrap();
+
+        run(&config, true, false, Some(&patterns), false, &fs).unwrap();
+        let exists = |p: &str| fs.exists(Path::new(&format!("{DOTFILES}/{p}")));
+        assert!(!exists(".generated/git/config"));
+        assert!(exists(".staged/git/config"));
+    }
+
+    #[test]
+    fn clean_selected_dry_run_keeps_files() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/.generated/hypr/a.conf"), "g");
+        let config = fileset_config(&fs);
+        let patterns = config.resolve_filesets(&["desktop".to_string()]).unwrap();
+        run(&config, true, false, Some(&patterns), true, &fs).unwrap();
+        assert!(fs.exists(Path::new(&format!("{DOTFILES}/.generated/hypr/a.conf"))));
+    }
+
     #[test]
     fn clean_orphans_removes_unconfigured() {
         let fs = setup_fs();
         fs.add_file(format!("{DOTFILES}/.generated/orphan.conf"), "orphan");
         fs.add_file(format!("{DOTFILES}/.generated/kept.conf"), "kept");
         let config = write_and_load_config(&fs, &make_config_toml(&[("kept.conf", None)]));
-        run(&config, false, true, false, &fs).unwrap();
+        run(&config, false, true, None, false, &fs).unwrap();
         assert!(!fs.exists(Path::new(&format!("{DOTFILES}/.generated/orphan.conf"))));
         assert!(fs.exists(Path::new(&format!("{DOTFILES}/.generated/kept.conf"))));
     }
@@ -323,7 +578,7 @@ mod tests {
         fs.add_file(format!("{DOTFILES}/.generated/a.conf"), "a");
         fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "a");
         let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
-        run(&config, false, true, false, &fs).unwrap();
+        run(&config, false, true, None, false, &fs).unwrap();
         assert!(fs.exists(Path::new(&format!("{DOTFILES}/.generated/a.conf"))));
         assert!(fs.exists(Path::new(&format!("{DOTFILES}/.staged/a.conf"))));
     }
@@ -341,7 +596,7 @@ mod tests {
             "[[deployed]]\nsrc = \"orphan.conf\"\ntarget = \"~/.config/orphan.conf\"\n";
         fs.add_file(format!("{DOTFILES}/.janus_state.toml"), state_toml);
         let config = write_and_load_config(&fs, &make_config_toml(&[]));
-        run(&config, false, true, false, &fs).unwrap();
+        run(&config, false, true, None, false, &fs).unwrap();
         // Staged orphan that is still deployed should be preserved
         assert!(fs.exists(Path::new(&staged_path)));
     }
@@ -359,7 +614,7 @@ mod tests {
         );
         // Not in config → orphan
         let config = write_and_load_config(&fs, &make_config_toml(&[]));
-        run(&config, false, true, false, &fs).unwrap();
+        run(&config, false, true, None, false, &fs).unwrap();
         assert!(!fs.exists(Path::new(&format!(
             "{DOTFILES}/.generated/deep/nested/orphan.conf"
         ))));
@@ -374,7 +629,31 @@ mod tests {
         fs.add_file(format!("{DOTFILES}/.staged/orphan.conf"), "orphan");
         // Not deployed
         let config = write_and_load_config(&fs, &make_config_toml(&[]));
-        run(&config, false, true, false, &fs).unwrap();
+        run(&config, false, true, None, false, &fs).unwrap();
         assert!(!fs.exists(Path::new(&format!("{DOTFILES}/.staged/orphan.conf"))));
     }
+
+    #[test]
+    fn clean_orphans_prunes_unused_blobs() {
+        let fs = setup_fs();
+        let staged_dir = Path::new(DOTFILES).join(".staged");
+        for (src, content) in [("kept.conf", "kept"), ("orphan.conf", "orphan")] {
+            store::link(
+                &staged_dir,
+                &staged_dir.join(src),
+                content.as_bytes(),
+                0o644,
+                &fs,
+            )
+            .unwrap();
+        }
+        let config = write_and_load_config(&fs, &make_config_toml(&[("kept.conf", None)]));
+        run(&config, false, true, None, false, &fs).unwrap();
+
+        let store_dir = store::store_dir(&staged_dir);
+        let blobs = fs.walk_dir(&store_dir, &WalkOptions::default()).unwrap();
+        assert_eq!(blobs.iter().filter(|e| e.is_file).count(), 1);
+        assert!(fs.exists(&staged_dir.join("kept.conf")));
+        assert!(!fs.exists(&staged_dir.join("orphan.conf")));
+    }
 }
@@ -0,0 +1,465 @@
+//! `janus config`: inspect the config file format, and edit the config.
+//!
+//! `janus config schema` prints a JSON Schema for `config.toml`, vars files,
+//! or secrets files. Editors with TOML schema support (taplo, VS Code's Even
+//! Better TOML) use it to validate and autocomplete those files. The config
+//! and secrets schemas are derived from the same types janus parses, so they
+//! can't drift from what it accepts.
+//!
+//! `get`, `set`, `unset`, and `add-file` read and edit `config.toml` by
+//! dotted key paths via `toml_edit`, keeping comments and formatting (see
+//! `parse_key` for how keys containing dots are resolved). An edit that
+//! would leave the config unparseable is refused.
+
+use anyhow::{Context, Result, bail};
+use schemars::schema_for;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use toml_edit::{DocumentMut, Item};
+use tracing::{info, warn};
+
+use super::fileset::edit_config;
+use super::import::append_config_entry;
+use crate::config::Config;
+use crate::plan;
+use crate::platform::Fs;
+use crate::secrets::SecretsFile;
+
+/// Kind of file to print a schema for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SchemaKind {
+    /// The janus `config.toml`.
+    Config,
+    /// A vars file listed in `vars`.
+    Vars,
+    /// A secrets file listed in `secrets`.
+    Secrets,
+}
+
+impl fmt::Display for SchemaKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SchemaKind::Config => "config",
+            SchemaKind::Vars => "vars",
+            SchemaKind::Secrets => "secrets",
+        })
+    }
+}
+
+/// Build the JSON Schema for `kind`, pretty-printed.
+pub fn schema(kind: SchemaKind) -> Result<String> {
+    let mut root = match kind {
+        SchemaKind::Config => schema_for!(Config),
+        SchemaKind::Secrets => schema_for!(SecretsFile),
+        // Vars files are free-form: any top-level keys, any TOML values
+        SchemaKind::Vars => schema_for!(HashMap<String, serde_json::Value>),
+    };
+    root.schema.metadata().title = Some(format!("janus {kind} file"));
+    serde_json::to_string_pretty(&root).context("Failed to serialize schema")
+}
+
+/// Print the schema for `kind` to stdout.
+pub fn print_schema(kind: SchemaKind) -> Result<()> {
+    println!("{}", schema(kind)?);
+    Ok(())
+}
+
+/// One step along a key path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Key(key) => f.write_str(key),
+            Step::Index(index) => write!(f, "{index}"),
+        }
+    }
+}
+
+/// How long `key` is if `path` starts with it as a whole segment.
+fn key_len(path: &str, key: &str) -> Option<usize> {
+    let rest = path.strip_prefix(key)?;
+    (rest.is_empty() || rest.starts_with('.')).then_some(key.len())
+}
+
+/// Split a dotted key path into steps through `doc`.
+///
+/// Keys may contain dots (`files.hypr/hypr.conf.target`), so at each level
+/// the longest existing key that fits wins, and anything else is read up to
+/// the next dot. An array of tables like `[[files]]` is indexed by an
+/// entry's `src` or its position; plain arrays by position.
+fn parse_key(doc: &DocumentMut, path: &str) -> Result<Vec<Step>> {
+    let mut steps = Vec::new();
+    let mut item = Some(doc.as_item());
+    let mut rest = path;
+    loop {
+        let segment = rest.split('.').next().unwrap_or(rest);
+        let (step, len) = match item {
+            Some(Item::ArrayOfTables(array)) => {
+                let by_src = array
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, table)| {
+                        let src = table.get("src")?.as_str()?;
+                        key_len(rest, src).map(|len| (i, len))
+                    })
+                    .max_by_key(|&(_, len)| len);
+                match by_src {
+                    Some((i, len)) => (Step::Index(i), len),
+                    None => {
+                        let index = segment
+                            .parse()
+                            .ok()
+                            .filter(|&i| i < array.len())
+                            .with_context(|| format!("No entry {segment} in {path}"))?;
+                        (Step::Index(index), segment.len())
+                    }
+                }
+            }
+            Some(Item::Value(toml_edit::Value::Array(_))) if segment.parse::<usize>().is_ok() => {
+                (Step::Index(segment.parse()?), segment.len())
+            }
+            _ => {
+                let len = item
+                    .and_then(|i| i.as_table_like())
+                    .and_then(|t| t.iter().filter_map(|(k, _)| key_len(rest, k)).max())
+                    .unwrap_or(segment.len());
+                (Step::Key(rest[..len].to_string()), len)
+            }
+        };
+        if len == 0 {
+            bail!("Empty segment in key {path}");
+        }
+        item = item.and_then(|i| match &step {
+            Step::Key(key) => i.get(key.as_str()),
+            Step::Index(index) => i.get(*index),
+        });
+        steps.push(step);
+        match rest[len..].strip_prefix('.') {
+            Some(next) => rest = next,
+            None => return Ok(steps),
+        }
+    }
+}
+
+fn lookup<'a>(doc: &'a DocumentMut, steps: &[Step]) -> Option<&'a Item> {
+    steps
+        .iter()
+        .try_fold(doc.as_item(), |item, step| match step {
+            Step::Key(key) => item.get(key.as_str()),
+            Step::Index(index) => item.get(*index),
+        })
+        .filter(|item| !item.is_none())
+}
+
+/// Print the value at `key`: strings bare, so scripts can use them as is,
+/// anything else as TOML.
+pub fn get(config_path: &Path, key: &str, fs: &impl Fs, out: &mut impl Write) -> Result<()> {
+    let contents = fs
+        .read_to_string(config_path)
+        .with_context(|| format!("Failed to read config: {}", config_path.display()))?;
+    let doc: DocumentMut = contents
+        .parse()
+        .with_context(|| "Failed to parse config for editing")?;
+    let steps = parse_key(&doc, key)?;
+    match lookup(&doc, &steps) {
+        Some(Item::Value(toml_edit::Value::String(s))) => writeln!(out, "{}", s.value())?,
+        Some(Item::Value(value)) => writeln!(out, "{}", value.to_string().trim())?,
+        Some(item) => write!(out, "{item}")?,
+        None => bail!("{key} isn't set"),
+    }
+    Ok(())
+}
+
+/// Parse `value` as a TOML value (`12`, `true`, `["a", "b"]`), or take it
+/// as a plain string if it isn't one.
+fn parse_value(value: &str) -> toml_edit::Value {
+    let mut parsed = value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| value.into());
+    parsed.decor_mut().clear();
+    parsed
+}
+
+/// Apply `edit` to the config, refusing the change if the result no longer
+/// parses as a config.
+fn edit_checked(
+    config_path: &Path,
+    fs: &impl Fs,
+    edit: impl FnOnce(&mut DocumentMut) -> Result<()>,
+) -> Result<()> {
+    edit_config(config_path, fs, |doc| {
+        edit(doc)?;
+        toml::from_str::<Config>(&doc.to_string())
+            .context("The change would leave the config invalid")?;
+        Ok(())
+    })
+}
+
+/// Set `key` to `value`, creating missing tables along the way.
+pub fn set(config_path: &Path, key: &str, value: &str, dry_run: bool, fs: &impl Fs) -> Result<()> {
+    let value = parse_value(value);
+    if dry_run {
+        info!("[dry-run] Would set {key} = {value}");
+        plan::action("set", &value.to_string(), key, "config set");
+        return Ok(());
+    }
+    edit_checked(config_path, fs, |doc| {
+        let steps = parse_key(doc, key)?;
+        let (last, parents) = steps.split_last().expect("keys have a step");
+        let mut item = doc.as_item_mut();
+        for step in parents {
+            item = match step {
+                Step::Key(k) => {
+                    let Some(table) = item.as_table_like_mut() else {
+                        bail!("Can't set {key}: the value holding {k} isn't a table");
+                    };
+                    table.entry(k).or_insert_with(|| {
+                        let mut table = toml_edit::Table::new();
+                        table.set_implicit(true);
+                        Item::Table(table)
+                    })
+                }
+                Step::Index(i) => item
+                    .get_mut(*i)
+                    .with_context(|| format!("No entry {i} in {key}"))?,
+            };
+        }
+        match last {
+            Step::Key(k) => {
+                let Some(table) = item.as_table_like_mut() else {
+                    bail!("Can't set {key}: the value holding {k} isn't a table");
+                };
+                table.insert(k, Item::Value(value.clone()));
+            }
+            Step::Index(i) => {
+                let slot = item
+                    .get_mut(*i)
+                    .with_context(|| format!("No entry {i} in {key}"))?;
+                *slot = Item::Value(value.clone());
+            }
+        }
+        Ok(())
+    })?;
+    info!("Set {key} = {value}");
+    Ok(())
+}
+
+/// Remove `key` from the config.
+pub fn unset(config_path: &Path, key: &str, dry_run: bool, fs: &impl Fs) -> Result<()> {
+    let contents = fs
+        .read_to_string(config_path)
+        .with_context(|| format!("Failed to read config: {}", config_path.display()))?;
+    let doc: DocumentMut = contents
+        .parse()
+        .with_context(|| "Failed to parse config for editing")?;
+    if lookup(&doc, &parse_key(&doc, key)?).is_none() {
+        bail!("{key} isn't set");
+    }
+    if dry_run {
+        info!("[dry-run] Would unset {key}");
+        plan::action("unset", "", key, "config unset");
+        return Ok(());
+    }
+    edit_checked(config_path, fs, |doc| {
+        let steps = parse_key(doc, key)?;
+        let (last, parents) = steps.split_last().expect("keys have a step");
+        let parent = lookup_mut(doc, parents).context("key disappeared")?;
+        match (last, parent) {
+            (Step::Index(i), Item::ArrayOfTables(array)) => {
+                array.remove(*i);
+            }
+            (Step::Index(i), Item::Value(toml_edit::Value::Array(array))) => {
+                array.remove(*i);
+            }
+            (Step::Key(k), parent) => {
+                parent
+                    .as_table_like_mut()
+                    .and_then(|t| t.remove(k))
+                    .context("key disappeared")?;
+            }
+            _ => bail!("Can't unset {key}"),
+        }
+        Ok(())
+    })?;
+    info!("Unset {key}");
+    Ok(())
+}
+
+fn lookup_mut<'a>(doc: &'a mut DocumentMut, steps: &[Step]) -> Option<&'a mut Item> {
+    steps
+        .iter()
+        .try_fold(doc.as_item_mut(), |item, step| match step {
+            Step::Key(key) => item.get_mut(key.as_str()),
+            Step::Index(index) => item.get_mut(*index),
+        })
+}
+
+/// Append a `[[files]]` entry for `src`, deployed to `target` (default
+/// `~/.config/<src>`). Fails if `src` is already configured.
+pub fn add_file(
+    config: &Config,
+    config_path: &Path,
+    src: &str,
+    target: Option<&str>,
+    dry_run: bool,
+    fs: &impl Fs,
+) -> Result<()> {
+    if config.files.iter().any(|e| e.src == src) {
+        bail!(
+            "{src} is already configured (use `janus config set files.{src}.<key>` to change it)"
+        );
+    }
+    if !fs.exists(&config.source_path(src, fs)) {
+        warn!("{src} doesn't exist in the dotfiles directory yet");
+    }
+    let target = target.map_or_else(|| format!("~/.config/{src}"), str::to_string);
+    if dry_run {
+        info!("[dry-run] Would add {src} -> {target}");
+        plan::action("add-entry", src, &target, "config add-file");
+        return Ok(());
+    }
+    append_config_entry(config_path, src, &target, fs)?;
+    info!("Added {src} -> {target}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+    use serde_json::Value;
+
+    fn parsed(kind: SchemaKind) -> Value {
+        serde_json::from_str(&schema(kind).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn config_schema_describes_fields() {
+        let schema = parsed(SchemaKind::Config);
+        assert_eq!(schema["title"], "janus config file");
+        assert_eq!(schema["required"], serde_json::json!(["dotfiles_dir"]));
+        assert!(schema["properties"]["use_trash"].is_object());
+
+        let file_entry = &schema["definitions"]["FileEntry"];
+        assert_eq!(file_entry["required"], serde_json::json!(["src"]));
+        assert_eq!(file_entry["properties"]["template"]["default"], true);
+        // Set at load time, not by the user
+        assert!(file_entry["properties"].get("host_target").is_none());
+    }
+
+    #[test]
+    fn secrets_schema_lists_entries() {
+        let schema = parsed(SchemaKind::Secrets);
+        assert_eq!(schema["properties"]["secret"]["type"], "array");
+        let entry = &schema["definitions"]["SecretEntry"];
+        assert_eq!(entry["required"], serde_json::json!(["engine", "name"]));
+        // Either form may be given, but not both
+        for key in ["reference", "references"] {
+            let description = entry["properties"][key]["description"].as_str().unwrap();
+            assert!(
+                description.contains("Exactly one of `reference` and `references`"),
+                "got: {description}"
+            );
+        }
+    }
+
+    const EDITABLE: &str = r#"dotfiles_dir = "/home/test/dotfiles"
+vars = ["vars.toml"]
+
+# Status bar
+[[files]]
+src = "waybar/config.jsonc"
+target = "~/.config/waybar/config.jsonc"
+
+[[files]]
+src = "waybar/config"
+"#;
+
+    fn get_str(fs: &crate::platform::FakeFs, key: &str) -> Result<String> {
+        let mut out = Vec::new();
+        get(Path::new(CONFIG_PATH), key, fs, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn keys_may_contain_dots() {
+        let fs = setup_fs();
+        write_and_load_config(&fs, EDITABLE);
+        assert_eq!(
+            get_str(&fs, "files.waybar/config.jsonc.target").unwrap(),
+            "~/.config/waybar/config.jsonc\n"
+        );
+        assert_eq!(get_str(&fs, "files.1.src").unwrap(), "waybar/config\n");
+        assert_eq!(get_str(&fs, "vars").unwrap(), "[\"vars.toml\"]\n");
+        assert_eq!(get_str(&fs, "vars.0").unwrap(), "vars.toml\n");
+        assert!(get_str(&fs, "files.waybar/config.target").is_err());
+        assert!(get_str(&fs, "files.nope.src").is_err());
+    }
+
+    #[test]
+    fn set_and_unset_keep_formatting() {
+        let fs = setup_fs();
+        write_and_load_config(&fs, EDITABLE);
+        let path = Path::new(CONFIG_PATH);
+        set(path, "vars", r#"["vars.toml", "work.toml"]"#, false, &fs).unwrap();
+        set(
+            path,
+            "files.waybar/config.target",
+            "~/.config/waybar/base",
+            false,
+            &fs,
+        )
+        .unwrap();
+        set(path, "ui.pager", "less -R", false, &fs).unwrap();
+        unset(path, "files.waybar/config.jsonc.target", false, &fs).unwrap();
+
+        let config = Config::load(path, &fs).unwrap();
+        assert_eq!(config.vars, vec!["vars.toml", "work.toml"]);
+        assert_eq!(config.files[0].target, None);
+        assert_eq!(config.files[1].target(), "~/.config/waybar/base");
+        assert_eq!(config.ui.pager.as_deref(), Some("less -R"));
+        let contents = fs.read_to_string(path).unwrap();
+        assert!(
+            contents.contains("# Status bar\n[[files]]"),
+            "got: {contents}"
+        );
+        assert!(unset(path, "files.waybar/config.jsonc.target", false, &fs).is_err());
+    }
+
+    #[test]
+    fn invalid_edits_are_refused() {
+        let fs = setup_fs();
+        write_and_load_config(&fs, EDITABLE);
+        let path = Path::new(CONFIG_PATH);
+        let err = set(path, "vars", "12", false, &fs).unwrap_err();
+        assert!(format!("{err:#}").contains("invalid"), "got: {err:#}");
+        assert_eq!(fs.read_to_string(path).unwrap(), EDITABLE);
+    }
+
+    #[test]
+    fn add_file_appends_entry() {
+        let fs = setup_fs();
+        let config = write_and_load_config(&fs, EDITABLE);
+        let path = Path::new(CONFIG_PATH);
+        add_file(&config, path, "kitty/kitty.conf", None, false, &fs).unwrap();
+        assert!(add_file(&config, path, "waybar/config", None, false, &fs).is_err());
+
+        let config = Config::load(path, &fs).unwrap();
+        assert_eq!(config.files.len(), 3);
+        assert_eq!(config.files[2].target(), "~/.config/kitty/kitty.conf");
+    }
+
+    #[test]
+    fn vars_schema_is_any_table() {
+        let schema = parsed(SchemaKind::Vars);
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["title"], "janus vars file");
+    }
+}
@@ -2,123 +2,653 @@
 //!
 //! Each target path becomes a symlink pointing to the corresponding file in
 //! `.staged/`. Existing files are backed up unless `--force` is set. Uses
-//! fail-fast strategy with state saved after each file.
+//! fail-fast strategy with state saved after each file; with `--keep-going`
+//! it collects per-file errors instead, like generate, and reports them at
+//! the end.
+//!
+//! The first backup of a target goes to `<name>.janus.bak` and is never
+//! overwritten; its path is recorded in state. Later backups are numbered
+//! (`.janus.bak.1` oldest) and only the newest `keep_backups` are kept.
+//! With `[backup] dir`, backups go under that directory instead, at the
+//! target's mirrored path with a timestamp, and each is recorded in state
+//! for `janus backups restore`; the first and newest `keep_backups` are
+//! kept there too.
+//!
+//! Targets must pass the [`crate::policy`] checks: under `[policy]
+//! allowed_targets` if set, and outside sensitive paths like `~/.ssh`
+//! unless `--allow-sensitive-targets`.
 //!
 //! The `atomic-deploy` feature (default) creates a temp symlink then atomically
 //! renames it over the target, avoiding any window where the file doesn't exist.
 
-use anyhow::{Context, Result};
-use std::path::Path;
+use anyhow::{Context, Result, bail};
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use tracing::{info, warn};
 
-use crate::config::Config;
-use crate::paths::expand_tilde;
-use crate::platform::Fs;
-use crate::state::{RecoveryInfo, State};
+use super::FileErrors;
+use super::diff::{unified_diff, write_colored};
+use crate::config::{Config, FileEntry};
+use crate::journal::{self, Action, Intent};
+use crate::paths::{collapse_tilde, expand_tilde};
+use crate::plan;
+use crate::platform::xdg_trash::format_file_date;
+use crate::platform::{CommandRunner, Fs, Prompter};
+use crate::policy;
+use crate::progress;
+use crate::state::{BackupEntry, RecoveryInfo, State, content_hash, unix_now};
+use crate::systemd::SystemdActions;
+
+/// How to handle an existing non-janus file at a deploy target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resolution {
+    /// Back up the existing file, then replace it.
+    Backup,
+    /// Replace the existing file without a backup.
+    Overwrite,
+    /// Copy the existing content into the source, then replace it.
+    Adopt,
+    /// Leave the existing file alone and don't deploy this entry.
+    Skip,
+    /// Stop deploying.
+    Abort,
+}
 
 /// Deploy staged files as symlinks to their target paths.
 ///
-/// Bails on the first error. Saves state after each successful deployment
-/// with recovery info in case the save itself fails.
+/// Existing non-janus files at a target are backed up, or overwritten if
+/// `force` is set. Bails on the first error. Saves state after each
+/// successful deployment with recovery info in case the save itself fails.
 pub fn run(
     config: &Config,
     files: Option<&[String]>,
     force: bool,
     dry_run: bool,
     fs: &impl Fs,
+    runner: &impl CommandRunner,
 ) -> Result<()> {
+    deploy(config, files, force, false, dry_run, fs, runner)?.into_result()
+}
+
+/// [`run`], but a file that fails to deploy doesn't stop the rest; every
+/// failure is reported at the end.
+pub fn run_keep_going(
+    config: &Config,
+    files: Option<&[String]>,
+    force: bool,
+    dry_run: bool,
+    fs: &impl Fs,
+    runner: &impl CommandRunner,
+) -> Result<()> {
+    deploy(config, files, force, true, dry_run, fs, runner)?.into_result()
+}
+
+/// [`run`], reporting what happened to each file.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn deploy(
+    config: &Config,
+    files: Option<&[String]>,
+    force: bool,
+    keep_going: bool,
+    dry_run: bool,
+    fs: &impl Fs,
+    runner: &impl CommandRunner,
+) -> Result<DeployOutcome> {
+    let resolution = if force {
+        Resolution::Overwrite
+    } else {
+        Resolution::Backup
+    };
+    deploy_entries(config, files, keep_going, dry_run, fs, runner, |_, _, _| {
+        Ok(resolution)
+    })
+}
+
+/// Deploy staged files, asking how to resolve each conflicting target.
+///
+/// For each existing non-janus file at a target, shows a diff between it and
+/// the content about to be deployed, then prompts: backup and replace, adopt
+/// the existing content into the source (unless that would overwrite template
+/// expressions), skip, or abort.
+pub fn run_interactive(
+    config: &Config,
+    files: Option<&[String]>,
+    dry_run: bool,
+    fs: &impl Fs,
+    prompter: &impl Prompter,
+    runner: &impl CommandRunner,
+    out: &mut impl Write,
+) -> Result<()> {
+    deploy_interactive(config, files, false, dry_run, fs, prompter, runner, out)?.into_result()
+}
+
+/// [`run_interactive`], but a file that fails to deploy doesn't stop the
+/// rest. Choosing abort at the prompt still stops.
+pub fn run_interactive_keep_going(
+    config: &Config,
+    files: Option<&[String]>,
+    dry_run: bool,
+    fs: &impl Fs,
+    prompter: &impl Prompter,
+    runner: &impl CommandRunner,
+    out: &mut impl Write,
+) -> Result<()> {
+    deploy_interactive(config, files, true, dry_run, fs, prompter, runner, out)?.into_result()
+}
+
+/// [`run_interactive`], reporting what happened to each file.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn deploy_interactive(
+    config: &Config,
+    files: Option<&[String]>,
+    keep_going: bool,
+    dry_run: bool,
+    fs: &impl Fs,
+    prompter: &impl Prompter,
+    runner: &impl CommandRunner,
+    out: &mut impl Write,
+) -> Result<DeployOutcome> {
+    deploy_entries(
+        config,
+        files,
+        keep_going,
+        dry_run,
+        fs,
+        runner,
+        |entry, link_source, target_path| {
+            prompt_conflict(config, entry, link_source, target_path, fs, prompter, out)
+        },
+    )
+}
+
+/// What a deploy got through before it finished or stopped.
+#[derive(Default)]
+pub(crate) struct DeployOutcome {
+    /// Files linked (or on a dry run, that would be), in order.
+    pub deployed: Vec<String>,
+    /// Files skipped at the conflict prompt.
+    pub skipped: Vec<String>,
+    /// The file deploy stopped at, and why.
+    pub failure: Option<(String, anyhow::Error)>,
+    /// Files that failed while deploy kept going past them.
+    pub errors: Vec<(String, anyhow::Error)>,
+    /// Why the systemd actions for the deployed files failed, if they did.
+    pub systemd: Option<anyhow::Error>,
+}
+
+impl DeployOutcome {
+    /// The deploy's result on its own: the file's error if one stopped it,
+    /// else the collected file errors, else the systemd error, if any.
+    pub fn into_result(self) -> Result<()> {
+        if let Some((_, e)) = self.failure {
+            return Err(e);
+        }
+        if !self.errors.is_empty() {
+            return Err(FileErrors::new("deploy", &self.errors).into());
+        }
+        match self.systemd {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The user chose abort at the conflict prompt, which stops deploy even
+/// when it's keeping going past errors.
+#[derive(Debug)]
+struct Aborted(String);
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Deploy aborted at {}", self.0)
+    }
+}
+
+impl std::error::Error for Aborted {}
+
+/// Shared deploy entry point. `resolve` is called for each target already
+/// occupied by something other than the expected janus symlink.
+///
+/// systemd actions for the files that were deployed run at the end, even if
+/// a later file failed.
+#[allow(clippy::too_many_arguments)]
+fn deploy_entries(
+    config: &Config,
+    files: Option<&[String]>,
+    keep_going: bool,
+    dry_run: bool,
+    fs: &impl Fs,
+    runner: &impl CommandRunner,
+    resolve: impl FnMut(&FileEntry, &Path, &Path) -> Result<Resolution>,
+) -> Result<DeployOutcome> {
     let entries = config.filter_files(files);
     if entries.is_empty() {
         config.bail_unmatched(files)?;
         info!("No files to deploy");
-        return Ok(());
+        return Ok(DeployOutcome::default());
+    }
+
+    let mut actions = SystemdActions::new();
+    let result = deploy_each(
+        config,
+        &entries,
+        keep_going,
+        dry_run,
+        fs,
+        &mut actions,
+        resolve,
+    );
+    let systemd_result = actions.run(dry_run, runner);
+    let mut outcome = result?;
+    if !dry_run && outcome.failure.is_none() {
+        if outcome.errors.is_empty() {
+            info!("Deployed {} file(s)", outcome.deployed.len());
+        } else {
+            info!(
+                "Deployed {} file(s) with {} failure(s)",
+                outcome.deployed.len(),
+                outcome.errors.len()
+            );
+        }
     }
+    outcome.systemd = systemd_result.err();
+    Ok(outcome)
+}
 
-    let dotfiles_dir = config.dotfiles_dir(fs);
+/// Deploy each entry in order, stopping at the first error unless
+/// `keep_going`.
+#[allow(clippy::too_many_arguments)]
+fn deploy_each(
+    config: &Config,
+    entries: &[&FileEntry],
+    keep_going: bool,
+    dry_run: bool,
+    fs: &impl Fs,
+    actions: &mut SystemdActions,
+    mut resolve: impl FnMut(&FileEntry, &Path, &Path) -> Result<Resolution>,
+) -> Result<DeployOutcome> {
     let staged_dir = config.staged_dir(fs);
-    let mut state = State::load(&dotfiles_dir, fs)?;
+    let state_dir = config.state_dir(fs);
+    let mut state = State::load(&state_dir, fs)?;
+    let mut outcome = DeployOutcome::default();
 
-    for entry in &entries {
-        let link_source = if entry.direct {
-            dotfiles_dir.join(&entry.src)
-        } else {
-            staged_dir.join(&entry.src)
-        };
-        let target_path = expand_tilde(&entry.target(), fs);
-
-        if !fs.exists(&link_source) {
-            if entry.direct {
-                anyhow::bail!(
-                    "Source file not found: {} (direct mode)",
-                    link_source.display()
-                );
-            } else {
-                anyhow::bail!(
-                    "Staged file not found: {} (run `janus stage` first)",
-                    link_source.display()
-                );
+    let progress = progress::phase("deploy", entries.len());
+    for entry in entries {
+        progress.advance(&entry.src);
+        match deploy_entry(
+            config,
+            entry,
+            &staged_dir,
+            &state_dir,
+            &mut state,
+            dry_run,
+            fs,
+            actions,
+            &mut resolve,
+        ) {
+            Ok(true) => outcome.deployed.push(entry.src.clone()),
+            Ok(false) => outcome.skipped.push(entry.src.clone()),
+            Err(e) if keep_going && !e.is::<Aborted>() => {
+                warn!("Failed to deploy {}: {e:#}", entry.src);
+                outcome.errors.push((entry.src.clone(), e));
+            }
+            Err(e) => {
+                outcome.failure = Some((entry.src.clone(), e));
+                break;
             }
         }
+    }
 
-        if dry_run {
-            info!(
-                "[dry-run] Would deploy: {} -> {}",
+    Ok(outcome)
+}
+
+/// Deploy one entry, saving state once it's linked. Returns `false` if
+/// `resolve` chose to skip it.
+#[allow(clippy::too_many_arguments)]
+fn deploy_entry(
+    config: &Config,
+    entry: &FileEntry,
+    staged_dir: &Path,
+    state_dir: &Path,
+    state: &mut State,
+    dry_run: bool,
+    fs: &impl Fs,
+    actions: &mut SystemdActions,
+    resolve: &mut impl FnMut(&FileEntry, &Path, &Path) -> Result<Resolution>,
+) -> Result<bool> {
+    let link_source = if entry.direct {
+        config.source_path(&entry.src, fs)
+    } else {
+        staged_dir.join(&entry.src)
+    };
+    let target_path = expand_tilde(&entry.target(), fs);
+
+    if !fs.exists(&link_source) {
+        if entry.direct {
+            anyhow::bail!(
+                "Source file not found: {} (direct mode)",
+                link_source.display()
+            );
+        } else {
+            anyhow::bail!(
+                "Staged file not found: {} (run `janus stage` first)",
+                link_source.display()
+            );
+        }
+    }
+
+    let mapped = config.mapped_targets(&entry.target(), fs);
+    for path in std::iter::once(&target_path).chain(&mapped) {
+        if let Some(problem) = policy::target_problem(config, path, fs) {
+            anyhow::bail!("{}: {problem}", entry.src);
+        }
+        if let Some(parent) = path.parent()
+            && !config.creates_parents(entry)
+            && !fs.is_dir(parent)
+        {
+            anyhow::bail!(
+                "{}: target directory {} doesn't exist (create_parents = false)",
                 entry.src,
-                target_path.display()
+                parent.display()
             );
-            continue;
         }
+    }
 
-        // Create parent directories
-        if let Some(parent) = target_path.parent() {
+    if dry_run {
+        info!(
+            "[dry-run] Would deploy: {} -> {}",
+            entry.src,
+            target_path.display()
+        );
+        let reason = if is_janus_symlink(&target_path, &link_source, fs) {
+            "already linked"
+        } else if fs.exists(&target_path) || fs.is_symlink(&target_path) {
+            "replaces an existing file"
+        } else {
+            "not deployed"
+        };
+        plan::action("deploy", &entry.src, &target_path.to_string_lossy(), reason);
+        for path in &mapped {
+            info!("[dry-run] Would also link {}", path.display());
+            plan::action(
+                "deploy",
+                &entry.src,
+                &path.to_string_lossy(),
+                "path_maps copy",
+            );
+        }
+        actions.record_deploy(entry, &target_path, fs);
+        return Ok(true);
+    }
+
+    let conflict = (fs.exists(&target_path) || fs.is_symlink(&target_path))
+        && !is_janus_symlink(&target_path, &link_source, fs);
+    let force = if conflict {
+        match resolve(entry, &link_source, &target_path)? {
+            Resolution::Backup => false,
+            Resolution::Overwrite => true,
+            Resolution::Adopt => {
+                adopt_existing(config, entry, &target_path, fs)?;
+                true
+            }
+            Resolution::Skip => {
+                info!("Skipped {}", entry.src);
+                return Ok(false);
+            }
+            Resolution::Abort => return Err(Aborted(entry.src.clone()).into()),
+        }
+    } else {
+        false
+    };
+
+    let backups = Backups::new(config, fs);
+
+    // Journal the change, so a crash before the state save below is
+    // reconciled by the next run
+    journal::begin(
+        state_dir,
+        Intent {
+            action: Action::Deploy,
+            src: entry.key().to_string(),
+            target: entry.target(),
+            link: link_source.clone(),
+            direct: entry.direct,
+            backup: match (conflict && !force, &backups) {
+                (false, _) => None,
+                (true, Backups::Sibling { .. }) => backups.original(&target_path),
+                // Where deploy_symlink is about to put it
+                (true, Backups::Dir { .. }) => Some(backups.next(&target_path, fs)?),
+            }
+            .map(|path| collapse_tilde(&path, fs)),
+            backup_at: match backups {
+                Backups::Dir { at, .. } if conflict && !force => Some(at),
+                _ => None,
+            },
+        },
+        fs,
+    )?;
+
+    // Create parent directories
+    if let Some(parent) = target_path.parent() {
+        fs.create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+
+    let mut made = Vec::new();
+    let backed_up = deploy_symlink(&link_source, &target_path, force, &backups, fs)?;
+    if let Some(backup) = &backed_up {
+        made.push((target_path.clone(), backup.clone()));
+    }
+    // Mapped copies are extra; anything already there is backed up
+    for path in &mapped {
+        if let Some(parent) = path.parent() {
             fs.create_dir_all(parent)
                 .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
         }
+        if let Some(backup) = deploy_symlink(&link_source, path, false, &backups, fs)? {
+            made.push((path.clone(), backup));
+        }
+        info!("Also linked {}", path.display());
+    }
+
+    state.add_deployed(entry.key().to_string(), entry.target());
+    if let Some(backup) = backed_up {
+        let original = backups.original(&target_path).unwrap_or(backup);
+        state.record_backup(entry.key(), collapse_tilde(&original, fs));
+    }
+    if let Backups::Dir { at, .. } = backups {
+        for (target, path) in made {
+            let target = collapse_tilde(&target, fs);
+            state.backups.push(BackupEntry {
+                src: entry.src.clone(),
+                target: target.clone(),
+                path: collapse_tilde(&path, fs),
+                at,
+            });
+            prune_dir_backups(
+                state,
+                &target,
+                config.keep_backups.unwrap_or(DEFAULT_KEEP_BACKUPS),
+                fs,
+            )?;
+        }
+    }
+    if entry.direct {
+        // Remember what the source looked like so status/sync can tell
+        // when it's been edited through the symlink.
+        let bytes = fs
+            .read(&link_source)
+            .with_context(|| format!("Failed to read source: {}", link_source.display()))?;
+        state.set_source_hash(entry.key(), content_hash(&bytes));
+    }
+    state.save_with_recovery(
+        RecoveryInfo {
+            situation: vec![format!(
+                "{} has been deployed to {}",
+                entry.src,
+                target_path.display()
+            )],
+            consequence: vec![format!(
+                "janus will not know {} is deployed to {}",
+                entry.src,
+                target_path.display()
+            )],
+            instructions: vec![
+                "The next janus command that changes anything records it from the deploy journal".to_string(),
+                format!(
+                    "If that fails too, add a [[deployed]] entry to the statefile with src = \"{}\" and target = \"{}\"",
+                    entry.key(),
+                    entry.target()
+                ),
+                format!("Or re-run: janus deploy {}", entry.selector()),
+            ],
+        },
+        fs,
+    )?;
+    if let Err(e) = journal::commit(state_dir, entry.key(), fs) {
+        warn!("{e:#}");
+    }
+    info!("Deployed {} -> {}", entry.src, target_path.display());
+    actions.record_deploy(entry, &target_path, fs);
+    Ok(true)
+}
 
-        deploy_symlink(&link_source, &target_path, force, fs)?;
+/// Show how an existing target differs from what would be deployed, then ask
+/// the user how to resolve the conflict. Adopting isn't offered when it would
+/// overwrite template expressions in the source.
+fn prompt_conflict(
+    config: &Config,
+    entry: &FileEntry,
+    link_source: &Path,
+    target_path: &Path,
+    fs: &impl Fs,
+    prompter: &impl Prompter,
+    out: &mut impl Write,
+) -> Result<Resolution> {
+    writeln!(
+        out,
+        "\n{} already exists and is not managed by janus",
+        target_path.display()
+    )?;
+    if fs.is_symlink(target_path) {
+        match fs.read_link(target_path) {
+            Ok(dest) => writeln!(out, "It is a symlink to {}", dest.display())?,
+            Err(_) => writeln!(out, "It is a symlink")?,
+        }
+    } else {
+        match (
+            fs.read_to_string(target_path),
+            fs.read_to_string(link_source),
+        ) {
+            (Ok(existing), Ok(incoming)) if existing == incoming => {
+                writeln!(out, "Its content is identical to {}", entry.src)?;
+            }
+            (Ok(existing), Ok(incoming)) => write_colored(
+                out,
+                &unified_diff(
+                    &existing,
+                    &incoming,
+                    &format!("existing/{}", entry.src),
+                    &format!("janus/{}", entry.src),
+                ),
+            )?,
+            _ => writeln!(out, "(binary or unreadable content, no diff shown)")?,
+        }
+    }
 
-        state.add_deployed(entry.src.clone(), entry.target());
-        state.save_with_recovery(
-            RecoveryInfo {
-                situation: vec![format!(
-                    "{} has been deployed to {}",
-                    entry.src,
-                    target_path.display()
-                )],
-                consequence: vec![format!(
-                    "janus will not know {} is deployed to {}",
-                    entry.src,
-                    target_path.display()
-                )],
-                instructions: vec![
-                    format!(
-                        "Add a [[deployed]] entry to the statefile with src = \"{}\" and target = \"{}\"",
-                        entry.src,
-                        entry.target()
-                    ),
-                    format!("Or re-run: janus deploy {}", entry.src),
-                ],
-            },
-            fs,
+    let mut choices = vec![("Backup and replace", Resolution::Backup)];
+    if adopt_clobbers_template(config, entry, fs) {
+        writeln!(
+            out,
+            "{} is a template, so its content can't be adopted; \
+             back it up and replace it, then run `janus sync` to merge its changes",
+            entry.src
         )?;
-        info!("Deployed {} -> {}", entry.src, target_path.display());
+    } else {
+        choices.push(("Adopt existing content into source", Resolution::Adopt));
+    }
+    choices.extend([("Skip", Resolution::Skip), ("Abort", Resolution::Abort)]);
+    let labels: Vec<&str> = choices.iter().map(|(label, _)| *label).collect();
+    let choice = prompter.select(
+        &format!("How should {} be resolved?", entry.src),
+        &labels,
+        0,
+    )?;
+    Ok(choices
+        .get(choice)
+        .map_or(Resolution::Abort, |(_, resolution)| *resolution))
+}
+
+/// Whether adopting a target into `entry`'s source would overwrite the
+/// `{{ }}`/`{% %}` expressions of a template. `janus sync` merges rendered
+/// changes back around them instead.
+fn adopt_clobbers_template(config: &Config, entry: &FileEntry, fs: &impl Fs) -> bool {
+    entry.template
+        && !entry.direct
+        && fs
+            .read_to_string(&config.source_path(&entry.src, fs))
+            .is_ok_and(|source| super::sync::has_tera_syntax(&source))
+}
+
+/// Copy the existing target's content into the entry's source (and, for
+/// pipeline files, into `.generated/` and `.staged/` so the deployed symlink
+/// serves it immediately). Refuses for templates with expressions.
+fn adopt_existing(
+    config: &Config,
+    entry: &FileEntry,
+    target_path: &Path,
+    fs: &impl Fs,
+) -> Result<()> {
+    if adopt_clobbers_template(config, entry, fs) {
+        bail!(
+            "Refusing to adopt {} into template {}: it would overwrite its template expressions \
+             (deploy with a backup, then run `janus sync` to merge the changes)",
+            target_path.display(),
+            entry.src
+        );
+    }
+    let content = fs
+        .read(target_path)
+        .with_context(|| format!("Failed to read existing file: {}", target_path.display()))?;
+
+    let mut paths = vec![config.source_path(&entry.src, fs)];
+    if !entry.direct {
+        paths.push(config.generated_dir(fs).join(&entry.src));
+        paths.push(config.staged_dir(fs).join(&entry.src));
+    }
+    for path in &paths {
+        if let Some(parent) = path.parent() {
+            fs.create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        fs.write(path, &content)
+            .with_context(|| format!("Failed to adopt into: {}", path.display()))?;
     }
 
-    info!("Deployed {} file(s)", entries.len());
+    info!("Adopted {} into {}", target_path.display(), entry.src);
     Ok(())
 }
 
 /// Create a symlink from `target_path` -> `staged_path` using atomic rename.
+/// Returns where an existing file was backed up, if one was.
 ///
 /// Creates a temporary symlink (`.janus.tmp`) then renames it over the target
 /// so there's never a moment where the file is missing.
 #[cfg(feature = "atomic-deploy")]
-fn deploy_symlink(staged_path: &Path, target_path: &Path, force: bool, fs: &impl Fs) -> Result<()> {
+fn deploy_symlink(
+    staged_path: &Path,
+    target_path: &Path,
+    force: bool,
+    backups: &Backups,
+    fs: &impl Fs,
+) -> Result<Option<PathBuf>> {
     let exists = fs.exists(target_path) || fs.is_symlink(target_path);
+    let mut backed_up = None;
 
     // Backup if needed (copy, so the original stays in place until the atomic swap)
     if exists && !force && !is_janus_symlink(target_path, staged_path, fs) {
-        let backup_path = backup_path_for(target_path);
+        let backup_path = backups.next(target_path, fs)?;
         warn!(
             "Backing up existing file: {} -> {}",
             target_path.display(),
@@ -126,6 +656,7 @@ fn deploy_symlink(staged_path: &Path, target_path: &Path, force: bool, fs: &impl
         );
         fs.copy(target_path, &backup_path)
             .with_context(|| format!("Failed to backup file: {}", target_path.display()))?;
+        backed_up = Some(backup_path);
     } else if exists && force && !is_janus_symlink(target_path, staged_path, fs) {
         warn!("Overwriting existing file: {}", target_path.display());
     }
@@ -151,14 +682,22 @@ fn deploy_symlink(staged_path: &Path, target_path: &Path, force: bool, fs: &impl
         format!("Failed to atomically replace: {}", target_path.display())
     })?;
 
-    Ok(())
+    Ok(backed_up)
 }
 
 /// Create a symlink from `target_path` -> `staged_path` using remove-then-create.
+/// Returns where an existing file was backed up, if one was.
 ///
 /// Non-atomic fallback: removes the existing file first, then creates the symlink.
 #[cfg(not(feature = "atomic-deploy"))]
-fn deploy_symlink(staged_path: &Path, target_path: &Path, force: bool, fs: &impl Fs) -> Result<()> {
+fn deploy_symlink(
+    staged_path: &Path,
+    target_path: &Path,
+    force: bool,
+    backups: &Backups,
+    fs: &impl Fs,
+) -> Result<Option<PathBuf>> {
+    let mut backed_up = None;
     if fs.exists(target_path) || fs.is_symlink(target_path) {
         if is_janus_symlink(target_path, staged_path, fs) {
             fs.remove_file(target_path).with_context(|| {
@@ -173,7 +712,7 @@ fn deploy_symlink(staged_path: &Path, target_path: &Path, force: bool, fs: &impl
                 format!("Failed to remove existing file: {}", target_path.display())
             })?;
         } else {
-            let backup_path = backup_path_for(target_path);
+            let backup_path = backups.next(target_path, fs)?;
             warn!(
                 "Backing up existing file: {} -> {}",
                 target_path.display(),
@@ -181,6 +720,7 @@ fn deploy_symlink(staged_path: &Path, target_path: &Path, force: bool, fs: &impl
             );
             fs.rename(target_path, &backup_path)
                 .with_context(|| format!("Failed to backup file: {}", target_path.display()))?;
+            backed_up = Some(backup_path);
         }
     }
 
@@ -192,18 +732,132 @@ fn deploy_symlink(staged_path: &Path, target_path: &Path, force: bool, fs: &impl
         )
     })?;
 
+    Ok(backed_up)
+}
+
+/// Default for `keep_backups`.
+const DEFAULT_KEEP_BACKUPS: usize = 5;
+
+/// Where deploy puts backups of what it replaces.
+enum Backups {
+    /// Next to the target, keeping `keep` numbered ones.
+    Sibling { keep: usize },
+    /// Under `[backup] dir`, stamped with `at`.
+    Dir { dir: PathBuf, at: u64 },
+}
+
+impl Backups {
+    fn new(config: &Config, fs: &impl Fs) -> Self {
+        match config.backup_dir(fs) {
+            Some(dir) => Backups::Dir {
+                dir,
+                at: unix_now(),
+            },
+            None => Backups::Sibling {
+                keep: config.keep_backups.unwrap_or(DEFAULT_KEEP_BACKUPS),
+            },
+        }
+    }
+
+    /// The path the first backup of `target_path` always has, if fixed.
+    fn original(&self, target_path: &Path) -> Option<PathBuf> {
+        match self {
+            Backups::Sibling { .. } => Some(backup_path_for(target_path, 0)),
+            Backups::Dir { .. } => None,
+        }
+    }
+
+    /// Where the next backup of `target_path` goes.
+    fn next(&self, target_path: &Path, fs: &impl Fs) -> Result<PathBuf> {
+        match self {
+            Backups::Sibling { keep } => next_backup_path(target_path, *keep, fs),
+            Backups::Dir { dir, at } => dir_backup_path(dir, target_path, *at, fs),
+        }
+    }
+}
+
+/// `target_path` mirrored under `dir` and suffixed with `at`
+/// (`/home/me/.bashrc` -> `<dir>/home/me/.bashrc.2024-05-01T12-00-00`),
+/// numbered if a backup from the same second exists. Creates its directory.
+fn dir_backup_path(dir: &Path, target_path: &Path, at: u64, fs: &impl Fs) -> Result<PathBuf> {
+    let mirrored = dir.join(target_path.strip_prefix("/").unwrap_or(target_path));
+    if let Some(parent) = mirrored.parent() {
+        fs.create_dir_all(parent)
+            .with_context(|| format!("Failed to create backup directory: {}", parent.display()))?;
+    }
+    let mut name = mirrored.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", format_file_date(at)));
+    let mut path = mirrored.with_file_name(&name);
+    let mut n = 0;
+    while fs.exists(&path) || fs.is_symlink(&path) {
+        n += 1;
+        let mut numbered = name.clone();
+        numbered.push(format!(".{n}"));
+        path = mirrored.with_file_name(numbered);
+    }
+    Ok(path)
+}
+
+/// Drop `target`'s backups under `[backup] dir` beyond the first and the
+/// newest `keep`, from disk and state.
+fn prune_dir_backups(state: &mut State, target: &str, keep: usize, fs: &impl Fs) -> Result<()> {
+    let of_target: Vec<usize> = (0..state.backups.len())
+        .filter(|&i| state.backups[i].target == target)
+        .collect();
+    let excess = of_target.len().saturating_sub(keep.max(1) + 1);
+    for &i in of_target[1..1 + excess].iter().rev() {
+        let old = state.backups.remove(i);
+        let path = expand_tilde(&old.path, fs);
+        if fs.exists(&path) || fs.is_symlink(&path) {
+            fs.remove_file(&path)
+                .with_context(|| format!("Failed to remove old backup: {}", path.display()))?;
+        }
+    }
     Ok(())
 }
 
-/// Compute the backup path for a file (e.g. `config.toml` -> `config.toml.janus.bak`).
-fn backup_path_for(target_path: &Path) -> std::path::PathBuf {
-    target_path.with_extension(format!(
-        "{}.janus.bak",
-        target_path
-            .extension()
-            .map(|e| e.to_string_lossy().to_string())
-            .unwrap_or_default()
-    ))
+/// Backup path number `n` for a file: the original backup for 0
+/// (`config.toml` -> `config.toml.janus.bak`), `config.toml.janus.bak.{n}`
+/// otherwise.
+fn backup_path_for(target_path: &Path, n: usize) -> PathBuf {
+    let mut name = target_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".janus.bak");
+    if n > 0 {
+        name.push(format!(".{n}"));
+    }
+    target_path.with_file_name(name)
+}
+
+/// Where the next backup of `target_path` goes.
+///
+/// The original backup is never replaced. Numbered backups stay contiguous
+/// from `.1` (oldest); once `keep` of them exist, the oldest is deleted and
+/// the rest shift down a number to make room.
+fn next_backup_path(target_path: &Path, keep: usize, fs: &impl Fs) -> Result<PathBuf> {
+    let present = |path: &Path| fs.exists(path) || fs.is_symlink(path);
+    let original = backup_path_for(target_path, 0);
+    if !present(&original) {
+        return Ok(original);
+    }
+
+    let mut count = 0;
+    while present(&backup_path_for(target_path, count + 1)) {
+        count += 1;
+    }
+    while count >= keep.max(1) {
+        let oldest = backup_path_for(target_path, 1);
+        fs.remove_file(&oldest)
+            .with_context(|| format!("Failed to remove old backup: {}", oldest.display()))?;
+        for n in 2..=count {
+            fs.rename(
+                &backup_path_for(target_path, n),
+                &backup_path_for(target_path, n - 1),
+            )
+            .with_context(|| format!("Failed to rotate backups of {}", target_path.display()))?;
+        }
+        count -= 1;
+    }
+    Ok(backup_path_for(target_path, count + 1))
 }
 
 use super::is_janus_symlink;
@@ -211,9 +865,10 @@ use super::is_janus_symlink;
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::platform::{FakeCommandRunner, FakePrompter};
     use crate::state::State;
     use crate::test_helpers::*;
-    use std::path::PathBuf;
+    use std::io;
 
     fn deploy_setup(fs: &crate::platform::FakeFs) -> Config {
         fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "staged content");
@@ -223,11 +878,103 @@ mod tests {
         )
     }
 
+    #[test]
+    fn links_path_map_copies() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/.staged/mpv/mpv.conf"), "vo=gpu");
+        let toml = format!(
+            "{}\n[path_maps]\n\"~/.config/mpv\" = [\"~/.var/app/io.mpv.Mpv/config/mpv\"]\n",
+            make_config_toml(&[("mpv/mpv.conf", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let runner = FakeCommandRunner::new();
+        run(&config, None, false, false, &fs, &runner).unwrap();
+
+        let staged = PathBuf::from(format!("{DOTFILES}/.staged/mpv/mpv.conf"));
+        let mapped = Path::new("/home/test/.var/app/io.mpv.Mpv/config/mpv/mpv.conf");
+        assert_eq!(fs.read_link(mapped).unwrap(), staged);
+
+        crate::ops::undeploy::run(&config, None, true, false, &fs, &runner).unwrap();
+        assert!(!fs.is_symlink(mapped));
+        assert!(!fs.exists(mapped));
+    }
+
+    #[test]
+    fn create_parents_false_requires_target_dir() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "a");
+        fs.add_file(format!("{DOTFILES}/.staged/b.conf"), "b");
+        let toml = format!(
+            "create_parents = false\n{}\ncreate_parents = true\n",
+            make_config_toml(&[
+                ("a.conf", Some("~/.confgi/app/a.conf")),
+                ("b.conf", Some("~/.local/app/b.conf")),
+            ])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        assert!(!config.creates_parents(&config.files[0]));
+        assert!(config.creates_parents(&config.files[1]));
+
+        let runner = FakeCommandRunner::new();
+        let only = |src: &str| Some(vec![src.to_string()]);
+        let msg = format!(
+            "{:#}",
+            run(
+                &config,
+                only("a.conf").as_deref(),
+                false,
+                false,
+                &fs,
+                &runner
+            )
+            .unwrap_err()
+        );
+        assert!(
+            msg.contains("/home/test/.confgi/app doesn't exist"),
+            "got: {msg}"
+        );
+        assert!(!fs.exists(Path::new("/home/test/.confgi")));
+
+        run(
+            &config,
+            only("b.conf").as_deref(),
+            false,
+            false,
+            &fs,
+            &runner,
+        )
+        .unwrap();
+        assert!(fs.is_symlink(Path::new("/home/test/.local/app/b.conf")));
+    }
+
+    #[test]
+    fn sensitive_targets_need_the_flag() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/.staged/keys"), "ssh-ed25519 AAAA");
+        let mut config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[("keys", Some("~/.ssh/authorized_keys"))]),
+        );
+        let runner = FakeCommandRunner::new();
+        let target = Path::new("/home/test/.ssh/authorized_keys");
+
+        let msg = format!(
+            "{:#}",
+            run(&config, None, false, false, &fs, &runner).unwrap_err()
+        );
+        assert!(msg.contains("--allow-sensitive-targets"), "got: {msg}");
+        assert!(!fs.exists(target));
+
+        config.allow_sensitive_targets = true;
+        run(&config, None, false, false, &fs, &runner).unwrap();
+        assert!(fs.is_symlink(target));
+    }
+
     #[test]
     fn creates_symlink() {
         let fs = setup_fs();
         let config = deploy_setup(&fs);
-        run(&config, None, false, false, &fs).unwrap();
+        run(&config, None, false, false, &fs, &FakeCommandRunner::new()).unwrap();
         let target = Path::new("/home/test/.config/a.conf");
         assert!(fs.is_symlink(target));
         let link_dest = fs.read_link(target).unwrap();
@@ -241,7 +988,7 @@ mod tests {
     fn updates_state() {
         let fs = setup_fs();
         let config = deploy_setup(&fs);
-        run(&config, None, false, false, &fs).unwrap();
+        run(&config, None, false, false, &fs, &FakeCommandRunner::new()).unwrap();
         let state = State::load(Path::new(DOTFILES), &fs).unwrap();
         assert!(state.is_deployed("a.conf"));
     }
@@ -258,7 +1005,7 @@ mod tests {
                 ("b.conf", Some("~/.config/b.conf")),
             ]),
         );
-        run(&config, None, false, false, &fs).unwrap();
+        run(&config, None, false, false, &fs, &FakeCommandRunner::new()).unwrap();
         let state = State::load(Path::new(DOTFILES), &fs).unwrap();
         assert!(state.is_deployed("a.conf"));
         assert!(state.is_deployed("b.conf"));
@@ -272,7 +1019,7 @@ mod tests {
             &fs,
             &make_config_toml(&[("deep/nested.conf", Some("~/.config/deep/nested.conf"))]),
         );
-        run(&config, None, false, false, &fs).unwrap();
+        run(&config, None, false, false, &fs, &FakeCommandRunner::new()).unwrap();
         assert!(fs.is_dir(Path::new("/home/test/.config/deep")));
     }
 
@@ -284,7 +1031,7 @@ mod tests {
             &fs,
             &make_config_toml(&[("missing.conf", Some("~/.config/missing.conf"))]),
         );
-        let result = run(&config, None, false, false, &fs);
+        let result = run(&config, None, false, false, &fs, &FakeCommandRunner::new());
         let msg = format!("{:#}", result.unwrap_err());
         assert!(
             msg.contains("Staged file not found") || msg.contains("missing.conf"),
@@ -292,23 +1039,124 @@ mod tests {
         );
     }
 
+    #[test]
+    fn keep_going_deploys_past_failures() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "staged content");
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[("missing.conf", None), ("a.conf", None)]),
+        );
+        let runner = FakeCommandRunner::new();
+        assert!(run(&config, None, false, false, &fs, &runner).is_err());
+        assert!(!fs.exists(Path::new("/home/test/.config/a.conf")));
+
+        let err = run_keep_going(&config, None, false, false, &fs, &runner).unwrap_err();
+        let errors = err.downcast_ref::<FileErrors>().expect("collected errors");
+        assert_eq!(errors.errors.len(), 1);
+        assert_eq!(errors.errors[0].0, "missing.conf");
+        assert!(fs.is_symlink(Path::new("/home/test/.config/a.conf")));
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert!(state.is_deployed("a.conf"));
+        assert!(!state.is_deployed("missing.conf"));
+    }
+
+    #[test]
+    fn keep_going_still_stops_at_abort() {
+        let fs = setup_fs();
+        for src in ["a.conf", "b.conf"] {
+            fs.add_file(format!("{DOTFILES}/.staged/{src}"), "staged content");
+            fs.add_file(format!("/home/test/.config/{src}"), "existing content");
+        }
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[("a.conf", None), ("b.conf", None)]),
+        );
+        let prompter = FakePrompter::new(vec![3, 0]);
+        let err = run_interactive_keep_going(
+            &config,
+            None,
+            false,
+            &fs,
+            &prompter,
+            &FakeCommandRunner::new(),
+            &mut io::sink(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("aborted at a.conf"), "got: {err}");
+        assert!(!fs.is_symlink(Path::new("/home/test/.config/b.conf")));
+    }
+
     #[test]
     fn backup_existing_file() {
         let fs = setup_fs();
         // Put a regular file at the target
         fs.add_file("/home/test/.config/a.conf", "existing content");
         let config = deploy_setup(&fs);
-        run(&config, None, false, false, &fs).unwrap();
+        run(&config, None, false, false, &fs, &FakeCommandRunner::new()).unwrap();
         // Should have created backup
         assert!(fs.exists(Path::new("/home/test/.config/a.conf.janus.bak")));
     }
 
+    /// Replace the deployed symlink with a regular file, then redeploy.
+    fn clobber_and_redeploy(fs: &crate::platform::FakeFs, config: &Config, content: &str) {
+        let target = Path::new("/home/test/.config/a.conf");
+        fs.remove_file(target).unwrap();
+        fs.add_file(target, content);
+        run(config, None, false, false, fs, &FakeCommandRunner::new()).unwrap();
+    }
+
+    fn backup(fs: &crate::platform::FakeFs, suffix: &str) -> Option<String> {
+        fs.read_to_string(Path::new(&format!(
+            "/home/test/.config/a.conf.janus.bak{suffix}"
+        )))
+        .ok()
+    }
+
+    #[test]
+    fn later_backups_are_numbered() {
+        let fs = setup_fs();
+        fs.add_file("/home/test/.config/a.conf", "original");
+        let config = deploy_setup(&fs);
+        run(&config, None, false, false, &fs, &FakeCommandRunner::new()).unwrap();
+        clobber_and_redeploy(&fs, &config, "second");
+
+        assert_eq!(backup(&fs, "").as_deref(), Some("original"));
+        assert_eq!(backup(&fs, ".1").as_deref(), Some("second"));
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert_eq!(
+            state.deployed[0].backup.as_deref(),
+            Some("~/.config/a.conf.janus.bak")
+        );
+    }
+
+    #[test]
+    fn keep_backups_drops_oldest_numbered() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "staged content");
+        fs.add_file("/home/test/.config/a.conf", "original");
+        let toml = format!(
+            "keep_backups = 2\n{}",
+            make_config_toml(&[("a.conf", Some("~/.config/a.conf"))])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        run(&config, None, false, false, &fs, &FakeCommandRunner::new()).unwrap();
+        for content in ["v2", "v3", "v4"] {
+            clobber_and_redeploy(&fs, &config, content);
+        }
+
+        assert_eq!(backup(&fs, "").as_deref(), Some("original"));
+        assert_eq!(backup(&fs, ".1").as_deref(), Some("v3"));
+        assert_eq!(backup(&fs, ".2").as_deref(), Some("v4"));
+        assert_eq!(backup(&fs, ".3"), None);
+    }
+
     #[test]
     fn force_overwrites() {
         let fs = setup_fs();
         fs.add_file("/home/test/.config/a.conf", "existing content");
         let config = deploy_setup(&fs);
-        run(&config, None, true, false, &fs).unwrap();
+        run(&config, None, true, false, &fs, &FakeCommandRunner::new()).unwrap();
         // No backup with force
         assert!(!fs.exists(Path::new("/home/test/.config/a.conf.janus.bak")));
         // But symlink should exist
@@ -320,9 +1168,9 @@ mod tests {
         let fs = setup_fs();
         let config = deploy_setup(&fs);
         // Deploy once
-        run(&config, None, false, false, &fs).unwrap();
+        run(&config, None, false, false, &fs, &FakeCommandRunner::new()).unwrap();
         // Deploy again — existing janus symlink should be replaced without backup
-        run(&config, None, false, false, &fs).unwrap();
+        run(&config, None, false, false, &fs, &FakeCommandRunner::new()).unwrap();
         assert!(!fs.exists(Path::new("/home/test/.config/a.conf.janus.bak")));
         assert!(fs.is_symlink(Path::new("/home/test/.config/a.conf")));
     }
@@ -331,7 +1179,7 @@ mod tests {
     fn dry_run() {
         let fs = setup_fs();
         let config = deploy_setup(&fs);
-        run(&config, None, false, true, &fs).unwrap();
+        run(&config, None, false, true, &fs, &FakeCommandRunner::new()).unwrap();
         assert!(!fs.exists(Path::new("/home/test/.config/a.conf")));
         let state = State::load(Path::new(DOTFILES), &fs).unwrap();
         assert!(!state.is_deployed("a.conf"));
@@ -347,7 +1195,7 @@ mod tests {
             &fs,
             &make_config_toml(&[("deep/nested.conf", Some("~/.config/deep/nested.conf"))]),
         );
-        run(&config, None, false, false, &fs).unwrap();
+        run(&config, None, false, false, &fs, &FakeCommandRunner::new()).unwrap();
         // Backup should exist at nested path
         assert!(fs.exists(Path::new("/home/test/.config/deep/nested.conf.janus.bak")));
         assert!(fs.is_symlink(Path::new("/home/test/.config/deep/nested.conf")));
@@ -388,12 +1236,269 @@ mod tests {
             "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\n\n[[files]]\nsrc = \"direct.conf\"\ntarget = \"~/.config/direct.conf\"\ndirect = true\ntemplate = false\n"
         );
         let config = write_and_load_config(&fs, &toml);
-        run(&config, None, false, false, &fs).unwrap();
+        run(&config, None, false, false, &fs, &FakeCommandRunner::new()).unwrap();
         let target = Path::new("/home/test/.config/direct.conf");
         assert!(fs.is_symlink(target));
         let link_dest = fs.read_link(target).unwrap();
         assert_eq!(link_dest, PathBuf::from(format!("{DOTFILES}/direct.conf")));
         let state = State::load(Path::new(DOTFILES), &fs).unwrap();
         assert!(state.is_deployed("direct.conf"));
+        assert_eq!(
+            state.source_hash("direct.conf"),
+            Some(content_hash(b"source content").as_str())
+        );
+    }
+
+    #[test]
+    fn interactive_backup_and_replace() {
+        let fs = setup_fs();
+        fs.add_file("/home/test/.config/a.conf", "existing content");
+        let config = deploy_setup(&fs);
+        let prompter = FakePrompter::new(vec![0]);
+        run_interactive(
+            &config,
+            None,
+            false,
+            &fs,
+            &prompter,
+            &FakeCommandRunner::new(),
+            &mut io::sink(),
+        )
+        .unwrap();
+        assert!(fs.exists(Path::new("/home/test/.config/a.conf.janus.bak")));
+        assert!(fs.is_symlink(Path::new("/home/test/.config/a.conf")));
+        assert_eq!(prompter.remaining(), 0);
+    }
+
+    #[test]
+    fn interactive_adopt_copies_into_pipeline() {
+        let fs = setup_fs();
+        fs.add_file("/home/test/.config/a.conf", "existing content");
+        fs.add_file(format!("{DOTFILES}/a.conf"), "source content");
+        let config = deploy_setup(&fs);
+        let prompter = FakePrompter::new(vec![1]);
+        run_interactive(
+            &config,
+            None,
+            false,
+            &fs,
+            &prompter,
+            &FakeCommandRunner::new(),
+            &mut io::sink(),
+        )
+        .unwrap();
+
+        for path in [
+            format!("{DOTFILES}/a.conf"),
+            format!("{DOTFILES}/.generated/a.conf"),
+            format!("{DOTFILES}/.staged/a.conf"),
+        ] {
+            assert_eq!(
+                fs.read_to_string(Path::new(&path)).unwrap(),
+                "existing content",
+                "{path}"
+            );
+        }
+        // Content lives in the source now, so no backup is needed
+        assert!(!fs.exists(Path::new("/home/test/.config/a.conf.janus.bak")));
+        assert!(fs.is_symlink(Path::new("/home/test/.config/a.conf")));
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert!(state.is_deployed("a.conf"));
+    }
+
+    #[test]
+    fn interactive_adopt_direct_file() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/direct.conf"), "source content");
+        fs.add_file("/home/test/.config/direct.conf", "existing content");
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\n\n[[files]]\nsrc = \"direct.conf\"\ntarget = \"~/.config/direct.conf\"\ndirect = true\ntemplate = false\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let prompter = FakePrompter::new(vec![1]);
+        run_interactive(
+            &config,
+            None,
+            false,
+            &fs,
+            &prompter,
+            &FakeCommandRunner::new(),
+            &mut io::sink(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs.read_to_string(Path::new(&format!("{DOTFILES}/direct.conf")))
+                .unwrap(),
+            "existing content"
+        );
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/.staged/direct.conf"))));
+        assert!(fs.is_symlink(Path::new("/home/test/.config/direct.conf")));
+    }
+
+    #[test]
+    fn interactive_does_not_offer_adopt_into_template() {
+        let fs = setup_fs();
+        fs.add_file("/home/test/.config/a.conf", "existing content");
+        fs.add_file(format!("{DOTFILES}/a.conf"), "color = {{ color }}");
+        let config = deploy_setup(&fs);
+        // Without adopt, the second choice is skip
+        let prompter = FakePrompter::new(vec![1]);
+        run_interactive(
+            &config,
+            None,
+            false,
+            &fs,
+            &prompter,
+            &FakeCommandRunner::new(),
+            &mut io::sink(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs.read_to_string(Path::new(&format!("{DOTFILES}/a.conf")))
+                .unwrap(),
+            "color = {{ color }}"
+        );
+        assert_eq!(
+            fs.read_to_string(Path::new("/home/test/.config/a.conf"))
+                .unwrap(),
+            "existing content"
+        );
+        assert!(!fs.is_symlink(Path::new("/home/test/.config/a.conf")));
+    }
+
+    #[test]
+    fn interactive_skip_leaves_target() {
+        let fs = setup_fs();
+        fs.add_file("/home/test/.config/a.conf", "existing content");
+        let config = deploy_setup(&fs);
+        let prompter = FakePrompter::new(vec![2]);
+        run_interactive(
+            &config,
+            None,
+            false,
+            &fs,
+            &prompter,
+            &FakeCommandRunner::new(),
+            &mut io::sink(),
+        )
+        .unwrap();
+        let target = Path::new("/home/test/.config/a.conf");
+        assert!(!fs.is_symlink(target));
+        assert_eq!(fs.read_to_string(target).unwrap(), "existing content");
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert!(!state.is_deployed("a.conf"));
+    }
+
+    #[test]
+    fn interactive_abort_stops() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/.staged/b.conf"), "b");
+        fs.add_file("/home/test/.config/a.conf", "existing content");
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[
+                ("a.conf", Some("~/.config/a.conf")),
+                ("b.conf", Some("~/.config/b.conf")),
+            ]),
+        );
+        fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "a");
+        let prompter = FakePrompter::new(vec![3]);
+        let result = run_interactive(
+            &config,
+            None,
+            false,
+            &fs,
+            &prompter,
+            &FakeCommandRunner::new(),
+            &mut io::sink(),
+        );
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(msg.contains("aborted"), "got: {msg}");
+        assert!(!fs.is_symlink(Path::new("/home/test/.config/b.conf")));
+    }
+
+    #[test]
+    fn interactive_no_prompt_without_conflict() {
+        let fs = setup_fs();
+        let config = deploy_setup(&fs);
+        // Empty queue: any prompt would fail
+        let prompter = FakePrompter::new(vec![]);
+        run_interactive(
+            &config,
+            None,
+            false,
+            &fs,
+            &prompter,
+            &FakeCommandRunner::new(),
+            &mut io::sink(),
+        )
+        .unwrap();
+        // Redeploying over our own symlink doesn't prompt either
+        run_interactive(
+            &config,
+            None,
+            false,
+            &fs,
+            &prompter,
+            &FakeCommandRunner::new(),
+            &mut io::sink(),
+        )
+        .unwrap();
+        assert!(fs.is_symlink(Path::new("/home/test/.config/a.conf")));
+    }
+
+    #[test]
+    fn systemd_unit_triggers_reload_and_restart() {
+        let fs = setup_fs();
+        fs.add_file(
+            format!("{DOTFILES}/.staged/systemd/user/waybar.service"),
+            "[Unit]",
+        );
+        fs.add_file(format!("{DOTFILES}/.staged/waybar/config"), "{}");
+        let toml = format!(
+            r#"
+dotfiles_dir = "{DOTFILES}"
+
+[[files]]
+src = "systemd/user/waybar.service"
+systemd_unit = true
+
+[[files]]
+src = "waybar/config"
+reload_units = ["waybar.service"]
+"#
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let runner = FakeCommandRunner::new();
+        run(&config, None, false, false, &fs, &runner).unwrap();
+        assert_eq!(
+            runner.commands(),
+            vec![
+                "systemctl --user daemon-reload",
+                "systemctl --user try-restart waybar.service",
+            ]
+        );
+    }
+
+    #[test]
+    fn systemd_dry_run_runs_nothing() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/.staged/unit.service"), "[Unit]");
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\n\n[[files]]\nsrc = \"unit.service\"\nsystemd_unit = true\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let runner = FakeCommandRunner::new();
+        run(&config, None, false, true, &fs, &runner).unwrap();
+        assert!(runner.calls().is_empty());
+    }
+
+    #[test]
+    fn no_systemd_calls_for_plain_files() {
+        let fs = setup_fs();
+        let config = deploy_setup(&fs);
+        let runner = FakeCommandRunner::new();
+        run(&config, None, false, false, &fs, &runner).unwrap();
+        assert!(runner.calls().is_empty());
     }
 }
@@ -1,85 +1,170 @@
 //! Show unified diffs between `.generated/` and `.staged/` files.
 //!
 //! This is a read-only operation that helps inspect what changed between
-//! the last generation and the last staging. Uses the `similar` crate for
-//! diff computation with colored terminal output.
+//! the last generation and the last staging. `--from`/`--to` pick other
+//! sides: the source, or the source at a git revision rendered the way
+//! generate would (`git:HEAD~1`), to preview what a pull will change, or
+//! what's deployed on another machine (`remote:user@host`, read over ssh),
+//! to spot drift between hosts before a push or pull. Uses the `similar`
+//! crate for diff computation with colored terminal output, paged via
+//! [`crate::output`].
+//! Each file is read once, then the diffs are computed in parallel.
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use rayon::prelude::*;
 use similar::{ChangeTag, TextDiff};
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
 use tracing::{debug, info};
 
-use crate::config::Config;
-use crate::platform::Fs;
+use crate::config::{Config, FileEntry};
+use crate::output::{GREEN, RED, RESET};
+use crate::platform::{CommandRunner, Fs, SecretEngine};
+use crate::secrets::SecretMask;
+use crate::shell;
+
+/// One side of a diff: a pipeline stage, the source as of a git revision, or
+/// a target on another host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stage {
+    /// The source file, unrendered.
+    Source,
+    /// The `.generated/` copy.
+    Generated,
+    /// The `.staged/` copy.
+    Staged,
+    /// The source at a git revision of the dotfiles repo, rendered the way
+    /// generate would render it now (with today's vars and secrets).
+    Git(String),
+    /// What's at the file's target on another host, read with `ssh <host>
+    /// cat`. The target is the one this machine's config gives.
+    Remote(String),
+}
+
+impl Stage {
+    /// Whether this side only exists for files that go through the pipeline.
+    fn in_pipeline(&self) -> bool {
+        matches!(self, Stage::Generated | Stage::Staged)
+    }
+}
+
+impl FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "source" => Ok(Stage::Source),
+            "generated" => Ok(Stage::Generated),
+            "staged" => Ok(Stage::Staged),
+            _ => {
+                if let Some(rev) = s.strip_prefix("git:").filter(|r| !r.is_empty()) {
+                    return Ok(Stage::Git(rev.to_string()));
+                }
+                if let Some(host) = s.strip_prefix("remote:").filter(|h| !h.is_empty()) {
+                    return Ok(Stage::Remote(host.to_string()));
+                }
+                Err(format!(
+                    "unknown stage `{s}` (expected source, generated, staged, git:<rev>, or remote:<host>)"
+                ))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Source => f.write_str("source"),
+            Stage::Generated => f.write_str("generated"),
+            Stage::Staged => f.write_str("staged"),
+            Stage::Git(rev) => write!(f, "git:{rev}"),
+            Stage::Remote(host) => write!(f, "remote:{host}"),
+        }
+    }
+}
 
 /// Computed diff result for a single file.
 pub struct FileDiff {
     /// Relative source path.
     pub src: String,
-    /// What happened: `Identical`, `MissingGenerated`, `MissingStaged`, or `Changed`.
+    /// What happened: `Identical`, `MissingGenerated`, `MissingStaged`,
+    /// `Missing`, or `Changed`.
     pub kind: DiffKind,
 }
 
 /// Classification of a file's diff result.
 #[derive(Debug)]
 pub enum DiffKind {
-    /// Generated and staged are identical.
+    /// Both sides are identical.
     Identical,
     /// No generated file exists.
     MissingGenerated,
     /// No staged file exists.
     MissingStaged,
+    /// The source side doesn't exist: no source file, the file isn't in
+    /// that git revision, or nothing is at the remote target.
+    Missing(Stage),
     /// Files differ; contains the unified diff text.
     Changed(String),
 }
 
-/// Compute diffs between generated and staged versions of the given files.
+impl DiffKind {
+    fn missing(stage: &Stage) -> Self {
+        match stage {
+            Stage::Generated => DiffKind::MissingGenerated,
+            Stage::Staged => DiffKind::MissingStaged,
+            other => DiffKind::Missing(other.clone()),
+        }
+    }
+}
+
+/// Compute diffs from the `from` side to the `to` side of the given files
+/// (generated to staged for plain `janus diff`).
 ///
-/// Returns structured results without printing.
-pub fn compute(config: &Config, files: Option<&[String]>, fs: &impl Fs) -> Result<Vec<FileDiff>> {
+/// Returns structured results without printing. Direct files are skipped
+/// when either side is `.generated/` or `.staged/`, which they don't have.
+pub fn compute(
+    config: &Config,
+    files: Option<&[String]>,
+    from: &Stage,
+    to: &Stage,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
+) -> Result<Vec<FileDiff>> {
     let entries = config.filter_files(files);
     if entries.is_empty() {
         config.bail_unmatched(files)?;
         return Ok(Vec::new());
     }
 
-    let generated_dir = config.generated_dir(fs);
-    let staged_dir = config.staged_dir(fs);
     let mut results = Vec::new();
+    // Indices into `results` of changed files, with both contents
+    let mut changed = Vec::new();
 
     for entry in &entries {
-        if entry.direct {
+        if entry.direct && (from.in_pipeline() || to.in_pipeline()) {
             debug!("Skipping direct file: {}", entry.src);
             continue;
         }
-        let generated_path = generated_dir.join(&entry.src);
-        let staged_path = staged_dir.join(&entry.src);
 
-        if !fs.exists(&generated_path) {
+        let Some(old) = read_side(config, entry, from, fs, engine, runner)? else {
             results.push(FileDiff {
                 src: entry.src.clone(),
-                kind: DiffKind::MissingGenerated,
+                kind: DiffKind::missing(from),
             });
             continue;
-        }
-        if !fs.exists(&staged_path) {
+        };
+        let Some(new) = read_side(config, entry, to, fs, engine, runner)? else {
             results.push(FileDiff {
                 src: entry.src.clone(),
-                kind: DiffKind::MissingStaged,
+                kind: DiffKind::missing(to),
             });
             continue;
-        }
+        };
 
-        let generated_content = fs.read_to_string(&generated_path).with_context(|| {
-            format!(
-                "Failed to read generated file: {}",
-                generated_path.display()
-            )
-        })?;
-        let staged_content = fs
-            .read_to_string(&staged_path)
-            .with_context(|| format!("Failed to read staged file: {}", staged_path.display()))?;
-
-        if generated_content == staged_content {
+        if old == new {
             results.push(FileDiff {
                 src: entry.src.clone(),
                 kind: DiffKind::Identical,
@@ -87,38 +172,155 @@ pub fn compute(config: &Config, files: Option<&[String]>, fs: &impl Fs) -> Resul
             continue;
         }
 
-        let diff = TextDiff::from_lines(&generated_content, &staged_content);
-        let mut diff_text = format!("--- generated/{}\n+++ staged/{}\n", entry.src, entry.src);
-        for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
-            diff_text.push_str(&format!("{}", hunk.header()));
-            for change in hunk.iter_changes() {
-                let prefix = match change.tag() {
-                    ChangeTag::Delete => "-",
-                    ChangeTag::Insert => "+",
-                    ChangeTag::Equal => " ",
-                };
-                diff_text.push_str(&format!("{prefix}{change}"));
-                if change.missing_newline() {
-                    diff_text.push('\n');
-                }
-            }
-        }
-
+        changed.push((results.len(), old, new));
         results.push(FileDiff {
             src: entry.src.clone(),
-            kind: DiffKind::Changed(diff_text),
+            // Replaced with the diff below
+            kind: DiffKind::Identical,
         });
     }
 
+    // `Fs` isn't required to be `Sync`, so reads above stay sequential; the
+    // diffs themselves only need the contents and run in parallel.
+    let diffs: Vec<(usize, String)> = changed
+        .into_par_iter()
+        .map(|(index, old, new)| {
+            let src = &results[index].src;
+            let diff_text =
+                unified_diff(&old, &new, &format!("{from}/{src}"), &format!("{to}/{src}"));
+            (index, diff_text)
+        })
+        .collect();
+    for (index, diff_text) in diffs {
+        results[index].kind = DiffKind::Changed(diff_text);
+    }
+
     Ok(results)
 }
 
-/// Display diffs between generated and staged versions of the given files.
+/// Read one side of `entry`'s diff, or `None` if it doesn't exist.
+fn read_side(
+    config: &Config,
+    entry: &FileEntry,
+    stage: &Stage,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
+) -> Result<Option<String>> {
+    let path = match stage {
+        Stage::Source => config.source_path(&entry.src, fs),
+        Stage::Generated => config.generated_dir(fs).join(&entry.src),
+        Stage::Staged => config.staged_dir(fs).join(&entry.src),
+        Stage::Git(rev) => {
+            let Some(source) = git_show(config, entry, rev, fs, runner)? else {
+                return Ok(None);
+            };
+            return super::generate::render_source(config, entry, source, fs, engine, runner)
+                .with_context(|| format!("Failed to render {} as of {rev}", entry.src))
+                .map(Some);
+        }
+        Stage::Remote(host) => return remote_cat(entry, host, runner),
+    };
+    if !fs.exists(&path) {
+        return Ok(None);
+    }
+    fs.read_to_string(&path)
+        .with_context(|| format!("Failed to read {stage} file: {}", path.display()))
+        .map(Some)
+}
+
+/// `entry`'s source as of git revision `rev`, or `None` if it didn't exist
+/// then. Read from the repo of the layer providing the file.
+fn git_show(
+    config: &Config,
+    entry: &FileEntry,
+    rev: &str,
+    fs: &impl Fs,
+    runner: &impl CommandRunner,
+) -> Result<Option<String>> {
+    let repo = config
+        .source_layer(&entry.src, fs)
+        .unwrap_or_else(|| config.dotfiles_dir(fs));
+    // A `rev` like `--output=x` must not reach git as an option
+    let command = format!(
+        "git -C {} show --end-of-options {}",
+        shell::quote(&repo.to_string_lossy()),
+        shell::quote(&format!("{rev}:./{}", entry.src))
+    );
+    let output = runner.run(&command, None)?;
+    if output.success {
+        return Ok(Some(output.stdout));
+    }
+    // git's wording when the revision exists but the path doesn't
+    if output.stderr.contains("does not exist in")
+        || output.stderr.contains("exists on disk, but not in")
+    {
+        return Ok(None);
+    }
+    bail!("`{command}` failed: {}", output.stderr.trim());
+}
+
+/// What's at `entry`'s target on `host`, or `None` if nothing is.
+///
+/// The remote side prints [`REMOTE_FOUND`] before the content and exits 0
+/// without it when the target doesn't exist, so a missing file is told
+/// from a failure by exit status and output rather than by the wording of
+/// an error, which depends on the remote locale.
+fn remote_cat(
+    entry: &FileEntry,
+    host: &str,
+    runner: &impl CommandRunner,
+) -> Result<Option<String>> {
+    let target = entry.target();
+    // Let the remote shell expand `~` to its own home
+    let path = match target.strip_prefix("~/") {
+        Some(rest) => format!("\"$HOME\"/{}", shell::quote(rest)),
+        None => shell::quote(&target),
+    };
+    let command = format!(
+        "ssh -o BatchMode=yes -- {} {}",
+        shell::quote(host),
+        shell::quote(&format!(
+            "test -e {path} || exit 0; echo {REMOTE_FOUND} && LC_ALL=C exec cat -- {path}"
+        ))
+    );
+    let output = runner.run(&command, None)?;
+    if !output.success {
+        bail!("`{command}` failed: {}", output.stderr.trim());
+    }
+    match output
+        .stdout
+        .strip_prefix(REMOTE_FOUND)
+        .and_then(|rest| rest.strip_prefix('\n'))
+    {
+        Some(content) => Ok(Some(content.to_string())),
+        None if output.stdout.is_empty() => Ok(None),
+        None => bail!("`{command}` printed unexpected output"),
+    }
+}
+
+/// Line [`remote_cat`]'s remote command prints before an existing target.
+const REMOTE_FOUND: &str = "janus-found";
+
+/// Write diffs from the `from` side to the `to` side of the given files to
+/// `out`.
 ///
-/// Files with no diff are silently skipped. Missing generated or staged files
-/// are reported but don't cause an error.
-pub fn run(config: &Config, files: Option<&[String]>, fs: &impl Fs) -> Result<()> {
-    let results = compute(config, files, fs)?;
+/// Files with no diff are silently skipped. Missing sides are reported but
+/// don't cause an error. Secret values in `mask` are replaced before
+/// writing.
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    config: &Config,
+    files: Option<&[String]>,
+    from: &Stage,
+    to: &Stage,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
+    mask: &SecretMask,
+    out: &mut impl Write,
+) -> Result<()> {
+    let results = compute(config, files, from, to, fs, engine, runner)?;
 
     let mut any_diff = false;
     for result in &results {
@@ -132,23 +334,14 @@ pub fn run(config: &Config, files: Option<&[String]>, fs: &impl Fs) -> Result<()
             DiffKind::MissingStaged => {
                 info!("{}: no staged file (run `janus stage` first)", result.src);
             }
+            DiffKind::Missing(stage) => {
+                info!("{}: not in {stage}", result.src);
+            }
             DiffKind::Identical => {}
             DiffKind::Changed(diff_text) => {
                 any_diff = true;
-                // Re-print with colors for terminal output
-                println!("--- generated/{}", result.src);
-                println!("+++ staged/{}", result.src);
-                for line in diff_text.lines().skip(2) {
-                    let (color_start, color_end) = if line.starts_with('-') {
-                        ("\x1b[31m", "\x1b[0m")
-                    } else if line.starts_with('+') {
-                        ("\x1b[32m", "\x1b[0m")
-                    } else {
-                        ("", "")
-                    };
-                    println!("{color_start}{line}{color_end}");
-                }
-                println!();
+                write_colored(out, &mask.mask(diff_text))?;
+                writeln!(out)?;
             }
         }
     }
@@ -160,18 +353,72 @@ pub fn run(config: &Config, files: Option<&[String]>, fs: &impl Fs) -> Result<()
     Ok(())
 }
 
+/// Build a unified diff (3 lines of context) from `old` to `new`, with
+/// `--- {old_label}` / `+++ {new_label}` header lines.
+pub(crate) fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let mut diff_text = format!("--- {old_label}\n+++ {new_label}\n");
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        diff_text.push_str(&format!("{}\n", hunk.header()));
+        for change in hunk.iter_changes() {
+            let prefix = match change.tag() {
+                ChangeTag::Delete => "-",
+                ChangeTag::Insert => "+",
+                ChangeTag::Equal => " ",
+            };
+            diff_text.push_str(&format!("{prefix}{change}"));
+            if change.missing_newline() {
+                diff_text.push('\n');
+            }
+        }
+    }
+    diff_text
+}
+
+/// Write a diff from [`unified_diff`] with colored `-`/`+` lines.
+pub(crate) fn write_colored(out: &mut impl Write, diff_text: &str) -> io::Result<()> {
+    for (i, line) in diff_text.lines().enumerate() {
+        // The first two lines are the ---/+++ headers
+        let (color_start, color_end) = if i < 2 {
+            ("", "")
+        } else if line.starts_with('-') {
+            (RED, RESET)
+        } else if line.starts_with('+') {
+            (GREEN, RESET)
+        } else {
+            ("", "")
+        };
+        writeln!(out, "{color_start}{line}{color_end}")?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::platform::{CommandOutput, FakeCommandRunner, FakeSecretEngine};
     use crate::test_helpers::*;
 
+    fn pipeline_diff(config: &Config, fs: &crate::platform::FakeFs) -> Vec<FileDiff> {
+        compute(
+            config,
+            None,
+            &Stage::Generated,
+            &Stage::Staged,
+            fs,
+            &FakeSecretEngine::new(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap()
+    }
+
     #[test]
     fn identical_returns_identical() {
         let fs = setup_fs();
         fs.add_file(format!("{DOTFILES}/.generated/a.conf"), "same");
         fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "same");
         let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
-        let results = compute(&config, None, &fs).unwrap();
+        let results = pipeline_diff(&config, &fs);
         assert_eq!(results.len(), 1);
         assert_eq!(results[0].src, "a.conf");
         assert!(matches!(results[0].kind, DiffKind::Identical));
@@ -183,7 +430,7 @@ mod tests {
         fs.add_file(format!("{DOTFILES}/.generated/a.conf"), "old\n");
         fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "new\n");
         let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
-        let results = compute(&config, None, &fs).unwrap();
+        let results = pipeline_diff(&config, &fs);
         assert_eq!(results.len(), 1);
         match &results[0].kind {
             DiffKind::Changed(text) => {
@@ -205,7 +452,7 @@ mod tests {
         let fs = setup_fs();
         fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "staged");
         let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
-        let results = compute(&config, None, &fs).unwrap();
+        let results = pipeline_diff(&config, &fs);
         assert_eq!(results.len(), 1);
         assert!(matches!(results[0].kind, DiffKind::MissingGenerated));
     }
@@ -215,7 +462,7 @@ mod tests {
         let fs = setup_fs();
         fs.add_file(format!("{DOTFILES}/.generated/a.conf"), "generated");
         let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
-        let results = compute(&config, None, &fs).unwrap();
+        let results = pipeline_diff(&config, &fs);
         assert_eq!(results.len(), 1);
         assert!(matches!(results[0].kind, DiffKind::MissingStaged));
     }
@@ -235,13 +482,76 @@ mod tests {
             &fs,
             &make_config_toml(&[("a.conf", None), ("b.conf", None), ("c.conf", None)]),
         );
-        let results = compute(&config, None, &fs).unwrap();
+        let results = pipeline_diff(&config, &fs);
         assert_eq!(results.len(), 3);
         assert!(matches!(results[0].kind, DiffKind::Identical));
         assert!(matches!(results[1].kind, DiffKind::Changed(_)));
         assert!(matches!(results[2].kind, DiffKind::MissingGenerated));
     }
 
+    #[test]
+    fn run_writes_colored_diff() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/.generated/a.conf"), "old\n");
+        fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "new\n");
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        let mut out = Vec::new();
+        run(
+            &config,
+            None,
+            &Stage::Generated,
+            &Stage::Staged,
+            &fs,
+            &FakeSecretEngine::new(),
+            &FakeCommandRunner::new(),
+            &SecretMask::default(),
+            &mut out,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.starts_with("--- generated/a.conf\n+++ staged/a.conf\n"),
+            "got: {out}"
+        );
+        assert!(out.contains(&format!("{RED}-old{RESET}")), "got: {out}");
+        assert!(out.contains(&format!("{GREEN}+new{RESET}")), "got: {out}");
+    }
+
+    #[test]
+    fn unavailable_secret_engine_still_diffs() {
+        let fs = setup_fs();
+        fs.add_file(
+            format!("{DOTFILES}/secrets.toml"),
+            "[[secret]]\nname = \"db_pass\"\nengine = \"1password\"\nreference = \"op://db/pass\"\n",
+        );
+        fs.add_file(format!("{DOTFILES}/db.conf"), "password={{ db_pass }}\n");
+        fs.add_file(format!("{DOTFILES}/.generated/db.conf"), "password=old\n");
+        fs.add_file(format!("{DOTFILES}/.staged/db.conf"), "password=new\n");
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\nsecrets = [\"secrets.toml\"]\n\n\
+             [[files]]\nsrc = \"db.conf\"\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let mut engine = FakeSecretEngine::new();
+        engine.set_unavailable("1password");
+        let mask = super::super::generate::secret_mask(&config, None, &fs, &engine);
+        let mut out = Vec::new();
+        run(
+            &config,
+            None,
+            &Stage::Generated,
+            &Stage::Staged,
+            &fs,
+            &engine,
+            &FakeCommandRunner::new(),
+            &mask,
+            &mut out,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("+password=new"), "got: {out}");
+    }
+
     #[test]
     fn direct_files_skipped() {
         let fs = setup_fs();
@@ -251,7 +561,140 @@ mod tests {
             "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\n\n[[files]]\nsrc = \"direct.conf\"\ndirect = true\ntemplate = false\n"
         );
         let config = write_and_load_config(&fs, &toml);
-        let results = compute(&config, None, &fs).unwrap();
+        let results = pipeline_diff(&config, &fs);
         assert!(results.is_empty());
     }
+
+    #[test]
+    fn parses_stages() {
+        assert_eq!("staged".parse::<Stage>(), Ok(Stage::Staged));
+        assert_eq!(
+            "git:HEAD~1".parse::<Stage>(),
+            Ok(Stage::Git("HEAD~1".to_string()))
+        );
+        assert!("git:".parse::<Stage>().is_err());
+        assert_eq!(
+            "remote:me@desk".parse::<Stage>(),
+            Ok(Stage::Remote("me@desk".to_string()))
+        );
+        assert!("deployed".parse::<Stage>().is_err());
+    }
+
+    fn git_response(stdout: &str, stderr: &str, success: bool) -> CommandOutput {
+        CommandOutput {
+            success,
+            stdout: stdout.to_string(),
+            stderr: stderr.to_string(),
+        }
+    }
+
+    #[test]
+    fn git_revision_rendered_against_staged() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "port = 80");
+        fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "port=80\n");
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        let mut runner = FakeCommandRunner::new();
+        runner.add_response(
+            &format!("git -C {DOTFILES} show --end-of-options 'HEAD~1:./a.conf'"),
+            git_response("port={{ port }}\nhost=x\n", "", true),
+        );
+
+        let results = compute(
+            &config,
+            None,
+            &Stage::Git("HEAD~1".to_string()),
+            &Stage::Staged,
+            &fs,
+            &FakeSecretEngine::new(),
+            &runner,
+        )
+        .unwrap();
+        match &results[0].kind {
+            DiffKind::Changed(text) => {
+                assert!(
+                    text.starts_with("--- git:HEAD~1/a.conf\n+++ staged/a.conf\n"),
+                    "got: {text}"
+                );
+                assert!(text.contains("-host=x"), "got: {text}");
+                // The old revision is rendered before it's compared
+                assert!(text.contains("\n port=80\n"), "got: {text}");
+            }
+            other => panic!("expected Changed, got: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn git_revision_without_file_is_missing() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.conf"), "new file");
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        let mut runner = FakeCommandRunner::new();
+        runner.add_response(
+            &format!("git -C {DOTFILES} show --end-of-options HEAD:./a.conf"),
+            git_response(
+                "",
+                "fatal: path 'a.conf' exists on disk, but not in 'HEAD'",
+                false,
+            ),
+        );
+
+        let results = compute(
+            &config,
+            None,
+            &Stage::Git("HEAD".to_string()),
+            &Stage::Source,
+            &fs,
+            &FakeSecretEngine::new(),
+            &runner,
+        )
+        .unwrap();
+        assert!(matches!(
+            &results[0].kind,
+            DiffKind::Missing(Stage::Git(rev)) if rev == "HEAD"
+        ));
+    }
+
+    #[test]
+    fn remote_target_against_staged() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "theme=dark\n");
+        fs.add_file(format!("{DOTFILES}/.staged/b.conf"), "b\n");
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[("a.conf", None), ("b.conf", Some("/etc/b.conf"))]),
+        );
+        let mut runner = FakeCommandRunner::new();
+        runner.add_response(
+            "ssh -o BatchMode=yes -- me@desk 'test -e \"$HOME\"/.config/a.conf || exit 0; echo janus-found && LC_ALL=C exec cat -- \"$HOME\"/.config/a.conf'",
+            git_response("janus-found\ntheme=light\n", "", true),
+        );
+        runner.add_response(
+            "ssh -o BatchMode=yes -- me@desk 'test -e /etc/b.conf || exit 0; echo janus-found && LC_ALL=C exec cat -- /etc/b.conf'",
+            git_response("", "", true),
+        );
+
+        let remote = Stage::Remote("me@desk".to_string());
+        let results = compute(
+            &config,
+            None,
+            &Stage::Staged,
+            &remote,
+            &fs,
+            &FakeSecretEngine::new(),
+            &runner,
+        )
+        .unwrap();
+        match &results[0].kind {
+            DiffKind::Changed(text) => {
+                assert!(
+                    text.starts_with("--- staged/a.conf\n+++ remote:me@desk/a.conf\n"),
+                    "got: {text}"
+                );
+                assert!(text.contains("+theme=light"), "got: {text}");
+            }
+            other => panic!("expected Changed, got: {other:?}"),
+        }
+        assert!(matches!(&results[1].kind, DiffKind::Missing(stage) if *stage == remote));
+    }
 }
@@ -0,0 +1,252 @@
+//! `janus disable` and `janus enable`: park a managed file without removing
+//! its entry.
+//!
+//! Disabling undeploys the file (leaving a copy at the target unless
+//! `--remove-file`, as `janus undeploy` does) and sets `enabled = false` on
+//! its `[[files]]` entry, so every pipeline command skips it. Enabling
+//! removes the flag and applies the file again.
+
+use anyhow::{Context, Result, bail};
+use std::path::Path;
+use tracing::info;
+
+use super::fileset::edit_config;
+use super::{apply, undeploy};
+use crate::config::{Config, FileEntry};
+use crate::plan;
+use crate::platform::{CommandRunner, Fs, Prompter, SecretEngine};
+
+/// The entries `files` names, disabled or not. Entries `attributes_dir`
+/// adds have no `[[files]]` table to flip, so they're refused.
+fn find_entries<'a>(
+    config: &'a Config,
+    config_path: &Path,
+    files: &[String],
+    fs: &impl Fs,
+) -> Result<Vec<&'a FileEntry>> {
+    let entries = config.find_files(files);
+    if entries.is_empty() {
+        let suggestions = config.suggest_files(files);
+        if suggestions.is_empty() {
+            bail!("No matching files found in config");
+        }
+        bail!(
+            "No matching files found in config. Did you mean: {}?",
+            suggestions.join(", ")
+        );
+    }
+
+    let doc: toml_edit::DocumentMut = fs
+        .read_to_string(config_path)
+        .with_context(|| format!("Failed to read config: {}", config_path.display()))?
+        .parse()
+        .context("Failed to parse config for editing")?;
+    let listed: Vec<&str> = doc
+        .get("files")
+        .and_then(|f| f.as_array_of_tables())
+        .map(|array| {
+            array
+                .iter()
+                .filter_map(|t| t.get("src").and_then(|v| v.as_str()))
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Some(entry) = entries.iter().find(|e| !listed.contains(&e.src.as_str())) {
+        bail!(
+            "{} comes from attributes_dir, so it has no [[files]] entry to disable",
+            entry.src
+        );
+    }
+    Ok(entries)
+}
+
+/// Set or clear `enabled = false` on each of `srcs`' `[[files]]` tables.
+fn write_enabled(config_path: &Path, srcs: &[String], enabled: bool, fs: &impl Fs) -> Result<()> {
+    edit_config(config_path, fs, |doc| {
+        let Some(array) = doc
+            .get_mut("files")
+            .and_then(|f| f.as_array_of_tables_mut())
+        else {
+            return Ok(());
+        };
+        for table in array.iter_mut() {
+            let Some(src) = table.get("src").and_then(|v| v.as_str()) else {
+                continue;
+            };
+            if !srcs.iter().any(|s| s == src) {
+                continue;
+            }
+            if enabled {
+                table.remove("enabled");
+            } else {
+                table.insert("enabled", toml_edit::value(false));
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Undeploy `files` and mark them `enabled = false`. Files already
+/// disabled are left alone.
+pub fn disable(
+    config: &Config,
+    config_path: &Path,
+    files: &[String],
+    remove_file: bool,
+    dry_run: bool,
+    fs: &impl Fs,
+    runner: &impl CommandRunner,
+) -> Result<()> {
+    let mut srcs = Vec::new();
+    for entry in find_entries(config, config_path, files, fs)? {
+        if entry.enabled {
+            srcs.push(entry.src.clone());
+        } else {
+            info!("Already disabled: {}", entry.src);
+        }
+    }
+    if srcs.is_empty() {
+        return Ok(());
+    }
+
+    undeploy::run(config, Some(&srcs), remove_file, dry_run, fs, runner)?;
+    if dry_run {
+        info!("[dry-run] Would disable: {}", srcs.join(", "));
+        for src in &srcs {
+            plan::action("disable", src, "", "");
+        }
+        return Ok(());
+    }
+    write_enabled(config_path, &srcs, false, fs)?;
+    info!("Disabled {} file(s)", srcs.len());
+    Ok(())
+}
+
+/// Clear `enabled = false` from `files`, then reload the config and
+/// generate, stage, and deploy them. Files already enabled are left alone.
+#[allow(clippy::too_many_arguments)]
+pub fn enable(
+    config: &Config,
+    config_path: &Path,
+    files: &[String],
+    dry_run: bool,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    prompter: &impl Prompter,
+    runner: &impl CommandRunner,
+) -> Result<()> {
+    let mut srcs = Vec::new();
+    for entry in find_entries(config, config_path, files, fs)? {
+        if entry.enabled {
+            info!("Already enabled: {}", entry.src);
+        } else {
+            srcs.push(entry.src.clone());
+        }
+    }
+    if srcs.is_empty() {
+        return Ok(());
+    }
+
+    if dry_run {
+        info!("[dry-run] Would enable and apply: {}", srcs.join(", "));
+        for src in &srcs {
+            plan::action("enable", src, "", "then apply");
+        }
+        return Ok(());
+    }
+    write_enabled(config_path, &srcs, true, fs)?;
+    info!("Enabled {} file(s)", srcs.len());
+
+    let config = Config::load(config_path, fs)?;
+    apply::run(
+        &config,
+        Some(&srcs),
+        false,
+        false,
+        false,
+        fs,
+        engine,
+        prompter,
+        runner,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{FakeCommandRunner, FakePrompter, FakeSecretEngine};
+    use crate::state::State;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn disable_undeploys_and_enable_redeploys() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file(format!("{DOTFILES}/a.conf"), "content");
+        let toml = make_config_toml(&[("a.conf", None), ("b.conf", None)]);
+        let config = write_and_load_config(&fs, &toml);
+        let runner = FakeCommandRunner::new();
+        let engine = FakeSecretEngine::new();
+        let prompter = FakePrompter::new(vec![]);
+        let files = vec!["a.conf".to_string()];
+        let target = Path::new("/home/test/.config/a.conf");
+        let config_path = Path::new(CONFIG_PATH);
+        apply::run(
+            &config,
+            Some(&files),
+            false,
+            false,
+            false,
+            &fs,
+            &engine,
+            &prompter,
+            &runner,
+        )
+        .unwrap();
+
+        disable(&config, config_path, &files, true, false, &fs, &runner).unwrap();
+        assert!(!fs.exists(target));
+        let config = Config::load(config_path, &fs).unwrap();
+        assert!(!config.files[0].enabled);
+        assert!(config.files[1].enabled);
+        let state = State::load(&config.state_dir(&fs), &fs).unwrap();
+        assert!(!state.is_deployed("a.conf"));
+
+        enable(
+            &config,
+            config_path,
+            &files,
+            false,
+            &fs,
+            &engine,
+            &prompter,
+            &runner,
+        )
+        .unwrap();
+        assert!(fs.is_symlink(target));
+        assert_eq!(fs.read_to_string(config_path).unwrap(), toml);
+    }
+
+    #[test]
+    fn refuses_attribute_entries() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/home/dot_bashrc"), "x");
+        let toml = format!(
+            "attributes_dir = \"home\"\n{}",
+            make_config_toml(&[("a.conf", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let files = vec!["home/dot_bashrc".to_string()];
+        let err = disable(
+            &config,
+            Path::new(CONFIG_PATH),
+            &files,
+            false,
+            false,
+            &fs,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("attributes_dir"), "got: {err}");
+    }
+}
@@ -0,0 +1,519 @@
+//! `janus generate --explain`: show where each template value comes from.
+//!
+//! Renders a single file the same way generate does (without writing it),
+//! then reports every `{{ ... }}` expression with its value and, for each
+//! variable it references, which vars file, vars script, fileset, or secret
+//! supplied it and which earlier definitions it overrode. Secret values are
+//! masked.
+//!
+//! Expressions that only make sense inside a block (e.g. a `{% for %}` loop
+//! variable) can't be evaluated on their own and are reported without a value.
+
+use anyhow::{Context, Result, bail};
+use std::collections::HashMap;
+
+use super::generate::{
+    load_vars, merge_vars, run_vars_script, template_vars, vars_to_tera_context,
+};
+use crate::config::{Config, MergeStrategy};
+use crate::platform::{CommandRunner, Fs, SecretEngine};
+use crate::secrets::{self, SecretResolver};
+use crate::template;
+
+/// A single `{{ ... }}` expression and where its inputs came from.
+pub struct Explanation {
+    /// 1-based line of the expression in the source template.
+    pub line: usize,
+    /// Expression text between the delimiters, trimmed.
+    pub expression: String,
+    /// Rendered value, `None` if it can't be evaluated outside its block.
+    pub value: Option<String>,
+    /// Provenance of each known variable the expression references.
+    pub sources: Vec<VarSource>,
+}
+
+/// Where a variable's effective value was defined.
+pub struct VarSource {
+    /// Variable name.
+    pub name: String,
+    /// Definitions in merge order; the last one wins.
+    pub origins: Vec<String>,
+}
+
+/// Render `file` and explain every expression in it.
+pub fn compute(
+    config: &Config,
+    file: &str,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
+) -> Result<Vec<Explanation>> {
+    let Some(entry) = config.files.iter().find(|e| e.src == file) else {
+        let pattern = [file.to_string()];
+        return config
+            .bail_unmatched(Some(&pattern[..]))
+            .map(|_| Vec::new());
+    };
+    if !entry.template || entry.direct {
+        bail!("{file} is not a template (template = false or direct = true)");
+    }
+
+    let dotfiles_dir = config.dotfiles_dir(fs);
+
+    // Same merge order as generate: var_defaults -> global -> vars_script ->
+    // fileset -> per-file
+    let global_var_layers: Vec<(String, String)> = config
+        .vars
+        .iter()
+        .map(|f| (f.clone(), format!("{f} (global)")))
+        .collect();
+    let mut var_layers: Vec<(String, String)> = Vec::new();
+    let mut secret_layers: Vec<(String, String)> = config
+        .secrets
+        .iter()
+        .map(|f| (f.clone(), format!("{f} (global)")))
+        .collect();
+    for (name, fileset) in config.matching_filesets_named(&entry.src) {
+        var_layers.extend(
+            fileset
+                .vars
+                .iter()
+                .map(|f| (f.clone(), format!("{f} (fileset {name})"))),
+        );
+        secret_layers.extend(
+            fileset
+                .secrets
+                .iter()
+                .map(|f| (f.clone(), format!("{f} (fileset {name})"))),
+        );
+    }
+    var_layers.extend(
+        entry
+            .vars
+            .iter()
+            .map(|f| (f.clone(), format!("{f} (per-file)"))),
+    );
+    secret_layers.extend(
+        entry
+            .secrets
+            .iter()
+            .map(|f| (f.clone(), format!("{f} (per-file)"))),
+    );
+
+    let mut vars = HashMap::new();
+    let mut origins: HashMap<String, Vec<String>> = HashMap::new();
+    merge_layer(
+        &mut vars,
+        &mut origins,
+        config.var_defaults.clone(),
+        config.merge_strategy,
+        "var_defaults (config)",
+    );
+    for (var_file, label) in &global_var_layers {
+        let layer = load_vars(&dotfiles_dir, std::slice::from_ref(var_file), fs)?;
+        merge_layer(&mut vars, &mut origins, layer, config.merge_strategy, label);
+    }
+    if let Some(script) = &config.vars_script {
+        let derived = run_vars_script(&dotfiles_dir, script, &vars, fs)?;
+        merge_layer(
+            &mut vars,
+            &mut origins,
+            derived,
+            config.merge_strategy,
+            &format!("{script} (vars_script)"),
+        );
+    }
+    for (var_file, label) in &var_layers {
+        let layer = load_vars(&dotfiles_dir, std::slice::from_ref(var_file), fs)?;
+        merge_layer(&mut vars, &mut origins, layer, config.merge_strategy, label);
+    }
+
+    let mut secret_entries = Vec::new();
+    for (secret_file, label) in &secret_layers {
+        let layer =
+            secrets::parse_secret_files(&dotfiles_dir, std::slice::from_ref(secret_file), fs)?;
+        for secret in &layer {
+            origins
+                .entry(secret.name.clone())
+                .or_default()
+                .push(format!(
+                    "secret {}:{} from {label}",
+                    secret.engine,
+                    secret.candidates().join(", ")
+                ));
+        }
+        secret_entries.extend(layer);
+    }
+
+    let mut resolver = SecretResolver::new();
+    let resolved_secrets = if !secret_entries.is_empty() {
+        secrets::resolve_secrets(&secret_entries, &vars, &mut resolver, engine)?
+    } else {
+        HashMap::new()
+    };
+    if !resolved_secrets.is_empty() {
+        secrets::check_conflicts(&vars, &resolved_secrets)?;
+    }
+    let secret_names: Vec<String> = resolved_secrets.keys().cloned().collect();
+    vars.extend(resolved_secrets);
+
+    let src_path = config.source_path(&entry.src, fs);
+    let template = fs
+        .read_to_string(&src_path)
+        .with_context(|| format!("Failed to read template: {}", src_path.display()))?;
+    let vars = template_vars(config, entry, &template, vars);
+    let mut context = vars_to_tera_context(&vars)?;
+    template::insert_janus(&mut context, config, entry, fs)?;
+
+    // Render the whole file first so real template errors surface as errors
+    let probes =
+        template::Probes::collect(&template, config.allow_exec, runner)?.with_managed(config, fs);
+    template::render(&entry.src, &template, &context, &probes)?;
+
+    let mut explanations = Vec::new();
+    for (line, expression) in find_expressions(&template) {
+        let names = referenced_names(&expression);
+        let value = if names.iter().any(|n| secret_names.contains(n)) {
+            Some("<secret>".to_string())
+        } else {
+            let expression = format!("{{{{ {expression} }}}}");
+            template::render(&entry.src, &expression, &context, &probes).ok()
+        };
+        let sources = names
+            .into_iter()
+            .filter_map(|name| {
+                origins.get(&name).map(|o| VarSource {
+                    origins: o.clone(),
+                    name,
+                })
+            })
+            .collect();
+        explanations.push(Explanation {
+            line,
+            expression,
+            value,
+            sources,
+        });
+    }
+
+    Ok(explanations)
+}
+
+/// Print the explanation report for `file` to stderr.
+pub fn run(
+    config: &Config,
+    file: &str,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
+) -> Result<()> {
+    let explanations = compute(config, file, fs, engine, runner)?;
+    if explanations.is_empty() {
+        eprintln!("{file}: no {{{{ ... }}}} expressions");
+        return Ok(());
+    }
+
+    for explanation in &explanations {
+        let value = explanation
+            .value
+            .as_deref()
+            .unwrap_or("(depends on block scope, not evaluated)");
+        eprintln!(
+            "{file}:{}: {{{{ {} }}}} = {value}",
+            explanation.line, explanation.expression
+        );
+        for source in &explanation.sources {
+            let (winner, overridden) = source
+                .origins
+                .split_last()
+                .expect("origins are only recorded when non-empty");
+            eprint!("    {} <- {winner}", source.name);
+            if !overridden.is_empty() {
+                eprint!(" (overrides {})", overridden.join(", "));
+            }
+            eprintln!();
+        }
+    }
+
+    Ok(())
+}
+
+/// Merge a vars layer into `vars` with `strategy`, recording `label` as an
+/// origin of each key.
+fn merge_layer(
+    vars: &mut HashMap<String, toml::Value>,
+    origins: &mut HashMap<String, Vec<String>>,
+    layer: HashMap<String, toml::Value>,
+    strategy: MergeStrategy,
+    label: &str,
+) {
+    for key in layer.keys() {
+        origins
+            .entry(key.clone())
+            .or_default()
+            .push(label.to_string());
+    }
+    merge_vars(vars, layer, strategy, label);
+}
+
+/// Find every `{{ ... }}` expression, returning `(line, expression)` pairs.
+///
+/// Whitespace-control markers (`{{-`, `-}}`) are stripped. Comments and raw
+/// blocks are not special-cased.
+fn find_expressions(template: &str) -> Vec<(usize, String)> {
+    let mut found = Vec::new();
+    let mut rest = template;
+    let mut offset = 0;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        let expression = after[..end]
+            .trim()
+            .trim_start_matches('-')
+            .trim_end_matches('-')
+            .trim()
+            .to_string();
+        let line = template[..offset + start].matches('\n').count() + 1;
+        found.push((line, expression));
+        let consumed = start + 2 + end + 2;
+        offset += consumed;
+        rest = &rest[consumed..];
+    }
+    found
+}
+
+/// Top-level identifiers an expression references (not attribute accesses,
+/// filter names after `|`, or string contents), deduplicated in order.
+pub(super) fn referenced_names(expression: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let chars: Vec<char> = expression.chars().collect();
+    let mut i = 0;
+    let mut prev_significant: Option<char> = None;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '"' || c == '\'' || c == '`' {
+            // Skip string literal
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                i += 1;
+            }
+            i += 1;
+            prev_significant = Some(c);
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            let is_attribute = prev_significant == Some('.');
+            let is_filter = prev_significant == Some('|');
+            if !is_attribute && !is_filter && !names.contains(&word) {
+                names.push(word);
+            }
+            prev_significant = Some('a');
+        } else {
+            if !c.is_whitespace() {
+                prev_significant = Some(c);
+            }
+            i += 1;
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{FakeCommandRunner, FakeSecretEngine};
+    use crate::test_helpers::*;
+    use std::path::Path;
+
+    #[test]
+    fn find_expressions_with_lines() {
+        let found = find_expressions("a {{ x }}\nb\nc {{- y | upper -}} {{z}}");
+        assert_eq!(
+            found,
+            vec![
+                (1, "x".to_string()),
+                (3, "y | upper".to_string()),
+                (3, "z".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn referenced_names_skips_attributes_filters_strings() {
+        assert_eq!(
+            referenced_names("host.name | default(value=\"fallback\") ~ port"),
+            vec!["host", "value", "port"]
+        );
+        assert_eq!(referenced_names("x | upper"), vec!["x"]);
+    }
+
+    #[test]
+    fn reports_override_chain() {
+        let fs = setup_fs();
+        fs.add_file(
+            format!("{DOTFILES}/vars.toml"),
+            "color = \"red\"\nhost = \"a\"",
+        );
+        fs.add_file(format!("{DOTFILES}/desktop.toml"), "color = \"blue\"");
+        fs.add_file(
+            format!("{DOTFILES}/hypr/hypr.conf"),
+            "c={{ color }}\nh={{ host }}\n",
+        );
+        let toml = format!(
+            r#"
+dotfiles_dir = "{DOTFILES}"
+vars = ["vars.toml"]
+
+[[files]]
+src = "hypr/hypr.conf"
+
+[filesets.desktop]
+patterns = ["hypr/*"]
+vars = ["desktop.toml"]
+"#
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let explanations = compute(
+            &config,
+            "hypr/hypr.conf",
+            &fs,
+            &FakeSecretEngine::new(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        assert_eq!(explanations.len(), 2);
+
+        assert_eq!(explanations[0].line, 1);
+        assert_eq!(explanations[0].value.as_deref(), Some("blue"));
+        assert_eq!(
+            explanations[0].sources[0].origins,
+            vec!["vars.toml (global)", "desktop.toml (fileset desktop)"]
+        );
+
+        assert_eq!(explanations[1].line, 2);
+        assert_eq!(explanations[1].value.as_deref(), Some("a"));
+        assert_eq!(
+            explanations[1].sources[0].origins,
+            vec!["vars.toml (global)"]
+        );
+        // Nothing written
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/.generated/hypr/hypr.conf"))));
+    }
+
+    #[test]
+    fn renders_with_var_defaults_and_undefined_vars() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file(
+            format!("{DOTFILES}/app.conf"),
+            "gap={{ gap }}\nfont={{ font }}\n",
+        );
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\nundefined_vars = \"empty\"\n\n[var_defaults]\ngap = 4\n\n[[files]]\nsrc = \"app.conf\"\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let explanations = compute(
+            &config,
+            "app.conf",
+            &fs,
+            &FakeSecretEngine::new(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        assert_eq!(explanations[0].value.as_deref(), Some("4"));
+        assert_eq!(
+            explanations[0].sources[0].origins,
+            vec!["var_defaults (config)"]
+        );
+        assert_eq!(explanations[1].value.as_deref(), Some(""));
+        assert!(explanations[1].sources.is_empty());
+    }
+
+    #[test]
+    fn secrets_masked() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file(
+            format!("{DOTFILES}/secrets.toml"),
+            "[[secret]]\nname = \"pw\"\nengine = \"1password\"\nreference = \"op://v/i/f\"\n",
+        );
+        fs.add_file(format!("{DOTFILES}/app.conf"), "pw={{ pw }}");
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\nsecrets = [\"secrets.toml\"]\n\n[[files]]\nsrc = \"app.conf\"\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let mut engine = FakeSecretEngine::new();
+        engine.add_secret("1password", "op://v/i/f", "hunter2");
+        let explanations =
+            compute(&config, "app.conf", &fs, &engine, &FakeCommandRunner::new()).unwrap();
+        assert_eq!(explanations[0].value.as_deref(), Some("<secret>"));
+        assert_eq!(
+            explanations[0].sources[0].origins,
+            vec!["secret 1password:op://v/i/f from secrets.toml (global)"]
+        );
+    }
+
+    #[test]
+    fn loop_variable_not_evaluated() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "items = [1, 2]");
+        fs.add_file(
+            format!("{DOTFILES}/a.conf"),
+            "{% for i in items %}{{ i }}{% endfor %}",
+        );
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        let explanations = compute(
+            &config,
+            "a.conf",
+            &fs,
+            &FakeSecretEngine::new(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        assert_eq!(explanations.len(), 1);
+        assert!(explanations[0].value.is_none());
+        assert!(explanations[0].sources.is_empty());
+    }
+
+    #[test]
+    fn non_template_errors() {
+        let fs = setup_fs();
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\n\n[[files]]\nsrc = \"a.conf\"\ntemplate = false\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        assert!(
+            compute(
+                &config,
+                "a.conf",
+                &fs,
+                &FakeSecretEngine::new(),
+                &FakeCommandRunner::new()
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn unknown_file_errors() {
+        let fs = setup_fs();
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        let result = compute(
+            &config,
+            "a.cnf",
+            &fs,
+            &FakeSecretEngine::new(),
+            &FakeCommandRunner::new(),
+        );
+        let Err(err) = result else {
+            panic!("expected an error");
+        };
+        let msg = format!("{err:#}");
+        assert!(msg.contains("Did you mean: a.conf"), "got: {msg}");
+    }
+}
@@ -0,0 +1,362 @@
+//! `janus export`: render managed files into a directory or tarball for
+//! sharing.
+//!
+//! Each selected file is rendered as generate would (templates, `inject`,
+//! `postprocess`) and written under its `src` path, so the export looks like
+//! the dotfiles repo with every template filled in. Direct files are copied
+//! as they are. Nothing in the pipeline directories or the state changes.
+//!
+//! With `--redact-secrets` no secret engine is called: every secret renders
+//! as `<secret NAME>`, and the home directory and hostname in the output are
+//! replaced with `~` and `<hostname>`, so the result can be posted publicly.
+//!
+//! An `--out` ending in `.tar`, `.tar.gz`, or `.tgz` is packed with `tar`:
+//! the files are rendered into `.janus_export` in the cache directory first
+//! and removed once the tarball is written.
+
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+use super::generate::render_source;
+use crate::config::{Config, FileEntry};
+use crate::plan;
+use crate::platform::{CommandRunner, Fs, SecretEngine, WalkOptions};
+use crate::secrets;
+use crate::shell;
+
+/// Directory within the cache directory a tarball's files are rendered in.
+const TARBALL_STAGING: &str = ".janus_export";
+
+/// A [`SecretEngine`] that never resolves anything: each reference renders
+/// as a placeholder naming the secret it belongs to.
+struct Redacted {
+    /// Secret name for each reference the config's secrets files list.
+    names: HashMap<String, String>,
+}
+
+impl Redacted {
+    /// Map every reference in the global, fileset, and per-file secrets
+    /// files to its secret's name.
+    fn new(config: &Config, fs: &impl Fs) -> Result<Self> {
+        let dotfiles_dir = config.dotfiles_dir(fs);
+        let mut files = config.secrets.clone();
+        for fileset in config.filesets.values() {
+            files.extend(fileset.secrets.iter().cloned());
+        }
+        for entry in &config.files {
+            files.extend(entry.secrets.iter().cloned());
+        }
+        let mut names = HashMap::new();
+        for secret in secrets::parse_secret_files(&dotfiles_dir, &files, fs)? {
+            for reference in secret.candidates() {
+                names.insert(reference.to_string(), secret.name.clone());
+            }
+        }
+        Ok(Self { names })
+    }
+}
+
+impl SecretEngine for Redacted {
+    fn resolve(&self, _engine: &str, reference: &str) -> Result<String> {
+        // References built from vars don't match any listed one
+        Ok(match self.names.get(reference) {
+            Some(name) => format!("<secret {name}>"),
+            None => "<secret>".to_string(),
+        })
+    }
+
+    fn check(&self, _engine: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Whether `out` names a tarball rather than a directory, and whether it's
+/// gzipped.
+fn tarball_kind(out: &Path) -> Option<bool> {
+    let name = out.file_name()?.to_string_lossy();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(true)
+    } else if name.ends_with(".tar") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Render the selected files (`None` for all) into `out`, a directory or
+/// tarball. With `redact_secrets`, secrets and machine-specific paths are
+/// replaced by placeholders and `engine` is never called.
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    config: &Config,
+    files: Option<&[String]>,
+    out: &Path,
+    redact_secrets: bool,
+    dry_run: bool,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
+) -> Result<()> {
+    let entries = config.filter_files(files);
+    if entries.is_empty() {
+        config.bail_unmatched(files)?;
+        info!("No files to export");
+        return Ok(());
+    }
+    let tarball = tarball_kind(out);
+    if tarball.is_none() && (fs.is_file(out) || fs.is_symlink(out)) {
+        bail!("{} exists and isn't a directory", out.display());
+    }
+
+    if dry_run {
+        for entry in &entries {
+            info!("[dry-run] Would export {}", entry.src);
+            plan::action(
+                "export",
+                &entry.src,
+                &out.to_string_lossy(),
+                if redact_secrets { "redacted" } else { "" },
+            );
+        }
+        info!(
+            "[dry-run] Would write {} file(s) to {}",
+            entries.len(),
+            out.display()
+        );
+        return Ok(());
+    }
+
+    let dir = match tarball {
+        Some(_) => config.cache_dir(fs).join(TARBALL_STAGING),
+        None => out.to_path_buf(),
+    };
+    if tarball.is_some() {
+        remove_tree(&dir, fs)?;
+    }
+    let result = if redact_secrets {
+        let redactions = Redactions::new(fs)?;
+        let engine = Redacted::new(config, fs)?;
+        export_each(
+            config,
+            &entries,
+            &dir,
+            Some(&redactions),
+            fs,
+            &engine,
+            runner,
+        )
+    } else {
+        export_each(config, &entries, &dir, None, fs, engine, runner)
+    };
+    let result = result.and_then(|()| match tarball {
+        Some(gzip) => pack(&dir, out, gzip, runner),
+        None => Ok(()),
+    });
+    if tarball.is_some()
+        && let Err(e) = remove_tree(&dir, fs)
+    {
+        debug!("Failed to clean up {}: {e:#}", dir.display());
+    }
+    result?;
+    info!("Exported {} file(s) to {}", entries.len(), out.display());
+    Ok(())
+}
+
+/// Render each entry into `dir` under its `src`.
+fn export_each(
+    config: &Config,
+    entries: &[&FileEntry],
+    dir: &Path,
+    redactions: Option<&Redactions>,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
+) -> Result<()> {
+    for entry in entries {
+        let source_path = config.source_path(&entry.src, fs);
+        let content = fs
+            .read_to_string(&source_path)
+            .with_context(|| format!("Failed to read source: {}", source_path.display()))?;
+        let rendered = if entry.direct {
+            content
+        } else {
+            render_source(config, entry, content, fs, engine, runner)
+                .with_context(|| format!("Failed to render {}", entry.src))?
+        };
+        let rendered = match redactions {
+            Some(redactions) => redactions.apply(&rendered),
+            None => rendered,
+        };
+        let path = dir.join(&entry.src);
+        if let Some(parent) = path.parent() {
+            fs.create_dir_all(parent)?;
+        }
+        fs.write(&path, rendered.as_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        debug!("Exported {} to {}", entry.src, path.display());
+    }
+    Ok(())
+}
+
+/// Machine-specific values replaced in redacted output.
+struct Redactions {
+    /// The home directory, replaced by `~`.
+    home: Option<String>,
+    /// The hostname as a whole word, replaced by `<hostname>`.
+    hostname: Option<Regex>,
+}
+
+impl Redactions {
+    fn new(fs: &impl Fs) -> Result<Self> {
+        let hostname = match fs.hostname().filter(|h| !h.is_empty()) {
+            Some(host) => Some(Regex::new(&format!(r"\b{}\b", regex::escape(&host)))?),
+            None => None,
+        };
+        Ok(Self {
+            home: fs.home_dir().map(|h| h.to_string_lossy().into_owned()),
+            hostname,
+        })
+    }
+
+    fn apply(&self, text: &str) -> String {
+        let mut text = match &self.home {
+            Some(home) => text.replace(home.as_str(), "~"),
+            None => text.to_string(),
+        };
+        if let Some(hostname) = &self.hostname {
+            text = hostname.replace_all(&text, "<hostname>").into_owned();
+        }
+        text
+    }
+}
+
+/// Pack the contents of `dir` into the tarball `out`.
+fn pack(dir: &Path, out: &Path, gzip: bool, runner: &impl CommandRunner) -> Result<()> {
+    let flags = if gzip { "-czf" } else { "-cf" };
+    let command = format!(
+        "tar {flags} {} -C {} .",
+        shell::quote(&out.to_string_lossy()),
+        shell::quote(&dir.to_string_lossy())
+    );
+    let output = runner.run(&command, None)?;
+    if !output.success {
+        bail!(
+            "Failed to write {}: {}",
+            out.display(),
+            output.stderr.trim()
+        );
+    }
+    Ok(())
+}
+
+/// Remove `dir` and everything in it, if it exists.
+fn remove_tree(dir: &Path, fs: &impl Fs) -> Result<()> {
+    if !fs.exists(dir) {
+        return Ok(());
+    }
+    let opts = WalkOptions {
+        max_depth: None,
+        min_depth: 0,
+        follow_links: false,
+        contents_first: true,
+    };
+    let paths: Vec<(PathBuf, bool)> = fs
+        .walk_dir(dir, &opts)?
+        .into_iter()
+        .map(|e| (e.path, e.is_dir && !e.is_symlink))
+        .collect();
+    for (path, is_dir) in paths {
+        if is_dir {
+            fs.remove_dir(&path)
+        } else {
+            fs.remove_file(&path)
+        }
+        .with_context(|| format!("Failed to remove {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{FakeCommandRunner, FakeSecretEngine};
+    use crate::test_helpers::*;
+
+    fn setup_secret_template(fs: &crate::platform::FakeFs) -> Config {
+        fs.add_file(
+            format!("{DOTFILES}/secrets.toml"),
+            "[[secret]]\nname = \"token\"\nengine = \"1password\"\nreference = \"op://Private/gh/token\"\n",
+        );
+        fs.add_file(
+            format!("{DOTFILES}/gh/hosts.yml"),
+            "oauth_token: {{ token }}\ncache: /home/test/.cache/gh\nuser: desk\n",
+        );
+        let toml = make_config_toml(&[("gh/hosts.yml", None)]).replacen(
+            "\n",
+            "\nsecrets = [\"secrets.toml\"]\n",
+            1,
+        );
+        write_and_load_config(fs, &toml)
+    }
+
+    #[test]
+    fn redacts_secrets_without_resolving_them() {
+        let fs = setup_fs();
+        fs.set_hostname("desk");
+        let config = setup_secret_template(&fs);
+        let engine = FakeSecretEngine::new();
+        run(
+            &config,
+            None,
+            Path::new("/tmp/share"),
+            true,
+            false,
+            &fs,
+            &engine,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+
+        let exported = fs
+            .read_to_string(Path::new("/tmp/share/gh/hosts.yml"))
+            .unwrap();
+        assert_eq!(
+            exported,
+            "oauth_token: <secret token>\ncache: ~/.cache/gh\nuser: <hostname>\n"
+        );
+        assert!(!fs.exists(&Path::new(DOTFILES).join(".generated/gh/hosts.yml")));
+    }
+
+    #[test]
+    fn tarball_is_packed_from_the_cache() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.conf"), "plain");
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        let runner = FakeCommandRunner::new();
+        run(
+            &config,
+            None,
+            Path::new("/tmp/share.tar.gz"),
+            true,
+            false,
+            &fs,
+            &FakeSecretEngine::new(),
+            &runner,
+        )
+        .unwrap();
+
+        let commands = runner.commands();
+        let staging = config.cache_dir(&fs).join(TARBALL_STAGING);
+        assert_eq!(
+            commands,
+            vec![format!(
+                "tar -czf /tmp/share.tar.gz -C {} .",
+                staging.display()
+            )]
+        );
+        assert!(!fs.exists(&staging.join("a.conf")));
+    }
+}
@@ -0,0 +1,430 @@
+//! Manage `[filesets.<name>]` entries without hand-editing the config.
+//!
+//! `create`, `add`, and `rm` edit the config file in place via `toml_edit`
+//! (like import's `append_config_entry`), so comments and formatting are
+//! preserved. `list` is read-only and can show each fileset's resolved files.
+
+use anyhow::{Context, Result, anyhow, bail};
+use std::io::{self, Write};
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::plan;
+use crate::platform::Fs;
+
+/// A fileset with its patterns and the configured files they select.
+pub struct FilesetMembership {
+    /// Fileset name.
+    pub name: String,
+    /// Glob patterns, as written in the config.
+    pub patterns: Vec<String>,
+    /// `src` of every configured file matched by at least one pattern.
+    pub files: Vec<String>,
+}
+
+/// Create a new fileset with the given patterns.
+///
+/// Errors if a fileset with that name already exists.
+pub fn create(
+    config: &Config,
+    config_path: &Path,
+    name: &str,
+    patterns: &[String],
+    dry_run: bool,
+    fs: &impl Fs,
+) -> Result<()> {
+    if config.filesets.contains_key(name) {
+        bail!("Fileset already exists: {name} (use `janus fileset add` to extend it)");
+    }
+    warn_unmatched(config, patterns);
+
+    if dry_run {
+        info!(
+            "[dry-run] Would create fileset {name} with patterns: {}",
+            patterns.join(", ")
+        );
+        plan::action(
+            "create-fileset",
+            &patterns.join(", "),
+            name,
+            "fileset create",
+        );
+        return Ok(());
+    }
+
+    edit_config(config_path, fs, |doc| {
+        let filesets = doc.entry("filesets").or_insert_with(|| {
+            // Implicit, so the file gets `[filesets.<name>]` headers rather
+            // than an empty `[filesets]` table.
+            let mut table = toml_edit::Table::new();
+            table.set_implicit(true);
+            toml_edit::Item::Table(table)
+        });
+        let Some(filesets) = filesets.as_table_like_mut() else {
+            bail!("Config 'filesets' field is malformed");
+        };
+
+        let mut array = toml_edit::Array::new();
+        for pattern in patterns {
+            array.push(pattern.as_str());
+        }
+        let mut table = toml_edit::Table::new();
+        table.insert("patterns", toml_edit::value(array));
+        filesets.insert(name, toml_edit::Item::Table(table));
+        Ok(())
+    })?;
+
+    info!("Created fileset {name}");
+    Ok(())
+}
+
+/// Add a pattern to an existing fileset.
+///
+/// A pattern the fileset already has is reported and left alone.
+pub fn add(
+    config: &Config,
+    config_path: &Path,
+    name: &str,
+    pattern: &str,
+    dry_run: bool,
+    fs: &impl Fs,
+) -> Result<()> {
+    let Some(fileset) = config.filesets.get(name) else {
+        return Err(unknown(config, name));
+    };
+    if fileset.patterns.iter().any(|p| p == pattern) {
+        info!("Fileset {name} already has pattern {pattern}");
+        return Ok(());
+    }
+    warn_unmatched(config, &[pattern.to_string()]);
+
+    if dry_run {
+        info!("[dry-run] Would add pattern {pattern} to fileset {name}");
+        plan::action("add-pattern", pattern, name, "fileset add");
+        return Ok(());
+    }
+
+    edit_config(config_path, fs, |doc| {
+        let Some(patterns) = doc
+            .get_mut("filesets")
+            .and_then(|f| f.get_mut(name))
+            .and_then(|f| f.get_mut("patterns"))
+            .and_then(|p| p.as_array_mut())
+        else {
+            bail!("Fileset {name} has no editable 'patterns' array");
+        };
+        patterns.push(pattern);
+        Ok(())
+    })?;
+
+    info!("Added pattern {pattern} to fileset {name}");
+    Ok(())
+}
+
+/// Remove a fileset from the config.
+///
+/// Only the fileset definition is removed; the files it matched stay managed.
+pub fn rm(
+    config: &Config,
+    config_path: &Path,
+    name: &str,
+    dry_run: bool,
+    fs: &impl Fs,
+) -> Result<()> {
+    if !config.filesets.contains_key(name) {
+        return Err(unknown(config, name));
+    }
+    if let Some(default_targets) = &config.default_targets
+        && default_targets.split(',').any(|t| t.trim() == name)
+    {
+        warn!("default_targets still references fileset {name}; update it to avoid errors");
+    }
+
+    if dry_run {
+        info!("[dry-run] Would remove fileset {name}");
+        plan::action("remove-fileset", "", name, "fileset rm");
+        return Ok(());
+    }
+
+    edit_config(config_path, fs, |doc| {
+        let removed = doc
+            .get_mut("filesets")
+            .and_then(|f| f.as_table_like_mut())
+            .and_then(|t| t.remove(name));
+        if removed.is_none() {
+            bail!("Fileset {name} not found in config file");
+        }
+        Ok(())
+    })?;
+
+    info!("Removed fileset {name}");
+    Ok(())
+}
+
+/// Resolve every fileset's membership, sorted by name.
+pub fn compute(config: &Config) -> Vec<FilesetMembership> {
+    let mut names: Vec<&String> = config.filesets.keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .map(|name| {
+            let patterns = config.filesets[name].patterns.clone();
+            let files = config
+                .filter_files(Some(patterns.as_slice()))
+                .into_iter()
+                .map(|e| e.src.clone())
+                .collect();
+            FilesetMembership {
+                name: name.clone(),
+                patterns,
+                files,
+            }
+        })
+        .collect()
+}
+
+/// Write filesets and their patterns to `out`, and with `show_files`, the
+/// files each one resolves to.
+pub fn list(config: &Config, show_files: bool, out: &mut impl Write) -> io::Result<()> {
+    let memberships = compute(config);
+    if memberships.is_empty() {
+        info!("No filesets configured");
+        return Ok(());
+    }
+
+    for fileset in &memberships {
+        writeln!(out, "{} ({} file(s))", fileset.name, fileset.files.len())?;
+        writeln!(out, "  patterns: {}", fileset.patterns.join(", "))?;
+        if show_files {
+            for file in &fileset.files {
+                writeln!(out, "    {file}")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read the config as a `toml_edit` document, apply `edit`, and write it back.
+pub(crate) fn edit_config(
+    config_path: &Path,
+    fs: &impl Fs,
+    edit: impl FnOnce(&mut toml_edit::DocumentMut) -> Result<()>,
+) -> Result<()> {
+    let contents = fs
+        .read_to_string(config_path)
+        .with_context(|| format!("Failed to read config: {}", config_path.display()))?;
+    let mut doc = contents
+        .parse::<toml_edit::DocumentMut>()
+        .with_context(|| "Failed to parse config for editing")?;
+
+    edit(&mut doc)?;
+
+    fs.write(config_path, doc.to_string().as_bytes())
+        .with_context(|| format!("Failed to write config: {}", config_path.display()))?;
+    Ok(())
+}
+
+/// The error for an unknown fileset name, with a suggestion if one is close.
+fn unknown(config: &Config, name: &str) -> anyhow::Error {
+    match config.suggest_fileset(name) {
+        Some(suggestion) => anyhow!("Unknown fileset: {name}. Did you mean: {suggestion}?"),
+        None => anyhow!("Unknown fileset: {name}"),
+    }
+}
+
+/// Warn about patterns that don't match any configured file (likely typos).
+fn warn_unmatched(config: &Config, patterns: &[String]) {
+    for pattern in patterns {
+        if config
+            .filter_files(Some(std::slice::from_ref(pattern)))
+            .is_empty()
+        {
+            warn!("Pattern {pattern} doesn't match any configured file");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    fn fileset_config(fs: &crate::platform::FakeFs) -> Config {
+        let toml = format!(
+            r#"dotfiles_dir = "{DOTFILES}"
+
+# shell things
+[[files]]
+src = "bashrc"
+
+[[files]]
+src = "hypr/hypr.conf"
+
+[[files]]
+src = "hypr/hyprpaper.conf"
+
+[filesets.desktop]
+patterns = ["hypr/*"]
+"#
+        );
+        write_and_load_config(fs, &toml)
+    }
+
+    fn reload(fs: &crate::platform::FakeFs) -> Config {
+        Config::load(Path::new(CONFIG_PATH), fs).unwrap()
+    }
+
+    #[test]
+    fn create_adds_fileset() {
+        let fs = setup_fs();
+        let config = fileset_config(&fs);
+        create(
+            &config,
+            Path::new(CONFIG_PATH),
+            "shell",
+            &["bashrc".to_string(), "zshrc".to_string()],
+            false,
+            &fs,
+        )
+        .unwrap();
+
+        let content = fs.read_to_string(Path::new(CONFIG_PATH)).unwrap();
+        assert!(content.contains("[filesets.shell]"), "got: {content}");
+        assert!(content.contains("# shell things"), "comments preserved");
+        let config = reload(&fs);
+        assert_eq!(config.filesets["shell"].patterns, vec!["bashrc", "zshrc"]);
+        assert_eq!(config.filesets["desktop"].patterns, vec!["hypr/*"]);
+    }
+
+    #[test]
+    fn create_without_existing_filesets() {
+        let fs = setup_fs();
+        let config = write_and_load_config(&fs, &make_config_toml(&[("bashrc", None)]));
+        create(
+            &config,
+            Path::new(CONFIG_PATH),
+            "shell",
+            &["bashrc".to_string()],
+            false,
+            &fs,
+        )
+        .unwrap();
+        let content = fs.read_to_string(Path::new(CONFIG_PATH)).unwrap();
+        assert!(!content.contains("[filesets]\n"), "got: {content}");
+        assert_eq!(reload(&fs).filesets["shell"].patterns, vec!["bashrc"]);
+    }
+
+    #[test]
+    fn create_existing_errors() {
+        let fs = setup_fs();
+        let config = fileset_config(&fs);
+        let result = create(
+            &config,
+            Path::new(CONFIG_PATH),
+            "desktop",
+            &["x".to_string()],
+            false,
+            &fs,
+        );
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(msg.contains("already exists"), "got: {msg}");
+    }
+
+    #[test]
+    fn add_appends_pattern() {
+        let fs = setup_fs();
+        let config = fileset_config(&fs);
+        add(
+            &config,
+            Path::new(CONFIG_PATH),
+            "desktop",
+            "waybar/*",
+            false,
+            &fs,
+        )
+        .unwrap();
+        assert_eq!(
+            reload(&fs).filesets["desktop"].patterns,
+            vec!["hypr/*", "waybar/*"]
+        );
+    }
+
+    #[test]
+    fn add_duplicate_pattern_noop() {
+        let fs = setup_fs();
+        let config = fileset_config(&fs);
+        let before = fs.read_to_string(Path::new(CONFIG_PATH)).unwrap();
+        add(
+            &config,
+            Path::new(CONFIG_PATH),
+            "desktop",
+            "hypr/*",
+            false,
+            &fs,
+        )
+        .unwrap();
+        let after = fs.read_to_string(Path::new(CONFIG_PATH)).unwrap();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn add_unknown_fileset_suggests() {
+        let fs = setup_fs();
+        let config = fileset_config(&fs);
+        let result = add(&config, Path::new(CONFIG_PATH), "desktp", "x", false, &fs);
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(msg.contains("Did you mean: desktop"), "got: {msg}");
+    }
+
+    #[test]
+    fn rm_removes_fileset() {
+        let fs = setup_fs();
+        let config = fileset_config(&fs);
+        rm(&config, Path::new(CONFIG_PATH), "desktop", false, &fs).unwrap();
+        let config = reload(&fs);
+        assert!(config.filesets.is_empty());
+        // Files stay managed
+        assert_eq!(config.files.len(), 3);
+    }
+
+    #[test]
+    fn rm_unknown_errors() {
+        let fs = setup_fs();
+        let config = fileset_config(&fs);
+        assert!(rm(&config, Path::new(CONFIG_PATH), "nope", false, &fs).is_err());
+    }
+
+    #[test]
+    fn dry_run_leaves_config() {
+        let fs = setup_fs();
+        let config = fileset_config(&fs);
+        let before = fs.read_to_string(Path::new(CONFIG_PATH)).unwrap();
+        create(
+            &config,
+            Path::new(CONFIG_PATH),
+            "shell",
+            &["bashrc".to_string()],
+            true,
+            &fs,
+        )
+        .unwrap();
+        add(&config, Path::new(CONFIG_PATH), "desktop", "x", true, &fs).unwrap();
+        rm(&config, Path::new(CONFIG_PATH), "desktop", true, &fs).unwrap();
+        let after = fs.read_to_string(Path::new(CONFIG_PATH)).unwrap();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn compute_resolves_membership() {
+        let fs = setup_fs();
+        let config = fileset_config(&fs);
+        let memberships = compute(&config);
+        assert_eq!(memberships.len(), 1);
+        assert_eq!(memberships[0].name, "desktop");
+        assert_eq!(
+            memberships[0].files,
+            vec!["hypr/hypr.conf", "hypr/hyprpaper.conf"]
+        );
+    }
+}
@@ -1,50 +1,206 @@
 //! Render templates and copy source files into `.generated/`.
 //!
 //! For files with `template = true`, renders the source through Tera with
-//! merged global + per-file variables and secrets. For non-template files,
-//! copies as-is. Preserves Unix file permissions on all output files.
+//! merged global (plus `vars_script` output) + per-file variables and
+//! secrets, plus the `janus` object describing the managed files (see
+//! [`template::JanusObject`]). For non-template files, copies as-is, except that
+//! `inject` placeholders are replaced with secret values. Output is
+//! then piped through the entry's `postprocess` commands, if any. Preserves
+//! Unix file permissions, ACLs, and `user.*` extended attributes on all
+//! output files. Direct files are skipped, except that a
+//! `regions_template` is rendered into their managed regions in place.
 //!
 //! Uses error-collection strategy: processes all files and reports failures
 //! at the end rather than bailing on the first error.
+//!
+//! Long-lived callers that regenerate repeatedly can hold a [`GenerateCache`]
+//! across [`run_cached`] calls so unchanged templates and vars files aren't
+//! re-parsed each time. Secrets are still resolved fresh on every run.
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use std::collections::HashMap;
-use std::path::Path;
-use tera::Tera;
+use std::path::{Path, PathBuf};
 use tracing::{debug, info, trace, warn};
 
-use crate::config::{Config, FileEntry};
-use crate::platform::{Fs, SecretEngine};
-use crate::secrets::{self, SecretEntry, SecretResolver};
+use super::Outcome;
+use crate::config::{Config, Encoding, FileEntry, MergeStrategy, Newline, UndefinedVars};
+use crate::plan;
+use crate::platform::{CommandRunner, FileStamp, Fs, SecretEngine};
+use crate::progress;
+use crate::regions;
+use crate::secrets::{self, SecretEntry, SecretMask, SecretResolver};
+use crate::state::State;
+use crate::template;
+use crate::timings;
+use crate::vars_script;
+
+/// Compiled templates and parsed vars files kept between generate runs.
+#[derive(Default)]
+pub struct GenerateCache {
+    templates: template::TemplateCache,
+    vars: VarsCache,
+}
+
+/// Parsed vars files by path, reused while the file's mtime and size are
+/// unchanged.
+#[derive(Default)]
+struct VarsCache {
+    tables: HashMap<PathBuf, (FileStamp, HashMap<String, toml::Value>)>,
+}
+
+impl VarsCache {
+    /// Load and merge `var_files` like [`load_vars`] with `strategy`,
+    /// parsing only the files that changed since this cache last saw them.
+    fn load(
+        &mut self,
+        dotfiles_dir: &Path,
+        var_files: &[String],
+        strategy: MergeStrategy,
+        fs: &impl Fs,
+    ) -> Result<HashMap<String, toml::Value>> {
+        let mut vars = HashMap::new();
+        for var_file in var_files {
+            let path = dotfiles_dir.join(var_file);
+            if !fs.exists(&path) {
+                debug!("Vars file not found, skipping: {}", path.display());
+                continue;
+            }
+            let stamp = fs.stamp(&path).ok();
+            if let Some((cached, table)) = self.tables.get(&path)
+                && stamp == Some(*cached)
+            {
+                trace!("Reusing parsed vars from {}", path.display());
+                merge_vars(&mut vars, table.clone(), strategy, var_file);
+                continue;
+            }
+            debug!("Loading vars from {}", path.display());
+            let contents = fs
+                .read_to_string(&path)
+                .with_context(|| format!("Failed to read vars file: {}", path.display()))?;
+            let table: HashMap<String, toml::Value> = toml::from_str(&contents)
+                .with_context(|| format!("Failed to parse vars file: {}", path.display()))?;
+            if let Some(stamp) = stamp {
+                self.tables.insert(path, (stamp, table.clone()));
+            }
+            merge_vars(&mut vars, table, strategy, var_file);
+        }
+        Ok(vars)
+    }
+}
 
 /// Load template variables from one or more TOML files in the dotfiles directory.
 ///
 /// Later files override earlier ones. Missing files are silently skipped.
-fn load_vars(
+pub(crate) fn load_vars(
     dotfiles_dir: &Path,
     var_files: &[String],
     fs: &impl Fs,
 ) -> Result<HashMap<String, toml::Value>> {
-    let mut vars = HashMap::new();
-    for var_file in var_files {
-        let path = dotfiles_dir.join(var_file);
-        if !fs.exists(&path) {
-            debug!("Vars file not found, skipping: {}", path.display());
-            continue;
+    VarsCache::default().load(dotfiles_dir, var_files, MergeStrategy::Shallow, fs)
+}
+
+/// Merge vars `layer` (named `label` in warnings) over `vars`.
+///
+/// With [`MergeStrategy::Deep`], tables on both sides are merged key by key.
+/// A table meeting a non-table is a conflict: the later value still wins,
+/// but the dotted key is reported.
+pub(crate) fn merge_vars(
+    vars: &mut HashMap<String, toml::Value>,
+    layer: HashMap<String, toml::Value>,
+    strategy: MergeStrategy,
+    label: &str,
+) {
+    if strategy == MergeStrategy::Shallow {
+        vars.extend(layer);
+        return;
+    }
+    for (key, value) in layer {
+        match vars.get_mut(&key) {
+            Some(existing) => merge_value(existing, value, &key, label),
+            None => {
+                vars.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Deep-merge `value` into `existing` at dotted key `path`.
+fn merge_value(existing: &mut toml::Value, value: toml::Value, path: &str, label: &str) {
+    match (existing, value) {
+        (toml::Value::Table(existing), toml::Value::Table(table)) => {
+            for (key, value) in table {
+                let path = format!("{path}.{key}");
+                match existing.get_mut(&key) {
+                    Some(slot) => merge_value(slot, value, &path, label),
+                    None => {
+                        existing.insert(key, value);
+                    }
+                }
+            }
+        }
+        (existing, value) => {
+            if existing.is_table() != value.is_table() {
+                warn!(
+                    "Vars conflict: {label} sets {path} to a {}, replacing a {}",
+                    value.type_str(),
+                    existing.type_str()
+                );
+            }
+            *existing = value;
         }
-        debug!("Loading vars from {}", path.display());
-        let contents = fs
-            .read_to_string(&path)
-            .with_context(|| format!("Failed to read vars file: {}", path.display()))?;
-        let table: HashMap<String, toml::Value> = toml::from_str(&contents)
-            .with_context(|| format!("Failed to parse vars file: {}", path.display()))?;
-        vars.extend(table);
+    }
+}
+
+/// Load the global `vars` files over `var_defaults`, then merge the
+/// `vars_script` output (if configured) on top.
+pub(crate) fn load_global_vars(
+    config: &Config,
+    dotfiles_dir: &Path,
+    fs: &impl Fs,
+) -> Result<HashMap<String, toml::Value>> {
+    global_vars(config, dotfiles_dir, &mut VarsCache::default(), fs)
+}
+
+/// [`load_global_vars`] through a [`VarsCache`]. The vars script always
+/// reruns, since its output can depend on more than the vars files.
+fn global_vars(
+    config: &Config,
+    dotfiles_dir: &Path,
+    cache: &mut VarsCache,
+    fs: &impl Fs,
+) -> Result<HashMap<String, toml::Value>> {
+    let mut vars = config.var_defaults.clone();
+    let files = cache.load(dotfiles_dir, &config.vars, config.merge_strategy, fs)?;
+    merge_vars(
+        &mut vars,
+        files,
+        config.merge_strategy,
+        &config.vars.join(", "),
+    );
+    if let Some(script) = &config.vars_script {
+        let derived = run_vars_script(dotfiles_dir, script, &vars, fs)?;
+        merge_vars(&mut vars, derived, config.merge_strategy, script);
     }
     Ok(vars)
 }
 
+/// Read and evaluate a vars script with `vars` in scope.
+pub(crate) fn run_vars_script(
+    dotfiles_dir: &Path,
+    script: &str,
+    vars: &HashMap<String, toml::Value>,
+    fs: &impl Fs,
+) -> Result<HashMap<String, toml::Value>> {
+    let path = dotfiles_dir.join(script);
+    let source = fs
+        .read_to_string(&path)
+        .with_context(|| format!("Failed to read vars script: {}", path.display()))?;
+    debug!("Running vars script {}", path.display());
+    vars_script::eval(script, &source, vars)
+}
+
 /// Convert a flat map of TOML values into a Tera template context.
-fn vars_to_tera_context(vars: &HashMap<String, toml::Value>) -> Result<tera::Context> {
+pub(crate) fn vars_to_tera_context(vars: &HashMap<String, toml::Value>) -> Result<tera::Context> {
     let mut context = tera::Context::new();
     for (key, value) in vars {
         context.insert(key, value);
@@ -63,19 +219,90 @@ pub fn run(
     dry_run: bool,
     fs: &impl Fs,
     engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
 ) -> Result<()> {
+    let mut cache = GenerateCache::default();
+    run_cached(config, files, dry_run, &mut cache, fs, engine, runner)
+}
+
+/// [`run`], reusing compiled templates and parsed vars files from earlier
+/// calls with the same `cache`.
+pub fn run_cached(
+    config: &Config,
+    files: Option<&[String]>,
+    dry_run: bool,
+    cache: &mut GenerateCache,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
+) -> Result<()> {
+    generate(config, files, dry_run, cache, fs, engine, runner)?.into_result()
+}
+
+/// Generate, then stage (and with `and_deploy`, deploy) the files that
+/// generated cleanly.
+///
+/// Unlike `apply`, a file that fails doesn't hold the others back: each
+/// step takes the files the previous one succeeded on, and failures from
+/// every step are reported at the end. Direct files aren't generated, so
+/// they're left for `janus deploy`.
+pub fn run_and_forward(
+    config: &Config,
+    files: Option<&[String]>,
+    and_deploy: bool,
+    dry_run: bool,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
+) -> Result<()> {
+    let mut cache = GenerateCache::default();
+    let generated = generate(config, files, dry_run, &mut cache, fs, engine, runner)?;
+    let mut staged = None;
+    let mut deployed = Ok(());
+    if !generated.succeeded.is_empty() {
+        let outcome = super::stage::stage(config, Some(&generated.succeeded), dry_run, fs)?;
+        if and_deploy && !outcome.succeeded.is_empty() {
+            deployed =
+                super::deploy::run(config, Some(&outcome.succeeded), false, dry_run, fs, runner);
+        }
+        staged = Some(outcome);
+    }
+
+    generated.into_result()?;
+    if let Some(staged) = staged {
+        staged.into_result()?;
+    }
+    deployed
+}
+
+/// Generate the selected files, collecting per-file failures.
+pub(crate) fn generate(
+    config: &Config,
+    files: Option<&[String]>,
+    dry_run: bool,
+    cache: &mut GenerateCache,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
+) -> Result<Outcome> {
     let entries = config.filter_files(files);
     if entries.is_empty() {
         config.bail_unmatched(files)?;
         info!("No files to generate");
-        return Ok(());
+        return Ok(Outcome {
+            succeeded: Vec::new(),
+            failure: None,
+        });
     }
 
     let dotfiles_dir = config.dotfiles_dir(fs);
     let generated_dir = config.generated_dir(fs);
 
-    // Load global vars
-    let global_vars = load_vars(&dotfiles_dir, &config.vars, fs)?;
+    // Load global vars, including any derived by vars_script
+    let global_vars = {
+        let _timing = timings::phase("vars", "global");
+        global_vars(config, &dotfiles_dir, &mut cache.vars, fs)?
+    };
 
     // Parse global secret entries (cheap TOML reads, no op calls yet)
     let global_secret_entries = secrets::parse_secret_files(&dotfiles_dir, &config.secrets, fs)?;
@@ -84,9 +311,11 @@ pub fn run(
     let mut resolver = SecretResolver::new();
 
     let mut errors: Vec<(String, anyhow::Error)> = Vec::new();
-    let mut succeeded = 0usize;
+    let mut succeeded = Vec::new();
 
+    let progress = progress::phase("generate", entries.len());
     for entry in &entries {
+        progress.advance(&entry.src);
         if entry.direct {
             if entry.template {
                 anyhow::bail!(
@@ -94,7 +323,30 @@ pub fn run(
                     entry.src
                 );
             }
-            debug!("Skipping direct file: {}", entry.src);
+            let Some(regions_template) = &entry.regions_template else {
+                debug!("Skipping direct file: {}", entry.src);
+                continue;
+            };
+            match generate_regions(
+                config,
+                entry,
+                regions_template,
+                &dotfiles_dir,
+                &global_vars,
+                &global_secret_entries,
+                &mut resolver,
+                cache,
+                dry_run,
+                fs,
+                engine,
+                runner,
+            ) {
+                Ok(()) => succeeded.push(entry.src.clone()),
+                Err(e) => {
+                    warn!("Failed to render regions of {}: {e:#}", entry.src);
+                    errors.push((entry.src.clone(), e));
+                }
+            }
             continue;
         }
         match generate_file(
@@ -105,11 +357,13 @@ pub fn run(
             &global_vars,
             &global_secret_entries,
             &mut resolver,
+            cache,
             dry_run,
             fs,
             engine,
+            runner,
         ) {
-            Ok(()) => succeeded += 1,
+            Ok(()) => succeeded.push(entry.src.clone()),
             Err(e) => {
                 warn!("Failed to generate {}: {e:#}", entry.src);
                 errors.push((entry.src.clone(), e));
@@ -117,22 +371,23 @@ pub fn run(
         }
     }
 
-    if errors.is_empty() {
-        info!("Generated {} file(s)", succeeded);
+    if !dry_run {
+        super::record_activity(config, &succeeded, State::record_generate, fs);
+    }
+
+    let failure = if errors.is_empty() {
+        info!("Generated {} file(s)", succeeded.len());
+        None
     } else {
         info!(
             "Generated {} file(s) with {} failure(s)",
-            succeeded,
+            succeeded.len(),
             errors.len()
         );
-        let mut msg = format!("Failed to generate {} file(s):", errors.len());
-        for (src, e) in &errors {
-            msg.push_str(&format!("\n  {src}: {e:#}"));
-        }
-        anyhow::bail!(msg);
-    }
+        Some(super::FileErrors::new("generate", &errors))
+    };
 
-    Ok(())
+    Ok(Outcome { succeeded, failure })
 }
 
 /// Generate a single file: render template or copy, then preserve permissions.
@@ -145,11 +400,13 @@ fn generate_file(
     global_vars: &HashMap<String, toml::Value>,
     global_secret_entries: &[SecretEntry],
     resolver: &mut SecretResolver,
+    cache: &mut GenerateCache,
     dry_run: bool,
     fs: &impl Fs,
     engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
 ) -> Result<()> {
-    let src_path = dotfiles_dir.join(&entry.src);
+    let src_path = config.source_path(&entry.src, fs);
     let dest_path = generated_dir.join(&entry.src);
 
     if !fs.exists(&src_path) {
@@ -158,6 +415,7 @@ fn generate_file(
 
     if dry_run {
         info!("[dry-run] Would generate: {}", entry.src);
+        plan::action("generate", &entry.src, &dest_path.to_string_lossy(), "");
         return Ok(());
     }
 
@@ -168,80 +426,627 @@ fn generate_file(
     }
 
     if entry.template {
-        // Look up matching filesets for this file
-        let matching_filesets = config.matching_filesets(&entry.src);
-
-        // Build vars: global -> fileset -> per-file (later wins)
-        let mut vars = global_vars.clone();
-        for fileset in &matching_filesets {
-            if !fileset.vars.is_empty() {
-                let fileset_vars = load_vars(dotfiles_dir, &fileset.vars, fs)?;
-                vars.extend(fileset_vars);
-            }
-        }
-        if !entry.vars.is_empty() {
-            let local_vars = load_vars(dotfiles_dir, &entry.vars, fs)?;
-            vars.extend(local_vars);
-        }
-
-        // Build secret entries: global -> fileset -> per-file
-        let mut secret_entries: Vec<SecretEntry> = global_secret_entries.to_vec();
-        for fileset in &matching_filesets {
-            if !fileset.secrets.is_empty() {
-                let fileset_secrets =
-                    secrets::parse_secret_files(dotfiles_dir, &fileset.secrets, fs)?;
-                secret_entries.extend(fileset_secrets);
-            }
-        }
-        if !entry.secrets.is_empty() {
-            let file_secrets = secrets::parse_secret_files(dotfiles_dir, &entry.secrets, fs)?;
-            secret_entries.extend(file_secrets);
-        }
-
-        // Resolve secrets (lazy - only calls op read for uncached references)
-        let resolved_secrets = if !secret_entries.is_empty() {
-            secrets::resolve_secrets(&secret_entries, resolver, engine)?
-        } else {
-            HashMap::new()
-        };
-
-        // Check for var/secret name collisions
-        if !resolved_secrets.is_empty() {
-            secrets::check_conflicts(&vars, &resolved_secrets)?;
-            vars.extend(resolved_secrets);
-        }
-
-        let context = vars_to_tera_context(&vars)?;
         let template_content = fs
             .read_to_string(&src_path)
             .with_context(|| format!("Failed to read template: {}", src_path.display()))?;
+        let rendered = render_template(
+            config,
+            entry,
+            &template_content,
+            dotfiles_dir,
+            global_vars,
+            global_secret_entries,
+            resolver,
+            cache,
+            fs,
+            engine,
+            runner,
+        )?;
+        let rendered = postprocess(entry, rendered, runner)?;
 
-        let rendered = Tera::one_off(&template_content, &context, false)
-            .with_context(|| format!("Failed to render template: {}", entry.src))?;
-
-        fs.write(&dest_path, rendered.as_bytes())
+        let output = encode_output(entry, rendered)?;
+        let _timing = timings::phase("write", &entry.src);
+        fs.write(&dest_path, &output)
+            .with_context(|| format!("Failed to write generated file: {}", dest_path.display()))?;
+    } else if !entry.inject.is_empty() {
+        let content = fs
+            .read(&src_path)
+            .with_context(|| format!("Failed to read source: {}", src_path.display()))?;
+        let mut content = inject_secrets(
+            config,
+            entry,
+            content,
+            dotfiles_dir,
+            global_vars,
+            global_secret_entries,
+            resolver,
+            cache,
+            fs,
+            engine,
+        )?;
+        if !entry.postprocess.is_empty() || entry.converts_output() {
+            let text = String::from_utf8(content).with_context(|| {
+                format!(
+                    "{}: postprocess, newline, and encoding need UTF-8 content",
+                    entry.src
+                )
+            })?;
+            content = encode_output(entry, postprocess(entry, text, runner)?)?;
+        }
+        let _timing = timings::phase("write", &entry.src);
+        fs.write(&dest_path, &content)
+            .with_context(|| format!("Failed to write generated file: {}", dest_path.display()))?;
+    } else if !entry.postprocess.is_empty() || entry.converts_output() {
+        let content = fs
+            .read_to_string(&src_path)
+            .with_context(|| format!("Failed to read source: {}", src_path.display()))?;
+        let output = encode_output(entry, postprocess(entry, content, runner)?)?;
+        let _timing = timings::phase("write", &entry.src);
+        fs.write(&dest_path, &output)
             .with_context(|| format!("Failed to write generated file: {}", dest_path.display()))?;
     } else {
         // Copy as-is
-        fs.copy(&src_path, &dest_path)
+        let _timing = timings::phase("write", &entry.src);
+        fs.copy_streaming(&src_path, &dest_path)
             .with_context(|| format!("Failed to copy file: {}", entry.src))?;
     }
 
-    // Preserve file permissions
-    let mode = fs
-        .file_mode(&src_path)
-        .with_context(|| format!("Failed to read metadata: {}", src_path.display()))?;
+    // Preserve file permissions unless the entry sets its own
+    let mode = match entry.mode {
+        Some(mode) => mode,
+        None => fs
+            .file_mode(&src_path)
+            .with_context(|| format!("Failed to read metadata: {}", src_path.display()))?,
+    };
     fs.set_file_mode(&dest_path, mode)
         .with_context(|| format!("Failed to set permissions: {}", dest_path.display()))?;
+    super::preserve_xattrs(&src_path, &dest_path, fs);
 
     info!("Generated {}", entry.src);
     Ok(())
 }
 
+/// Render a direct file's `regions_template` and write its managed regions
+/// into the matching regions of the source, leaving the rest of the source
+/// as it is. Writes nothing when the regions are already up to date.
+///
+/// The source is tracked in the dotfiles repo, so a template using secrets
+/// (or an entry with `inject`) is refused rather than committing them.
+#[allow(clippy::too_many_arguments)]
+fn generate_regions(
+    config: &Config,
+    entry: &FileEntry,
+    regions_template: &str,
+    dotfiles_dir: &Path,
+    global_vars: &HashMap<String, toml::Value>,
+    global_secret_entries: &[SecretEntry],
+    resolver: &mut SecretResolver,
+    cache: &mut GenerateCache,
+    dry_run: bool,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
+) -> Result<()> {
+    let src_path = config.source_path(&entry.src, fs);
+    let template_path = dotfiles_dir.join(regions_template);
+
+    if !fs.exists(&src_path) {
+        bail!("Source file not found: {}", src_path.display());
+    }
+    if !fs.exists(&template_path) {
+        bail!("regions_template not found: {}", template_path.display());
+    }
+
+    if !entry.inject.is_empty() {
+        bail!(
+            "{}: inject can't be used with regions_template; the source is tracked, so \
+             secrets would be written into the repo",
+            entry.src
+        );
+    }
+
+    if dry_run {
+        info!("[dry-run] Would render managed regions of: {}", entry.src);
+        plan::action(
+            "render-regions",
+            regions_template,
+            &src_path.to_string_lossy(),
+            "",
+        );
+        return Ok(());
+    }
+
+    let template_content = fs
+        .read_to_string(&template_path)
+        .with_context(|| format!("Failed to read template: {}", template_path.display()))?;
+    let (_, secret_entries) = file_scope(
+        config,
+        entry,
+        dotfiles_dir,
+        global_vars,
+        global_secret_entries,
+        cache,
+        fs,
+    )?;
+    if let Some(secret) = secret_entries
+        .iter()
+        .find(|s| super::secrets::template_uses(&template_content, &s.name))
+    {
+        bail!(
+            "{}: {regions_template} uses secret {}; the source is tracked, so it \
+             would be written into the repo",
+            entry.src,
+            secret.name
+        );
+    }
+    let rendered = render_template(
+        config,
+        entry,
+        &template_content,
+        dotfiles_dir,
+        global_vars,
+        global_secret_entries,
+        resolver,
+        cache,
+        fs,
+        engine,
+        runner,
+    )?;
+    let source = fs
+        .read_to_string(&src_path)
+        .with_context(|| format!("Failed to read source: {}", src_path.display()))?;
+    let updated = regions::splice(&source, &rendered).with_context(|| {
+        format!(
+            "{} doesn't match the regions of {regions_template}",
+            entry.src
+        )
+    })?;
+    if updated == source {
+        debug!("Managed regions of {} are up to date", entry.src);
+        return Ok(());
+    }
+
+    let _timing = timings::phase("write", &entry.src);
+    fs.write(&src_path, updated.as_bytes())
+        .with_context(|| format!("Failed to write source: {}", src_path.display()))?;
+    info!("Rendered managed regions of {}", entry.src);
+    Ok(())
+}
+
+/// Render `content` as `entry`'s template, with the same vars, secrets, and
+/// `janus` object generate uses.
+#[allow(clippy::too_many_arguments)]
+fn render_template(
+    config: &Config,
+    entry: &FileEntry,
+    content: &str,
+    dotfiles_dir: &Path,
+    global_vars: &HashMap<String, toml::Value>,
+    global_secret_entries: &[SecretEntry],
+    resolver: &mut SecretResolver,
+    cache: &mut GenerateCache,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
+) -> Result<String> {
+    let (mut vars, secret_entries) = file_scope(
+        config,
+        entry,
+        dotfiles_dir,
+        global_vars,
+        global_secret_entries,
+        cache,
+        fs,
+    )?;
+
+    // Resolve the secrets the template mentions (lazy - only calls op read
+    // for uncached references). References are rendered with the merged
+    // vars first.
+    let secret_entries: Vec<SecretEntry> = secret_entries
+        .into_iter()
+        .filter(|s| super::secrets::template_uses(content, &s.name))
+        .collect();
+    let resolved_secrets = if !secret_entries.is_empty() {
+        secrets::resolve_secrets(&secret_entries, &vars, resolver, engine)?
+    } else {
+        HashMap::new()
+    };
+
+    // Check for var/secret name collisions
+    if !resolved_secrets.is_empty() {
+        secrets::check_conflicts(&vars, &resolved_secrets)?;
+        vars.extend(resolved_secrets);
+    }
+
+    let vars = template_vars(config, entry, content, vars);
+
+    let _timing = timings::phase("render", &entry.src);
+    let mut context = vars_to_tera_context(&vars)?;
+    template::insert_janus(&mut context, config, entry, fs)?;
+    let probes =
+        template::Probes::collect(content, config.allow_exec, runner)?.with_managed(config, fs);
+    cache
+        .templates
+        .render(&entry.src, content, &context, &probes)
+}
+
+/// The vars `entry`'s template `content` renders with: `vars` merged over
+/// `var_defaults`, then the variables it uses that are still undefined
+/// filled in per [`fill_undefined`]. Generate, explain, and test all render
+/// through this, so they agree on what a template sees.
+///
+/// Vars that already started from `var_defaults` (see [`load_global_vars`])
+/// merge over them unchanged.
+pub(crate) fn template_vars(
+    config: &Config,
+    entry: &FileEntry,
+    content: &str,
+    vars: HashMap<String, toml::Value>,
+) -> HashMap<String, toml::Value> {
+    let mut merged = config.var_defaults.clone();
+    merge_vars(&mut merged, vars, config.merge_strategy, "vars");
+    fill_undefined(config, entry, content, &mut merged);
+    merged
+}
+
+/// Define the variables `content` uses that `vars` lacks as empty strings,
+/// unless `entry`'s `undefined_vars` policy is `error`.
+fn fill_undefined(
+    config: &Config,
+    entry: &FileEntry,
+    content: &str,
+    vars: &mut HashMap<String, toml::Value>,
+) {
+    let policy = config.undefined_vars_for(entry);
+    if policy == UndefinedVars::Error {
+        return;
+    }
+    for (name, line) in super::vars::template_variables(content) {
+        if vars.contains_key(&name) {
+            continue;
+        }
+        if policy == UndefinedVars::Warn {
+            warn!(
+                "{}:{line}: {name} is undefined; rendering it empty",
+                entry.src
+            );
+        }
+        vars.insert(name, toml::Value::String(String::new()));
+    }
+}
+
+/// The vars and secret entries that apply to `entry`.
+fn file_scope(
+    config: &Config,
+    entry: &FileEntry,
+    dotfiles_dir: &Path,
+    global_vars: &HashMap<String, toml::Value>,
+    global_secret_entries: &[SecretEntry],
+    cache: &mut GenerateCache,
+    fs: &impl Fs,
+) -> Result<(HashMap<String, toml::Value>, Vec<SecretEntry>)> {
+    let _timing = timings::phase("vars", &entry.src);
+    // Look up matching filesets for this file
+    let matching_filesets = config.matching_filesets(&entry.src);
+
+    // Build vars: global -> fileset -> per-file (later wins)
+    let strategy = config.merge_strategy;
+    let mut vars = global_vars.clone();
+    for fileset in &matching_filesets {
+        if !fileset.vars.is_empty() {
+            let fileset_vars = cache.vars.load(dotfiles_dir, &fileset.vars, strategy, fs)?;
+            merge_vars(&mut vars, fileset_vars, strategy, &fileset.vars.join(", "));
+        }
+    }
+    if !entry.vars.is_empty() {
+        let local_vars = cache.vars.load(dotfiles_dir, &entry.vars, strategy, fs)?;
+        merge_vars(&mut vars, local_vars, strategy, &entry.vars.join(", "));
+    }
+
+    // Build secret entries: global -> fileset -> per-file
+    let mut secret_entries: Vec<SecretEntry> = global_secret_entries.to_vec();
+    for fileset in &matching_filesets {
+        if !fileset.secrets.is_empty() {
+            let fileset_secrets = secrets::parse_secret_files(dotfiles_dir, &fileset.secrets, fs)?;
+            secret_entries.extend(fileset_secrets);
+        }
+    }
+    if !entry.secrets.is_empty() {
+        let file_secrets = secrets::parse_secret_files(dotfiles_dir, &entry.secrets, fs)?;
+        secret_entries.extend(file_secrets);
+    }
+    Ok((vars, secret_entries))
+}
+
+/// Replace each of `entry`'s `inject` placeholders in `content` with its
+/// secret's value. Only the secrets named by `inject` are resolved.
+#[allow(clippy::too_many_arguments)]
+fn inject_secrets(
+    config: &Config,
+    entry: &FileEntry,
+    mut content: Vec<u8>,
+    dotfiles_dir: &Path,
+    global_vars: &HashMap<String, toml::Value>,
+    global_secret_entries: &[SecretEntry],
+    resolver: &mut SecretResolver,
+    cache: &mut GenerateCache,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+) -> Result<Vec<u8>> {
+    let (vars, secret_entries) = file_scope(
+        config,
+        entry,
+        dotfiles_dir,
+        global_vars,
+        global_secret_entries,
+        cache,
+        fs,
+    )?;
+    for injection in &entry.inject {
+        // Later secrets files override earlier ones, as for templates
+        let Some(secret) = secret_entries
+            .iter()
+            .rev()
+            .find(|s| s.name == injection.secret)
+        else {
+            bail!(
+                "inject: no secrets file for {} defines {}",
+                entry.src,
+                injection.secret
+            );
+        };
+        let resolved =
+            secrets::resolve_secrets(std::slice::from_ref(secret), &vars, resolver, engine)?;
+        let value = match &resolved[&secret.name] {
+            toml::Value::String(value) => value.clone(),
+            other => other.to_string(),
+        };
+        let (replaced, count) =
+            replace_bytes(&content, injection.placeholder.as_bytes(), value.as_bytes());
+        if count == 0 {
+            warn!(
+                "{}: placeholder {} not found",
+                entry.src, injection.placeholder
+            );
+        }
+        content = replaced;
+    }
+    Ok(content)
+}
+
+/// Replace every non-overlapping occurrence of `needle` in `haystack`,
+/// returning the result and how many were replaced. `needle` must not be
+/// empty.
+fn replace_bytes(haystack: &[u8], needle: &[u8], with: &[u8]) -> (Vec<u8>, usize) {
+    let mut out = Vec::with_capacity(haystack.len());
+    let mut count = 0;
+    let mut rest = haystack;
+    while let Some(at) = rest.windows(needle.len()).position(|w| w == needle) {
+        out.extend_from_slice(&rest[..at]);
+        out.extend_from_slice(with);
+        rest = &rest[at + needle.len()..];
+        count += 1;
+    }
+    out.extend_from_slice(rest);
+    (out, count)
+}
+
+/// Produce what generate would write for `entry` given `content` as its
+/// source, without writing anything: render it if it's a template (or fill
+/// in its `inject` placeholders), then run its `postprocess` commands. Used
+/// to render sources that aren't on disk, such as an older revision.
+pub(crate) fn render_source(
+    config: &Config,
+    entry: &FileEntry,
+    content: String,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
+) -> Result<String> {
+    let content = if entry.template {
+        let dotfiles_dir = config.dotfiles_dir(fs);
+        let global_vars = load_global_vars(config, &dotfiles_dir, fs)?;
+        let global_secret_entries =
+            secrets::parse_secret_files(&dotfiles_dir, &config.secrets, fs)?;
+        render_template(
+            config,
+            entry,
+            &content,
+            &dotfiles_dir,
+            &global_vars,
+            &global_secret_entries,
+            &mut SecretResolver::new(),
+            &mut GenerateCache::default(),
+            fs,
+            engine,
+            runner,
+        )?
+    } else if !entry.inject.is_empty() {
+        let dotfiles_dir = config.dotfiles_dir(fs);
+        let global_vars = load_global_vars(config, &dotfiles_dir, fs)?;
+        let global_secret_entries =
+            secrets::parse_secret_files(&dotfiles_dir, &config.secrets, fs)?;
+        let injected = inject_secrets(
+            config,
+            entry,
+            content.into_bytes(),
+            &dotfiles_dir,
+            &global_vars,
+            &global_secret_entries,
+            &mut SecretResolver::new(),
+            &mut GenerateCache::default(),
+            fs,
+            engine,
+        )?;
+        String::from_utf8_lossy(&injected).into_owned()
+    } else {
+        content
+    };
+    postprocess(entry, content, runner)
+}
+
+/// Resolve the secrets `files` (or every file) render, so their values can
+/// be masked when showing generated content. Only secrets a template
+/// mentions or an `inject` names are resolved.
+///
+/// Showing content must work without the secret engines, so secrets that
+/// can't be resolved are left out of the mask with a warning.
+pub fn secret_mask(
+    config: &Config,
+    files: Option<&[String]>,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+) -> SecretMask {
+    let mut mask = SecretMask::default();
+    if let Err(e) = add_secret_values(config, files, fs, engine, &mut mask) {
+        warn!("Secret values may be shown unmasked: {e:#}");
+    }
+    mask
+}
+
+fn add_secret_values(
+    config: &Config,
+    files: Option<&[String]>,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    mask: &mut SecretMask,
+) -> Result<()> {
+    let entries = config.filter_files(files);
+    if !entries
+        .iter()
+        .any(|e| !e.direct && (e.template || !e.inject.is_empty()))
+    {
+        return Ok(());
+    }
+
+    let dotfiles_dir = config.dotfiles_dir(fs);
+    let mut cache = GenerateCache::default();
+    let global_vars = global_vars(config, &dotfiles_dir, &mut cache.vars, fs)?;
+    let global_secret_entries = secrets::parse_secret_files(&dotfiles_dir, &config.secrets, fs)?;
+    let mut resolver = SecretResolver::new();
+
+    for entry in entries {
+        if entry.direct || (!entry.template && entry.inject.is_empty()) {
+            continue;
+        }
+        let (vars, secret_entries) = file_scope(
+            config,
+            entry,
+            &dotfiles_dir,
+            &global_vars,
+            &global_secret_entries,
+            &mut cache,
+            fs,
+        )?;
+        let mut names: Vec<&str> = entry.inject.iter().map(|i| i.secret.as_str()).collect();
+        if entry.template {
+            // An unreadable source has nothing to show, so nothing to mask
+            let Ok(content) = fs.read_to_string(&config.source_path(&entry.src, fs)) else {
+                continue;
+            };
+            names.extend(
+                secret_entries
+                    .iter()
+                    .filter(|s| super::secrets::template_uses(&content, &s.name))
+                    .map(|s| s.name.as_str()),
+            );
+        }
+        for name in names {
+            // Later secrets files override earlier ones, as for rendering
+            let Some(secret) = secret_entries.iter().rev().find(|s| s.name == name) else {
+                continue;
+            };
+            let resolved = match secrets::resolve_secrets(
+                std::slice::from_ref(secret),
+                &vars,
+                &mut resolver,
+                engine,
+            ) {
+                Ok(resolved) => resolved,
+                Err(e) => {
+                    warn!("Secret '{name}' is shown unmasked: {e:#}");
+                    continue;
+                }
+            };
+            match &resolved[&secret.name] {
+                toml::Value::String(value) => mask.add(name, value),
+                other => mask.add(name, &other.to_string()),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Apply `entry`'s `newline` and `encoding` to generated text.
+pub(crate) fn encode_output(entry: &FileEntry, text: String) -> Result<Vec<u8>> {
+    let text = match entry.newline {
+        Newline::Preserve => text,
+        Newline::Lf => text.replace("\r\n", "\n"),
+        Newline::Crlf => text.replace("\r\n", "\n").replace('\n', "\r\n"),
+    };
+    match entry.encoding {
+        Encoding::Utf8 => Ok(text.into_bytes()),
+        Encoding::Latin1 => text
+            .chars()
+            .map(|c| {
+                u8::try_from(u32::from(c)).map_err(|_| {
+                    anyhow::anyhow!(
+                        "{}: {c:?} (U+{:04X}) can't be encoded as latin1",
+                        entry.src,
+                        u32::from(c)
+                    )
+                })
+            })
+            .collect(),
+    }
+}
+
+/// Undo [`encode_output`]: decode a generated or staged copy back to the
+/// UTF-8, `\n`-terminated text its source uses.
+pub(crate) fn decode_output(entry: &FileEntry, bytes: Vec<u8>) -> Result<String> {
+    let text = match entry.encoding {
+        Encoding::Utf8 => {
+            String::from_utf8(bytes).with_context(|| format!("{}: not valid UTF-8", entry.src))?
+        }
+        Encoding::Latin1 => bytes.into_iter().map(char::from).collect(),
+    };
+    Ok(match entry.newline {
+        Newline::Crlf => text.replace("\r\n", "\n"),
+        Newline::Preserve | Newline::Lf => text,
+    })
+}
+
+/// Pipe `content` through each of the entry's `postprocess` commands in turn.
+///
+/// A command that exits non-zero fails the file, so a formatter that rejects
+/// its input never leaves a half-written `.generated/` copy.
+pub(crate) fn postprocess(
+    entry: &FileEntry,
+    content: String,
+    runner: &impl CommandRunner,
+) -> Result<String> {
+    let mut content = content;
+    for command in &entry.postprocess {
+        let _timing = timings::phase("postprocess", &entry.src);
+        debug!("Postprocessing {} with `{command}`", entry.src);
+        let output = runner
+            .run(command, Some(&content))
+            .with_context(|| format!("Failed to run postprocess command `{command}`"))?;
+        if !output.success {
+            bail!(
+                "Postprocess command `{command}` failed: {}",
+                output.stderr.trim()
+            );
+        }
+        content = output.stdout;
+    }
+    Ok(content)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::platform::FakeSecretEngine;
+    use crate::platform::{CommandOutput, FakeCommandRunner, FakeSecretEngine};
     use crate::test_helpers::*;
 
     fn make_engine() -> FakeSecretEngine {
@@ -254,11 +1059,119 @@ mod tests {
         fs.add_file(format!("{DOTFILES}/vars.toml"), "name = \"world\"");
         fs.add_file(format!("{DOTFILES}/greet.conf"), "Hello {{ name }}!");
         let config = write_and_load_config(&fs, &make_config_toml(&[("greet.conf", None)]));
-        run(&config, None, false, &fs, &make_engine()).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         let content = fs
             .read_to_string(Path::new(&format!("{DOTFILES}/.generated/greet.conf")))
             .unwrap();
         assert_eq!(content, "Hello world!");
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert!(
+            state
+                .activity("greet.conf")
+                .unwrap()
+                .last_generate
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn run_cached_reparses_only_changed_vars() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "name = \"world\"");
+        fs.add_file(format!("{DOTFILES}/greet.conf"), "Hello {{ name }}!");
+        let config = write_and_load_config(&fs, &make_config_toml(&[("greet.conf", None)]));
+        let (engine, runner) = (make_engine(), FakeCommandRunner::new());
+        let mut cache = GenerateCache::default();
+        let generated = format!("{DOTFILES}/.generated/greet.conf");
+
+        run_cached(&config, None, false, &mut cache, &fs, &engine, &runner).unwrap();
+        let before = fs.read_count();
+        run(&config, None, false, &fs, &engine, &runner).unwrap();
+        let uncached = fs.read_count() - before;
+        let before = fs.read_count();
+        run_cached(&config, None, false, &mut cache, &fs, &engine, &runner).unwrap();
+        // The vars file isn't read again
+        assert_eq!(fs.read_count() - before, uncached - 1);
+
+        fs.write(
+            Path::new(&format!("{DOTFILES}/vars.toml")),
+            b"name = \"janus\"",
+        )
+        .unwrap();
+        run_cached(&config, None, false, &mut cache, &fs, &engine, &runner).unwrap();
+        assert_eq!(
+            fs.read_to_string(Path::new(&generated)).unwrap(),
+            "Hello janus!"
+        );
+    }
+
+    #[test]
+    fn run_and_forward_skips_failed_files() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file(format!("{DOTFILES}/good.conf"), "good");
+        fs.add_file(format!("{DOTFILES}/bad.conf"), "{{ undefined }}");
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[("good.conf", None), ("bad.conf", None)]),
+        );
+        let err = run_and_forward(
+            &config,
+            None,
+            true,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap_err();
+        let failed = err.downcast_ref::<crate::ops::FileErrors>().unwrap();
+        assert_eq!(failed.action, "generate");
+        assert_eq!(failed.errors[0].0, "bad.conf");
+
+        assert!(fs.is_symlink(Path::new("/home/test/.config/good.conf")));
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/.staged/bad.conf"))));
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert!(state.is_deployed("good.conf"));
+        assert!(!state.is_deployed("bad.conf"));
+    }
+
+    #[test]
+    fn janus_object_in_context() {
+        let fs = setup_fs();
+        fs.add_file(
+            format!("{DOTFILES}/list.conf"),
+            "{% for f in janus.files %}{{ f.src }} -> {{ f.target }}\n{% endfor %}",
+        );
+        fs.add_file(format!("{DOTFILES}/b.conf"), "b");
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[("list.conf", None), ("b.conf", Some("~/.b"))]),
+        );
+        run(
+            &config,
+            Some(&["list.conf".to_string()]),
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        let content = fs
+            .read_to_string(Path::new(&format!("{DOTFILES}/.generated/list.conf")))
+            .unwrap();
+        assert_eq!(
+            content,
+            "list.conf -> ~/.config/list.conf\nb.conf -> ~/.b\n"
+        );
     }
 
     #[test]
@@ -271,7 +1184,15 @@ mod tests {
             "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\n\n[[files]]\nsrc = \"data.bin\"\ntemplate = false\n"
         );
         let config = write_and_load_config(&fs, &toml);
-        run(&config, None, false, &fs, &make_engine()).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         let content = fs
             .read(Path::new(&format!("{DOTFILES}/.generated/data.bin")))
             .unwrap();
@@ -284,7 +1205,15 @@ mod tests {
         fs.add_file(format!("{DOTFILES}/vars.toml"), "");
         fs.add_file_with_mode(format!("{DOTFILES}/script.sh"), "#!/bin/bash", 0o755);
         let config = write_and_load_config(&fs, &make_config_toml(&[("script.sh", None)]));
-        run(&config, None, false, &fs, &make_engine()).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         let mode = fs
             .file_mode(Path::new(&format!("{DOTFILES}/.generated/script.sh")))
             .unwrap();
@@ -297,7 +1226,15 @@ mod tests {
         fs.add_file(format!("{DOTFILES}/vars.toml"), "");
         fs.add_file(format!("{DOTFILES}/hypr/hypr.conf"), "content");
         let config = write_and_load_config(&fs, &make_config_toml(&[("hypr/hypr.conf", None)]));
-        run(&config, None, false, &fs, &make_engine()).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         assert!(fs.is_dir(Path::new(&format!("{DOTFILES}/.generated/hypr"))));
     }
 
@@ -311,7 +1248,14 @@ mod tests {
             &fs,
             &make_config_toml(&[("good.conf", None), ("bad.conf", None)]),
         );
-        let result = run(&config, None, false, &fs, &make_engine());
+        let result = run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        );
         assert!(result.is_err());
         // good.conf should still have been generated
         assert!(fs.exists(Path::new(&format!("{DOTFILES}/.generated/good.conf"))));
@@ -323,7 +1267,15 @@ mod tests {
         // vars.toml doesn't exist but that's OK
         fs.add_file(format!("{DOTFILES}/a.conf"), "plain content");
         let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
-        run(&config, None, false, &fs, &make_engine()).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
     }
 
     #[test]
@@ -352,20 +1304,66 @@ vars = ["fs-vars.toml"]
 "#
         );
         let config = write_and_load_config(&fs, &toml);
-        run(&config, None, false, &fs, &make_engine()).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        let content = fs
+            .read_to_string(Path::new(&format!("{DOTFILES}/.generated/test.conf")))
+            .unwrap();
+        assert_eq!(content, "perfile");
+    }
+
+    #[test]
+    fn secret_injection() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file(
+            format!("{DOTFILES}/secrets.toml"),
+            "[[secret]]\nname = \"db_pass\"\nengine = \"1password\"\nreference = \"op://db/pass\"\n",
+        );
+        fs.add_file(format!("{DOTFILES}/db.conf"), "password={{ db_pass }}");
+
+        let toml = format!(
+            r#"
+dotfiles_dir = "{DOTFILES}"
+vars = ["vars.toml"]
+secrets = ["secrets.toml"]
+
+[[files]]
+src = "db.conf"
+"#
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let mut engine = FakeSecretEngine::new();
+        engine.add_secret("1password", "op://db/pass", "s3cret");
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &engine,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         let content = fs
-            .read_to_string(Path::new(&format!("{DOTFILES}/.generated/test.conf")))
+            .read_to_string(Path::new(&format!("{DOTFILES}/.generated/db.conf")))
             .unwrap();
-        assert_eq!(content, "perfile");
+        assert_eq!(content, "password=s3cret");
     }
 
     #[test]
-    fn secret_injection() {
+    fn secret_reference_rendered_from_vars() {
         let fs = setup_fs();
-        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "vault = \"Work\"");
         fs.add_file(
             format!("{DOTFILES}/secrets.toml"),
-            "[[secret]]\nname = \"db_pass\"\nengine = \"1password\"\nreference = \"op://db/pass\"\n",
+            "[[secret]]\nname = \"db_pass\"\nengine = \"1password\"\nreference = \"op://{{ vault }}/db/pass\"\n",
         );
         fs.add_file(format!("{DOTFILES}/db.conf"), "password={{ db_pass }}");
 
@@ -381,12 +1379,20 @@ src = "db.conf"
         );
         let config = write_and_load_config(&fs, &toml);
         let mut engine = FakeSecretEngine::new();
-        engine.add_secret("1password", "op://db/pass", "s3cret");
-        run(&config, None, false, &fs, &engine).unwrap();
+        engine.add_secret("1password", "op://Work/db/pass", "w0rk");
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &engine,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         let content = fs
             .read_to_string(Path::new(&format!("{DOTFILES}/.generated/db.conf")))
             .unwrap();
-        assert_eq!(content, "password=s3cret");
+        assert_eq!(content, "password=w0rk");
     }
 
     #[test]
@@ -412,12 +1418,217 @@ src = "test.conf"
         let config = write_and_load_config(&fs, &toml);
         let mut engine = FakeSecretEngine::new();
         engine.add_secret("1password", "op://x", "val");
-        let result = run(&config, None, false, &fs, &engine);
+        let result = run(
+            &config,
+            None,
+            false,
+            &fs,
+            &engine,
+            &FakeCommandRunner::new(),
+        );
         assert!(result.is_err());
         let msg = format!("{:#}", result.unwrap_err());
         assert!(msg.contains("collision"), "got: {msg}");
     }
 
+    #[test]
+    fn unavailable_engine_fails_only_files_using_secrets() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file(
+            format!("{DOTFILES}/secrets.toml"),
+            "[[secret]]\nname = \"db_pass\"\nengine = \"1password\"\nreference = \"op://db/pass\"\n",
+        );
+        fs.add_file(format!("{DOTFILES}/db.conf"), "password={{ db_pass }}");
+        fs.add_file(format!("{DOTFILES}/plain.conf"), "greeting=hi");
+        let toml = format!(
+            "secrets = [\"secrets.toml\"]\n{}",
+            make_config_toml(&[("db.conf", None), ("plain.conf", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let mut engine = FakeSecretEngine::new();
+        engine.set_unavailable("1password");
+
+        let err = run(
+            &config,
+            None,
+            false,
+            &fs,
+            &engine,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap_err();
+        let msg = format!("{err:#}");
+        assert!(
+            msg.contains("db.conf") && !msg.contains("plain.conf"),
+            "got: {msg}"
+        );
+        assert!(fs.exists(Path::new(&format!("{DOTFILES}/.generated/plain.conf"))));
+
+        let engine = secrets::SkipSecrets::new(engine, true);
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &engine,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        let content = fs
+            .read_to_string(Path::new(&format!("{DOTFILES}/.generated/db.conf")))
+            .unwrap();
+        assert_eq!(
+            content,
+            format!("password={}", secrets::placeholder("op://db/pass"))
+        );
+    }
+
+    #[test]
+    fn newline_and_encoding_applied_to_output() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "name = \"Zoë\"");
+        fs.add_file(format!("{DOTFILES}/win.ini"), "[user]\r\nname={{ name }}\n");
+        fs.add_file(format!("{DOTFILES}/plain.txt"), "café\n");
+        let toml = format!(
+            "{}newline = \"crlf\"\nencoding = \"latin1\"\n\n\
+             [[files]]\nsrc = \"plain.txt\"\ntemplate = false\nencoding = \"latin1\"\n",
+            make_config_toml(&[("win.ini", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        let read = |name: &str| {
+            fs.read(Path::new(&format!("{DOTFILES}/.generated/{name}")))
+                .unwrap()
+        };
+        assert_eq!(read("win.ini"), b"[user]\r\nname=Zo\xEB\r\n");
+        assert_eq!(read("plain.txt"), b"caf\xE9\n");
+        assert_eq!(
+            decode_output(&config.files[0], read("win.ini")).unwrap(),
+            "[user]\nname=Zoë\n"
+        );
+    }
+
+    #[test]
+    fn latin1_rejects_wide_characters() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.txt"), "→\n");
+        let toml = format!(
+            "{}template = false\nencoding = \"latin1\"\n",
+            make_config_toml(&[("a.txt", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let err = run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("latin1"), "got: {err:#}");
+    }
+
+    fn inject_config(fs: &crate::platform::FakeFs, secret: &str) -> Config {
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file(
+            format!("{DOTFILES}/secrets.toml"),
+            "[[secret]]\nname = \"api_token\"\nengine = \"1password\"\nreference = \"op://api/token\"\n\n\
+             [[secret]]\nname = \"unused\"\nengine = \"1password\"\nreference = \"op://missing\"\n",
+        );
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\nsecrets = [\"secrets.toml\"]\n\n\
+             [[files]]\nsrc = \"app.dat\"\ntemplate = false\n\
+             inject = [{{ placeholder = \"@@TOKEN@@\", secret = \"{secret}\" }}]\n"
+        );
+        write_and_load_config(fs, &toml)
+    }
+
+    #[test]
+    fn inject_replaces_placeholders_byte_for_byte() {
+        let fs = setup_fs();
+        // {{ }} is left alone: the file isn't a template
+        fs.add_file(
+            format!("{DOTFILES}/app.dat"),
+            b"\x00\xffkey=@@TOKEN@@ {{ x }}\n@@TOKEN@@".to_vec(),
+        );
+        let config = inject_config(&fs, "api_token");
+        let mut engine = FakeSecretEngine::new();
+        // `unused` has no value, so resolving it would fail
+        engine.add_secret("1password", "op://api/token", "t0k");
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &engine,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        let content = fs
+            .read(Path::new(&format!("{DOTFILES}/.generated/app.dat")))
+            .unwrap();
+        assert_eq!(content, b"\x00\xffkey=t0k {{ x }}\nt0k");
+    }
+
+    #[test]
+    fn inject_unknown_secret_fails() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/app.dat"), "key=@@TOKEN@@");
+        let config = inject_config(&fs, "nope");
+        let result = run(
+            &config,
+            None,
+            false,
+            &fs,
+            &FakeSecretEngine::new(),
+            &FakeCommandRunner::new(),
+        );
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(msg.contains("defines nope"), "got: {msg}");
+    }
+
+    #[test]
+    fn secret_mask_covers_injected_secrets_only() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/app.dat"), "key=@@TOKEN@@");
+        let config = inject_config(&fs, "api_token");
+        let mut engine = FakeSecretEngine::new();
+        // `unused` has no value, so resolving it would fail
+        engine.add_secret("1password", "op://api/token", "t0k3n");
+        let mask = secret_mask(&config, None, &fs, &engine);
+        assert_eq!(mask.mask("+key=t0k3n"), "+key=<secret api_token>");
+    }
+
+    #[test]
+    fn secret_mask_covers_template_secrets() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file(
+            format!("{DOTFILES}/secrets.toml"),
+            "[[secret]]\nname = \"db_pass\"\nengine = \"1password\"\nreference = \"op://db/pass\"\n",
+        );
+        fs.add_file(format!("{DOTFILES}/db.conf"), "password={{ db_pass }}");
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\nsecrets = [\"secrets.toml\"]\n\n\
+             [[files]]\nsrc = \"db.conf\"\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let mut engine = FakeSecretEngine::new();
+        engine.add_secret("1password", "op://db/pass", "s3cret");
+        let mask = secret_mask(&config, None, &fs, &engine);
+        assert_eq!(mask.mask("password=s3cret"), "password=<secret db_pass>");
+    }
+
     #[test]
     fn all_files() {
         let fs = setup_fs();
@@ -428,7 +1639,15 @@ src = "test.conf"
             &fs,
             &make_config_toml(&[("a.conf", None), ("b.conf", None)]),
         );
-        run(&config, None, false, &fs, &make_engine()).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         assert!(fs.exists(Path::new(&format!("{DOTFILES}/.generated/a.conf"))));
         assert!(fs.exists(Path::new(&format!("{DOTFILES}/.generated/b.conf"))));
     }
@@ -444,7 +1663,15 @@ src = "test.conf"
             &make_config_toml(&[("a.conf", None), ("b.conf", None)]),
         );
         let patterns = vec!["a.conf".to_string()];
-        run(&config, Some(&patterns), false, &fs, &make_engine()).unwrap();
+        run(
+            &config,
+            Some(&patterns),
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         assert!(fs.exists(Path::new(&format!("{DOTFILES}/.generated/a.conf"))));
         assert!(!fs.exists(Path::new(&format!("{DOTFILES}/.generated/b.conf"))));
     }
@@ -463,7 +1690,14 @@ src = "test.conf"
                 ("missing2.conf", None),
             ]),
         );
-        let result = run(&config, None, false, &fs, &make_engine());
+        let result = run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        );
         assert!(result.is_err());
         let msg = format!("{:#}", result.unwrap_err());
         assert!(msg.contains("2 file(s)"), "got: {msg}");
@@ -475,7 +1709,15 @@ src = "test.conf"
         fs.add_file(format!("{DOTFILES}/vars.toml"), "");
         fs.add_file(format!("{DOTFILES}/a.conf"), "content");
         let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
-        run(&config, None, true, &fs, &make_engine()).unwrap();
+        run(
+            &config,
+            None,
+            true,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         assert!(!fs.exists(Path::new(&format!("{DOTFILES}/.generated/a.conf"))));
     }
 
@@ -500,20 +1742,124 @@ vars = ["fs-vars.toml"]
 "#
         );
         let config = write_and_load_config(&fs, &toml);
-        run(&config, None, false, &fs, &make_engine()).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         let content = fs
             .read_to_string(Path::new(&format!("{DOTFILES}/.generated/test.conf")))
             .unwrap();
         assert_eq!(content, "blue");
     }
 
+    #[cfg(feature = "vars-script")]
+    #[test]
+    fn vars_script_derives_vars() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "dpi = 192\nbase_font = 11");
+        fs.add_file(
+            format!("{DOTFILES}/vars.rhai"),
+            "let scale = if vars.dpi > 144 { 2 } else { 1 };\n#{ font_size: vars.base_font * scale, dpi: 0 }",
+        );
+        fs.add_file(format!("{DOTFILES}/local.toml"), "dpi = 96");
+        fs.add_file(
+            format!("{DOTFILES}/a.conf"),
+            "size={{ font_size }} dpi={{ dpi }}",
+        );
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\nvars_script = \"vars.rhai\"\n\n[[files]]\nsrc = \"a.conf\"\nvars = [\"local.toml\"]\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        let content = fs
+            .read_to_string(Path::new(&format!("{DOTFILES}/.generated/a.conf")))
+            .unwrap();
+        // Script output overrides global vars; per-file vars override the script
+        assert_eq!(content, "size=22 dpi=96");
+    }
+
+    #[test]
+    fn missing_vars_script_errors() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.conf"), "x");
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nvars_script = \"vars.rhai\"\n\n[[files]]\nsrc = \"a.conf\"\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let msg = format!(
+            "{:#}",
+            run(
+                &config,
+                None,
+                false,
+                &fs,
+                &make_engine(),
+                &FakeCommandRunner::new()
+            )
+            .unwrap_err()
+        );
+        assert!(msg.contains("Failed to read vars script"), "got: {msg}");
+    }
+
+    #[test]
+    fn template_assertion_reported_per_file() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "bar_height = 0");
+        fs.add_file(
+            format!("{DOTFILES}/bar.conf"),
+            "{{ assert(cond=bar_height > 0, message=\"bar_height must be positive\") }}h={{ bar_height }}",
+        );
+        fs.add_file(format!("{DOTFILES}/b.conf"), "fine");
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[("bar.conf", None), ("b.conf", None)]),
+        );
+        let result = run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        );
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(
+            msg.contains(
+                "bar.conf: Template assertion failed in bar.conf: bar_height must be positive"
+            ),
+            "got: {msg}"
+        );
+        // Other files are still generated
+        assert!(fs.exists(Path::new(&format!("{DOTFILES}/.generated/b.conf"))));
+    }
+
     #[test]
     fn undefined_variable_errors() {
         let fs = setup_fs();
         fs.add_file(format!("{DOTFILES}/vars.toml"), "");
         fs.add_file(format!("{DOTFILES}/a.conf"), "Hello {{ undefined_name }}!");
         let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
-        let result = run(&config, None, false, &fs, &make_engine());
+        let result = run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        );
         assert!(result.is_err());
         let msg = format!("{:#}", result.unwrap_err());
         assert!(
@@ -524,6 +1870,53 @@ vars = ["fs-vars.toml"]
         );
     }
 
+    #[test]
+    fn undefined_vars_policy_and_defaults() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "accent = \"#fff\"\n");
+        fs.add_file(
+            format!("{DOTFILES}/a.conf"),
+            "{{ accent }} {{ gap }} [{{ border }}]",
+        );
+        fs.add_file(format!("{DOTFILES}/b.conf"), "{{ border }}");
+        let toml = format!(
+            "{}\n[var_defaults]\naccent = \"#000\"\ngap = 4\n",
+            make_config_toml(&[("a.conf", None), ("b.conf", None)])
+                .replacen("\n", "\nundefined_vars = \"empty\"\n", 1)
+                .replace(
+                    "src = \"b.conf\"\n",
+                    "src = \"b.conf\"\nundefined_vars = \"error\"\n"
+                )
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let runner = FakeCommandRunner::new();
+
+        run(
+            &config,
+            Some(&["a.conf".to_string()]),
+            false,
+            &fs,
+            &make_engine(),
+            &runner,
+        )
+        .unwrap();
+        assert_eq!(
+            fs.read_to_string(Path::new(&format!("{DOTFILES}/.generated/a.conf")))
+                .unwrap(),
+            "#fff 4 []"
+        );
+        let err = run(
+            &config,
+            Some(&["b.conf".to_string()]),
+            false,
+            &fs,
+            &make_engine(),
+            &runner,
+        )
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("b.conf"), "got: {err:#}");
+    }
+
     #[test]
     fn direct_files_skipped() {
         let fs = setup_fs();
@@ -533,10 +1926,86 @@ vars = ["fs-vars.toml"]
             "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\n\n[[files]]\nsrc = \"direct.conf\"\ndirect = true\ntemplate = false\n"
         );
         let config = write_and_load_config(&fs, &toml);
-        run(&config, None, false, &fs, &make_engine()).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         assert!(!fs.exists(Path::new(&format!("{DOTFILES}/.generated/direct.conf"))));
     }
 
+    #[test]
+    fn direct_file_regions_rendered_in_place() {
+        let fs = setup_fs();
+        fs.add_file(
+            format!("{DOTFILES}/vars.toml"),
+            "email = \"me@example.com\"\n",
+        );
+        fs.add_file(
+            format!("{DOTFILES}/gitconfig"),
+            "[core]\n# janus:begin managed\nemail = old\n# janus:end\n[alias]\n",
+        );
+        fs.add_file(
+            format!("{DOTFILES}/gitconfig.regions"),
+            "# janus:begin managed\nemail = {{ email }}\n# janus:end\n",
+        );
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\n\n[[files]]\nsrc = \"gitconfig\"\ndirect = true\ntemplate = false\nregions_template = \"gitconfig.regions\"\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs.read_to_string(Path::new(&format!("{DOTFILES}/gitconfig")))
+                .unwrap(),
+            "[core]\n# janus:begin managed\nemail = me@example.com\n# janus:end\n[alias]\n"
+        );
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/.generated/gitconfig"))));
+
+        // Secrets would end up committed with the source
+        fs.add_file(
+            format!("{DOTFILES}/secrets.toml"),
+            "[[secret]]\nname = \"token\"\nengine = \"1password\"\nreference = \"op://x\"\n",
+        );
+        fs.add_file(
+            format!("{DOTFILES}/gitconfig.regions"),
+            "# janus:begin managed\nemail = {{ token }}\n# janus:end\n",
+        );
+        let config = write_and_load_config(
+            &fs,
+            &toml.replace("vars = [", "secrets = [\"secrets.toml\"]\nvars = ["),
+        );
+        let err = run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("uses secret token"),
+            "got: {err:#}"
+        );
+        assert!(
+            fs.read_to_string(Path::new(&format!("{DOTFILES}/gitconfig")))
+                .unwrap()
+                .contains("email = me@example.com")
+        );
+    }
+
     #[test]
     fn direct_and_template_errors() {
         let fs = setup_fs();
@@ -546,9 +2015,194 @@ vars = ["fs-vars.toml"]
             "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\n\n[[files]]\nsrc = \"bad.conf\"\ndirect = true\ntemplate = true\n"
         );
         let config = write_and_load_config(&fs, &toml);
-        let result = run(&config, None, false, &fs, &make_engine());
+        let result = run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        );
         assert!(result.is_err());
         let msg = format!("{:#}", result.unwrap_err());
         assert!(msg.contains("mutually exclusive"), "got: {msg}");
     }
+
+    fn postprocess_toml(template: bool) -> String {
+        format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\n\n[[files]]\nsrc = \"a.json\"\n\
+             template = {template}\npostprocess = [\"jq .\", \"sort\"]\n"
+        )
+    }
+
+    fn ok(stdout: &str) -> CommandOutput {
+        CommandOutput {
+            success: true,
+            stdout: stdout.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn postprocess_pipes_through_each_command() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "name = \"x\"");
+        fs.add_file(format!("{DOTFILES}/a.json"), "{\"a\":\"{{ name }}\"}");
+        let config = write_and_load_config(&fs, &postprocess_toml(true));
+        let mut runner = FakeCommandRunner::new();
+        runner.add_response("jq .", ok("{\n  \"a\": \"x\"\n}\n"));
+        runner.add_response("sort", ok("sorted\n"));
+
+        run(&config, None, false, &fs, &make_engine(), &runner).unwrap();
+        assert_eq!(
+            runner.calls(),
+            vec![
+                ("jq .".to_string(), Some("{\"a\":\"x\"}".to_string())),
+                (
+                    "sort".to_string(),
+                    Some("{\n  \"a\": \"x\"\n}\n".to_string())
+                ),
+            ]
+        );
+        let content = fs
+            .read_to_string(Path::new(&format!("{DOTFILES}/.generated/a.json")))
+            .unwrap();
+        assert_eq!(content, "sorted\n");
+    }
+
+    #[test]
+    fn postprocess_applies_to_non_templates() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file_with_mode(format!("{DOTFILES}/a.json"), "{{ raw }}", 0o600);
+        let config = write_and_load_config(&fs, &postprocess_toml(false));
+        let mut runner = FakeCommandRunner::new();
+        runner.add_response("sort", ok("done"));
+
+        run(&config, None, false, &fs, &make_engine(), &runner).unwrap();
+        assert_eq!(runner.calls()[0].1.as_deref(), Some("{{ raw }}"));
+        let dest = format!("{DOTFILES}/.generated/a.json");
+        assert_eq!(fs.read_to_string(Path::new(&dest)).unwrap(), "done");
+        assert_eq!(fs.file_mode(Path::new(&dest)).unwrap(), 0o600);
+    }
+
+    fn render_with_strategy(strategy: &str) -> String {
+        let fs = setup_fs();
+        fs.add_file(
+            format!("{DOTFILES}/vars.toml"),
+            "[colors]\nfg = \"white\"\nbg = \"black\"\n",
+        );
+        fs.add_file(
+            format!("{DOTFILES}/bar-vars.toml"),
+            "[colors]\nbg = \"navy\"\n",
+        );
+        fs.add_file(
+            format!("{DOTFILES}/bar.conf"),
+            "{{ colors.fg | default(value=\"none\") }} on {{ colors.bg }}",
+        );
+        let toml = format!(
+            "merge_strategy = \"{strategy}\"\n{}vars = [\"bar-vars.toml\"]\n",
+            make_config_toml(&[("bar.conf", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        fs.read_to_string(Path::new(&format!("{DOTFILES}/.generated/bar.conf")))
+            .unwrap()
+    }
+
+    #[test]
+    fn per_file_tables_replace_or_merge_by_strategy() {
+        assert_eq!(render_with_strategy("shallow"), "none on navy");
+        assert_eq!(render_with_strategy("deep"), "white on navy");
+    }
+
+    #[test]
+    fn deep_merge_conflict_takes_later_value() {
+        let mut vars: HashMap<String, toml::Value> =
+            toml::from_str("[font]\nsize = 10\nface = \"mono\"\n").unwrap();
+        let layer: HashMap<String, toml::Value> =
+            toml::from_str("font = \"Iosevka\"\n[extra]\nx = 1\n").unwrap();
+        merge_vars(&mut vars, layer, MergeStrategy::Deep, "layer.toml");
+        assert_eq!(vars["font"].as_str(), Some("Iosevka"));
+        assert_eq!(vars["extra"]["x"].as_integer(), Some(1));
+    }
+
+    #[test]
+    fn entry_mode_overrides_source_mode() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file_with_mode(format!("{DOTFILES}/netrc"), "machine x", 0o644);
+        let toml = format!("{}mode = 0o600\n", make_config_toml(&[("netrc", None)]));
+        let config = write_and_load_config(&fs, &toml);
+
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        let dest = format!("{DOTFILES}/.generated/netrc");
+        assert_eq!(fs.file_mode(Path::new(&dest)).unwrap(), 0o600);
+    }
+
+    #[test]
+    fn postprocess_failure_fails_the_file() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file(format!("{DOTFILES}/a.json"), "not json");
+        let config = write_and_load_config(&fs, &postprocess_toml(false));
+        let mut runner = FakeCommandRunner::new();
+        runner.add_response(
+            "jq .",
+            CommandOutput {
+                success: false,
+                stderr: "parse error\n".to_string(),
+                ..Default::default()
+            },
+        );
+
+        let msg = format!(
+            "{:#}",
+            run(&config, None, false, &fs, &make_engine(), &runner).unwrap_err()
+        );
+        assert!(msg.contains("`jq .` failed: parse error"), "got: {msg}");
+        assert_eq!(runner.commands(), vec!["jq .".to_string()]);
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/.generated/a.json"))));
+    }
+
+    #[test]
+    fn renders_source_from_lower_layer() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "name = \"team\"");
+        fs.add_file("/srv/team/greet.conf", "hi {{ name }}");
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\ndotfiles_dirs = [\"/srv/team\"]\nvars = [\"vars.toml\"]\n\n\
+             [[files]]\nsrc = \"greet.conf\"\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        run(
+            &config,
+            None,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        let content = fs
+            .read_to_string(Path::new(&format!("{DOTFILES}/.generated/greet.conf")))
+            .unwrap();
+        assert_eq!(content, "hi team");
+    }
 }
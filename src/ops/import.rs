@@ -5,55 +5,182 @@
 //! directory, adds a `[[files]]` entry to the config, and runs the full forward
 //! pipeline (generate -> stage -> deploy).
 //!
+//! Directory walks follow symlinks unless `follow_links` is off. Links back
+//! into a directory the walk is already inside are not descended, and a file
+//! reachable through several links is only considered once, under its real
+//! path when that was walked. Files that resolve into the generated, staged,
+//! or a dotfiles directory (janus's own deployed symlinks, say, or a link to
+//! a directory in the dotfiles) are skipped, since they're already janus's.
+//!
+//! When the walk is inside a git repository (an nvim config, say), files its
+//! `.gitignore` rules ignore and anything under `.git/` are left out, so
+//! build output and plugin checkouts aren't offered; `--no-gitignore` turns
+//! that off. See [`crate::gitignore`] for the rules understood.
+//!
+//! Before anything is copied, files over [`Limits::max_file_size`] are
+//! skipped with a warning, and a walk that turns up more files or bytes
+//! than the other [`Limits`] (app data under `~/.config`, say) is summed up
+//! with a prompt to continue, unless `--yes` is given.
+//!
+//! An `http://` or `https://` URL is downloaded instead (see [`run_url`]),
+//! optionally checked against a pinned SHA-256, and becomes a new source
+//! file the same way.
+//!
 //! Uses fail-fast strategy since each file mutates config, state, and the filesystem.
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use sha2::{Digest, Sha256};
 use std::collections::HashSet;
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use tracing::{debug, info, warn};
 
 use crate::config::Config;
+use crate::gitignore::Gitignore;
+use crate::ops::stats::format_size;
 use crate::paths::{collapse_tilde, expand_tilde};
-use crate::platform::{Fs, Prompter, SecretEngine, WalkOptions};
+use crate::plan;
+use crate::platform::{CommandRunner, Fs, HttpClient, Prompter, SecretEngine, WalkOptions};
 use crate::state::{RecoveryInfo, State};
 
+/// A size in bytes, written as a number with an optional binary unit
+/// suffix: `512`, `64K`, `1MiB`, `2G`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number: u64 = number
+            .parse()
+            .map_err(|_| format!("invalid size `{s}` (expected e.g. 512K, 1M, or 2G)"))?;
+        let shift = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 0,
+            "k" | "kb" | "kib" => 10,
+            "m" | "mb" | "mib" => 20,
+            "g" | "gb" | "gib" => 30,
+            _ => return Err(format!("unknown size unit `{unit}` (expected K, M, or G)")),
+        };
+        number
+            .checked_mul(1 << shift)
+            .map(ByteSize)
+            .ok_or_else(|| format!("size `{s}` is too large"))
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(&format_size(self.0))
+    }
+}
+
+/// Guardrails checked before a directory import copies anything.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Files larger than this are skipped.
+    pub max_file_size: ByteSize,
+    /// Ask before importing more bytes than this in total.
+    pub max_total_size: ByteSize,
+    /// Ask before importing more files than this.
+    pub max_files: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_file_size: ByteSize(1 << 20),
+            max_total_size: ByteSize(20 << 20),
+            max_files: 200,
+        }
+    }
+}
+
+/// How [`run`] walks, filters, and confirms what it imports.
+#[derive(Debug, Clone, Copy)]
+pub struct ImportOptions {
+    /// Skip the per-file prompts and import everything.
+    pub all: bool,
+    /// Maximum directory traversal depth.
+    pub max_depth: usize,
+    /// Follow symlinks while walking a directory.
+    pub follow_links: bool,
+    /// Leave out what `.gitignore` rules ignore, and anything under `.git/`.
+    pub gitignore: bool,
+    /// Size and count guardrails (see `preflight`).
+    pub limits: Limits,
+    /// Import past `limits` without asking.
+    pub yes: bool,
+    /// Offer paths ignored earlier again, clearing them.
+    pub forget: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            all: false,
+            max_depth: 10,
+            follow_links: true,
+            gitignore: true,
+            limits: Limits::default(),
+            yes: false,
+            forget: false,
+        }
+    }
+}
+
 /// Import files from the given path into janus management.
 ///
-/// If `import_all` is true, skips interactive prompts and imports everything.
+/// With `options.all`, skips interactive prompts and imports everything.
+/// Paths ignored earlier (declined at the prompt, or tombstoned by
+/// `unimport`) are skipped unless `options.forget` is set, which clears
+/// them. Files over `options.limits` are skipped or confirmed first (see
+/// `preflight`); `options.yes` skips that confirmation.
 /// Each imported file is immediately deployed (generate -> stage -> deploy).
 #[allow(clippy::too_many_arguments)]
 pub fn run(
     config: &Config,
     config_path: &Path,
     path: &str,
-    import_all: bool,
-    max_depth: usize,
+    options: &ImportOptions,
     dry_run: bool,
     fs: &impl Fs,
     engine: &impl SecretEngine,
     prompter: &impl Prompter,
+    runner: &impl CommandRunner,
 ) -> Result<()> {
     let source_path = expand_tilde(path, fs);
     let dotfiles_dir = config.dotfiles_dir(fs);
-    let mut state = State::load(&dotfiles_dir, fs)?;
+    let mut state = State::load(&config.state_dir(fs), fs)?;
 
     if !fs.exists(&source_path) {
         anyhow::bail!("Path does not exist: {}", source_path.display());
     }
 
     let files: Vec<PathBuf> = if fs.is_dir(&source_path) {
-        fs.walk_dir(
-            &source_path,
-            &WalkOptions {
-                max_depth: Some(max_depth),
-                follow_links: true,
-                ..Default::default()
-            },
-        )?
-        .into_iter()
-        .filter(|e| e.is_file)
-        .map(|e| e.path)
-        .collect()
+        let walked = fs
+            .walk_dir(
+                &source_path,
+                &WalkOptions {
+                    max_depth: Some(options.max_depth),
+                    follow_links: options.follow_links,
+                    ..Default::default()
+                },
+            )?
+            .into_iter()
+            .filter(|e| e.is_file)
+            .map(|e| e.path)
+            .collect();
+        let walked = if options.gitignore {
+            drop_gitignored(&source_path, walked, fs)?
+        } else {
+            walked
+        };
+        dedup_by_canonical(walked, fs)
     } else {
         vec![source_path.clone()]
     };
@@ -70,7 +197,13 @@ pub fn run(
         .iter()
         .map(|f| expand_tilde(&f.target(), fs))
         .collect();
+    let janus_dirs: Vec<PathBuf> = [config.generated_dir(fs), config.staged_dir(fs)]
+        .into_iter()
+        .chain(config.layers(fs))
+        .map(|dir| fs.canonicalize(&dir).unwrap_or(dir))
+        .collect();
 
+    let mut candidates = Vec::new();
     for file_path in &files {
         let target_str = collapse_tilde(file_path, fs);
 
@@ -79,14 +212,57 @@ pub fn run(
             debug!("Already managed, skipping: {}", target_str);
             continue;
         }
+        let real = fs
+            .canonicalize(file_path)
+            .unwrap_or_else(|_| file_path.clone());
+        if let Some(dir) = janus_dirs.iter().find(|dir| real.starts_with(dir)) {
+            debug!(
+                "Skipping {target_str}: resolves to {} in {}",
+                real.display(),
+                dir.display()
+            );
+            continue;
+        }
 
-        // Check if ignored
+        // Check if ignored (cleared below with `forget`)
+        if !options.forget && state.is_ignored(&target_str) {
+            debug!("Already ignored, skipping: {}", target_str);
+            continue;
+        }
+
+        let size = fs
+            .stamp(file_path)
+            .with_context(|| format!("Failed to read metadata: {}", file_path.display()))?
+            .len;
+        if size > options.limits.max_file_size.0 {
+            warn!(
+                "Skipping {target_str}: {} is over the {} limit (see --max-file-size)",
+                ByteSize(size),
+                options.limits.max_file_size
+            );
+            continue;
+        }
+        candidates.push((file_path, target_str, size));
+    }
+
+    preflight(&candidates, &options.limits, options.yes, dry_run, prompter)?;
+
+    for (file_path, target_str, _) in candidates {
+        if options.forget && state.is_ignored(&target_str) {
+            let removed = state.forget_ignored(&target_str);
+            if !dry_run {
+                state.save(fs)?;
+            }
+            for entry in removed {
+                info!("Forgot earlier decision to ignore {}", entry);
+            }
+        }
         if state.is_ignored(&target_str) {
             debug!("Already ignored, skipping: {}", target_str);
             continue;
         }
 
-        if !import_all {
+        if !options.all {
             let selection = prompter.select(
                 &format!("Import {}?", target_str),
                 &["Import", "Ignore", "Skip"],
@@ -130,12 +306,105 @@ pub fn run(
             dry_run,
             fs,
             engine,
+            runner,
         )?;
     }
 
     Ok(())
 }
 
+/// Check `candidates` (path, target, size) against the total `limits`
+/// before anything is imported. Over either, the count, total size, and
+/// largest files are listed and the user asked to continue, defaulting to
+/// no; `yes` skips the question and `dry_run` only reports.
+fn preflight(
+    candidates: &[(&PathBuf, String, u64)],
+    limits: &Limits,
+    yes: bool,
+    dry_run: bool,
+    prompter: &impl Prompter,
+) -> Result<()> {
+    let total: u64 = candidates.iter().map(|(_, _, size)| size).sum();
+    if candidates.len() <= limits.max_files && total <= limits.max_total_size.0 {
+        return Ok(());
+    }
+    let summary = format!("{} files, {}", candidates.len(), ByteSize(total));
+    if dry_run {
+        info!("[dry-run] Would ask before importing {summary}");
+        return Ok(());
+    }
+    if yes {
+        debug!("--yes: skipping confirmation to import {summary}");
+        return Ok(());
+    }
+
+    let mut largest: Vec<_> = candidates.iter().collect();
+    largest.sort_by_key(|(_, _, size)| std::cmp::Reverse(*size));
+    println!("Importing {summary}. Largest:");
+    for (_, target, size) in largest.iter().take(5) {
+        println!("  {:>10}  {target}", ByteSize(*size));
+    }
+    if !prompter.confirm(&format!("{summary} — continue?"), false)? {
+        bail!(
+            "Aborted: import would bring in {summary} (over --max-files {} or --max-total-size {}); pass --yes to skip this check",
+            limits.max_files,
+            limits.max_total_size
+        );
+    }
+    Ok(())
+}
+
+/// Drop walked files under `root` that `.gitignore` rules ignore or that are
+/// inside a `.git` directory.
+fn drop_gitignored(root: &Path, files: Vec<PathBuf>, fs: &impl Fs) -> Result<Vec<PathBuf>> {
+    let rules = Gitignore::load(root, &files, fs)?;
+    Ok(files
+        .into_iter()
+        .filter(|file| {
+            let in_git_dir = file
+                .strip_prefix(root)
+                .is_ok_and(|rel| rel.components().any(|c| c.as_os_str() == ".git"));
+            let skip = in_git_dir || rules.is_ignored(file);
+            if skip {
+                debug!("Skipping gitignored {}", file.display());
+            }
+            !skip
+        })
+        .collect())
+}
+
+/// Drop walked files that are the same file reached through different
+/// symlinks. Keeps the file's real path if the walk found it there, and
+/// otherwise the first path it was found under.
+fn dedup_by_canonical(files: Vec<PathBuf>, fs: &impl Fs) -> Vec<PathBuf> {
+    let canonical: Vec<PathBuf> = files
+        .iter()
+        .map(|file| fs.canonicalize(file).unwrap_or_else(|_| file.clone()))
+        .collect();
+    let walked: HashSet<&PathBuf> = files.iter().collect();
+    let mut seen = HashSet::new();
+    files
+        .iter()
+        .zip(&canonical)
+        .filter(|(file, real)| {
+            let keep = if walked.contains(real) {
+                file == real
+            } else {
+                seen.insert(*real)
+            };
+            if !keep {
+                debug!(
+                    "Skipping {}: same file as {}",
+                    file.display(),
+                    real.display()
+                );
+            }
+            keep
+        })
+        .map(|(file, _)| file.clone())
+        .collect()
+}
+
 /// Import a single file: copy to dotfiles dir, add config entry, run pipeline.
 #[allow(clippy::too_many_arguments)]
 fn import_file(
@@ -147,6 +416,7 @@ fn import_file(
     dry_run: bool,
     fs: &impl Fs,
     engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
 ) -> Result<()> {
     // Determine destination path in dotfiles dir
     let dest_relative = determine_dest_path(file_path, fs)?;
@@ -164,6 +434,7 @@ fn import_file(
             "[dry-run] Would import: {} -> {}",
             target_str, dest_relative
         );
+        plan::action("import", target_str, &dest_relative, "");
         return Ok(());
     }
 
@@ -186,15 +457,39 @@ fn import_file(
     // Append config entry using toml_edit
     append_config_entry(config_path, &dest_relative, target_str, fs)?;
 
+    deploy_new_entry(
+        config_path,
+        &dest_relative,
+        target_str,
+        state,
+        fs,
+        engine,
+        runner,
+    )?;
+    info!("Imported {}", target_str);
+    Ok(())
+}
+
+/// Run the forward pipeline for a freshly added entry and record it as
+/// deployed.
+fn deploy_new_entry(
+    config_path: &Path,
+    dest_relative: &str,
+    target_str: &str,
+    state: &mut State,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
+) -> Result<()> {
     // Generate, stage, and deploy
     let config = crate::config::Config::load(config_path, fs)?;
-    let file_patterns = vec![dest_relative.clone()];
+    let file_patterns = vec![dest_relative.to_string()];
 
-    crate::ops::generate::run(&config, Some(&file_patterns), false, fs, engine)?;
+    crate::ops::generate::run(&config, Some(&file_patterns), false, fs, engine, runner)?;
     crate::ops::stage::run(&config, Some(&file_patterns), false, fs)?;
-    crate::ops::deploy::run(&config, Some(&file_patterns), true, false, fs)?;
+    crate::ops::deploy::run(&config, Some(&file_patterns), true, false, fs, runner)?;
 
-    state.add_deployed(dest_relative.clone(), target_str.to_string());
+    state.add_deployed(dest_relative.to_string(), target_str.to_string());
     state.save_with_recovery(
         RecoveryInfo {
             situation: vec![format!("{target_str} has been imported and deployed")],
@@ -210,18 +505,93 @@ fn import_file(
             ],
         },
         fs,
+    )
+}
+
+/// Whether an import path is a URL to download rather than a local path.
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("https://") || path.starts_with("http://")
+}
+
+/// Download `url` into the dotfiles directory as `src`, add its `[[files]]`
+/// entry, and deploy it.
+///
+/// `src` defaults to the last segment of the URL's path. When `sha256` is
+/// given, the download must hash to it, so re-running the import on another
+/// machine gets exactly the same file; otherwise the hash is logged for
+/// pinning next time. Source, target, and duplicate checks are the same as
+/// `janus new`. The entry is added with `template = false`, so downloaded
+/// content is never rendered; set `template = true` after reviewing it.
+#[allow(clippy::too_many_arguments)]
+pub fn run_url(
+    config: &Config,
+    config_path: &Path,
+    url: &str,
+    src: Option<&str>,
+    sha256: Option<&str>,
+    dry_run: bool,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
+    http: &impl HttpClient,
+) -> Result<()> {
+    let src = match src {
+        Some(src) => src.to_string(),
+        None => url_file_name(url)
+            .with_context(|| format!("Can't name a source file after {url}; pass --as"))?,
+    };
+
+    let body = http.get(url)?;
+    let digest = format!("{:x}", Sha256::digest(&body));
+    match sha256 {
+        Some(pinned) if !pinned.eq_ignore_ascii_case(&digest) => {
+            bail!("Checksum mismatch for {url}: expected sha256 {pinned}, got {digest}");
+        }
+        Some(_) => debug!("{url} matches its pinned sha256"),
+        None => info!("Downloaded {url} (sha256 {digest}); pass --sha256 {digest} to pin it"),
+    }
+    let contents =
+        String::from_utf8(body).with_context(|| format!("{url} didn't return UTF-8 text"))?;
+
+    super::new::run(config, config_path, &src, None, &contents, dry_run, fs)?;
+    if dry_run {
+        return Ok(());
+    }
+    // Someone else's text must not see our vars and secrets, or run
+    // commands through template functions
+    disable_template(config_path, &src, fs)?;
+
+    let target_str = format!("~/.config/{src}");
+    let mut state = State::load(&config.state_dir(fs), fs)?;
+    deploy_new_entry(
+        config_path,
+        &src,
+        &target_str,
+        &mut state,
+        fs,
+        engine,
+        runner,
     )?;
-    info!("Imported {}", target_str);
+    info!("Imported {url} as {src}");
     Ok(())
 }
 
+/// The last non-empty segment of a URL's path, ignoring any query or
+/// fragment.
+fn url_file_name(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next()?;
+    let (_, path) = path.split_once("://")?;
+    let (_, path) = path.split_once('/')?;
+    path.rsplit('/').find(|s| !s.is_empty()).map(str::to_string)
+}
+
 /// Determine the relative destination path within the dotfiles directory.
 ///
 /// Resolution order:
 /// 1. Files under `~/.config/` -> strip that prefix (e.g. `~/.config/hypr/hypr.conf` -> `hypr/hypr.conf`)
 /// 2. Files under `~/` -> strip home + leading dot (e.g. `~/.bashrc` -> `bashrc`)
 /// 3. Files elsewhere -> flatten parent with underscores (e.g. `/etc/systemd/system/foo.service` -> `etc_systemd_system/foo.service`)
-fn determine_dest_path(file_path: &Path, fs: &impl Fs) -> Result<String> {
+pub(crate) fn determine_dest_path(file_path: &Path, fs: &impl Fs) -> Result<String> {
     let config_dir = fs
         .config_dir()
         .unwrap_or_else(|| expand_tilde("~/.config", fs));
@@ -260,7 +630,12 @@ fn determine_dest_path(file_path: &Path, fs: &impl Fs) -> Result<String> {
 }
 
 /// Append a `[[files]]` entry to the config file using `toml_edit` to preserve formatting.
-fn append_config_entry(config_path: &Path, src: &str, target: &str, fs: &impl Fs) -> Result<()> {
+pub(crate) fn append_config_entry(
+    config_path: &Path,
+    src: &str,
+    target: &str,
+    fs: &impl Fs,
+) -> Result<()> {
     let contents = fs
         .read_to_string(config_path)
         .with_context(|| format!("Failed to read config: {}", config_path.display()))?;
@@ -294,10 +669,36 @@ fn append_config_entry(config_path: &Path, src: &str, target: &str, fs: &impl Fs
     Ok(())
 }
 
+/// Set `template = false` on `src`'s `[[files]]` entry in the config file.
+fn disable_template(config_path: &Path, src: &str, fs: &impl Fs) -> Result<()> {
+    let contents = fs
+        .read_to_string(config_path)
+        .with_context(|| format!("Failed to read config: {}", config_path.display()))?;
+    let mut doc = contents
+        .parse::<toml_edit::DocumentMut>()
+        .with_context(|| "Failed to parse config for editing")?;
+
+    let entry = doc
+        .get_mut("files")
+        .and_then(|files| files.as_array_of_tables_mut())
+        .and_then(|files| {
+            files
+                .iter_mut()
+                .find(|t| t.get("src").and_then(|s| s.as_str()) == Some(src))
+        });
+    let Some(entry) = entry else {
+        bail!("{src} has no [[files]] entry in {}", config_path.display());
+    };
+    entry.insert("template", toml_edit::value(false));
+
+    fs.write(config_path, doc.to_string().as_bytes())
+        .with_context(|| format!("Failed to write config: {}", config_path.display()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::platform::{FakePrompter, FakeSecretEngine};
+    use crate::platform::{FakeCommandRunner, FakeHttpClient, FakePrompter, FakeSecretEngine};
     use crate::state::State;
     use crate::test_helpers::*;
 
@@ -305,6 +706,68 @@ mod tests {
         FakeSecretEngine::new()
     }
 
+    const KITTY_URL: &str = "https://example.com/dotfiles/kitty.conf?raw=1";
+    const KITTY_SHA256: &str = "328a8e619163d06ff011fea93079aee46521f5891fc5e50e732078166ceb24b9";
+
+    fn import_url(
+        src: Option<&str>,
+        sha256: Option<&str>,
+    ) -> (crate::platform::FakeFs, Result<()>) {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        let config = write_and_load_config(&fs, &make_config_toml(&[]));
+        let mut http = FakeHttpClient::new();
+        http.add_response(KITTY_URL, "font_size 12\n");
+        let result = run_url(
+            &config,
+            Path::new(CONFIG_PATH),
+            KITTY_URL,
+            src,
+            sha256,
+            false,
+            &fs,
+            &make_engine(),
+            &FakeCommandRunner::new(),
+            &http,
+        );
+        (fs, result)
+    }
+
+    #[test]
+    fn url_import_downloads_and_deploys() {
+        let (fs, result) = import_url(Some("kitty/kitty.conf"), Some(KITTY_SHA256));
+        result.unwrap();
+        assert_eq!(
+            fs.read_to_string(Path::new(&format!("{DOTFILES}/kitty/kitty.conf")))
+                .unwrap(),
+            "font_size 12\n"
+        );
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert!(state.is_deployed("kitty/kitty.conf"));
+        assert!(fs.is_symlink(Path::new("/home/test/.config/kitty/kitty.conf")));
+        // Downloaded content is never rendered as a template
+        let config = Config::load(Path::new(CONFIG_PATH), &fs).unwrap();
+        let entry = config.files.iter().find(|e| e.src == "kitty/kitty.conf");
+        assert!(!entry.unwrap().template);
+    }
+
+    #[test]
+    fn url_import_names_source_after_url() {
+        let (fs, result) = import_url(None, None);
+        result.unwrap();
+        assert!(fs.exists(Path::new(&format!("{DOTFILES}/kitty.conf"))));
+    }
+
+    #[test]
+    fn url_import_rejects_checksum_mismatch() {
+        let (fs, result) = import_url(Some("kitty/kitty.conf"), Some(&"0".repeat(64)));
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(msg.contains("Checksum mismatch"), "got: {msg}");
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/kitty/kitty.conf"))));
+        let config_content = fs.read_to_string(Path::new(CONFIG_PATH)).unwrap();
+        assert!(!config_content.contains("kitty"));
+    }
+
     #[test]
     fn dest_path_under_config() {
         let fs = crate::platform::FakeFs::new("/home/test");
@@ -339,12 +802,12 @@ mod tests {
             &config,
             Path::new(CONFIG_PATH),
             "~/.config/hypr/hypr.conf",
-            false,
-            10,
+            &ImportOptions::default(),
             false,
             &fs,
             &make_engine(),
             &prompter,
+            &FakeCommandRunner::new(),
         )
         .unwrap();
         // File should be copied to dotfiles dir
@@ -375,12 +838,12 @@ mod tests {
             &config,
             Path::new(CONFIG_PATH),
             "~/.config/a.conf",
-            false,
-            10,
+            &ImportOptions::default(),
             false,
             &fs,
             &make_engine(),
             &prompter,
+            &FakeCommandRunner::new(),
         )
         .unwrap();
         // File should NOT be copied again (no duplicate in dotfiles dir)
@@ -401,18 +864,119 @@ mod tests {
             &config,
             Path::new(CONFIG_PATH),
             "~/.config/ignored.conf",
-            false,
-            10,
+            &ImportOptions::default(),
             false,
             &fs,
             &make_engine(),
             &prompter,
+            &FakeCommandRunner::new(),
         )
         .unwrap();
         // File should NOT be imported
         assert!(!fs.exists(Path::new(&format!("{DOTFILES}/ignored.conf"))));
     }
 
+    #[test]
+    fn skips_paths_matching_ignored_patterns() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file("/home/test/.config/Slack/Preferences", "{}");
+        fs.add_file("/home/test/.config/Slack/storage/state.json", "{}");
+        fs.add_file("/home/test/.config/Slack.conf", "content");
+        let state_toml = "[[ignored]]\npath = \"~/.config/Slack/**\"\nreason = \"user_declined\"\n";
+        fs.add_file(format!("{DOTFILES}/.janus_state.toml"), state_toml);
+        let config = write_and_load_config(&fs, &make_config_toml(&[]));
+        run(
+            &config,
+            Path::new(CONFIG_PATH),
+            "~/.config",
+            &ImportOptions {
+                all: true,
+                ..Default::default()
+            },
+            false,
+            &fs,
+            &make_engine(),
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/Slack/Preferences"))));
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/Slack/storage/state.json"))));
+        assert!(fs.exists(Path::new(&format!("{DOTFILES}/Slack.conf"))));
+    }
+
+    #[test]
+    fn skips_files_resolving_into_janus_dirs() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        // A deployed symlink whose entry has since been retargeted
+        fs.add_file(format!("{DOTFILES}/.staged/old.conf"), "staged");
+        fs.add_symlink(
+            "/home/test/.config/old.conf",
+            format!("{DOTFILES}/.staged/old.conf"),
+        );
+        // A directory linked into the dotfiles
+        fs.add_file(format!("{DOTFILES}/nvim/init.lua"), "source");
+        fs.add_symlink("/home/test/.config/nvim", format!("{DOTFILES}/nvim"));
+        fs.add_file("/home/test/.config/new.conf", "content");
+        let config = write_and_load_config(&fs, &make_config_toml(&[]));
+        run(
+            &config,
+            Path::new(CONFIG_PATH),
+            "~/.config",
+            &ImportOptions {
+                all: true,
+                ..Default::default()
+            },
+            false,
+            &fs,
+            &make_engine(),
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        let config = Config::load(Path::new(CONFIG_PATH), &fs).unwrap();
+        let srcs: Vec<&str> = config.files.iter().map(|e| e.src.as_str()).collect();
+        assert!(srcs.contains(&"new.conf"), "got: {srcs:?}");
+        assert!(
+            !srcs
+                .iter()
+                .any(|s| s.contains("old.conf") || s.contains("init.lua")),
+            "got: {srcs:?}"
+        );
+    }
+
+    #[test]
+    fn forget_clears_ignored() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file("/home/test/.config/ignored.conf", "content");
+        let state_toml =
+            "[[ignored]]\npath = \"~/.config/ignored.conf\"\nreason = \"unimported\"\n";
+        fs.add_file(format!("{DOTFILES}/.janus_state.toml"), state_toml);
+        let config = write_and_load_config(&fs, &make_config_toml(&[]));
+        run(
+            &config,
+            Path::new(CONFIG_PATH),
+            "~/.config/ignored.conf",
+            &ImportOptions {
+                all: true,
+                forget: true,
+                ..Default::default()
+            },
+            false,
+            &fs,
+            &make_engine(),
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        assert!(fs.exists(Path::new(&format!("{DOTFILES}/ignored.conf"))));
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert!(!state.is_ignored("~/.config/ignored.conf"));
+    }
+
     #[test]
     fn user_ignores() {
         let fs = setup_fs();
@@ -424,12 +988,12 @@ mod tests {
             &config,
             Path::new(CONFIG_PATH),
             "~/.config/new.conf",
-            false,
-            10,
+            &ImportOptions::default(),
             false,
             &fs,
             &make_engine(),
             &prompter,
+            &FakeCommandRunner::new(),
         )
         .unwrap();
         let state = State::load(Path::new(DOTFILES), &fs).unwrap();
@@ -447,12 +1011,12 @@ mod tests {
             &config,
             Path::new(CONFIG_PATH),
             "~/.config/skip.conf",
-            false,
-            10,
+            &ImportOptions::default(),
             false,
             &fs,
             &make_engine(),
             &prompter,
+            &FakeCommandRunner::new(),
         )
         .unwrap();
         // Should not be ignored or imported
@@ -472,17 +1036,172 @@ mod tests {
             &config,
             Path::new(CONFIG_PATH),
             "~/.config/auto.conf",
-            true, // import_all
-            10,
+            &ImportOptions {
+                all: true,
+                ..Default::default()
+            },
             false,
             &fs,
             &make_engine(),
             &prompter,
+            &FakeCommandRunner::new(),
         )
         .unwrap();
         assert!(fs.exists(Path::new(&format!("{DOTFILES}/auto.conf"))));
     }
 
+    fn import_dir_all(fs: &crate::platform::FakeFs, path: &str, follow_links: bool) -> Config {
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        let config = write_and_load_config(fs, &make_config_toml(&[]));
+        run(
+            &config,
+            Path::new(CONFIG_PATH),
+            path,
+            &ImportOptions {
+                all: true,
+                follow_links,
+                ..Default::default()
+            },
+            false,
+            fs,
+            &make_engine(),
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        Config::load(Path::new(CONFIG_PATH), fs).unwrap()
+    }
+
+    fn managed_srcs(config: &Config) -> Vec<&str> {
+        config.files.iter().map(|e| e.src.as_str()).collect()
+    }
+
+    #[test]
+    fn symlink_loops_are_not_followed() {
+        let fs = setup_fs();
+        fs.add_file("/home/test/.config/app/app.conf", "app");
+        fs.add_symlink("/home/test/.config/app/sub/up", "/home/test/.config/app");
+        fs.add_symlink("/home/test/.config/app/home", "/home/test");
+        let config = import_dir_all(&fs, "~/.config/app", true);
+        assert_eq!(managed_srcs(&config), vec!["app/app.conf"]);
+    }
+
+    #[test]
+    fn gitignored_files_are_skipped() {
+        let fs = setup_fs();
+        fs.add_file("/home/test/.config/nvim/.git/HEAD", "ref");
+        fs.add_file("/home/test/.config/nvim/.gitignore", "plugin/\n*.log\n");
+        fs.add_file("/home/test/.config/nvim/init.lua", "init");
+        fs.add_file("/home/test/.config/nvim/plugin/packer_compiled.lua", "gen");
+        fs.add_file("/home/test/.config/nvim/debug.log", "log");
+        let config = import_dir_all(&fs, "~/.config/nvim", true);
+        assert_eq!(
+            managed_srcs(&config),
+            vec!["nvim/.gitignore", "nvim/init.lua"]
+        );
+    }
+
+    #[test]
+    fn byte_sizes_parse_with_units() {
+        assert_eq!("512".parse(), Ok(ByteSize(512)));
+        assert_eq!("64K".parse(), Ok(ByteSize(64 << 10)));
+        assert_eq!("1MiB".parse(), Ok(ByteSize(1 << 20)));
+        assert_eq!("2g".parse(), Ok(ByteSize(2 << 30)));
+        assert!("1T".parse::<ByteSize>().is_err());
+        assert!("M".parse::<ByteSize>().is_err());
+    }
+
+    /// Import `~/.config/app` (a 2-byte and a 10-byte file) under `limits`.
+    fn import_limited(
+        fs: &crate::platform::FakeFs,
+        limits: &Limits,
+        prompter: &FakePrompter,
+    ) -> Result<()> {
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file("/home/test/.config/app/small.conf", "ab");
+        fs.add_file("/home/test/.config/app/big.conf", "0123456789");
+        let config = write_and_load_config(fs, &make_config_toml(&[]));
+        run(
+            &config,
+            Path::new(CONFIG_PATH),
+            "~/.config/app",
+            &ImportOptions {
+                all: true,
+                limits: *limits,
+                ..Default::default()
+            },
+            false,
+            fs,
+            &make_engine(),
+            prompter,
+            &FakeCommandRunner::new(),
+        )
+    }
+
+    #[test]
+    fn files_over_max_file_size_are_skipped() {
+        let fs = setup_fs();
+        let limits = Limits {
+            max_file_size: ByteSize(5),
+            ..Limits::default()
+        };
+        import_limited(&fs, &limits, &FakePrompter::new(vec![])).unwrap();
+        let config = Config::load(Path::new(CONFIG_PATH), &fs).unwrap();
+        assert_eq!(managed_srcs(&config), vec!["app/small.conf"]);
+    }
+
+    #[test]
+    fn large_imports_ask_before_copying() {
+        let fs = setup_fs();
+        let limits = Limits {
+            max_total_size: ByteSize(8),
+            ..Limits::default()
+        };
+        let prompter = FakePrompter::new(vec![]).with_confirms(&[false]);
+        let err = import_limited(&fs, &limits, &prompter).unwrap_err();
+        assert!(err.to_string().contains("2 files, 12 B"), "got: {err}");
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/app/small.conf"))));
+
+        let limits = Limits {
+            max_files: 1,
+            ..Limits::default()
+        };
+        let prompter = FakePrompter::new(vec![]).with_confirms(&[true]);
+        import_limited(&fs, &limits, &prompter).unwrap();
+        assert_eq!(prompter.remaining(), 0);
+        assert!(fs.exists(Path::new(&format!("{DOTFILES}/app/small.conf"))));
+    }
+
+    #[test]
+    fn file_reached_through_links_imported_once() {
+        let fs = setup_fs();
+        fs.add_file("/home/test/.config/app/real/a.conf", "a");
+        fs.add_symlink(
+            "/home/test/.config/app/alias",
+            "/home/test/.config/app/real",
+        );
+        fs.add_symlink(
+            "/home/test/.config/app/b.conf",
+            "/home/test/.config/app/real/a.conf",
+        );
+        let config = import_dir_all(&fs, "~/.config/app", true);
+        assert_eq!(managed_srcs(&config), vec!["app/real/a.conf"]);
+    }
+
+    #[test]
+    fn no_follow_links_skips_symlinks() {
+        let fs = setup_fs();
+        fs.add_file("/home/test/.config/app/plain.conf", "plain");
+        fs.add_file("/srv/shared/linked.conf", "linked");
+        fs.add_symlink(
+            "/home/test/.config/app/linked.conf",
+            "/srv/shared/linked.conf",
+        );
+        fs.add_symlink("/home/test/.config/app/shared", "/srv/shared");
+        let config = import_dir_all(&fs, "~/.config/app", false);
+        assert_eq!(managed_srcs(&config), vec!["app/plain.conf"]);
+    }
+
     #[test]
     fn nonexistent_path_errors() {
         let fs = setup_fs();
@@ -492,12 +1211,12 @@ mod tests {
             &config,
             Path::new(CONFIG_PATH),
             "/nonexistent/file",
-            false,
-            10,
+            &ImportOptions::default(),
             false,
             &fs,
             &make_engine(),
             &prompter,
+            &FakeCommandRunner::new(),
         );
         let msg = format!("{:#}", result.unwrap_err());
         assert!(
@@ -521,12 +1240,12 @@ mod tests {
             &config,
             Path::new(CONFIG_PATH),
             "~/.config/dup.conf",
-            false,
-            10,
+            &ImportOptions::default(),
             false,
             &fs,
             &make_engine(),
             &prompter,
+            &FakeCommandRunner::new(),
         );
         assert!(result.is_err());
         let msg = format!("{:#}", result.unwrap_err());
@@ -544,12 +1263,12 @@ mod tests {
             &config,
             Path::new(CONFIG_PATH),
             "~/.config/dry.conf",
-            false,
-            10,
-            true, // dry_run
+            &ImportOptions::default(),
+            true,
             &fs,
             &make_engine(),
             &prompter,
+            &FakeCommandRunner::new(),
         )
         .unwrap();
         // Nothing should be written
@@ -10,7 +10,7 @@ use tracing::info;
 
 use crate::config::Config;
 use crate::paths::expand_tilde;
-use crate::platform::{Fs, SecretEngine};
+use crate::platform::{CommandRunner, Fs, Prompter, SecretEngine};
 
 /// Scaffold the dotfiles directory, state file, and config file.
 ///
@@ -22,6 +22,8 @@ pub fn run(
     dry_run: bool,
     fs: &impl Fs,
     engine: &impl SecretEngine,
+    prompter: &impl Prompter,
+    runner: &impl CommandRunner,
 ) -> Result<()> {
     let dotfiles_path = expand_tilde(dotfiles_dir, fs);
 
@@ -101,7 +103,9 @@ pub fn run(
     // Load config from source and deploy through the pipeline
     let config = Config::load(&config_src, fs)?;
     info!("Deploying config through pipeline...");
-    crate::ops::apply::run(&config, None, false, dry_run, fs, engine)?;
+    crate::ops::apply::run(
+        &config, None, false, false, dry_run, fs, engine, prompter, runner,
+    )?;
 
     info!("Initialization complete");
     Ok(())
@@ -110,7 +114,7 @@ pub fn run(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::platform::{FakeFs, FakeSecretEngine};
+    use crate::platform::{FakeCommandRunner, FakeFs, FakePrompter, FakeSecretEngine};
     use crate::state::State;
     use std::path::Path;
 
@@ -118,7 +122,15 @@ mod tests {
     fn creates_all_dirs() {
         let fs = FakeFs::new("/home/test");
         let engine = FakeSecretEngine::new();
-        run("~/dotfiles", false, &fs, &engine).unwrap();
+        run(
+            "~/dotfiles",
+            false,
+            &fs,
+            &engine,
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         assert!(fs.is_dir(Path::new("/home/test/dotfiles")));
         assert!(fs.is_dir(Path::new("/home/test/dotfiles/.generated")));
         assert!(fs.is_dir(Path::new("/home/test/dotfiles/.staged")));
@@ -129,7 +141,15 @@ mod tests {
     fn creates_default_files() {
         let fs = FakeFs::new("/home/test");
         let engine = FakeSecretEngine::new();
-        run("~/dotfiles", false, &fs, &engine).unwrap();
+        run(
+            "~/dotfiles",
+            false,
+            &fs,
+            &engine,
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         assert!(fs.exists(Path::new("/home/test/dotfiles/vars.toml")));
         assert!(fs.exists(Path::new("/home/test/dotfiles/.janus_state.toml")));
         // Config source exists in dotfiles dir
@@ -147,12 +167,28 @@ mod tests {
     fn idempotent() {
         let fs = FakeFs::new("/home/test");
         let engine = FakeSecretEngine::new();
-        run("~/dotfiles", false, &fs, &engine).unwrap();
+        run(
+            "~/dotfiles",
+            false,
+            &fs,
+            &engine,
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         let source_content = fs
             .read_to_string(Path::new("/home/test/dotfiles/janus/config.toml"))
             .unwrap();
         // Run again
-        run("~/dotfiles", false, &fs, &engine).unwrap();
+        run(
+            "~/dotfiles",
+            false,
+            &fs,
+            &engine,
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         // Source should be unchanged
         let source_content2 = fs
             .read_to_string(Path::new("/home/test/dotfiles/janus/config.toml"))
@@ -166,7 +202,15 @@ mod tests {
     fn dry_run() {
         let fs = FakeFs::new("/home/test");
         let engine = FakeSecretEngine::new();
-        run("~/dotfiles", true, &fs, &engine).unwrap();
+        run(
+            "~/dotfiles",
+            true,
+            &fs,
+            &engine,
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         assert!(!fs.exists(Path::new("/home/test/dotfiles")));
         assert!(!fs.exists(Path::new("/home/test/dotfiles/janus/config.toml")));
         assert!(!fs.exists(Path::new("/home/test/.config/janus/config.toml")));
@@ -176,7 +220,15 @@ mod tests {
     fn config_deployed_as_symlink() {
         let fs = FakeFs::new("/home/test");
         let engine = FakeSecretEngine::new();
-        run("~/dotfiles", false, &fs, &engine).unwrap();
+        run(
+            "~/dotfiles",
+            false,
+            &fs,
+            &engine,
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         let target = Path::new("/home/test/.config/janus/config.toml");
         assert!(fs.is_symlink(target));
         let link_dest = fs.read_link(target).unwrap();
@@ -191,7 +243,15 @@ mod tests {
     fn config_content_self_referencing() {
         let fs = FakeFs::new("/home/test");
         let engine = FakeSecretEngine::new();
-        run("~/dotfiles", false, &fs, &engine).unwrap();
+        run(
+            "~/dotfiles",
+            false,
+            &fs,
+            &engine,
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         let content = fs
             .read_to_string(Path::new("/home/test/dotfiles/janus/config.toml"))
             .unwrap();
@@ -204,7 +264,15 @@ mod tests {
     fn state_records_deployment() {
         let fs = FakeFs::new("/home/test");
         let engine = FakeSecretEngine::new();
-        run("~/dotfiles", false, &fs, &engine).unwrap();
+        run(
+            "~/dotfiles",
+            false,
+            &fs,
+            &engine,
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         let state = State::load(Path::new("/home/test/dotfiles"), &fs).unwrap();
         assert!(state.is_deployed("janus/config.toml"));
     }
@@ -213,7 +281,15 @@ mod tests {
     fn config_loadable_via_symlink() {
         let fs = FakeFs::new("/home/test");
         let engine = FakeSecretEngine::new();
-        run("~/dotfiles", false, &fs, &engine).unwrap();
+        run(
+            "~/dotfiles",
+            false,
+            &fs,
+            &engine,
+            &FakePrompter::new(vec![]),
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         // Load config via the deployed symlink path (as janus would normally do)
         let config = Config::load(Path::new("/home/test/.config/janus/config.toml"), &fs).unwrap();
         assert_eq!(config.dotfiles_dir, "~/dotfiles");
@@ -0,0 +1,973 @@
+//! `janus migrate`: bring dotfiles over from GNU stow, chezmoi, or yadm.
+//!
+//! Reads the other tool's layout and plans one `[[files]]` entry per managed
+//! file, with targets and dotfiles-dir paths chosen the same way as import:
+//!
+//! - **stow**: every file in every package directory, with the package
+//!   mirroring `~`. `dot-` prefixes (stow's `--dotfiles`) become `.`.
+//! - **chezmoi**: the source directory's attribute prefixes (`dot_`,
+//!   `private_`, `executable_`, ...) are decoded into a target path and file
+//!   mode. `.tmpl` files are converted from Go templates to Tera, and
+//!   `.chezmoidata.toml` becomes a vars file.
+//! - **yadm**: the files tracked by the yadm repo (listed with `git
+//!   ls-files`), read from `~`. `##default` alternates are used as the plain
+//!   file.
+//!
+//! Files are copied into the dotfiles directory and the config is edited
+//! once; nothing is deployed, so the result can be reviewed with `janus
+//! status` and `janus diff` before `janus apply`. Anything that couldn't be
+//! translated (scripts, encrypted files, other alternates, template
+//! functions) is listed at the end. Untranslatable template actions become a
+//! `throw()`, so generate fails on that file instead of rendering it wrong.
+
+use anyhow::{Context, Result, bail};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use tracing::{info, warn};
+
+use super::fileset::edit_config;
+use super::import::determine_dest_path;
+use crate::config::Config;
+use crate::paths::{collapse_tilde, expand_tilde};
+use crate::plan;
+use crate::platform::{CommandRunner, Fs, WalkOptions};
+use crate::shell;
+
+/// Dotfile manager to migrate from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MigrateFrom {
+    Stow,
+    Chezmoi,
+    Yadm,
+}
+
+impl fmt::Display for MigrateFrom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MigrateFrom::Stow => "stow",
+            MigrateFrom::Chezmoi => "chezmoi",
+            MigrateFrom::Yadm => "yadm",
+        })
+    }
+}
+
+/// Name of the vars file written from chezmoi's data file.
+const CHEZMOI_VARS: &str = "chezmoi-vars.toml";
+
+/// One file to bring under janus management.
+#[derive(Debug)]
+pub struct Migrated {
+    /// Where the file lives now.
+    pub origin: PathBuf,
+    /// Relative path within the dotfiles directory.
+    pub src: String,
+    /// Deploy target (with `~`).
+    pub target: String,
+    /// Whether the file is a template (after conversion).
+    pub template: bool,
+    /// Converted content, or `None` to copy `origin` as-is.
+    pub content: Option<String>,
+    /// Mode to set, or `None` to keep `origin`'s.
+    pub mode: Option<u32>,
+}
+
+/// Everything a migration would do.
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub files: Vec<Migrated>,
+    /// Vars file to write: `(name, contents)`.
+    pub vars: Option<(String, String)>,
+    /// Things that weren't translated, for the user to handle by hand.
+    pub notes: Vec<String>,
+}
+
+/// Migrate the dotfiles managed by `from` at `path` into janus.
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    config: &Config,
+    config_path: &Path,
+    from: MigrateFrom,
+    path: &str,
+    dry_run: bool,
+    fs: &impl Fs,
+    runner: &impl CommandRunner,
+) -> Result<()> {
+    let root = expand_tilde(path, fs);
+    if !fs.exists(&root) {
+        bail!("Path does not exist: {}", root.display());
+    }
+    let mut plan = match from {
+        MigrateFrom::Stow => plan_stow(&root, fs)?,
+        MigrateFrom::Chezmoi => plan_chezmoi(&root, fs)?,
+        MigrateFrom::Yadm => plan_yadm(&root, fs, runner)?,
+    };
+
+    // Leave alone anything janus already manages or would overwrite
+    let dotfiles_dir = config.dotfiles_dir(fs);
+    let managed: HashSet<PathBuf> = config
+        .files
+        .iter()
+        .map(|f| expand_tilde(&f.target(), fs))
+        .collect();
+    let mut files = Vec::new();
+    for file in std::mem::take(&mut plan.files) {
+        if managed.contains(&expand_tilde(&file.target, fs)) {
+            plan.notes
+                .push(format!("{}: already managed by janus", file.target));
+        } else if fs.exists(&dotfiles_dir.join(&file.src)) {
+            plan.notes.push(format!(
+                "{}: {} already exists in the dotfiles directory",
+                file.target, file.src
+            ));
+        } else {
+            files.push(file);
+        }
+    }
+    plan.files = files;
+
+    if dry_run {
+        for file in &plan.files {
+            info!("[dry-run] Would migrate {} -> {}", file.target, file.src);
+            plan::action("import", &file.target, &file.src, "migrate");
+        }
+        if let Some((name, _)) = &plan.vars {
+            info!("[dry-run] Would write vars file {name}");
+            plan::action("write", "", name, "migrated vars");
+        }
+    } else {
+        apply(&plan, &dotfiles_dir, config_path, fs)?;
+        info!(
+            "Migrated {} file(s) from {from}. Review with `janus status` and `janus diff`, then run `janus apply`",
+            plan.files.len()
+        );
+    }
+
+    if !plan.notes.is_empty() {
+        warn!("Not migrated automatically:");
+        for note in &plan.notes {
+            warn!("  {note}");
+        }
+    }
+    Ok(())
+}
+
+/// Copy the planned files into the dotfiles directory and add them to the
+/// config in one edit.
+fn apply(plan: &Plan, dotfiles_dir: &Path, config_path: &Path, fs: &impl Fs) -> Result<()> {
+    for file in &plan.files {
+        let dest = dotfiles_dir.join(&file.src);
+        if let Some(parent) = dest.parent() {
+            fs.create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        let copied = match &file.content {
+            Some(content) => fs.write(&dest, content.as_bytes()),
+            None => fs.copy(&file.origin, &dest),
+        };
+        copied.with_context(|| format!("Failed to copy file: {}", file.origin.display()))?;
+        let mode = match file.mode {
+            Some(mode) => mode,
+            None => fs
+                .file_mode(&file.origin)
+                .with_context(|| format!("Failed to read metadata: {}", file.origin.display()))?,
+        };
+        fs.set_file_mode(&dest, mode)
+            .with_context(|| format!("Failed to set permissions: {}", dest.display()))?;
+    }
+    if let Some((name, contents)) = &plan.vars {
+        fs.write(&dotfiles_dir.join(name), contents.as_bytes())
+            .with_context(|| format!("Failed to write vars file: {name}"))?;
+    }
+
+    edit_config(config_path, fs, |doc| {
+        if let Some((name, _)) = &plan.vars {
+            let vars = doc
+                .entry("vars")
+                .or_insert_with(|| toml_edit::value(toml_edit::Array::new()));
+            let Some(vars) = vars.as_array_mut() else {
+                bail!("Config 'vars' field is malformed");
+            };
+            if !vars.iter().any(|v| v.as_str() == Some(name.as_str())) {
+                vars.push(name.as_str());
+            }
+        }
+
+        let files = doc
+            .entry("files")
+            .or_insert_with(|| toml_edit::Item::ArrayOfTables(toml_edit::ArrayOfTables::new()));
+        let Some(array) = files.as_array_of_tables_mut() else {
+            bail!("Config 'files' field is malformed");
+        };
+        for file in &plan.files {
+            let mut table = toml_edit::Table::new();
+            table.insert("src", toml_edit::value(file.src.as_str()));
+            if file.target != format!("~/.config/{}", file.src) {
+                table.insert("target", toml_edit::value(file.target.as_str()));
+            }
+            if !file.template {
+                table.insert("template", toml_edit::value(false));
+            }
+            array.push(table);
+        }
+        Ok(())
+    })
+}
+
+/// Plan a migration for a file that will live at `target_path`.
+fn migrated(origin: PathBuf, target_path: &Path, fs: &impl Fs) -> Result<Migrated> {
+    Ok(Migrated {
+        origin,
+        src: determine_dest_path(target_path, fs)?,
+        target: collapse_tilde(target_path, fs),
+        template: false,
+        content: None,
+        mode: None,
+    })
+}
+
+fn home(fs: &impl Fs) -> Result<PathBuf> {
+    fs.home_dir().context("Could not determine home directory")
+}
+
+/// Every regular file under `dir`, as paths relative to it, sorted. Symlinks
+/// are reported in `notes` rather than followed.
+fn files_under(dir: &Path, notes: &mut Vec<String>, fs: &impl Fs) -> Result<Vec<PathBuf>> {
+    let opts = WalkOptions {
+        min_depth: 1,
+        ..Default::default()
+    };
+    let mut files = Vec::new();
+    for entry in fs.walk_dir(dir, &opts)? {
+        let Ok(relative) = entry.path.strip_prefix(dir) else {
+            continue;
+        };
+        if relative.components().any(|c| c.as_os_str() == ".git") {
+            continue;
+        }
+        if entry.is_symlink {
+            notes.push(format!(
+                "{}: symlinks aren't migrated",
+                entry.path.display()
+            ));
+        } else if entry.is_file {
+            files.push(relative.to_path_buf());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// ---------------------------------------------------------------------------
+// stow
+// ---------------------------------------------------------------------------
+
+/// Each top-level directory of the stow dir is a package mirroring `~`.
+fn plan_stow(stow_dir: &Path, fs: &impl Fs) -> Result<Plan> {
+    let home = home(fs)?;
+    let mut plan = Plan::default();
+    let opts = WalkOptions {
+        min_depth: 1,
+        max_depth: Some(1),
+        ..Default::default()
+    };
+    let mut packages: Vec<PathBuf> = fs
+        .walk_dir(stow_dir, &opts)?
+        .into_iter()
+        .filter(|e| e.is_dir && e.path.file_name().is_some_and(|n| n != ".git"))
+        .map(|e| e.path)
+        .collect();
+    packages.sort();
+
+    for package in packages {
+        for relative in files_under(&package, &mut plan.notes, fs)? {
+            if relative
+                .file_name()
+                .is_some_and(|n| n == ".stow-local-ignore")
+            {
+                plan.notes.push(format!(
+                    "{}: stow ignore lists aren't translated",
+                    package.join(&relative).display()
+                ));
+                continue;
+            }
+            let target: PathBuf = relative
+                .components()
+                .map(|c| {
+                    let name = c.as_os_str().to_string_lossy();
+                    match name.strip_prefix("dot-") {
+                        Some(rest) => format!(".{rest}"),
+                        None => name.into_owned(),
+                    }
+                })
+                .collect();
+            plan.files
+                .push(migrated(package.join(&relative), &home.join(target), fs)?);
+        }
+    }
+    Ok(plan)
+}
+
+// ---------------------------------------------------------------------------
+// chezmoi
+// ---------------------------------------------------------------------------
+
+/// Attributes decoded from a chezmoi source file name.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ChezmoiAttrs {
+    template: bool,
+    private: bool,
+    executable: bool,
+    create: bool,
+}
+
+/// Prefixes with no janus equivalent; files carrying them are skipped.
+const CHEZMOI_UNSUPPORTED: &[&str] = &["encrypted_", "modify_", "run_", "symlink_", "remove_"];
+
+/// Prefixes that only affect how chezmoi applies the file.
+const CHEZMOI_IGNORED: &[&str] = &[
+    "exact_",
+    "readonly_",
+    "empty_",
+    "external_",
+    "once_",
+    "onchange_",
+    "before_",
+    "after_",
+];
+
+/// Decode one path component of a chezmoi source name into its target name.
+///
+/// Errors with the unsupported prefix if the entry can't be migrated.
+fn chezmoi_name(component: &str, is_file: bool) -> Result<(String, ChezmoiAttrs), String> {
+    let mut attrs = ChezmoiAttrs::default();
+    let mut name = component;
+    if is_file && let Some(stripped) = name.strip_suffix(".tmpl") {
+        attrs.template = true;
+        name = stripped;
+    }
+    loop {
+        if let Some(rest) = name.strip_prefix("literal_") {
+            return Ok((rest.to_string(), attrs));
+        }
+        if let Some(prefix) = CHEZMOI_UNSUPPORTED.iter().find(|p| name.starts_with(*p)) {
+            return Err(prefix.trim_end_matches('_').to_string());
+        }
+        if let Some(rest) = name.strip_prefix("private_") {
+            attrs.private = true;
+            name = rest;
+        } else if let Some(rest) = name.strip_prefix("executable_") {
+            attrs.executable = true;
+            name = rest;
+        } else if let Some(rest) = name.strip_prefix("create_") {
+            attrs.create = true;
+            name = rest;
+        } else if let Some(rest) = CHEZMOI_IGNORED.iter().find_map(|p| name.strip_prefix(p)) {
+            name = rest;
+        } else {
+            break;
+        }
+    }
+    let name = match name.strip_prefix("dot_") {
+        Some(rest) => format!(".{rest}"),
+        None => name.to_string(),
+    };
+    Ok((name, attrs))
+}
+
+/// Translate a chezmoi source directory.
+fn plan_chezmoi(source_dir: &Path, fs: &impl Fs) -> Result<Plan> {
+    let home = home(fs)?;
+    let mut plan = Plan::default();
+    let mut data = toml::Table::new();
+
+    'files: for relative in files_under(source_dir, &mut plan.notes, fs)? {
+        let origin = source_dir.join(&relative);
+        let display = relative.display();
+        let components: Vec<String> = relative
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+
+        if components.iter().any(|c| c.starts_with(".chezmoi")) {
+            let is_data = match components.as_slice() {
+                [name] => name == ".chezmoidata.toml",
+                [dir, name] => dir == ".chezmoidata" && name.ends_with(".toml"),
+                _ => false,
+            };
+            if is_data {
+                let contents = fs
+                    .read_to_string(&origin)
+                    .with_context(|| format!("Failed to read {}", origin.display()))?;
+                let table: toml::Table = toml::from_str(&contents)
+                    .with_context(|| format!("Failed to parse {}", origin.display()))?;
+                data.extend(table);
+            } else {
+                plan.notes
+                    .push(format!("{display}: chezmoi config files aren't translated"));
+            }
+            continue;
+        }
+        if components.iter().any(|c| c.starts_with('.')) {
+            // chezmoi ignores other dotfiles in the source dir (.git, README
+            // tooling, ...)
+            continue;
+        }
+
+        let mut target = home.clone();
+        let mut attrs = ChezmoiAttrs::default();
+        for (i, component) in components.iter().enumerate() {
+            let is_file = i == components.len() - 1;
+            match chezmoi_name(component, is_file) {
+                Ok((name, component_attrs)) => {
+                    target.push(name);
+                    if is_file {
+                        attrs = component_attrs;
+                    }
+                }
+                Err(prefix) => {
+                    plan.notes
+                        .push(format!("{display}: `{prefix}_` entries aren't supported"));
+                    continue 'files;
+                }
+            }
+        }
+
+        let mut file = migrated(origin.clone(), &target, fs)?;
+        if attrs.create {
+            plan.notes.push(format!(
+                "{display}: `create_` isn't supported; janus will manage the whole file"
+            ));
+        }
+        file.mode = match (attrs.private, attrs.executable) {
+            (true, true) => Some(0o700),
+            (true, false) => Some(0o600),
+            (false, true) => Some(0o755),
+            (false, false) => None,
+        };
+        if attrs.template {
+            let source = fs
+                .read_to_string(&origin)
+                .with_context(|| format!("Failed to read {}", origin.display()))?;
+            let (converted, issues) = convert_go_template(&source);
+            plan.notes.extend(
+                issues
+                    .into_iter()
+                    .map(|issue| format!("{display}: {issue}")),
+            );
+            file.template = true;
+            file.content = Some(converted);
+        }
+        plan.files.push(file);
+    }
+
+    if !data.is_empty() {
+        let contents = toml::to_string_pretty(&data).context("Failed to serialize chezmoi data")?;
+        plan.vars = Some((CHEZMOI_VARS.to_string(), contents));
+    }
+    Ok(plan)
+}
+
+/// What a translated template action becomes.
+#[derive(Debug, PartialEq)]
+enum Action {
+    /// `{{ ... }}`
+    Expr(String),
+    /// `{% ... %}`
+    Tag(String),
+    /// `{# ... #}`
+    Comment(String),
+}
+
+/// Convert a Go `text/template` (as used by chezmoi) to Tera.
+///
+/// Handles field references, `if`/`else if`/`else`/`end`, `range` over a
+/// list, comments, whitespace trimming, and the `eq`/`ne`/`lt`/`le`/`gt`/
+/// `ge`/`not`/`and`/`or` functions. Anything else is replaced with a
+/// `throw()` and reported in the returned issues.
+pub fn convert_go_template(source: &str) -> (String, Vec<String>) {
+    let mut out = String::new();
+    let mut issues = Vec::new();
+    let mut blocks: Vec<&'static str> = Vec::new();
+    let mut pos = 0;
+
+    while let Some(start) = source[pos..].find("{{").map(|i| pos + i) {
+        out.push_str(&source[pos..start]);
+        let Some(len) = source[start + 2..].find("}}") else {
+            pos = start;
+            break;
+        };
+        let line = source[..start].matches('\n').count() + 1;
+        let mut inner = &source[start + 2..start + 2 + len];
+        pos = start + 2 + len + 2;
+
+        let ltrim = inner.starts_with("- ") || inner == "-";
+        if ltrim {
+            inner = &inner[1..];
+        }
+        let rtrim = inner.ends_with(" -");
+        if rtrim {
+            inner = &inner[..inner.len() - 1];
+        }
+        let (l, r) = (if ltrim { "-" } else { "" }, if rtrim { "-" } else { "" });
+        let body = inner.trim();
+
+        match translate_action(body, &mut blocks) {
+            Ok(Action::Expr(expr)) => out.push_str(&format!("{{{{{l} {expr} {r}}}}}")),
+            Ok(Action::Tag(tag)) => out.push_str(&format!("{{%{l} {tag} {r}%}}")),
+            Ok(Action::Comment(text)) => out.push_str(&format!("{{# {text} #}}")),
+            Err(reason) => {
+                issues.push(format!("line {line}: `{{{{ {body} }}}}`: {reason}"));
+                out.push_str(&format!(
+                    "{{{{ throw(message=\"untranslated chezmoi template on line {line}\") }}}}"
+                ));
+            }
+        }
+    }
+    out.push_str(&source[pos..]);
+
+    if !blocks.is_empty() {
+        issues.push(format!("{} block(s) never closed with `end`", blocks.len()));
+    }
+    (out, issues)
+}
+
+/// Translate the body of one `{{ ... }}` action.
+fn translate_action(body: &str, blocks: &mut Vec<&'static str>) -> Result<Action, String> {
+    if let Some(comment) = body.strip_prefix("/*").and_then(|b| b.strip_suffix("*/")) {
+        return Ok(Action::Comment(comment.trim().to_string()));
+    }
+    let words = split_words(body)?;
+    let in_range = blocks.contains(&"endfor");
+    let first = words.first().map(String::as_str).unwrap_or("");
+    match first {
+        "if" => {
+            let cond = expression(&words[1..], in_range)?;
+            blocks.push("endif");
+            Ok(Action::Tag(format!("if {cond}")))
+        }
+        "else" if words.get(1).map(String::as_str) == Some("if") => {
+            if blocks.last() != Some(&"endif") {
+                return Err("`else if` outside an `if`".to_string());
+            }
+            let cond = expression(&words[2..], in_range)?;
+            Ok(Action::Tag(format!("elif {cond}")))
+        }
+        "else" if words.len() == 1 => {
+            if blocks.last() != Some(&"endif") {
+                return Err("`else` is only supported in an `if`".to_string());
+            }
+            Ok(Action::Tag("else".to_string()))
+        }
+        "end" if words.len() == 1 => match blocks.pop() {
+            Some(tag) => Ok(Action::Tag(tag.to_string())),
+            None => Err("`end` without a matching block".to_string()),
+        },
+        "range" => {
+            if words.iter().any(|w| w == ":=") {
+                return Err("`range` with variables isn't supported".to_string());
+            }
+            let list = expression(&words[1..], in_range)?;
+            blocks.push("endfor");
+            Ok(Action::Tag(format!("for item in {list}")))
+        }
+        "with" | "define" | "template" | "block" => Err(format!("`{first}` isn't supported")),
+        _ => Ok(Action::Expr(expression(&words, in_range)?)),
+    }
+}
+
+/// Split an action body into words, keeping quoted strings whole.
+fn split_words(body: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut chars = body.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut word = String::new();
+        if c == '"' || c == '`' {
+            word.push(chars.next().unwrap_or(c));
+            loop {
+                match chars.next() {
+                    Some('\\') if c == '"' => {
+                        word.push('\\');
+                        word.extend(chars.next());
+                    }
+                    Some(ch) if ch == c => {
+                        word.push(ch);
+                        break;
+                    }
+                    Some(ch) => word.push(ch),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+        } else {
+            while let Some(&ch) = chars.peek() {
+                if ch.is_whitespace() {
+                    break;
+                }
+                word.push(ch);
+                chars.next();
+            }
+        }
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Translate a Go template expression (a value or a function call).
+fn expression(words: &[String], in_range: bool) -> Result<String, String> {
+    let Some(first) = words.first() else {
+        return Err("empty expression".to_string());
+    };
+    if words.iter().any(|w| w == "|") {
+        return Err("pipelines aren't supported".to_string());
+    }
+    let operands = || -> Result<Vec<String>, String> {
+        words[1..].iter().map(|w| operand(w, in_range)).collect()
+    };
+    let comparison = match first.as_str() {
+        "eq" => Some("=="),
+        "ne" => Some("!="),
+        "lt" => Some("<"),
+        "le" => Some("<="),
+        "gt" => Some(">"),
+        "ge" => Some(">="),
+        _ => None,
+    };
+    if let Some(op) = comparison {
+        let args = operands()?;
+        if args.len() != 2 {
+            return Err(format!(
+                "`{first}` with {} arguments isn't supported",
+                args.len()
+            ));
+        }
+        return Ok(format!("{} {op} {}", args[0], args[1]));
+    }
+    match first.as_str() {
+        "not" if words.len() == 2 => Ok(format!("not {}", operand(&words[1], in_range)?)),
+        "and" | "or" if words.len() >= 3 => Ok(operands()?.join(&format!(" {first} "))),
+        _ if words.len() == 1 => operand(first, in_range),
+        other => Err(format!("`{other}` has no Tera equivalent")),
+    }
+}
+
+/// Translate a single value: a field reference or a literal.
+fn operand(word: &str, in_range: bool) -> Result<String, String> {
+    if word.starts_with('"') || word == "true" || word == "false" {
+        return Ok(word.to_string());
+    }
+    if let Some(raw) = word.strip_prefix('`').and_then(|w| w.strip_suffix('`')) {
+        if raw.contains(['"', '\\']) {
+            return Err("raw strings with quotes or backslashes aren't supported".to_string());
+        }
+        return Ok(format!("\"{raw}\""));
+    }
+    if word.parse::<f64>().is_ok() {
+        return Ok(word.to_string());
+    }
+    if word == "." {
+        return if in_range {
+            Ok("item".to_string())
+        } else {
+            Err("`.` outside `range` isn't supported".to_string())
+        };
+    }
+    if word == ".chezmoi" || word.starts_with(".chezmoi.") {
+        return Err("chezmoi's built-in variables have no janus equivalent".to_string());
+    }
+    if let Some(field) = word.strip_prefix('.')
+        && !field.is_empty()
+        && field
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+    {
+        return Ok(if in_range {
+            format!("item.{field}")
+        } else {
+            field.to_string()
+        });
+    }
+    if word.starts_with('$') {
+        return Err("template variables aren't supported".to_string());
+    }
+    Err(format!("`{word}` has no Tera equivalent"))
+}
+
+// ---------------------------------------------------------------------------
+// yadm
+// ---------------------------------------------------------------------------
+
+/// Translate the files tracked by a yadm repo (whose work tree is `~`).
+fn plan_yadm(repo: &Path, fs: &impl Fs, runner: &impl CommandRunner) -> Result<Plan> {
+    let home = home(fs)?;
+    let mut plan = Plan::default();
+    // -z leaves non-ASCII names as they are instead of quoting them
+    let command = format!(
+        "git --git-dir={} ls-files -z",
+        shell::quote(&repo.display().to_string())
+    );
+    let output = runner.run(&command, None).context("Failed to run git")?;
+    if !output.success {
+        bail!(
+            "Failed to list files in yadm repo {}: {}",
+            repo.display(),
+            output.stderr.trim()
+        );
+    }
+
+    let mut tracked: Vec<&str> = output
+        .stdout
+        .split('\0')
+        .filter(|l| !l.is_empty())
+        .collect();
+    tracked.sort();
+    for relative in tracked {
+        if relative.starts_with(".config/yadm/") || relative.starts_with(".local/share/yadm/") {
+            plan.notes
+                .push(format!("~/{relative}: yadm's own files aren't translated"));
+            continue;
+        }
+        let target = match relative.split_once("##") {
+            None => relative,
+            Some((base, "default")) => base,
+            Some(_) => {
+                plan.notes.push(format!(
+                    "~/{relative}: yadm alternates and templates aren't translated"
+                ));
+                continue;
+            }
+        };
+        let origin = home.join(relative);
+        if !fs.exists(&origin) {
+            plan.notes
+                .push(format!("~/{relative}: tracked but missing from ~"));
+            continue;
+        }
+        plan.files.push(migrated(origin, &home.join(target), fs)?);
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{CommandOutput, FakeCommandRunner, FakeFs};
+    use crate::test_helpers::*;
+
+    fn load(fs: &FakeFs) -> Config {
+        write_and_load_config(fs, &make_config_toml(&[]))
+    }
+
+    fn reload(fs: &FakeFs) -> Config {
+        Config::load(Path::new(CONFIG_PATH), fs).unwrap()
+    }
+
+    #[test]
+    fn stow_packages_mirror_home() {
+        let fs = setup_fs();
+        fs.add_file("/home/test/stow/bash/.bashrc", "alias ll='ls -l'");
+        fs.add_file("/home/test/stow/bash/dot-profile", "export A=1");
+        fs.add_file("/home/test/stow/hypr/.config/hypr/hypr.conf", "{{ raw }}");
+        fs.add_file("/home/test/stow/hypr/.stow-local-ignore", "README");
+        let config = load(&fs);
+
+        run(
+            &config,
+            Path::new(CONFIG_PATH),
+            MigrateFrom::Stow,
+            "~/stow",
+            false,
+            &fs,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+
+        let config = reload(&fs);
+        let entries: Vec<(String, String, bool)> = config
+            .files
+            .iter()
+            .map(|f| (f.src.clone(), f.target(), f.template))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("bashrc".to_string(), "~/.bashrc".to_string(), false),
+                ("profile".to_string(), "~/.profile".to_string(), false),
+                (
+                    "hypr/hypr.conf".to_string(),
+                    "~/.config/hypr/hypr.conf".to_string(),
+                    false
+                ),
+            ]
+        );
+        assert_eq!(
+            fs.read_to_string(Path::new(&format!("{DOTFILES}/hypr/hypr.conf")))
+                .unwrap(),
+            "{{ raw }}"
+        );
+    }
+
+    #[test]
+    fn chezmoi_names_decoded() {
+        assert_eq!(
+            chezmoi_name("private_executable_dot_script", true).unwrap(),
+            (
+                ".script".to_string(),
+                ChezmoiAttrs {
+                    private: true,
+                    executable: true,
+                    ..Default::default()
+                }
+            )
+        );
+        assert_eq!(chezmoi_name("dot_config", false).unwrap().0, ".config");
+        assert_eq!(chezmoi_name("literal_dot_x", true).unwrap().0, "dot_x");
+        assert!(chezmoi_name("dot_gitconfig.tmpl", true).unwrap().1.template);
+        assert_eq!(
+            chezmoi_name("run_once_install.sh", true).unwrap_err(),
+            "run"
+        );
+    }
+
+    #[test]
+    fn chezmoi_source_dir_migrated() {
+        let fs = setup_fs();
+        let root = "/home/test/.local/share/chezmoi";
+        fs.add_file(
+            format!("{root}/dot_gitconfig.tmpl"),
+            "[user]\n  email = {{ .email }}\n",
+        );
+        fs.add_file(format!("{root}/private_dot_ssh/private_config"), "Host *");
+        fs.add_file(format!("{root}/.chezmoidata.toml"), "email = \"me@x.org\"");
+        fs.add_file(format!("{root}/.chezmoiignore"), "README.md");
+        fs.add_file(format!("{root}/encrypted_dot_netrc.age"), "...");
+        let config = load(&fs);
+
+        let plan = plan_chezmoi(Path::new(root), &fs).unwrap();
+        assert_eq!(plan.notes.len(), 2, "{:?}", plan.notes);
+
+        run(
+            &config,
+            Path::new(CONFIG_PATH),
+            MigrateFrom::Chezmoi,
+            root,
+            false,
+            &fs,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+
+        let config = reload(&fs);
+        assert!(config.vars.contains(&CHEZMOI_VARS.to_string()));
+        let gitconfig = config.files.iter().find(|f| f.src == "gitconfig").unwrap();
+        assert!(gitconfig.template);
+        assert_eq!(gitconfig.target(), "~/.gitconfig");
+        assert_eq!(
+            fs.read_to_string(Path::new(&format!("{DOTFILES}/gitconfig")))
+                .unwrap(),
+            "[user]\n  email = {{ email }}\n"
+        );
+        let ssh = config.files.iter().find(|f| f.src == "ssh/config").unwrap();
+        assert!(!ssh.template);
+        assert_eq!(
+            fs.file_mode(Path::new(&format!("{DOTFILES}/ssh/config")))
+                .unwrap(),
+            0o600
+        );
+        assert_eq!(
+            fs.read_to_string(Path::new(&format!("{DOTFILES}/{CHEZMOI_VARS}")))
+                .unwrap(),
+            "email = \"me@x.org\"\n"
+        );
+    }
+
+    #[test]
+    fn go_template_control_flow() {
+        let source = "{{- if eq .os \"linux\" }}a{{ else if not .b }}b{{ else }}c{{ end -}}\n\
+                      {{ range .hosts }}{{ .name }}={{ $.x }}{{ end }}{{/* note */}}";
+        let (converted, issues) = convert_go_template(source);
+        assert_eq!(
+            converted,
+            "{%- if os == \"linux\" %}a{% elif not b %}b{% else %}c{% endif -%}\n\
+             {% for item in hosts %}{{ item.name }}=\
+             {{ throw(message=\"untranslated chezmoi template on line 2\") }}\
+             {% endfor %}{# note #}"
+        );
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].contains("line 2"), "got: {issues:?}");
+    }
+
+    #[test]
+    fn go_template_functions_reported() {
+        let (converted, issues) = convert_go_template("x={{ .chezmoi.hostname | quote }}");
+        assert!(converted.starts_with("x={{ throw("), "got: {converted}");
+        assert_eq!(issues.len(), 1);
+        let (_, issues) = convert_go_template("{{ if .a }}unclosed");
+        assert!(issues[0].contains("never closed"), "got: {issues:?}");
+    }
+
+    #[test]
+    fn yadm_tracked_files() {
+        let fs = setup_fs();
+        fs.add_file("/home/test/.zshrc", "zsh");
+        fs.add_file("/home/test/.config/kitty/kitty.conf##default", "kitty");
+        fs.add_file("/home/test/.config/kitty/kitty.conf##os.Darwin", "mac");
+        fs.add_file("/home/test/.config/caf\u{e9}.conf", "caf\u{e9}");
+        let mut runner = FakeCommandRunner::new();
+        runner.add_response(
+            "git --git-dir=/home/test/.local/share/yadm/repo.git ls-files -z",
+            CommandOutput {
+                success: true,
+                stdout: ".zshrc\0.config/kitty/kitty.conf##default\0.config/kitty/kitty.conf##os.Darwin\0\
+                         .config/caf\u{e9}.conf\0"
+                    .to_string(),
+                ..Default::default()
+            },
+        );
+        fs.add_dir("/home/test/.local/share/yadm/repo.git");
+
+        let plan = plan_yadm(
+            Path::new("/home/test/.local/share/yadm/repo.git"),
+            &fs,
+            &runner,
+        )
+        .unwrap();
+        let targets: Vec<&str> = plan.files.iter().map(|f| f.target.as_str()).collect();
+        assert_eq!(
+            targets,
+            vec![
+                "~/.config/caf\u{e9}.conf",
+                "~/.config/kitty/kitty.conf",
+                "~/.zshrc"
+            ]
+        );
+        assert_eq!(plan.files[1].src, "kitty/kitty.conf");
+        assert_eq!(plan.notes.len(), 1);
+    }
+
+    #[test]
+    fn dry_run_changes_nothing() {
+        let fs = setup_fs();
+        fs.add_file("/home/test/stow/bash/.bashrc", "x");
+        let config = load(&fs);
+        let before = fs.read_to_string(Path::new(CONFIG_PATH)).unwrap();
+        run(
+            &config,
+            Path::new(CONFIG_PATH),
+            MigrateFrom::Stow,
+            "~/stow",
+            true,
+            &fs,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        assert_eq!(fs.read_to_string(Path::new(CONFIG_PATH)).unwrap(), before);
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/bashrc"))));
+    }
+}
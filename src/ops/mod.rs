@@ -2,24 +2,160 @@
 //!
 //! Each submodule corresponds to a CLI subcommand and exposes a `run()` function.
 //! The forward pipeline is `generate` → `stage` → `deploy` (or `apply` as a
-//! compound shortcut). Reverse operations are `undeploy`, `unimport`, and `clean`.
+//! compound shortcut); `import` and `new` bring files under management.
+//! Reverse operations are `undeploy`, `unimport`, and `clean`; `undo`
+//! reverts whichever command ran last, and `repair` fixes drift. `which`
+//! maps a path back to the entry that manages it, and `stats` summarizes
+//! the whole repo. `test` checks templates against fixture vars, and
+//! `state` rebuilds lost state from the symlinks on disk. `archive` keeps
+//! sources unimported with `--archive` for `restore-archived`, and
+//! `shell_init` prints shell functions for the repo. `export` renders
+//! files, optionally redacted, for sharing.
 
 pub mod apply;
+pub mod archive;
+pub mod backups;
+pub mod cat;
+pub mod check;
 pub mod clean;
+pub mod config;
 pub mod deploy;
 pub mod diff;
+pub mod enable;
+pub mod explain;
+pub mod export;
+pub mod fileset;
 pub mod generate;
 pub mod import;
 pub mod init;
+pub mod migrate;
+pub mod new;
+pub mod repair;
+pub mod secrets;
+pub mod shell_init;
 pub mod stage;
+pub mod state;
+pub mod stats;
 pub mod status;
 pub mod sync;
+pub mod test;
+pub mod trash;
 pub mod undeploy;
+pub mod undo;
 pub mod unimport;
+pub mod vars;
+pub mod which;
 
+use anyhow::{Result, bail};
+use std::fmt;
 use std::path::Path;
+use tracing::{debug, warn};
 
-use crate::platform::Fs;
+use crate::config::Config;
+use crate::platform::{Fs, Prompter};
+use crate::state::{State, content_hash, unix_now};
+
+/// Per-file failures collected by an error-collecting op (generate, stage,
+/// sync, clean).
+///
+/// Kept structured rather than flattened into a message so callers such as
+/// the `[on_failure]` hook can report exactly which files failed.
+#[derive(Debug)]
+pub struct FileErrors {
+    /// Verb used in the summary line (e.g. `"generate"`).
+    pub action: String,
+    /// `(file, error)` pairs in processing order; errors are rendered with
+    /// their full context chain.
+    pub errors: Vec<(String, String)>,
+}
+
+impl FileErrors {
+    pub fn new(action: &str, errors: &[(String, anyhow::Error)]) -> Self {
+        Self {
+            action: action.to_string(),
+            errors: errors
+                .iter()
+                .map(|(file, e)| (file.clone(), format!("{e:#}")))
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for FileErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Failed to {} {} file(s):",
+            self.action,
+            self.errors.len()
+        )?;
+        for (file, e) in &self.errors {
+            write!(f, "\n  {file}: {e}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for FileErrors {}
+
+/// What an error-collecting op did, so a follow-up step can take only the
+/// files that succeeded.
+pub(crate) struct Outcome {
+    /// Files processed cleanly, in processing order.
+    pub succeeded: Vec<String>,
+    /// The files that failed, if any did.
+    pub failure: Option<FileErrors>,
+}
+
+impl Outcome {
+    /// The op's result on its own: an error if any file failed.
+    pub fn into_result(self) -> Result<()> {
+        match self.failure {
+            Some(failure) => Err(failure.into()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Resolve file selection from explicit files, `--all`, or `--filesets`.
+///
+/// Exactly one source must be provided. Returns `None` for "all files",
+/// or `Some(patterns)` for explicit files or resolved filesets.
+pub fn resolve_file_selection(
+    files: Vec<String>,
+    all: bool,
+    filesets: Vec<String>,
+    config: &Config,
+) -> Result<Option<Vec<String>>> {
+    let sources = [!files.is_empty(), all, !filesets.is_empty()]
+        .iter()
+        .filter(|&&b| b)
+        .count();
+
+    if sources > 1 {
+        bail!("Cannot combine explicit files, --all, and --filesets");
+    }
+
+    if all {
+        return Ok(None);
+    }
+    if !filesets.is_empty() {
+        return Ok(Some(config.resolve_filesets(&filesets)?));
+    }
+    if !files.is_empty() {
+        return Ok(Some(files));
+    }
+
+    // No explicit source — fall back to default_targets from config
+    match config.default_targets.as_deref() {
+        Some("all") => Ok(None),
+        Some(filesets) => {
+            let names: Vec<String> = filesets.split(',').map(|s| s.trim().to_string()).collect();
+            Ok(Some(config.resolve_filesets(&names)?))
+        }
+        None => bail!("Specify files to process, --all, or --filesets"),
+    }
+}
 
 /// Check if `target` is a symlink pointing to `expected_staged`.
 pub(crate) fn is_janus_symlink(target: &Path, expected_staged: &Path, fs: &impl Fs) -> bool {
@@ -31,3 +167,318 @@ pub(crate) fn is_janus_symlink(target: &Path, expected_staged: &Path, fs: &impl
         Err(_) => false,
     }
 }
+
+/// Whether a deployed entry's target is now a regular file rather than the
+/// symlink deploy made, as when an app saves by writing a temp file and
+/// renaming it over the target. Its edits then never reach the pipeline.
+pub(crate) fn target_replaced(state: &State, key: &str, target: &Path, fs: &impl Fs) -> bool {
+    state.is_deployed(key) && !fs.is_symlink(target) && fs.is_file(target)
+}
+
+/// Whether a deployed direct file's source changed since it was deployed.
+///
+/// `false` when no deploy-time hash was recorded (e.g. deployed by an older
+/// janus) or the source can't be read.
+pub(crate) fn direct_source_changed(
+    state: &State,
+    src: &str,
+    source_path: &Path,
+    fs: &impl Fs,
+) -> bool {
+    let Some(recorded) = state.source_hash(src) else {
+        return false;
+    };
+    match fs.read(source_path) {
+        Ok(bytes) => content_hash(&bytes) != recorded,
+        Err(_) => false,
+    }
+}
+
+/// Give the file at `to` the ACL and `user.*` extended attributes of the one
+/// at `from`.
+///
+/// Security labels (`security.selinux` and the like) aren't copied: a
+/// target such as `~/.ssh/config` needs the context policy gives its own
+/// path, not the one of a file in the dotfiles directory. Failures are only
+/// logged: setting some attributes needs privileges or filesystem support
+/// the user may not have, and the copy is still usable.
+pub(crate) fn preserve_xattrs(from: &Path, to: &Path, fs: &impl Fs) {
+    let result = fs.xattrs(from).and_then(|mut attrs| {
+        attrs
+            .retain(|(name, _)| name.starts_with("user.") || name.starts_with("system.posix_acl_"));
+        if attrs.is_empty() {
+            return Ok(());
+        }
+        fs.set_xattrs(to, &attrs)
+    });
+    if let Err(e) = result {
+        warn!(
+            "Failed to preserve extended attributes of {} on {}: {e:#}",
+            from.display(),
+            to.display()
+        );
+    }
+}
+
+/// Stamp each of `srcs` in the state file with `record` (e.g.
+/// [`State::record_generate`]) at the current time.
+///
+/// Failures are only logged: the timestamps feed `janus status --stale` and
+/// aren't worth failing a command over.
+pub(crate) fn record_activity(
+    config: &Config,
+    srcs: &[String],
+    record: fn(&mut State, &str, u64),
+    fs: &impl Fs,
+) {
+    if srcs.is_empty() {
+        return;
+    }
+    let result = State::load(&config.state_dir(fs), fs).and_then(|mut state| {
+        let now = unix_now();
+        for src in srcs {
+            record(&mut state, src, now);
+        }
+        state.save(fs)
+    });
+    if let Err(e) = result {
+        warn!("Failed to record pipeline timestamps: {e:#}");
+    }
+}
+
+/// Safe-mode gate for mutating commands: when `files` is longer than the
+/// config's `confirm_threshold`, list them and ask before continuing.
+///
+/// Skipped with `yes` (`--yes`) or `dry_run`, and when no threshold is set.
+/// Defaults to aborting, so a non-interactive run fails rather than
+/// proceeding.
+pub fn confirm_bulk(
+    config: &Config,
+    action: &str,
+    files: &[String],
+    yes: bool,
+    dry_run: bool,
+    prompter: &impl Prompter,
+) -> Result<()> {
+    let Some(threshold) = config.confirm_threshold else {
+        return Ok(());
+    };
+    if files.len() <= threshold || dry_run {
+        return Ok(());
+    }
+    if yes {
+        debug!(
+            "--yes: skipping confirmation to {action} {} files",
+            files.len()
+        );
+        return Ok(());
+    }
+
+    println!("{action} will affect {} files:", files.len());
+    for file in files {
+        println!("  {file}");
+    }
+    if !prompter.confirm(
+        &format!("Continue to {action} {} files?", files.len()),
+        false,
+    )? {
+        bail!(
+            "Aborted: {action} would affect {} files (confirm_threshold = {threshold}); pass --yes to skip this check",
+            files.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::FakePrompter;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn file_errors_display() {
+        let errors = vec![
+            ("a.conf".to_string(), anyhow::anyhow!("boom")),
+            (
+                "b.conf".to_string(),
+                anyhow::anyhow!("inner").context("outer"),
+            ),
+        ];
+        let msg = FileErrors::new("generate", &errors).to_string();
+        assert_eq!(
+            msg,
+            "Failed to generate 2 file(s):\n  a.conf: boom\n  b.conf: outer: inner"
+        );
+    }
+
+    #[test]
+    fn file_errors_downcast_from_anyhow() {
+        let errors = vec![("a.conf".to_string(), anyhow::anyhow!("boom"))];
+        let err: anyhow::Error = FileErrors::new("stage", &errors).into();
+        let file_errors = err.downcast_ref::<FileErrors>().unwrap();
+        assert_eq!(file_errors.errors[0].0, "a.conf");
+    }
+
+    fn threshold_config(fs: &crate::platform::FakeFs, threshold: &str) -> Config {
+        write_and_load_config(fs, &format!("dotfiles_dir = \"{DOTFILES}\"\n{threshold}"))
+    }
+
+    fn files(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("f{i}.conf")).collect()
+    }
+
+    #[test]
+    fn confirm_bulk_under_threshold_skips_prompt() {
+        let fs = setup_fs();
+        let config = threshold_config(&fs, "confirm_threshold = 2");
+        let prompter = FakePrompter::new(vec![]);
+        confirm_bulk(&config, "deploy", &files(2), false, false, &prompter).unwrap();
+    }
+
+    #[test]
+    fn confirm_bulk_without_threshold_skips_prompt() {
+        let fs = setup_fs();
+        let config = threshold_config(&fs, "");
+        let prompter = FakePrompter::new(vec![]);
+        confirm_bulk(&config, "deploy", &files(100), false, false, &prompter).unwrap();
+    }
+
+    #[test]
+    fn confirm_bulk_over_threshold_prompts() {
+        let fs = setup_fs();
+        let config = threshold_config(&fs, "confirm_threshold = 2");
+        let prompter = FakePrompter::new(vec![]).with_confirms(&[true]);
+        confirm_bulk(&config, "deploy", &files(3), false, false, &prompter).unwrap();
+        assert_eq!(prompter.remaining(), 0);
+
+        let prompter = FakePrompter::new(vec![]).with_confirms(&[false]);
+        let result = confirm_bulk(&config, "deploy", &files(3), false, false, &prompter);
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(msg.contains("deploy would affect 3 files"), "got: {msg}");
+    }
+
+    #[test]
+    fn confirm_bulk_yes_or_dry_run_skips_prompt() {
+        let fs = setup_fs();
+        let config = threshold_config(&fs, "confirm_threshold = 2");
+        let prompter = FakePrompter::new(vec![]);
+        confirm_bulk(&config, "deploy", &files(3), true, false, &prompter).unwrap();
+        confirm_bulk(&config, "deploy", &files(3), false, true, &prompter).unwrap();
+    }
+
+    fn test_config() -> Config {
+        let fs = setup_fs();
+        let toml = format!(
+            r#"
+dotfiles_dir = "{DOTFILES}"
+
+[[files]]
+src = "a.conf"
+
+[[files]]
+src = "hypr/hypr.conf"
+
+[filesets.desktop]
+patterns = ["hypr/*"]
+"#
+        );
+        write_and_load_config(&fs, &toml)
+    }
+
+    #[test]
+    fn all_returns_none() {
+        let config = test_config();
+        let result = resolve_file_selection(vec![], true, vec![], &config).unwrap();
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn explicit_files() {
+        let config = test_config();
+        let result =
+            resolve_file_selection(vec!["a.conf".to_string()], false, vec![], &config).unwrap();
+        assert_eq!(result, Some(vec!["a.conf".to_string()]));
+    }
+
+    #[test]
+    fn filesets_resolved() {
+        let config = test_config();
+        let result =
+            resolve_file_selection(vec![], false, vec!["desktop".to_string()], &config).unwrap();
+        assert_eq!(result, Some(vec!["hypr/*".to_string()]));
+    }
+
+    #[test]
+    fn no_source_errors() {
+        let config = test_config();
+        let result = resolve_file_selection(vec![], false, vec![], &config);
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(msg.contains("Specify"), "got: {msg}");
+    }
+
+    #[test]
+    fn multiple_sources_errors() {
+        let config = test_config();
+        let result = resolve_file_selection(vec!["a.conf".to_string()], true, vec![], &config);
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(msg.contains("Cannot combine"), "got: {msg}");
+    }
+
+    fn test_config_with_default_targets(default_targets: &str) -> Config {
+        let fs = setup_fs();
+        let toml = format!(
+            r#"
+dotfiles_dir = "{DOTFILES}"
+default_targets = "{default_targets}"
+
+[[files]]
+src = "a.conf"
+
+[[files]]
+src = "hypr/hypr.conf"
+
+[filesets.desktop]
+patterns = ["hypr/*"]
+"#
+        );
+        write_and_load_config(&fs, &toml)
+    }
+
+    #[test]
+    fn default_targets_all() {
+        let config = test_config_with_default_targets("all");
+        let result = resolve_file_selection(vec![], false, vec![], &config).unwrap();
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn default_targets_fileset() {
+        let config = test_config_with_default_targets("desktop");
+        let result = resolve_file_selection(vec![], false, vec![], &config).unwrap();
+        assert_eq!(result, Some(vec!["hypr/*".to_string()]));
+    }
+
+    #[test]
+    fn default_targets_overridden_by_explicit() {
+        let config = test_config_with_default_targets("all");
+        let result =
+            resolve_file_selection(vec!["a.conf".to_string()], false, vec![], &config).unwrap();
+        assert_eq!(result, Some(vec!["a.conf".to_string()]));
+    }
+
+    #[test]
+    fn default_targets_overridden_by_all() {
+        let config = test_config_with_default_targets("desktop");
+        let result = resolve_file_selection(vec![], true, vec![], &config).unwrap();
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn default_targets_overridden_by_filesets() {
+        let config = test_config_with_default_targets("all");
+        let result =
+            resolve_file_selection(vec![], false, vec!["desktop".to_string()], &config).unwrap();
+        assert_eq!(result, Some(vec!["hypr/*".to_string()]));
+    }
+}
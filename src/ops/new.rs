@@ -0,0 +1,174 @@
+//! `janus new`: start managing a config that doesn't exist yet.
+//!
+//! The forward counterpart to import: instead of copying an existing file
+//! in, creates the source file in the dotfiles directory (empty, or with the
+//! given contents) and appends its `[[files]]` entry. Nothing is generated
+//! or deployed; fill the file in, then run `janus apply <src>`.
+
+use anyhow::{Result, bail};
+use std::path::{Component, Path, PathBuf};
+use tracing::info;
+
+use crate::config::Config;
+use crate::paths::expand_tilde;
+use crate::plan;
+use crate::platform::Fs;
+
+/// Create `src` in the dotfiles directory with `contents` and add it to the
+/// config, deploying to `target` (default `~/.config/{src}`).
+///
+/// Refuses to overwrite an existing source file, to manage the same `src`
+/// twice, or to reuse another entry's target. Returns the new source path.
+pub fn run(
+    config: &Config,
+    config_path: &Path,
+    src: &str,
+    target: Option<&str>,
+    contents: &str,
+    dry_run: bool,
+    fs: &impl Fs,
+) -> Result<PathBuf> {
+    let relative = Path::new(src);
+    if src.is_empty()
+        || relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        bail!("{src}: must be a relative path inside the dotfiles directory");
+    }
+    if config.files.iter().any(|e| e.src == src) {
+        bail!("{src} is already managed");
+    }
+
+    let source_path = config.dotfiles_dir(fs).join(src);
+    if fs.exists(&source_path) || fs.is_symlink(&source_path) {
+        bail!(
+            "{} already exists; add it to the config instead",
+            source_path.display()
+        );
+    }
+
+    let target = target
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("~/.config/{src}"));
+    let expanded = expand_tilde(&target, fs);
+    if let Some(other) = config
+        .files
+        .iter()
+        .find(|e| expand_tilde(&e.target(), fs) == expanded)
+    {
+        bail!("{target} is already the target of {}", other.src);
+    }
+
+    if dry_run {
+        info!("[dry-run] Would create {src} deploying to {target}");
+        plan::action("create", src, &source_path.to_string_lossy(), "new");
+        return Ok(source_path);
+    }
+
+    if let Some(parent) = source_path.parent() {
+        fs.create_dir_all(parent)?;
+    }
+    fs.write(&source_path, contents.as_bytes())?;
+    super::import::append_config_entry(config_path, src, &target, fs)?;
+
+    info!("Created {src}; run `janus apply {src}` to deploy it to {target}");
+    Ok(source_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    fn setup() -> (crate::platform::FakeFs, Config) {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.conf"), "a");
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        (fs, config)
+    }
+
+    #[test]
+    fn creates_source_and_entry() {
+        let (fs, config) = setup();
+        let path = run(
+            &config,
+            Path::new(CONFIG_PATH),
+            "foot/foot.ini",
+            None,
+            "font=mono\n",
+            false,
+            &fs,
+        )
+        .unwrap();
+        assert_eq!(path, Path::new(DOTFILES).join("foot/foot.ini"));
+        assert_eq!(fs.read_to_string(&path).unwrap(), "font=mono\n");
+
+        let config = Config::load(Path::new(CONFIG_PATH), &fs).unwrap();
+        let entry = config
+            .files
+            .iter()
+            .find(|e| e.src == "foot/foot.ini")
+            .unwrap();
+        assert_eq!(entry.target(), "~/.config/foot/foot.ini");
+    }
+
+    #[test]
+    fn custom_target() {
+        let (fs, config) = setup();
+        run(
+            &config,
+            Path::new(CONFIG_PATH),
+            "inputrc",
+            Some("~/.inputrc"),
+            "",
+            false,
+            &fs,
+        )
+        .unwrap();
+        let config = Config::load(Path::new(CONFIG_PATH), &fs).unwrap();
+        let entry = config.files.iter().find(|e| e.src == "inputrc").unwrap();
+        assert_eq!(entry.target(), "~/.inputrc");
+    }
+
+    #[test]
+    fn dry_run_changes_nothing() {
+        let (fs, config) = setup();
+        let before = fs.read_to_string(Path::new(CONFIG_PATH)).unwrap();
+        let path = run(
+            &config,
+            Path::new(CONFIG_PATH),
+            "b.conf",
+            None,
+            "",
+            true,
+            &fs,
+        )
+        .unwrap();
+        assert!(!fs.exists(&path));
+        assert_eq!(fs.read_to_string(Path::new(CONFIG_PATH)).unwrap(), before);
+    }
+
+    #[test]
+    fn refuses_existing_and_escaping_paths() {
+        let (fs, config) = setup();
+        fs.add_file(format!("{DOTFILES}/loose.conf"), "x");
+        for (src, target, expected) in [
+            ("a.conf", None, "already managed"),
+            ("loose.conf", None, "already exists"),
+            ("../outside", None, "relative path"),
+            ("/etc/foo", None, "relative path"),
+            (
+                "b.conf",
+                Some("~/.config/a.conf"),
+                "already the target of a.conf",
+            ),
+        ] {
+            let msg = format!(
+                "{:#}",
+                run(&config, Path::new(CONFIG_PATH), src, target, "", false, &fs).unwrap_err()
+            );
+            assert!(msg.contains(expected), "{src}: got {msg}");
+        }
+    }
+}
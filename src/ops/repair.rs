@@ -0,0 +1,126 @@
+//! `janus repair`: put drifted pipeline files back the way janus left them.
+//!
+//! `--modes` restores the permission bits each file was staged with (as
+//! recorded in `.janus_state.toml`) on its generated and staged copies. A
+//! deployed target is a symlink to the staged copy, so fixing staged fixes
+//! the target too. Files staged before modes were recorded are skipped
+//! until their next `janus stage`.
+
+use anyhow::{Context, Result, bail};
+use tracing::{debug, info};
+
+use crate::config::Config;
+use crate::plan;
+use crate::platform::Fs;
+use crate::state::State;
+
+/// Repair the given files (or all files). Only `modes` is supported so far,
+/// and at least one kind of repair must be requested.
+pub fn run(
+    config: &Config,
+    files: Option<&[String]>,
+    modes: bool,
+    dry_run: bool,
+    fs: &impl Fs,
+) -> Result<()> {
+    if !modes {
+        bail!("Nothing to repair; pass --modes");
+    }
+
+    let entries = config.filter_files(files);
+    if entries.is_empty() {
+        config.bail_unmatched(files)?;
+        info!("No files to repair");
+        return Ok(());
+    }
+
+    let state = State::load(&config.state_dir(fs), fs)?;
+    let generated_dir = config.generated_dir(fs);
+    let staged_dir = config.staged_dir(fs);
+    let mut repaired = 0;
+
+    for entry in &entries {
+        let Some(expected) = state.activity(&entry.src).and_then(|a| a.mode) else {
+            debug!("No recorded mode for {}", entry.src);
+            continue;
+        };
+        for path in [generated_dir.join(&entry.src), staged_dir.join(&entry.src)] {
+            let Ok(mode) = fs.file_mode(&path) else {
+                continue;
+            };
+            if mode & 0o7777 == expected {
+                continue;
+            }
+            if dry_run {
+                info!(
+                    "[dry-run] Would restore mode {expected:04o} on {}",
+                    path.display()
+                );
+                plan::action(
+                    "set-mode",
+                    &entry.src,
+                    &path.to_string_lossy(),
+                    &format!("mode is {:04o}, expected {expected:04o}", mode & 0o7777),
+                );
+            } else {
+                fs.set_file_mode(&path, expected)
+                    .with_context(|| format!("Failed to set permissions: {}", path.display()))?;
+                info!("Restored mode {expected:04o} on {}", path.display());
+            }
+            repaired += 1;
+        }
+    }
+
+    if repaired == 0 {
+        info!("All modes match");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+    use std::path::Path;
+
+    fn staged_with_mode(fs: &crate::platform::FakeFs) -> Config {
+        fs.add_file(format!("{DOTFILES}/ssh_config"), "Host *");
+        fs.add_file_with_mode(format!("{DOTFILES}/.generated/ssh_config"), "Host *", 0o600);
+        let config = write_and_load_config(fs, &make_config_toml(&[("ssh_config", None)]));
+        crate::ops::stage::run(&config, None, false, fs).unwrap();
+        config
+    }
+
+    #[test]
+    fn restores_staged_and_generated_modes() {
+        let fs = setup_fs();
+        let config = staged_with_mode(&fs);
+        let generated = Path::new(DOTFILES).join(".generated/ssh_config");
+        let staged = Path::new(DOTFILES).join(".staged/ssh_config");
+        fs.set_file_mode(&generated, 0o644).unwrap();
+        fs.set_file_mode(&staged, 0o666).unwrap();
+
+        run(&config, None, true, false, &fs).unwrap();
+        assert_eq!(fs.file_mode(&generated).unwrap(), 0o600);
+        assert_eq!(fs.file_mode(&staged).unwrap(), 0o600);
+    }
+
+    #[test]
+    fn dry_run_changes_nothing() {
+        let fs = setup_fs();
+        let config = staged_with_mode(&fs);
+        let staged = Path::new(DOTFILES).join(".staged/ssh_config");
+        fs.set_file_mode(&staged, 0o644).unwrap();
+
+        run(&config, None, true, true, &fs).unwrap();
+        assert_eq!(fs.file_mode(&staged).unwrap(), 0o644);
+    }
+
+    #[test]
+    fn requires_a_repair_kind() {
+        let fs = setup_fs();
+        let config = staged_with_mode(&fs);
+        let msg = format!("{:#}", run(&config, None, false, false, &fs).unwrap_err());
+        assert!(msg.contains("--modes"), "got: {msg}");
+    }
+}
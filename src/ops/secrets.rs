@@ -0,0 +1,207 @@
+//! `janus secrets rotate`: push a rotated credential out everywhere it's used.
+//!
+//! After a secret changes in the secret manager, finds every template that
+//! uses it (through any global, fileset, or per-file secrets file), then
+//! regenerates, restages, and redeploys just those files. Secrets are never
+//! cached between runs, so regenerating is enough to pick up the new value.
+
+use anyhow::{Context, Result, bail};
+use std::io::Write;
+use tracing::info;
+
+use super::explain::referenced_names;
+use crate::config::{Config, FileEntry};
+use crate::platform::{CommandRunner, Fs, SecretEngine};
+use crate::secrets;
+use crate::state::State;
+use crate::template::template_blocks;
+
+/// Managed templates that use the secret `name`, in config order.
+///
+/// A file uses it when one of the secrets files that apply to it defines
+/// `name` and either its template mentions `name` inside `{{ }}` or `{% %}`,
+/// or one of its `inject` placeholders takes that secret.
+pub fn referencing_files<'a>(
+    config: &'a Config,
+    name: &str,
+    fs: &impl Fs,
+) -> Result<Vec<&'a FileEntry>> {
+    let dotfiles_dir = config.dotfiles_dir(fs);
+    let mut defined = false;
+    let mut found = Vec::new();
+    for entry in &config.files {
+        let injects = entry.inject.iter().any(|i| i.secret == name);
+        if entry.direct || !(entry.template || injects) {
+            continue;
+        }
+        let mut secret_files = config.secrets.clone();
+        for fileset in config.matching_filesets(&entry.src) {
+            secret_files.extend(fileset.secrets.iter().cloned());
+        }
+        secret_files.extend(entry.secrets.iter().cloned());
+        let applies = secrets::parse_secret_files(&dotfiles_dir, &secret_files, fs)?
+            .iter()
+            .any(|s| s.name == name);
+        if !applies {
+            continue;
+        }
+        defined = true;
+        if injects {
+            found.push(entry);
+            continue;
+        }
+
+        let source_path = config.source_path(&entry.src, fs);
+        if !fs.exists(&source_path) {
+            continue;
+        }
+        let template = fs
+            .read_to_string(&source_path)
+            .with_context(|| format!("Failed to read template: {}", source_path.display()))?;
+        if template_uses(&template, name) {
+            found.push(entry);
+        }
+    }
+    if !defined {
+        bail!("No secrets file defines {name}");
+    }
+    Ok(found)
+}
+
+/// Whether `template` mentions `name` inside `{{ }}` or `{% %}`.
+pub(crate) fn template_uses(template: &str, name: &str) -> bool {
+    template_blocks(template).any(|block| referenced_names(block).iter().any(|n| n == name))
+}
+
+/// Regenerate, restage, and redeploy every file using the secret `name`,
+/// then list them to `out`.
+///
+/// Files that aren't deployed are regenerated and restaged but left
+/// undeployed.
+pub fn rotate(
+    config: &Config,
+    name: &str,
+    dry_run: bool,
+    fs: &impl Fs,
+    engine: &impl SecretEngine,
+    runner: &impl CommandRunner,
+    out: &mut impl Write,
+) -> Result<()> {
+    let entries = referencing_files(config, name, fs)?;
+    let files: Vec<String> = entries.iter().map(|e| e.src.clone()).collect();
+    if files.is_empty() {
+        info!("No templates use {name}");
+        return Ok(());
+    }
+
+    super::generate::run(config, Some(&files), dry_run, fs, engine, runner)?;
+    super::stage::run(config, Some(&files), dry_run, fs)?;
+
+    let state = State::load(&config.state_dir(fs), fs)?;
+    let deployed: Vec<String> = entries
+        .iter()
+        .filter(|e| state.is_deployed(e.key()))
+        .map(|e| e.selector())
+        .collect();
+    if !deployed.is_empty() {
+        super::deploy::run(config, Some(&deployed), false, dry_run, fs, runner)?;
+    }
+
+    let verb = if dry_run { "Would update" } else { "Updated" };
+    writeln!(out, "{verb} {} file(s) using {name}:", entries.len())?;
+    for entry in &entries {
+        let selector = entry.selector();
+        let note = if deployed.contains(&selector) {
+            ""
+        } else {
+            " (not deployed)"
+        };
+        writeln!(out, "  {selector}{note}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{FakeCommandRunner, FakeSecretEngine};
+    use crate::test_helpers::*;
+    use std::path::Path;
+
+    fn setup() -> (crate::platform::FakeFs, Config) {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "");
+        fs.add_file(
+            format!("{DOTFILES}/secrets.toml"),
+            "[[secret]]\nname = \"token\"\nengine = \"1password\"\nreference = \"op://v/token\"\n",
+        );
+        fs.add_file(format!("{DOTFILES}/a.conf"), "key={{ token }}");
+        fs.add_file(format!("{DOTFILES}/b.conf"), "{% if token %}on{% endif %}");
+        fs.add_file(format!("{DOTFILES}/c.conf"), "token but not a tag");
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\nsecrets = [\"secrets.toml\"]\n\n\
+             [[files]]\nsrc = \"a.conf\"\n\n[[files]]\nsrc = \"b.conf\"\n\n[[files]]\nsrc = \"c.conf\"\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        (fs, config)
+    }
+
+    fn engine(value: &str) -> FakeSecretEngine {
+        let mut engine = FakeSecretEngine::new();
+        engine.add_secret("1password", "op://v/token", value);
+        engine
+    }
+
+    #[test]
+    fn finds_expression_and_tag_uses() {
+        let (fs, config) = setup();
+        let srcs: Vec<&str> = referencing_files(&config, "token", &fs)
+            .unwrap()
+            .iter()
+            .map(|e| e.src.as_str())
+            .collect();
+        assert_eq!(srcs, vec!["a.conf", "b.conf"]);
+    }
+
+    #[test]
+    fn unknown_secret_fails() {
+        let (fs, config) = setup();
+        let msg = format!("{:#}", referencing_files(&config, "nope", &fs).unwrap_err());
+        assert!(msg.contains("No secrets file defines nope"), "got: {msg}");
+    }
+
+    #[test]
+    fn rotate_regenerates_and_redeploys() {
+        let (fs, config) = setup();
+        let runner = FakeCommandRunner::new();
+        crate::ops::apply::run(
+            &config,
+            Some(&["a.conf".to_string()]),
+            false,
+            false,
+            false,
+            &fs,
+            &engine("old"),
+            &crate::platform::FakePrompter::new(vec![]),
+            &runner,
+        )
+        .unwrap();
+
+        rotate(
+            &config,
+            "token",
+            false,
+            &fs,
+            &engine("new"),
+            &runner,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let target = Path::new("/home/test/.config/a.conf");
+        assert_eq!(fs.read_to_string(target).unwrap(), "key=new");
+        let staged = format!("{DOTFILES}/.staged/b.conf");
+        assert_eq!(fs.read_to_string(Path::new(&staged)).unwrap(), "on");
+        // b.conf wasn't deployed before, so rotation doesn't deploy it
+        assert!(!fs.exists(Path::new("/home/test/.config/b.conf")));
+    }
+}
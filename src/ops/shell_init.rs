@@ -0,0 +1,234 @@
+//! `janus shell-init`: shell functions for working with the dotfiles repo.
+//!
+//! Meant to be evaluated on every shell start, e.g.
+//! `eval "$(janus shell-init bash)"`. Emits, as configured by the `[shell]`
+//! block ([`ShellConfig`](crate::config::ShellConfig)):
+//!
+//! - an edit function (`jedit <src>`) opening a managed source in
+//!   `$EDITOR`, with completion over the `src` paths in the config;
+//! - a cd function (`jcd`) changing to `dotfiles_dir`;
+//! - a `janus status --only-diffs` call, only when the status cache
+//!   suggests drift.
+//!
+//! The drift check only compares file stamps against the status cache (see
+//! [`crate::status_cache`]), so it reads no file contents and keeps shell
+//! startup fast. A file whose copies changed since `status` last looked,
+//! or that status last saw differing, counts as drift.
+
+use anyhow::{Result, bail};
+use std::fmt;
+use std::io::Write;
+
+use crate::config::Config;
+use crate::platform::Fs;
+use crate::shell;
+use crate::status_cache::{Stamps, StatusCache};
+
+/// Shell to emit functions for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum InitShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl fmt::Display for InitShell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            InitShell::Bash => "bash",
+            InitShell::Zsh => "zsh",
+            InitShell::Fish => "fish",
+        })
+    }
+}
+
+/// Write the init script for `shell` to `out`.
+pub fn run(config: &Config, shell: InitShell, fs: &impl Fs, out: &mut impl Write) -> Result<()> {
+    let settings = &config.shell;
+    for name in [&settings.edit, &settings.cd] {
+        if !name.is_empty() && !is_function_name(name) {
+            bail!("Invalid [shell] function name: {name:?}");
+        }
+    }
+
+    let dotfiles_dir = config.dotfiles_dir(fs).to_string_lossy().into_owned();
+    let srcs: Vec<&str> = config
+        .files
+        .iter()
+        .filter(|e| e.enabled)
+        .map(|e| e.src.as_str())
+        .collect();
+    let quote = |s: &str| match shell {
+        InitShell::Fish => fish_quote(s),
+        InitShell::Bash | InitShell::Zsh => shell::quote(s),
+    };
+    let dir = quote(&dotfiles_dir);
+    let words = srcs.iter().map(|s| quote(s)).collect::<Vec<_>>().join(" ");
+
+    writeln!(out, "# janus shell-init {shell}")?;
+    if !settings.edit.is_empty() {
+        let name = &settings.edit;
+        match shell {
+            InitShell::Bash | InitShell::Zsh => {
+                writeln!(out, "{name}() {{")?;
+                writeln!(out, "    \"${{EDITOR:-vi}}\" {dir}/\"$1\"")?;
+                writeln!(out, "}}")?;
+            }
+            InitShell::Fish => {
+                writeln!(out, "function {name}")?;
+                writeln!(out, "    set -l editor $EDITOR")?;
+                writeln!(out, "    test -n \"$editor\"; or set editor vi")?;
+                writeln!(out, "    $editor {dir}/$argv[1]")?;
+                writeln!(out, "end")?;
+            }
+        }
+        match shell {
+            InitShell::Bash => {
+                // One src per line, so srcs with spaces stay whole
+                let lines = srcs.join("\n");
+                writeln!(out, "_{name}() {{")?;
+                writeln!(out, "    local IFS=$'\\n'")?;
+                writeln!(
+                    out,
+                    "    COMPREPLY=($(compgen -W {} -- \"${{COMP_WORDS[COMP_CWORD]}}\"))",
+                    shell::quote(&lines)
+                )?;
+                writeln!(out, "}}")?;
+                writeln!(out, "complete -o filenames -F _{name} {name}")?;
+            }
+            InitShell::Zsh => {
+                writeln!(out, "_{name}() {{ compadd -- {words}; }}")?;
+                writeln!(out, "(( $+functions[compdef] )) && compdef _{name} {name}")?;
+            }
+            InitShell::Fish => {
+                writeln!(out, "complete -c {name} -f -a {}", fish_quote(&words))?;
+            }
+        }
+    }
+    if !settings.cd.is_empty() {
+        let name = &settings.cd;
+        match shell {
+            InitShell::Bash | InitShell::Zsh => writeln!(out, "{name}() {{ cd {dir}; }}")?,
+            InitShell::Fish => writeln!(out, "function {name}; cd {dir}; end")?,
+        }
+    }
+    if settings.status_on_start && shows_drift(config, fs) {
+        writeln!(out, "command janus status --only-diffs")?;
+    }
+    Ok(())
+}
+
+/// Whether the status cache suggests any non-direct file `--all` covers
+/// has drifted: its copies changed since status compared them, it was never
+/// compared, or the last comparison found a difference.
+fn shows_drift(config: &Config, fs: &impl Fs) -> bool {
+    let cache = StatusCache::load(&config.cache_dir(fs), fs);
+    let generated_dir = config.generated_dir(fs);
+    let staged_dir = config.staged_dir(fs);
+    config
+        .filter_files(None)
+        .into_iter()
+        .filter(|e| !e.direct)
+        .any(|entry| {
+            let src = &entry.src;
+            let stamps = Stamps::read(
+                &config.source_path(src, fs),
+                &generated_dir.join(src),
+                &staged_dir.join(src),
+                fs,
+            );
+            cache
+                .get(src, &stamps)
+                .is_none_or(|c| !c.source_matches_generated || !c.generated_matches_staged)
+        })
+}
+
+/// Whether `name` is safe to define as a function in every supported shell.
+fn is_function_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || "_-".contains(c))
+}
+
+/// Quote `s` for fish, where `\` and `'` are escaped inside single quotes.
+fn fish_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    fn init(config: &Config, shell: InitShell, fs: &crate::platform::FakeFs) -> String {
+        let mut out = Vec::new();
+        run(config, shell, fs, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn emits_functions_and_completions() {
+        let fs = setup_fs();
+        let toml = format!(
+            "{}\n[shell]\ncd = \"\"\n",
+            make_config_toml(&[("hypr/hypr.conf", None), ("my notes.md", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+
+        let bash = init(&config, InitShell::Bash, &fs);
+        assert!(
+            bash.contains("jedit() {\n    \"${EDITOR:-vi}\" /home/test/dotfiles/\"$1\""),
+            "got: {bash}"
+        );
+        assert!(
+            bash.contains("compgen -W 'hypr/hypr.conf\nmy notes.md' -- "),
+            "got: {bash}"
+        );
+        assert!(
+            bash.contains("complete -o filenames -F _jedit jedit"),
+            "got: {bash}"
+        );
+        assert!(!bash.contains("jcd"), "got: {bash}");
+
+        let fish = init(&config, InitShell::Fish, &fs);
+        assert!(fish.contains("function jedit\n"), "got: {fish}");
+        assert!(fish.contains("complete -c jedit -f -a "), "got: {fish}");
+    }
+
+    #[test]
+    fn status_runs_only_when_cache_shows_drift() {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "a.conf", "same");
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        // Never compared yet
+        assert!(init(&config, InitShell::Zsh, &fs).contains("janus status --only-diffs"));
+
+        let filters = crate::ops::status::StatusFilters {
+            only_diffs: true,
+            deployed: false,
+            undeployed: false,
+            stale: false,
+        };
+        crate::ops::status::compute(&config, None, &filters, &fs).unwrap();
+        assert!(!init(&config, InitShell::Zsh, &fs).contains("janus status"));
+
+        fs.write(
+            std::path::Path::new(&format!("{DOTFILES}/.staged/a.conf")),
+            b"edited by the app",
+        )
+        .unwrap();
+        assert!(init(&config, InitShell::Zsh, &fs).contains("janus status --only-diffs"));
+    }
+
+    #[test]
+    fn rejects_bad_function_names() {
+        let fs = setup_fs();
+        let toml = format!("{}\n[shell]\nedit = \"j; rm\"\n", make_config_toml(&[]));
+        let config = write_and_load_config(&fs, &toml);
+        let mut out = Vec::new();
+        let err = run(&config, InitShell::Bash, &fs, &mut out).unwrap_err();
+        assert!(err.to_string().contains("Invalid [shell]"), "got: {err}");
+    }
+}
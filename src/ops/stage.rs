@@ -4,6 +4,21 @@
 //! paths by `deploy`. This separation allows inspecting diffs between generated
 //! and staged content before deploying.
 //!
+//! When a generated file has managed regions (see [`crate::regions`]), only
+//! those regions of an existing staged copy are replaced.
+//!
+//! With `staged_store`, other files are staged as hard links into the
+//! content-addressed [`crate::store`] instead of copied.
+//!
+//! Each staged copy is then checked: it must be a regular file (not a
+//! symlink that a copy wrote through), have the generated file's mode, and
+//! hold exactly the staged content. A mismatch fails the file, catching
+//! filesystems that drop permissions (FAT) or misbehave (some overlayfs
+//! setups) before deploy links to it.
+//!
+//! A file generated with `--skip-secrets` holds placeholders instead of its
+//! secrets, so it fails to stage rather than being deployed that way.
+//!
 //! Uses error-collection strategy: processes all files and reports failures
 //! at the end rather than bailing on the first error.
 
@@ -11,33 +26,67 @@ use anyhow::{Context, Result};
 use std::path::Path;
 use tracing::{debug, info, warn};
 
+use super::Outcome;
 use crate::config::Config;
+use crate::plan;
 use crate::platform::Fs;
+use crate::progress;
+use crate::regions;
+use crate::secrets;
+use crate::state::{State, content_hash};
+use crate::{store, timings};
 
 /// Stage generated files for the given file patterns (or all files).
 ///
 /// Collects per-file errors and reports them at the end. Returns an error
 /// if any file failed to stage.
 pub fn run(config: &Config, files: Option<&[String]>, dry_run: bool, fs: &impl Fs) -> Result<()> {
+    stage(config, files, dry_run, fs)?.into_result()
+}
+
+/// Stage the selected files, collecting per-file failures.
+pub(crate) fn stage(
+    config: &Config,
+    files: Option<&[String]>,
+    dry_run: bool,
+    fs: &impl Fs,
+) -> Result<Outcome> {
     let entries = config.filter_files(files);
     if entries.is_empty() {
         config.bail_unmatched(files)?;
         info!("No files to stage");
-        return Ok(());
+        return Ok(Outcome {
+            succeeded: Vec::new(),
+            failure: None,
+        });
     }
 
     let generated_dir = config.generated_dir(fs);
     let staged_dir = config.staged_dir(fs);
     let mut errors: Vec<(String, anyhow::Error)> = Vec::new();
-    let mut succeeded = 0usize;
+    let mut succeeded = Vec::new();
+    let mut modes = Vec::new();
 
+    let progress = progress::phase("stage", entries.len());
     for entry in &entries {
+        progress.advance(&entry.src);
         if entry.direct {
             debug!("Skipping direct file: {}", entry.src);
             continue;
         }
-        match stage_file(entry, &generated_dir, &staged_dir, dry_run, fs) {
-            Ok(()) => succeeded += 1,
+        match stage_file(
+            entry,
+            &generated_dir,
+            &staged_dir,
+            config.staged_store,
+            dry_run,
+            fs,
+        ) {
+            Ok(Some(mode)) => {
+                succeeded.push(entry.src.clone());
+                modes.push((entry.src.clone(), mode));
+            }
+            Ok(None) => succeeded.push(entry.src.clone()),
             Err(e) => {
                 warn!("Failed to stage {}: {e:#}", entry.src);
                 errors.push((entry.src.clone(), e));
@@ -45,32 +94,55 @@ pub fn run(config: &Config, files: Option<&[String]>, dry_run: bool, fs: &impl F
         }
     }
 
-    if errors.is_empty() {
-        info!("Staged {} file(s)", succeeded);
+    if !dry_run {
+        super::record_activity(config, &succeeded, State::record_sync, fs);
+        record_modes(config, &modes, fs);
+    }
+
+    let failure = if errors.is_empty() {
+        info!("Staged {} file(s)", succeeded.len());
+        None
     } else {
         info!(
             "Staged {} file(s) with {} failure(s)",
-            succeeded,
+            succeeded.len(),
             errors.len()
         );
-        let mut msg = format!("Failed to stage {} file(s):", errors.len());
-        for (src, e) in &errors {
-            msg.push_str(&format!("\n  {src}: {e:#}"));
+        Some(super::FileErrors::new("stage", &errors))
+    };
+
+    Ok(Outcome { succeeded, failure })
+}
+
+/// Remember the mode each file was staged with, so `janus status` can spot
+/// permission drift. Like the timestamps, failures are only logged.
+fn record_modes(config: &Config, modes: &[(String, u32)], fs: &impl Fs) {
+    if modes.is_empty() {
+        return;
+    }
+    let result = State::load(&config.state_dir(fs), fs).and_then(|mut state| {
+        for (src, mode) in modes {
+            state.record_mode(src, *mode);
         }
-        anyhow::bail!(msg);
+        state.save(fs)
+    });
+    if let Err(e) = result {
+        warn!("Failed to record staged modes: {e:#}");
     }
-
-    Ok(())
 }
 
-/// Copy a single file from `.generated/` to `.staged/`, preserving permissions.
+/// Copy a single file from `.generated/` to `.staged/`, preserving
+/// permissions, or link it from the store when `use_store` is set.
+///
+/// Returns the mode it was staged with, or `None` on a dry run.
 fn stage_file(
     entry: &crate::config::FileEntry,
     generated_dir: &Path,
     staged_dir: &Path,
+    use_store: bool,
     dry_run: bool,
     fs: &impl Fs,
-) -> Result<()> {
+) -> Result<Option<u32>> {
     let src_path = generated_dir.join(&entry.src);
     let dest_path = staged_dir.join(&entry.src);
 
@@ -80,10 +152,28 @@ fn stage_file(
             src_path.display()
         );
     }
+    // Only rendered files can hold secrets, so others are never read here
+    if entry.template || !entry.inject.is_empty() {
+        let content = fs
+            .read(&src_path)
+            .with_context(|| format!("Failed to read generated: {}", src_path.display()))?;
+        if secrets::has_placeholder(&content) {
+            anyhow::bail!(
+                "{} was generated with --skip-secrets; run `janus generate` without it before staging",
+                entry.src
+            );
+        }
+    }
 
     if dry_run {
         info!("[dry-run] Would stage: {}", entry.src);
-        return Ok(());
+        plan::action(
+            "stage",
+            &src_path.to_string_lossy(),
+            &dest_path.to_string_lossy(),
+            "",
+        );
+        return Ok(None);
     }
 
     // Ensure parent directory exists
@@ -92,18 +182,125 @@ fn stage_file(
             .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
     }
 
-    fs.copy(&src_path, &dest_path)
-        .with_context(|| format!("Failed to stage file: {}", entry.src))?;
-
-    // Preserve permissions
+    let _timing = timings::phase("stage", &entry.src);
     let mode = fs
         .file_mode(&src_path)
         .with_context(|| format!("Failed to read metadata: {}", src_path.display()))?;
+    let merged = merge_regions(&src_path, &dest_path, fs)
+        .with_context(|| format!("Failed to stage managed regions: {}", entry.src))?;
+
+    // Files with managed regions are meant to be edited in place, so they
+    // never share a blob
+    if use_store && merged.is_none() {
+        let content = fs
+            .read(&src_path)
+            .with_context(|| format!("Failed to read generated: {}", src_path.display()))?;
+        store::link(staged_dir, &dest_path, &content, mode, fs)
+            .with_context(|| format!("Failed to stage file: {}", entry.src))?;
+        verify_staged(&dest_path, &content_hash(&content), mode, fs)?;
+        info!("Staged {}", entry.src);
+        return Ok(Some(mode));
+    }
+
+    // A copy linked from the store shares its data with other files, so
+    // replace it instead of writing through it
+    if fs.is_dir(&store::store_dir(staged_dir)) && fs.is_file(&dest_path) {
+        fs.remove_file(&dest_path)
+            .with_context(|| format!("Failed to replace staged: {}", dest_path.display()))?;
+    }
+    let expected = match merged {
+        Some(merged) => {
+            fs.write(&dest_path, merged.as_bytes())
+                .with_context(|| format!("Failed to stage file: {}", entry.src))?;
+            content_hash(merged.as_bytes())
+        }
+        None => {
+            fs.copy_streaming(&src_path, &dest_path)
+                .with_context(|| format!("Failed to stage file: {}", entry.src))?;
+            fs.hash_file(&src_path)
+                .with_context(|| format!("Failed to read generated: {}", src_path.display()))?
+        }
+    };
+
+    // Preserve permissions and extended attributes
     fs.set_file_mode(&dest_path, mode)
         .with_context(|| format!("Failed to set permissions: {}", dest_path.display()))?;
+    super::preserve_xattrs(&src_path, &dest_path, fs);
+    verify_staged(&dest_path, &expected, mode, fs)?;
 
     info!("Staged {}", entry.src);
-    Ok(())
+    Ok(Some(mode))
+}
+
+/// Check that the staged copy at `path` is a regular file with `mode` and
+/// content whose [`content_hash`] is `expected`, listing every discrepancy.
+/// Both sides are hashed as they're streamed, so a large file is never read
+/// into memory just to check it.
+fn verify_staged(path: &Path, expected: &str, mode: u32, fs: &impl Fs) -> Result<()> {
+    let mut problems = Vec::new();
+    if fs.is_symlink(path) {
+        let dest = fs
+            .read_link(path)
+            .map(|d| d.display().to_string())
+            .unwrap_or_else(|_| "?".to_string());
+        problems.push(format!("it's a symlink to {dest}, not a file"));
+    } else if !fs.is_file(path) {
+        problems.push("it isn't a regular file".to_string());
+    }
+    match fs.file_mode(path) {
+        Ok(actual) if actual & 0o7777 != mode & 0o7777 => problems.push(format!(
+            "its mode is {:04o}, expected {:04o}",
+            actual & 0o7777,
+            mode & 0o7777
+        )),
+        Ok(_) => {}
+        Err(e) => problems.push(format!("its mode can't be read: {e:#}")),
+    }
+    match fs.hash_file(path) {
+        Ok(actual) if actual != expected => {
+            problems.push("its content differs from what was staged".to_string())
+        }
+        Ok(_) => {}
+        Err(e) => problems.push(format!("it can't be read back: {e:#}")),
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+    anyhow::bail!(
+        "Staged copy {} failed verification: {}",
+        path.display(),
+        problems.join("; ")
+    );
+}
+
+/// When the generated copy has managed regions and there's a staged copy
+/// already, the staged copy with only its regions replaced, keeping what an
+/// app or the user changed around them. `None` to copy the whole file.
+fn merge_regions(
+    generated_path: &Path,
+    staged_path: &Path,
+    fs: &impl Fs,
+) -> Result<Option<String>> {
+    if !fs.exists(staged_path) {
+        return Ok(None);
+    }
+    let Ok(generated) = String::from_utf8(fs.read(generated_path)?) else {
+        return Ok(None);
+    };
+    if !regions::has_regions(&generated) {
+        return Ok(None);
+    }
+    let staged = fs
+        .read_to_string(staged_path)
+        .with_context(|| format!("Failed to read staged: {}", staged_path.display()))?;
+    let merged = regions::splice(&staged, &generated).context(
+        "the staged copy's markers don't match the generated ones; fix them, or delete the staged copy to restage it whole",
+    )?;
+    debug!(
+        "Replacing only the managed regions of {}",
+        staged_path.display()
+    );
+    Ok(Some(merged))
 }
 
 #[cfg(test)]
@@ -123,6 +320,83 @@ mod tests {
         assert_eq!(content, "generated content");
     }
 
+    #[test]
+    fn refuses_skipped_secret_placeholders() {
+        let fs = setup_fs();
+        fs.add_file(
+            format!("{DOTFILES}/.generated/a.conf"),
+            format!("password={}\n", secrets::placeholder("op://db/pass")),
+        );
+        fs.add_file(format!("{DOTFILES}/.generated/b.conf"), "plain\n");
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[("a.conf", None), ("b.conf", None)]),
+        );
+        let err = run(&config, None, false, &fs).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("--skip-secrets"),
+            "got: {err:#}"
+        );
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/.staged/a.conf"))));
+        assert!(fs.exists(Path::new(&format!("{DOTFILES}/.staged/b.conf"))));
+    }
+
+    #[test]
+    fn replaces_only_managed_regions() {
+        let fs = setup_fs();
+        fs.add_file(
+            format!("{DOTFILES}/.generated/a.conf"),
+            "# janus:begin managed\nfont = 12\n# janus:end\n",
+        );
+        fs.add_file(
+            format!("{DOTFILES}/.staged/a.conf"),
+            "# janus:begin managed\nfont = 10\n# janus:end\nadded_by_app = true\n",
+        );
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        run(&config, None, false, &fs).unwrap();
+        let content = fs
+            .read_to_string(Path::new(&format!("{DOTFILES}/.staged/a.conf")))
+            .unwrap();
+        assert_eq!(
+            content,
+            "# janus:begin managed\nfont = 12\n# janus:end\nadded_by_app = true\n"
+        );
+
+        // A staged copy that lost its markers isn't overwritten
+        fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "font = 10\n");
+        let err = run(&config, None, false, &fs).unwrap_err();
+        assert!(format!("{err:#}").contains("markers"), "got: {err:#}");
+        assert_eq!(
+            fs.read_to_string(Path::new(&format!("{DOTFILES}/.staged/a.conf")))
+                .unwrap(),
+            "font = 10\n"
+        );
+    }
+
+    #[test]
+    fn store_links_identical_renders() {
+        let fs = setup_fs();
+        for src in ["ws1/bar.conf", "ws2/bar.conf"] {
+            fs.add_file(format!("{DOTFILES}/.generated/{src}"), "height = 30\n");
+        }
+        let toml = format!(
+            "staged_store = true\n{}",
+            make_config_toml(&[("ws1/bar.conf", None), ("ws2/bar.conf", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        run(&config, None, false, &fs).unwrap();
+
+        let store = store::store_dir(&config.staged_dir(&fs));
+        let blobs = fs.walk_dir(&store, &Default::default()).unwrap();
+        assert_eq!(blobs.iter().filter(|e| e.is_file).count(), 1);
+        for src in ["ws1/bar.conf", "ws2/bar.conf"] {
+            let staged = fs
+                .read_to_string(Path::new(&format!("{DOTFILES}/.staged/{src}")))
+                .unwrap();
+            assert_eq!(staged, "height = 30\n");
+        }
+    }
+
     #[test]
     fn preserves_permissions() {
         let fs = setup_fs();
@@ -137,6 +411,62 @@ mod tests {
             .file_mode(Path::new(&format!("{DOTFILES}/.staged/script.sh")))
             .unwrap();
         assert_eq!(mode, 0o755);
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert_eq!(state.activity("script.sh").unwrap().mode, Some(0o755));
+    }
+
+    #[test]
+    fn preserves_extended_attributes() {
+        let fs = setup_fs();
+        let generated = format!("{DOTFILES}/.generated/a.conf");
+        fs.add_file(&generated, "content");
+        let acl = vec![(
+            "system.posix_acl_access".to_string(),
+            b"\x02\0\0\0".to_vec(),
+        )];
+        let mut attrs = acl.clone();
+        // Labels come from policy for the path, never from the copy
+        attrs.push((
+            "security.selinux".to_string(),
+            b"unconfined_u:object_r:user_home_t:s0".to_vec(),
+        ));
+        fs.set_xattrs(Path::new(&generated), &attrs).unwrap();
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        run(&config, None, false, &fs).unwrap();
+        let staged = fs
+            .xattrs(Path::new(&format!("{DOTFILES}/.staged/a.conf")))
+            .unwrap();
+        assert_eq!(staged, acl);
+    }
+
+    #[test]
+    fn verification_reports_symlinks_and_mode() {
+        let fs = setup_fs();
+        let source = format!("{DOTFILES}/a.conf");
+        let staged = format!("{DOTFILES}/.staged/a.conf");
+        fs.add_file_with_mode(&source, "content", 0o644);
+        fs.add_symlink(&staged, &source);
+        let err =
+            verify_staged(Path::new(&staged), &content_hash(b"content"), 0o600, &fs).unwrap_err();
+        let msg = format!("{err:#}");
+        assert!(msg.contains("symlink to"), "got: {msg}");
+        assert!(msg.contains("mode is 0644, expected 0600"), "got: {msg}");
+        assert!(!msg.contains("content differs"), "got: {msg}");
+        fs.add_file_with_mode(&staged, "content", 0o600);
+        verify_staged(Path::new(&staged), &content_hash(b"content"), 0o600, &fs).unwrap();
+    }
+
+    #[test]
+    fn binary_files_are_copied_byte_for_byte() {
+        let fs = setup_fs();
+        let wallpaper: Vec<u8> = (0..=255).cycle().take(4096).collect();
+        fs.add_file(format!("{DOTFILES}/.generated/wall.png"), wallpaper.clone());
+        let config = write_and_load_config(&fs, &make_config_toml(&[("wall.png", None)]));
+        run(&config, None, false, &fs).unwrap();
+        let staged = fs
+            .read(Path::new(&format!("{DOTFILES}/.staged/wall.png")))
+            .unwrap();
+        assert_eq!(staged, wallpaper);
     }
 
     #[test]
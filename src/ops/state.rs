@@ -0,0 +1,415 @@
+//! `janus state`: repair `.janus_state.toml` from what's on disk.
+//!
+//! `import-existing` is for a dotfiles repo restored onto a machine that
+//! janus already deployed to but whose state file was lost: every target is
+//! still a janus symlink, yet status shows everything undeployed. It finds
+//! each configured target that is a symlink to that file's staged copy (or
+//! source, for direct files) and records it as deployed after confirmation.
+//!
+//! `compact-ignored` collapses many exact `[[ignored]]` entries under one
+//! directory into a single `dir/**` pattern.
+
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::config::{Config, FileEntry};
+use crate::paths::expand_tilde;
+use crate::plan;
+use crate::platform::{Fs, Prompter};
+use crate::state::{State, content_hash, is_pattern};
+
+/// A deployed-but-unrecorded file found by [`scan`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct Found {
+    /// The entry's key: its `name`, else its `src`.
+    pub src: String,
+    /// Where its symlink is.
+    pub target: PathBuf,
+}
+
+/// Where `path`, a symlink, points, resolving a relative link against its
+/// directory.
+fn link_destination(path: &Path, fs: &impl Fs) -> Option<PathBuf> {
+    let dest = fs.read_link(path).ok()?;
+    Some(match path.parent() {
+        Some(parent) if dest.is_relative() => parent.join(dest),
+        _ => dest,
+    })
+}
+
+/// The path `entry`'s target symlink points at when janus deployed it.
+fn expected_link(config: &Config, entry: &FileEntry, fs: &impl Fs) -> PathBuf {
+    if entry.direct {
+        config.source_path(&entry.src, fs)
+    } else {
+        config.staged_dir(fs).join(&entry.src)
+    }
+}
+
+/// Find entries the state doesn't list as deployed whose target is already
+/// a symlink to their staged copy (or source). Symlinks into the staged
+/// directory that point at another file are returned separately as
+/// `(target, destination)`, since they can't be attributed safely.
+pub fn scan(config: &Config, state: &State, fs: &impl Fs) -> (Vec<Found>, Vec<(PathBuf, PathBuf)>) {
+    let staged_dir = config.staged_dir(fs);
+    let mut found = Vec::new();
+    let mut mismatched = Vec::new();
+    for entry in &config.files {
+        if state.is_deployed(entry.key()) {
+            continue;
+        }
+        let target = expand_tilde(&entry.target(), fs);
+        if !fs.is_symlink(&target) {
+            continue;
+        }
+        let Some(dest) = link_destination(&target, fs) else {
+            continue;
+        };
+        if dest == expected_link(config, entry, fs) {
+            found.push(Found {
+                src: entry.key().to_string(),
+                target,
+            });
+        } else if dest.starts_with(&staged_dir) {
+            mismatched.push((target, dest));
+        }
+    }
+    (found, mismatched)
+}
+
+/// Record every file [`scan`] finds as deployed, after listing them to
+/// `out` and confirming (skipped with `yes`). With `dry_run`, only lists.
+pub fn import_existing(
+    config: &Config,
+    yes: bool,
+    dry_run: bool,
+    fs: &impl Fs,
+    prompter: &impl Prompter,
+    out: &mut impl Write,
+) -> Result<()> {
+    let mut state = State::load(&config.state_dir(fs), fs)?;
+    let (found, mismatched) = scan(config, &state, fs);
+
+    for (target, dest) in &mismatched {
+        writeln!(
+            out,
+            "Skipping {}: links to {}, not its own staged copy",
+            target.display(),
+            dest.display()
+        )?;
+    }
+    if found.is_empty() {
+        info!("No unrecorded janus symlinks found");
+        return Ok(());
+    }
+
+    writeln!(
+        out,
+        "Found {} deployed file(s) missing from the state:",
+        found.len()
+    )?;
+    for f in &found {
+        writeln!(out, "  {} -> {}", f.src, f.target.display())?;
+    }
+    if dry_run {
+        info!("[dry-run] Would record {} file(s) as deployed", found.len());
+        for f in &found {
+            plan::action(
+                "record-deployed",
+                &f.src,
+                &f.target.to_string_lossy(),
+                "linked but missing from the state",
+            );
+        }
+        return Ok(());
+    }
+    if !yes
+        && !prompter.confirm(
+            &format!("Record {} file(s) as deployed?", found.len()),
+            false,
+        )?
+    {
+        bail!("Aborted; the state was not changed");
+    }
+
+    for f in &found {
+        let entry = config
+            .files
+            .iter()
+            .find(|e| e.key() == f.src)
+            .expect("scan only returns configured entries");
+        state.add_deployed(entry.key().to_string(), entry.target());
+        if entry.direct {
+            // Same baseline deploy records, so later source edits are noticed
+            let source = config.source_path(&entry.src, fs);
+            let bytes = fs
+                .read(&source)
+                .with_context(|| format!("Failed to read source: {}", source.display()))?;
+            state.set_source_hash(entry.key(), content_hash(&bytes));
+        }
+    }
+    state.save(fs)?;
+    info!("Recorded {} file(s) as deployed", found.len());
+    Ok(())
+}
+
+/// Directories too broad to ignore wholesale, however many of their files
+/// are ignored one by one.
+const TOO_BROAD: &[&str] = &[
+    "",
+    "~",
+    "~/.config",
+    "~/.local",
+    "~/.local/share",
+    "~/.local/state",
+    "~/.cache",
+];
+
+/// One `dir/**` pattern and the exact ignored paths it replaces.
+#[derive(Debug, PartialEq)]
+pub struct Compaction {
+    pub pattern: String,
+    pub replaces: Vec<String>,
+}
+
+/// Plan which exact ignored entries to collapse: each directory directly
+/// holding at least `min` of them becomes `dir/**`, taking in every exact
+/// entry below it. Shallower directories win, and those in `TOO_BROAD`
+/// are left alone.
+pub fn plan_compaction(state: &State, min: usize) -> Vec<Compaction> {
+    let exact: Vec<&str> = state
+        .ignored
+        .iter()
+        .map(|e| e.path.as_str())
+        .filter(|p| !is_pattern(p))
+        .collect();
+    let mut by_parent: BTreeMap<&str, usize> = BTreeMap::new();
+    for path in &exact {
+        let parent = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+        *by_parent.entry(parent).or_default() += 1;
+    }
+    let mut dirs: Vec<&str> = by_parent
+        .into_iter()
+        .filter(|&(dir, count)| count >= min && !TOO_BROAD.contains(&dir))
+        .map(|(dir, _)| dir)
+        .collect();
+    dirs.sort_by_key(|dir| dir.matches('/').count());
+
+    let mut plan: Vec<Compaction> = Vec::new();
+    let mut taken: Vec<String> = Vec::new();
+    for dir in dirs {
+        let prefix = format!("{dir}/");
+        if taken.iter().any(|t| prefix.starts_with(t.as_str())) {
+            continue;
+        }
+        let pattern = format!("{}/**", glob::Pattern::escape(dir));
+        let replaces = exact
+            .iter()
+            .filter(|p| p.starts_with(&prefix))
+            .map(|p| p.to_string())
+            .collect();
+        plan.push(Compaction { pattern, replaces });
+        taken.push(prefix);
+    }
+    plan
+}
+
+/// Replace the entries [`plan_compaction`] picks with their patterns, after
+/// listing them to `out` and confirming (skipped with `yes`). With
+/// `dry_run`, only lists.
+pub fn compact_ignored(
+    config: &Config,
+    min: usize,
+    yes: bool,
+    dry_run: bool,
+    fs: &impl Fs,
+    prompter: &impl Prompter,
+    out: &mut impl Write,
+) -> Result<()> {
+    let mut state = State::load(&config.state_dir(fs), fs)?;
+    let plan = plan_compaction(&state, min.max(1));
+    if plan.is_empty() {
+        info!("No directory has {min} or more ignored paths to collapse");
+        return Ok(());
+    }
+
+    let replaced: usize = plan.iter().map(|c| c.replaces.len()).sum();
+    writeln!(
+        out,
+        "Would replace {replaced} ignored path(s) with {} pattern(s):",
+        plan.len()
+    )?;
+    for c in &plan {
+        writeln!(out, "  {} ({} path(s))", c.pattern, c.replaces.len())?;
+    }
+    if dry_run {
+        info!("[dry-run] Would collapse {replaced} ignored path(s)");
+        for c in &plan {
+            plan::action(
+                "collapse-ignored",
+                "",
+                &c.pattern,
+                &format!("replaces {} ignored path(s)", c.replaces.len()),
+            );
+        }
+        return Ok(());
+    }
+    if !yes && !prompter.confirm(&format!("Replace {replaced} ignored path(s)?"), false)? {
+        bail!("Aborted; the state was not changed");
+    }
+
+    for c in plan {
+        // Keep the reason when every replaced entry shares one
+        let reasons: Vec<&str> = state
+            .ignored
+            .iter()
+            .filter(|e| c.replaces.contains(&e.path))
+            .map(|e| e.reason.as_str())
+            .collect();
+        let reason = match reasons.first() {
+            Some(first) if reasons.iter().all(|r| r == first) => first.to_string(),
+            _ => "compacted".to_string(),
+        };
+        for path in &c.replaces {
+            state.remove_ignored(path);
+        }
+        state.add_ignored(c.pattern, reason);
+    }
+    state.save(fs)?;
+    info!("Collapsed {replaced} ignored path(s)");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::FakePrompter;
+    use crate::test_helpers::*;
+
+    fn setup() -> (crate::platform::FakeFs, Config) {
+        let fs = setup_fs();
+        for src in ["a.conf", "b.conf", "c.conf"] {
+            setup_pipeline_file(&fs, src, src);
+        }
+        fs.add_symlink(
+            "/home/test/.config/a.conf",
+            format!("{DOTFILES}/.staged/a.conf"),
+        );
+        // Points into .staged/ but at another file
+        fs.add_symlink(
+            "/home/test/.config/b.conf",
+            format!("{DOTFILES}/.staged/c.conf"),
+        );
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[("a.conf", None), ("b.conf", None), ("c.conf", None)]),
+        );
+        (fs, config)
+    }
+
+    #[test]
+    fn records_only_matching_symlinks() {
+        let (fs, config) = setup();
+        let mut out = Vec::new();
+        import_existing(
+            &config,
+            false,
+            false,
+            &fs,
+            &FakePrompter::new(vec![]).with_confirms(&[true]),
+            &mut out,
+        )
+        .unwrap();
+
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert!(state.is_deployed("a.conf"));
+        assert!(!state.is_deployed("b.conf"));
+        assert!(!state.is_deployed("c.conf"));
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.contains("Skipping /home/test/.config/b.conf"),
+            "got: {out}"
+        );
+    }
+
+    #[test]
+    fn abort_and_dry_run_leave_state_alone() {
+        let (fs, config) = setup();
+        let prompter = FakePrompter::new(vec![]).with_confirms(&[false]);
+        assert!(import_existing(&config, false, false, &fs, &prompter, &mut Vec::new()).is_err());
+        import_existing(&config, false, true, &fs, &prompter, &mut Vec::new()).unwrap();
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert!(!state.is_deployed("a.conf"));
+    }
+
+    fn ignore_all(state: &mut State, paths: &[&str]) {
+        for path in paths {
+            state.add_ignored(path.to_string(), "user_declined".to_string());
+        }
+    }
+
+    #[test]
+    fn compaction_prefers_shallowest_directory() {
+        let mut state = State::default();
+        ignore_all(
+            &mut state,
+            &[
+                "~/.config/Slack/Cookies",
+                "~/.config/Slack/Preferences",
+                "~/.config/Slack/storage/a.json",
+                "~/.config/Slack/storage/b.json",
+                "~/.config/Slack/storage/c.json",
+                "~/.config/Slack/window.json",
+                "~/.config/one.conf",
+                "~/.config/two.conf",
+                "~/.config/three.conf",
+            ],
+        );
+        let plan = plan_compaction(&state, 3);
+        assert_eq!(plan.len(), 1, "got: {plan:?}");
+        assert_eq!(plan[0].pattern, "~/.config/Slack/**");
+        assert_eq!(plan[0].replaces.len(), 6);
+    }
+
+    #[test]
+    fn compact_replaces_entries_with_pattern() {
+        let (fs, config) = setup();
+        let mut state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        ignore_all(
+            &mut state,
+            &["~/.mozilla/a", "~/.mozilla/b", "~/.mozilla/c", "~/.vimrc"],
+        );
+        state.save(&fs).unwrap();
+
+        let mut out = Vec::new();
+        compact_ignored(
+            &config,
+            3,
+            true,
+            false,
+            &fs,
+            &FakePrompter::new(vec![]),
+            &mut out,
+        )
+        .unwrap();
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        let paths: Vec<&str> = state.ignored.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["~/.vimrc", "~/.mozilla/**"]);
+        assert_eq!(state.ignored[1].reason, "user_declined");
+        assert!(state.is_ignored("~/.mozilla/firefox/prefs.js"));
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("~/.mozilla/** (3 path(s))"), "got: {out}");
+    }
+
+    #[test]
+    fn already_recorded_files_are_skipped() {
+        let (fs, config) = setup();
+        let mut state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        state.add_deployed("a.conf".to_string(), "~/.config/a.conf".to_string());
+        let (found, _) = scan(&config, &state, &fs);
+        assert!(found.is_empty());
+    }
+}
@@ -0,0 +1,298 @@
+//! `janus stats`: counts for auditing a large dotfiles repo.
+//!
+//! Reports how many files each fileset manages, how they're processed
+//! (templated, copied, or direct), how many files use each secret, the
+//! total size of the generated output, which files aren't deployed, and
+//! which have the most staged changes waiting to be synced.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+use super::status::{self, StatusFilters};
+use crate::config::Config;
+use crate::platform::Fs;
+use crate::secrets;
+use crate::state::State;
+use crate::timings::RunTimings;
+
+/// How many of the largest diffs to list.
+const LARGEST_DIFFS: usize = 5;
+
+/// Metrics over every managed file, including those excluded from `--all`.
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Managed files.
+    pub files: usize,
+    /// Files matched by each fileset, by name. A file may count in several.
+    pub by_fileset: BTreeMap<String, usize>,
+    /// Files no fileset matches.
+    pub no_fileset: usize,
+    /// Files rendered as templates.
+    pub templated: usize,
+    /// Files copied into `.generated/` (possibly with `inject` or
+    /// `postprocess`) rather than rendered.
+    pub copied: usize,
+    /// Files symlinked straight from the source.
+    pub direct: usize,
+    /// Secret names with how many files use each, most used first.
+    pub secret_usage: Vec<(String, usize)>,
+    /// Combined size of the `.generated/` copies that exist, in bytes.
+    pub generated_bytes: u64,
+    /// Files whose `.generated/` copy exists.
+    pub generated_files: usize,
+    /// Files that aren't deployed, in config order.
+    pub undeployed: Vec<String>,
+    /// Files with the most changed lines between generated and staged,
+    /// largest first.
+    pub largest_diffs: Vec<(String, usize)>,
+    /// The last run recorded with `--timings`.
+    pub last_timings: Option<RunTimings>,
+}
+
+/// Gather [`Stats`] for every `[[files]]` entry.
+pub fn compute(config: &Config, fs: &impl Fs) -> Result<Stats> {
+    let mut stats = Stats {
+        files: config.files.len(),
+        ..Stats::default()
+    };
+    let generated_dir = config.generated_dir(fs);
+    for entry in &config.files {
+        let filesets = config.matching_filesets_named(&entry.src);
+        if filesets.is_empty() {
+            stats.no_fileset += 1;
+        }
+        for (name, _) in filesets {
+            *stats.by_fileset.entry(name.to_string()).or_default() += 1;
+        }
+
+        if entry.direct {
+            stats.direct += 1;
+            continue;
+        }
+        if entry.template {
+            stats.templated += 1;
+        } else {
+            stats.copied += 1;
+        }
+        if let Ok(stamp) = fs.stamp(&generated_dir.join(&entry.src)) {
+            stats.generated_bytes += stamp.len;
+            stats.generated_files += 1;
+        }
+    }
+
+    // Every secret any secrets file defines, then who uses each
+    let dotfiles_dir = config.dotfiles_dir(fs);
+    let mut secret_files: BTreeSet<&String> = config.secrets.iter().collect();
+    secret_files.extend(config.filesets.values().flat_map(|f| &f.secrets));
+    secret_files.extend(config.files.iter().flat_map(|e| &e.secrets));
+    let secret_files: Vec<String> = secret_files.into_iter().cloned().collect();
+    let names: BTreeSet<String> = secrets::parse_secret_files(&dotfiles_dir, &secret_files, fs)?
+        .into_iter()
+        .map(|s| s.name)
+        .collect();
+    for name in names {
+        let users = super::secrets::referencing_files(config, &name, fs)?.len();
+        stats.secret_usage.push((name, users));
+    }
+    stats
+        .secret_usage
+        .sort_by(|(a, a_users), (b, b_users)| b_users.cmp(a_users).then_with(|| a.cmp(b)));
+
+    // Every enabled entry, including `exclude_from_all` ones; status bails
+    // on an empty list, so skip it when there's nothing to check
+    let all: Vec<String> = config
+        .files
+        .iter()
+        .filter(|e| e.enabled)
+        .map(|e| e.src.clone())
+        .collect();
+    let filters = StatusFilters {
+        only_diffs: false,
+        deployed: false,
+        undeployed: false,
+        stale: false,
+    };
+    let statuses = if all.is_empty() {
+        Vec::new()
+    } else {
+        status::compute(config, Some(&all), &filters, fs)?.statuses
+    };
+    let mut diffs = Vec::new();
+    for status in statuses {
+        if !status.deployed {
+            stats.undeployed.push(status.src.clone());
+        }
+        if status.changed_lines > 0 {
+            diffs.push((status.src, status.changed_lines));
+        }
+    }
+    diffs.sort_by(|(a, a_lines), (b, b_lines)| b_lines.cmp(a_lines).then_with(|| a.cmp(b)));
+    diffs.truncate(LARGEST_DIFFS);
+    stats.largest_diffs = diffs;
+
+    stats.last_timings = State::load(&config.state_dir(fs), fs)?.timings;
+    Ok(stats)
+}
+
+/// Print [`Stats`] for the config.
+pub fn run(config: &Config, fs: &impl Fs, out: &mut impl Write) -> Result<()> {
+    let stats = compute(config, fs)?;
+
+    writeln!(out, "Files: {}", stats.files)?;
+    writeln!(
+        out,
+        "  {} templated, {} copied, {} direct",
+        stats.templated, stats.copied, stats.direct
+    )?;
+
+    if !stats.by_fileset.is_empty() {
+        writeln!(out, "\nFilesets:")?;
+        let width = stats.by_fileset.keys().map(|n| n.len()).max().unwrap_or(0);
+        for (name, count) in &stats.by_fileset {
+            writeln!(out, "  {name:<width$}  {count}")?;
+        }
+        if stats.no_fileset > 0 {
+            writeln!(out, "  ({} in no fileset)", stats.no_fileset)?;
+        }
+    }
+
+    if !stats.secret_usage.is_empty() {
+        writeln!(out, "\nSecrets:")?;
+        let width = stats
+            .secret_usage
+            .iter()
+            .map(|(n, _)| n.len())
+            .max()
+            .unwrap_or(0);
+        for (name, users) in &stats.secret_usage {
+            let files = if *users == 1 { "file" } else { "files" };
+            writeln!(out, "  {name:<width$}  {users} {files}")?;
+        }
+    }
+
+    writeln!(
+        out,
+        "\nGenerated: {} across {} file(s)",
+        format_size(stats.generated_bytes),
+        stats.generated_files
+    )?;
+
+    if stats.undeployed.is_empty() {
+        writeln!(out, "Not deployed: none")?;
+    } else {
+        writeln!(out, "Not deployed ({}):", stats.undeployed.len())?;
+        for src in &stats.undeployed {
+            writeln!(out, "  {src}")?;
+        }
+    }
+
+    if !stats.largest_diffs.is_empty() {
+        writeln!(out, "\nLargest unsynced diffs:")?;
+        for (src, lines) in &stats.largest_diffs {
+            writeln!(out, "  {lines:>5} lines  {src}")?;
+        }
+    }
+
+    if let Some(timings) = &stats.last_timings {
+        writeln!(out, "\nLast --timings run:")?;
+        timings.write(None, out)?;
+    }
+    Ok(())
+}
+
+/// `bytes` in the largest binary unit that keeps it at least 1.
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn counts_modes_filesets_and_diffs() {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "hypr/hypr.conf", "a\n");
+        setup_pipeline_file(&fs, "git/config", "b\n");
+        fs.add_file(format!("{DOTFILES}/.staged/git/config"), "b\nc\n");
+        fs.add_file(format!("{DOTFILES}/bin/tool"), "#!/bin/sh\n");
+        let toml = format!(
+            "{}template = false\n\n[[files]]\nsrc = \"bin/tool\"\ndirect = true\n\n\
+             [filesets.desktop]\npatterns = [\"hypr/*\"]\n",
+            make_config_toml(&[("hypr/hypr.conf", None), ("git/config", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+
+        let stats = compute(&config, &fs).unwrap();
+        assert_eq!(stats.files, 3);
+        assert_eq!((stats.templated, stats.copied, stats.direct), (1, 1, 1));
+        assert_eq!(stats.by_fileset["desktop"], 1);
+        assert_eq!(stats.no_fileset, 2);
+        assert_eq!(stats.generated_files, 2);
+        assert_eq!(stats.generated_bytes, 4);
+        assert_eq!(stats.undeployed.len(), 3);
+        assert_eq!(stats.largest_diffs, [("git/config".to_string(), 1)]);
+        assert!(stats.last_timings.is_none());
+    }
+
+    #[test]
+    fn shows_last_recorded_timings() {
+        let fs = setup_fs();
+        fs.add_file(
+            format!("{DOTFILES}/.janus_state.toml"),
+            "[timings]\ncommand = \"generate\"\nat = 0\ntotal_us = 19120000\n\n\
+             [[timings.phases]]\nphase = \"secret\"\ntotal_us = 18200000\ncount = 4\n",
+        );
+        let config = write_and_load_config(&fs, &make_config_toml(&[]));
+        let mut out = Vec::new();
+        run(&config, &fs, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.contains("Last --timings run:\nTimings for `generate`: 19.12s\n  secret"),
+            "got: {out}"
+        );
+    }
+
+    #[test]
+    fn counts_secret_users() {
+        let fs = setup_fs();
+        fs.add_file(
+            format!("{DOTFILES}/secrets.toml"),
+            "[[secret]]\nname = \"token\"\nengine = \"1password\"\nreference = \"op://t\"\n\n\
+             [[secret]]\nname = \"unused\"\nengine = \"1password\"\nreference = \"op://u\"\n",
+        );
+        setup_pipeline_file(&fs, "a.conf", "{{ token }}");
+        setup_pipeline_file(&fs, "b.conf", "x");
+        let toml = format!(
+            "secrets = [\"secrets.toml\"]\n{}",
+            make_config_toml(&[("a.conf", None), ("b.conf", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+
+        let stats = compute(&config, &fs).unwrap();
+        assert_eq!(
+            stats.secret_usage,
+            [("token".to_string(), 1), ("unused".to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn sizes_use_binary_units() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
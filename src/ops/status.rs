@@ -3,16 +3,43 @@
 //! For each configured file, checks whether the source, generated, staged,
 //! and deployed versions exist and are in sync. Supports filtering by
 //! deployment state and diff presence.
+//!
+//! Files are also flagged stale when the pipeline looks forgotten: the source
+//! was edited after the last generate, or staged has differed from generated
+//! for longer than `stale_days` (default 7) without a stage or sync.
+//!
+//! A deployed file whose symlink an app replaced with a regular file (by
+//! saving to a temp file and renaming it over the target) shows as "target
+//! replaced"; `janus sync` offers to re-adopt its content.
+//!
+//! Staged files whose permissions no longer match the mode they were staged
+//! with (say, an ssh config that lost its `0600`) are flagged as mode drift;
+//! `janus repair --modes` restores them.
+//!
+//! `janus status -l` lays the same facts out in columns: which pipeline
+//! copies exist, changed lines, source and staged ages, and the target,
+//! truncated to fit the terminal.
+//!
+//! Content comparisons are cached by mtime and size (see
+//! [`crate::status_cache`]), so repeated runs only read files that changed.
+//! Files that do need comparing are read once each, then compared in
+//! parallel.
 
 use anyhow::{Result, bail};
+use rayon::prelude::*;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::io::Write;
 use std::path::Path;
-use tracing::info;
+use tracing::{debug, info};
 
-use crate::config::Config;
-use crate::paths::expand_tilde;
-use crate::platform::Fs;
-use crate::state::State;
+use crate::config::{Config, FileEntry};
+use crate::output::{DIM, GREEN, RED, RESET, YELLOW};
+use crate::paths::{collapse_tilde, expand_tilde};
+use crate::platform::{FileStamp, Fs};
+use crate::regions;
+use crate::state::{ActivityEntry, State, unix_now};
+use crate::status_cache::{Comparison, Stamps, StatusCache};
 
 /// Filtering options for the status display.
 pub struct StatusFilters {
@@ -22,8 +49,16 @@ pub struct StatusFilters {
     pub deployed: bool,
     /// Only show files that are NOT currently deployed.
     pub undeployed: bool,
+    /// Only show stale files.
+    pub stale: bool,
 }
 
+/// Detail for a deployed file whose symlink was replaced by a regular file.
+const TARGET_REPLACED: &str = "target replaced, run `janus sync` to re-adopt";
+
+/// Default for `stale_days`.
+const DEFAULT_STALE_DAYS: u64 = 7;
+
 /// Computed status for a single managed file.
 #[derive(Debug)]
 pub struct FileStatus {
@@ -35,6 +70,46 @@ pub struct FileStatus {
     pub detail: String,
     /// Number of changed lines between generated and staged (0 if identical or missing).
     pub changed_lines: usize,
+    /// Why the file looks forgotten, if it does (e.g. "source edited since
+    /// last generate").
+    pub stale: Option<String>,
+    /// The `dotfiles_dirs` layer providing the source, when layers are
+    /// configured (e.g. `~/team-dotfiles`).
+    pub layer: Option<String>,
+    /// Which pipeline copies no longer have the mode recorded at stage time
+    /// (e.g. "staged 0644, expected 0600").
+    pub mode_drift: Option<String>,
+    /// `path_maps` copies of a deployed file that aren't linked (e.g.
+    /// `~/.var/app/io.mpv.Mpv/config/mpv/mpv.conf`).
+    pub missing_mapped: Vec<String>,
+    /// Configured target path (e.g. `~/.config/hypr/hypr.conf`).
+    pub target: String,
+    /// Which pipeline copies exist. Direct files only have a source.
+    pub copies: Copies,
+    /// When the source was last modified, in seconds since the Unix epoch.
+    pub source_modified: Option<u64>,
+    /// When the staged copy was last modified, in seconds since the Unix
+    /// epoch. Always `None` for direct files.
+    pub staged_modified: Option<u64>,
+}
+
+/// Which of a file's pipeline copies exist.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Copies {
+    pub source: bool,
+    pub generated: bool,
+    pub staged: bool,
+}
+
+/// How [`run`] lays out each file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One line per file: deployed state and a detail string.
+    Short,
+    /// Aligned columns (`-l`). With `width`, the target and then the source
+    /// columns are shortened from the left so rows fit; `color` adds ANSI
+    /// colors.
+    Long { width: Option<usize>, color: bool },
 }
 
 /// Result of computing pipeline status for all files.
@@ -69,59 +144,144 @@ pub fn compute(
         });
     }
 
-    let dotfiles_dir = config.dotfiles_dir(fs);
     let generated_dir = config.generated_dir(fs);
     let staged_dir = config.staged_dir(fs);
-    let state = State::load(&dotfiles_dir, fs)?;
-
-    let mut statuses: Vec<FileStatus> = Vec::new();
+    let state = State::load(&config.state_dir(fs), fs)?;
+    let mut cache = StatusCache::load(&config.cache_dir(fs), fs);
+    let stale_days = config.stale_days.unwrap_or(DEFAULT_STALE_DAYS);
+    let now = unix_now();
 
+    // `Fs` isn't required to be `Sync`, so reads stay sequential; only the
+    // comparisons, which need nothing but the contents, run in parallel.
+    let mut probes = Vec::with_capacity(entries.len());
     for entry in &entries {
         let src = &entry.src;
-        let source_path = dotfiles_dir.join(src);
+        let source_path = config.source_path(src, fs);
         let target_path = expand_tilde(&entry.target(), fs);
 
-        let (deployed, detail, changed_lines) = if entry.direct {
+        let probe = if entry.direct {
             let deployed =
-                state.is_deployed(src) && is_janus_symlink(&target_path, &source_path, fs);
+                state.is_deployed(entry.key()) && is_janus_symlink(&target_path, &source_path, fs);
             let detail = if !fs.exists(&source_path) {
                 "source missing".to_string()
+            } else if deployed && direct_source_changed(&state, entry.key(), &source_path, fs) {
+                "source changed since deploy (direct)".to_string()
             } else if deployed {
                 "deployed (direct)".to_string()
             } else {
                 "ready to deploy (direct)".to_string()
             };
-            (deployed, detail, 0)
+            (*entry, deployed, Probe::Direct(detail))
         } else {
             let generated_path = generated_dir.join(src);
             let staged_path = staged_dir.join(src);
             let deployed =
-                state.is_deployed(src) && is_janus_symlink(&target_path, &staged_path, fs);
-            let detail = compute_detail(&source_path, &generated_path, &staged_path, deployed, fs);
-            let changed_lines = count_changed_lines(&generated_path, &staged_path, fs);
-            (deployed, detail, changed_lines)
+                state.is_deployed(entry.key()) && is_janus_symlink(&target_path, &staged_path, fs);
+            let stamps = Stamps::read(&source_path, &generated_path, &staged_path, fs);
+            let probe = match cache.get(src, &stamps) {
+                Some(comparison) => Probe::Known(stamps, Some(comparison)),
+                // Without a generated copy there's nothing to compare
+                None if stamps.generated.is_none() => Probe::Known(stamps, None),
+                None => Probe::Read(
+                    stamps,
+                    Contents::read(&source_path, &generated_path, &staged_path, fs),
+                ),
+            };
+            (*entry, deployed, probe)
         };
+        probes.push(probe);
+    }
 
-        let has_diff =
-            detail.contains("diff") || detail.contains("missing") || detail.contains("not yet");
+    let probes: Vec<(&FileEntry, bool, Probe)> = probes
+        .into_par_iter()
+        .map(|(entry, deployed, probe)| (entry, deployed, probe.compare()))
+        .collect();
 
-        // Apply filters
-        if filters.deployed && !deployed {
-            continue;
-        }
-        if filters.undeployed && deployed {
-            continue;
-        }
-        if filters.only_diffs && !has_diff {
-            continue;
+    let mut statuses: Vec<FileStatus> = Vec::new();
+    for (entry, deployed, probe) in probes {
+        let src = &entry.src;
+        let layer = layer_label(config, src, fs);
+        let replaced = !deployed
+            && target_replaced(&state, entry.key(), &expand_tilde(&entry.target(), fs), fs);
+        let missing_mapped = if deployed {
+            let link_source = if entry.direct {
+                config.source_path(src, fs)
+            } else {
+                staged_dir.join(src)
+            };
+            config
+                .mapped_targets(&entry.target(), fs)
+                .iter()
+                .filter(|path| !is_janus_symlink(path, &link_source, fs))
+                .map(|path| collapse_tilde(path, fs))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let (stamps, comparison) = match probe {
+            Probe::Direct(detail) => {
+                let detail = if replaced {
+                    TARGET_REPLACED.to_string()
+                } else {
+                    detail
+                };
+                if let Some(mut status) = filtered(filters, src, deployed, detail, 0, None, layer) {
+                    let source = fs.stamp(&config.source_path(src, fs)).ok();
+                    status.missing_mapped = missing_mapped;
+                    status.target = entry.target();
+                    status.copies.source = source.is_some();
+                    status.source_modified = source.map(seconds);
+                    statuses.push(status);
+                }
+                continue;
+            }
+            Probe::Known(stamps, comparison) => (stamps, comparison),
+            Probe::Compared(stamps, comparison) => {
+                cache.insert(src, stamps, comparison);
+                (stamps, Some(comparison))
+            }
+            Probe::Read(..) => unreachable!("every read probe is compared"),
+        };
+        let detail = if replaced {
+            TARGET_REPLACED.to_string()
+        } else {
+            compute_detail(&stamps, comparison, deployed)
+        };
+        let changed_lines = comparison.map_or(0, |c| c.changed_lines);
+        let stale = staleness(&stamps, comparison, state.activity(src), stale_days, now);
+        let drift = state
+            .activity(src)
+            .and_then(|a| a.mode)
+            .and_then(|expected| {
+                mode_drift(
+                    expected,
+                    &generated_dir.join(src),
+                    &staged_dir.join(src),
+                    fs,
+                )
+            });
+        if let Some(mut status) =
+            filtered(filters, src, deployed, detail, changed_lines, stale, layer)
+        {
+            status.mode_drift = drift;
+            status.missing_mapped = missing_mapped;
+            status.target = entry.target();
+            status.copies = Copies {
+                source: stamps.source.is_some(),
+                generated: stamps.generated.is_some(),
+                staged: stamps.staged.is_some(),
+            };
+            status.source_modified = stamps.source.map(seconds);
+            status.staged_modified = stamps.staged.map(seconds);
+            statuses.push(status);
         }
+    }
 
-        statuses.push(FileStatus {
-            src: src.clone(),
-            deployed,
-            detail,
-            changed_lines,
-        });
+    // Read-only runs are for inspecting, so they leave the cache as it was
+    if !config.readonly
+        && let Err(e) = cache.save(fs)
+    {
+        debug!("{e:#}");
     }
 
     let fileset_summary = if !config.filesets.is_empty() {
@@ -136,7 +296,133 @@ pub fn compute(
     })
 }
 
-/// Display pipeline status for the given files, applying optional filters.
+/// Build a file's status, or `None` if the filters exclude it.
+fn filtered(
+    filters: &StatusFilters,
+    src: &str,
+    deployed: bool,
+    detail: String,
+    changed_lines: usize,
+    stale: Option<String>,
+    layer: Option<String>,
+) -> Option<FileStatus> {
+    let has_diff = detail.contains("diff")
+        || detail.contains("missing")
+        || detail.contains("not yet")
+        || detail.contains("changed")
+        || detail.contains("replaced");
+
+    if filters.deployed && !deployed {
+        return None;
+    }
+    if filters.undeployed && deployed {
+        return None;
+    }
+    if filters.only_diffs && !has_diff {
+        return None;
+    }
+    if filters.stale && stale.is_none() {
+        return None;
+    }
+
+    Some(FileStatus {
+        src: src.to_string(),
+        deployed,
+        detail,
+        changed_lines,
+        stale,
+        layer,
+        mode_drift: None,
+        missing_mapped: Vec::new(),
+        target: String::new(),
+        copies: Copies::default(),
+        source_modified: None,
+        staged_modified: None,
+    })
+}
+
+fn seconds(stamp: FileStamp) -> u64 {
+    stamp.modified_ns / 1_000_000_000
+}
+
+/// Describe the pipeline copies whose permission bits differ from
+/// `expected`, or `None` if they all match. Missing copies are skipped.
+///
+/// A deployed target is a symlink to the staged copy, so checking staged
+/// covers it.
+fn mode_drift(
+    expected: u32,
+    generated_path: &Path,
+    staged_path: &Path,
+    fs: &impl Fs,
+) -> Option<String> {
+    let drifted: Vec<String> = [("generated", generated_path), ("staged", staged_path)]
+        .into_iter()
+        .filter_map(|(label, path)| {
+            let mode = fs.file_mode(path).ok()? & 0o7777;
+            (mode != expected).then(|| format!("{label} {mode:04o}"))
+        })
+        .collect();
+    if drifted.is_empty() {
+        return None;
+    }
+    Some(format!("{}, expected {expected:04o}", drifted.join(", ")))
+}
+
+/// The layer providing `src`, for display. `None` unless `dotfiles_dirs`
+/// is set, so single-directory setups see no change.
+fn layer_label(config: &Config, src: &str, fs: &impl Fs) -> Option<String> {
+    if config.dotfiles_dirs.is_empty() {
+        return None;
+    }
+    config
+        .source_layer(src, fs)
+        .map(|layer| collapse_tilde(&layer, fs))
+}
+
+/// Why a file's pipeline looks forgotten, if it does.
+///
+/// Stale when the source was edited after the last generate (per the
+/// generated file's mtime or the recorded generate time, whichever is later)
+/// and no longer matches, or when staged has differed from generated for
+/// more than `stale_days` since the last stage or sync (or, with no record,
+/// since staged was last written).
+fn staleness(
+    stamps: &Stamps,
+    comparison: Option<Comparison>,
+    activity: Option<&ActivityEntry>,
+    stale_days: u64,
+    now: u64,
+) -> Option<String> {
+    let comparison = comparison?;
+    let (source, generated) = (stamps.source?, stamps.generated?);
+    let recorded_generate = activity
+        .and_then(|a| a.last_generate)
+        .map_or(0, |secs| secs.saturating_mul(1_000_000_000));
+    if !comparison.source_matches_generated
+        && source.modified_ns > generated.modified_ns.max(recorded_generate)
+    {
+        return Some("source edited since last generate".to_string());
+    }
+
+    let staged = stamps.staged?;
+    if comparison.generated_matches_staged {
+        return None;
+    }
+    let since = activity
+        .and_then(|a| a.last_sync)
+        .unwrap_or(staged.modified_ns / 1_000_000_000);
+    let elapsed = now.saturating_sub(since);
+    (elapsed > stale_days.saturating_mul(86_400)).then(|| {
+        format!(
+            "staged differs, last synced {} day(s) ago",
+            elapsed / 86_400
+        )
+    })
+}
+
+/// Write pipeline status for the given files to `out`, applying optional
+/// filters.
 ///
 /// `--deployed` and `--undeployed` are mutually exclusive. `--only-diffs`
 /// can be combined with either.
@@ -144,7 +430,9 @@ pub fn run(
     config: &Config,
     files: Option<&[String]>,
     filters: StatusFilters,
+    format: Format,
     fs: &impl Fs,
+    out: &mut impl Write,
 ) -> Result<()> {
     let result = compute(config, files, &filters, fs)?;
 
@@ -153,33 +441,16 @@ pub fn run(
         return Ok(());
     }
 
-    // Find max src width for alignment
-    let max_src_len = result
-        .statuses
-        .iter()
-        .map(|s| s.src.len())
-        .max()
-        .unwrap_or(0);
-
-    for status in &result.statuses {
-        let state_str = if status.deployed {
-            "deployed  "
-        } else {
-            "undeployed"
-        };
-
-        println!(
-            "  {:<width$}  {}  ({})",
-            status.src,
-            state_str,
-            status.detail,
-            width = max_src_len,
-        );
+    match format {
+        Format::Short => write_short(&result.statuses, out)?,
+        Format::Long { width, color } => {
+            write_long(&result.statuses, width, color, unix_now(), out)?
+        }
     }
 
     if !result.fileset_summary.is_empty() {
-        println!();
-        println!("Filesets needing sync:");
+        writeln!(out)?;
+        writeln!(out, "Filesets needing sync:")?;
         let max_name_len = result
             .fileset_summary
             .iter()
@@ -187,58 +458,274 @@ pub fn run(
             .max()
             .unwrap_or(0);
         for (name, files_changed, total_lines) in &result.fileset_summary {
-            println!(
+            writeln!(
+                out,
                 "  {:<width$}  {} file(s) changed, {} line(s)",
                 name,
                 files_changed,
                 total_lines,
                 width = max_name_len,
-            );
+            )?;
         }
     }
 
     Ok(())
 }
 
+/// One line per file: source, deployed state, detail, and any notes.
+fn write_short(statuses: &[FileStatus], out: &mut impl Write) -> Result<()> {
+    // Find max src width for alignment
+    let max_src_len = statuses.iter().map(|s| s.src.len()).max().unwrap_or(0);
+
+    for status in statuses {
+        let state_str = if status.deployed {
+            "deployed  "
+        } else {
+            "undeployed"
+        };
+
+        let stale = match &status.stale {
+            Some(reason) => format!("  [stale: {reason}]"),
+            None => String::new(),
+        };
+        let layer = match &status.layer {
+            Some(layer) => format!("  [from {layer}]"),
+            None => String::new(),
+        };
+        let drift = match &status.mode_drift {
+            Some(drift) => format!("  [mode drift: {drift}]"),
+            None => String::new(),
+        };
+        let mapped: String = status
+            .missing_mapped
+            .iter()
+            .map(|path| format!("  [not linked at {path}]"))
+            .collect();
+        writeln!(
+            out,
+            "  {:<width$}  {}  ({}){}{}{}{}",
+            status.src,
+            state_str,
+            status.detail,
+            layer,
+            stale,
+            drift,
+            mapped,
+            width = max_src_len,
+        )?;
+    }
+    Ok(())
+}
+
+/// Narrowest a truncated column gets, so rows stay recognizable.
+const MIN_COLUMN_WIDTH: usize = 12;
+
+/// Aligned columns: source, which copies exist (S/G/St) and whether it's
+/// deployed (D), changed lines, source and staged ages, and target.
+///
+/// With `width`, the target column and then the source column are shortened
+/// from the left (keeping file names) until a row fits.
+fn write_long(
+    statuses: &[FileStatus],
+    width: Option<usize>,
+    color: bool,
+    now: u64,
+    out: &mut impl Write,
+) -> Result<()> {
+    let rows: Vec<(String, String, String)> = statuses
+        .iter()
+        .map(|s| {
+            let changed = match s.changed_lines {
+                0 => "-".to_string(),
+                n => n.to_string(),
+            };
+            (
+                changed,
+                s.source_modified.map_or("-".to_string(), |t| age(t, now)),
+                s.staged_modified.map_or("-".to_string(), |t| age(t, now)),
+            )
+        })
+        .collect();
+    let mut src_width = column_width("FILE", statuses.iter().map(|s| s.src.chars().count()));
+    let changed_width = column_width("LINES", rows.iter().map(|r| r.0.len()));
+    let source_width = column_width("SOURCE", rows.iter().map(|r| r.1.len()));
+    let staged_width = column_width("STAGED", rows.iter().map(|r| r.2.len()));
+    let mut target_width =
+        column_width("TARGET", statuses.iter().map(|s| s.target.chars().count()));
+
+    if let Some(width) = width {
+        // Indent, the "S G St D" ticks, and two spaces between columns
+        let fixed = 2 + 8 + changed_width + source_width + staged_width + 2 * 5;
+        let over = (fixed + src_width + target_width).saturating_sub(width);
+        let shrink = over.min(target_width.saturating_sub(MIN_COLUMN_WIDTH));
+        target_width -= shrink;
+        let over = over - shrink;
+        src_width -= over.min(src_width.saturating_sub(MIN_COLUMN_WIDTH));
+    }
+
+    let paint = |text: &str, code: &str| {
+        if color {
+            format!("{code}{text}{RESET}")
+        } else {
+            text.to_string()
+        }
+    };
+    writeln!(
+        out,
+        "  {:<src_width$}  S G St D  {:>changed_width$}  {:>source_width$}  {:>staged_width$}  TARGET",
+        "FILE", "LINES", "SOURCE", "STAGED",
+    )?;
+    for (status, (changed, source_age, staged_age)) in statuses.iter().zip(&rows) {
+        let tick = |present: bool, label: &str| {
+            if present {
+                paint(label, GREEN)
+            } else {
+                paint(&"-".repeat(label.len()), RED)
+            }
+        };
+        let ticks = [
+            tick(status.copies.source, "S"),
+            tick(status.copies.generated, "G"),
+            tick(status.copies.staged, "St"),
+            if status.deployed {
+                paint("D", GREEN)
+            } else {
+                paint("-", DIM)
+            },
+        ]
+        .join(" ");
+        let changed = format!("{changed:>changed_width$}");
+        let changed = if status.changed_lines > 0 {
+            paint(&changed, YELLOW)
+        } else {
+            changed
+        };
+        writeln!(
+            out,
+            "  {:<src_width$}  {ticks}  {changed}  {source_age:>source_width$}  {staged_age:>staged_width$}  {}",
+            truncate_left(&status.src, src_width),
+            truncate_left(&status.target, target_width),
+        )?;
+    }
+    Ok(())
+}
+
+/// Width of a column: its widest cell or its header.
+fn column_width(header: &str, cells: impl Iterator<Item = usize>) -> usize {
+    cells.max().unwrap_or(0).max(header.len())
+}
+
+/// `text` shortened to `max` characters by replacing its start with `…`.
+fn truncate_left(text: &str, max: usize) -> String {
+    let len = text.chars().count();
+    if len <= max {
+        return text.to_string();
+    }
+    let keep = max.saturating_sub(1);
+    let tail: String = text.chars().skip(len - keep).collect();
+    format!("…{tail}")
+}
+
+/// How long ago `then` was, coarsely (e.g. "5m ago", "3d ago").
+fn age(then: u64, now: u64) -> String {
+    match now.saturating_sub(then) {
+        secs if secs < 60 => "just now".to_string(),
+        secs if secs < 3_600 => format!("{}m ago", secs / 60),
+        secs if secs < 86_400 => format!("{}h ago", secs / 3_600),
+        secs => format!("{}d ago", secs / 86_400),
+    }
+}
+
+/// What the sequential pass learned about a file, before comparison.
+enum Probe {
+    /// Direct files have no pipeline copies; the detail is already known.
+    Direct(String),
+    /// Comparison came from the cache, or there was nothing to compare.
+    Known(Stamps, Option<Comparison>),
+    /// Cache miss: the contents, waiting to be compared.
+    Read(Stamps, Contents),
+    /// Freshly compared, to be stored in the cache.
+    Compared(Stamps, Comparison),
+}
+
+impl Probe {
+    /// Compare the contents of a [`Probe::Read`]; other probes pass through.
+    fn compare(self) -> Self {
+        match self {
+            Probe::Read(stamps, contents) => Probe::Compared(stamps, contents.compare()),
+            other => other,
+        }
+    }
+}
+
+/// A file's pipeline copies, each read at most once per run. `None` means
+/// the copy is missing or unreadable.
+struct Contents {
+    source: Option<Vec<u8>>,
+    generated: Option<Vec<u8>>,
+    staged: Option<Vec<u8>>,
+}
+
+impl Contents {
+    fn read(source_path: &Path, generated_path: &Path, staged_path: &Path, fs: &impl Fs) -> Self {
+        Contents {
+            source: fs.read(source_path).ok(),
+            generated: fs.read(generated_path).ok(),
+            staged: fs.read(staged_path).ok(),
+        }
+    }
+
+    /// Compare the copies by content. Unreadable copies never match.
+    fn compare(&self) -> Comparison {
+        let view = self.managed_staged();
+        let staged = if view.is_some() { &view } else { &self.staged };
+        Comparison {
+            source_matches_generated: both_match(&self.source, &self.generated),
+            generated_matches_staged: both_match(&self.generated, staged),
+            changed_lines: count_changed_lines(&self.generated, staged),
+        }
+    }
+
+    /// The staged copy limited to its managed regions, when the generated
+    /// copy has any (see [`regions::managed_view`]).
+    fn managed_staged(&self) -> Option<Vec<u8>> {
+        let generated = std::str::from_utf8(self.generated.as_deref()?).ok()?;
+        let staged = std::str::from_utf8(self.staged.as_deref()?).ok()?;
+        match regions::managed_view(generated, staged) {
+            Cow::Owned(view) => Some(view.into_bytes()),
+            Cow::Borrowed(_) => None,
+        }
+    }
+}
+
 /// Compute a human-readable detail string describing the file's pipeline state.
 ///
-/// Checks existence and content equality at each stage: source -> generated -> staged.
-/// Returns descriptions like "up to date", "not yet generated", "source -> generated diff".
-fn compute_detail(
-    source_path: &Path,
-    generated_path: &Path,
-    staged_path: &Path,
-    is_deployed: bool,
-    fs: &impl Fs,
-) -> String {
-    if !fs.exists(source_path) {
+/// Checks existence at each stage (source -> generated -> staged) from
+/// `stamps`, and content equality from `comparison`. Returns descriptions like
+/// "up to date", "not yet generated", "source -> generated diff".
+fn compute_detail(stamps: &Stamps, comparison: Option<Comparison>, is_deployed: bool) -> String {
+    if stamps.source.is_none() {
         return "source missing".to_string();
     }
 
-    if !fs.exists(generated_path) {
+    let Some(comparison) = comparison else {
         return "not yet generated".to_string();
-    }
-
-    // Check source vs generated
-    let source_matches_generated = files_match(source_path, generated_path, fs);
+    };
 
-    if !fs.exists(staged_path) {
-        if !source_matches_generated {
+    if stamps.staged.is_none() {
+        if !comparison.source_matches_generated {
             return "source -> generated diff, not yet staged".to_string();
         }
         return "not yet staged".to_string();
     }
 
-    // Check generated vs staged
-    let generated_matches_staged = files_match(generated_path, staged_path, fs);
-
     let mut parts = Vec::new();
 
-    if !source_matches_generated {
+    if !comparison.source_matches_generated {
         parts.push("source -> generated diff");
     }
 
-    if !generated_matches_staged {
+    if !comparison.generated_matches_staged {
         parts.push("generated -> staged diff");
     }
 
@@ -253,31 +740,25 @@ fn compute_detail(
     }
 }
 
-/// Compare two files by content. Returns false if either file can't be read.
-fn files_match(a: &Path, b: &Path, fs: &impl Fs) -> bool {
-    let Ok(content_a) = fs.read(a) else {
-        return false;
-    };
-    let Ok(content_b) = fs.read(b) else {
-        return false;
-    };
-    content_a == content_b
+fn both_match(a: &Option<Vec<u8>>, b: &Option<Vec<u8>>) -> bool {
+    matches!((a, b), (Some(a), Some(b)) if a == b)
 }
 
-/// Count the number of changed lines between generated and staged files.
+/// Count the number of changed lines between generated and staged contents.
 ///
-/// Returns 0 if either file is missing or they are identical.
-fn count_changed_lines(generated_path: &Path, staged_path: &Path, fs: &impl Fs) -> usize {
-    let Ok(generated) = fs.read_to_string(generated_path) else {
+/// Returns 0 if either is missing or not UTF-8, or they are identical.
+fn count_changed_lines(generated: &Option<Vec<u8>>, staged: &Option<Vec<u8>>) -> usize {
+    let (Some(generated), Some(staged)) = (generated, staged) else {
         return 0;
     };
-    let Ok(staged) = fs.read_to_string(staged_path) else {
+    let (Ok(generated), Ok(staged)) = (std::str::from_utf8(generated), std::str::from_utf8(staged))
+    else {
         return 0;
     };
     if generated == staged {
         return 0;
     }
-    let diff = similar::TextDiff::from_lines(&generated, &staged);
+    let diff = similar::TextDiff::from_lines(generated, staged);
     diff.ops()
         .iter()
         .map(|op| match *op {
@@ -322,11 +803,11 @@ fn fileset_sync_summary(config: &Config, statuses: &[FileStatus]) -> Vec<(String
         .into_iter()
         .map(|(name, (files, lines))| (name.to_string(), files, lines))
         .collect();
-    result.sort_by(|a, b| b.2.cmp(&a.2));
+    result.sort_by_key(|r| std::cmp::Reverse(r.2));
     result
 }
 
-use super::is_janus_symlink;
+use super::{direct_source_changed, is_janus_symlink, target_replaced};
 
 #[cfg(test)]
 mod tests {
@@ -338,6 +819,7 @@ mod tests {
             only_diffs,
             deployed,
             undeployed,
+            stale: false,
         }
     }
 
@@ -361,6 +843,250 @@ mod tests {
         assert_eq!(result.statuses[0].changed_lines, 0);
     }
 
+    #[test]
+    fn run_writes_aligned_rows() {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "a.conf", "content");
+        fs.add_file(format!("{DOTFILES}/longer.conf"), "content");
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[("a.conf", None), ("longer.conf", None)]),
+        );
+        let mut out = Vec::new();
+        run(
+            &config,
+            None,
+            make_filters(false, false, false),
+            Format::Short,
+            &fs,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "  a.conf       undeployed  (ready to deploy)\n  longer.conf  undeployed  (not yet generated)\n"
+        );
+    }
+
+    fn long_rows(width: Option<usize>) -> Vec<String> {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "a.conf", "content");
+        fs.write(Path::new(&format!("{DOTFILES}/.staged/a.conf")), b"edited")
+            .unwrap();
+        fs.add_file(format!("{DOTFILES}/longer.conf"), "content");
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[("a.conf", None), ("longer.conf", None)]),
+        );
+        let result = compute(&config, None, &make_filters(false, false, false), &fs).unwrap();
+        let mut out = Vec::new();
+        // The fake clock's mtimes are all near the epoch
+        write_long(&result.statuses, width, false, 0, &mut out).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn long_format_columns() {
+        assert_eq!(
+            long_rows(None),
+            vec![
+                "  FILE         S G St D  LINES    SOURCE    STAGED  TARGET",
+                "  a.conf       S G St -      2  just now  just now  ~/.config/a.conf",
+                "  longer.conf  S - -- -      -  just now         -  ~/.config/longer.conf",
+            ]
+        );
+    }
+
+    #[test]
+    fn long_format_truncates_target_to_width() {
+        let rows = long_rows(Some(64));
+        assert!(
+            rows.iter().all(|row| row.chars().count() <= 64),
+            "{rows:#?}"
+        );
+        assert!(rows[2].ends_with("  …longer.conf"), "{rows:#?}");
+        assert!(rows[2].starts_with("  longer.conf  "), "{rows:#?}");
+    }
+
+    #[test]
+    fn ages_are_coarse() {
+        assert_eq!(age(100, 130), "just now");
+        assert_eq!(age(0, 600), "10m ago");
+        assert_eq!(age(0, 7_200), "2h ago");
+        assert_eq!(age(0, 3 * 86_400), "3d ago");
+        // Clock skew: a future mtime reads as just now
+        assert_eq!(age(500, 0), "just now");
+    }
+
+    #[test]
+    fn unchanged_files_skip_content_reads() {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "a.conf", "content");
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        let filters = make_filters(false, false, false);
+
+        compute(&config, None, &filters, &fs).unwrap();
+        let before = fs.read_count();
+        let result = compute(&config, None, &filters, &fs).unwrap();
+        // Only the state file and the cache itself are read
+        assert_eq!(fs.read_count() - before, 2);
+        assert_eq!(result.statuses[0].detail, "ready to deploy");
+    }
+
+    #[test]
+    fn cache_miss_reads_each_copy_once() {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "a.conf", "content");
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        let filters = make_filters(false, false, false);
+        compute(&config, None, &filters, &fs).unwrap();
+
+        fs.write(
+            Path::new(&format!("{DOTFILES}/.staged/a.conf")),
+            b"edited\n",
+        )
+        .unwrap();
+        let before = fs.read_count();
+        let result = compute(&config, None, &filters, &fs).unwrap();
+        // State file and cache, then source, generated, and staged once each
+        assert_eq!(fs.read_count() - before, 5);
+        assert_eq!(result.statuses[0].detail, "generated -> staged diff");
+    }
+
+    #[test]
+    fn stale_when_source_edited_after_generate() {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "a.conf", "content");
+        fs.write(Path::new(&format!("{DOTFILES}/a.conf")), b"edited")
+            .unwrap();
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        let result = compute(&config, None, &make_filters(false, false, false), &fs).unwrap();
+        assert_eq!(
+            result.statuses[0].stale.as_deref(),
+            Some("source edited since last generate")
+        );
+    }
+
+    #[test]
+    fn stale_drift_depends_on_last_sync() {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "a.conf", "content");
+        setup_pipeline_file(&fs, "b.conf", "content");
+        for src in ["a.conf", "b.conf"] {
+            fs.write(Path::new(&format!("{DOTFILES}/.staged/{src}")), b"edited")
+                .unwrap();
+        }
+        let state_toml = format!(
+            "[[activity]]\nsrc = \"a.conf\"\nlast_sync = 0\n\n\
+             [[activity]]\nsrc = \"b.conf\"\nlast_sync = {}\n",
+            unix_now()
+        );
+        fs.add_file(format!("{DOTFILES}/.janus_state.toml"), state_toml);
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[("a.conf", None), ("b.conf", None)]),
+        );
+
+        let mut filters = make_filters(false, false, false);
+        filters.stale = true;
+        let result = compute(&config, None, &filters, &fs).unwrap();
+        assert_eq!(result.statuses.len(), 1);
+        assert_eq!(result.statuses[0].src, "a.conf");
+        let reason = result.statuses[0].stale.as_deref().unwrap();
+        assert!(
+            reason.starts_with("staged differs, last synced"),
+            "got: {reason}"
+        );
+    }
+
+    #[test]
+    fn reports_unlinked_mapped_copies() {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "mpv/mpv.conf", "vo=gpu");
+        let staged = format!("{DOTFILES}/.staged/mpv/mpv.conf");
+        fs.add_symlink("/home/test/.config/mpv/mpv.conf", &staged);
+        let state_toml =
+            "[[deployed]]\nsrc = \"mpv/mpv.conf\"\ntarget = \"~/.config/mpv/mpv.conf\"\n";
+        fs.add_file(format!("{DOTFILES}/.janus_state.toml"), state_toml);
+        let toml = format!(
+            "{}\n[path_maps]\n\"~/.config/mpv\" = [\"~/.var/app/io.mpv.Mpv/config/mpv\"]\n",
+            make_config_toml(&[("mpv/mpv.conf", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let filters = make_filters(false, false, false);
+
+        let result = compute(&config, None, &filters, &fs).unwrap();
+        assert_eq!(
+            result.statuses[0].missing_mapped,
+            vec!["~/.var/app/io.mpv.Mpv/config/mpv/mpv.conf"]
+        );
+
+        fs.add_symlink(
+            "/home/test/.var/app/io.mpv.Mpv/config/mpv/mpv.conf",
+            &staged,
+        );
+        let result = compute(&config, None, &filters, &fs).unwrap();
+        assert!(result.statuses[0].missing_mapped.is_empty());
+    }
+
+    #[test]
+    fn reports_mode_drift() {
+        let fs = setup_fs();
+        fs.add_file_with_mode(format!("{DOTFILES}/.generated/ssh_config"), "Host *", 0o600);
+        fs.add_file(format!("{DOTFILES}/ssh_config"), "Host *");
+        let config = write_and_load_config(&fs, &make_config_toml(&[("ssh_config", None)]));
+        crate::ops::stage::run(&config, None, false, &fs).unwrap();
+        let filters = make_filters(false, false, false);
+        let result = compute(&config, None, &filters, &fs).unwrap();
+        assert_eq!(result.statuses[0].mode_drift, None);
+
+        let staged = format!("{DOTFILES}/.staged/ssh_config");
+        fs.set_file_mode(Path::new(&staged), 0o644).unwrap();
+        let result = compute(&config, None, &filters, &fs).unwrap();
+        assert_eq!(
+            result.statuses[0].mode_drift.as_deref(),
+            Some("staged 0644, expected 0600")
+        );
+    }
+
+    #[test]
+    fn readonly_run_leaves_cache_unwritten() {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "a.conf", "content");
+        let mut config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        config.readonly = true;
+        compute(&config, None, &make_filters(false, false, false), &fs).unwrap();
+        let cache_path = config.cache_dir(&fs).join(crate::status_cache::CACHE_FILE);
+        assert!(!fs.exists(&cache_path));
+    }
+
+    #[test]
+    fn cache_invalidated_by_staged_edit() {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "a.conf", "content");
+        setup_pipeline_file(&fs, "b.conf", "content");
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[("a.conf", None), ("b.conf", None)]),
+        );
+        let filters = make_filters(false, false, false);
+        compute(&config, None, &filters, &fs).unwrap();
+
+        fs.write(
+            Path::new(&format!("{DOTFILES}/.staged/a.conf")),
+            b"edited\n",
+        )
+        .unwrap();
+        let result = compute(&config, None, &filters, &fs).unwrap();
+        assert_eq!(result.statuses[0].detail, "generated -> staged diff");
+        assert!(result.statuses[0].changed_lines > 0);
+        assert_eq!(result.statuses[1].detail, "ready to deploy");
+    }
+
     #[test]
     fn not_generated() {
         let fs = setup_fs();
@@ -581,6 +1307,24 @@ patterns = ["hypr/*"]
         assert_eq!(result.statuses[0].changed_lines, 0);
     }
 
+    #[test]
+    fn replaced_target_reported() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.conf"), "same");
+        fs.add_file(format!("{DOTFILES}/.generated/a.conf"), "same");
+        fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "same");
+        fs.add_file("/home/test/.config/a.conf", "rewritten by the app");
+        fs.add_file(
+            format!("{DOTFILES}/.janus_state.toml"),
+            "[[deployed]]\nsrc = \"a.conf\"\ntarget = \"~/.config/a.conf\"\n",
+        );
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        let result = compute(&config, None, &make_filters(true, false, false), &fs).unwrap();
+        assert_eq!(result.statuses.len(), 1);
+        assert!(!result.statuses[0].deployed);
+        assert_eq!(result.statuses[0].detail, TARGET_REPLACED);
+    }
+
     #[test]
     fn direct_file_ready() {
         let fs = setup_fs();
@@ -594,4 +1338,44 @@ patterns = ["hypr/*"]
         assert!(!result.statuses[0].deployed);
         assert_eq!(result.statuses[0].detail, "ready to deploy (direct)");
     }
+
+    #[test]
+    fn direct_file_changed_since_deploy() {
+        let fs = setup_fs();
+        let source = format!("{DOTFILES}/direct.conf");
+        fs.add_file(&source, "edited through the symlink");
+        fs.add_symlink("/home/test/.config/direct.conf", &source);
+        let state_toml = format!(
+            "[[deployed]]\nsrc = \"direct.conf\"\ntarget = \"~/.config/direct.conf\"\nsource_hash = \"{}\"\n",
+            crate::state::content_hash(b"content")
+        );
+        fs.add_file(format!("{DOTFILES}/.janus_state.toml"), state_toml);
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\n\n[[files]]\nsrc = \"direct.conf\"\ntarget = \"~/.config/direct.conf\"\ndirect = true\ntemplate = false\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let result = compute(&config, None, &make_filters(true, false, false), &fs).unwrap();
+        assert_eq!(result.statuses.len(), 1);
+        assert!(result.statuses[0].deployed);
+        assert_eq!(
+            result.statuses[0].detail,
+            "source changed since deploy (direct)"
+        );
+    }
+
+    #[test]
+    fn shows_providing_layer() {
+        let fs = setup_fs();
+        fs.add_file("/home/test/team/a.conf", "team");
+        fs.add_file(format!("{DOTFILES}/b.conf"), "mine");
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\ndotfiles_dirs = [\"~/team\"]\n\n\
+             [[files]]\nsrc = \"a.conf\"\n\n[[files]]\nsrc = \"b.conf\"\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let result = compute(&config, None, &make_filters(false, false, false), &fs).unwrap();
+        let layers: Vec<Option<&str>> =
+            result.statuses.iter().map(|s| s.layer.as_deref()).collect();
+        assert_eq!(layers, vec![Some("~/team"), Some("~/dotfiles")]);
+    }
 }
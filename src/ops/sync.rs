@@ -5,24 +5,70 @@
 //! (common ancestor) vs staged (current deployed content) and lets the user
 //! choose per-hunk whether to apply the staged change back to the source.
 //!
+//! When the generated copy has managed regions (see [`crate::regions`]),
+//! only edits inside them are offered; the rest of the file belongs to
+//! whatever app or user changed it.
+//!
+//! Direct files symlink the source itself, so edits already land there; sync
+//! only reports the ones whose source changed since deploy.
+//!
+//! Some apps save by writing a temp file and renaming it over the target,
+//! replacing the symlink, so their edits never reach `.staged/`. Sync
+//! notices a deployed target that's now a regular file and offers to copy
+//! it back into the staged copy (the source, for direct files) and link it
+//! again; its changes are then reviewed like any other.
+//!
+//! Files with changes are walked grouped by fileset. When there's more than
+//! one, sync first lists them with their hunk counts and asks, fileset by
+//! fileset, which to review, so whole files or filesets can be skipped
+//! before any hunk is shown. It ends with a per-file report of what was
+//! applied and skipped.
+//!
+//! Skipped hunks are remembered in state (see
+//! [`State::skipped_hunks`]), keyed by a hash of their generated and
+//! staged lines, and skipped without asking on later syncs until either
+//! side of them changes. `--reset-decisions` forgets them for the selected
+//! files, so every hunk is asked about again.
+//!
+//! An app can write through the symlink while sync is prompting, which
+//! would make the reviewed hunks stale. Before writing a source, sync
+//! re-reads the staged copy; if it changed, the user can review the file
+//! again against the new content or leave its source untouched.
+//!
 //! Uses error-collection strategy: processes all files and reports failures
 //! at the end rather than bailing on the first error.
 
 use anyhow::{Context, Result};
 use similar::DiffOp;
 use std::collections::HashSet;
+use std::io::{self, Write};
+use std::path::Path;
 use tracing::{debug, info, warn};
 
+use super::diff::{unified_diff, write_colored};
+use super::{direct_source_changed, target_replaced};
 use crate::config::{Config, FileEntry};
+use crate::output::{GREEN, RED, RESET};
+use crate::paths::expand_tilde;
+use crate::plan;
 use crate::platform::{Fs, Prompter};
+use crate::regions;
+use crate::secrets::SecretMask;
+use crate::state::{State, content_hash};
 
-/// Run interactive sync for the given file patterns (or all files).
+/// Run interactive sync for the given file patterns (or all files),
+/// writing hunks, summaries, and the closing report to `out`. With
+/// `reset_decisions`, hunks skipped at earlier syncs are asked about again.
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     config: &Config,
     files: Option<&[String]>,
+    reset_decisions: bool,
     dry_run: bool,
     fs: &impl Fs,
     prompter: &impl Prompter,
+    mask: &SecretMask,
+    out: &mut impl Write,
 ) -> Result<()> {
     let entries = config.filter_files(files);
     if entries.is_empty() {
@@ -31,44 +77,420 @@ pub fn run(
         return Ok(());
     }
 
+    let state = State::load(&config.state_dir(fs), fs)?;
+    let remembered = |src: &str| -> &[String] {
+        if reset_decisions {
+            &[]
+        } else {
+            state.skipped_hunks(src)
+        }
+    };
+    let mut decisions = Vec::new();
     let mut errors: Vec<(String, anyhow::Error)> = Vec::new();
     let mut modified = 0usize;
+    let mut synced = Vec::new();
+    let mut direct_changed = 0usize;
+    let mut pending = Vec::new();
     for entry in &entries {
+        let target_path = expand_tilde(&entry.target(), fs);
+        if target_replaced(&state, entry.key(), &target_path, fs)
+            && let Err(e) = readopt_target(config, entry, &target_path, dry_run, fs, prompter, out)
+        {
+            warn!("Failed to re-adopt {}: {e:#}", target_path.display());
+            errors.push((entry.src.clone(), e));
+            continue;
+        }
         if entry.direct {
-            debug!("Skipping direct file: {}", entry.src);
+            // Direct files are edited in place, so there's nothing to merge;
+            // just point out the ones that changed since deploy.
+            let source_path = config.source_path(&entry.src, fs);
+            if direct_source_changed(&state, entry.key(), &source_path, fs) {
+                info!("{} changed since deploy (direct file)", entry.src);
+                direct_changed += 1;
+            } else {
+                debug!("Direct file unchanged: {}", entry.src);
+            }
+            continue;
+        }
+        match count_hunks(config, entry, remembered(&entry.src), fs) {
+            Some(0) => {
+                debug!(
+                    "{}: generated and staged are identical, skipping",
+                    entry.src
+                );
+                synced.push(entry.src.clone());
+            }
+            // Unreadable copies stay in so sync_file reports why
+            hunks => pending.push(Pending {
+                entry,
+                fileset: config
+                    .matching_filesets_named(&entry.src)
+                    .into_iter()
+                    .map(|(name, _)| name)
+                    .min(),
+                hunks,
+            }),
+        }
+    }
+
+    // Group by fileset (alphabetically, files in no fileset last), keeping
+    // config order within each group
+    pending.sort_by_key(|p| (p.fileset.is_none(), p.fileset));
+    let skip = if pending.len() > 1 {
+        print_summary(&pending, out)?;
+        if dry_run {
+            HashSet::new()
+        } else {
+            choose_skips(&pending, prompter)?
+        }
+    } else {
+        HashSet::new()
+    };
+
+    let mut report = Vec::new();
+    for p in &pending {
+        let src = &p.entry.src;
+        if skip.contains(src) {
+            report.push((src.as_str(), FileReport::Skipped));
             continue;
         }
-        match sync_file(config, entry, dry_run, fs, prompter) {
-            Ok(true) => modified += 1,
-            Ok(false) => {}
+        let mut skipped_keys = Vec::new();
+        match sync_file(
+            config,
+            p.entry,
+            remembered(src),
+            &mut skipped_keys,
+            dry_run,
+            fs,
+            prompter,
+            mask,
+            out,
+        ) {
+            Ok(hunks) => {
+                decisions.push((src.as_str(), skipped_keys));
+                modified += hunks.written as usize;
+                synced.push(src.clone());
+                report.push((src.as_str(), FileReport::Reviewed(hunks)));
+            }
             Err(e) => {
-                warn!("Failed to sync {}: {e:#}", entry.src);
-                errors.push((entry.src.clone(), e));
+                warn!("Failed to sync {src}: {e:#}");
+                errors.push((src.clone(), e));
+                report.push((src.as_str(), FileReport::Failed));
             }
         }
     }
+    if !report.is_empty() {
+        print_report(&report, dry_run, out)?;
+    }
+
+    if !dry_run && (reset_decisions || !decisions.is_empty()) {
+        let mut state = State::load(&config.state_dir(fs), fs)?;
+        if reset_decisions {
+            for entry in &entries {
+                state.set_skipped_hunks(&entry.src, Vec::new());
+            }
+        }
+        for (src, keys) in decisions {
+            state.set_skipped_hunks(src, keys);
+        }
+        state.save(fs)?;
+    }
+    if !dry_run {
+        super::record_activity(config, &synced, State::record_sync, fs);
+    }
 
     if modified > 0 {
         info!("Modified {} source file(s)", modified);
-        println!("\nRun `janus generate` to re-render updated templates.");
-    } else if errors.is_empty() {
+        writeln!(
+            out,
+            "\nRun `janus generate` to re-render updated templates."
+        )?;
+    }
+    if direct_changed > 0 {
+        writeln!(
+            out,
+            "\n{direct_changed} direct file(s) changed in place; commit them to keep the changes."
+        )?;
+    }
+    if modified == 0 && direct_changed == 0 && errors.is_empty() {
         info!("No files needed syncing");
     }
 
     if !errors.is_empty() {
         info!("{} file(s) failed to sync", errors.len());
-        let mut msg = format!("Failed to sync {} file(s):", errors.len());
-        for (src, e) in &errors {
-            msg.push_str(&format!("\n  {src}: {e:#}"));
+        return Err(super::FileErrors::new("sync", &errors).into());
+    }
+
+    Ok(())
+}
+
+/// A file with staged changes waiting to be reviewed.
+struct Pending<'a> {
+    entry: &'a FileEntry,
+    /// First matching fileset by name, used for grouping.
+    fileset: Option<&'a str>,
+    /// Hunks between generated and staged, or `None` if a copy couldn't
+    /// be read.
+    hunks: Option<usize>,
+}
+
+/// What reviewing one file's hunks did.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct HunkCounts {
+    applied: usize,
+    skipped: usize,
+    /// Skipped without asking, as at an earlier sync.
+    remembered: usize,
+    /// Whether the source was rewritten.
+    written: bool,
+}
+
+/// How each file ended up, for the closing report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileReport {
+    Reviewed(HunkCounts),
+    Skipped,
+    Failed,
+}
+
+/// Offer to copy a target that replaced its symlink back into the copy it
+/// should link to (staged, or the source for direct files) and link it
+/// again.
+///
+/// A staged copy is reviewed hunk by hunk afterwards, but a direct file's
+/// source is overwritten outright, and it may have moved on since deploy
+/// (a `git pull`, say). So for direct files the diff from the source to the
+/// target is shown first, and the prompt defaults to no.
+fn readopt_target(
+    config: &Config,
+    entry: &FileEntry,
+    target_path: &Path,
+    dry_run: bool,
+    fs: &impl Fs,
+    prompter: &impl Prompter,
+    out: &mut impl Write,
+) -> Result<()> {
+    let link_source = if entry.direct {
+        config.source_path(&entry.src, fs)
+    } else {
+        config.staged_dir(fs).join(&entry.src)
+    };
+    warn!(
+        "{} was replaced by a regular file, so its changes never reached {}",
+        target_path.display(),
+        entry.src
+    );
+    if dry_run {
+        info!(
+            "[dry-run] Would offer to re-adopt {} into {}",
+            target_path.display(),
+            link_source.display()
+        );
+        plan::action(
+            "readopt",
+            &target_path.to_string_lossy(),
+            &link_source.to_string_lossy(),
+            "target replaced by a regular file",
+        );
+        return Ok(());
+    }
+    if entry.direct {
+        match (
+            fs.read_to_string(&link_source),
+            fs.read_to_string(target_path),
+        ) {
+            (Ok(source), Ok(target)) if source == target => {
+                writeln!(out, "Its content is identical to {}", entry.src)?;
+            }
+            (Ok(source), Ok(target)) => write_colored(
+                out,
+                &unified_diff(
+                    &source,
+                    &target,
+                    &format!("source/{}", entry.src),
+                    &format!("target/{}", entry.src),
+                ),
+            )?,
+            _ => writeln!(out, "(binary or unreadable content, no diff shown)")?,
         }
-        anyhow::bail!(msg);
     }
+    let question = if entry.direct {
+        format!(
+            "Overwrite {} with {} and link it again?",
+            entry.src,
+            target_path.display()
+        )
+    } else {
+        format!(
+            "Re-adopt {} into {} and link it again?",
+            target_path.display(),
+            entry.src
+        )
+    };
+    if !prompter.confirm(&question, !entry.direct)? {
+        return Ok(());
+    }
+    let content = fs
+        .read(target_path)
+        .with_context(|| format!("Failed to read {}", target_path.display()))?;
+    fs.write(&link_source, &content)
+        .with_context(|| format!("Failed to write {}", link_source.display()))?;
+    fs.remove_file(target_path)
+        .with_context(|| format!("Failed to remove {}", target_path.display()))?;
+    fs.symlink(&link_source, target_path).with_context(|| {
+        format!(
+            "Failed to create symlink: {} -> {}",
+            target_path.display(),
+            link_source.display()
+        )
+    })?;
+    info!(
+        "Re-adopted {} and linked it to {}",
+        target_path.display(),
+        link_source.display()
+    );
+    Ok(())
+}
+
+/// Read a generated or staged copy as the text its source uses, undoing the
+/// entry's `newline` and `encoding`.
+fn read_output(entry: &FileEntry, path: &Path, fs: &impl Fs) -> Result<String> {
+    super::generate::decode_output(entry, fs.read(path)?)
+}
+
+/// Count the hunks between a file's generated and staged copies without
+/// prompting, leaving out the `remembered` ones. `None` if either copy is
+/// missing or unreadable.
+fn count_hunks(
+    config: &Config,
+    entry: &FileEntry,
+    remembered: &[String],
+    fs: &impl Fs,
+) -> Option<usize> {
+    let generated = read_output(entry, &config.generated_dir(fs).join(&entry.src), fs).ok()?;
+    let staged = read_output(entry, &config.staged_dir(fs).join(&entry.src), fs).ok()?;
+    let staged = regions::managed_view(&generated, &staged);
+    let diff = similar::TextDiff::from_lines(generated.as_str(), &*staged);
+    let generated_lines = split_lines_inclusive(&generated);
+    let staged_lines = split_lines_inclusive(&staged);
+    Some(
+        diff.ops()
+            .iter()
+            .filter(|op| !matches!(op, DiffOp::Equal { .. }))
+            .filter(|op| {
+                let key = hunk_key(
+                    &generated_lines[op.old_range()],
+                    &staged_lines[op.new_range()],
+                );
+                !remembered.contains(&key)
+            })
+            .count(),
+    )
+}
+
+/// What a skipped hunk is remembered by: a hash of the generated lines it
+/// replaces and the staged lines it brings.
+fn hunk_key(generated: &[&str], staged: &[&str]) -> String {
+    let mut bytes = Vec::new();
+    for line in generated {
+        bytes.push(b'-');
+        bytes.extend_from_slice(line.as_bytes());
+    }
+    for line in staged {
+        bytes.push(b'+');
+        bytes.extend_from_slice(line.as_bytes());
+    }
+    content_hash(&bytes)
+}
 
+fn fileset_label(fileset: Option<&str>) -> &str {
+    fileset.unwrap_or("(no fileset)")
+}
+
+fn hunk_label(hunks: Option<usize>) -> String {
+    match hunks {
+        Some(n) => format!("{n} hunk(s)"),
+        None => "unreadable".to_string(),
+    }
+}
+
+/// List the files about to be reviewed, grouped by fileset.
+fn print_summary(pending: &[Pending], out: &mut impl Write) -> io::Result<()> {
+    let total: usize = pending.iter().filter_map(|p| p.hunks).sum();
+    writeln!(
+        out,
+        "{} file(s) with {total} hunk(s) to sync:",
+        pending.len()
+    )?;
+    let width = pending.iter().map(|p| p.entry.src.len()).max().unwrap_or(0);
+    for group in pending.chunk_by(|a, b| a.fileset == b.fileset) {
+        writeln!(out, "  {}:", fileset_label(group[0].fileset))?;
+        for p in group {
+            writeln!(out, "    {:<width$}  {}", p.entry.src, hunk_label(p.hunks))?;
+        }
+    }
     Ok(())
 }
 
-/// Check if a line contains Tera template syntax.
-fn has_tera_syntax(line: &str) -> bool {
+/// Ask, fileset by fileset, which files to review before any hunk is
+/// shown. Returns the sources to skip.
+fn choose_skips(pending: &[Pending], prompter: &impl Prompter) -> Result<HashSet<String>> {
+    let mut skip = HashSet::new();
+    for group in pending.chunk_by(|a, b| a.fileset == b.fileset) {
+        let hunks: usize = group.iter().filter_map(|p| p.hunks).sum();
+        let prompt = format!(
+            "{} ({} file(s), {hunks} hunk(s))",
+            fileset_label(group[0].fileset),
+            group.len()
+        );
+        match prompter.select(&prompt, &["Review", "Skip all", "Choose files"], 0)? {
+            0 => {}
+            1 => skip.extend(group.iter().map(|p| p.entry.src.clone())),
+            _ => {
+                for p in group {
+                    let prompt = format!("{} ({})", p.entry.src, hunk_label(p.hunks));
+                    if prompter.select(&prompt, &["Review", "Skip"], 0)? == 1 {
+                        skip.insert(p.entry.src.clone());
+                    }
+                }
+            }
+        }
+    }
+    Ok(skip)
+}
+
+/// Print what happened to each reviewed or skipped file.
+fn print_report(
+    report: &[(&str, FileReport)],
+    dry_run: bool,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "
+Sync report:"
+    )?;
+    let width = report.iter().map(|(src, _)| src.len()).max().unwrap_or(0);
+    for (src, outcome) in report {
+        let summary = match outcome {
+            FileReport::Reviewed(_) if dry_run => "previewed".to_string(),
+            FileReport::Reviewed(hunks) if hunks.remembered > 0 => format!(
+                "{} applied, {} skipped, {} skipped earlier",
+                hunks.applied, hunks.skipped, hunks.remembered
+            ),
+            FileReport::Reviewed(hunks) => {
+                format!("{} applied, {} skipped", hunks.applied, hunks.skipped)
+            }
+            FileReport::Skipped => "skipped".to_string(),
+            FileReport::Failed => "failed".to_string(),
+        };
+        writeln!(out, "  {src:<width$}  {summary}")?;
+    }
+    Ok(())
+}
+
+/// Check if a line (or any text) contains Tera template syntax.
+pub(super) fn has_tera_syntax(line: &str) -> bool {
     line.contains("{{") || line.contains("{%") || line.contains("{#")
 }
 
@@ -91,19 +513,26 @@ fn split_lines_inclusive(text: &str) -> Vec<&str> {
     lines
 }
 
-/// Sync a single file. Returns `Ok(true)` if the source was modified.
+/// Sync a single file, returning how many hunks were applied and skipped
+/// and whether the source was rewritten. Hunks in `remembered` are skipped
+/// without asking; the keys of every hunk skipped either way are pushed to
+/// `skipped_keys`.
+#[allow(clippy::too_many_arguments)]
 fn sync_file(
     config: &Config,
     entry: &FileEntry,
+    remembered: &[String],
+    skipped_keys: &mut Vec<String>,
     dry_run: bool,
     fs: &impl Fs,
     prompter: &impl Prompter,
-) -> Result<bool> {
-    let dotfiles_dir = config.dotfiles_dir(fs);
+    mask: &SecretMask,
+    out: &mut impl Write,
+) -> Result<HunkCounts> {
     let generated_dir = config.generated_dir(fs);
     let staged_dir = config.staged_dir(fs);
 
-    let source_path = dotfiles_dir.join(&entry.src);
+    let source_path = config.source_path(&entry.src, fs);
     let generated_path = generated_dir.join(&entry.src);
     let staged_path = staged_dir.join(&entry.src);
 
@@ -121,25 +550,26 @@ fn sync_file(
     let source = fs
         .read_to_string(&source_path)
         .with_context(|| format!("Failed to read source: {}", source_path.display()))?;
-    let generated = fs
-        .read_to_string(&generated_path)
+    let generated = read_output(entry, &generated_path, fs)
         .with_context(|| format!("Failed to read generated: {}", generated_path.display()))?;
-    let staged = fs
-        .read_to_string(&staged_path)
+    let staged = read_output(entry, &staged_path, fs)
         .with_context(|| format!("Failed to read staged: {}", staged_path.display()))?;
 
+    // Edits outside managed regions aren't synced
+    let view = regions::managed_view(&generated, &staged);
+
     // No changes to sync
-    if generated == staged {
+    if generated == *view {
         debug!(
             "{}: generated and staged are identical, skipping",
             entry.src
         );
-        return Ok(false);
+        return Ok(HunkCounts::default());
     }
 
     let source_lines = split_lines_inclusive(&source);
     let generated_lines = split_lines_inclusive(&generated);
-    let staged_lines = split_lines_inclusive(&staged);
+    let staged_lines = split_lines_inclusive(&view);
 
     // For template files, check for structural mismatch
     if entry.template && source_lines.len() != generated_lines.len() {
@@ -150,7 +580,7 @@ fn sync_file(
             source_lines.len(),
             generated_lines.len()
         );
-        return Ok(false);
+        return Ok(HunkCounts::default());
     }
 
     // Build set of line indices where source has template syntax
@@ -166,7 +596,7 @@ fn sync_file(
     };
 
     // Diff generated vs staged
-    let diff = similar::TextDiff::from_lines(&generated, &staged);
+    let diff = similar::TextDiff::from_lines(generated.as_str(), &*view);
     let ops: Vec<DiffOp> = diff.ops().to_vec();
 
     // Count non-equal hunks for display numbering
@@ -176,22 +606,43 @@ fn sync_file(
         .count();
 
     if total_hunks == 0 {
-        return Ok(false);
+        return Ok(HunkCounts::default());
     }
 
-    println!(
+    writeln!(
+        out,
         "\n=== {} ({} hunk{}) ===",
         entry.src,
         total_hunks,
         if total_hunks == 1 { "" } else { "s" }
-    );
+    )?;
 
     // Build output from source lines, selectively applying staged changes
     let mut output_lines: Vec<&str> = Vec::new();
-    let mut any_applied = false;
+    let mut counts = HunkCounts::default();
     let mut hunk_num = 0;
 
     for op in &ops {
+        let key = (!matches!(op, DiffOp::Equal { .. })).then(|| {
+            hunk_key(
+                &generated_lines[op.old_range()],
+                &staged_lines[op.new_range()],
+            )
+        });
+        if let Some(key) = key.as_ref().filter(|k| remembered.contains(k)) {
+            hunk_num += 1;
+            writeln!(
+                out,
+                "\n--- Hunk {hunk_num}/{total_hunks} of {}: skipped at an earlier sync \
+                 (--reset-decisions to review it again)",
+                entry.src
+            )?;
+            // Keep the source as it is
+            output_lines.extend_from_slice(&source_lines[op.old_range()]);
+            counts.remembered += 1;
+            skipped_keys.push(key.clone());
+            continue;
+        }
         match *op {
             DiffOp::Equal { old_index, len, .. } => {
                 // Copy source lines for equal regions (preserves template syntax)
@@ -206,10 +657,26 @@ fn sync_file(
                 let staged_range = &staged_lines[new_index..new_index + new_len];
 
                 if dry_run {
-                    print_insert_hunk(&entry.src, hunk_num, total_hunks, new_index, staged_range);
-                    println!("  [dry-run] Would prompt: default Apply");
+                    print_insert_hunk(
+                        &entry.src,
+                        hunk_num,
+                        total_hunks,
+                        new_index,
+                        staged_range,
+                        mask,
+                        out,
+                    )?;
+                    writeln!(out, "  [dry-run] Would prompt: default Apply")?;
                 } else {
-                    print_insert_hunk(&entry.src, hunk_num, total_hunks, new_index, staged_range);
+                    print_insert_hunk(
+                        &entry.src,
+                        hunk_num,
+                        total_hunks,
+                        new_index,
+                        staged_range,
+                        mask,
+                        out,
+                    )?;
 
                     let selection = prompter.select("Action", &["Apply", "Skip"], 0)?;
 
@@ -217,9 +684,12 @@ fn sync_file(
                         for line in staged_range {
                             output_lines.push(line);
                         }
-                        any_applied = true;
+                        counts.applied += 1;
+                    } else {
+                        // Skip = don't add anything (lines didn't exist in source)
+                        counts.skipped += 1;
+                        skipped_keys.extend(key);
                     }
-                    // Skip = don't add anything (lines didn't exist in source)
                 }
             }
             DiffOp::Delete {
@@ -259,15 +729,18 @@ fn sync_file(
                         old_len,
                         &source_range,
                         &classification,
-                    );
-                    println!(
+                        mask,
+                        out,
+                    )?;
+                    writeln!(
+                        out,
                         "  [dry-run] Would prompt: default {}",
                         if classification.is_safe {
                             "Apply"
                         } else {
                             "Skip"
                         }
-                    );
+                    )?;
                     // Preserve source lines in dry-run
                     for line in &source_range {
                         output_lines.push(line);
@@ -281,7 +754,9 @@ fn sync_file(
                         old_len,
                         &source_range,
                         &classification,
-                    );
+                        mask,
+                        out,
+                    )?;
 
                     let selection = prompter.select(
                         "Action",
@@ -291,11 +766,13 @@ fn sync_file(
 
                     if selection == 0 {
                         // Apply = delete these lines (don't add them to output)
-                        any_applied = true;
+                        counts.applied += 1;
                     } else {
                         for line in &source_range {
                             output_lines.push(line);
                         }
+                        counts.skipped += 1;
+                        skipped_keys.extend(key);
                     }
                 }
             }
@@ -341,15 +818,18 @@ fn sync_file(
                         &source_range,
                         staged_range,
                         &classification,
-                    );
-                    println!(
+                        mask,
+                        out,
+                    )?;
+                    writeln!(
+                        out,
                         "  [dry-run] Would prompt: default {}",
                         if classification.is_safe {
                             "Apply"
                         } else {
                             "Skip"
                         }
-                    );
+                    )?;
                     // Preserve source lines in dry-run
                     for line in &source_range {
                         output_lines.push(line);
@@ -364,7 +844,9 @@ fn sync_file(
                         &source_range,
                         staged_range,
                         &classification,
-                    );
+                        mask,
+                        out,
+                    )?;
 
                     let selection = prompter.select(
                         "Action",
@@ -376,24 +858,57 @@ fn sync_file(
                         for line in staged_range {
                             output_lines.push(line);
                         }
-                        any_applied = true;
+                        counts.applied += 1;
                     } else {
                         for line in &source_range {
                             output_lines.push(line);
                         }
+                        counts.skipped += 1;
+                        skipped_keys.extend(key);
                     }
                 }
             }
         }
     }
 
-    if !any_applied {
+    if counts.applied == 0 {
         debug!("{}: no hunks applied", entry.src);
-        return Ok(false);
+        return Ok(counts);
     }
 
     if dry_run {
-        return Ok(false);
+        return Ok(counts);
+    }
+
+    // The hunks were reviewed against the snapshot read above; if the staged
+    // copy moved underneath us, writing them could drop the newer change
+    let current = read_output(entry, &staged_path, fs)
+        .with_context(|| format!("Failed to read staged: {}", staged_path.display()))?;
+    if current != staged {
+        warn!("{}: staged copy changed during review", entry.src);
+        let selection = prompter.select(
+            &format!("{} changed in .staged/ while you were reviewing", entry.src),
+            &["Review again", "Abort this file"],
+            0,
+        )?;
+        if selection == 0 {
+            skipped_keys.clear();
+            return sync_file(
+                config,
+                entry,
+                remembered,
+                skipped_keys,
+                dry_run,
+                fs,
+                prompter,
+                mask,
+                out,
+            );
+        }
+        anyhow::bail!(
+            "staged copy changed during review; source left unchanged (run `janus sync {}` again)",
+            entry.src
+        );
     }
 
     // Write updated source
@@ -407,7 +922,8 @@ fn sync_file(
         .with_context(|| format!("Failed to set permissions: {}", source_path.display()))?;
 
     info!("Updated source: {}", entry.src);
-    Ok(true)
+    counts.written = true;
+    Ok(counts)
 }
 
 struct HunkClassification {
@@ -450,21 +966,32 @@ fn classify_hunk(
     }
 }
 
-fn print_insert_hunk(src: &str, hunk_num: usize, total: usize, new_index: usize, staged: &[&str]) {
-    println!(
+fn print_insert_hunk(
+    src: &str,
+    hunk_num: usize,
+    total: usize,
+    new_index: usize,
+    staged: &[&str],
+    mask: &SecretMask,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    writeln!(
+        out,
         "\n--- {}: hunk {}/{} (insert after line {}) ---",
         src, hunk_num, total, new_index
-    );
-    println!("\n  Staged (new lines):");
+    )?;
+    writeln!(out, "\n  Staged (new lines):")?;
     for line in staged {
-        print!("    \x1b[32m+{}\x1b[0m", line);
+        let line = mask.mask(line);
+        write!(out, "    {GREEN}+{line}{RESET}")?;
         if !line.ends_with('\n') {
-            println!();
+            writeln!(out)?;
         }
     }
-    println!();
+    writeln!(out)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn print_delete_hunk(
     src: &str,
     hunk_num: usize,
@@ -473,26 +1000,30 @@ fn print_delete_hunk(
     old_len: usize,
     source_range: &[&str],
     classification: &HunkClassification,
-) {
-    println!(
+    mask: &SecretMask,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    writeln!(
+        out,
         "\n--- {}: hunk {}/{} (lines {}-{}) ---",
         src,
         hunk_num,
         total,
         old_index + 1,
         old_index + old_len
-    );
-    println!("\n  Source (would be deleted):");
+    )?;
+    writeln!(out, "\n  Source (would be deleted):")?;
     for line in source_range {
-        print!("    \x1b[31m-{}\x1b[0m", line);
+        let line = mask.mask(line);
+        write!(out, "    {RED}-{line}{RESET}")?;
         if !line.ends_with('\n') {
-            println!();
+            writeln!(out)?;
         }
     }
     if let Some(annotation) = classification.annotation {
-        println!("\n  {}", annotation);
+        writeln!(out, "\n  {}", annotation)?;
     }
-    println!();
+    writeln!(out)
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -505,38 +1036,43 @@ fn print_replace_hunk(
     source_range: &[&str],
     staged: &[&str],
     classification: &HunkClassification,
-) {
+    mask: &SecretMask,
+    out: &mut impl Write,
+) -> io::Result<()> {
     let label = if classification.is_safe {
         "Current"
     } else {
         "Source"
     };
-    println!(
+    writeln!(
+        out,
         "\n--- {}: hunk {}/{} (lines {}-{}) ---",
         src,
         hunk_num,
         total,
         old_index + 1,
         old_index + old_len
-    );
-    println!("\n  {}:", label);
+    )?;
+    writeln!(out, "\n  {}:", label)?;
     for line in source_range {
-        print!("    {}", line);
+        let line = mask.mask(line);
+        write!(out, "    {}", line)?;
         if !line.ends_with('\n') {
-            println!();
+            writeln!(out)?;
         }
     }
-    println!("\n  Staged:");
+    writeln!(out, "\n  Staged:")?;
     for line in staged {
-        print!("    {}", line);
+        let line = mask.mask(line);
+        write!(out, "    {}", line)?;
         if !line.ends_with('\n') {
-            println!();
+            writeln!(out)?;
         }
     }
     if let Some(annotation) = classification.annotation {
-        println!("\n  {}", annotation);
+        writeln!(out, "\n  {}", annotation)?;
     }
-    println!();
+    writeln!(out)
 }
 
 #[cfg(test)]
@@ -563,7 +1099,287 @@ mod tests {
         let fs = setup_fs();
         let config = sync_setup(&fs, "same\n", "same\n", "same\n");
         let prompter = FakePrompter::new(vec![]); // No prompts
-        run(&config, None, false, &fs, &prompter).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn direct_file_only_reported() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/direct.conf"), "edited\n");
+        let state_toml = format!(
+            "[[deployed]]\nsrc = \"direct.conf\"\ntarget = \"~/direct.conf\"\nsource_hash = \"{}\"\n",
+            crate::state::content_hash(b"original\n")
+        );
+        fs.add_file(format!("{DOTFILES}/.janus_state.toml"), state_toml.clone());
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\n\n[[files]]\nsrc = \"direct.conf\"\ndirect = true\ntemplate = false\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let prompter = FakePrompter::new(vec![]); // No prompts
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
+        // Nothing is rewritten; the hash is only refreshed by deploy
+        let path = |p: &str| std::path::PathBuf::from(format!("{DOTFILES}/{p}"));
+        assert_eq!(fs.read_to_string(&path("direct.conf")).unwrap(), "edited\n");
+        assert_eq!(
+            fs.read_to_string(&path(".janus_state.toml")).unwrap(),
+            state_toml
+        );
+    }
+
+    #[test]
+    fn replaced_target_readopted_then_reviewed() {
+        let fs = setup_fs();
+        let config = sync_setup(&fs, "old\n", "old\n", "old\n");
+        let target = std::path::Path::new("/home/test/.config/a.conf");
+        fs.add_file(target, "new\n");
+        fs.add_file(
+            format!("{DOTFILES}/.janus_state.toml"),
+            "[[deployed]]\nsrc = \"a.conf\"\ntarget = \"~/.config/a.conf\"\n",
+        );
+        // Re-adopt, then apply the one hunk
+        let prompter = FakePrompter::new(vec![0]).with_confirms(&[true]);
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
+        let staged = std::path::PathBuf::from(format!("{DOTFILES}/.staged/a.conf"));
+        assert_eq!(fs.read_link(target).unwrap(), staged);
+        assert_eq!(fs.read_to_string(&staged).unwrap(), "new\n");
+        let source = fs
+            .read_to_string(std::path::Path::new(&format!("{DOTFILES}/a.conf")))
+            .unwrap();
+        assert_eq!(source, "new\n");
+    }
+
+    #[test]
+    fn replaced_direct_target_declined_keeps_source() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/direct.conf"), "pulled\n");
+        let target = std::path::Path::new("/home/test/direct.conf");
+        fs.add_file(target, "app\n");
+        fs.add_file(
+            format!("{DOTFILES}/.janus_state.toml"),
+            "[[deployed]]\nsrc = \"direct.conf\"\ntarget = \"~/direct.conf\"\n",
+        );
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\n\n[[files]]\nsrc = \"direct.conf\"\ndirect = true\ntemplate = false\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let prompter = FakePrompter::new(vec![]).with_confirms(&[false]);
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
+        let source = std::path::PathBuf::from(format!("{DOTFILES}/direct.conf"));
+        assert_eq!(fs.read_to_string(&source).unwrap(), "pulled\n");
+        assert_eq!(fs.read_to_string(target).unwrap(), "app\n");
+        assert!(!fs.is_symlink(target));
+    }
+
+    #[test]
+    fn skips_chosen_up_front_by_fileset() {
+        let fs = setup_fs();
+        for name in ["a.conf", "b.conf", "c.conf"] {
+            fs.add_file(format!("{DOTFILES}/{name}"), "old\n");
+            fs.add_file(format!("{DOTFILES}/.generated/{name}"), "old\n");
+            fs.add_file(format!("{DOTFILES}/.staged/{name}"), "new\n");
+        }
+        let toml = format!(
+            "{}\n[filesets.beta]\npatterns = [\"b.conf\"]\n\n[filesets.alpha]\npatterns = [\"a.conf\"]\n",
+            make_config_toml(&[("c.conf", None), ("b.conf", None), ("a.conf", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let prompter = FakePrompter::new(vec![
+            1, // alpha: skip all
+            2, // beta: choose files
+            1, //   b.conf: skip
+            0, // (no fileset): review
+            0, // c.conf hunk: apply
+        ]);
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
+        let source = |name: &str| {
+            fs.read_to_string(std::path::Path::new(&format!("{DOTFILES}/{name}")))
+                .unwrap()
+        };
+        assert_eq!(source("a.conf"), "old\n");
+        assert_eq!(source("b.conf"), "old\n");
+        assert_eq!(source("c.conf"), "new\n");
+    }
+
+    #[test]
+    fn count_hunks_ignores_equal_runs() {
+        let fs = setup_fs();
+        let config = sync_setup(&fs, "a\nb\nc\n", "a\nb\nc\n", "A\nb\nC\n");
+        let entry = &config.files[0];
+        assert_eq!(count_hunks(&config, entry, &[], &fs), Some(2));
+        fs.remove_file(std::path::Path::new(&format!("{DOTFILES}/.staged/a.conf")))
+            .unwrap();
+        assert_eq!(count_hunks(&config, entry, &[], &fs), None);
+    }
+
+    /// Answers like `FakePrompter`, but rewrites a.conf's staged copy at the
+    /// first prompt, as an app writing through the symlink would.
+    struct RacingPrompter<'a> {
+        fs: &'a crate::platform::FakeFs,
+        write: std::cell::Cell<Option<&'static str>>,
+        inner: FakePrompter,
+    }
+
+    impl Prompter for RacingPrompter<'_> {
+        fn select(&self, prompt: &str, items: &[&str], default: usize) -> Result<usize> {
+            if let Some(content) = self.write.take() {
+                self.fs
+                    .add_file(format!("{DOTFILES}/.staged/a.conf"), content);
+            }
+            self.inner.select(prompt, items, default)
+        }
+
+        fn confirm(&self, prompt: &str, default: bool) -> Result<bool> {
+            self.inner.confirm(prompt, default)
+        }
+
+        fn input(&self, prompt: &str, default: Option<&str>) -> Result<String> {
+            self.inner.input(prompt, default)
+        }
+
+        fn password(&self, prompt: &str) -> Result<String> {
+            self.inner.password(prompt)
+        }
+
+        fn multi_select(
+            &self,
+            prompt: &str,
+            items: &[&str],
+            defaults: &[bool],
+        ) -> Result<Vec<usize>> {
+            self.inner.multi_select(prompt, items, defaults)
+        }
+    }
+
+    #[test]
+    fn staged_change_during_review_aborts_file() {
+        let fs = setup_fs();
+        let config = sync_setup(&fs, "old\n", "old\n", "new\n");
+        let prompter = RacingPrompter {
+            fs: &fs,
+            write: std::cell::Cell::new(Some("newer\n")),
+            inner: FakePrompter::new(vec![0, 1]), // Apply, then Abort
+        };
+        let err = run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("a.conf"), "got: {err:#}");
+        let content = fs
+            .read_to_string(std::path::Path::new(&format!("{DOTFILES}/a.conf")))
+            .unwrap();
+        assert_eq!(content, "old\n");
+    }
+
+    #[test]
+    fn staged_change_during_review_rediffs() {
+        let fs = setup_fs();
+        let config = sync_setup(&fs, "old\n", "old\n", "new\n");
+        let prompter = RacingPrompter {
+            fs: &fs,
+            write: std::cell::Cell::new(Some("newer\n")),
+            inner: FakePrompter::new(vec![0, 0, 0]), // Apply, Review again, Apply
+        };
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
+        let content = fs
+            .read_to_string(std::path::Path::new(&format!("{DOTFILES}/a.conf")))
+            .unwrap();
+        assert_eq!(content, "newer\n");
+    }
+
+    #[test]
+    fn crlf_output_synced_back_as_lf() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.conf"), "a\nold\n");
+        fs.add_file(format!("{DOTFILES}/.generated/a.conf"), "a\r\nold\r\n");
+        fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "a\r\nnew\r\n");
+        let toml = format!(
+            "{}newline = \"crlf\"\n",
+            make_config_toml(&[("a.conf", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let prompter = FakePrompter::new(vec![0]); // Apply
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
+        let content = fs
+            .read_to_string(std::path::Path::new(&format!("{DOTFILES}/a.conf")))
+            .unwrap();
+        assert_eq!(content, "a\nnew\n");
     }
 
     #[test]
@@ -571,31 +1387,127 @@ mod tests {
         let fs = setup_fs();
         let config = sync_setup(&fs, "old line\n", "old line\n", "new line\n");
         let prompter = FakePrompter::new(vec![0]); // Apply
-        run(&config, None, false, &fs, &prompter).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
         let content = fs
             .read_to_string(std::path::Path::new(&format!("{DOTFILES}/a.conf")))
             .unwrap();
         assert_eq!(content, "new line\n");
     }
 
+    #[test]
+    fn only_managed_regions_are_synced() {
+        let fs = setup_fs();
+        let managed = "# janus:begin managed\nfont = 12\n# janus:end\n";
+        let staged = "# janus:begin managed\nfont = 14\n# janus:end\nadded_by_app = true\n";
+        let config = sync_setup(&fs, managed, managed, staged);
+        // One hunk, for the region
+        let prompter = FakePrompter::new(vec![0]);
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
+        let content = fs
+            .read_to_string(std::path::Path::new(&format!("{DOTFILES}/a.conf")))
+            .unwrap();
+        assert_eq!(content, "# janus:begin managed\nfont = 14\n# janus:end\n");
+    }
+
     #[test]
     fn skip_replace_hunk() {
         let fs = setup_fs();
         let config = sync_setup(&fs, "old line\n", "old line\n", "new line\n");
         let prompter = FakePrompter::new(vec![1]); // Skip
-        run(&config, None, false, &fs, &prompter).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
         let content = fs
             .read_to_string(std::path::Path::new(&format!("{DOTFILES}/a.conf")))
             .unwrap();
         assert_eq!(content, "old line\n");
     }
 
+    #[test]
+    fn skipped_hunks_are_remembered_until_reset() {
+        let fs = setup_fs();
+        let config = sync_setup(&fs, "a\nb\nold\n", "a\nb\nold\n", "A\nb\nnew\n");
+        let sync = |reset: bool, answers: Vec<usize>| {
+            let prompter = FakePrompter::new(answers);
+            run(
+                &config,
+                None,
+                reset,
+                false,
+                &fs,
+                &prompter,
+                &SecretMask::default(),
+                &mut io::sink(),
+            )
+            .unwrap();
+            prompter.remaining()
+        };
+        let source = || {
+            fs.read_to_string(std::path::Path::new(&format!("{DOTFILES}/a.conf")))
+                .unwrap()
+        };
+
+        // Apply the first hunk, skip the second, then regenerate
+        assert_eq!(sync(false, vec![0, 1]), 0);
+        assert_eq!(source(), "A\nb\nold\n");
+        let state = State::load(std::path::Path::new(DOTFILES), &fs).unwrap();
+        assert_eq!(state.skipped_hunks("a.conf").len(), 1);
+        fs.add_file(format!("{DOTFILES}/.generated/a.conf"), "A\nb\nold\n");
+
+        // Nothing new to ask about
+        assert_eq!(sync(false, vec![]), 0);
+        assert_eq!(source(), "A\nb\nold\n");
+
+        assert_eq!(sync(true, vec![0]), 0);
+        assert_eq!(source(), "A\nb\nnew\n");
+        let state = State::load(std::path::Path::new(DOTFILES), &fs).unwrap();
+        assert!(state.skipped_hunks("a.conf").is_empty());
+    }
+
     #[test]
     fn apply_insert_hunk() {
         let fs = setup_fs();
         let config = sync_setup(&fs, "line1\n", "line1\n", "line1\nnewline\n");
         let prompter = FakePrompter::new(vec![0]); // Apply
-        run(&config, None, false, &fs, &prompter).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
         let content = fs
             .read_to_string(std::path::Path::new(&format!("{DOTFILES}/a.conf")))
             .unwrap();
@@ -607,7 +1519,17 @@ mod tests {
         let fs = setup_fs();
         let config = sync_setup(&fs, "line1\nline2\n", "line1\nline2\n", "line1\n");
         let prompter = FakePrompter::new(vec![0]); // Apply (delete)
-        run(&config, None, false, &fs, &prompter).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
         let content = fs
             .read_to_string(std::path::Path::new(&format!("{DOTFILES}/a.conf")))
             .unwrap();
@@ -629,7 +1551,17 @@ mod tests {
         // Actually, both are 1 line, but source has template syntax
         // The hunk should have default=Skip (unsafe), so prompter response 1 = Skip
         let prompter = FakePrompter::new(vec![1]); // Skip
-        run(&config, None, false, &fs, &prompter).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
         let content = fs
             .read_to_string(std::path::Path::new(&format!("{DOTFILES}/t.conf")))
             .unwrap();
@@ -648,7 +1580,17 @@ mod tests {
         fs.add_file(format!("{DOTFILES}/.staged/plain.conf"), "new\n");
         let config = write_and_load_config(&fs, &toml);
         let prompter = FakePrompter::new(vec![0]); // Apply (default for safe)
-        run(&config, None, false, &fs, &prompter).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
         let content = fs
             .read_to_string(std::path::Path::new(&format!("{DOTFILES}/plain.conf")))
             .unwrap();
@@ -668,7 +1610,17 @@ mod tests {
         let config = write_and_load_config(&fs, &toml);
         // Default for unsafe is Skip (index 1)
         let prompter = FakePrompter::new(vec![1]); // Skip
-        run(&config, None, false, &fs, &prompter).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
         let content = fs
             .read_to_string(std::path::Path::new(&format!("{DOTFILES}/edited.conf")))
             .unwrap();
@@ -687,7 +1639,17 @@ mod tests {
         fs.add_file(format!("{DOTFILES}/.staged/tmpl.conf"), staged);
         let config = write_and_load_config(&fs, &make_config_toml(&[("tmpl.conf", None)]));
         let prompter = FakePrompter::new(vec![]); // No prompts expected
-        run(&config, None, false, &fs, &prompter).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
         // Source should be unchanged
         let content = fs
             .read_to_string(std::path::Path::new(&format!("{DOTFILES}/tmpl.conf")))
@@ -703,7 +1665,17 @@ mod tests {
         fs.add_file(format!("{DOTFILES}/.staged/a.conf"), "new\n");
         let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
         let prompter = FakePrompter::new(vec![0]); // Apply
-        run(&config, None, false, &fs, &prompter).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            false,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
         let mode = fs
             .file_mode(std::path::Path::new(&format!("{DOTFILES}/a.conf")))
             .unwrap();
@@ -715,7 +1687,17 @@ mod tests {
         let fs = setup_fs();
         let config = sync_setup(&fs, "old\n", "old\n", "new\n");
         let prompter = FakePrompter::new(vec![]); // No prompts in dry run
-        run(&config, None, true, &fs, &prompter).unwrap();
+        run(
+            &config,
+            None,
+            false,
+            true,
+            &fs,
+            &prompter,
+            &SecretMask::default(),
+            &mut io::sink(),
+        )
+        .unwrap();
         let content = fs
             .read_to_string(std::path::Path::new(&format!("{DOTFILES}/a.conf")))
             .unwrap();
@@ -0,0 +1,219 @@
+//! `janus test`: render templates against fixture vars and check the output.
+//!
+//! Each `[[tests]]` entry renders one managed file's source with only the
+//! vars in its fixture file over `var_defaults` (no global, fileset, or
+//! per-file vars, and no secret lookups). Like generate, it fills in
+//! undefined variables per the file's `undefined_vars` and runs the output
+//! through its `postprocess`, `newline`, and `encoding`, then compares it to a golden file and/or
+//! checks it against regexes. Nothing is written, so it's safe in CI.
+
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use std::io::Write;
+
+use super::diff::unified_diff;
+use super::generate::{encode_output, load_vars, postprocess, template_vars, vars_to_tera_context};
+use crate::config::{Config, TemplateTest};
+use crate::platform::{CommandRunner, Fs};
+use crate::template;
+
+/// Run one test, returning why it failed (`None` if it passed).
+pub fn run_one(
+    config: &Config,
+    test: &TemplateTest,
+    fs: &impl Fs,
+    runner: &impl CommandRunner,
+) -> Result<Option<String>> {
+    let Some(entry) = config.files.iter().find(|e| e.src == test.src) else {
+        bail!("{} isn't a managed file", test.src);
+    };
+    if !entry.template {
+        bail!("{} isn't a template", test.src);
+    }
+    let dotfiles_dir = config.dotfiles_dir(fs);
+    let vars_path = dotfiles_dir.join(&test.vars);
+    if !fs.exists(&vars_path) {
+        bail!("vars fixture not found: {}", vars_path.display());
+    }
+    let source_path = config.source_path(&entry.src, fs);
+    let content = fs
+        .read_to_string(&source_path)
+        .with_context(|| format!("Failed to read template: {}", source_path.display()))?;
+
+    let vars = load_vars(&dotfiles_dir, std::slice::from_ref(&test.vars), fs)?;
+    let vars = template_vars(config, entry, &content, vars);
+    let mut context = vars_to_tera_context(&vars)?;
+    template::insert_janus(&mut context, config, entry, fs)?;
+    let rendered = match template::Probes::collect(&content, config.allow_exec, runner)
+        .map(|probes| probes.with_managed(config, fs))
+        .and_then(|probes| template::render(&entry.src, &content, &context, &probes))
+    {
+        Ok(rendered) => rendered,
+        Err(e) => return Ok(Some(format!("render failed: {e:#}"))),
+    };
+    let rendered = match postprocess(entry, rendered, runner) {
+        Ok(rendered) => rendered,
+        Err(e) => return Ok(Some(format!("{e:#}"))),
+    };
+    let output = encode_output(entry, rendered.clone())?;
+
+    let mut problems = Vec::new();
+    if let Some(expected) = &test.expected {
+        let expected_path = dotfiles_dir.join(expected);
+        let golden = fs
+            .read(&expected_path)
+            .with_context(|| format!("Failed to read golden file: {}", expected_path.display()))?;
+        if golden != output {
+            let golden = String::from_utf8_lossy(&golden);
+            let output = String::from_utf8_lossy(&output);
+            problems.push(format!(
+                "output differs from {expected}:\n{}",
+                unified_diff(&golden, &output, expected, "rendered")
+            ));
+        }
+    }
+    for pattern in &test.matches {
+        let regex =
+            Regex::new(pattern).with_context(|| format!("Invalid regex in matches: {pattern}"))?;
+        if !regex.is_match(&rendered) {
+            problems.push(format!("no match for /{pattern}/"));
+        }
+    }
+    Ok((!problems.is_empty()).then(|| problems.join("\n")))
+}
+
+/// Run the tests named in `names` (all when empty), reporting each, and
+/// fail if any did.
+pub fn run(
+    config: &Config,
+    names: &[String],
+    fs: &impl Fs,
+    runner: &impl CommandRunner,
+    out: &mut impl Write,
+) -> Result<()> {
+    let tests: Vec<&TemplateTest> = config
+        .tests
+        .iter()
+        .filter(|t| names.is_empty() || names.iter().any(|n| n == t.name()))
+        .collect();
+    if let Some(unknown) = names
+        .iter()
+        .find(|n| !config.tests.iter().any(|t| t.name() == n.as_str()))
+    {
+        bail!("No [[tests]] entry named {unknown}");
+    }
+    if tests.is_empty() {
+        writeln!(out, "No template tests configured")?;
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for test in &tests {
+        let failure = match run_one(config, test, fs, runner) {
+            Ok(failure) => failure,
+            Err(e) => Some(format!("{e:#}")),
+        };
+        match failure {
+            None => writeln!(out, "ok    {}", test.name())?,
+            Some(why) => {
+                failed += 1;
+                writeln!(out, "FAIL  {}", test.name())?;
+                for line in why.lines() {
+                    writeln!(out, "      {line}")?;
+                }
+            }
+        }
+    }
+    writeln!(out, "\n{} passed, {failed} failed", tests.len() - failed)?;
+    if failed > 0 {
+        bail!("{failed} template test(s) failed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::FakeCommandRunner;
+    use crate::test_helpers::*;
+
+    fn setup(test_toml: &str) -> (crate::platform::FakeFs, Config) {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "user = \"real\"");
+        fs.add_file(format!("{DOTFILES}/tests/vars.toml"), "user = \"ci\"");
+        fs.add_file(
+            format!("{DOTFILES}/git/config"),
+            "[user]\nname = {{ user }}\n",
+        );
+        fs.add_file(
+            format!("{DOTFILES}/tests/git.golden"),
+            "[user]\nname = ci\n",
+        );
+        let toml = format!(
+            "{}\n[[tests]]\nsrc = \"git/config\"\nvars = \"tests/vars.toml\"\n{test_toml}",
+            make_config_toml(&[("git/config", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        (fs, config)
+    }
+
+    fn run_all(config: &Config, fs: &crate::platform::FakeFs) -> (Result<()>, String) {
+        let mut out = Vec::new();
+        let result = run(config, &[], fs, &FakeCommandRunner::new(), &mut out);
+        (result, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn passes_with_fixture_vars_only() {
+        let (fs, config) = setup("expected = \"tests/git.golden\"\nmatches = [\"name = c.\"]\n");
+        let (result, out) = run_all(&config, &fs);
+        result.unwrap();
+        assert!(out.contains("ok    git/config"), "got: {out}");
+    }
+
+    #[test]
+    fn reports_golden_diff_and_missed_regex() {
+        let (fs, config) = setup("expected = \"tests/git.golden\"\nmatches = [\"email\"]\n");
+        fs.add_file(format!("{DOTFILES}/tests/vars.toml"), "user = \"other\"");
+        let (result, out) = run_all(&config, &fs);
+        assert!(result.is_err());
+        assert!(out.contains("FAIL  git/config"), "got: {out}");
+        assert!(out.contains("+name = other"), "got: {out}");
+        assert!(out.contains("no match for /email/"), "got: {out}");
+    }
+
+    #[test]
+    fn renders_over_var_defaults_and_fills_undefined() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/tests/vars.toml"), "user = \"ci\"");
+        fs.add_file(
+            format!("{DOTFILES}/git/config"),
+            "name = {{ user }}\nemail = {{ email }}\nkey = {{ key }}\n",
+        );
+        fs.add_file(
+            format!("{DOTFILES}/tests/git.golden"),
+            "name = ci\nemail = ci@example.com\nkey = \n",
+        );
+        let toml = format!(
+            "undefined_vars = \"empty\"\n{}\n[var_defaults]\nemail = \"ci@example.com\"\n\n[[tests]]\nsrc = \"git/config\"\nvars = \"tests/vars.toml\"\nexpected = \"tests/git.golden\"\n",
+            make_config_toml(&[("git/config", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let (result, out) = run_all(&config, &fs);
+        result.unwrap();
+        assert!(out.contains("ok    git/config"), "got: {out}");
+    }
+
+    #[test]
+    fn unknown_test_name_errors() {
+        let (fs, config) = setup("");
+        let result = run(
+            &config,
+            &["nope".to_string()],
+            &fs,
+            &FakeCommandRunner::new(),
+            &mut Vec::new(),
+        );
+        assert!(result.is_err());
+    }
+}
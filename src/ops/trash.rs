@@ -0,0 +1,177 @@
+//! `janus trash`: list and restore files janus moved to the trash.
+//!
+//! Only files removed while `use_trash = true` are known here; they're read
+//! from the log kept by [`crate::trash`]. Restoring moves a file back to its
+//! original path and drops its `.trashinfo`, so file managers stop showing
+//! it as trashed.
+
+use anyhow::{Context, Result, bail};
+use std::io::Write;
+use tracing::info;
+
+use crate::config::Config;
+use crate::paths::{collapse_tilde, expand_tilde};
+use crate::plan;
+use crate::platform::Fs;
+use crate::platform::xdg_trash;
+use crate::trash::TrashLog;
+
+/// Write the files janus trashed to `out`, oldest first.
+pub fn list(config: &Config, fs: &impl Fs, out: &mut impl Write) -> Result<()> {
+    let log = TrashLog::load(&config.state_dir(fs), fs)?;
+    if log.trashed.is_empty() {
+        info!("Nothing in the trash from janus");
+        return Ok(());
+    }
+    for entry in &log.trashed {
+        let gone = if fs.exists(&entry.trashed) || fs.is_symlink(&entry.trashed) {
+            ""
+        } else {
+            "  (no longer in the trash)"
+        };
+        writeln!(
+            out,
+            "  {}  {}{gone}",
+            xdg_trash::format_date(entry.timestamp),
+            collapse_tilde(&entry.original, fs),
+        )?;
+    }
+    Ok(())
+}
+
+/// Move trashed files back to their original paths.
+///
+/// Each path is matched against the original paths in the log (`~` is
+/// expanded; relative paths are taken from the dotfiles directory). If the
+/// same path was trashed more than once, the most recent copy is restored.
+/// Refuses to overwrite anything now at the original path.
+pub fn restore(config: &Config, paths: &[String], dry_run: bool, fs: &impl Fs) -> Result<()> {
+    let dotfiles_dir = config.dotfiles_dir(fs);
+    let state_dir = config.state_dir(fs);
+    let mut log = TrashLog::load(&state_dir, fs)?;
+
+    for path in paths {
+        let original = dotfiles_dir.join(expand_tilde(path, fs));
+        let shown = collapse_tilde(&original, fs);
+        let Some(index) = log.trashed.iter().rposition(|e| e.original == original) else {
+            bail!("{shown} isn't in the trash (see `janus trash list`)");
+        };
+        let trashed = log.trashed[index].trashed.clone();
+        if !fs.exists(&trashed) && !fs.is_symlink(&trashed) {
+            bail!("{shown} is no longer in the trash");
+        }
+        if fs.exists(&original) || fs.is_symlink(&original) {
+            bail!("{shown} already exists; move it aside to restore the trashed copy");
+        }
+
+        if dry_run {
+            info!("[dry-run] Would restore {shown}");
+            plan::action(
+                "restore",
+                &trashed.to_string_lossy(),
+                &original.to_string_lossy(),
+                "trash restore",
+            );
+            continue;
+        }
+        if let Some(parent) = original.parent() {
+            fs.create_dir_all(parent)?;
+        }
+        fs.rename(&trashed, &original)
+            .with_context(|| format!("Failed to restore {shown}"))?;
+        if let Some(info_path) = xdg_trash::info_path(&trashed) {
+            let _ = fs.remove_file(&info_path);
+        }
+        log.trashed.remove(index);
+        log.save(&state_dir, fs)?;
+        info!("Restored {shown}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::FakeCommandRunner;
+    use crate::test_helpers::*;
+    use std::path::Path;
+
+    fn unimport_trashed(fs: &crate::platform::FakeFs) -> Config {
+        setup_pipeline_file(fs, "a.conf", "source");
+        let toml = format!(
+            "use_trash = true\n{}",
+            make_config_toml(&[("a.conf", None)])
+        );
+        let config = write_and_load_config(fs, &toml);
+        crate::ops::unimport::run(
+            &config,
+            Path::new(CONFIG_PATH),
+            &["a.conf".to_string()],
+            false,
+            false,
+            false,
+            false,
+            fs,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        config
+    }
+
+    #[test]
+    fn list_shows_trashed_files() {
+        let fs = setup_fs();
+        let config = unimport_trashed(&fs);
+        let mut out = Vec::new();
+        list(&config, &fs, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let original = collapse_tilde(Path::new(&format!("{DOTFILES}/a.conf")), &fs);
+        assert!(out.contains(&original), "got: {out}");
+        assert!(out.contains("/.staged/a.conf"), "got: {out}");
+    }
+
+    #[test]
+    fn restore_moves_source_back() {
+        let fs = setup_fs();
+        let config = unimport_trashed(&fs);
+        let source = Path::new(DOTFILES).join("a.conf");
+        assert!(!fs.exists(&source));
+
+        restore(&config, &["a.conf".to_string()], false, &fs).unwrap();
+        assert_eq!(fs.read_to_string(&source).unwrap(), "source");
+        assert!(!fs.exists(Path::new(
+            "/home/test/.local/share/Trash/info/a.conf.trashinfo"
+        )));
+        let log = TrashLog::load(Path::new(DOTFILES), &fs).unwrap();
+        assert!(log.trashed.iter().all(|e| e.original != source));
+    }
+
+    #[test]
+    fn restore_refuses_to_overwrite() {
+        let fs = setup_fs();
+        let config = unimport_trashed(&fs);
+        fs.add_file(format!("{DOTFILES}/a.conf"), "new");
+
+        let msg = format!(
+            "{:#}",
+            restore(&config, &["a.conf".to_string()], false, &fs).unwrap_err()
+        );
+        assert!(msg.contains("already exists"), "got: {msg}");
+        assert_eq!(
+            fs.read_to_string(Path::new(&format!("{DOTFILES}/a.conf")))
+                .unwrap(),
+            "new"
+        );
+    }
+
+    #[test]
+    fn restore_unknown_path() {
+        let fs = setup_fs();
+        let config = write_and_load_config(&fs, &make_config_toml(&[]));
+        let msg = format!(
+            "{:#}",
+            restore(&config, &["a.conf".to_string()], false, &fs).unwrap_err()
+        );
+        assert!(msg.contains("isn't in the trash"), "got: {msg}");
+    }
+}
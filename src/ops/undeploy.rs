@@ -8,19 +8,23 @@
 //! deploy behavior.
 
 use anyhow::{Context, Result};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tracing::{info, warn};
 
-use crate::config::Config;
+use crate::config::{Config, FileEntry};
+use crate::journal::{self, Action, Intent};
 use crate::paths::expand_tilde;
-use crate::platform::Fs;
+use crate::plan;
+use crate::platform::{CommandRunner, Fs};
 use crate::state::{RecoveryInfo, State};
+use crate::systemd::SystemdActions;
 
 use super::is_janus_symlink;
 
 /// Undeploy a single file's symlink. Verifies it's a janus symlink pointing to
 /// the expected staged path, then either removes the symlink or replaces it with
-/// a regular file copy.
+/// a regular file copy. Its `path_maps` copies in `mapped` get the same
+/// treatment, where they're still janus symlinks.
 ///
 /// Updates `state` to mark the file as no longer deployed. Does NOT save state.
 ///
@@ -29,6 +33,7 @@ pub fn undeploy_single(
     src: &str,
     link_path: &Path,
     target_path: &Path,
+    mapped: &[PathBuf],
     remove_file: bool,
     state: &mut State,
     fs: &impl Fs,
@@ -41,11 +46,16 @@ pub fn undeploy_single(
         return Ok(false);
     }
 
-    if remove_file {
-        fs.remove_file(target_path)
-            .with_context(|| format!("Failed to remove symlink: {}", target_path.display()))?;
-    } else {
-        undeploy_with_copy(link_path, target_path, fs)?;
+    let mapped = mapped
+        .iter()
+        .filter(|path| is_janus_symlink(path, link_path, fs));
+    for path in std::iter::once(target_path).chain(mapped.map(PathBuf::as_path)) {
+        if remove_file {
+            fs.remove_file(path)
+                .with_context(|| format!("Failed to remove symlink: {}", path.display()))?;
+        } else {
+            undeploy_with_copy(link_path, path, fs)?;
+        }
     }
 
     state.remove_deployed(src);
@@ -57,12 +67,15 @@ pub fn undeploy_single(
 /// Default behavior copies the staged file to the target so the application
 /// keeps a working config. `remove_file = true` just deletes the symlink.
 /// Skips files that aren't deployed or whose target isn't a janus symlink.
+/// systemd units among the undeployed files trigger a `daemon-reload` at the
+/// end, even if a later file failed.
 pub fn run(
     config: &Config,
     files: Option<&[String]>,
     remove_file: bool,
     dry_run: bool,
     fs: &impl Fs,
+    runner: &impl CommandRunner,
 ) -> Result<()> {
     let entries = config.filter_files(files);
     if entries.is_empty() {
@@ -71,13 +84,31 @@ pub fn run(
         return Ok(());
     }
 
-    let dotfiles_dir = config.dotfiles_dir(fs);
+    let mut actions = SystemdActions::new();
+    let result = undeploy_each(config, &entries, remove_file, dry_run, fs, &mut actions);
+    let systemd_result = actions.run(dry_run, runner);
+    let count = result?;
+    info!("Undeployed {} file(s)", count);
+    systemd_result
+}
+
+/// Undeploy each entry in order, bailing on the first error. Returns how many
+/// files were undeployed.
+fn undeploy_each(
+    config: &Config,
+    entries: &[&FileEntry],
+    remove_file: bool,
+    dry_run: bool,
+    fs: &impl Fs,
+    actions: &mut SystemdActions,
+) -> Result<usize> {
     let staged_dir = config.staged_dir(fs);
-    let mut state = State::load(&dotfiles_dir, fs)?;
+    let state_dir = config.state_dir(fs);
+    let mut state = State::load(&state_dir, fs)?;
     let mut count = 0usize;
 
-    for entry in &entries {
-        if !state.is_deployed(&entry.src) {
+    for entry in entries {
+        if !state.is_deployed(entry.key()) {
             info!("Not deployed, skipping: {}", entry.src);
             continue;
         }
@@ -98,24 +129,38 @@ pub fn run(
                     target_path.display()
                 );
             }
+            plan::action(
+                "undeploy",
+                &entry.src,
+                &target_path.to_string_lossy(),
+                if remove_file {
+                    "remove file"
+                } else {
+                    "leave copy"
+                },
+            );
+            actions.record_undeploy(entry, &target_path, fs);
             count += 1;
             continue;
         }
 
         let link_path = if entry.direct {
-            dotfiles_dir.join(&entry.src)
+            config.source_path(&entry.src, fs)
         } else {
             staged_dir.join(&entry.src)
         };
 
+        journal::begin(&state_dir, undeploy_intent(entry, &link_path), fs)?;
         if !undeploy_single(
-            &entry.src,
+            entry.key(),
             &link_path,
             &target_path,
+            &config.mapped_targets(&entry.target(), fs),
             remove_file,
             &mut state,
             fs,
         )? {
+            journal::commit(&state_dir, entry.key(), fs)?;
             continue;
         }
 
@@ -132,26 +177,44 @@ pub fn run(
                     target_path.display()
                 )],
                 instructions: vec![
+                    "The next janus command that changes anything records it from the deploy journal".to_string(),
                     format!(
-                        "Remove the [[deployed]] entry from the statefile with src = \"{}\"",
-                        entry.src
+                        "If that fails too, remove the [[deployed]] entry from the statefile with src = \"{}\"",
+                        entry.key()
                     ),
-                    format!("Or re-run: janus undeploy {}", entry.src),
+                    format!("Or re-run: janus undeploy {}", entry.selector()),
                 ],
             },
             fs,
         )?;
+        if let Err(e) = journal::commit(&state_dir, entry.key(), fs) {
+            warn!("{e:#}");
+        }
 
         if remove_file {
             info!("Undeployed {} (file removed)", entry.src);
         } else {
             info!("Undeployed {} (copy left at target)", entry.src);
         }
+        actions.record_undeploy(entry, &target_path, fs);
         count += 1;
     }
 
-    info!("Undeployed {} file(s)", count);
-    Ok(())
+    Ok(count)
+}
+
+/// The journal entry for undeploying `entry`, whose targets link to
+/// `link_path`.
+pub(crate) fn undeploy_intent(entry: &FileEntry, link_path: &Path) -> Intent {
+    Intent {
+        action: Action::Undeploy,
+        src: entry.key().to_string(),
+        target: entry.target(),
+        link: link_path.to_path_buf(),
+        direct: entry.direct,
+        backup: None,
+        backup_at: None,
+    }
 }
 
 /// Replace a symlink with a regular file copy, atomically.
@@ -173,6 +236,7 @@ fn undeploy_with_copy(staged_path: &Path, target_path: &Path, fs: &impl Fs) -> R
             temp_path.display()
         )
     })?;
+    super::preserve_xattrs(staged_path, &temp_path, fs);
 
     fs.rename(&temp_path, target_path).with_context(|| {
         let _ = fs.remove_file(&temp_path);
@@ -199,6 +263,7 @@ fn undeploy_with_copy(staged_path: &Path, target_path: &Path, fs: &impl Fs) -> R
             target_path.display()
         )
     })?;
+    super::preserve_xattrs(staged_path, target_path, fs);
 
     Ok(())
 }
@@ -206,6 +271,7 @@ fn undeploy_with_copy(staged_path: &Path, target_path: &Path, fs: &impl Fs) -> R
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::platform::FakeCommandRunner;
     use crate::test_helpers::*;
 
     fn deploy_and_undeploy_setup(fs: &crate::platform::FakeFs) -> Config {
@@ -226,7 +292,7 @@ mod tests {
     fn leaves_copy_default() {
         let fs = setup_fs();
         let config = deploy_and_undeploy_setup(&fs);
-        run(&config, None, false, false, &fs).unwrap();
+        run(&config, None, false, false, &fs, &FakeCommandRunner::new()).unwrap();
         let target = Path::new("/home/test/.config/a.conf");
         // Should be a regular file, not a symlink
         assert!(!fs.is_symlink(target));
@@ -239,7 +305,7 @@ mod tests {
     fn remove_file_deletes() {
         let fs = setup_fs();
         let config = deploy_and_undeploy_setup(&fs);
-        run(&config, None, true, false, &fs).unwrap();
+        run(&config, None, true, false, &fs, &FakeCommandRunner::new()).unwrap();
         assert!(!fs.exists(Path::new("/home/test/.config/a.conf")));
     }
 
@@ -247,7 +313,7 @@ mod tests {
     fn updates_state() {
         let fs = setup_fs();
         let config = deploy_and_undeploy_setup(&fs);
-        run(&config, None, false, false, &fs).unwrap();
+        run(&config, None, false, false, &fs, &FakeCommandRunner::new()).unwrap();
         // Verify state was persisted to disk (not just in-memory)
         let state = State::load(Path::new(DOTFILES), &fs).unwrap();
         assert!(!state.is_deployed("a.conf"));
@@ -261,6 +327,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn named_entry_sharing_a_src_undeploys_alone() {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "git/config", "[user]");
+        let toml = make_config_toml(&[
+            ("git/config", Some("~/.gitconfig")),
+            ("git/config", Some("~/work/.gitconfig")),
+        ])
+        .replacen(
+            "target = \"~/work/.gitconfig\"",
+            "name = \"work-gitconfig\"\ntarget = \"~/work/.gitconfig\"",
+            1,
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let runner = FakeCommandRunner::new();
+        crate::ops::deploy::run(&config, None, false, false, &fs, &runner).unwrap();
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert!(state.is_deployed("git/config"));
+        assert!(state.is_deployed("work-gitconfig"));
+
+        let named = ["name:work-gitconfig".to_string()];
+        run(&config, Some(&named), true, false, &fs, &runner).unwrap();
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert!(state.is_deployed("git/config"));
+        assert!(!state.is_deployed("work-gitconfig"));
+        assert!(fs.is_symlink(Path::new("/home/test/.gitconfig")));
+        assert!(!fs.exists(Path::new("/home/test/work/.gitconfig")));
+    }
+
     #[test]
     fn skips_not_deployed() {
         let fs = setup_fs();
@@ -271,7 +366,7 @@ mod tests {
             &make_config_toml(&[("a.conf", Some("~/.config/a.conf"))]),
         );
         // Should succeed without error (just skips)
-        run(&config, None, false, false, &fs).unwrap();
+        run(&config, None, false, false, &fs, &FakeCommandRunner::new()).unwrap();
         // File should be untouched (nothing to undeploy)
         assert!(!fs.exists(Path::new("/home/test/.config/a.conf")));
     }
@@ -290,7 +385,7 @@ mod tests {
             &make_config_toml(&[("a.conf", Some("~/.config/a.conf"))]),
         );
         // Should succeed without error, but skip the non-janus symlink
-        run(&config, None, false, false, &fs).unwrap();
+        run(&config, None, false, false, &fs, &FakeCommandRunner::new()).unwrap();
         // Symlink should still exist (wasn't touched)
         assert!(fs.is_symlink(Path::new("/home/test/.config/a.conf")));
     }
@@ -299,7 +394,7 @@ mod tests {
     fn dry_run() {
         let fs = setup_fs();
         let config = deploy_and_undeploy_setup(&fs);
-        run(&config, None, false, true, &fs).unwrap();
+        run(&config, None, false, true, &fs, &FakeCommandRunner::new()).unwrap();
         // Symlink should still exist
         assert!(fs.is_symlink(Path::new("/home/test/.config/a.conf")));
     }
@@ -318,11 +413,30 @@ mod tests {
             "dotfiles_dir = \"{DOTFILES}\"\nvars = [\"vars.toml\"]\n\n[[files]]\nsrc = \"direct.conf\"\ntarget = \"~/.config/direct.conf\"\ndirect = true\ntemplate = false\n"
         );
         let config = write_and_load_config(&fs, &toml);
-        run(&config, None, false, false, &fs).unwrap();
+        run(&config, None, false, false, &fs, &FakeCommandRunner::new()).unwrap();
         // Should be a regular file copy, not a symlink
         assert!(!fs.is_symlink(Path::new(target)));
         assert!(fs.is_file(Path::new(target)));
         let state = State::load(Path::new(DOTFILES), &fs).unwrap();
         assert!(!state.is_deployed("direct.conf"));
     }
+
+    #[test]
+    fn systemd_unit_triggers_daemon_reload() {
+        let fs = setup_fs();
+        let staged = format!("{DOTFILES}/.staged/unit.service");
+        fs.add_file(&staged, "[Unit]");
+        fs.add_symlink("/home/test/.config/unit.service", &staged);
+        let toml = format!(
+            "dotfiles_dir = \"{DOTFILES}\"\n\n[[files]]\nsrc = \"unit.service\"\nsystemd_unit = true\nreload_units = [\"unit.service\"]\n"
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let state_toml =
+            "[[deployed]]\nsrc = \"unit.service\"\ntarget = \"~/.config/unit.service\"\n";
+        fs.add_file(format!("{DOTFILES}/.janus_state.toml"), state_toml);
+
+        let runner = FakeCommandRunner::new();
+        run(&config, None, true, false, &fs, &runner).unwrap();
+        assert_eq!(runner.commands(), vec!["systemctl --user daemon-reload"]);
+    }
 }
@@ -0,0 +1,199 @@
+//! `janus undo`: revert the most recent mutating command.
+//!
+//! Restores every path recorded in the [`UndoRecord`] to its state before
+//! that command ran, in reverse order, then deletes the record, so undo
+//! itself can't be undone. Paths that changed again since the command ran
+//! are refused unless `--force` is set. Directories the command created and
+//! anything it did outside the filesystem (reload commands, systemd units)
+//! are not reverted.
+
+use anyhow::{Context, Result, bail};
+use std::path::Path;
+use tracing::info;
+
+use crate::config::Config;
+use crate::paths::collapse_tilde;
+use crate::plan;
+use crate::platform::Fs;
+use crate::undo::{Before, Change, UndoRecord, fingerprint};
+
+/// Revert the changes recorded for the most recent mutating command.
+pub fn run(config: &Config, force: bool, dry_run: bool, fs: &impl Fs) -> Result<()> {
+    let state_dir = config.state_dir(fs);
+    let Some(record) = UndoRecord::load(&state_dir, fs)? else {
+        bail!("Nothing to undo");
+    };
+
+    let modified: Vec<&Change> = record
+        .changes
+        .iter()
+        .filter(|change| fingerprint(&change.path, fs) != change.after)
+        .collect();
+    if !modified.is_empty() && !force {
+        let paths: Vec<String> = modified
+            .iter()
+            .map(|change| format!("  {}", collapse_tilde(&change.path, fs)))
+            .collect();
+        bail!(
+            "Changed since `{}` ran (pass --force to restore anyway):\n{}",
+            record.command,
+            paths.join("\n")
+        );
+    }
+
+    info!("Undoing `{}`", record.command);
+    for change in record.changes.iter().rev() {
+        let shown = collapse_tilde(&change.path, fs);
+        if dry_run {
+            info!("[dry-run] Would restore {shown}");
+            plan::action(
+                "restore",
+                "",
+                &change.path.to_string_lossy(),
+                &format!("undo `{}`", record.command),
+            );
+            continue;
+        }
+        restore(change, &state_dir, fs).with_context(|| format!("Failed to restore {shown}"))?;
+        info!("Restored {shown}");
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+    UndoRecord::clear(&state_dir, fs)?;
+    info!(
+        "Undid {} change(s). Commands it ran (reload hooks, systemd units) were not reverted",
+        record.changes.len()
+    );
+    Ok(())
+}
+
+/// Put a single path back the way it was before the recorded command.
+fn restore(change: &Change, state_dir: &Path, fs: &impl Fs) -> Result<()> {
+    let path = &change.path;
+    // Never write through a symlink the command left behind
+    if fs.is_symlink(path) || fs.is_file(path) {
+        fs.remove_file(path)?;
+    }
+    match &change.before {
+        Before::Absent => {
+            if fs.is_dir(path) {
+                // Only succeeds if empty; leave non-empty directories alone
+                let _ = fs.remove_dir(path);
+            }
+        }
+        Before::Dir => fs.create_dir_all(path)?,
+        Before::Symlink { target } => {
+            create_parent(path, fs)?;
+            fs.symlink(target, path)?;
+        }
+        Before::File { blob, mode } => {
+            let content = UndoRecord::blob(state_dir, blob, fs)?;
+            create_parent(path, fs)?;
+            fs.write(path, &content)?;
+            fs.set_file_mode(path, *mode)?;
+        }
+    }
+    Ok(())
+}
+
+fn create_parent(path: &Path, fs: &impl Fs) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs.create_dir_all(parent)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::FakeCommandRunner;
+    use crate::test_helpers::*;
+    use crate::undo::RecordingFs;
+
+    fn deploy_recorded(fs: &crate::platform::FakeFs, config: &Config) {
+        let recording = RecordingFs::new(fs, Path::new(DOTFILES));
+        crate::ops::deploy::run(
+            config,
+            None,
+            false,
+            false,
+            &recording,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        recording.save("janus deploy --all").unwrap();
+    }
+
+    #[test]
+    fn undo_deploy_restores_original_file() {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "a.conf", "managed");
+        fs.add_file("/home/test/.config/a.conf", "hand-written");
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        deploy_recorded(&fs, &config);
+        assert!(fs.is_symlink(Path::new("/home/test/.config/a.conf")));
+
+        run(&config, false, false, &fs).unwrap();
+
+        let target = Path::new("/home/test/.config/a.conf");
+        assert!(!fs.is_symlink(target));
+        assert_eq!(fs.read_to_string(target).unwrap(), "hand-written");
+        assert_eq!(
+            fs.read_to_string(Path::new(&format!("{DOTFILES}/.janus_state.toml")))
+                .unwrap(),
+            ""
+        );
+        assert!(
+            UndoRecord::load(Path::new(DOTFILES), &fs)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn refuses_paths_changed_since() {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "a.conf", "managed");
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        deploy_recorded(&fs, &config);
+        fs.remove_file(Path::new("/home/test/.config/a.conf"))
+            .unwrap();
+        fs.add_file("/home/test/.config/a.conf", "edited later");
+
+        let msg = format!("{:#}", run(&config, false, false, &fs).unwrap_err());
+        assert!(
+            msg.contains("Changed since `janus deploy --all`"),
+            "got: {msg}"
+        );
+        assert!(msg.contains("~/.config/a.conf"), "got: {msg}");
+
+        run(&config, true, false, &fs).unwrap();
+        assert!(!fs.exists(Path::new("/home/test/.config/a.conf")));
+    }
+
+    #[test]
+    fn dry_run_keeps_everything() {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "a.conf", "managed");
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        deploy_recorded(&fs, &config);
+
+        run(&config, false, true, &fs).unwrap();
+        assert!(fs.is_symlink(Path::new("/home/test/.config/a.conf")));
+        assert!(
+            UndoRecord::load(Path::new(DOTFILES), &fs)
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn nothing_to_undo() {
+        let fs = setup_fs();
+        let config = write_and_load_config(&fs, &make_config_toml(&[("a.conf", None)]));
+        let msg = format!("{:#}", run(&config, false, false, &fs).unwrap_err());
+        assert_eq!(msg, "Nothing to undo");
+    }
+}
@@ -2,128 +2,302 @@
 //! copies of a file (source, generated, staged).
 //!
 //! By default, leaves a regular file at the target path (safety by default).
-//! With `--remove-file`, the target is deleted entirely.
+//! With `--remove-file`, the target is deleted entirely. With
+//! `use_trash = true`, the source, generated, and staged copies go to the
+//! trash instead of being deleted (see [`crate::trash`]). With
+//! `--tombstone`, the target is added to the state's ignored list (reason
+//! `unimported`) so a later `janus import` of its directory doesn't offer it
+//! again; `janus import --forget` clears that. With `--archive`, the source
+//! and the config entry are moved to `archive/` in the dotfiles directory
+//! instead, for `janus restore-archived` (see [`super::archive`]).
 //!
 //! Intentionally has no `--all` flag — unimporting everything is too destructive.
-//! Requires an explicit file list.
+//! Requires an explicit file list, or `--filesets`, which lists the files and
+//! asks for the fileset names to be typed back (see [`confirm_filesets`]).
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use std::path::Path;
 use tracing::{debug, info, warn};
 
-use crate::config::Config;
-use crate::paths::expand_tilde;
-use crate::platform::Fs;
+use crate::config::{Config, FileEntry};
+use crate::journal;
+use crate::paths::{collapse_tilde, expand_tilde};
+use crate::plan;
+use crate::platform::{CommandRunner, Fs, Prompter};
 use crate::state::State;
+use crate::systemd::SystemdActions;
+use crate::trash;
 
 /// Unimport files: undeploy, remove config entry, delete source/generated/staged copies.
 ///
 /// For each matched file:
 /// 1. Undeploy if currently deployed (respects `remove_file` flag)
 /// 2. Remove the `[[files]]` config entry via `toml_edit`
-/// 3. Delete source, generated, and staged files
-/// 4. Remove any corresponding ignored entry from state
+/// 3. Delete source, generated, and staged files, or with `archive`, move
+///    the source and the removed config entry to the archive
+/// 4. With `tombstone`, mark the target ignored so import skips it
 /// 5. Save state
+///
+/// A file that fails doesn't stop the rest; all failures are reported at
+/// the end.
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     config: &Config,
     config_path: &Path,
     files: &[String],
     remove_file: bool,
+    tombstone: bool,
+    archive: bool,
     dry_run: bool,
     fs: &impl Fs,
+    runner: &impl CommandRunner,
 ) -> Result<()> {
     if files.is_empty() {
         anyhow::bail!("Specify files to unimport");
     }
 
-    let dotfiles_dir = config.dotfiles_dir(fs);
-    let generated_dir = config.generated_dir(fs);
-    let staged_dir = config.staged_dir(fs);
-    let mut state = State::load(&dotfiles_dir, fs)?;
-
     let entries = config.filter_files(Some(files));
     if entries.is_empty() {
         config.bail_unmatched(Some(files))?;
     }
 
+    // Undeployed systemd units get a daemon-reload, even if a later file failed
+    let mut actions = SystemdActions::new();
+    let result = unimport_each(
+        config,
+        config_path,
+        &entries,
+        remove_file,
+        tombstone,
+        archive,
+        dry_run,
+        fs,
+        &mut actions,
+    );
+    let systemd_result = actions.run(dry_run, runner);
+    result?;
+    systemd_result
+}
+
+/// Confirm unimporting whole filesets: list the files their `patterns`
+/// resolve to and require the fileset names to be typed back exactly
+/// (comma-separated, as given). Skipped with `yes` (`--yes`) or `dry_run`.
+pub fn confirm_filesets(
+    config: &Config,
+    filesets: &[String],
+    patterns: &[String],
+    yes: bool,
+    dry_run: bool,
+    prompter: &impl Prompter,
+) -> Result<()> {
+    if yes || dry_run {
+        return Ok(());
+    }
+    let entries = config.filter_files(Some(patterns));
+    if entries.is_empty() {
+        // run() reports the unmatched patterns
+        return Ok(());
+    }
+    let names = filesets.join(",");
+    println!(
+        "unimport --filesets {names} will remove {} file(s) from management:",
+        entries.len()
+    );
     for entry in &entries {
-        let src = &entry.src;
-        let target_path = expand_tilde(&entry.target(), fs);
+        println!("  {}", entry.src);
+    }
+    let typed = prompter.input(&format!("Type {names} to confirm"), None)?;
+    if typed.trim() != names {
+        bail!("Aborted: confirmation didn't match {names}; pass --yes to skip this check");
+    }
+    Ok(())
+}
 
-        if dry_run {
-            info!("[dry-run] Would unimport: {}", src);
-            continue;
+/// Unimport each entry in order. A failure doesn't stop the rest; every
+/// failed file is reported at the end as [`super::FileErrors`].
+#[allow(clippy::too_many_arguments)]
+fn unimport_each(
+    config: &Config,
+    config_path: &Path,
+    entries: &[&FileEntry],
+    remove_file: bool,
+    tombstone: bool,
+    archive: bool,
+    dry_run: bool,
+    fs: &impl Fs,
+    actions: &mut SystemdActions,
+) -> Result<()> {
+    let mut state = State::load(&config.state_dir(fs), fs)?;
+    let mut errors = Vec::new();
+    for entry in entries {
+        if let Err(e) = unimport_one(
+            config,
+            config_path,
+            entry,
+            remove_file,
+            tombstone,
+            archive,
+            dry_run,
+            &mut state,
+            fs,
+            actions,
+        ) {
+            warn!("Failed to unimport {}: {e:#}", entry.src);
+            errors.push((entry.src.clone(), e));
         }
+    }
+    if !errors.is_empty() {
+        return Err(super::FileErrors::new("unimport", &errors).into());
+    }
+    Ok(())
+}
+
+/// Unimport one entry: undeploy, remove its config entry and copies, and
+/// save state.
+#[allow(clippy::too_many_arguments)]
+fn unimport_one(
+    config: &Config,
+    config_path: &Path,
+    entry: &FileEntry,
+    remove_file: bool,
+    tombstone: bool,
+    archive: bool,
+    dry_run: bool,
+    state: &mut State,
+    fs: &impl Fs,
+    actions: &mut SystemdActions,
+) -> Result<()> {
+    let state_dir = config.state_dir(fs);
+    let staged_dir = config.staged_dir(fs);
+    let src = &entry.src;
+    let key = entry.key();
+    let target_path = expand_tilde(&entry.target(), fs);
+    // Another entry deploying the same source keeps its copies
+    let shared = config.files.iter().any(|e| e.src == *src && e.key() != key);
 
-        // 1. Undeploy if currently deployed
-        if state.is_deployed(src) {
-            let link_path = if entry.direct {
-                dotfiles_dir.join(src)
+    if dry_run {
+        info!("[dry-run] Would unimport: {}", entry.selector());
+        plan::action(
+            "unimport",
+            &entry.selector(),
+            &target_path.to_string_lossy(),
+            if shared {
+                "src shared with another entry"
             } else {
-                staged_dir.join(src)
-            };
-            super::undeploy::undeploy_single(
-                src,
-                &link_path,
-                &target_path,
-                remove_file,
-                &mut state,
-                fs,
-            )?;
+                ""
+            },
+        );
+        if state.is_deployed(key) {
+            actions.record_undeploy(entry, &target_path, fs);
         }
+        return Ok(());
+    }
 
-        // 2. Remove config entry
-        remove_config_entry(config_path, src, fs)?;
-
-        // 3. Remove source file from dotfiles dir
-        let source_path = dotfiles_dir.join(src);
-        if fs.exists(&source_path) {
-            fs.remove_file(&source_path).with_context(|| {
-                format!("Failed to remove source file: {}", source_path.display())
-            })?;
-            // Clean up empty parent directories
-            remove_empty_parents(&source_path, &dotfiles_dir, fs);
-            debug!("Removed source: {}", source_path.display());
+    // 1. Undeploy if currently deployed
+    if state.is_deployed(key) {
+        let link_path = if entry.direct {
+            config.source_path(src, fs)
+        } else {
+            staged_dir.join(src)
+        };
+        journal::begin(
+            &state_dir,
+            super::undeploy::undeploy_intent(entry, &link_path),
+            fs,
+        )?;
+        if super::undeploy::undeploy_single(
+            key,
+            &link_path,
+            &target_path,
+            &config.mapped_targets(&entry.target(), fs),
+            remove_file,
+            state,
+            fs,
+        )? {
+            actions.record_undeploy(entry, &target_path, fs);
         }
+    }
 
-        // 4. Remove generated file
-        let generated_path = generated_dir.join(src);
-        if fs.exists(&generated_path) {
-            fs.remove_file(&generated_path).with_context(|| {
-                format!(
-                    "Failed to remove generated file: {}",
-                    generated_path.display()
-                )
-            })?;
-            remove_empty_parents(&generated_path, &generated_dir, fs);
-            debug!("Removed generated: {}", generated_path.display());
-        }
+    // 2. Remove config entry
+    let removed = remove_config_entry(config_path, src, entry.name.as_deref(), fs)?;
 
-        // 5. Remove staged file
-        let staged_path = staged_dir.join(src);
-        if fs.exists(&staged_path) {
-            fs.remove_file(&staged_path).with_context(|| {
-                format!("Failed to remove staged file: {}", staged_path.display())
-            })?;
-            remove_empty_parents(&staged_path, &staged_dir, fs);
-            debug!("Removed staged: {}", staged_path.display());
-        }
+    if shared {
+        info!("Keeping {src}: another entry still deploys it");
+    } else if archive {
+        super::archive::archive(config, src, removed.as_deref().unwrap_or_default(), fs)?;
+        state.remove_activity(src);
+    } else {
+        remove_copies(config, src, fs)?;
+        state.remove_activity(src);
+    }
 
-        state
-            .save(fs)
-            .with_context(|| format!("Failed to save state after unimporting {}", src))?;
+    if tombstone {
+        state.add_ignored(collapse_tilde(&target_path, fs), "unimported".to_string());
+    }
+    state
+        .save(fs)
+        .with_context(|| format!("Failed to save state after unimporting {}", src))?;
+    if let Err(e) = journal::commit(&state_dir, key, fs) {
+        warn!("{e:#}");
+    }
 
-        info!("Unimported {}", src);
+    info!("Unimported {}", entry.selector());
+    Ok(())
+}
+
+/// Delete (or trash) `src`'s source, generated, and staged copies.
+fn remove_copies(config: &Config, src: &str, fs: &impl Fs) -> Result<()> {
+    let dotfiles_dir = config.dotfiles_dir(fs);
+    let generated_dir = config.generated_dir(fs);
+    let staged_dir = config.staged_dir(fs);
+
+    // 3. Remove source file from dotfiles dir (never from a lower layer,
+    // which is usually someone else's repo)
+    let source_path = dotfiles_dir.join(src);
+    if fs.exists(&source_path) {
+        trash::remove_file(config, &source_path, fs)
+            .with_context(|| format!("Failed to remove source file: {}", source_path.display()))?;
+        // Clean up empty parent directories
+        remove_empty_parents(&source_path, &dotfiles_dir, fs);
+        debug!("Removed source: {}", source_path.display());
+    }
+
+    // 4. Remove generated file
+    let generated_path = generated_dir.join(src);
+    if fs.exists(&generated_path) {
+        trash::remove_file(config, &generated_path, fs).with_context(|| {
+            format!(
+                "Failed to remove generated file: {}",
+                generated_path.display()
+            )
+        })?;
+        remove_empty_parents(&generated_path, &generated_dir, fs);
+        debug!("Removed generated: {}", generated_path.display());
     }
 
+    // 5. Remove staged file
+    let staged_path = staged_dir.join(src);
+    if fs.exists(&staged_path) {
+        trash::remove_file(config, &staged_path, fs)
+            .with_context(|| format!("Failed to remove staged file: {}", staged_path.display()))?;
+        remove_empty_parents(&staged_path, &staged_dir, fs);
+        debug!("Removed staged: {}", staged_path.display());
+    }
     Ok(())
 }
 
-/// Remove the `[[files]]` entry matching `src` from the config file.
+/// Remove the `[[files]]` entry matching `src` and `name` from the config
+/// file. Without a `name`, the first unnamed entry for `src` matches.
 ///
 /// Uses `toml_edit` to preserve formatting and comments in the config.
-/// Warns (but doesn't error) if no matching entry is found.
-fn remove_config_entry(config_path: &Path, src: &str, fs: &impl Fs) -> Result<()> {
+/// Warns (but doesn't error) if no matching entry is found. Returns the
+/// removed `[[files]]` table as written.
+fn remove_config_entry(
+    config_path: &Path,
+    src: &str,
+    name: Option<&str>,
+    fs: &impl Fs,
+) -> Result<Option<String>> {
     let contents = fs
         .read_to_string(config_path)
         .with_context(|| format!("Failed to read config: {}", config_path.display()))?;
@@ -132,6 +306,7 @@ fn remove_config_entry(config_path: &Path, src: &str, fs: &impl Fs) -> Result<()
         .parse::<toml_edit::DocumentMut>()
         .with_context(|| "Failed to parse config for editing")?;
 
+    let mut removed = None;
     if let Some(files) = doc.get_mut("files")
         && let Some(array) = files.as_array_of_tables_mut()
     {
@@ -140,6 +315,7 @@ fn remove_config_entry(config_path: &Path, src: &str, fs: &impl Fs) -> Result<()
         for (i, table) in array.iter().enumerate() {
             if let Some(entry_src) = table.get("src").and_then(|v| v.as_str())
                 && entry_src == src
+                && table.get("name").and_then(|v| v.as_str()) == name
             {
                 index_to_remove = Some(i);
                 break;
@@ -147,7 +323,14 @@ fn remove_config_entry(config_path: &Path, src: &str, fs: &impl Fs) -> Result<()
         }
 
         if let Some(idx) = index_to_remove {
+            let mut removed_doc = toml_edit::DocumentMut::new();
+            let mut removed_array = toml_edit::ArrayOfTables::new();
+            if let Some(table) = array.get(idx) {
+                removed_array.push(table.clone());
+            }
             array.remove(idx);
+            removed_doc.insert("files", toml_edit::Item::ArrayOfTables(removed_array));
+            removed = Some(removed_doc.to_string());
         } else {
             warn!("Config entry not found for src: {}", src);
         }
@@ -157,11 +340,11 @@ fn remove_config_entry(config_path: &Path, src: &str, fs: &impl Fs) -> Result<()
         .with_context(|| format!("Failed to write config: {}", config_path.display()))?;
 
     debug!("Removed config entry: src={}", src);
-    Ok(())
+    Ok(removed)
 }
 
 /// Remove empty parent directories up to (but not including) the stop directory.
-fn remove_empty_parents(path: &Path, stop_at: &Path, fs: &impl Fs) {
+pub(crate) fn remove_empty_parents(path: &Path, stop_at: &Path, fs: &impl Fs) {
     let mut current = path.parent();
     while let Some(dir) = current {
         if dir == stop_at {
@@ -177,6 +360,7 @@ fn remove_empty_parents(path: &Path, stop_at: &Path, fs: &impl Fs) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::platform::{FakeCommandRunner, FakePrompter};
     use crate::state::State;
     use crate::test_helpers::*;
 
@@ -201,7 +385,18 @@ mod tests {
         let fs = setup_fs();
         let config = setup_managed_file(&fs);
         let files = vec!["a.conf".to_string()];
-        run(&config, Path::new(CONFIG_PATH), &files, false, false, &fs).unwrap();
+        run(
+            &config,
+            Path::new(CONFIG_PATH),
+            &files,
+            false,
+            false,
+            false,
+            false,
+            &fs,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         // Source, generated, staged should be removed
         assert!(!fs.exists(Path::new(&format!("{DOTFILES}/a.conf"))));
         assert!(!fs.exists(Path::new(&format!("{DOTFILES}/.generated/a.conf"))));
@@ -214,6 +409,28 @@ mod tests {
         assert!(!state.is_deployed("a.conf"));
     }
 
+    #[test]
+    fn tombstone_marks_target_ignored() {
+        let fs = setup_fs();
+        let config = setup_managed_file(&fs);
+        let files = vec!["a.conf".to_string()];
+        run(
+            &config,
+            Path::new(CONFIG_PATH),
+            &files,
+            false,
+            true,
+            false,
+            false,
+            &fs,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert!(state.is_ignored("~/.config/a.conf"));
+        assert_eq!(state.ignored[0].reason, "unimported");
+    }
+
     #[test]
     fn not_deployed() {
         let fs = setup_fs();
@@ -226,16 +443,88 @@ mod tests {
             &make_config_toml(&[("a.conf", Some("~/.config/a.conf"))]),
         );
         let files = vec!["a.conf".to_string()];
-        run(&config, Path::new(CONFIG_PATH), &files, false, false, &fs).unwrap();
+        run(
+            &config,
+            Path::new(CONFIG_PATH),
+            &files,
+            false,
+            false,
+            false,
+            false,
+            &fs,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         // Files should still be cleaned up
         assert!(!fs.exists(Path::new(&format!("{DOTFILES}/a.conf"))));
     }
 
+    #[test]
+    fn failed_file_does_not_stop_the_rest() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.conf"), "a");
+        fs.add_dir(format!("{DOTFILES}/.generated/a.conf"));
+        fs.add_file(format!("{DOTFILES}/b.conf"), "b");
+        let config = write_and_load_config(
+            &fs,
+            &make_config_toml(&[("a.conf", None), ("b.conf", None)]),
+        );
+        let files = vec!["a.conf".to_string(), "b.conf".to_string()];
+        let err = run(
+            &config,
+            Path::new(CONFIG_PATH),
+            &files,
+            false,
+            false,
+            false,
+            false,
+            &fs,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap_err();
+        let failed = err.downcast_ref::<crate::ops::FileErrors>().unwrap();
+        assert_eq!(failed.errors.len(), 1);
+        assert_eq!(failed.errors[0].0, "a.conf");
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/b.conf"))));
+    }
+
+    #[test]
+    fn fileset_confirmation_requires_typed_names() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.conf"), "a");
+        let toml = format!(
+            "{}\n[filesets.desktop]\npatterns = [\"a.conf\"]\n",
+            make_config_toml(&[("a.conf", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let filesets = vec!["desktop".to_string()];
+        let patterns = config.resolve_filesets(&filesets).unwrap();
+        let confirm = |typed: &str, yes: bool| {
+            let prompter = FakePrompter::new(vec![]).with_inputs(&[typed]);
+            confirm_filesets(&config, &filesets, &patterns, yes, false, &prompter)
+        };
+
+        assert!(confirm("desktop", false).is_ok());
+        let msg = format!("{:#}", confirm("yes", false).unwrap_err());
+        assert!(msg.contains("Aborted"), "got: {msg}");
+        assert!(confirm("", true).is_ok());
+    }
+
     #[test]
     fn empty_files_errors() {
         let fs = setup_fs();
         let config = write_and_load_config(&fs, &make_config_toml(&[]));
-        let result = run(&config, Path::new(CONFIG_PATH), &[], false, false, &fs);
+        let result = run(
+            &config,
+            Path::new(CONFIG_PATH),
+            &[],
+            false,
+            false,
+            false,
+            false,
+            &fs,
+            &FakeCommandRunner::new(),
+        );
         let msg = format!("{:#}", result.unwrap_err());
         assert!(
             msg.contains("No files") || msg.contains("Specify"),
@@ -248,7 +537,18 @@ mod tests {
         let fs = setup_fs();
         let config = setup_managed_file(&fs);
         let files = vec!["a.conf".to_string()];
-        run(&config, Path::new(CONFIG_PATH), &files, false, true, &fs).unwrap();
+        run(
+            &config,
+            Path::new(CONFIG_PATH),
+            &files,
+            false,
+            false,
+            false,
+            true,
+            &fs,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         // Nothing should be removed
         assert!(fs.exists(Path::new(&format!("{DOTFILES}/a.conf"))));
         assert!(fs.is_symlink(Path::new("/home/test/.config/a.conf")));
@@ -269,7 +569,18 @@ mod tests {
             &make_config_toml(&[("deep/nested/a.conf", Some("~/.config/deep/nested/a.conf"))]),
         );
         let files = vec!["deep/nested/a.conf".to_string()];
-        run(&config, Path::new(CONFIG_PATH), &files, false, false, &fs).unwrap();
+        run(
+            &config,
+            Path::new(CONFIG_PATH),
+            &files,
+            false,
+            false,
+            false,
+            false,
+            &fs,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         // Parent dirs should be removed since they're empty
         assert!(!fs.exists(Path::new(&format!("{DOTFILES}/deep/nested"))));
         assert!(!fs.exists(Path::new(&format!("{DOTFILES}/deep"))));
@@ -280,7 +591,18 @@ mod tests {
         let fs = setup_fs();
         let config = setup_managed_file(&fs);
         let files = vec!["a.conf".to_string()];
-        run(&config, Path::new(CONFIG_PATH), &files, false, false, &fs).unwrap();
+        run(
+            &config,
+            Path::new(CONFIG_PATH),
+            &files,
+            false,
+            false,
+            false,
+            false,
+            &fs,
+            &FakeCommandRunner::new(),
+        )
+        .unwrap();
         // Config should no longer contain the entry
         let content = fs.read_to_string(Path::new(CONFIG_PATH)).unwrap();
         assert!(
@@ -296,6 +618,6 @@ mod tests {
         let toml = make_config_toml(&[("a.conf", Some("~/.config/a.conf"))]);
         fs.add_file(CONFIG_PATH, toml.as_str());
         // Removing a non-existent entry should warn but not error
-        super::remove_config_entry(Path::new(CONFIG_PATH), "nonexistent.conf", &fs).unwrap();
+        super::remove_config_entry(Path::new(CONFIG_PATH), "nonexistent.conf", None, &fs).unwrap();
     }
 }
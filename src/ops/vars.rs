@@ -0,0 +1,841 @@
+//! `janus vars`: document the repo's template variables, and detect
+//! machine-specific ones.
+//!
+//! `janus vars docs` scans every template for the variables it references
+//! and cross-checks them against the vars files, vars script, and secrets
+//! files that apply to it, producing a Markdown table of each variable,
+//! where it's used, where it's defined, and whether any file using it has
+//! no definition.
+//!
+//! Names are found statically, so loop variables, `set` variables, macro
+//! arguments, function calls, and tests after `is` are left out; `janus` is
+//! always defined.
+//!
+//! `janus vars detect` probes the system for values that differ between
+//! machines (monitors, GPU vendor, battery, dark or light preference) and
+//! writes them to a vars file, by default [`DEFAULT_DETECTED_FILE`]. Every
+//! probe runs through the [`CommandRunner`], and a tool that's missing just
+//! leaves its keys out.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+use tracing::{debug, info};
+
+use super::explain::referenced_names;
+use super::generate::{load_vars, run_vars_script};
+use crate::config::{Config, FileEntry};
+use crate::plan;
+use crate::platform::{CommandRunner, Fs};
+use crate::secrets;
+use crate::template::template_blocks;
+
+/// Words Tera gives meaning to, plus the always-present `janus` object.
+const RESERVED: &[&str] = &[
+    "and",
+    "or",
+    "not",
+    "in",
+    "is",
+    "if",
+    "elif",
+    "else",
+    "endif",
+    "for",
+    "endfor",
+    "set",
+    "set_global",
+    "macro",
+    "endmacro",
+    "block",
+    "endblock",
+    "extends",
+    "include",
+    "import",
+    "filter",
+    "endfilter",
+    "raw",
+    "endraw",
+    "break",
+    "continue",
+    "true",
+    "false",
+    "True",
+    "False",
+    "loop",
+    "self",
+    "super",
+    "as",
+    "ignore",
+    "missing",
+    "with",
+    "janus",
+];
+
+/// One variable's documentation row.
+#[derive(Debug, PartialEq, Eq)]
+pub struct VarDoc {
+    /// Top-level variable name.
+    pub name: String,
+    /// `src:line` of each file's first use, in config order.
+    pub used_in: Vec<String>,
+    /// Every vars file, script, or secrets file defining it, labelled with
+    /// how it applies (`vars.toml (global)`).
+    pub defined_in: Vec<String>,
+    /// Files using it with no applicable definition.
+    pub missing_for: Vec<String>,
+}
+
+impl VarDoc {
+    fn new(name: &str) -> Self {
+        VarDoc {
+            name: name.to_string(),
+            used_in: Vec::new(),
+            defined_in: Vec::new(),
+            missing_for: Vec::new(),
+        }
+    }
+
+    fn status(&self) -> String {
+        if self.used_in.is_empty() {
+            "unused".to_string()
+        } else if self.missing_for.is_empty() {
+            "ok".to_string()
+        } else if self.missing_for.len() == self.used_in.len() {
+            "missing".to_string()
+        } else {
+            format!("missing for {}", self.missing_for.join(", "))
+        }
+    }
+}
+
+/// The variables `template` reads from its context, with the line each is
+/// first used on.
+pub(crate) fn template_variables(template: &str) -> Vec<(String, usize)> {
+    let blocks: Vec<&str> = template_blocks(template).collect();
+    let mut locals: HashSet<String> = HashSet::new();
+    let local_patterns = [
+        Regex::new(r"^\s*for\s+(\w+)(?:\s*,\s*(\w+))?\s+in\b").expect("valid regex"),
+        Regex::new(r"^\s*set(?:_global)?\s+(\w+)\s*=").expect("valid regex"),
+        Regex::new(r"^\s*macro\s+(\w+)\s*\(([^)]*)\)").expect("valid regex"),
+    ];
+    for block in &blocks {
+        for pattern in &local_patterns {
+            let Some(captures) = pattern.captures(block) else {
+                continue;
+            };
+            for m in captures.iter().skip(1).flatten() {
+                for arg in m.as_str().split(',') {
+                    let name = arg.split('=').next().unwrap_or_default().trim();
+                    if !name.is_empty() {
+                        locals.insert(name.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    let mut found: Vec<(String, usize)> = Vec::new();
+    for block in blocks {
+        // Blocks are slices of the template, so their offset gives the line
+        let offset = block.as_ptr() as usize - template.as_ptr() as usize;
+        let line = template[..offset].matches('\n').count() + 1;
+        for name in referenced_names(block) {
+            let word = regex::escape(&name);
+            let call = Regex::new(&format!(r"\b{word}\s*\(")).expect("valid regex");
+            let test = Regex::new(&format!(r"\bis\s+(?:not\s+)?{word}\b")).expect("valid regex");
+            if RESERVED.contains(&name.as_str())
+                || locals.contains(&name)
+                || call.is_match(block)
+                || test.is_match(block)
+                || found.iter().any(|(n, _)| *n == name)
+            {
+                continue;
+            }
+            found.push((name, line));
+        }
+    }
+    found
+}
+
+/// Caches each vars and secrets file's top-level names.
+#[derive(Default)]
+struct Definitions {
+    files: HashMap<(String, bool), Vec<String>>,
+}
+
+impl Definitions {
+    fn names(
+        &mut self,
+        config: &Config,
+        file: &str,
+        secret: bool,
+        fs: &impl Fs,
+    ) -> Result<&[String]> {
+        let key = (file.to_string(), secret);
+        if !self.files.contains_key(&key) {
+            let dotfiles_dir = config.dotfiles_dir(fs);
+            let files = std::slice::from_ref(&key.0);
+            let names = if secret {
+                secrets::parse_secret_files(&dotfiles_dir, files, fs)?
+                    .into_iter()
+                    .map(|s| s.name)
+                    .collect()
+            } else {
+                load_vars(&dotfiles_dir, files, fs)?.into_keys().collect()
+            };
+            self.files.insert(key.clone(), names);
+        }
+        Ok(&self.files[&key])
+    }
+}
+
+/// The `(label, names)` layers that supply `entry`'s variables, in merge
+/// order.
+fn layers(
+    config: &Config,
+    entry: &FileEntry,
+    script_names: &[String],
+    defs: &mut Definitions,
+    fs: &impl Fs,
+) -> Result<Vec<(String, Vec<String>)>> {
+    let mut sources: Vec<(String, String, bool)> = Vec::new();
+    for f in &config.vars {
+        sources.push((f.clone(), format!("{f} (global)"), false));
+    }
+    for f in &config.secrets {
+        sources.push((f.clone(), format!("{f} (global secrets)"), true));
+    }
+    for (name, fileset) in config.matching_filesets_named(&entry.src) {
+        for f in &fileset.vars {
+            sources.push((f.clone(), format!("{f} (fileset {name})"), false));
+        }
+        for f in &fileset.secrets {
+            sources.push((f.clone(), format!("{f} (fileset {name} secrets)"), true));
+        }
+    }
+    for f in &entry.vars {
+        sources.push((f.clone(), format!("{f} (per-file)"), false));
+    }
+    for f in &entry.secrets {
+        sources.push((f.clone(), format!("{f} (per-file secrets)"), true));
+    }
+
+    let mut layers = Vec::new();
+    if !config.var_defaults.is_empty() {
+        let mut names: Vec<String> = config.var_defaults.keys().cloned().collect();
+        names.sort();
+        layers.push(("var_defaults (config)".to_string(), names));
+    }
+    if let Some(script) = &config.vars_script {
+        layers.push((format!("{script} (vars_script)"), script_names.to_vec()));
+    }
+    for (file, label, secret) in sources {
+        let names = defs.names(config, &file, secret, fs)?.to_vec();
+        layers.push((label, names));
+    }
+    Ok(layers)
+}
+
+/// Document every variable the templates use or the vars files define.
+pub fn compute(config: &Config, fs: &impl Fs) -> Result<Vec<VarDoc>> {
+    let dotfiles_dir = config.dotfiles_dir(fs);
+    let mut defs = Definitions::default();
+
+    // The script's output names depend only on the global vars
+    let script_names: Vec<String> = match &config.vars_script {
+        Some(script) => {
+            let globals = load_vars(&dotfiles_dir, &config.vars, fs)?;
+            run_vars_script(&dotfiles_dir, script, &globals, fs)?
+                .into_keys()
+                .collect()
+        }
+        None => Vec::new(),
+    };
+
+    let mut docs: BTreeMap<String, VarDoc> = BTreeMap::new();
+    for entry in config.files.iter().filter(|e| e.template && !e.direct) {
+        let layers = layers(config, entry, &script_names, &mut defs, fs)?;
+        for (label, names) in &layers {
+            for name in names {
+                let doc = docs
+                    .entry(name.clone())
+                    .or_insert_with(|| VarDoc::new(name));
+                if !doc.defined_in.contains(label) {
+                    doc.defined_in.push(label.clone());
+                }
+            }
+        }
+
+        let source = config.source_path(&entry.src, fs);
+        if !fs.exists(&source) {
+            continue;
+        }
+        let template = fs
+            .read_to_string(&source)
+            .with_context(|| format!("Failed to read template: {}", source.display()))?;
+        for (name, line) in template_variables(&template) {
+            let doc = docs
+                .entry(name.clone())
+                .or_insert_with(|| VarDoc::new(&name));
+            doc.used_in.push(format!("{}:{line}", entry.src));
+            if !layers.iter().any(|(_, names)| names.contains(&name)) {
+                doc.missing_for.push(entry.src.clone());
+            }
+        }
+    }
+    Ok(docs.into_values().collect())
+}
+
+/// Write the variables table to `out` as Markdown.
+pub fn docs(config: &Config, fs: &impl Fs, out: &mut impl Write) -> Result<()> {
+    let docs = compute(config, fs)?;
+    writeln!(out, "| Variable | Used in | Defined in | Status |")?;
+    writeln!(out, "|----------|---------|------------|--------|")?;
+    for doc in &docs {
+        writeln!(
+            out,
+            "| `{}` | {} | {} | {} |",
+            doc.name,
+            doc.used_in.join(", "),
+            doc.defined_in.join(", "),
+            doc.status()
+        )?;
+    }
+    Ok(())
+}
+
+/// File `janus vars detect` writes, relative to `dotfiles_dir`.
+pub const DEFAULT_DETECTED_FILE: &str = "vars.detected.toml";
+
+/// Command listing outputs and modes on wlroots compositors.
+const WLR_RANDR: &str = "wlr-randr";
+/// Command listing outputs and modes under X11 (and XWayland).
+const XRANDR: &str = "xrandr --current";
+/// Command listing PCI devices, display controllers among them.
+const LSPCI: &str = "lspci";
+/// Command printing the type of every power supply. It succeeds with no
+/// output when there are none, and fails only without the sysfs class.
+const POWER_SUPPLIES: &str = "cd /sys/class/power_supply && { cat -- */type 2>/dev/null; true; }";
+/// Commands reading the desktop's dark or light preference.
+const COLOR_SCHEME: &str = "gsettings get org.gnome.desktop.interface color-scheme";
+const GTK_THEME: &str = "gsettings get org.gnome.desktop.interface gtk-theme";
+
+/// A connected monitor's current mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Monitor {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    /// Refresh rate in Hz, if the tool reported it.
+    pub refresh: Option<f64>,
+    /// Output scale, if the tool reported it (wlr-randr does).
+    pub scale: Option<f64>,
+    pub primary: bool,
+}
+
+/// What the probes found. A probe that couldn't run or found nothing
+/// leaves its field empty.
+#[derive(Debug, Default, PartialEq)]
+pub struct Detected {
+    pub monitors: Vec<Monitor>,
+    /// `nvidia`, `amd`, `intel`, or `other`, one per display controller.
+    pub gpu_vendors: Vec<String>,
+    /// `None` only when power supplies can't be listed at all.
+    pub has_battery: Option<bool>,
+    /// `dark` or `light`.
+    pub color_scheme: Option<String>,
+}
+
+/// Probe the system through `runner`.
+pub fn probe(runner: &impl CommandRunner) -> Detected {
+    let ran = |command: &str| match runner.run(command, None) {
+        Ok(out) if out.success => Some(out.stdout),
+        Ok(out) => {
+            debug!("`{command}` failed: {}", out.stderr.trim());
+            None
+        }
+        Err(e) => {
+            debug!("`{command}` didn't run: {e:#}");
+            None
+        }
+    };
+    let output = |command: &str| ran(command).filter(|out| !out.trim().is_empty());
+    let mut monitors = output(WLR_RANDR)
+        .map(|out| parse_wlr_randr(&out))
+        .unwrap_or_default();
+    if monitors.is_empty() {
+        monitors = output(XRANDR)
+            .map(|out| parse_xrandr(&out))
+            .unwrap_or_default();
+    }
+    let gpu_vendors = output(LSPCI)
+        .map(|out| parse_lspci(&out))
+        .unwrap_or_default();
+    let has_battery = ran(POWER_SUPPLIES).map(|out| out.lines().any(|l| l.trim() == "Battery"));
+    let color_scheme = output(COLOR_SCHEME)
+        .and_then(|out| match out.trim().trim_matches('\'') {
+            "prefer-dark" => Some("dark"),
+            "prefer-light" => Some("light"),
+            _ => None,
+        })
+        .or_else(|| {
+            output(GTK_THEME).map(|out| {
+                if out.to_lowercase().contains("dark") {
+                    "dark"
+                } else {
+                    "light"
+                }
+            })
+        })
+        .map(str::to_string);
+    Detected {
+        monitors,
+        gpu_vendors,
+        has_battery,
+        color_scheme,
+    }
+}
+
+/// Parse `WxH` at the start of `s`.
+fn parse_resolution(s: &str) -> Option<(u32, u32)> {
+    let (width, rest) = s.split_once('x')?;
+    let height: String = rest.chars().take_while(char::is_ascii_digit).collect();
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+/// Parse `wlr-randr` output: an unindented line per output, then indented
+/// properties, with the current mode marked `current`.
+fn parse_wlr_randr(output: &str) -> Vec<Monitor> {
+    let mut monitors: Vec<Monitor> = Vec::new();
+    let mut current: Option<Monitor> = None;
+    let mut enabled = true;
+    let mut finish = |monitor: Option<Monitor>, enabled: bool| {
+        if let Some(m) = monitor.filter(|m| enabled && m.width > 0) {
+            monitors.push(m);
+        }
+    };
+    for line in output.lines() {
+        if !line.starts_with(char::is_whitespace) && !line.is_empty() {
+            finish(current.take(), enabled);
+            enabled = true;
+            current = line.split_whitespace().next().map(|name| Monitor {
+                name: name.to_string(),
+                width: 0,
+                height: 0,
+                refresh: None,
+                scale: None,
+                primary: false,
+            });
+            continue;
+        }
+        let Some(monitor) = current.as_mut() else {
+            continue;
+        };
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix("Enabled:") {
+            enabled = value.trim() == "yes";
+        } else if let Some(value) = line.strip_prefix("Scale:") {
+            monitor.scale = value.trim().parse().ok();
+        } else if line.contains("current")
+            && let Some((width, height)) = parse_resolution(line)
+        {
+            monitor.width = width;
+            monitor.height = height;
+            monitor.refresh = line
+                .split_once(", ")
+                .and_then(|(_, rest)| rest.split_whitespace().next())
+                .and_then(|hz| hz.parse().ok());
+        }
+    }
+    finish(current, enabled);
+    monitors
+}
+
+/// Parse `xrandr` output: a `<name> connected [primary] WxH+X+Y` line per
+/// active output, followed by its modes with the current one marked `*`.
+fn parse_xrandr(output: &str) -> Vec<Monitor> {
+    let mut monitors: Vec<Monitor> = Vec::new();
+    let mut in_connected = false;
+    for line in output.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            in_connected = false;
+            if words.get(1) != Some(&"connected") {
+                continue;
+            }
+            let primary = words.get(2) == Some(&"primary");
+            // Connected but off outputs have no geometry
+            let Some((width, height)) = words[2..]
+                .iter()
+                .find(|w| w.contains('+'))
+                .and_then(|w| parse_resolution(w))
+            else {
+                continue;
+            };
+            in_connected = true;
+            monitors.push(Monitor {
+                name: words[0].to_string(),
+                width,
+                height,
+                refresh: None,
+                scale: None,
+                primary,
+            });
+        } else if in_connected
+            && let Some(monitor) = monitors.last_mut()
+            && monitor.refresh.is_none()
+            && let Some(rate) = line.split_whitespace().find(|w| w.contains('*'))
+        {
+            monitor.refresh = rate.trim_end_matches(['*', '+']).parse().ok();
+        }
+    }
+    monitors
+}
+
+/// Vendors of the display controllers in `lspci` output.
+fn parse_lspci(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter(|l| {
+            [
+                "VGA compatible controller",
+                "3D controller",
+                "Display controller",
+            ]
+            .iter()
+            .any(|class| l.contains(class))
+        })
+        .map(|l| {
+            let device = l.split_once(": ").map_or(l, |(_, d)| d);
+            if device.contains("NVIDIA") {
+                "nvidia"
+            } else if device.contains("AMD") || device.contains("ATI") {
+                "amd"
+            } else if device.contains("Intel") {
+                "intel"
+            } else {
+                "other"
+            }
+            .to_string()
+        })
+        .collect()
+}
+
+/// Probe the system and write what was found to `output` (default
+/// [`DEFAULT_DETECTED_FILE`]) in `dotfiles_dir`.
+///
+/// Only the keys janus detects are replaced; anything else in the file is
+/// kept. A key whose probe found nothing is removed. The default file
+/// describes this machine alone, so it's added to the repo's `.gitignore`;
+/// an explicit `output` may be a per-host file meant to be committed.
+pub fn detect(
+    config: &Config,
+    output: Option<&str>,
+    dry_run: bool,
+    fs: &impl Fs,
+    runner: &impl CommandRunner,
+) -> Result<()> {
+    let name = output.unwrap_or(DEFAULT_DETECTED_FILE);
+    let path = config.dotfiles_dir(fs).join(name);
+    let detected = probe(runner);
+
+    let mut doc = if fs.exists(&path) {
+        fs.read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .parse::<toml_edit::DocumentMut>()
+            .with_context(|| format!("Failed to parse {}", path.display()))?
+    } else {
+        let mut doc = toml_edit::DocumentMut::new();
+        doc.decor_mut().set_prefix(
+            "# Written by `janus vars detect`; run it again after hardware changes.\n\n",
+        );
+        doc
+    };
+    write_detected(&mut doc, &detected);
+
+    if dry_run {
+        info!("[dry-run] Would write {}:\n{doc}", path.display());
+        plan::action("write", "", &path.to_string_lossy(), "vars detect");
+    } else {
+        fs.write(&path, doc.to_string().as_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        info!("Wrote {}", path.display());
+    }
+    if output.is_none() {
+        gitignore_detected(&config.dotfiles_dir(fs), dry_run, fs)?;
+    }
+    if !config.vars.iter().any(|v| v == name) {
+        info!("Add \"{name}\" to vars in the config for templates to use these");
+    }
+    Ok(())
+}
+
+/// Add [`DEFAULT_DETECTED_FILE`] to the `.gitignore` in `dotfiles_dir`
+/// unless it's listed already.
+fn gitignore_detected(dotfiles_dir: &Path, dry_run: bool, fs: &impl Fs) -> Result<()> {
+    let path = dotfiles_dir.join(".gitignore");
+    let line = format!("/{DEFAULT_DETECTED_FILE}");
+    let existing = if fs.exists(&path) {
+        fs.read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+    } else {
+        String::new()
+    };
+    if existing
+        .lines()
+        .any(|l| l.trim() == line || l.trim() == DEFAULT_DETECTED_FILE)
+    {
+        return Ok(());
+    }
+    if dry_run {
+        info!("[dry-run] Would add {line} to {}", path.display());
+        plan::action("write", "", &path.to_string_lossy(), "ignore detected vars");
+        return Ok(());
+    }
+    let separator = if existing.is_empty() || existing.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    fs.write(&path, format!("{existing}{separator}{line}\n").as_bytes())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    info!("Added {line} to {}", path.display());
+    Ok(())
+}
+
+/// Set the detected keys in `doc`, removing those with no value.
+fn write_detected(doc: &mut toml_edit::DocumentMut, detected: &Detected) {
+    use toml_edit::{ArrayOfTables, Item, Table, value};
+
+    let mut set = |key: &str, item: Option<Item>| match item {
+        Some(item) => {
+            doc.insert(key, item);
+        }
+        None => {
+            doc.remove(key);
+        }
+    };
+    set(
+        "monitor_count",
+        (!detected.monitors.is_empty()).then(|| value(detected.monitors.len() as i64)),
+    );
+    set(
+        "gpu_vendor",
+        detected.gpu_vendors.first().map(|v| value(v.as_str())),
+    );
+    set(
+        "gpu_vendors",
+        (!detected.gpu_vendors.is_empty()).then(|| {
+            value(
+                detected
+                    .gpu_vendors
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<toml_edit::Array>(),
+            )
+        }),
+    );
+    set("has_battery", detected.has_battery.map(value));
+    set("color_scheme", detected.color_scheme.as_deref().map(value));
+    let mut monitors = ArrayOfTables::new();
+    for monitor in &detected.monitors {
+        let mut table = Table::new();
+        table.insert("name", value(monitor.name.as_str()));
+        table.insert("width", value(i64::from(monitor.width)));
+        table.insert("height", value(i64::from(monitor.height)));
+        if let Some(refresh) = monitor.refresh {
+            table.insert("refresh", value(refresh));
+        }
+        if let Some(scale) = monitor.scale {
+            table.insert("scale", value(scale));
+        }
+        table.insert("primary", value(monitor.primary));
+        monitors.push(table);
+    }
+    set(
+        "monitors",
+        (!monitors.is_empty()).then_some(Item::ArrayOfTables(monitors)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{CommandOutput, FakeCommandRunner};
+    use crate::test_helpers::*;
+
+    #[test]
+    fn skips_locals_calls_and_tests() {
+        let template = "\
+{% set accent = colors.fg %}
+{% for name, size in fonts %}{{ name }} {{ size }}{% endfor %}
+{{ accent }} {{ now() }} {% if theme is defined %}{{ theme | upper }}{% endif %}
+{% macro pad(width, fill=\" \") %}{{ width }}{% endmacro %}
+{{ janus.hostname }} {{ gap }}
+";
+        let names: Vec<(String, usize)> = template_variables(template);
+        assert_eq!(
+            names,
+            vec![
+                ("colors".to_string(), 1),
+                ("fonts".to_string(), 2),
+                ("theme".to_string(), 3),
+                ("gap".to_string(), 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn cross_references_definitions() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/vars.toml"), "font = 12\nold = 1\n");
+        fs.add_file(format!("{DOTFILES}/work.toml"), "proxy = \"p\"\n");
+        fs.add_file(format!("{DOTFILES}/a.conf"), "{{ font }} {{ proxy }}\n");
+        fs.add_file(
+            format!("{DOTFILES}/work/b.conf"),
+            "\n{{ proxy }} {{ gap }}\n",
+        );
+        let toml = format!(
+            "{}\n[filesets.work]\npatterns = [\"work/*\"]\nvars = [\"work.toml\"]\n",
+            make_config_toml(&[("a.conf", None), ("work/b.conf", None)])
+        );
+        let config = write_and_load_config(&fs, &toml);
+
+        let rows = compute(&config, &fs).unwrap();
+        let row = |name: &str| rows.iter().find(|d| d.name == name).unwrap();
+        assert_eq!(row("font").status(), "ok");
+        assert_eq!(row("font").defined_in, vec!["vars.toml (global)"]);
+        assert_eq!(row("proxy").used_in, vec!["a.conf:1", "work/b.conf:2"]);
+        assert_eq!(row("proxy").defined_in, vec!["work.toml (fileset work)"]);
+        assert_eq!(row("proxy").status(), "missing for a.conf");
+        assert_eq!(row("gap").status(), "missing");
+        assert_eq!(row("old").status(), "unused");
+
+        let mut out = Vec::new();
+        docs(&config, &fs, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.contains("| `font` | a.conf:1 | vars.toml (global) | ok |"),
+            "got: {out}"
+        );
+    }
+
+    fn respond(runner: &mut FakeCommandRunner, command: &str, stdout: &str) {
+        runner.add_response(
+            command,
+            CommandOutput {
+                success: true,
+                stdout: stdout.to_string(),
+                stderr: String::new(),
+            },
+        );
+    }
+
+    #[test]
+    fn parses_wlr_randr_and_xrandr() {
+        let wlr = "\
+DP-1 \"Dell Inc. DELL U2720Q\"
+  Enabled: yes
+  Modes:
+    1920x1080 px, 60.000000 Hz
+    3840x2160 px, 59.997002 Hz (preferred, current)
+  Scale: 1.500000
+HDMI-A-1 \"Unknown\"
+  Enabled: no
+  Modes:
+    1920x1080 px, 60.000000 Hz (current)
+";
+        let monitors = parse_wlr_randr(wlr);
+        assert_eq!(monitors.len(), 1);
+        assert_eq!(monitors[0].name, "DP-1");
+        assert_eq!((monitors[0].width, monitors[0].height), (3840, 2160));
+        assert_eq!(monitors[0].refresh, Some(59.997002));
+        assert_eq!(monitors[0].scale, Some(1.5));
+
+        let xrandr = "\
+Screen 0: minimum 8 x 8, current 4480 x 1440, maximum 32767 x 32767
+eDP-1 connected primary 1920x1080+0+0 (normal left inverted) 344mm x 193mm
+   1920x1080     60.01*+  59.93
+HDMI-1 connected 2560x1440+1920+0 (normal left inverted) 597mm x 336mm
+   2560x1440     59.95 +  143.97*
+DP-2 disconnected (normal left inverted right x axis y axis)
+";
+        let monitors = parse_xrandr(xrandr);
+        assert_eq!(monitors.len(), 2);
+        assert!(monitors[0].primary);
+        assert_eq!(monitors[0].refresh, Some(60.01));
+        assert_eq!(monitors[1].name, "HDMI-1");
+        assert_eq!((monitors[1].width, monitors[1].height), (2560, 1440));
+        assert_eq!(monitors[1].refresh, Some(143.97));
+        assert!(!monitors[1].primary);
+    }
+
+    #[test]
+    fn detect_updates_only_its_own_keys() {
+        let fs = setup_fs();
+        let path = format!("{DOTFILES}/{DEFAULT_DETECTED_FILE}");
+        fs.add_file(&path, "# mine\nfont = 12\nhas_battery = true\n");
+        let toml =
+            make_config_toml(&[]).replace("\"vars.toml\"", &format!("\"{DEFAULT_DETECTED_FILE}\""));
+        let config = write_and_load_config(&fs, &toml);
+        let mut runner = FakeCommandRunner::new();
+        runner.add_response(
+            WLR_RANDR,
+            CommandOutput {
+                success: false,
+                stdout: String::new(),
+                stderr: "compositor doesn't support wlr-output-management".to_string(),
+            },
+        );
+        respond(
+            &mut runner,
+            XRANDR,
+            "eDP-1 connected primary 2256x1504+0+0 (normal) 285mm x 190mm\n   2256x1504     59.99*+\n",
+        );
+        respond(
+            &mut runner,
+            LSPCI,
+            "00:02.0 VGA compatible controller: Intel Corporation Alder Lake-P GT2\n01:00.0 3D controller: NVIDIA Corporation GA107M\n",
+        );
+        respond(&mut runner, COLOR_SCHEME, "'prefer-dark'\n");
+        runner.add_response(
+            POWER_SUPPLIES,
+            CommandOutput {
+                success: false,
+                stdout: String::new(),
+                stderr: "cd: can't cd to /sys/class/power_supply".to_string(),
+            },
+        );
+
+        detect(&config, None, false, &fs, &runner).unwrap();
+        let written = fs.read_to_string(Path::new(&path)).unwrap();
+        let vars: toml::Table = toml::from_str(&written).unwrap();
+        assert!(written.starts_with("# mine"), "got: {written}");
+        assert_eq!(vars["font"].as_integer(), Some(12));
+        assert_eq!(vars["monitor_count"].as_integer(), Some(1));
+        assert_eq!(vars["monitors"][0]["name"].as_str(), Some("eDP-1"));
+        assert_eq!(vars["monitors"][0]["width"].as_integer(), Some(2256));
+        assert_eq!(vars["gpu_vendor"].as_str(), Some("intel"));
+        assert_eq!(vars["gpu_vendors"][1].as_str(), Some("nvidia"));
+        assert_eq!(vars["color_scheme"].as_str(), Some("dark"));
+        // Power supplies can't be listed, so the stale value is dropped
+        assert!(!vars.contains_key("has_battery"), "got: {written}");
+        assert!(!runner.commands().contains(&GTK_THEME.to_string()));
+        let gitignore = fs
+            .read_to_string(Path::new(&format!("{DOTFILES}/.gitignore")))
+            .unwrap();
+        assert_eq!(gitignore, "/vars.detected.toml\n");
+    }
+
+    #[test]
+    fn no_power_supplies_means_no_battery() {
+        let mut runner = FakeCommandRunner::new();
+        respond(&mut runner, POWER_SUPPLIES, "");
+        assert_eq!(probe(&runner).has_battery, Some(false));
+        respond(&mut runner, POWER_SUPPLIES, "Mains\nBattery\n");
+        assert_eq!(probe(&runner).has_battery, Some(true));
+    }
+}
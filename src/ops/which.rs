@@ -0,0 +1,230 @@
+//! `janus which`: find the `[[files]]` entry behind a path.
+//!
+//! The reverse of deploy: given a deployed target (or one of its
+//! `path_maps` copies), or a file's source, generated, or staged copy,
+//! reports which entry it belongs to, the filesets that match it, and its
+//! pipeline status. A symlink at some other path is traced through its link,
+//! so a copy janus didn't deploy itself still resolves.
+
+use anyhow::{Result, bail};
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+
+use super::status::{self, StatusFilters};
+use crate::config::{Config, FileEntry, normalize_path};
+use crate::paths::{collapse_tilde, expand_tilde};
+use crate::platform::Fs;
+
+/// What a looked-up path is to the entry it belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// The entry's deploy target.
+    Target,
+    /// A `path_maps` copy of the target.
+    MappedTarget,
+    /// The source in the dotfiles directory (or a layer).
+    Source,
+    /// The copy in `.generated/`.
+    Generated,
+    /// The copy in `.staged/`.
+    Staged,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::Target => "target",
+            Role::MappedTarget => "path_maps copy of the target",
+            Role::Source => "source",
+            Role::Generated => "generated copy",
+            Role::Staged => "staged copy",
+        })
+    }
+}
+
+/// The entry `path` belongs to, and what the path is to it. `None` if
+/// janus doesn't manage it.
+///
+/// `path` should be absolute. If it doesn't match directly but is a
+/// symlink, its link is looked up instead.
+pub fn lookup<'a>(config: &'a Config, path: &Path, fs: &impl Fs) -> Option<(&'a FileEntry, Role)> {
+    let path = normalize_path(path);
+    if let Some(found) = lookup_exact(config, &path, fs) {
+        return Some(found);
+    }
+    let link = fs.read_link(&path).ok()?;
+    let resolved = match path.parent() {
+        Some(parent) if link.is_relative() => parent.join(link),
+        _ => link,
+    };
+    lookup_exact(config, &normalize_path(&resolved), fs)
+}
+
+fn lookup_exact<'a>(
+    config: &'a Config,
+    path: &Path,
+    fs: &impl Fs,
+) -> Option<(&'a FileEntry, Role)> {
+    for entry in &config.files {
+        let target = entry.target();
+        if normalize_path(&expand_tilde(&target, fs)) == path {
+            return Some((entry, Role::Target));
+        }
+        let mapped = config.mapped_targets(&target, fs);
+        if mapped.iter().any(|p| normalize_path(p) == path) {
+            return Some((entry, Role::MappedTarget));
+        }
+    }
+
+    // The pipeline directories sit inside the dotfiles directory, so they
+    // go first
+    let dirs = [
+        (config.generated_dir(fs), Role::Generated),
+        (config.staged_dir(fs), Role::Staged),
+    ]
+    .into_iter()
+    .chain(config.layers(fs).into_iter().map(|l| (l, Role::Source)));
+    for (dir, role) in dirs {
+        let Ok(relative) = path.strip_prefix(normalize_path(&dir)) else {
+            continue;
+        };
+        return config
+            .files
+            .iter()
+            .find(|e| Path::new(&e.src) == relative)
+            .map(|entry| (entry, role));
+    }
+    None
+}
+
+/// Describe the entry behind `path` (which may start with `~` or be
+/// relative to the working directory). Fails if janus doesn't manage it.
+pub fn run(config: &Config, path: &str, fs: &impl Fs, out: &mut impl Write) -> Result<()> {
+    let absolute = std::path::absolute(expand_tilde(path, fs))?;
+    let Some((entry, role)) = lookup(config, &absolute, fs) else {
+        bail!("{path} isn't managed by janus");
+    };
+
+    let mut filesets: Vec<&str> = config
+        .matching_filesets_named(&entry.src)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    filesets.sort_unstable();
+    let filters = StatusFilters {
+        only_diffs: false,
+        deployed: false,
+        undeployed: false,
+        stale: false,
+    };
+    let result = status::compute(config, Some(std::slice::from_ref(&entry.src)), &filters, fs)?;
+
+    writeln!(
+        out,
+        "{} is the {role} of {}",
+        collapse_tilde(&absolute, fs),
+        entry.src
+    )?;
+    writeln!(
+        out,
+        "  source:    {}",
+        collapse_tilde(&config.source_path(&entry.src, fs), fs)
+    )?;
+    writeln!(out, "  target:    {}", entry.target())?;
+    if !filesets.is_empty() {
+        writeln!(out, "  filesets:  {}", filesets.join(", "))?;
+    }
+    if let Some(status) = result.statuses.first() {
+        let deployed = if status.deployed {
+            "deployed"
+        } else {
+            "undeployed"
+        };
+        writeln!(out, "  status:    {deployed} ({})", status.detail)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    fn setup() -> (crate::platform::FakeFs, Config) {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "hypr/hypr.conf", "x");
+        let toml = format!(
+            "{}\n[filesets.desktop]\npatterns = [\"hypr/*\"]\n",
+            make_config_toml(&[("hypr/hypr.conf", Some("~/.config/hypr/hypr.conf"))])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        (fs, config)
+    }
+
+    fn role_of(config: &Config, path: &str, fs: &crate::platform::FakeFs) -> Option<Role> {
+        lookup(config, Path::new(path), fs).map(|(entry, role)| {
+            assert_eq!(entry.src, "hypr/hypr.conf");
+            role
+        })
+    }
+
+    #[test]
+    fn finds_target_and_pipeline_copies() {
+        let (fs, config) = setup();
+        let cases = [
+            (
+                "/home/test/.config/hypr/./hypr.conf".to_string(),
+                Role::Target,
+            ),
+            (format!("{DOTFILES}/hypr/hypr.conf"), Role::Source),
+            (
+                format!("{DOTFILES}/.generated/hypr/hypr.conf"),
+                Role::Generated,
+            ),
+            (format!("{DOTFILES}/.staged/hypr/hypr.conf"), Role::Staged),
+        ];
+        for (path, role) in cases {
+            assert_eq!(role_of(&config, &path, &fs), Some(role), "{path}");
+        }
+        assert_eq!(role_of(&config, "/home/test/.config/other.conf", &fs), None);
+        assert_eq!(
+            role_of(&config, &format!("{DOTFILES}/vars.toml"), &fs),
+            None
+        );
+    }
+
+    #[test]
+    fn follows_symlinks_into_the_pipeline() {
+        let (fs, config) = setup();
+        fs.add_symlink(
+            "/home/test/hypr-link.conf",
+            format!("{DOTFILES}/.staged/hypr/hypr.conf"),
+        );
+        assert_eq!(
+            role_of(&config, "/home/test/hypr-link.conf", &fs),
+            Some(Role::Staged)
+        );
+    }
+
+    #[test]
+    fn run_describes_entry() {
+        let (fs, config) = setup();
+        let mut out = Vec::new();
+        run(&config, "~/.config/hypr/hypr.conf", &fs, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "~/.config/hypr/hypr.conf is the target of hypr/hypr.conf\n\
+             \x20 source:    ~/dotfiles/hypr/hypr.conf\n\
+             \x20 target:    ~/.config/hypr/hypr.conf\n\
+             \x20 filesets:  desktop\n\
+             \x20 status:    undeployed (ready to deploy)\n"
+        );
+
+        let msg = format!(
+            "{:#}",
+            run(&config, "/etc/hosts", &fs, &mut Vec::new()).unwrap_err()
+        );
+        assert!(msg.contains("isn't managed by janus"), "got: {msg}");
+    }
+}
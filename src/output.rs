@@ -0,0 +1,193 @@
+//! Shared terminal output for diff, status, and sync.
+//!
+//! [`Output`] sends long output through a pager the way git does: only when
+//! stdout is a terminal, using `[ui] pager` from the config, then `$PAGER`,
+//! then `less -R`. `--no-pager`, an empty pager, or `cat` turn it off. Sync
+//! prompts between hunks, so it isn't paged and only shares the color codes.
+
+use std::io::{self, IsTerminal, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use tracing::{debug, warn};
+
+/// ANSI color for removed lines.
+pub const RED: &str = "\x1b[31m";
+/// ANSI color for added lines.
+pub const GREEN: &str = "\x1b[32m";
+/// ANSI color for changed line counts.
+pub const YELLOW: &str = "\x1b[33m";
+/// ANSI dim, for absent values.
+pub const DIM: &str = "\x1b[2m";
+/// ANSI reset.
+pub const RESET: &str = "\x1b[0m";
+
+/// Fallback when neither the config nor `$PAGER` names a pager.
+const DEFAULT_PAGER: &str = "less -R";
+
+/// Stdout, stderr, or the stdin of a running pager.
+///
+/// Dropping a paged `Output` closes the pager's input and waits for the user
+/// to quit it.
+pub enum Output {
+    Stdout(io::Stdout),
+    /// Under `--format json`, where stdout holds only the plan.
+    Stderr(io::Stderr),
+    Pager {
+        child: Child,
+        stdin: Option<ChildStdin>,
+    },
+}
+
+impl Output {
+    /// Page through the configured pager if stdout is a terminal, otherwise
+    /// write straight to stdout.
+    pub fn paged(configured: Option<&str>, no_pager: bool) -> Self {
+        let env_pager = std::env::var("PAGER").ok();
+        match resolve_pager(
+            no_pager,
+            configured,
+            env_pager.as_deref(),
+            io::stdout().is_terminal(),
+        ) {
+            Some(pager) => Self::spawn(&pager),
+            None => Self::Stdout(io::stdout()),
+        }
+    }
+
+    /// Start `pager` via `sh -c`, falling back to stdout if it can't start.
+    fn spawn(pager: &str) -> Self {
+        debug!("Starting pager: {pager}");
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(pager).stdin(Stdio::piped());
+        // Like git: quit if it fits on one screen, keep colors, don't clear
+        if std::env::var_os("LESS").is_none() {
+            command.env("LESS", "FRX");
+        }
+        match command.spawn() {
+            Ok(mut child) => {
+                let stdin = child.stdin.take();
+                Self::Pager { child, stdin }
+            }
+            Err(e) => {
+                warn!("Failed to start pager {pager}: {e}");
+                Self::Stdout(io::stdout())
+            }
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Stdout(stdout) => stdout.write(buf),
+            Self::Stderr(stderr) => stderr.write(buf),
+            Self::Pager { stdin, .. } => {
+                let Some(pipe) = stdin else {
+                    return Ok(buf.len());
+                };
+                match pipe.write(buf) {
+                    // The user quit the pager early; drop the rest quietly
+                    Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                        *stdin = None;
+                        Ok(buf.len())
+                    }
+                    result => result,
+                }
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Stdout(stdout) => stdout.flush(),
+            Self::Stderr(stderr) => stderr.flush(),
+            Self::Pager { stdin, .. } => match stdin {
+                Some(pipe) => match pipe.flush() {
+                    Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+                    result => result,
+                },
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        if let Self::Pager { child, stdin } = self {
+            // Closing stdin signals EOF so the pager can finish
+            stdin.take();
+            if let Err(e) = child.wait() {
+                warn!("Failed to wait for pager: {e}");
+            }
+        }
+    }
+}
+
+/// Columns to fit `status -l` rows into, or `None` when stdout isn't a
+/// terminal (so piped output is never truncated). Reads `$COLUMNS`,
+/// defaulting to 80.
+pub fn terminal_width() -> Option<usize> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    let columns = std::env::var("COLUMNS").ok();
+    Some(columns.and_then(|c| c.parse().ok()).unwrap_or(80))
+}
+
+/// Pick the pager command, or `None` to write to stdout.
+///
+/// Precedence: `no_pager` and non-terminal stdout disable paging; otherwise
+/// the configured pager, then `$PAGER`, then `less -R`. An empty command or
+/// `cat` also disables paging.
+pub fn resolve_pager(
+    no_pager: bool,
+    configured: Option<&str>,
+    env_pager: Option<&str>,
+    is_tty: bool,
+) -> Option<String> {
+    if no_pager || !is_tty {
+        return None;
+    }
+    let pager = configured.or(env_pager).unwrap_or(DEFAULT_PAGER).trim();
+    if pager.is_empty() || pager == "cat" {
+        return None;
+    }
+    Some(pager.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_pager_without_tty() {
+        assert_eq!(resolve_pager(false, Some("less"), None, false), None);
+    }
+
+    #[test]
+    fn no_pager_flag_wins() {
+        assert_eq!(resolve_pager(true, Some("less"), Some("more"), true), None);
+    }
+
+    #[test]
+    fn configured_before_env() {
+        assert_eq!(
+            resolve_pager(false, Some("bat"), Some("more"), true).as_deref(),
+            Some("bat")
+        );
+        assert_eq!(
+            resolve_pager(false, None, Some("more"), true).as_deref(),
+            Some("more")
+        );
+        assert_eq!(
+            resolve_pager(false, None, None, true).as_deref(),
+            Some("less -R")
+        );
+    }
+
+    #[test]
+    fn empty_or_cat_disables() {
+        assert_eq!(resolve_pager(false, Some(""), Some("more"), true), None);
+        assert_eq!(resolve_pager(false, None, Some("cat"), true), None);
+    }
+}
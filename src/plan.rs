@@ -0,0 +1,187 @@
+//! `--dry-run --format json`: what a dry run would change, as data.
+//!
+//! Wherever an op logs `[dry-run] Would ...` it also calls [`action`] with
+//! the kind of change, what it reads from, what it would change, and why.
+//! Each is a `trace` event for the `janus::plan` target, so without [`layer`]
+//! installed they cost nothing and only show up at `-vv`. With
+//! `--format json`, [`PlanLayer`] collects them and [`Recorder::write`]
+//! prints them as one JSON document once the command finishes, for tools
+//! that present or compare plans.
+
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Target of every plan event.
+const TARGET: &str = "janus::plan";
+
+/// How a dry run reports what it would do (`--format`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// `[dry-run] Would ...` log lines.
+    #[default]
+    Text,
+    /// A JSON plan on stdout.
+    Json,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text => write!(f, "text"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// Record that the dry run would do `kind` (e.g. `deploy`) from `source`
+/// to `destination` because of `reason`. Empty strings are left out of the
+/// plan.
+pub fn action(kind: &'static str, source: &str, destination: &str, reason: &str) {
+    tracing::trace!(target: TARGET, kind, source, destination, reason);
+}
+
+/// One change a dry run would make.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Action {
+    /// What kind of change, e.g. `deploy`, `remove`, or `run`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The entry or path the change reads from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// The path, entry, or command the change affects.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    /// Why the change would be made.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Visit for Action {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        let value = (!value.is_empty()).then(|| value.to_string());
+        match field.name() {
+            "kind" => self.kind = value.unwrap_or_default(),
+            "source" => self.source = value,
+            "destination" => self.destination = value,
+            "reason" => self.reason = value,
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record_str(field, &format!("{value:?}"));
+    }
+}
+
+/// The whole plan, as printed.
+#[derive(Serialize)]
+struct Plan<'a> {
+    dry_run: bool,
+    actions: &'a [Action],
+}
+
+/// Install [`PlanLayer`] in the subscriber, then call [`Recorder::write`]
+/// when the command is done.
+pub fn layer() -> (PlanLayer, Recorder) {
+    let actions = Arc::new(Mutex::new(Vec::new()));
+    (
+        PlanLayer {
+            actions: actions.clone(),
+        },
+        Recorder { actions },
+    )
+}
+
+/// A [`Layer`] collecting every plan [`action`].
+pub struct PlanLayer {
+    actions: Arc<Mutex<Vec<Action>>>,
+}
+
+impl<S: Subscriber> Layer<S> for PlanLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() != TARGET {
+            return;
+        }
+        let mut action = Action::default();
+        event.record(&mut action);
+        self.actions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(action);
+    }
+}
+
+/// Reads back what [`PlanLayer`] collected.
+pub struct Recorder {
+    actions: Arc<Mutex<Vec<Action>>>,
+}
+
+impl Recorder {
+    /// The actions recorded so far, in order.
+    pub fn actions(&self) -> Vec<Action> {
+        self.actions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Print the plan as pretty JSON.
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let actions = self.actions();
+        let plan = Plan {
+            dry_run: true,
+            actions: &actions,
+        };
+        serde_json::to_writer_pretty(&mut *out, &plan)?;
+        writeln!(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn collects_actions_as_json() {
+        let (layer, recorder) = layer();
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            action(
+                "deploy",
+                "a.conf",
+                "/home/test/.config/a.conf",
+                "not deployed",
+            );
+            tracing::trace!(target: "janus::progress", action = "start");
+            action("run", "", "hyprctl reload", "");
+        });
+        assert_eq!(recorder.actions().len(), 2);
+
+        let mut out = Vec::new();
+        recorder.write(&mut out).unwrap();
+        let plan: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            plan,
+            serde_json::json!({
+                "dry_run": true,
+                "actions": [
+                    {
+                        "type": "deploy",
+                        "source": "a.conf",
+                        "destination": "/home/test/.config/a.conf",
+                        "reason": "not deployed",
+                    },
+                    { "type": "run", "destination": "hyprctl reload" },
+                ],
+            })
+        );
+    }
+}
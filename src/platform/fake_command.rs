@@ -0,0 +1,109 @@
+//! Fake command runner for testing.
+//!
+//! Records every command line (and its stdin) in order. Responses are
+//! registered per exact command line via `add_response()`; unregistered
+//! commands succeed with empty output.
+
+use anyhow::Result;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use super::{CommandOutput, CommandRunner};
+
+/// Mock command runner — records calls and returns pre-configured output.
+pub struct FakeCommandRunner {
+    /// Map of command line -> output to return.
+    responses: HashMap<String, CommandOutput>,
+    /// Every `(command, stdin)` pair passed to `run()`, in order.
+    calls: RefCell<Vec<(String, Option<String>)>>,
+}
+
+impl Default for FakeCommandRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FakeCommandRunner {
+    pub fn new() -> Self {
+        Self {
+            responses: HashMap::new(),
+            calls: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Register the output `run()` will return for an exact command line.
+    /// Returns the previous output if one was already registered.
+    pub fn add_response(&mut self, command: &str, output: CommandOutput) -> Option<CommandOutput> {
+        self.responses.insert(command.to_string(), output)
+    }
+
+    /// All `(command, stdin)` pairs run so far, in order.
+    pub fn calls(&self) -> Vec<(String, Option<String>)> {
+        self.calls.borrow().clone()
+    }
+
+    /// Just the command lines run so far, in order.
+    pub fn commands(&self) -> Vec<String> {
+        self.calls.borrow().iter().map(|(c, _)| c.clone()).collect()
+    }
+}
+
+impl CommandRunner for FakeCommandRunner {
+    fn run(&self, command: &str, stdin: Option<&str>) -> Result<CommandOutput> {
+        self.calls
+            .borrow_mut()
+            .push((command.to_string(), stdin.map(str::to_string)));
+        Ok(self
+            .responses
+            .get(command)
+            .cloned()
+            .unwrap_or_else(|| CommandOutput {
+                success: true,
+                ..Default::default()
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_calls() {
+        let runner = FakeCommandRunner::new();
+        runner.run("echo hi", None).unwrap();
+        runner.run("cat", Some("input")).unwrap();
+        assert_eq!(
+            runner.calls(),
+            vec![
+                ("echo hi".to_string(), None),
+                ("cat".to_string(), Some("input".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unregistered_succeeds() {
+        let runner = FakeCommandRunner::new();
+        let output = runner.run("true", None).unwrap();
+        assert!(output.success);
+        assert!(output.stdout.is_empty());
+    }
+
+    #[test]
+    fn test_registered_response() {
+        let mut runner = FakeCommandRunner::new();
+        runner.add_response(
+            "false",
+            CommandOutput {
+                success: false,
+                stdout: String::new(),
+                stderr: "boom".to_string(),
+            },
+        );
+        let output = runner.run("false", None).unwrap();
+        assert!(!output.success);
+        assert_eq!(output.stderr, "boom");
+    }
+}
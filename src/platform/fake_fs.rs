@@ -6,17 +6,24 @@
 //!
 //! Non-trait setup methods (`add_file`, `add_dir`, `add_symlink`) auto-create
 //! parent directories for convenience in test setup.
+//!
+//! Modification times come from a logical clock that ticks on every file
+//! write, so [`Fs::stamp`] changes whenever a file does. Content reads are
+//! counted so tests can check that caches avoid them. Extended attributes
+//! are only recorded: whatever [`Fs::set_xattrs`] sets on a file,
+//! [`Fs::xattrs`] reports back, and nothing else carries them.
 
 use anyhow::{Result, bail};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
-use super::{DirEntry, Fs, WalkOptions};
+use super::{DirEntry, FileStamp, Fs, WalkOptions, Xattrs, xdg_trash};
+use crate::state::content_hash;
 
 /// A single entry in the fake filesystem.
 #[derive(Clone, Debug)]
-pub(crate) enum FakeEntry {
+pub enum FakeEntry {
     File { content: Vec<u8>, mode: u32 },
     Symlink { target: PathBuf },
     Dir,
@@ -31,6 +38,14 @@ pub struct FakeFs {
     home: PathBuf,
     config_dir: PathBuf,
     fail_writes: RefCell<bool>,
+    /// Logical modification time of each file, from `clock`.
+    mtimes: RefCell<HashMap<PathBuf, u64>>,
+    clock: RefCell<u64>,
+    /// Number of `read`/`read_to_string` calls.
+    reads: RefCell<usize>,
+    hostname: RefCell<Option<String>>,
+    /// Extended attributes set on each file.
+    xattrs: RefCell<HashMap<PathBuf, Xattrs>>,
 }
 
 impl FakeFs {
@@ -50,9 +65,31 @@ impl FakeFs {
             home,
             config_dir,
             fail_writes: RefCell::new(false),
+            mtimes: RefCell::new(HashMap::new()),
+            clock: RefCell::new(0),
+            reads: RefCell::new(0),
+            hostname: RefCell::new(None),
+            xattrs: RefCell::new(HashMap::new()),
         }
     }
 
+    /// Set the hostname reported by [`Fs::hostname`] (unset by default).
+    pub fn set_hostname(&self, hostname: &str) {
+        *self.hostname.borrow_mut() = Some(hostname.to_string());
+    }
+
+    /// How many times file contents have been read.
+    pub fn read_count(&self) -> usize {
+        *self.reads.borrow()
+    }
+
+    /// Record a modification of `path` at the next clock tick.
+    fn touch(&self, path: &Path) {
+        let mut clock = self.clock.borrow_mut();
+        *clock += 1;
+        self.mtimes.borrow_mut().insert(path.to_path_buf(), *clock);
+    }
+
     /// Toggle write failures. When enabled, all `Fs::write` calls bail.
     pub fn set_fail_writes(&self, fail: bool) {
         *self.fail_writes.borrow_mut() = fail;
@@ -69,6 +106,7 @@ impl FakeFs {
     ) -> Option<FakeEntry> {
         let path = path.into();
         self.ensure_parents(&path);
+        self.touch(&path);
         self.entries.borrow_mut().insert(
             path,
             FakeEntry::File {
@@ -88,6 +126,7 @@ impl FakeFs {
     ) -> Option<FakeEntry> {
         let path = path.into();
         self.ensure_parents(&path);
+        self.touch(&path);
         self.entries.borrow_mut().insert(
             path,
             FakeEntry::File {
@@ -160,6 +199,7 @@ impl FakeFs {
 
 impl Fs for FakeFs {
     fn read_to_string(&self, path: &Path) -> Result<String> {
+        *self.reads.borrow_mut() += 1;
         let resolved = self.resolve_path(path);
         let entries = self.entries.borrow();
         match entries.get(&resolved) {
@@ -170,6 +210,7 @@ impl Fs for FakeFs {
     }
 
     fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        *self.reads.borrow_mut() += 1;
         let resolved = self.resolve_path(path);
         let entries = self.entries.borrow();
         match entries.get(&resolved) {
@@ -179,6 +220,10 @@ impl Fs for FakeFs {
         }
     }
 
+    fn hash_file(&self, path: &Path) -> Result<String> {
+        Ok(content_hash(&self.read(path)?))
+    }
+
     fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
         if *self.fail_writes.borrow() {
             bail!("simulated write failure: {}", path.display());
@@ -192,6 +237,7 @@ impl Fs for FakeFs {
                 _ => 0o644,
             }
         };
+        self.touch(&resolved);
         self.entries.borrow_mut().insert(
             resolved,
             FakeEntry::File {
@@ -202,11 +248,35 @@ impl Fs for FakeFs {
         Ok(())
     }
 
+    fn append(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        if *self.fail_writes.borrow() {
+            bail!("simulated write failure: {}", path.display());
+        }
+        let resolved = self.resolve_path(path);
+        self.touch(&resolved);
+        let mut entries = self.entries.borrow_mut();
+        match entries.get_mut(&resolved) {
+            Some(FakeEntry::File { content, .. }) => content.extend_from_slice(contents),
+            Some(_) => bail!("not a file: {}", path.display()),
+            None => {
+                entries.insert(
+                    resolved,
+                    FakeEntry::File {
+                        content: contents.to_vec(),
+                        mode: 0o644,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
     fn copy(&self, from: &Path, to: &Path) -> Result<()> {
         let resolved = self.resolve_path(from);
         let entry = { self.entries.borrow().get(&resolved).cloned() };
         match entry {
             Some(FakeEntry::File { content, mode }) => {
+                self.touch(to);
                 self.entries
                     .borrow_mut()
                     .insert(to.to_path_buf(), FakeEntry::File { content, mode });
@@ -216,11 +286,16 @@ impl Fs for FakeFs {
         }
     }
 
+    fn copy_streaming(&self, from: &Path, to: &Path) -> Result<()> {
+        self.copy(from, to)
+    }
+
     fn remove_file(&self, path: &Path) -> Result<()> {
         let mut entries = self.entries.borrow_mut();
         match entries.get(path) {
             Some(FakeEntry::File { .. } | FakeEntry::Symlink { .. }) => {
                 entries.remove(path);
+                self.xattrs.borrow_mut().remove(path);
                 Ok(())
             }
             Some(FakeEntry::Dir) => bail!("is a directory: {}", path.display()),
@@ -245,11 +320,41 @@ impl Fs for FakeFs {
         Ok(())
     }
 
+    /// Trashes into `<home>/.local/share/Trash`, with a deletion date of
+    /// the Unix epoch.
+    fn trash(&self, path: &Path) -> Result<PathBuf> {
+        match self.entries.borrow().get(path) {
+            Some(FakeEntry::File { .. } | FakeEntry::Symlink { .. }) => {}
+            Some(FakeEntry::Dir) => bail!("is a directory: {}", path.display()),
+            None => bail!("file not found: {}", path.display()),
+        }
+        let trash_dir = self.home.join(".local/share/Trash");
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let (info_path, trashed) = (1..)
+            .map(|n| xdg_trash::slot(&trash_dir, &name, n))
+            .find(|(info, file)| !self.exists(info) && !self.is_symlink(file) && !self.exists(file))
+            .expect("some trash slot is free");
+        self.create_dir_all(&trash_dir.join("files"))?;
+        self.create_dir_all(&trash_dir.join("info"))?;
+        self.write(&info_path, xdg_trash::trash_info(path, 0).as_bytes())?;
+        self.rename(path, &trashed)?;
+        Ok(trashed)
+    }
+
     fn rename(&self, from: &Path, to: &Path) -> Result<()> {
         let mut entries = self.entries.borrow_mut();
         match entries.remove(from) {
             Some(entry) => {
                 entries.insert(to.to_path_buf(), entry);
+                let mut mtimes = self.mtimes.borrow_mut();
+                if let Some(mtime) = mtimes.remove(from) {
+                    mtimes.insert(to.to_path_buf(), mtime);
+                }
+                let mut xattrs = self.xattrs.borrow_mut();
+                xattrs.remove(to);
+                if let Some(attrs) = xattrs.remove(from) {
+                    xattrs.insert(to.to_path_buf(), attrs);
+                }
                 Ok(())
             }
             None => bail!("not found: {}", from.display()),
@@ -276,6 +381,19 @@ impl Fs for FakeFs {
         }
     }
 
+    fn stamp(&self, path: &Path) -> Result<FileStamp> {
+        let resolved = self.resolve_path(path);
+        let entries = self.entries.borrow();
+        match entries.get(&resolved) {
+            Some(FakeEntry::File { content, .. }) => Ok(FileStamp {
+                modified_ns: self.mtimes.borrow().get(&resolved).copied().unwrap_or(0),
+                len: content.len() as u64,
+            }),
+            Some(_) => bail!("not a file: {}", path.display()),
+            None => bail!("file not found: {}", path.display()),
+        }
+    }
+
     fn set_file_mode(&self, path: &Path, mode: u32) -> Result<()> {
         let resolved = self.resolve_path(path);
         let mut entries = self.entries.borrow_mut();
@@ -288,6 +406,36 @@ impl Fs for FakeFs {
         }
     }
 
+    fn xattrs(&self, path: &Path) -> Result<Xattrs> {
+        let resolved = self.resolve_path(path);
+        match self.entries.borrow().get(&resolved) {
+            Some(FakeEntry::File { .. } | FakeEntry::Dir) => Ok(self
+                .xattrs
+                .borrow()
+                .get(&resolved)
+                .cloned()
+                .unwrap_or_default()),
+            _ => bail!("not found: {}", path.display()),
+        }
+    }
+
+    fn set_xattrs(&self, path: &Path, attrs: &Xattrs) -> Result<()> {
+        let resolved = self.resolve_path(path);
+        if !matches!(
+            self.entries.borrow().get(&resolved),
+            Some(FakeEntry::File { .. } | FakeEntry::Dir)
+        ) {
+            bail!("not found: {}", path.display());
+        }
+        let mut xattrs = self.xattrs.borrow_mut();
+        let recorded = xattrs.entry(resolved).or_default();
+        for (name, value) in attrs {
+            recorded.retain(|(n, _)| n != name);
+            recorded.push((name.clone(), value.clone()));
+        }
+        Ok(())
+    }
+
     fn symlink(&self, original: &Path, link: &Path) -> Result<()> {
         self.entries.borrow_mut().insert(
             link.to_path_buf(),
@@ -298,6 +446,27 @@ impl Fs for FakeFs {
         Ok(())
     }
 
+    /// Entries hold their contents by value, so the link is a copy: writes
+    /// through one name don't show through the other.
+    fn hard_link(&self, original: &Path, link: &Path) -> Result<()> {
+        if self.entries.borrow().contains_key(link) {
+            bail!("already exists: {}", link.display());
+        }
+        self.copy(original, link)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        let mut resolved = PathBuf::new();
+        for component in path.components() {
+            resolved.push(component);
+            resolved = self.resolve_path(&resolved);
+        }
+        if !self.entries.borrow().contains_key(&resolved) {
+            bail!("not found: {}", path.display());
+        }
+        Ok(resolved)
+    }
+
     fn read_link(&self, path: &Path) -> Result<PathBuf> {
         let entries = self.entries.borrow();
         match entries.get(path) {
@@ -333,48 +502,8 @@ impl Fs for FakeFs {
 
     fn walk_dir(&self, path: &Path, opts: &WalkOptions) -> Result<Vec<DirEntry>> {
         let entries = self.entries.borrow();
-        let root_components = path.components().count();
-
-        let mut results: Vec<DirEntry> = entries
-            .iter()
-            .filter(|(p, _)| p.starts_with(path))
-            .filter_map(|(p, entry)| {
-                let depth = p.components().count().saturating_sub(root_components);
-
-                if depth < opts.min_depth {
-                    return None;
-                }
-                if let Some(max) = opts.max_depth
-                    && depth > max
-                {
-                    return None;
-                }
-
-                let (is_file, is_dir, is_symlink) = match entry {
-                    FakeEntry::File { .. } => (true, false, false),
-                    FakeEntry::Dir => (false, true, false),
-                    FakeEntry::Symlink { target } => {
-                        if opts.follow_links {
-                            // Resolve symlink target type
-                            match entries.get(target) {
-                                Some(FakeEntry::File { .. }) => (true, false, true),
-                                Some(FakeEntry::Dir) => (false, true, true),
-                                _ => (false, false, true),
-                            }
-                        } else {
-                            (false, false, true)
-                        }
-                    }
-                };
-
-                Some(DirEntry {
-                    path: p.clone(),
-                    is_file,
-                    is_dir,
-                    is_symlink,
-                })
-            })
-            .collect();
+        let mut results = Vec::new();
+        walk_under(&entries, path, path, 0, &mut Vec::new(), opts, &mut results);
 
         if opts.contents_first {
             // Deeper entries first, then alphabetical within same depth
@@ -397,6 +526,99 @@ impl Fs for FakeFs {
     fn config_dir(&self) -> Option<PathBuf> {
         Some(self.config_dir.clone())
     }
+
+    fn state_dir(&self) -> Option<PathBuf> {
+        Some(self.home.join(".local/state"))
+    }
+
+    fn cache_dir(&self) -> Option<PathBuf> {
+        Some(self.home.join(".cache"))
+    }
+
+    fn hostname(&self) -> Option<String> {
+        self.hostname.borrow().clone()
+    }
+}
+
+/// Collect the entries under `real_root` into `results` as if they lived
+/// under `shown_root`, `base_depth` levels below the walk's root.
+///
+/// With `follow_links`, symlinked directories are walked through too,
+/// except ones pointing at a directory the walk is already inside (the
+/// walk root, or any parent of a link followed on the way here). Those are
+/// dropped, as the real walk drops the loop errors `walkdir` reports.
+fn walk_under(
+    entries: &HashMap<PathBuf, FakeEntry>,
+    real_root: &Path,
+    shown_root: &Path,
+    base_depth: usize,
+    chain: &mut Vec<PathBuf>,
+    opts: &WalkOptions,
+    results: &mut Vec<DirEntry>,
+) {
+    let root_components = real_root.components().count();
+    for (p, entry) in entries.iter().filter(|(p, _)| p.starts_with(real_root)) {
+        let relative = p.strip_prefix(real_root).unwrap_or(p);
+        if relative.as_os_str().is_empty() && !chain.is_empty() {
+            // A followed link's target; the link itself was already listed
+            continue;
+        }
+        let depth = base_depth + p.components().count() - root_components;
+        if let Some(max) = opts.max_depth
+            && depth > max
+        {
+            continue;
+        }
+        let shown = if relative.as_os_str().is_empty() {
+            shown_root.to_path_buf()
+        } else {
+            shown_root.join(relative)
+        };
+
+        let (is_file, is_dir, is_symlink) = match entry {
+            FakeEntry::File { .. } => (true, false, false),
+            FakeEntry::Dir => (false, true, false),
+            FakeEntry::Symlink { target } if opts.follow_links => {
+                let mut resolved = target.clone();
+                for _ in 0..32 {
+                    match entries.get(&resolved) {
+                        Some(FakeEntry::Symlink { target }) => resolved = target.clone(),
+                        _ => break,
+                    }
+                }
+                match entries.get(&resolved) {
+                    Some(FakeEntry::File { .. }) => (true, false, true),
+                    Some(FakeEntry::Dir) => {
+                        let parent = p.parent().unwrap_or(p);
+                        let looped = chain
+                            .iter()
+                            .map(PathBuf::as_path)
+                            .chain([real_root, parent])
+                            .any(|dir| dir.starts_with(&resolved));
+                        if looped {
+                            continue;
+                        }
+                        chain.push(parent.to_path_buf());
+                        walk_under(entries, &resolved, &shown, depth, chain, opts, results);
+                        chain.pop();
+                        (false, true, true)
+                    }
+                    _ => (false, false, true),
+                }
+            }
+            FakeEntry::Symlink { .. } => (false, false, true),
+        };
+
+        if depth < opts.min_depth {
+            continue;
+        }
+        results.push(DirEntry {
+            path: shown,
+            is_file,
+            is_dir,
+            is_symlink,
+        });
+    }
 }
 
 #[cfg(test)]
@@ -536,6 +758,35 @@ mod tests {
         assert!(paths.contains(&Path::new("/root/sub/b.txt")));
     }
 
+    #[test]
+    fn test_walk_dir_follows_links_without_looping() {
+        let fs = FakeFs::new("/home/test");
+        fs.add_file("/root/a.txt", "a");
+        fs.add_file("/other/b.txt", "b");
+        fs.add_symlink("/root/other", "/other");
+        fs.add_symlink("/other/back", "/root");
+        let opts = WalkOptions {
+            follow_links: true,
+            ..Default::default()
+        };
+
+        let entries = fs.walk_dir(Path::new("/root"), &opts).unwrap();
+        let paths: Vec<&Path> = entries.iter().map(|e| e.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                Path::new("/root"),
+                Path::new("/root/a.txt"),
+                Path::new("/root/other"),
+                Path::new("/root/other/b.txt"),
+            ]
+        );
+        assert_eq!(
+            fs.canonicalize(Path::new("/root/other/b.txt")).unwrap(),
+            Path::new("/other/b.txt")
+        );
+    }
+
     #[test]
     fn test_walk_dir_min_depth() {
         let fs = FakeFs::new("/home/test");
@@ -620,6 +871,55 @@ mod tests {
         assert_eq!(fs.file_mode(Path::new("/tmp/script.sh")).unwrap(), 0o755);
     }
 
+    #[test]
+    fn test_stamp_changes_on_write() {
+        let fs = FakeFs::new("/home/test");
+        fs.add_file("/a", "one");
+        let before = fs.stamp(Path::new("/a")).unwrap();
+        assert_eq!(before.len, 3);
+        assert_eq!(fs.stamp(Path::new("/a")).unwrap(), before);
+        fs.write(Path::new("/a"), b"two").unwrap();
+        let after = fs.stamp(Path::new("/a")).unwrap();
+        assert_eq!(after.len, 3);
+        assert_ne!(after, before);
+        assert!(fs.stamp(Path::new("/missing")).is_err());
+    }
+
+    #[test]
+    fn test_read_count() {
+        let fs = FakeFs::new("/home/test");
+        fs.add_file("/a", "one");
+        fs.read(Path::new("/a")).unwrap();
+        fs.read_to_string(Path::new("/a")).unwrap();
+        assert_eq!(fs.read_count(), 2);
+    }
+
+    #[test]
+    fn test_trash_picks_free_names() {
+        let fs = FakeFs::new("/home/test");
+        fs.add_file("/x/a.conf", "one");
+        fs.add_file("/y/a.conf", "two");
+
+        let first = fs.trash(Path::new("/x/a.conf")).unwrap();
+        let second = fs.trash(Path::new("/y/a.conf")).unwrap();
+        assert_eq!(
+            first,
+            Path::new("/home/test/.local/share/Trash/files/a.conf")
+        );
+        assert_eq!(
+            second,
+            Path::new("/home/test/.local/share/Trash/files/a.conf.2")
+        );
+        assert!(!fs.exists(Path::new("/x/a.conf")));
+        assert_eq!(fs.read_to_string(&second).unwrap(), "two");
+        let info = fs
+            .read_to_string(Path::new(
+                "/home/test/.local/share/Trash/info/a.conf.2.trashinfo",
+            ))
+            .unwrap();
+        assert!(info.contains("Path=/y/a.conf\n"), "got: {info}");
+    }
+
     #[test]
     fn test_auto_creates_parents() {
         let fs = FakeFs::new("/home/test");
@@ -0,0 +1,54 @@
+//! Fake HTTP client for testing.
+//!
+//! Bodies are registered per exact URL via `add_response()`; fetching any
+//! other URL fails as a 404 would. Every fetched URL is recorded in order.
+
+use anyhow::{Result, bail};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use super::HttpClient;
+
+/// In-memory HTTP client — serves pre-configured bodies without a network.
+pub struct FakeHttpClient {
+    /// Map of URL -> response body.
+    responses: HashMap<String, Vec<u8>>,
+    /// Every URL passed to `get()`, in order.
+    requests: RefCell<Vec<String>>,
+}
+
+impl Default for FakeHttpClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FakeHttpClient {
+    pub fn new() -> Self {
+        Self {
+            responses: HashMap::new(),
+            requests: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Register the body `get()` will return for `url`.
+    /// Returns the previous body if one was already registered.
+    pub fn add_response(&mut self, url: &str, body: impl Into<Vec<u8>>) -> Option<Vec<u8>> {
+        self.responses.insert(url.to_string(), body.into())
+    }
+
+    /// All URLs fetched so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.borrow().clone()
+    }
+}
+
+impl HttpClient for FakeHttpClient {
+    fn get(&self, url: &str) -> Result<Vec<u8>> {
+        self.requests.borrow_mut().push(url.to_string());
+        match self.responses.get(url) {
+            Some(body) => Ok(body.clone()),
+            None => bail!("Failed to download {url}: 404 Not Found"),
+        }
+    }
+}
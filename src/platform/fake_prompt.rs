@@ -2,7 +2,9 @@
 //!
 //! Pre-loaded with a queue of responses. Each `select()` call pops the next
 //! response from the front. Errors if the queue is exhausted or a response
-//! index is out of range for the given items.
+//! index is out of range for the given items. `confirm()`, `input()`,
+//! `password()`, and `multi_select()` answers come from separate queues set
+//! with the `with_*` methods.
 
 use anyhow::{Result, bail};
 use std::cell::RefCell;
@@ -14,6 +16,23 @@ use super::Prompter;
 pub struct FakePrompter {
     /// FIFO queue of selection indices to return.
     responses: RefCell<VecDeque<usize>>,
+    /// FIFO queue of answers for `confirm()`.
+    confirms: RefCell<VecDeque<bool>>,
+    /// FIFO queue of text answers for `input()`.
+    inputs: RefCell<VecDeque<String>>,
+    /// FIFO queue of answers for `password()`.
+    passwords: RefCell<VecDeque<String>>,
+    /// FIFO queue of checked indices for `multi_select()`.
+    multi_selections: RefCell<VecDeque<Vec<usize>>>,
+}
+
+/// Pop the next answer from `queue`, failing with `kind` and `prompt` if
+/// it's empty.
+fn pop<T>(queue: &RefCell<VecDeque<T>>, kind: &str, prompt: &str) -> Result<T> {
+    match queue.borrow_mut().pop_front() {
+        Some(answer) => Ok(answer),
+        None => bail!("FakePrompter: no more {kind} queued (prompt: \"{prompt}\")"),
+    }
 }
 
 impl FakePrompter {
@@ -23,12 +42,49 @@ impl FakePrompter {
     pub fn new(responses: Vec<usize>) -> Self {
         Self {
             responses: RefCell::new(responses.into()),
+            confirms: RefCell::new(VecDeque::new()),
+            inputs: RefCell::new(VecDeque::new()),
+            passwords: RefCell::new(VecDeque::new()),
+            multi_selections: RefCell::new(VecDeque::new()),
         }
     }
 
-    /// How many unconsumed responses remain.
+    /// Queue answers for `confirm()`, returned in order.
+    pub fn with_confirms(self, confirms: &[bool]) -> Self {
+        self.confirms.borrow_mut().extend(confirms);
+        self
+    }
+
+    /// Queue text answers for `input()`, returned in order. An empty answer
+    /// stands for pressing Enter, so `input()` returns its default instead.
+    pub fn with_inputs(self, inputs: &[&str]) -> Self {
+        self.inputs
+            .borrow_mut()
+            .extend(inputs.iter().map(|s| s.to_string()));
+        self
+    }
+
+    /// Queue answers for `password()`, returned in order.
+    pub fn with_passwords(self, passwords: &[&str]) -> Self {
+        self.passwords
+            .borrow_mut()
+            .extend(passwords.iter().map(|s| s.to_string()));
+        self
+    }
+
+    /// Queue checked indices for `multi_select()`, returned in order.
+    pub fn with_multi_selections(self, selections: Vec<Vec<usize>>) -> Self {
+        self.multi_selections.borrow_mut().extend(selections);
+        self
+    }
+
+    /// How many unconsumed answers remain, across every queue.
     pub fn remaining(&self) -> usize {
         self.responses.borrow().len()
+            + self.confirms.borrow().len()
+            + self.inputs.borrow().len()
+            + self.passwords.borrow().len()
+            + self.multi_selections.borrow().len()
     }
 }
 
@@ -53,6 +109,33 @@ impl Prompter for FakePrompter {
             ),
         }
     }
+
+    fn confirm(&self, prompt: &str, _default: bool) -> Result<bool> {
+        pop(&self.confirms, "confirms", prompt)
+    }
+
+    fn input(&self, prompt: &str, default: Option<&str>) -> Result<String> {
+        let text = pop(&self.inputs, "inputs", prompt)?;
+        match default {
+            Some(default) if text.is_empty() => Ok(default.to_string()),
+            _ => Ok(text),
+        }
+    }
+
+    fn password(&self, prompt: &str) -> Result<String> {
+        pop(&self.passwords, "passwords", prompt)
+    }
+
+    fn multi_select(&self, prompt: &str, items: &[&str], _defaults: &[bool]) -> Result<Vec<usize>> {
+        let checked = pop(&self.multi_selections, "multi-selections", prompt)?;
+        if let Some(idx) = checked.iter().find(|&&i| i >= items.len()) {
+            bail!(
+                "FakePrompter: checked index {idx} out of range for {} items (prompt: \"{prompt}\")",
+                items.len()
+            );
+        }
+        Ok(checked)
+    }
 }
 
 #[cfg(test)]
@@ -80,11 +163,45 @@ mod tests {
         assert!(prompter.select("q1", &["a", "b"], 0).is_err());
     }
 
+    #[test]
+    fn test_inputs_in_order() {
+        let prompter = FakePrompter::new(vec![]).with_inputs(&["one", "two"]);
+        assert_eq!(prompter.input("q1", None).unwrap(), "one");
+        assert_eq!(prompter.input("q2", None).unwrap(), "two");
+        assert!(prompter.input("q3", None).is_err());
+    }
+
+    #[test]
+    fn test_empty_input_takes_default() {
+        let prompter = FakePrompter::new(vec![]).with_inputs(&["", "", "x"]);
+        assert_eq!(prompter.input("q1", Some("def")).unwrap(), "def");
+        assert_eq!(prompter.input("q2", None).unwrap(), "");
+        assert_eq!(prompter.input("q3", Some("def")).unwrap(), "x");
+    }
+
+    #[test]
+    fn test_confirms_passwords_and_multi_selections() {
+        let prompter = FakePrompter::new(vec![])
+            .with_confirms(&[true, false])
+            .with_passwords(&["hunter2"])
+            .with_multi_selections(vec![vec![0, 2], vec![3]]);
+        assert!(prompter.confirm("q1", false).unwrap());
+        assert!(!prompter.confirm("q2", true).unwrap());
+        assert!(prompter.confirm("q3", true).is_err());
+        assert_eq!(prompter.password("q4").unwrap(), "hunter2");
+        assert_eq!(
+            prompter.multi_select("q5", &["a", "b", "c"], &[]).unwrap(),
+            vec![0, 2]
+        );
+        assert!(prompter.multi_select("q6", &["a", "b"], &[]).is_err());
+    }
+
     #[test]
     fn test_remaining() {
-        let prompter = FakePrompter::new(vec![0, 1]);
-        assert_eq!(prompter.remaining(), 2);
+        let prompter = FakePrompter::new(vec![0, 1]).with_confirms(&[true]);
+        assert_eq!(prompter.remaining(), 3);
         prompter.select("q", &["a", "b"], 0).unwrap();
+        prompter.confirm("q", false).unwrap();
         assert_eq!(prompter.remaining(), 1);
     }
 }
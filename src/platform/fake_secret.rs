@@ -1,10 +1,11 @@
 //! Fake secret engine for testing.
 //!
 //! Pre-loaded with secret values via `add_secret()`. Calls to `resolve()`
-//! return the matching value or bail if no secret was registered.
+//! return the matching value or bail if no secret was registered. Engines
+//! marked with `set_unavailable()` fail `check()`.
 
 use anyhow::{Result, bail};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use super::SecretEngine;
 
@@ -12,15 +13,29 @@ use super::SecretEngine;
 pub struct FakeSecretEngine {
     /// Map of `(engine, reference)` -> resolved value.
     secrets: HashMap<(String, String), String>,
+    /// Engines whose `check()` fails, as if not installed.
+    unavailable: HashSet<String>,
+}
+
+impl Default for FakeSecretEngine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl FakeSecretEngine {
     pub fn new() -> Self {
         Self {
             secrets: HashMap::new(),
+            unavailable: HashSet::new(),
         }
     }
 
+    /// Make `check()` fail for `engine`.
+    pub fn set_unavailable(&mut self, engine: &str) {
+        self.unavailable.insert(engine.to_string());
+    }
+
     /// Register a secret that `resolve()` will return.
     /// Returns the previous value if one was already registered for this key.
     pub fn add_secret(&mut self, engine: &str, reference: &str, value: &str) -> Option<String> {
@@ -43,6 +58,13 @@ impl SecretEngine for FakeSecretEngine {
             ),
         }
     }
+
+    fn check(&self, engine: &str) -> Result<()> {
+        if self.unavailable.contains(engine) {
+            bail!("FakeSecretEngine: {engine} is unavailable");
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -75,4 +97,12 @@ mod tests {
 
         assert!(engine.resolve("bitwarden", "op://Vault/Item").is_err());
     }
+
+    #[test]
+    fn test_check_unavailable_engine_fails() {
+        let mut engine = FakeSecretEngine::new();
+        assert!(engine.check("1password").is_ok());
+        engine.set_unavailable("1password");
+        assert!(engine.check("1password").is_err());
+    }
 }
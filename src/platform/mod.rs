@@ -1,48 +1,75 @@
 //! Abstracted external dependencies for testability.
 //!
-//! Three traits cover all side effects: [`Fs`] for filesystem operations,
-//! [`SecretEngine`] for resolving secrets from external managers, and
-//! [`Prompter`] for interactive user prompts.
+//! These traits cover all side effects: [`Fs`] for filesystem operations,
+//! [`SecretEngine`] for resolving secrets from external managers,
+//! [`Prompter`] for interactive user prompts, [`Locker`] for the process
+//! lock, [`CommandRunner`] for spawning external commands, and
+//! [`HttpClient`] for downloads.
 //!
 //! Production code uses the real implementations ([`RealFs`], [`RealSecretEngine`],
-//! [`RealPrompter`]). Tests substitute fakes via generics — no trait objects needed.
+//! [`RealPrompter`], [`RealLocker`], [`RealCommandRunner`], [`RealHttpClient`]). Tests substitute
+//! fakes via generics — no trait objects needed. The fakes are also built
+//! with the `test-util` feature, for tests outside this crate.
+//!
+//! [`ReadonlyFs`] wraps any [`Fs`] to refuse writes under `--readonly`, and
+//! [`PackedFs`] to keep the generated directory in one pack file.
 
+mod packed_fs;
+mod readonly_fs;
+mod real_command;
 mod real_fs;
+mod real_http;
 mod real_locker;
 mod real_prompt;
 mod real_secret;
+pub(crate) mod xdg_trash;
 
+pub use packed_fs::PackedFs;
+pub use readonly_fs::ReadonlyFs;
+pub use real_command::RealCommandRunner;
 pub use real_fs::RealFs;
+pub use real_http::RealHttpClient;
 pub use real_locker::RealLocker;
 pub use real_prompt::RealPrompter;
 pub use real_secret::RealSecretEngine;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "test-util"))]
+mod fake_command;
+#[cfg(any(test, feature = "test-util"))]
 mod fake_fs;
-#[cfg(test)]
+#[cfg(any(test, feature = "test-util"))]
+mod fake_http;
+#[cfg(any(test, feature = "test-util"))]
 mod fake_locker;
-#[cfg(test)]
+#[cfg(any(test, feature = "test-util"))]
 mod fake_prompt;
-#[cfg(test)]
+#[cfg(any(test, feature = "test-util"))]
 mod fake_secret;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "test-util"))]
+#[allow(unused_imports)]
+pub use self::fake_command::FakeCommandRunner;
+#[cfg(any(test, feature = "test-util"))]
 #[allow(unused_imports)]
-pub(crate) use self::fake_fs::FakeEntry;
-#[cfg(test)]
+pub use self::fake_fs::FakeEntry;
+#[cfg(any(test, feature = "test-util"))]
 #[allow(unused_imports)]
 pub use self::fake_fs::FakeFs;
-#[cfg(test)]
+#[cfg(any(test, feature = "test-util"))]
+#[allow(unused_imports)]
+pub use self::fake_http::FakeHttpClient;
+#[cfg(any(test, feature = "test-util"))]
 #[allow(unused_imports)]
 pub use self::fake_locker::FakeLocker;
-#[cfg(test)]
+#[cfg(any(test, feature = "test-util"))]
 #[allow(unused_imports)]
 pub use self::fake_prompt::FakePrompter;
-#[cfg(test)]
+#[cfg(any(test, feature = "test-util"))]
 #[allow(unused_imports)]
 pub use self::fake_secret::FakeSecretEngine;
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 
 // ---------------------------------------------------------------------------
@@ -76,6 +103,19 @@ pub struct DirEntry {
     pub is_symlink: bool,
 }
 
+/// Cheap change-detection metadata for a file, from [`Fs::stamp`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FileStamp {
+    /// Modification time in nanoseconds since the Unix epoch.
+    pub modified_ns: u64,
+    /// File size in bytes.
+    pub len: u64,
+}
+
+/// A file's extended attributes as name and value pairs, e.g.
+/// `security.selinux` or the `system.posix_acl_access` that holds its ACL.
+pub type Xattrs = Vec<(String, Vec<u8>)>;
+
 /// Abstraction over all filesystem operations, directory traversal, and
 /// system path queries (home dir, config dir).
 ///
@@ -89,14 +129,28 @@ pub trait Fs {
     /// Read the entire contents of a file as raw bytes.
     fn read(&self, path: &Path) -> Result<Vec<u8>>;
 
+    /// [`content_hash`](crate::state::content_hash) of a file's contents,
+    /// streamed rather than read whole where the filesystem allows.
+    fn hash_file(&self, path: &Path) -> Result<String>;
+
     // -- Writing --
 
     /// Write `contents` to a file, creating it or truncating if it exists.
     fn write(&self, path: &Path, contents: &[u8]) -> Result<()>;
 
+    /// Append `contents` to the end of a file, creating it if it doesn't
+    /// exist.
+    fn append(&self, path: &Path, contents: &[u8]) -> Result<()>;
+
     /// Copy a file from `from` to `to`, overwriting `to` if it exists.
     fn copy(&self, from: &Path, to: &Path) -> Result<()>;
 
+    /// Copy a file byte for byte without holding it in memory, overwriting
+    /// `to` if it exists. Shares extents with `from` where the filesystem
+    /// supports cloning (btrfs, XFS); otherwise streams the data, leaving
+    /// holes in sparse files as holes.
+    fn copy_streaming(&self, from: &Path, to: &Path) -> Result<()>;
+
     // -- File/directory removal --
 
     /// Remove a single file (or symlink).
@@ -105,6 +159,11 @@ pub trait Fs {
     /// Remove an empty directory.
     fn remove_dir(&self, path: &Path) -> Result<()>;
 
+    /// Move a file or symlink to the user's trash (the freedesktop.org
+    /// trash under the data dir, e.g. `~/.local/share/Trash`). Returns where
+    /// it ended up, for restoring it later.
+    fn trash(&self, path: &Path) -> Result<PathBuf>;
+
     /// Atomically rename `from` to `to`.
     fn rename(&self, from: &Path, to: &Path) -> Result<()>;
 
@@ -121,14 +180,35 @@ pub trait Fs {
     /// Set the Unix file mode (permission bits) of a file.
     fn set_file_mode(&self, path: &Path, mode: u32) -> Result<()>;
 
+    /// Get the modification time and size of a file (follows symlinks).
+    fn stamp(&self, path: &Path) -> Result<FileStamp>;
+
+    // -- Extended attributes --
+
+    /// Get the extended attributes of a file (follows symlinks), including
+    /// its ACL and SELinux label. Empty where the filesystem has none.
+    fn xattrs(&self, path: &Path) -> Result<Xattrs>;
+
+    /// Set each of `attrs` on a file (follows symlinks), leaving its other
+    /// extended attributes alone.
+    fn set_xattrs(&self, path: &Path, attrs: &Xattrs) -> Result<()>;
+
     // -- Symlinks --
 
     /// Create a symbolic link at `link` pointing to `original`.
     fn symlink(&self, original: &Path, link: &Path) -> Result<()>;
 
+    /// Create a hard link at `link` to the file `original`. Fails if `link`
+    /// exists.
+    fn hard_link(&self, original: &Path, link: &Path) -> Result<()>;
+
     /// Read the target of a symbolic link.
     fn read_link(&self, path: &Path) -> Result<PathBuf>;
 
+    /// Resolve every symlink in `path`, giving the path it really names.
+    /// Fails if it doesn't exist.
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf>;
+
     // -- Path queries --
 
     /// Check if a path exists (follows symlinks; broken symlinks return false).
@@ -156,6 +236,17 @@ pub trait Fs {
 
     /// Return the user's config directory (e.g. `~/.config`), if it can be determined.
     fn config_dir(&self) -> Option<PathBuf>;
+
+    /// Return the user's state directory (`$XDG_STATE_HOME`, else
+    /// `~/.local/state`), if it can be determined.
+    fn state_dir(&self) -> Option<PathBuf>;
+
+    /// Return the user's cache directory (`$XDG_CACHE_HOME`, else
+    /// `~/.cache`), if it can be determined.
+    fn cache_dir(&self) -> Option<PathBuf>;
+
+    /// Return this machine's hostname, if it can be determined.
+    fn hostname(&self) -> Option<String>;
 }
 
 // ---------------------------------------------------------------------------
@@ -171,6 +262,11 @@ pub trait SecretEngine {
     ///
     /// Returns the secret value as a string.
     fn resolve(&self, engine: &str, reference: &str) -> Result<String>;
+
+    /// Check that `engine` can resolve anything at all (its CLI is
+    /// installed and signed in). Callers check once per engine rather than
+    /// letting every `resolve` fail the same way.
+    fn check(&self, engine: &str) -> Result<()>;
 }
 
 // ---------------------------------------------------------------------------
@@ -186,6 +282,21 @@ pub trait Prompter {
     /// `prompt` is the question text, `items` are the choices, and `default`
     /// is the pre-selected index.
     fn select(&self, prompt: &str, items: &[&str], default: usize) -> Result<usize>;
+
+    /// Ask a yes/no question, with `default` as the answer to a bare Enter.
+    fn confirm(&self, prompt: &str, default: bool) -> Result<bool>;
+
+    /// Ask for a line of free text and return it (without the newline).
+    /// An empty answer returns `default`, if given.
+    fn input(&self, prompt: &str, default: Option<&str>) -> Result<String>;
+
+    /// Ask for a line of text without echoing it.
+    fn password(&self, prompt: &str) -> Result<String>;
+
+    /// Present a checklist and return the indices of the checked items, in
+    /// order. `defaults` are the items checked initially; missing entries
+    /// are unchecked.
+    fn multi_select(&self, prompt: &str, items: &[&str], defaults: &[bool]) -> Result<Vec<usize>>;
 }
 
 // ---------------------------------------------------------------------------
@@ -210,3 +321,45 @@ pub trait Locker {
     /// Path to the lock file.
     fn lock_path(&self) -> &Path;
 }
+
+// ---------------------------------------------------------------------------
+// Command runner
+// ---------------------------------------------------------------------------
+
+/// Captured result of running an external command.
+#[derive(Debug, Clone, Default)]
+pub struct CommandOutput {
+    /// Whether the command exited with status 0.
+    pub success: bool,
+    /// Everything the command wrote to stdout (lossily decoded as UTF-8).
+    pub stdout: String,
+    /// Everything the command wrote to stderr (lossily decoded as UTF-8).
+    pub stderr: String,
+}
+
+/// Abstraction over spawning external commands.
+///
+/// In production, runs command lines through `sh -c`. In tests, records the
+/// calls and returns pre-configured output.
+pub trait CommandRunner {
+    /// Run a shell command line, feeding `stdin` to it if provided, and wait
+    /// for it to finish.
+    ///
+    /// A non-zero exit is reported via [`CommandOutput::success`], not as an
+    /// `Err`; `Err` means the command could not be run at all.
+    fn run(&self, command: &str, stdin: Option<&str>) -> Result<CommandOutput>;
+}
+
+// ---------------------------------------------------------------------------
+// HTTP client
+// ---------------------------------------------------------------------------
+
+/// Abstraction over downloading files (used by `janus import <url>`).
+///
+/// In production, shells out to `curl`. In tests, returns pre-configured
+/// bodies.
+pub trait HttpClient {
+    /// Fetch `url` and return the response body. Fails on a network error
+    /// or a non-success HTTP status.
+    fn get(&self, url: &str) -> Result<Vec<u8>>;
+}
@@ -0,0 +1,777 @@
+//! Packed store for the generated directory (`packed_store = true`).
+//!
+//! Cloud storage syncs thousands of small files slowly. With
+//! `packed_store`, nothing under the generated directory is written as its
+//! own file: contents go into `<dir>.pack`, appended one after another, and
+//! `<dir>.pack.toml` indexes where each path's current content starts, how
+//! long it is, and its mode. [`PackedFs`] serves paths under the directory
+//! from the pack and passes everything else through, so ops don't change.
+//!
+//! Rewriting a path appends the new content and leaves the old bytes dead
+//! until they outweigh the live ones, when the pack is compacted. Changes
+//! are held in memory and written out by [`PackedFs::flush`], or on drop:
+//! new content is appended to the pack file, which is only rewritten when
+//! compacted, and then the index is replaced by renaming a new one over it.
+//!
+//! The index records how long the pack it describes is and a hash of it,
+//! checked on load. A flush interrupted after appending leaves bytes past
+//! that length, which are dropped; one interrupted while compacting leaves
+//! a pack that doesn't match, which is an error rather than content read
+//! from the wrong place.
+//!
+//! The staged directory isn't packed, since deployed symlinks point into
+//! it; `staged_dir` can move it out of `dotfiles_dir` instead.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use super::{DirEntry, FileStamp, Fs, WalkOptions, Xattrs};
+use crate::state::{content_hash, unix_now_ns};
+
+/// Where one path's content lives in the pack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct IndexEntry {
+    offset: u64,
+    len: u64,
+    mode: u32,
+    modified_ns: u64,
+}
+
+/// The `<dir>.pack.toml` index, keyed by path relative to the directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Index {
+    /// Length of the pack this index describes. Indexes written before it
+    /// was recorded have neither this nor `pack_hash`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pack_len: Option<u64>,
+    /// [`content_hash`] of the first `pack_len` bytes of the pack.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pack_hash: Option<String>,
+    #[serde(default)]
+    files: BTreeMap<String, IndexEntry>,
+}
+
+/// A loaded pack.
+struct Pack {
+    data: Vec<u8>,
+    index: Index,
+    /// Whether the pack file already existed, or has been written to.
+    exists: bool,
+    dirty: bool,
+    /// How much of `data` is already in the pack file.
+    flushed_len: u64,
+    /// Whether the pack file must be rewritten rather than appended to,
+    /// after compacting or dropping an unindexed tail.
+    rewrite: bool,
+}
+
+impl Pack {
+    fn get(&self, key: &str) -> Option<&[u8]> {
+        let entry = self.index.files.get(key)?;
+        Some(&self.data[entry.offset as usize..(entry.offset + entry.len) as usize])
+    }
+
+    fn put(&mut self, key: &str, content: &[u8], mode: u32) {
+        let modified_ns = unix_now_ns();
+        if self.get(key) == Some(content) {
+            let entry = self.index.files.get_mut(key).expect("just read");
+            entry.mode = mode;
+            entry.modified_ns = modified_ns;
+        } else {
+            let offset = self.data.len() as u64;
+            self.data.extend_from_slice(content);
+            self.index.files.insert(
+                key.to_string(),
+                IndexEntry {
+                    offset,
+                    len: content.len() as u64,
+                    mode,
+                    modified_ns,
+                },
+            );
+        }
+        self.exists = true;
+        self.dirty = true;
+    }
+
+    fn remove(&mut self, key: &str) -> Option<IndexEntry> {
+        let removed = self.index.files.remove(key);
+        self.dirty |= removed.is_some();
+        removed
+    }
+
+    /// Whether `key` is a directory: the root, or a prefix of a file.
+    fn is_dir(&self, key: &str) -> bool {
+        if key.is_empty() {
+            return self.exists;
+        }
+        let prefix = format!("{key}/");
+        self.index
+            .files
+            .range(prefix.clone()..)
+            .next()
+            .is_some_and(|(k, _)| k.starts_with(&prefix))
+    }
+
+    /// Rewrite the data with only the live content, in path order.
+    fn compact(&mut self) {
+        let mut data = Vec::with_capacity(self.live_len() as usize);
+        for entry in self.index.files.values_mut() {
+            let start = entry.offset as usize;
+            entry.offset = data.len() as u64;
+            data.extend_from_slice(&self.data[start..start + entry.len as usize]);
+        }
+        self.data = data;
+    }
+
+    fn live_len(&self) -> u64 {
+        self.index.files.values().map(|e| e.len).sum()
+    }
+}
+
+/// The pack file for `dir`.
+fn pack_path(dir: &Path) -> PathBuf {
+    let mut name = dir.file_name().unwrap_or_default().to_os_string();
+    name.push(".pack");
+    dir.with_file_name(name)
+}
+
+/// The index file for `dir`.
+fn index_path(dir: &Path) -> PathBuf {
+    let mut name = pack_path(dir).into_os_string();
+    name.push(".toml");
+    PathBuf::from(name)
+}
+
+/// Order walk entries as [`Fs::walk_dir`] does: by path, or deepest first
+/// with `contents_first`.
+fn sort_entries(entries: &mut [DirEntry], contents_first: bool) {
+    if contents_first {
+        entries.sort_by(|a, b| {
+            let a_depth = a.path.components().count();
+            let b_depth = b.path.components().count();
+            b_depth.cmp(&a_depth).then(a.path.cmp(&b.path))
+        });
+    } else {
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+}
+
+/// An [`Fs`] keeping everything under `dir` in a pack file beside it. With
+/// no `dir`, every call passes straight through.
+pub struct PackedFs<'a, F: Fs> {
+    inner: &'a F,
+    dir: Option<PathBuf>,
+    pack: RefCell<Option<Pack>>,
+}
+
+impl<'a, F: Fs> PackedFs<'a, F> {
+    pub fn new(inner: &'a F, dir: Option<PathBuf>) -> Self {
+        Self {
+            inner,
+            dir,
+            pack: RefCell::new(None),
+        }
+    }
+
+    /// `path` relative to the packed directory, if it's inside it.
+    fn key(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(self.dir.as_ref()?).ok()?;
+        Some(
+            relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"),
+        )
+    }
+
+    /// Run `f` on the pack, reading it from disk first if needed.
+    fn with_pack<T>(&self, f: impl FnOnce(&mut Pack) -> T) -> Result<T> {
+        let mut pack = self.pack.borrow_mut();
+        if pack.is_none() {
+            *pack = Some(self.load()?);
+        }
+        Ok(f(pack.as_mut().expect("just loaded")))
+    }
+
+    fn load(&self) -> Result<Pack> {
+        let dir = self.dir.as_ref().expect("only loaded with a dir");
+        let pack_path = pack_path(dir);
+        let index_path = index_path(dir);
+        if !self.inner.is_file(&pack_path) {
+            return Ok(Pack {
+                data: Vec::new(),
+                index: Index::default(),
+                exists: false,
+                dirty: false,
+                flushed_len: 0,
+                rewrite: false,
+            });
+        }
+        let mut data = self.inner.read(&pack_path)?;
+        let index: Index = if self.inner.is_file(&index_path) {
+            toml::from_str(&self.inner.read_to_string(&index_path)?)
+                .with_context(|| format!("Failed to parse {}", index_path.display()))?
+        } else {
+            Index::default()
+        };
+        let mut rewrite = false;
+        if let (Some(len), Some(hash)) = (index.pack_len, &index.pack_hash) {
+            if data.len() < len as usize || content_hash(&data[..len as usize]) != *hash {
+                bail!(
+                    "{} doesn't match {}, a write was probably interrupted; delete both \
+                     and run `janus generate` to rebuild them",
+                    pack_path.display(),
+                    index_path.display()
+                );
+            }
+            if data.len() > len as usize {
+                debug!(
+                    "Dropping {} unindexed byte(s) from {}",
+                    data.len() - len as usize,
+                    pack_path.display()
+                );
+                data.truncate(len as usize);
+                rewrite = true;
+            }
+        }
+        if let Some((key, _)) = index
+            .files
+            .iter()
+            .find(|(_, e)| e.offset + e.len > data.len() as u64)
+        {
+            bail!(
+                "{} points past the end of {} at {key}; delete both and run \
+                 `janus generate` to rebuild them",
+                index_path.display(),
+                pack_path.display()
+            );
+        }
+        debug!(
+            "Loaded {} packed file(s) from {}",
+            index.files.len(),
+            pack_path.display()
+        );
+        Ok(Pack {
+            flushed_len: data.len() as u64,
+            data,
+            index,
+            exists: true,
+            dirty: false,
+            rewrite,
+        })
+    }
+
+    /// Write out the pack and its index if anything changed, compacting
+    /// first when dead content outweighs live. The pack is written first,
+    /// so an index on disk never describes content that isn't there yet.
+    pub fn flush(&self) -> Result<()> {
+        let mut pack = self.pack.borrow_mut();
+        let (Some(dir), Some(pack)) = (&self.dir, pack.as_mut()) else {
+            return Ok(());
+        };
+        if !pack.dirty {
+            return Ok(());
+        }
+        if pack.data.len() as u64 > 2 * pack.live_len() {
+            pack.compact();
+            pack.rewrite = true;
+        }
+        let pack_path = pack_path(dir);
+        let index_path = index_path(dir);
+        if let Some(parent) = dir.parent() {
+            self.inner.create_dir_all(parent)?;
+        }
+        let written = if pack.rewrite {
+            self.inner.write(&pack_path, &pack.data)
+        } else {
+            self.inner
+                .append(&pack_path, &pack.data[pack.flushed_len as usize..])
+        };
+        written.with_context(|| format!("Failed to write {}", pack_path.display()))?;
+        pack.flushed_len = pack.data.len() as u64;
+        pack.rewrite = false;
+
+        pack.index.pack_len = Some(pack.flushed_len);
+        pack.index.pack_hash = Some(content_hash(&pack.data));
+        let mut temp = index_path.clone().into_os_string();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        self.inner
+            .write(&temp, toml::to_string(&pack.index)?.as_bytes())
+            .with_context(|| format!("Failed to write {}", temp.display()))?;
+        self.inner
+            .rename(&temp, &index_path)
+            .with_context(|| format!("Failed to replace {}", index_path.display()))?;
+        pack.dirty = false;
+        Ok(())
+    }
+
+    /// Content and mode of the packed file at `path`.
+    fn packed(&self, key: &str, path: &Path) -> Result<(Vec<u8>, u32)> {
+        self.with_pack(|pack| {
+            let mode = pack.index.files.get(key).map(|e| e.mode);
+            pack.get(key).map(<[u8]>::to_vec).zip(mode)
+        })?
+        .with_context(|| format!("file not found: {}", path.display()))
+    }
+
+    /// Write packed content out as a real file at `to`.
+    fn unpack_to(&self, key: &str, from: &Path, to: &Path) -> Result<()> {
+        let (content, mode) = self.packed(key, from)?;
+        self.inner.write(to, &content)?;
+        self.inner.set_file_mode(to, mode)
+    }
+
+    /// Pack the real file at `from` as `key`.
+    fn pack_from(&self, from: &Path, key: &str) -> Result<()> {
+        let content = self.inner.read(from)?;
+        let mode = self.inner.file_mode(from)?;
+        self.with_pack(|pack| pack.put(key, &content, mode))
+    }
+
+    fn copy_with(
+        &self,
+        from: &Path,
+        to: &Path,
+        copy: impl FnOnce(&Path, &Path) -> Result<()>,
+    ) -> Result<()> {
+        match (self.key(from), self.key(to)) {
+            (Some(from_key), Some(to_key)) => {
+                let (content, mode) = self.packed(&from_key, from)?;
+                self.with_pack(|pack| pack.put(&to_key, &content, mode))
+            }
+            (Some(key), None) => self.unpack_to(&key, from, to),
+            (None, Some(key)) => self.pack_from(from, &key),
+            (None, None) => copy(from, to),
+        }
+    }
+
+    /// The files and directories in the pack under `path`, as `walk_dir`
+    /// would find them.
+    fn walk_packed(&self, path: &Path, opts: &WalkOptions) -> Result<Vec<DirEntry>> {
+        let dir = self.dir.as_ref().expect("only walked with a dir");
+        let (files, exists) = self.with_pack(|pack| {
+            let files: Vec<String> = pack.index.files.keys().cloned().collect();
+            (files, pack.exists)
+        })?;
+        let mut dirs = BTreeSet::new();
+        if exists {
+            dirs.insert(dir.clone());
+        }
+        let files: Vec<PathBuf> = files.iter().map(|key| dir.join(key)).collect();
+        for file in &files {
+            for ancestor in file.ancestors().skip(1) {
+                if !ancestor.starts_with(dir) || !dirs.insert(ancestor.to_path_buf()) {
+                    break;
+                }
+            }
+        }
+
+        let depth = |p: &Path| p.strip_prefix(path).map(|r| r.components().count());
+        let in_range = |p: &Path| {
+            depth(p).is_ok_and(|d| d >= opts.min_depth && opts.max_depth.is_none_or(|max| d <= max))
+        };
+        let entry = |path: &PathBuf, is_file: bool| DirEntry {
+            path: path.clone(),
+            is_file,
+            is_dir: !is_file,
+            is_symlink: false,
+        };
+        let mut entries: Vec<DirEntry> = dirs
+            .iter()
+            .filter(|p| in_range(p))
+            .map(|p| entry(p, false))
+            .chain(files.iter().filter(|p| in_range(p)).map(|p| entry(p, true)))
+            .collect();
+        sort_entries(&mut entries, opts.contents_first);
+        Ok(entries)
+    }
+}
+
+impl<F: Fs> Drop for PackedFs<'_, F> {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            warn!("Failed to write the generated pack: {e:#}");
+        }
+    }
+}
+
+impl<F: Fs> Fs for PackedFs<'_, F> {
+    fn read_to_string(&self, path: &Path) -> Result<String> {
+        match self.key(path) {
+            Some(key) => Ok(String::from_utf8(self.packed(&key, path)?.0)?),
+            None => self.inner.read_to_string(path),
+        }
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        match self.key(path) {
+            Some(key) => Ok(self.packed(&key, path)?.0),
+            None => self.inner.read(path),
+        }
+    }
+
+    fn hash_file(&self, path: &Path) -> Result<String> {
+        match self.key(path) {
+            Some(key) => Ok(content_hash(&self.packed(&key, path)?.0)),
+            None => self.inner.hash_file(path),
+        }
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        match self.key(path) {
+            Some(key) => self.with_pack(|pack| {
+                let mode = pack.index.files.get(&key).map_or(0o644, |e| e.mode);
+                pack.put(&key, contents, mode);
+            }),
+            None => self.inner.write(path, contents),
+        }
+    }
+
+    fn append(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        match self.key(path) {
+            Some(key) => self.with_pack(|pack| {
+                let (mut content, mode) = match pack.index.files.get(&key) {
+                    Some(entry) => (pack.get(&key).unwrap_or_default().to_vec(), entry.mode),
+                    None => (Vec::new(), 0o644),
+                };
+                content.extend_from_slice(contents);
+                pack.put(&key, &content, mode);
+            }),
+            None => self.inner.append(path, contents),
+        }
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.copy_with(from, to, |from, to| self.inner.copy(from, to))
+    }
+
+    fn copy_streaming(&self, from: &Path, to: &Path) -> Result<()> {
+        self.copy_with(from, to, |from, to| self.inner.copy_streaming(from, to))
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        match self.key(path) {
+            Some(key) => self
+                .with_pack(|pack| pack.remove(&key))?
+                .map(|_| ())
+                .with_context(|| format!("file not found: {}", path.display())),
+            None => self.inner.remove_file(path),
+        }
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<()> {
+        match self.key(path) {
+            Some(key) => {
+                if !key.is_empty() && self.with_pack(|pack| pack.is_dir(&key))? {
+                    bail!("directory not empty: {}", path.display());
+                }
+                Ok(())
+            }
+            None => self.inner.remove_dir(path),
+        }
+    }
+
+    fn trash(&self, path: &Path) -> Result<PathBuf> {
+        match self.key(path) {
+            Some(key) => {
+                // Trashing needs a real file to move
+                if let Some(parent) = path.parent() {
+                    self.inner.create_dir_all(parent)?;
+                }
+                self.unpack_to(&key, path, path)?;
+                let trashed = self.inner.trash(path)?;
+                self.with_pack(|pack| pack.remove(&key))?;
+                Ok(trashed)
+            }
+            None => self.inner.trash(path),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        match (self.key(from), self.key(to)) {
+            (Some(from_key), Some(to_key)) => {
+                let (content, mode) = self.packed(&from_key, from)?;
+                self.with_pack(|pack| {
+                    pack.remove(&from_key);
+                    pack.put(&to_key, &content, mode);
+                })
+            }
+            (Some(key), None) => {
+                self.unpack_to(&key, from, to)?;
+                self.with_pack(|pack| pack.remove(&key)).map(|_| ())
+            }
+            (None, Some(key)) => {
+                self.pack_from(from, &key)?;
+                self.inner.remove_file(from)
+            }
+            (None, None) => self.inner.rename(from, to),
+        }
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        match self.key(path) {
+            // Directories in the pack are implied by the files in them
+            Some(_) => Ok(()),
+            None => self.inner.create_dir_all(path),
+        }
+    }
+
+    fn file_mode(&self, path: &Path) -> Result<u32> {
+        match self.key(path) {
+            Some(key) => Ok(self.packed(&key, path)?.1),
+            None => self.inner.file_mode(path),
+        }
+    }
+
+    fn set_file_mode(&self, path: &Path, mode: u32) -> Result<()> {
+        match self.key(path) {
+            Some(key) => self
+                .with_pack(|pack| {
+                    let entry = pack.index.files.get_mut(&key)?;
+                    entry.mode = mode;
+                    pack.dirty = true;
+                    Some(())
+                })?
+                .with_context(|| format!("file not found: {}", path.display())),
+            None => self.inner.set_file_mode(path, mode),
+        }
+    }
+
+    fn xattrs(&self, path: &Path) -> Result<Xattrs> {
+        match self.key(path) {
+            Some(_) => Ok(Vec::new()),
+            None => self.inner.xattrs(path),
+        }
+    }
+
+    /// The pack has nowhere to keep extended attributes, so files in it
+    /// drop them.
+    fn set_xattrs(&self, path: &Path, attrs: &Xattrs) -> Result<()> {
+        match self.key(path) {
+            Some(_) => Ok(()),
+            None => self.inner.set_xattrs(path, attrs),
+        }
+    }
+
+    fn stamp(&self, path: &Path) -> Result<FileStamp> {
+        match self.key(path) {
+            Some(key) => self
+                .with_pack(|pack| {
+                    pack.index.files.get(&key).map(|e| FileStamp {
+                        modified_ns: e.modified_ns,
+                        len: e.len,
+                    })
+                })?
+                .with_context(|| format!("file not found: {}", path.display())),
+            None => self.inner.stamp(path),
+        }
+    }
+
+    fn symlink(&self, original: &Path, link: &Path) -> Result<()> {
+        if self.key(link).is_some() {
+            bail!("the packed store can't hold symlinks: {}", link.display());
+        }
+        self.inner.symlink(original, link)
+    }
+
+    fn hard_link(&self, original: &Path, link: &Path) -> Result<()> {
+        if self.key(original).is_some() || self.key(link).is_some() {
+            bail!("the packed store can't hold hard links: {}", link.display());
+        }
+        self.inner.hard_link(original, link)
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf> {
+        if self.key(path).is_some() {
+            bail!("not a symlink: {}", path.display());
+        }
+        self.inner.read_link(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        let (Some(dir), Some(key)) = (&self.dir, self.key(path)) else {
+            return self.inner.canonicalize(path);
+        };
+        if !self.exists(path) {
+            bail!("file not found: {}", path.display());
+        }
+        let base = match (dir.parent(), dir.file_name()) {
+            (Some(parent), Some(name)) => self
+                .inner
+                .canonicalize(parent)
+                .map(|p| p.join(name))
+                .unwrap_or_else(|_| dir.clone()),
+            _ => dir.clone(),
+        };
+        Ok(if key.is_empty() { base } else { base.join(key) })
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        match self.key(path) {
+            Some(key) => self
+                .with_pack(|pack| pack.index.files.contains_key(&key) || pack.is_dir(&key))
+                .unwrap_or(false),
+            None => self.inner.exists(path),
+        }
+    }
+
+    fn is_symlink(&self, path: &Path) -> bool {
+        match self.key(path) {
+            Some(_) => false,
+            None => self.inner.is_symlink(path),
+        }
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        match self.key(path) {
+            Some(key) => self
+                .with_pack(|pack| pack.index.files.contains_key(&key))
+                .unwrap_or(false),
+            None => self.inner.is_file(path),
+        }
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        match self.key(path) {
+            Some(key) => self.with_pack(|pack| pack.is_dir(&key)).unwrap_or(false),
+            None => self.inner.is_dir(path),
+        }
+    }
+
+    fn walk_dir(&self, path: &Path, opts: &WalkOptions) -> Result<Vec<DirEntry>> {
+        let Some(dir) = &self.dir else {
+            return self.inner.walk_dir(path, opts);
+        };
+        if path.starts_with(dir) {
+            return self.walk_packed(path, opts);
+        }
+        let mut entries = self.inner.walk_dir(path, opts)?;
+        if dir.starts_with(path) {
+            // The packed directory sits somewhere under this walk
+            entries.extend(self.walk_packed(path, opts)?);
+            sort_entries(&mut entries, opts.contents_first);
+        }
+        Ok(entries)
+    }
+
+    fn home_dir(&self) -> Option<PathBuf> {
+        self.inner.home_dir()
+    }
+
+    fn config_dir(&self) -> Option<PathBuf> {
+        self.inner.config_dir()
+    }
+
+    fn state_dir(&self) -> Option<PathBuf> {
+        self.inner.state_dir()
+    }
+
+    fn cache_dir(&self) -> Option<PathBuf> {
+        self.inner.cache_dir()
+    }
+
+    fn hostname(&self) -> Option<String> {
+        self.inner.hostname()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::FakeFs;
+
+    const DIR: &str = "/home/test/dotfiles/.generated";
+
+    #[test]
+    fn serves_generated_paths_from_one_pack() {
+        let fake = FakeFs::new("/home/test");
+        fake.add_file("/home/test/dotfiles/a.conf", "source");
+        {
+            let fs = PackedFs::new(&fake, Some(PathBuf::from(DIR)));
+            let a = Path::new(DIR).join("hypr/a.conf");
+            fs.create_dir_all(a.parent().unwrap()).unwrap();
+            fs.write(&a, b"first version").unwrap();
+            fs.write(&a, b"two").unwrap();
+            fs.copy(
+                Path::new("/home/test/dotfiles/a.conf"),
+                &Path::new(DIR).join("b.conf"),
+            )
+            .unwrap();
+            assert_eq!(fs.read_to_string(&a).unwrap(), "two");
+            assert!(fs.is_dir(&Path::new(DIR).join("hypr")));
+            assert!(!fake.exists(Path::new(DIR)));
+
+            let walked: Vec<_> = fs
+                .walk_dir(Path::new(DIR), &WalkOptions::default())
+                .unwrap()
+                .into_iter()
+                .map(|e| (e.path.display().to_string(), e.is_file))
+                .collect();
+            assert_eq!(
+                walked,
+                vec![
+                    (DIR.to_string(), false),
+                    (format!("{DIR}/b.conf"), true),
+                    (format!("{DIR}/hypr"), false),
+                    (format!("{DIR}/hypr/a.conf"), true),
+                ]
+            );
+        }
+
+        // Dropping wrote the pack, compacted since the first version was
+        // more dead bytes than there were live ones
+        let pack = pack_path(Path::new(DIR));
+        assert_eq!(fake.read_to_string(&pack).unwrap(), "sourcetwo");
+        let fs = PackedFs::new(&fake, Some(PathBuf::from(DIR)));
+        let a = Path::new(DIR).join("hypr/a.conf");
+        assert_eq!(fs.read_to_string(&a).unwrap(), "two");
+        fs.copy(&a, Path::new("/home/test/.config/a.conf")).unwrap();
+        assert_eq!(
+            fake.read_to_string(Path::new("/home/test/.config/a.conf"))
+                .unwrap(),
+            "two"
+        );
+        fs.remove_file(&a).unwrap();
+        assert!(!fs.exists(&Path::new(DIR).join("hypr")));
+    }
+
+    #[test]
+    fn flush_appends_and_load_checks_the_index() {
+        let fake = FakeFs::new("/home/test");
+        let pack = pack_path(Path::new(DIR));
+        let a = Path::new(DIR).join("a.conf");
+        let b = Path::new(DIR).join("b.conf");
+        {
+            let fs = PackedFs::new(&fake, Some(PathBuf::from(DIR)));
+            fs.write(&a, b"aaaa").unwrap();
+            fs.flush().unwrap();
+            fs.write(&b, b"bb").unwrap();
+            fs.flush().unwrap();
+        }
+        assert_eq!(fake.read_to_string(&pack).unwrap(), "aaaabb");
+
+        // A flush that appended but never wrote its index leaves a tail,
+        // which the old index doesn't cover
+        fake.append(&pack, b"orphaned").unwrap();
+        {
+            let fs = PackedFs::new(&fake, Some(PathBuf::from(DIR)));
+            assert_eq!(fs.read_to_string(&b).unwrap(), "bb");
+            fs.write(&b, b"cc").unwrap();
+        }
+        assert_eq!(fake.read_to_string(&pack).unwrap(), "aaaabbcc");
+
+        // A pack rewritten under an index that doesn't describe it is
+        // refused rather than read at the wrong offsets
+        fake.write(&pack, b"bbaaaacc").unwrap();
+        let fs = PackedFs::new(&fake, Some(PathBuf::from(DIR)));
+        let err = fs.read(&a).unwrap_err();
+        assert!(format!("{err:#}").contains("doesn't match"), "got: {err:#}");
+    }
+}
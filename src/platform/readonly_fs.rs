@@ -0,0 +1,203 @@
+//! Read-only filesystem guard for `--readonly`.
+//!
+//! Wraps another [`Fs`] and, when enabled, refuses every call that would
+//! change the filesystem. Unlike `--dry-run`, which each command honors on
+//! its own, this is enforced here, so no code path can write by accident.
+
+use anyhow::{Result, bail};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use super::{DirEntry, FileStamp, Fs, WalkOptions, Xattrs};
+
+/// An [`Fs`] that, when `readonly` is set, fails every mutating call and
+/// logs what it refused. Reads and queries pass straight through.
+pub struct ReadonlyFs<F> {
+    inner: F,
+    readonly: bool,
+}
+
+impl<F: Fs> ReadonlyFs<F> {
+    pub fn new(inner: F, readonly: bool) -> Self {
+        Self { inner, readonly }
+    }
+
+    /// Refuse `action` on `path` in read-only mode.
+    fn guard(&self, action: &str, path: &Path) -> Result<()> {
+        if self.readonly {
+            warn!("Read-only mode: refused to {action} {}", path.display());
+            bail!("read-only mode: refused to {action} {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+impl<F: Fs> Fs for ReadonlyFs<F> {
+    fn read_to_string(&self, path: &Path) -> Result<String> {
+        self.inner.read_to_string(path)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.inner.read(path)
+    }
+
+    fn hash_file(&self, path: &Path) -> Result<String> {
+        self.inner.hash_file(path)
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        self.guard("write", path)?;
+        self.inner.write(path, contents)
+    }
+
+    fn append(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        self.guard("append to", path)?;
+        self.inner.append(path, contents)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.guard("copy to", to)?;
+        self.inner.copy(from, to)
+    }
+
+    fn copy_streaming(&self, from: &Path, to: &Path) -> Result<()> {
+        self.guard("copy to", to)?;
+        self.inner.copy_streaming(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        self.guard("remove", path)?;
+        self.inner.remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<()> {
+        self.guard("remove", path)?;
+        self.inner.remove_dir(path)
+    }
+
+    fn trash(&self, path: &Path) -> Result<PathBuf> {
+        self.guard("trash", path)?;
+        self.inner.trash(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.guard("rename", from)?;
+        self.inner.rename(from, to)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        self.guard("create", path)?;
+        self.inner.create_dir_all(path)
+    }
+
+    fn file_mode(&self, path: &Path) -> Result<u32> {
+        self.inner.file_mode(path)
+    }
+
+    fn set_file_mode(&self, path: &Path, mode: u32) -> Result<()> {
+        self.guard("change permissions of", path)?;
+        self.inner.set_file_mode(path, mode)
+    }
+
+    fn stamp(&self, path: &Path) -> Result<FileStamp> {
+        self.inner.stamp(path)
+    }
+
+    fn xattrs(&self, path: &Path) -> Result<Xattrs> {
+        self.inner.xattrs(path)
+    }
+
+    fn set_xattrs(&self, path: &Path, attrs: &Xattrs) -> Result<()> {
+        self.guard("change extended attributes of", path)?;
+        self.inner.set_xattrs(path, attrs)
+    }
+
+    fn symlink(&self, original: &Path, link: &Path) -> Result<()> {
+        self.guard("create symlink", link)?;
+        self.inner.symlink(original, link)
+    }
+
+    fn hard_link(&self, original: &Path, link: &Path) -> Result<()> {
+        self.guard("create hard link", link)?;
+        self.inner.hard_link(original, link)
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf> {
+        self.inner.read_link(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        self.inner.canonicalize(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.inner.exists(path)
+    }
+
+    fn is_symlink(&self, path: &Path) -> bool {
+        self.inner.is_symlink(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.inner.is_file(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.inner.is_dir(path)
+    }
+
+    fn walk_dir(&self, path: &Path, opts: &WalkOptions) -> Result<Vec<DirEntry>> {
+        self.inner.walk_dir(path, opts)
+    }
+
+    fn home_dir(&self) -> Option<PathBuf> {
+        self.inner.home_dir()
+    }
+
+    fn config_dir(&self) -> Option<PathBuf> {
+        self.inner.config_dir()
+    }
+
+    fn state_dir(&self) -> Option<PathBuf> {
+        self.inner.state_dir()
+    }
+
+    fn cache_dir(&self) -> Option<PathBuf> {
+        self.inner.cache_dir()
+    }
+
+    fn hostname(&self) -> Option<String> {
+        self.inner.hostname()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::FakeFs;
+
+    #[test]
+    fn refuses_writes_when_readonly() {
+        let fake = FakeFs::new("/home/test");
+        fake.add_file("/home/test/a", "a");
+        let fs = ReadonlyFs::new(fake, true);
+        assert_eq!(fs.read_to_string(Path::new("/home/test/a")).unwrap(), "a");
+
+        let err = fs.write(Path::new("/home/test/a"), b"b").unwrap_err();
+        assert!(err.to_string().contains("read-only"), "got: {err}");
+        assert!(fs.remove_file(Path::new("/home/test/a")).is_err());
+        assert!(
+            fs.symlink(Path::new("/home/test/a"), Path::new("/home/test/b"))
+                .is_err()
+        );
+        assert_eq!(fs.read_to_string(Path::new("/home/test/a")).unwrap(), "a");
+        assert!(!fs.exists(Path::new("/home/test/b")));
+    }
+
+    #[test]
+    fn passes_writes_through_otherwise() {
+        let fs = ReadonlyFs::new(FakeFs::new("/home/test"), false);
+        fs.write(Path::new("/home/test/a"), b"b").unwrap();
+        assert_eq!(fs.read_to_string(Path::new("/home/test/a")).unwrap(), "b");
+    }
+}
@@ -0,0 +1,53 @@
+//! Real command runner implementation spawning processes via `sh -c`.
+
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use super::{CommandOutput, CommandRunner};
+
+/// Real command runner — runs command lines through `sh -c`.
+pub struct RealCommandRunner;
+
+impl CommandRunner for RealCommandRunner {
+    fn run(&self, command: &str, stdin: Option<&str>) -> Result<CommandOutput> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(if stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run `{command}`"))?;
+
+        // Feed stdin from a separate thread so a command that writes output
+        // before consuming all of its input can't deadlock on a full pipe.
+        let writer = match (stdin, child.stdin.take()) {
+            (Some(input), Some(mut pipe)) => {
+                let input = input.to_string();
+                Some(std::thread::spawn(move || pipe.write_all(input.as_bytes())))
+            }
+            _ => None,
+        };
+
+        let output = child
+            .wait_with_output()
+            .with_context(|| format!("Failed to wait for `{command}`"))?;
+
+        if let Some(writer) = writer {
+            // A command may legitimately exit without reading all of stdin
+            // (broken pipe); its exit status is what matters.
+            let _ = writer.join();
+        }
+
+        Ok(CommandOutput {
+            success: output.status.success(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+}
@@ -4,12 +4,16 @@
 //! Methods return bare errors without added context — callers add their own
 //! `.with_context()` messages for domain-specific error descriptions.
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 use walkdir::WalkDir;
 
-use super::{DirEntry, Fs, WalkOptions};
+use super::{DirEntry, FileStamp, Fs, WalkOptions, Xattrs, xdg_trash};
+use crate::state::content_hash_reader;
 
 /// Real filesystem — delegates every operation to the OS.
 pub struct RealFs;
@@ -23,15 +27,38 @@ impl Fs for RealFs {
         Ok(std::fs::read(path)?)
     }
 
+    fn hash_file(&self, path: &Path) -> Result<String> {
+        Ok(content_hash_reader(File::open(path)?)?)
+    }
+
     fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
         Ok(std::fs::write(path, contents)?)
     }
 
+    fn append(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+        file.write_all(contents)?;
+        Ok(file.sync_data()?)
+    }
+
     fn copy(&self, from: &Path, to: &Path) -> Result<()> {
         std::fs::copy(from, to)?;
         Ok(())
     }
 
+    fn copy_streaming(&self, from: &Path, to: &Path) -> Result<()> {
+        let mut source = File::open(from)?;
+        let mut dest = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(to)?;
+        if clone_file(&source, &dest) {
+            return Ok(());
+        }
+        copy_sparse(&mut source, &mut dest)
+    }
+
     fn remove_file(&self, path: &Path) -> Result<()> {
         Ok(std::fs::remove_file(path)?)
     }
@@ -40,6 +67,50 @@ impl Fs for RealFs {
         Ok(std::fs::remove_dir(path)?)
     }
 
+    fn trash(&self, path: &Path) -> Result<PathBuf> {
+        let trash_dir = dirs::data_dir()
+            .context("Cannot determine the data directory")?
+            .join("Trash");
+        std::fs::create_dir_all(trash_dir.join("files"))?;
+        std::fs::create_dir_all(trash_dir.join("info"))?;
+        let original = std::path::absolute(path)?;
+        let name = path
+            .file_name()
+            .with_context(|| format!("No file name: {}", path.display()))?
+            .to_string_lossy();
+        let deleted = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let mut n = 1;
+        loop {
+            let (info_path, trashed) = xdg_trash::slot(&trash_dir, &name, n);
+            n += 1;
+            // Creating the info file first claims the name
+            let mut info = match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&info_path)
+            {
+                Ok(info) => info,
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if trashed.symlink_metadata().is_ok() {
+                drop(info);
+                std::fs::remove_file(&info_path)?;
+                continue;
+            }
+            info.write_all(xdg_trash::trash_info(&original, deleted).as_bytes())?;
+            if let Err(e) = move_to_trash(path, &trashed) {
+                let _ = std::fs::remove_file(&info_path);
+                return Err(e);
+            }
+            return Ok(trashed);
+        }
+    }
+
     fn rename(&self, from: &Path, to: &Path) -> Result<()> {
         Ok(std::fs::rename(from, to)?)
     }
@@ -59,14 +130,39 @@ impl Fs for RealFs {
         )?)
     }
 
+    fn stamp(&self, path: &Path) -> Result<FileStamp> {
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+        Ok(FileStamp {
+            modified_ns: modified.as_nanos() as u64,
+            len: metadata.len(),
+        })
+    }
+
+    fn xattrs(&self, path: &Path) -> Result<Xattrs> {
+        read_xattrs(path)
+    }
+
+    fn set_xattrs(&self, path: &Path, attrs: &Xattrs) -> Result<()> {
+        write_xattrs(path, attrs)
+    }
+
     fn symlink(&self, original: &Path, link: &Path) -> Result<()> {
         Ok(std::os::unix::fs::symlink(original, link)?)
     }
 
+    fn hard_link(&self, original: &Path, link: &Path) -> Result<()> {
+        Ok(std::fs::hard_link(original, link)?)
+    }
+
     fn read_link(&self, path: &Path) -> Result<PathBuf> {
         Ok(std::fs::read_link(path)?)
     }
 
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        Ok(std::fs::canonicalize(path)?)
+    }
+
     fn exists(&self, path: &Path) -> bool {
         path.exists()
     }
@@ -92,8 +188,20 @@ impl Fs for RealFs {
             .follow_links(opts.follow_links)
             .contents_first(opts.contents_first);
 
+        // walkdir catches links back into the directories it's inside, but
+        // not links to a parent of the walk root, which it would walk in full
+        // before looping back around
+        let root = std::fs::canonicalize(path).ok();
         let entries = walker
             .into_iter()
+            .filter_entry(|e| {
+                let above_root = e.path_is_symlink()
+                    && e.file_type().is_dir()
+                    && root.as_ref().is_some_and(|root| {
+                        std::fs::canonicalize(e.path()).is_ok_and(|target| root.starts_with(target))
+                    });
+                !above_root
+            })
             .filter_map(|e| e.ok())
             .map(|e| {
                 let ft = e.file_type();
@@ -121,4 +229,182 @@ impl Fs for RealFs {
     fn config_dir(&self) -> Option<PathBuf> {
         dirs::config_dir()
     }
+
+    fn state_dir(&self) -> Option<PathBuf> {
+        // `dirs` only knows a state dir on Linux; use the XDG default elsewhere
+        dirs::state_dir().or_else(|| dirs::home_dir().map(|home| home.join(".local/state")))
+    }
+
+    fn cache_dir(&self) -> Option<PathBuf> {
+        dirs::cache_dir()
+    }
+
+    fn hostname(&self) -> Option<String> {
+        ["/proc/sys/kernel/hostname", "/etc/hostname"]
+            .iter()
+            .filter_map(|path| std::fs::read_to_string(path).ok())
+            .map(|name| name.trim().to_string())
+            .find(|name| !name.is_empty())
+    }
+}
+
+/// Rename `path` into the trash, copying it over when the trash is on
+/// another filesystem.
+fn move_to_trash(path: &Path, trashed: &Path) -> Result<()> {
+    match std::fs::rename(path, trashed) {
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            if path.is_symlink() {
+                std::os::unix::fs::symlink(std::fs::read_link(path)?, trashed)?;
+            } else {
+                std::fs::copy(path, trashed)?;
+            }
+            Ok(std::fs::remove_file(path)?)
+        }
+        result => Ok(result?),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_xattrs(path: &Path) -> Result<Xattrs> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: `path` is NUL-terminated and the buffer is `size` bytes long
+    let names =
+        xattr_buffer(|buf, size| unsafe { libc::listxattr(path.as_ptr(), buf.cast(), size) });
+    let names = match names {
+        Err(e) if e.raw_os_error() == Some(libc::ENOTSUP) => return Ok(Vec::new()),
+        names => names?,
+    };
+    let mut attrs = Vec::new();
+    for name in names.split(|&b| b == 0).filter(|name| !name.is_empty()) {
+        let cname = CString::new(name)?;
+        // SAFETY: as above, and `cname` is NUL-terminated
+        let value = xattr_buffer(|buf, size| unsafe {
+            libc::getxattr(path.as_ptr(), cname.as_ptr(), buf.cast(), size)
+        })
+        .with_context(|| format!("xattr {}", String::from_utf8_lossy(name)))?;
+        attrs.push((String::from_utf8_lossy(name).into_owned(), value));
+    }
+    Ok(attrs)
+}
+
+/// Run a syscall that fills a buffer of a size it reports when passed an
+/// empty one (`listxattr`, `getxattr`), retrying if the data grew between
+/// the two calls.
+#[cfg(target_os = "linux")]
+fn xattr_buffer(call: impl Fn(*mut u8, usize) -> libc::ssize_t) -> io::Result<Vec<u8>> {
+    loop {
+        let size = call(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = vec![0; size as usize];
+        let len = call(buf.as_mut_ptr(), buf.len());
+        if len >= 0 {
+            buf.truncate(len as usize);
+            return Ok(buf);
+        }
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn write_xattrs(path: &Path, attrs: &Xattrs) -> Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    for (name, value) in attrs {
+        let cname = CString::new(name.as_str())?;
+        // SAFETY: both strings are NUL-terminated and `value` outlives the call
+        let result = unsafe {
+            libc::setxattr(
+                path.as_ptr(),
+                cname.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error()).with_context(|| format!("xattr {name}"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_xattrs(_path: &Path) -> Result<Xattrs> {
+    Ok(Vec::new())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn write_xattrs(_path: &Path, _attrs: &Xattrs) -> Result<()> {
+    Ok(())
+}
+
+/// Make `dest` share `source`'s extents (a reflink). Returns `false` when
+/// the filesystem can't, e.g. ext4 or across filesystems.
+#[cfg(target_os = "linux")]
+fn clone_file(source: &File, dest: &File) -> bool {
+    use std::os::fd::AsRawFd;
+    // SAFETY: both descriptors stay open for the duration of the call
+    unsafe { libc::ioctl(dest.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn clone_file(_source: &File, _dest: &File) -> bool {
+    false
+}
+
+/// Stream `source` into the empty `dest`, copying only the data regions so
+/// holes stay holes. Falls back to a plain copy when the filesystem can't
+/// report holes.
+#[cfg(target_os = "linux")]
+fn copy_sparse(source: &mut File, dest: &mut File) -> Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let len = source.metadata()?.len();
+    // Extending the file leaves the gaps between data regions as holes
+    dest.set_len(len)?;
+    let fd = source.as_raw_fd();
+    let mut offset = 0;
+    while offset < len as libc::off_t {
+        // SAFETY: `fd` is open; lseek only moves the file offset
+        let data = unsafe { libc::lseek(fd, offset, libc::SEEK_DATA) };
+        if data < 0 {
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                // Only holes remain
+                Some(libc::ENXIO) => break,
+                Some(libc::EINVAL) if offset == 0 => {
+                    source.seek(SeekFrom::Start(0))?;
+                    io::copy(source, dest)?;
+                    return Ok(());
+                }
+                _ => return Err(err.into()),
+            }
+        }
+        // SAFETY: as above
+        let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+        if hole < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        source.seek(SeekFrom::Start(data as u64))?;
+        dest.seek(SeekFrom::Start(data as u64))?;
+        io::copy(&mut Read::by_ref(source).take((hole - data) as u64), dest)?;
+        offset = hole;
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_sparse(source: &mut File, dest: &mut File) -> Result<()> {
+    io::copy(source, dest)?;
+    Ok(())
 }
@@ -0,0 +1,27 @@
+//! Real HTTP client implementation using the `curl` CLI.
+
+use anyhow::{Context, Result, bail};
+use std::process::Command;
+
+use super::HttpClient;
+
+/// Real HTTP client — downloads with `curl`, following redirects.
+pub struct RealHttpClient;
+
+impl HttpClient for RealHttpClient {
+    fn get(&self, url: &str) -> Result<Vec<u8>> {
+        let output = Command::new("curl")
+            .args(["--fail", "--silent", "--show-error", "--location"])
+            .args(["--proto", "=https,http"])
+            .arg("--")
+            .arg(url)
+            .output()
+            .context("Failed to run `curl`. Is it installed?")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("Failed to download {url}: {}", stderr.trim());
+        }
+        Ok(output.stdout)
+    }
+}
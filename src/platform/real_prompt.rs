@@ -1,15 +1,18 @@
 //! Real prompter implementation using `dialoguer`.
 
 use anyhow::{Context, Result};
-use dialoguer::Select;
+use dialoguer::{Confirm, Input, MultiSelect, Password, Select};
 
 use super::Prompter;
+use crate::progress;
 
-/// Real prompter — delegates to `dialoguer::Select` for interactive terminal prompts.
+/// Real prompter — delegates to `dialoguer` for interactive terminal prompts.
+/// Any progress bar is hidden while a prompt is up.
 pub struct RealPrompter;
 
 impl Prompter for RealPrompter {
     fn select(&self, prompt: &str, items: &[&str], default: usize) -> Result<usize> {
+        let _paused = progress::pause();
         Select::new()
             .with_prompt(prompt)
             .items(items)
@@ -17,4 +20,44 @@ impl Prompter for RealPrompter {
             .interact()
             .context("Prompt interaction failed")
     }
+
+    fn confirm(&self, prompt: &str, default: bool) -> Result<bool> {
+        let _paused = progress::pause();
+        Confirm::new()
+            .with_prompt(prompt)
+            .default(default)
+            .interact()
+            .context("Prompt interaction failed")
+    }
+
+    fn input(&self, prompt: &str, default: Option<&str>) -> Result<String> {
+        let _paused = progress::pause();
+        let mut input = Input::<String>::new().with_prompt(prompt).allow_empty(true);
+        if let Some(default) = default {
+            input = input.default(default.to_string());
+        }
+        input.interact_text().context("Prompt interaction failed")
+    }
+
+    fn password(&self, prompt: &str) -> Result<String> {
+        let _paused = progress::pause();
+        Password::new()
+            .with_prompt(prompt)
+            .allow_empty_password(true)
+            .interact()
+            .context("Prompt interaction failed")
+    }
+
+    fn multi_select(&self, prompt: &str, items: &[&str], defaults: &[bool]) -> Result<Vec<usize>> {
+        let _paused = progress::pause();
+        let checked: Vec<bool> = (0..items.len())
+            .map(|i| defaults.get(i).copied().unwrap_or(false))
+            .collect();
+        MultiSelect::new()
+            .with_prompt(prompt)
+            .items(items)
+            .defaults(&checked)
+            .interact()
+            .context("Prompt interaction failed")
+    }
 }
@@ -15,6 +15,29 @@ impl SecretEngine for RealSecretEngine {
             other => bail!("Unknown secret engine: {other}"),
         }
     }
+
+    fn check(&self, engine: &str) -> Result<()> {
+        match engine {
+            "1password" => check_onepassword(),
+            other => bail!("Unknown secret engine: {other}"),
+        }
+    }
+}
+
+/// Check that the 1Password CLI is installed and signed in via `op whoami`.
+fn check_onepassword() -> Result<()> {
+    let output = Command::new("op")
+        .arg("whoami")
+        .output()
+        .context("Failed to run `op`. Is 1Password CLI installed?")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(
+            "not signed in to 1Password CLI (run `op signin`): {}",
+            stderr.trim()
+        );
+    }
+    Ok(())
 }
 
 /// Resolve a 1Password reference by calling `op read <reference>`.
@@ -0,0 +1,120 @@
+//! Layout of the freedesktop.org trash, shared by the real and fake
+//! [`Fs::trash`](super::Fs::trash) implementations.
+//!
+//! A trashed file lives at `Trash/files/<name>`, next to a
+//! `Trash/info/<name>.trashinfo` recording where it came from and when, so
+//! desktop file managers can show and restore it too. Names that are taken
+//! get a `.2`, `.3`, ... suffix.
+
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// The `(info, files)` paths for the `n`th candidate name of a file called
+/// `name` (1 is the name itself).
+pub(crate) fn slot(trash_dir: &Path, name: &str, n: usize) -> (PathBuf, PathBuf) {
+    let name = if n == 1 {
+        name.to_string()
+    } else {
+        format!("{name}.{n}")
+    };
+    (
+        trash_dir.join("info").join(format!("{name}.trashinfo")),
+        trash_dir.join("files").join(name),
+    )
+}
+
+/// The `.trashinfo` file belonging to a path under `Trash/files/`.
+pub(crate) fn info_path(trashed: &Path) -> Option<PathBuf> {
+    let name = trashed.file_name()?.to_str()?;
+    let trash_dir = trashed.parent()?.parent()?;
+    Some(trash_dir.join("info").join(format!("{name}.trashinfo")))
+}
+
+/// Contents of the `.trashinfo` file for `original`, deleted `deleted_secs`
+/// after the Unix epoch.
+///
+/// The spec asks for local time; without a timezone database we write UTC,
+/// which file managers only use for display.
+pub(crate) fn trash_info(original: &Path, deleted_secs: u64) -> String {
+    format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_path(original),
+        format_date(deleted_secs)
+    )
+}
+
+/// [`format_date`] with `-` for `:`, for file names, which can't hold a
+/// `:` on FAT or SMB.
+pub(crate) fn format_file_date(secs: u64) -> String {
+    format_date(secs).replace(':', "-")
+}
+
+/// Format seconds since the Unix epoch as `YYYY-MM-DDThh:mm:ss` (UTC).
+pub(crate) fn format_date(secs: u64) -> String {
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Percent-encode a path as the spec requires, leaving `/` and unreserved
+/// characters alone.
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+/// Convert days since the Unix epoch to a `(year, month, day)` date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(format_date(0), "1970-01-01T00:00:00");
+        assert_eq!(format_date(951_782_400), "2000-02-29T00:00:00");
+        assert_eq!(format_date(1_792_152_000), "2026-10-16T12:00:00");
+        assert_eq!(format_file_date(1_792_152_000), "2026-10-16T12-00-00");
+    }
+
+    #[test]
+    fn info_encodes_path() {
+        let info = trash_info(Path::new("/home/test/my file%.conf"), 0);
+        assert_eq!(
+            info,
+            "[Trash Info]\nPath=/home/test/my%20file%25.conf\nDeletionDate=1970-01-01T00:00:00\n"
+        );
+    }
+
+    #[test]
+    fn slots_and_info_paths_agree() {
+        let trash = Path::new("/home/test/.local/share/Trash");
+        let (info, file) = slot(trash, "a.conf", 2);
+        assert_eq!(file, trash.join("files/a.conf.2"));
+        assert_eq!(info_path(&file), Some(info));
+    }
+}
@@ -0,0 +1,170 @@
+//! Where a config may read sources from and deploy targets to.
+//!
+//! A config from a shared or team repo shouldn't be able to plant a symlink
+//! at `~/.ssh/authorized_keys` or link a file from outside the dotfiles
+//! directory. Three rules guard against that:
+//!
+//! - a `src` must be a relative path that stays inside `dotfiles_dir` (no
+//!   `..` components), checked when the config is loaded;
+//! - with `[policy] allowed_targets`, every target must be under one of the
+//!   listed directories;
+//! - a target under a sensitive path ([`SENSITIVE_TARGETS`] plus
+//!   `[policy] sensitive_targets`) needs `--allow-sensitive-targets`.
+//!
+//! The target rules are checked by deploy, for each target and its
+//! `path_maps` links, before anything is written there, and reported by
+//! `janus check`. Both the path as written and the one its existing
+//! directories resolve to are checked, so a symlinked directory can't lead
+//! a target into `~/.ssh` or out of `allowed_targets`. Templates are
+//! already confined: they can't read files, and the functions that run
+//! commands need `allow_exec`.
+
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::{Config, normalize_path};
+use crate::paths::expand_tilde;
+use crate::platform::Fs;
+
+/// Paths deploying into needs `--allow-sensitive-targets`: credentials,
+/// and what grants access to this machine or others.
+pub const SENSITIVE_TARGETS: &[&str] = &[
+    "~/.ssh",
+    "~/.gnupg",
+    "~/.aws",
+    "~/.kube",
+    "~/.netrc",
+    "~/.config/autostart",
+    "/etc",
+];
+
+/// Why `src` isn't allowed, if it isn't: it's absolute or climbs out of the
+/// dotfiles directory.
+pub fn src_problem(src: &str) -> Option<String> {
+    let path = Path::new(src);
+    if path.is_absolute() {
+        return Some(format!("{src}: src must be relative to dotfiles_dir"));
+    }
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Some(format!("{src}: src must not leave dotfiles_dir (`..`)"));
+    }
+    None
+}
+
+/// Why deploying to `target` isn't allowed, if it isn't.
+pub fn target_problem(config: &Config, target: &Path, fs: &impl Fs) -> Option<String> {
+    let target = normalize_path(target);
+    let resolved = resolve(&target, fs);
+    // Lexically, or once symlinked directories are followed
+    let under = |dir: &str| {
+        let dir = normalize_path(&expand_tilde(dir, fs));
+        target.starts_with(&dir) || resolved.starts_with(resolve(&dir, fs))
+    };
+    let policy = &config.policy;
+
+    let allowed = |dir: &str| {
+        let dir = normalize_path(&expand_tilde(dir, fs));
+        resolved.starts_with(resolve(&dir, fs))
+    };
+    if !policy.allowed_targets.is_empty() && !policy.allowed_targets.iter().any(|d| allowed(d)) {
+        return Some(format!(
+            "{} is outside [policy] allowed_targets",
+            target.display()
+        ));
+    }
+    if !config.allow_sensitive_targets
+        && let Some(dir) = SENSITIVE_TARGETS
+            .iter()
+            .copied()
+            .chain(policy.sensitive_targets.iter().map(String::as_str))
+            .find(|d| under(d))
+    {
+        return Some(format!(
+            "{} is under sensitive path {dir}; pass --allow-sensitive-targets to deploy there",
+            target.display()
+        ));
+    }
+    None
+}
+
+/// `path` with its deepest existing ancestor canonicalized, so symlinked
+/// directories on the way are followed. The last component is kept as is:
+/// a deployed target is itself a symlink into the dotfiles.
+fn resolve(path: &Path, fs: &impl Fs) -> PathBuf {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return path.to_path_buf();
+    };
+    let mut existing = parent;
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(canonical) = fs.canonicalize(existing) {
+            let mut resolved = canonical;
+            resolved.extend(rest.iter().rev());
+            resolved.push(name);
+            return resolved;
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(up), Some(component)) => {
+                rest.push(component);
+                existing = up;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn srcs_stay_inside_dotfiles_dir() {
+        assert_eq!(src_problem("hypr/hypr.conf"), None);
+        assert!(src_problem("../../other/file").is_some());
+        assert!(src_problem("a/../../b").is_some());
+        assert!(src_problem("/etc/passwd").is_some());
+    }
+
+    #[test]
+    fn targets_follow_allowlist_and_sensitive_paths() {
+        let fs = setup_fs();
+        let toml = format!(
+            "{}\n[policy]\nallowed_targets = [\"~/.config\", \"~/.ssh\"]\nsensitive_targets = [\"~/.config/secret\"]\n",
+            make_config_toml(&[])
+        );
+        let mut config = write_and_load_config(&fs, &toml);
+        let problem = |path: &str, config: &Config| target_problem(config, Path::new(path), &fs);
+
+        assert_eq!(problem("/home/test/.config/a.conf", &config), None);
+        let outside = problem("/home/test/.bashrc", &config).unwrap();
+        assert!(outside.contains("allowed_targets"), "got: {outside}");
+        let climbing = problem("/home/test/.config/../.bashrc", &config).unwrap();
+        assert!(climbing.contains("allowed_targets"), "got: {climbing}");
+        let ssh = problem("/home/test/.ssh/authorized_keys", &config).unwrap();
+        assert!(ssh.contains("--allow-sensitive-targets"), "got: {ssh}");
+        assert!(problem("/home/test/.config/secret/x", &config).is_some());
+
+        config.allow_sensitive_targets = true;
+        assert_eq!(problem("/home/test/.ssh/authorized_keys", &config), None);
+    }
+
+    #[test]
+    fn targets_through_symlinked_dirs_are_resolved() {
+        let fs = setup_fs();
+        fs.add_dir("/home/test/.ssh");
+        fs.add_dir("/home/test/elsewhere");
+        fs.add_symlink("/home/test/.config/keys", "/home/test/.ssh");
+        fs.add_symlink("/home/test/.config/out", "/home/test/elsewhere");
+        let toml = format!(
+            "{}\n[policy]\nallowed_targets = [\"~/.config\", \"~/.ssh\"]\n",
+            make_config_toml(&[])
+        );
+        let config = write_and_load_config(&fs, &toml);
+        let problem = |path: &str| target_problem(&config, Path::new(path), &fs);
+
+        let ssh = problem("/home/test/.config/keys/authorized_keys").unwrap();
+        assert!(ssh.contains("sensitive path ~/.ssh"), "got: {ssh}");
+        let outside = problem("/home/test/.config/out/new/a.conf").unwrap();
+        assert!(outside.contains("allowed_targets"), "got: {outside}");
+    }
+}
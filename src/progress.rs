@@ -0,0 +1,287 @@
+//! Progress bars for generate, stage, and deploy over many files.
+//!
+//! Ops don't draw anything themselves: they open a [`Phase`] naming the phase
+//! and how many files it covers, [`Phase::advance`] as each file starts, and
+//! drop it when done. Each is a `trace` event for the `janus::progress`
+//! target, so without [`layer`] installed they cost nothing and only show up
+//! at `-vv`. [`ProgressLayer`] turns them into one bar at a time, labelled
+//! with the phase and the current file.
+//!
+//! Console logs go through [`ConsoleWriter`], which hides the bar while each
+//! line is written, and prompts [`pause`] the bar so it doesn't draw over
+//! them.
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Target of every progress event.
+const TARGET: &str = "janus::progress";
+
+/// Phases covering fewer files than this get no bar; they'd only flash.
+const MIN_FILES: u64 = 10;
+
+/// One phase of work over `total` files. Dropping it ends the phase.
+pub struct Phase {
+    phase: &'static str,
+}
+
+/// Start `phase` over `total` files.
+pub fn phase(phase: &'static str, total: usize) -> Phase {
+    tracing::trace!(target: TARGET, action = "start", phase, total = total as u64);
+    Phase { phase }
+}
+
+impl Phase {
+    /// Mark the start of work on `item`.
+    pub fn advance(&self, item: &str) {
+        tracing::trace!(target: TARGET, action = "advance", phase = self.phase, item);
+    }
+}
+
+impl Drop for Phase {
+    fn drop(&mut self) {
+        tracing::trace!(target: TARGET, action = "finish", phase = self.phase);
+    }
+}
+
+/// Hide any bar until the returned guard drops, e.g. around a prompt.
+pub fn pause() -> Paused {
+    tracing::trace!(target: TARGET, action = "pause");
+    Paused(())
+}
+
+/// Guard returned by [`pause`].
+pub struct Paused(());
+
+impl Drop for Paused {
+    fn drop(&mut self) {
+        tracing::trace!(target: TARGET, action = "resume");
+    }
+}
+
+/// The bar being drawn, if any.
+struct Active {
+    phase: String,
+    total: u64,
+    position: u64,
+    item: String,
+    bar: Option<ProgressBar>,
+}
+
+type Shared = Arc<Mutex<Option<Active>>>;
+
+/// A [`Layer`] drawing a bar for each progress [`Phase`].
+pub struct ProgressLayer {
+    active: Shared,
+    hidden: bool,
+}
+
+/// Create the layer. Only install it when stderr is a terminal.
+pub fn layer() -> ProgressLayer {
+    ProgressLayer {
+        active: Arc::default(),
+        hidden: false,
+    }
+}
+
+impl ProgressLayer {
+    /// A writer for console logs that keeps them clear of the bar.
+    pub fn writer(&self) -> ConsoleWriter {
+        ConsoleWriter(self.active.clone())
+    }
+
+    fn bar(&self, active: &Active) -> ProgressBar {
+        let target = if self.hidden {
+            ProgressDrawTarget::hidden()
+        } else {
+            ProgressDrawTarget::stderr()
+        };
+        let bar = ProgressBar::with_draw_target(Some(active.total), target).with_style(
+            ProgressStyle::with_template("{prefix:>8} [{bar:30}] {pos}/{len} {wide_msg}")
+                .expect("valid template")
+                .progress_chars("=> "),
+        );
+        bar.set_prefix(active.phase.clone());
+        bar.set_position(active.position);
+        bar.set_message(active.item.clone());
+        bar
+    }
+}
+
+/// The fields of one progress event.
+#[derive(Default)]
+struct Fields {
+    action: String,
+    phase: String,
+    item: String,
+    total: u64,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "action" => self.action = value.to_string(),
+            "phase" => self.phase = value.to_string(),
+            "item" => self.item = value.to_string(),
+            _ => {}
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if field.name() == "total" {
+            self.total = value;
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.record_str(field, &format!("{value:?}"));
+    }
+}
+
+impl<S: Subscriber> Layer<S> for ProgressLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() != TARGET {
+            return;
+        }
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let mut active = self.active.lock().unwrap_or_else(PoisonError::into_inner);
+        match fields.action.as_str() {
+            "start" => {
+                if let Some(bar) = active.take().and_then(|a| a.bar) {
+                    bar.finish_and_clear();
+                }
+                if fields.total >= MIN_FILES {
+                    let mut started = Active {
+                        phase: fields.phase,
+                        total: fields.total,
+                        position: 0,
+                        item: String::new(),
+                        bar: None,
+                    };
+                    started.bar = Some(self.bar(&started));
+                    *active = Some(started);
+                }
+            }
+            "advance" => {
+                if let Some(active) = active.as_mut().filter(|a| a.phase == fields.phase) {
+                    active.position += 1;
+                    active.item = fields.item;
+                    if let Some(bar) = &active.bar {
+                        bar.set_position(active.position);
+                        bar.set_message(active.item.clone());
+                    }
+                }
+            }
+            "finish" => {
+                if active.as_ref().is_some_and(|a| a.phase == fields.phase)
+                    && let Some(bar) = active.take().and_then(|a| a.bar)
+                {
+                    bar.finish_and_clear();
+                }
+            }
+            "pause" => {
+                if let Some(bar) = active.as_mut().and_then(|a| a.bar.take()) {
+                    bar.finish_and_clear();
+                }
+            }
+            "resume" => {
+                if let Some(active) = active.as_mut()
+                    && active.bar.is_none()
+                {
+                    active.bar = Some(self.bar(active));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Writes console logs to stdout, hiding the bar while it does.
+#[derive(Clone, Default)]
+pub struct ConsoleWriter(Shared);
+
+impl Write for ConsoleWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let active = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        match active.as_ref().and_then(|a| a.bar.as_ref()) {
+            Some(bar) => bar.suspend(|| io::stdout().write(buf)),
+            None => io::stdout().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for ConsoleWriter {
+    type Writer = ConsoleWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::prelude::*;
+
+    fn hidden_layer() -> (ProgressLayer, Shared) {
+        let layer = ProgressLayer {
+            active: Arc::default(),
+            hidden: true,
+        };
+        let active = layer.active.clone();
+        (layer, active)
+    }
+
+    fn position(active: &Shared) -> Option<(String, u64, String, bool)> {
+        active
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|a| (a.phase.clone(), a.position, a.item.clone(), a.bar.is_some()))
+    }
+
+    #[test]
+    fn tracks_phases_and_pauses() {
+        let (layer, active) = hidden_layer();
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            {
+                let small = phase("stage", 3);
+                small.advance("a.conf");
+                assert_eq!(position(&active), None);
+            }
+
+            let deploy = phase("deploy", 20);
+            deploy.advance("a.conf");
+            deploy.advance("b.conf");
+            assert_eq!(
+                position(&active),
+                Some(("deploy".to_string(), 2, "b.conf".to_string(), true))
+            );
+            {
+                let _paused = pause();
+                assert_eq!(
+                    position(&active),
+                    Some(("deploy".to_string(), 2, "b.conf".to_string(), false))
+                );
+            }
+            assert_eq!(
+                position(&active).map(|p| p.3),
+                Some(true),
+                "bar is redrawn after the prompt"
+            );
+            drop(deploy);
+            assert_eq!(position(&active), None);
+        });
+    }
+}
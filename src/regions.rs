@@ -0,0 +1,166 @@
+//! Managed regions: the parts of a file janus owns when it shares the file
+//! with an application or the user.
+//!
+//! A region starts after a line containing `janus:begin managed` and ends
+//! before the next line containing `janus:end`, normally written as comments
+//! (`# janus:begin managed`, `// janus:end`). When a file's generated copy
+//! has regions, `stage` replaces only the regions of the staged copy and
+//! keeps everything outside them, so lines a program appends to its own
+//! config survive. `sync` and `status` likewise only count edits inside
+//! regions (see [`managed_view`]).
+
+use anyhow::{Result, bail};
+use std::borrow::Cow;
+use tracing::warn;
+
+/// Marker opening a managed region.
+pub const BEGIN: &str = "janus:begin managed";
+/// Marker closing a managed region.
+pub const END: &str = "janus:end";
+
+/// A file cut at its region markers: `outside[0]`, `regions[0]`,
+/// `outside[1]`, ... concatenate back to the file. Marker lines belong to
+/// `outside`.
+struct Split<'a> {
+    outside: Vec<&'a str>,
+    regions: Vec<&'a str>,
+}
+
+fn split(text: &str) -> Result<Split<'_>> {
+    let mut outside = Vec::new();
+    let mut regions = Vec::new();
+    let mut start = 0;
+    let mut pos = 0;
+    let mut open: Option<usize> = None;
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        let end = pos + line.len();
+        if line.contains(BEGIN) {
+            if let Some(opened) = open {
+                bail!(
+                    "line {}: `{BEGIN}` inside the region opened on line {}",
+                    i + 1,
+                    opened + 1
+                );
+            }
+            outside.push(&text[start..end]);
+            start = end;
+            open = Some(i);
+        } else if line.contains(END) {
+            if open.is_none() {
+                bail!("line {}: `{END}` without a `{BEGIN}` before it", i + 1);
+            }
+            regions.push(&text[start..pos]);
+            start = pos;
+            open = None;
+        }
+        pos = end;
+    }
+    if let Some(opened) = open {
+        bail!("line {}: `{BEGIN}` is never closed by `{END}`", opened + 1);
+    }
+    outside.push(&text[start..]);
+    Ok(Split { outside, regions })
+}
+
+/// Whether `text` marks any managed regions.
+pub fn has_regions(text: &str) -> bool {
+    text.contains(BEGIN)
+}
+
+/// `base` with the body of each of its regions replaced by the matching
+/// region of `donor`. Fails if either file's markers are malformed or they
+/// don't have the same number of regions.
+pub fn splice(base: &str, donor: &str) -> Result<String> {
+    let base = split(base)?;
+    let donor = split(donor)?;
+    if base.regions.len() != donor.regions.len() {
+        bail!(
+            "{} managed region(s) where {} were expected",
+            base.regions.len(),
+            donor.regions.len()
+        );
+    }
+    let mut out = String::new();
+    for (outside, region) in base.outside.iter().zip(&donor.regions) {
+        out.push_str(outside);
+        out.push_str(region);
+    }
+    out.push_str(base.outside.last().unwrap_or(&""));
+    Ok(out)
+}
+
+/// The staged copy as sync and status compare it against `generated`.
+///
+/// When `generated` has managed regions, that's `generated` with only its
+/// regions taken from `staged`, so edits outside them don't show up. If
+/// `staged` has lost or broken its markers, it's compared whole.
+pub fn managed_view<'a>(generated: &str, staged: &'a str) -> Cow<'a, str> {
+    if !has_regions(generated) {
+        return Cow::Borrowed(staged);
+    }
+    match splice(generated, staged) {
+        Ok(view) => Cow::Owned(view),
+        Err(e) => {
+            warn!("Comparing the whole staged copy, its managed regions don't line up: {e:#}");
+            Cow::Borrowed(staged)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENERATED: &str = "\
+# janus:begin managed
+font = 12
+# janus:end
+";
+
+    #[test]
+    fn splice_keeps_text_outside_regions() {
+        let staged = "\
+top = 1
+# janus:begin managed
+font = 10
+# janus:end
+appended = true
+";
+        assert_eq!(
+            splice(staged, GENERATED).unwrap(),
+            "top = 1\n# janus:begin managed\nfont = 12\n# janus:end\nappended = true\n"
+        );
+    }
+
+    #[test]
+    fn view_ignores_edits_outside_regions() {
+        let staged = "# janus:begin managed\nfont = 12\n# janus:end\nappended = true\n";
+        assert_eq!(managed_view(GENERATED, staged), GENERATED);
+
+        let edited = "# janus:begin managed\nfont = 14\n# janus:end\n";
+        assert_eq!(managed_view(GENERATED, edited), edited);
+    }
+
+    #[test]
+    fn malformed_markers_are_errors() {
+        let unclosed = "# janus:begin managed\nfont = 12\n";
+        let err = splice(unclosed, GENERATED).unwrap_err();
+        assert!(err.to_string().contains("never closed"), "got: {err}");
+
+        let stray = "font = 12\n// janus:end\n";
+        assert!(splice(stray, GENERATED).is_err());
+
+        // A staged copy that lost its markers is compared whole
+        assert_eq!(managed_view(GENERATED, "font = 12\n"), "font = 12\n");
+    }
+
+    #[test]
+    fn region_counts_must_match() {
+        let two = format!("{GENERATED}{GENERATED}");
+        let err = splice(&two, GENERATED).unwrap_err();
+        assert!(
+            err.to_string().contains("2 managed region(s)"),
+            "got: {err}"
+        );
+    }
+}
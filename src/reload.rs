@@ -0,0 +1,194 @@
+//! Built-in reload recipes for programs that can pick up a new config
+//! without restarting.
+//!
+//! An entry with `reload = "auto"` uses the recipe matching its target, if
+//! any; `reload = "<cmd>"` runs that command instead. Recipes first check
+//! that the program is running, so deploying while it's stopped isn't an
+//! error. The commands are batched with the systemd actions in
+//! [`crate::systemd::SystemdActions`] and run once at the end of a deploy.
+//!
+//! A hook with `debounce = true` runs after all the others, so a window
+//! manager restarts once at the end. Like any reload it runs once per
+//! distinct command line, so a recipe whose command names the target (tmux)
+//! still runs once for each target.
+
+use std::path::Path;
+
+use crate::config::FileEntry;
+use crate::platform::Fs;
+use crate::shell;
+
+/// How to reload one program.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Recipe {
+    /// Program name shown in messages.
+    pub program: &'static str,
+    /// Target paths under `~/` this recipe applies to. A trailing `/`
+    /// matches everything in that directory.
+    targets: &'static [&'static str],
+    /// Command that succeeds only while the program is running.
+    running: &'static str,
+    /// The reload command. `{target}` is replaced with the deployed path.
+    command: &'static str,
+}
+
+/// The built-in recipes.
+pub const RECIPES: &[Recipe] = &[
+    Recipe {
+        program: "hyprland",
+        targets: &[".config/hypr/"],
+        running: "pgrep -x Hyprland",
+        command: "hyprctl reload",
+    },
+    Recipe {
+        program: "sway",
+        targets: &[".config/sway/"],
+        running: "pgrep -x sway",
+        command: "swaymsg reload",
+    },
+    Recipe {
+        program: "waybar",
+        targets: &[".config/waybar/"],
+        running: "pgrep -x waybar",
+        command: "pkill -SIGUSR2 -x waybar",
+    },
+    Recipe {
+        program: "kitty",
+        targets: &[".config/kitty/"],
+        running: "pgrep -x kitty",
+        command: "pkill -SIGUSR1 -x kitty",
+    },
+    Recipe {
+        program: "tmux",
+        targets: &[".tmux.conf", ".config/tmux/"],
+        running: "tmux info",
+        command: "tmux source-file {target}",
+    },
+];
+
+impl Recipe {
+    /// Whether this recipe applies to a file deployed at `target_path`.
+    fn matches(&self, target_path: &Path, fs: &impl Fs) -> bool {
+        let Some(relative) = fs
+            .home_dir()
+            .and_then(|home| target_path.strip_prefix(home).ok().map(Path::to_path_buf))
+        else {
+            return false;
+        };
+        let relative = relative.to_string_lossy();
+        self.targets.iter().any(|t| match t.strip_suffix('/') {
+            Some(dir) => relative.starts_with(t) || relative == dir,
+            None => relative == *t,
+        })
+    }
+
+    /// The full shell command for a file deployed at `target_path`,
+    /// skipping the reload when the program isn't running.
+    pub fn command_line(&self, target_path: &Path) -> String {
+        let command = self
+            .command
+            .replace("{target}", &shell::quote(&target_path.to_string_lossy()));
+        format!("{} >/dev/null 2>&1 || exit 0; {command}", self.running)
+    }
+}
+
+/// The recipe for a file deployed at `target_path`, if any.
+pub fn recipe_for(target_path: &Path, fs: &impl Fs) -> Option<&'static Recipe> {
+    RECIPES.iter().find(|r| r.matches(target_path, fs))
+}
+
+/// What deploying `entry` to `target_path` should run, per its `reload`
+/// setting.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReloadAction {
+    /// Run this command.
+    Run(String),
+    /// Run this command once, after all the [`ReloadAction::Run`] ones.
+    Debounce(String),
+    /// `reload` is unset but `recipe` would apply; suggest it.
+    Suggest(&'static Recipe),
+    /// Nothing to do.
+    None,
+}
+
+/// Decide the reload action for `entry` deployed at `target_path`.
+pub fn action_for(entry: &FileEntry, target_path: &Path, fs: &impl Fs) -> ReloadAction {
+    let Some(hook) = &entry.reload else {
+        return match recipe_for(target_path, fs) {
+            Some(recipe) => ReloadAction::Suggest(recipe),
+            None => ReloadAction::None,
+        };
+    };
+    let command = match hook.command() {
+        "never" => return ReloadAction::None,
+        "auto" => match recipe_for(target_path, fs) {
+            Some(recipe) => recipe.command_line(target_path),
+            None => return ReloadAction::None,
+        },
+        command => command.to_string(),
+    };
+    if hook.debounce() {
+        ReloadAction::Debounce(command)
+    } else {
+        ReloadAction::Run(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ReloadHook;
+    use crate::test_helpers::*;
+
+    fn entry(reload: Option<&str>) -> FileEntry {
+        let mut entry: FileEntry = toml::from_str("src = \"tmux.conf\"").unwrap();
+        entry.reload = reload.map(ReloadHook::from);
+        entry
+    }
+
+    #[test]
+    fn recipes_match_by_target() {
+        let fs = setup_fs();
+        let program = |path: &str| recipe_for(Path::new(path), &fs).map(|r| r.program);
+        assert_eq!(
+            program("/home/test/.config/hypr/hyprland.conf"),
+            Some("hyprland")
+        );
+        assert_eq!(
+            program("/home/test/.config/waybar/style.css"),
+            Some("waybar")
+        );
+        assert_eq!(program("/home/test/.tmux.conf"), Some("tmux"));
+        assert_eq!(program("/home/test/.config/hyprpaper.conf"), None);
+        assert_eq!(program("/etc/sway/config"), None);
+    }
+
+    #[test]
+    fn action_follows_reload_setting() {
+        let fs = setup_fs();
+        let target = Path::new("/home/test/.tmux.conf");
+        assert_eq!(
+            action_for(&entry(Some("auto")), target, &fs),
+            ReloadAction::Run(
+                "tmux info >/dev/null 2>&1 || exit 0; tmux source-file /home/test/.tmux.conf"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            action_for(&entry(Some("my-reload")), target, &fs),
+            ReloadAction::Run("my-reload".to_string())
+        );
+        assert_eq!(
+            action_for(&entry(Some("never")), target, &fs),
+            ReloadAction::None
+        );
+        assert!(matches!(
+            action_for(&entry(None), target, &fs),
+            ReloadAction::Suggest(r) if r.program == "tmux"
+        ));
+        assert_eq!(
+            action_for(&entry(Some("auto")), Path::new("/home/test/.bashrc"), &fs),
+            ReloadAction::None
+        );
+    }
+}
@@ -4,65 +4,191 @@
 //! from external secret engines (e.g. 1Password CLI). Secret config files
 //! are parsed eagerly, but actual secret resolution is deferred until needed
 //! and cached so each unique reference is resolved at most once.
+//!
+//! References may contain Tera expressions (e.g. `op://{{ vault }}/db/password`)
+//! which are rendered with the file's merged vars before resolution.
+//!
+//! A secret may list `references` instead of one `reference`: they're tried
+//! in order and the first that resolves is used, so the same repo works on
+//! machines signed in to only one of several vaults.
+//!
+//! Each engine is checked once before its first lookup; if it's unavailable
+//! (not installed, not signed in) only the files that need its secrets
+//! fail. [`SkipSecrets`] answers every lookup with a placeholder instead,
+//! for `--skip-secrets`; stage refuses files holding one, so placeholders
+//! never reach a target.
 
 use anyhow::{Context, Result, bail};
+use schemars::JsonSchema;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::path::Path;
-use tracing::debug;
+use tracing::{debug, warn};
 
 use crate::platform::{Fs, SecretEngine};
+use crate::timings;
 
 /// A single secret entry from a secrets config file.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 pub struct SecretEntry {
     /// Template variable name this secret will be available as.
     pub name: String,
     /// Secret engine to use (e.g. "1password").
     pub engine: String,
     /// Engine-specific reference (e.g. "op://Private/foobar/password").
+    /// May contain Tera expressions resolved from vars.
+    /// Exactly one of `reference` and `references` must be set.
+    #[serde(default)]
     pub reference: String,
+    /// References to try in order instead of `reference`; the first that
+    /// resolves wins. Exactly one of `reference` and `references` must be
+    /// set.
+    #[serde(default)]
+    pub references: Vec<String>,
+}
+
+impl SecretEntry {
+    /// The references to try, in order.
+    pub fn candidates(&self) -> Vec<&str> {
+        if self.references.is_empty() {
+            vec![self.reference.as_str()]
+        } else {
+            self.references.iter().map(String::as_str).collect()
+        }
+    }
 }
 
 /// Top-level structure of a secrets TOML file.
-#[derive(Debug, Deserialize)]
-struct SecretsFile {
+#[derive(Debug, Deserialize, JsonSchema)]
+pub(crate) struct SecretsFile {
+    /// `[[secret]]` entries, one per template variable.
     #[serde(default)]
     secret: Vec<SecretEntry>,
 }
 
 /// Caching resolver that dispatches to a [`SecretEngine`] implementation.
 ///
-/// Caches resolved values by `"engine:reference"` so each unique
-/// secret is fetched at most once per generate run.
+/// Caches resolved values by `"engine:reference"` (using the rendered
+/// reference) so each unique secret is fetched at most once per generate run.
 pub struct SecretResolver {
     cache: HashMap<String, String>,
+    /// Engines checked so far, with the reason for each unavailable one.
+    engines: HashMap<String, Option<String>>,
+}
+
+impl Default for SecretResolver {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SecretResolver {
     pub fn new() -> Self {
         Self {
             cache: HashMap::new(),
+            engines: HashMap::new(),
         }
     }
 
     /// Resolve a secret entry, returning the cached value or fetching via the engine.
     pub fn resolve(&mut self, entry: &SecretEntry, engine: &impl SecretEngine) -> Result<String> {
-        let cache_key = format!("{}:{}", entry.engine, entry.reference);
+        let candidates = entry.candidates();
+        let cache_key = format!("{}:{}", entry.engine, candidates.join("|"));
         if let Some(cached) = self.cache.get(&cache_key) {
             debug!("Secret cache hit: {}", entry.name);
             return Ok(cached.clone());
         }
 
-        let value = engine
-            .resolve(&entry.engine, &entry.reference)
-            .with_context(|| format!("Failed to resolve secret '{}'", entry.name))?;
+        let _timing = timings::phase("secret", &entry.engine);
+        let unavailable = self.engines.entry(entry.engine.clone()).or_insert_with(|| {
+            let reason = engine.check(&entry.engine).err()?;
+            warn!(
+                "Secret engine '{}' is unavailable: {reason:#}. Files using its secrets \
+                     will fail; pass --skip-secrets to render placeholders instead",
+                entry.engine
+            );
+            Some(format!("{reason:#}"))
+        });
+        if let Some(reason) = unavailable {
+            bail!(
+                "Failed to resolve secret '{}': engine '{}' is unavailable: {reason}",
+                entry.name,
+                entry.engine
+            );
+        }
 
-        self.cache.insert(cache_key, value.clone());
-        Ok(value)
+        let mut failures = Vec::new();
+        for reference in &candidates {
+            match engine.resolve(&entry.engine, reference) {
+                Ok(value) => {
+                    if candidates.len() > 1 {
+                        debug!("Secret '{}' resolved from {reference}", entry.name);
+                    }
+                    self.cache.insert(cache_key, value.clone());
+                    return Ok(value);
+                }
+                Err(e) if candidates.len() == 1 => {
+                    return Err(e.context(format!("Failed to resolve secret '{}'", entry.name)));
+                }
+                Err(e) => {
+                    debug!("Secret '{}' not at {reference}: {e:#}", entry.name);
+                    failures.push(format!("\n  {reference}: {e:#}"));
+                }
+            }
+        }
+        bail!(
+            "Failed to resolve secret '{}' from any of its references:{}",
+            entry.name,
+            failures.concat()
+        )
     }
 }
 
+/// A [`SecretEngine`] that, when `skip` is set, never calls `inner` and
+/// resolves every reference to a placeholder naming it.
+pub struct SkipSecrets<E> {
+    inner: E,
+    skip: bool,
+}
+
+impl<E: SecretEngine> SkipSecrets<E> {
+    pub fn new(inner: E, skip: bool) -> Self {
+        Self { inner, skip }
+    }
+}
+
+impl<E: SecretEngine> SecretEngine for SkipSecrets<E> {
+    fn resolve(&self, engine: &str, reference: &str) -> Result<String> {
+        if self.skip {
+            return Ok(placeholder(reference));
+        }
+        self.inner.resolve(engine, reference)
+    }
+
+    fn check(&self, engine: &str) -> Result<()> {
+        if self.skip {
+            return Ok(());
+        }
+        self.inner.check(engine)
+    }
+}
+
+/// How every [`placeholder`] starts.
+const PLACEHOLDER_PREFIX: &str = "<skipped secret ";
+
+/// The value `--skip-secrets` renders in place of the secret at `reference`.
+pub fn placeholder(reference: &str) -> String {
+    format!("{PLACEHOLDER_PREFIX}{reference}>")
+}
+
+/// Whether `content` holds a [`placeholder`], i.e. was rendered with
+/// `--skip-secrets`.
+pub fn has_placeholder(content: &[u8]) -> bool {
+    content
+        .windows(PLACEHOLDER_PREFIX.len())
+        .any(|w| w == PLACEHOLDER_PREFIX.as_bytes())
+}
+
 /// Parse secret entries from one or more TOML files in the dotfiles directory.
 ///
 /// Missing files are silently skipped (consistent with `load_vars` behavior).
@@ -84,22 +210,67 @@ pub fn parse_secret_files(
             .with_context(|| format!("Failed to read secrets file: {}", path.display()))?;
         let secrets_file: SecretsFile = toml::from_str(&contents)
             .with_context(|| format!("Failed to parse secrets file: {}", path.display()))?;
+        for secret in &secrets_file.secret {
+            if secret.reference.is_empty() == secret.references.is_empty() {
+                bail!(
+                    "Secret '{}' in {} must set exactly one of `reference` or `references`",
+                    secret.name,
+                    path.display()
+                );
+            }
+        }
         entries.extend(secrets_file.secret);
     }
     Ok(entries)
 }
 
+/// Render any template expressions in a secret's reference using `vars`.
+///
+/// References without Tera syntax are returned unchanged.
+pub fn render_reference(
+    entry: &SecretEntry,
+    vars: &HashMap<String, toml::Value>,
+) -> Result<String> {
+    render_one(&entry.name, &entry.reference, vars)
+}
+
+/// Render one of secret `name`'s references using `vars`.
+fn render_one(name: &str, reference: &str, vars: &HashMap<String, toml::Value>) -> Result<String> {
+    if !reference.contains("{{") && !reference.contains("{%") {
+        return Ok(reference.to_string());
+    }
+    let mut context = tera::Context::new();
+    for (key, value) in vars {
+        context.insert(key, value);
+    }
+    let rendered = tera::Tera::one_off(reference, &context, false)
+        .with_context(|| format!("Failed to render reference for secret '{name}'"))?;
+    debug!("Rendered reference for secret '{name}'");
+    Ok(rendered)
+}
+
 /// Resolve all secret entries into a map of template variable name -> value.
 ///
-/// Uses the shared resolver for caching across calls.
+/// References are rendered with `vars` first, so the resolver caches by the
+/// rendered reference. Uses the shared resolver for caching across calls.
 pub fn resolve_secrets(
     entries: &[SecretEntry],
+    vars: &HashMap<String, toml::Value>,
     resolver: &mut SecretResolver,
     engine: &impl SecretEngine,
 ) -> Result<HashMap<String, toml::Value>> {
     let mut secrets = HashMap::new();
     for entry in entries {
-        let value = resolver.resolve(entry, engine)?;
+        let rendered = SecretEntry {
+            reference: render_reference(entry, vars)?,
+            references: entry
+                .references
+                .iter()
+                .map(|r| render_one(&entry.name, r, vars))
+                .collect::<Result<_>>()?,
+            ..entry.clone()
+        };
+        let value = resolver.resolve(&rendered, engine)?;
         secrets.insert(entry.name.clone(), toml::Value::String(value));
     }
     Ok(secrets)
@@ -124,6 +295,56 @@ pub fn check_conflicts(
     );
 }
 
+/// Secret values shorter than this aren't masked; replacing every `on` or
+/// `1` in a diff would hide more than it protects.
+const MIN_MASKED_LEN: usize = 4;
+
+/// Hides resolved secret values in text shown to the user (diffs, sync
+/// hunks), replacing each with `<secret NAME>`.
+///
+/// Multi-line values are also masked line by line, since line-oriented
+/// output never shows them whole. The default mask hides nothing.
+#[derive(Debug, Default)]
+pub struct SecretMask {
+    /// `(value, name)` pairs, longest value first so a secret containing
+    /// another is replaced whole.
+    values: Vec<(String, String)>,
+}
+
+impl SecretMask {
+    /// Mask `value` as secret `name`.
+    pub fn add(&mut self, name: &str, value: &str) {
+        let mut parts = vec![value];
+        if value.contains('\n') {
+            parts.extend(value.lines());
+        }
+        for part in parts {
+            let part = part.trim_end_matches(['\r', '\n']);
+            if part.len() < MIN_MASKED_LEN {
+                if !part.trim().is_empty() {
+                    debug!("Not masking secret '{name}': too short");
+                }
+                continue;
+            }
+            if !self.values.iter().any(|(v, _)| v == part) {
+                self.values.push((part.to_string(), name.to_string()));
+            }
+        }
+        self.values.sort_by_key(|(v, _)| std::cmp::Reverse(v.len()));
+    }
+
+    /// `text` with every known secret value replaced.
+    pub fn mask(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (value, name) in &self.values {
+            if text.contains(value.as_str()) {
+                text = text.replace(value.as_str(), &format!("<secret {name}>"));
+            }
+        }
+        text
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -202,6 +423,7 @@ mod tests {
             name: "test".to_string(),
             engine: "1password".to_string(),
             reference: "op://a".to_string(),
+            references: Vec::new(),
         };
         let result = resolver.resolve(&entry, &engine).unwrap();
         assert_eq!(result, "secret_value");
@@ -216,6 +438,7 @@ mod tests {
             name: "test".to_string(),
             engine: "1password".to_string(),
             reference: "op://a".to_string(),
+            references: Vec::new(),
         };
         let v1 = resolver.resolve(&entry, &engine).unwrap();
         // Remove from engine — should still get cached value
@@ -224,6 +447,76 @@ mod tests {
         assert_eq!(v1, v2);
     }
 
+    #[test]
+    fn resolver_checks_each_engine_once() {
+        let mut engine = FakeSecretEngine::new();
+        engine.set_unavailable("1password");
+        let mut resolver = SecretResolver::new();
+        let entry = |name: &str| SecretEntry {
+            name: name.to_string(),
+            engine: "1password".to_string(),
+            reference: format!("op://{name}"),
+            references: Vec::new(),
+        };
+        let err = resolver.resolve(&entry("a"), &engine).unwrap_err();
+        assert!(format!("{err:#}").contains("unavailable"), "got: {err:#}");
+
+        // Later lookups reuse the verdict rather than checking again
+        let engine = FakeSecretEngine::new();
+        assert!(resolver.resolve(&entry("b"), &engine).is_err());
+    }
+
+    #[test]
+    fn skip_secrets_renders_placeholders() {
+        let mut inner = FakeSecretEngine::new();
+        inner.set_unavailable("1password");
+        let engine = SkipSecrets::new(inner, true);
+        assert!(engine.check("1password").is_ok());
+        assert_eq!(
+            engine.resolve("1password", "op://a").unwrap(),
+            placeholder("op://a")
+        );
+    }
+
+    #[test]
+    fn mask_replaces_values_and_lines() {
+        let mut mask = SecretMask::default();
+        mask.add("token", "hunter22");
+        mask.add("token_prefix", "hunter");
+        mask.add("key", "-----BEGIN KEY-----\nabcdef\n-----END KEY-----\n");
+        mask.add("flag", "on");
+        assert_eq!(
+            mask.mask("token=hunter22 prefix=hunter mode=on"),
+            "token=<secret token> prefix=<secret token_prefix> mode=on"
+        );
+        assert_eq!(mask.mask("+abcdef\n"), "+<secret key>\n");
+        assert_eq!(SecretMask::default().mask("hunter22"), "hunter22");
+    }
+
+    #[test]
+    fn resolver_falls_back_through_references() {
+        let mut engine = FakeSecretEngine::new();
+        engine.add_secret("1password", "op://Personal/gh", "personal");
+        let mut resolver = SecretResolver::new();
+        let entry = SecretEntry {
+            name: "token".to_string(),
+            engine: "1password".to_string(),
+            reference: String::new(),
+            references: vec!["op://Work/gh".to_string(), "op://Personal/gh".to_string()],
+        };
+        assert_eq!(resolver.resolve(&entry, &engine).unwrap(), "personal");
+
+        let entry = SecretEntry {
+            references: vec!["op://Work/gh".to_string(), "op://Other/gh".to_string()],
+            ..entry
+        };
+        let err = format!("{:#}", resolver.resolve(&entry, &engine).unwrap_err());
+        assert!(
+            err.contains("op://Work/gh") && err.contains("op://Other/gh"),
+            "got: {err}"
+        );
+    }
+
     #[test]
     fn resolver_different_refs() {
         let mut engine = FakeSecretEngine::new();
@@ -234,11 +527,13 @@ mod tests {
             name: "a".to_string(),
             engine: "1password".to_string(),
             reference: "op://a".to_string(),
+            references: Vec::new(),
         };
         let entry_b = SecretEntry {
             name: "b".to_string(),
             engine: "1password".to_string(),
             reference: "op://b".to_string(),
+            references: Vec::new(),
         };
         assert_eq!(resolver.resolve(&entry_a, &engine).unwrap(), "val_a");
         assert_eq!(resolver.resolve(&entry_b, &engine).unwrap(), "val_b");
@@ -253,14 +548,69 @@ mod tests {
             name: "my_secret".to_string(),
             engine: "1password".to_string(),
             reference: "op://a".to_string(),
+            references: Vec::new(),
         }];
-        let map = resolve_secrets(&entries, &mut resolver, &engine).unwrap();
+        let map = resolve_secrets(&entries, &HashMap::new(), &mut resolver, &engine).unwrap();
         assert_eq!(
             map.get("my_secret"),
             Some(&toml::Value::String("secret_a".to_string()))
         );
     }
 
+    #[test]
+    fn render_reference_plain_unchanged() {
+        let entry = SecretEntry {
+            name: "a".to_string(),
+            engine: "1password".to_string(),
+            reference: "op://Vault/a".to_string(),
+            references: Vec::new(),
+        };
+        assert_eq!(
+            render_reference(&entry, &HashMap::new()).unwrap(),
+            "op://Vault/a"
+        );
+    }
+
+    #[test]
+    fn resolve_secrets_templated_reference() {
+        let mut engine = FakeSecretEngine::new();
+        engine.add_secret("1password", "op://Work/db/password", "work_pw");
+        engine.add_secret("1password", "op://Personal/db/password", "home_pw");
+        let mut resolver = SecretResolver::new();
+        let entries = vec![SecretEntry {
+            name: "db_pass".to_string(),
+            engine: "1password".to_string(),
+            reference: "op://{{ vault }}/db/password".to_string(),
+            references: Vec::new(),
+        }];
+
+        let mut vars = HashMap::new();
+        vars.insert("vault".to_string(), toml::Value::String("Work".to_string()));
+        let map = resolve_secrets(&entries, &vars, &mut resolver, &engine).unwrap();
+        assert_eq!(map["db_pass"].as_str(), Some("work_pw"));
+
+        // Same resolver, different vars: cache is keyed by the rendered reference
+        vars.insert(
+            "vault".to_string(),
+            toml::Value::String("Personal".to_string()),
+        );
+        let map = resolve_secrets(&entries, &vars, &mut resolver, &engine).unwrap();
+        assert_eq!(map["db_pass"].as_str(), Some("home_pw"));
+    }
+
+    #[test]
+    fn render_reference_undefined_var_errors() {
+        let entry = SecretEntry {
+            name: "db_pass".to_string(),
+            engine: "1password".to_string(),
+            reference: "op://{{ vault }}/db".to_string(),
+            references: Vec::new(),
+        };
+        let result = render_reference(&entry, &HashMap::new());
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(msg.contains("db_pass"), "got: {msg}");
+    }
+
     #[test]
     fn check_conflicts_none() {
         let mut vars = HashMap::new();
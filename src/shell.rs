@@ -0,0 +1,41 @@
+//! Quoting for commands janus builds and hands to `sh -c` (or prints for a
+//! POSIX shell to evaluate).
+
+/// Quote `s` as one POSIX shell word, leaving plain words untouched.
+///
+/// Anything outside a conservative safe set (including `~`, which the shell
+/// would expand) goes in single quotes, and the empty string becomes `''` so
+/// it still counts as an argument.
+pub(crate) fn quote(s: &str) -> String {
+    if !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c))
+    {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaves_plain_words_alone() {
+        assert_eq!(quote("foo@1.service"), "foo@1.service");
+        assert_eq!(
+            quote("/home/me/.config/a-b_c@1:2"),
+            "/home/me/.config/a-b_c@1:2"
+        );
+    }
+
+    #[test]
+    fn quotes_everything_else() {
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("~/x"), "'~/x'");
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("$(rm -rf ~)"), "'$(rm -rf ~)'");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+    }
+}
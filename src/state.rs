@@ -1,16 +1,35 @@
-//! Persistent state tracking for deployed symlinks and ignored import paths.
+//! Persistent state tracking for deployed symlinks, ignored import paths,
+//! and when each file was last generated and synced.
 //!
-//! State is stored in `.janus_state.toml` within the dotfiles directory.
+//! State is stored in `.janus_state.toml` in the state directory: the
+//! dotfiles directory, or with `xdg_state`, one under `$XDG_STATE_HOME`
+//! ([`Config::state_dir`]). [`migrate`] moves it when that setting changes.
 //! Both `deployed` and `ignored` vectors have companion `HashSet` indexes
-//! for O(1) lookups; add/remove methods keep both in sync.
+//! for O(1) lookups; add/remove methods keep both in sync. Ignored paths
+//! may also be glob patterns (`~/.config/Slack/**`), which are compiled
+//! alongside the index. Loading doesn't replay a deploy or undeploy a crash
+//! left in the [`crate::journal`]; commands that change anything do that
+//! first with [`journal::recover`].
 
 use anyhow::{Context, Result};
+use glob::{MatchOptions, Pattern};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
-use tracing::warn;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
 
+use crate::config::Config;
+use crate::journal;
 use crate::platform::Fs;
+use crate::status_cache::CACHE_FILE;
+use crate::timings::RunTimings;
+use crate::trash::TRASH_LOG;
+use crate::undo::UndoRecord;
+
+/// File name of the state file within the state directory.
+pub const STATE_FILE: &str = ".janus_state.toml";
 
 /// Structured recovery instructions emitted when a state save fails after a
 /// mutation has already been applied to the filesystem.
@@ -22,7 +41,7 @@ pub struct RecoveryInfo {
     pub situation: Vec<String>,
     /// What will be broken if not fixed (e.g. "janus will not know ... is deployed").
     pub consequence: Vec<String>,
-    /// Steps the user can take to fix it (e.g. "Add a [[deployed]] entry ...").
+    /// Steps the user can take to fix it (e.g. "Add a `[[deployed]]` entry ...").
     pub instructions: Vec<String>,
 }
 
@@ -35,6 +54,17 @@ pub struct State {
     /// Files currently deployed as symlinks.
     #[serde(default)]
     pub deployed: Vec<DeployedEntry>,
+    /// When each file last went through generate and sync, for spotting
+    /// stale pipelines in `janus status`.
+    #[serde(default)]
+    pub activity: Vec<ActivityEntry>,
+    /// Backups deploy made under `[backup] dir`, oldest first, for
+    /// `janus backups`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backups: Vec<BackupEntry>,
+    /// The last `--timings` run, for comparing against and `janus stats`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timings: Option<RunTimings>,
 
     /// Filesystem path to the state file (set on load, not serialized).
     #[serde(skip)]
@@ -42,6 +72,9 @@ pub struct State {
     /// O(1) lookup index for ignored paths.
     #[serde(skip)]
     ignored_index: HashSet<String>,
+    /// Ignored entries that are glob patterns, compiled.
+    #[serde(skip)]
+    ignored_patterns: Vec<Pattern>,
     /// O(1) lookup index for deployed src keys.
     #[serde(skip)]
     deployed_index: HashSet<String>,
@@ -59,10 +92,54 @@ pub struct IgnoredEntry {
 /// A file currently deployed as a symlink.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct DeployedEntry {
-    /// Relative source path within the dotfiles directory.
+    /// The file's key (see [`FileEntry::key`](crate::config::FileEntry::key)):
+    /// its `name`, else its relative source path.
     pub src: String,
     /// Target path where the symlink lives (may contain `~`).
     pub target: String,
+    /// Content hash of the source at deploy time. Only recorded for direct
+    /// files, whose source is edited in place through the symlink.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_hash: Option<String>,
+    /// Where deploy backed up the file that was at the target before janus
+    /// first replaced it (tilde-collapsed).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<String>,
+}
+
+/// A backup deploy made under `[backup] dir`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BackupEntry {
+    /// Relative source path of the file deployed over it.
+    pub src: String,
+    /// The path it was backed up from (tilde-collapsed).
+    pub target: String,
+    /// Where the backup is (tilde-collapsed).
+    pub path: String,
+    /// When it was made, in seconds since the Unix epoch.
+    pub at: u64,
+}
+
+/// Pipeline timestamps for one file, in seconds since the Unix epoch.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ActivityEntry {
+    /// Relative source path within the dotfiles directory.
+    pub src: String,
+    /// Last successful `generate`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_generate: Option<u64>,
+    /// Last time staged was reconciled with generated: a `stage`, which
+    /// overwrites it, or a `sync`, which merges it back into the source.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_sync: Option<u64>,
+    /// Permission bits the staged copy had at the last `stage`, which
+    /// `janus status` checks the pipeline copies against.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// Hunks skipped at the last `sync`, by [`content_hash`] of their
+    /// generated and staged lines, so later syncs don't ask again.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_hunks: Vec<String>,
 }
 
 impl State {
@@ -71,25 +148,36 @@ impl State {
     fn rebuild_indexes(&mut self) {
         self.ignored_index = self.ignored.iter().map(|e| e.path.clone()).collect();
         self.deployed_index = self.deployed.iter().map(|e| e.src.clone()).collect();
+        self.rebuild_patterns();
+    }
+
+    fn rebuild_patterns(&mut self) {
+        self.ignored_patterns = self
+            .ignored
+            .iter()
+            .filter_map(|e| ignore_pattern(&e.path))
+            .collect();
     }
 
-    /// Load state from `.janus_state.toml` in the given dotfiles directory.
+    /// Load state from `.janus_state.toml` in the given state directory.
     /// Returns a default empty state if the file doesn't exist yet.
-    pub fn load(dotfiles_dir: &Path, fs: &impl Fs) -> Result<Self> {
-        let path = dotfiles_dir.join(".janus_state.toml");
-        if !fs.exists(&path) {
-            return Ok(State {
+    pub fn load(state_dir: &Path, fs: &impl Fs) -> Result<Self> {
+        let path = state_dir.join(STATE_FILE);
+        let state = if fs.exists(&path) {
+            let contents = fs
+                .read_to_string(&path)
+                .with_context(|| format!("Failed to read state file: {}", path.display()))?;
+            let mut state: State =
+                toml::from_str(&contents).with_context(|| "Failed to parse state file")?;
+            state.path = path;
+            state.rebuild_indexes();
+            state
+        } else {
+            State {
                 path,
                 ..Default::default()
-            });
-        }
-        let contents = fs
-            .read_to_string(&path)
-            .with_context(|| format!("Failed to read state file: {}", path.display()))?;
-        let mut state: State =
-            toml::from_str(&contents).with_context(|| "Failed to parse state file")?;
-        state.path = path;
-        state.rebuild_indexes();
+            }
+        };
         Ok(state)
     }
 
@@ -125,9 +213,14 @@ impl State {
         Ok(())
     }
 
-    /// Check if a path has been marked as ignored (O(1) lookup).
+    /// Check if a path has been marked as ignored, exactly (O(1) lookup) or
+    /// by one of the ignored glob patterns.
     pub fn is_ignored(&self, path: &str) -> bool {
         self.ignored_index.contains(path)
+            || self
+                .ignored_patterns
+                .iter()
+                .any(|p| p.matches_with(path, IGNORE_MATCH))
     }
 
     /// Check if a source file is currently deployed (O(1) lookup).
@@ -138,6 +231,9 @@ impl State {
     /// Mark a path as ignored. No-op if already ignored.
     pub fn add_ignored(&mut self, path: String, reason: String) {
         if self.ignored_index.insert(path.clone()) {
+            if let Some(pattern) = ignore_pattern(&path) {
+                self.ignored_patterns.push(pattern);
+            }
             self.ignored.push(IgnoredEntry { path, reason });
         }
     }
@@ -145,12 +241,40 @@ impl State {
     /// Record a file as deployed. Updates the target if already tracked.
     pub fn add_deployed(&mut self, src: String, target: String) {
         if self.deployed_index.insert(src.clone()) {
-            self.deployed.push(DeployedEntry { src, target });
+            self.deployed.push(DeployedEntry {
+                src,
+                target,
+                source_hash: None,
+                backup: None,
+            });
         } else if let Some(entry) = self.deployed.iter_mut().find(|e| e.src == src) {
             entry.target = target;
         }
     }
 
+    /// Record the deploy-time source hash for a deployed file. No-op if not tracked.
+    pub fn set_source_hash(&mut self, src: &str, hash: String) {
+        if let Some(entry) = self.deployed.iter_mut().find(|e| e.src == src) {
+            entry.source_hash = Some(hash);
+        }
+    }
+
+    /// Record where a deployed file's original target was backed up. Keeps
+    /// the first path recorded; no-op if not tracked.
+    pub fn record_backup(&mut self, src: &str, path: String) {
+        if let Some(entry) = self.deployed.iter_mut().find(|e| e.src == src) {
+            entry.backup.get_or_insert(path);
+        }
+    }
+
+    /// The deploy-time source hash for a deployed file, if one was recorded.
+    pub fn source_hash(&self, src: &str) -> Option<&str> {
+        self.deployed
+            .iter()
+            .find(|e| e.src == src)
+            .and_then(|e| e.source_hash.as_deref())
+    }
+
     /// Remove a deployed entry by source path. No-op if not tracked.
     pub fn remove_deployed(&mut self, src: &str) {
         if self.deployed_index.remove(src) {
@@ -158,15 +282,222 @@ impl State {
         }
     }
 
+    /// The recorded pipeline timestamps for a file, if any.
+    pub fn activity(&self, src: &str) -> Option<&ActivityEntry> {
+        self.activity.iter().find(|e| e.src == src)
+    }
+
+    /// Record that a file was generated at `at`.
+    pub fn record_generate(&mut self, src: &str, at: u64) {
+        self.activity_mut(src).last_generate = Some(at);
+    }
+
+    /// Record that a file's staged copy was reconciled at `at`.
+    pub fn record_sync(&mut self, src: &str, at: u64) {
+        self.activity_mut(src).last_sync = Some(at);
+    }
+
+    /// Record the permission bits a file was staged with.
+    pub fn record_mode(&mut self, src: &str, mode: u32) {
+        self.activity_mut(src).mode = Some(mode & 0o7777);
+    }
+
+    /// The hunks of a file skipped at the last `sync`.
+    pub fn skipped_hunks(&self, src: &str) -> &[String] {
+        self.activity(src).map_or(&[], |a| &a.skipped_hunks)
+    }
+
+    /// Replace the hunks of a file remembered as skipped.
+    pub fn set_skipped_hunks(&mut self, src: &str, hunks: Vec<String>) {
+        if hunks.is_empty() && self.activity(src).is_none() {
+            return;
+        }
+        self.activity_mut(src).skipped_hunks = hunks;
+    }
+
+    /// Forget a file's pipeline timestamps. No-op if not tracked.
+    pub fn remove_activity(&mut self, src: &str) {
+        self.activity.retain(|e| e.src != src);
+    }
+
+    fn activity_mut(&mut self, src: &str) -> &mut ActivityEntry {
+        let index = match self.activity.iter().position(|e| e.src == src) {
+            Some(index) => index,
+            None => {
+                self.activity.push(ActivityEntry {
+                    src: src.to_string(),
+                    last_generate: None,
+                    last_sync: None,
+                    mode: None,
+                    skipped_hunks: Vec::new(),
+                });
+                self.activity.len() - 1
+            }
+        };
+        &mut self.activity[index]
+    }
+
     /// Remove an ignored entry by path. No-op if not tracked.
-    #[allow(dead_code)]
     pub fn remove_ignored(&mut self, path: &str) {
         if self.ignored_index.remove(path) {
             self.ignored.retain(|e| e.path != path);
+            if is_pattern(path) {
+                self.rebuild_patterns();
+            }
+        }
+    }
+
+    /// Stop ignoring `path`: remove its exact entry and every pattern that
+    /// matches it. Returns the removed entries' paths.
+    pub fn forget_ignored(&mut self, path: &str) -> Vec<String> {
+        let removed: Vec<String> = self
+            .ignored
+            .iter()
+            .filter(|e| {
+                e.path == path
+                    || ignore_pattern(&e.path).is_some_and(|p| p.matches_with(path, IGNORE_MATCH))
+            })
+            .map(|e| e.path.clone())
+            .collect();
+        for entry in &removed {
+            self.remove_ignored(entry);
         }
+        removed
     }
 }
 
+/// `*` and `?` in ignore patterns stay within one path component; `**`
+/// crosses directories.
+const IGNORE_MATCH: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Whether an ignored path is a glob pattern rather than a literal path.
+pub fn is_pattern(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// Compile an ignored path if it's a glob pattern. Invalid patterns are
+/// warned about and then only match literally.
+fn ignore_pattern(path: &str) -> Option<Pattern> {
+    if !is_pattern(path) {
+        return None;
+    }
+    match Pattern::new(path) {
+        Ok(pattern) => Some(pattern),
+        Err(e) => {
+            warn!("Ignored path {path} is not a valid glob pattern, matching it literally: {e}");
+            None
+        }
+    }
+}
+
+/// The current time in seconds since the Unix epoch, for [`ActivityEntry`]
+/// timestamps.
+pub fn unix_now() -> u64 {
+    unix_now_ns() / 1_000_000_000
+}
+
+/// The current time in nanoseconds since the Unix epoch, for modification
+/// stamps finer than a second.
+pub fn unix_now_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// Hash file content for change detection (64-bit FNV-1a, hex-encoded).
+///
+/// Not cryptographic; only used to notice that a file changed between runs.
+/// Stable across platforms and Rust versions, unlike `DefaultHasher`.
+pub fn content_hash(bytes: &[u8]) -> String {
+    format!("{:016x}", fnv1a(FNV_OFFSET, bytes))
+}
+
+/// [`content_hash`] of everything `reader` yields, read a chunk at a time
+/// so large files never sit in memory whole.
+pub fn content_hash_reader(mut reader: impl Read) -> io::Result<String> {
+    let mut hash = FNV_OFFSET;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(format!("{hash:016x}")),
+            Ok(n) => hash = fnv1a(hash, &buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Continue an FNV-1a hash over `bytes`.
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Move janus's own files to where `xdg_state` says they belong.
+///
+/// Runs when the state directory has no state file but the other location
+/// (the dotfiles directory, or the XDG directories after `xdg_state` is
+/// turned off) does. The state file, journal, and trash log move to
+/// [`Config::state_dir`] and the status cache to [`Config::cache_dir`].
+/// The undo record is dropped: it restores the state file at its old path.
+/// Returns whether anything moved.
+pub fn migrate(config: &Config, fs: &impl Fs) -> Result<bool> {
+    let state_dir = config.state_dir(fs);
+    let cache_dir = config.cache_dir(fs);
+    let (from_state, from_cache) = if config.xdg_state {
+        (config.dotfiles_dir(fs), config.dotfiles_dir(fs))
+    } else {
+        (config.xdg_state_dir(fs), config.xdg_cache_dir(fs))
+    };
+    if fs.exists(&state_dir.join(STATE_FILE)) || !fs.exists(&from_state.join(STATE_FILE)) {
+        return Ok(false);
+    }
+    info!(
+        "Moving janus state from {} to {}",
+        from_state.display(),
+        state_dir.display()
+    );
+    fs.create_dir_all(&state_dir)
+        .with_context(|| format!("Failed to create {}", state_dir.display()))?;
+    // The state file goes last, so an interrupted move is picked up again
+    for name in [journal::JOURNAL_FILE, TRASH_LOG, STATE_FILE] {
+        move_file(&from_state.join(name), &state_dir.join(name), fs)?;
+    }
+    if fs.exists(&from_cache.join(CACHE_FILE)) {
+        fs.create_dir_all(&cache_dir)
+            .with_context(|| format!("Failed to create {}", cache_dir.display()))?;
+        move_file(
+            &from_cache.join(CACHE_FILE),
+            &cache_dir.join(CACHE_FILE),
+            fs,
+        )?;
+    }
+    UndoRecord::clear(&from_state, fs)?;
+    Ok(true)
+}
+
+/// Move `from` to `to` if it exists, copying when a rename can't cross
+/// filesystems.
+pub(crate) fn move_file(from: &Path, to: &Path, fs: &impl Fs) -> Result<()> {
+    if !fs.exists(from) || fs.rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs.copy(from, to)
+        .with_context(|| format!("Failed to copy {} to {}", from.display(), to.display()))?;
+    fs.remove_file(from)
+        .with_context(|| format!("Failed to remove {}", from.display()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -177,6 +508,35 @@ mod tests {
         State::load(Path::new(DOTFILES), fs).unwrap()
     }
 
+    #[test]
+    fn migrate_follows_xdg_state_both_ways() {
+        let fs = setup_fs();
+        fs.add_file(
+            format!("{DOTFILES}/{STATE_FILE}"),
+            "[[deployed]]\nsrc = \"a.conf\"\ntarget = \"~/.config/a.conf\"\n",
+        );
+        fs.add_file(format!("{DOTFILES}/{TRASH_LOG}"), "");
+        fs.add_file(format!("{DOTFILES}/{CACHE_FILE}"), "");
+        let toml = format!("xdg_state = true\n{}", make_config_toml(&[]));
+        let config = write_and_load_config(&fs, &toml);
+
+        assert!(migrate(&config, &fs).unwrap());
+        let state_dir = config.state_dir(&fs);
+        assert!(State::load(&state_dir, &fs).unwrap().is_deployed("a.conf"));
+        assert!(fs.exists(&state_dir.join(TRASH_LOG)));
+        assert!(fs.exists(&config.cache_dir(&fs).join(CACHE_FILE)));
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/{STATE_FILE}"))));
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/{CACHE_FILE}"))));
+        // Nothing left to move
+        assert!(!migrate(&config, &fs).unwrap());
+
+        // Turning it off moves everything back
+        let config = write_and_load_config(&fs, &make_config_toml(&[]));
+        assert!(migrate(&config, &fs).unwrap());
+        assert!(load_state(&fs).is_deployed("a.conf"));
+        assert!(!fs.exists(&state_dir.join(STATE_FILE)));
+    }
+
     #[test]
     fn load_missing_returns_default() {
         let fs = FakeFs::new(HOME);
@@ -294,6 +654,39 @@ reason = "user_declined"
         assert!(state.ignored.is_empty());
     }
 
+    #[test]
+    fn ignored_patterns_match_and_forget() {
+        let mut state = State::default();
+        state.add_ignored(
+            "~/.config/Slack/**".to_string(),
+            "user_declined".to_string(),
+        );
+        state.add_ignored("~/.cache/*.log".to_string(), "user_declined".to_string());
+        assert!(state.is_ignored("~/.config/Slack/storage/root-state.json"));
+        assert!(state.is_ignored("~/.cache/app.log"));
+        assert!(!state.is_ignored("~/.cache/app/debug.log"));
+        assert!(!state.is_ignored("~/.config/Slacker/settings"));
+
+        let removed = state.forget_ignored("~/.config/Slack/Preferences");
+        assert_eq!(removed, vec!["~/.config/Slack/**"]);
+        assert!(!state.is_ignored("~/.config/Slack/storage/root-state.json"));
+        assert!(state.is_ignored("~/.cache/app.log"));
+    }
+
+    #[test]
+    fn ignored_patterns_survive_reload() {
+        let fs = setup_fs();
+        let mut state = load_state(&fs);
+        state.add_ignored(
+            "~/.config/Slack/**".to_string(),
+            "user_declined".to_string(),
+        );
+        state.save(&fs).unwrap();
+
+        let reloaded = load_state(&fs);
+        assert!(reloaded.is_ignored("~/.config/Slack/Cookies"));
+    }
+
     #[test]
     fn is_ignored() {
         let mut state = State::default();
@@ -323,6 +716,50 @@ reason = "user_declined"
         assert!(state.is_deployed("d"));
     }
 
+    #[test]
+    fn source_hash_roundtrip() {
+        let fs = setup_fs();
+        let mut state = load_state(&fs);
+        state.add_deployed("a.conf".to_string(), "~/a.conf".to_string());
+        state.add_deployed("b.conf".to_string(), "~/b.conf".to_string());
+        state.set_source_hash("a.conf", content_hash(b"hello"));
+        // Untracked src is ignored
+        state.set_source_hash("c.conf", content_hash(b"x"));
+        state.save(&fs).unwrap();
+
+        let contents = fs
+            .read_to_string(Path::new(&format!("{DOTFILES}/.janus_state.toml")))
+            .unwrap();
+        assert_eq!(
+            contents.matches("source_hash").count(),
+            1,
+            "got: {contents}"
+        );
+        let reloaded = load_state(&fs);
+        assert_eq!(
+            reloaded.source_hash("a.conf"),
+            Some(content_hash(b"hello").as_str())
+        );
+        assert_eq!(reloaded.source_hash("b.conf"), None);
+        assert_eq!(reloaded.source_hash("c.conf"), None);
+    }
+
+    #[test]
+    fn content_hash_stable() {
+        assert_eq!(content_hash(b""), "cbf29ce484222325");
+        assert_eq!(content_hash(b"a"), "af63dc4c8601ec8c");
+        assert_ne!(content_hash(b"a"), content_hash(b"b"));
+    }
+
+    #[test]
+    fn content_hash_reader_matches_across_chunks() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        assert_eq!(
+            content_hash_reader(data.as_slice()).unwrap(),
+            content_hash(&data)
+        );
+    }
+
     #[test]
     fn save_with_recovery_success() {
         let fs = setup_fs();
@@ -0,0 +1,186 @@
+//! Cached content comparisons for `janus status`.
+//!
+//! Status compares the source, generated, and staged copies of every file,
+//! which dominates its runtime once there are a few hundred files. The cache
+//! stores each file's comparison results together with the [`FileStamp`]
+//! (mtime and size) of all three copies. While the stamps still match, the
+//! cached results are reused and no file contents are read; a write to any
+//! copy changes its stamp and invalidates that file's entry alone.
+//!
+//! Stored in `.janus_status_cache.toml` in the cache directory
+//! ([`Config::cache_dir`](crate::config::Config::cache_dir)). The cache
+//! is only an optimization: a missing or unparseable cache loads as empty.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+use crate::platform::{FileStamp, Fs};
+
+/// File name of the cache within the cache directory.
+pub const CACHE_FILE: &str = ".janus_status_cache.toml";
+
+/// Stamps of a file's three pipeline copies. `None` means the copy is missing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Stamps {
+    pub source: Option<FileStamp>,
+    pub generated: Option<FileStamp>,
+    pub staged: Option<FileStamp>,
+}
+
+impl Stamps {
+    /// Stamp each copy, treating any copy that can't be stamped as missing.
+    pub fn read(source: &Path, generated: &Path, staged: &Path, fs: &impl Fs) -> Self {
+        Self {
+            source: fs.stamp(source).ok(),
+            generated: fs.stamp(generated).ok(),
+            staged: fs.stamp(staged).ok(),
+        }
+    }
+}
+
+/// Content comparison results for one file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Comparison {
+    pub source_matches_generated: bool,
+    pub generated_matches_staged: bool,
+    /// Changed lines between generated and staged.
+    pub changed_lines: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedEntry {
+    stamps: Stamps,
+    comparison: Comparison,
+}
+
+/// Per-file comparison results, keyed by relative source path.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct StatusCache {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    dirty: bool,
+    #[serde(default)]
+    files: BTreeMap<String, CachedEntry>,
+}
+
+impl StatusCache {
+    /// Load the cache from the cache directory, starting empty if it's
+    /// missing or can't be parsed.
+    pub fn load(cache_dir: &Path, fs: &impl Fs) -> Self {
+        let path = cache_dir.join(CACHE_FILE);
+        let mut cache = fs
+            .read_to_string(&path)
+            .ok()
+            .and_then(|contents| match toml::from_str::<StatusCache>(&contents) {
+                Ok(cache) => Some(cache),
+                Err(e) => {
+                    debug!("Ignoring unreadable status cache: {e}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        cache.path = path;
+        cache
+    }
+
+    /// Cached comparison for `src`, if it was recorded with the same stamps.
+    pub fn get(&self, src: &str, stamps: &Stamps) -> Option<Comparison> {
+        self.files
+            .get(src)
+            .filter(|entry| entry.stamps == *stamps)
+            .map(|entry| entry.comparison)
+    }
+
+    /// Record the comparison for `src` at the given stamps.
+    pub fn insert(&mut self, src: &str, stamps: Stamps, comparison: Comparison) {
+        self.files
+            .insert(src.to_string(), CachedEntry { stamps, comparison });
+        self.dirty = true;
+    }
+
+    /// Write the cache back if anything was inserted since it was loaded.
+    pub fn save(&self, fs: &impl Fs) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let contents = toml::to_string_pretty(self).context("Failed to serialize status cache")?;
+        if let Some(parent) = self.path.parent()
+            && !fs.exists(parent)
+        {
+            fs.create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        fs.write(&self.path, contents.as_bytes())
+            .with_context(|| format!("Failed to write status cache: {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    fn stamps(fs: &crate::platform::FakeFs) -> Stamps {
+        Stamps::read(
+            Path::new(&format!("{DOTFILES}/a.conf")),
+            Path::new(&format!("{DOTFILES}/.generated/a.conf")),
+            Path::new(&format!("{DOTFILES}/.staged/a.conf")),
+            fs,
+        )
+    }
+
+    const SAME: Comparison = Comparison {
+        source_matches_generated: true,
+        generated_matches_staged: true,
+        changed_lines: 0,
+    };
+
+    #[test]
+    fn roundtrip_and_invalidate() {
+        let fs = setup_fs();
+        setup_pipeline_file(&fs, "a.conf", "content");
+        let before = stamps(&fs);
+
+        let mut cache = StatusCache::load(Path::new(DOTFILES), &fs);
+        assert_eq!(cache.get("a.conf", &before), None);
+        cache.insert("a.conf", before, SAME);
+        cache.save(&fs).unwrap();
+
+        let cache = StatusCache::load(Path::new(DOTFILES), &fs);
+        assert_eq!(cache.get("a.conf", &before), Some(SAME));
+
+        fs.write(Path::new(&format!("{DOTFILES}/.staged/a.conf")), b"edited")
+            .unwrap();
+        assert_eq!(cache.get("a.conf", &stamps(&fs)), None);
+    }
+
+    #[test]
+    fn missing_copy_stamped_as_none() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.conf"), "content");
+        let stamps = stamps(&fs);
+        assert!(stamps.source.is_some());
+        assert_eq!(stamps.generated, None);
+        assert_eq!(stamps.staged, None);
+    }
+
+    #[test]
+    fn corrupt_cache_loads_empty() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/{CACHE_FILE}"), "not [valid toml");
+        let cache = StatusCache::load(Path::new(DOTFILES), &fs);
+        assert!(cache.files.is_empty());
+    }
+
+    #[test]
+    fn clean_cache_not_written() {
+        let fs = setup_fs();
+        let cache = StatusCache::load(Path::new(DOTFILES), &fs);
+        cache.save(&fs).unwrap();
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/{CACHE_FILE}"))));
+    }
+}
@@ -0,0 +1,194 @@
+//! Content-addressed store for staged files (`staged_store = true`).
+//!
+//! Each distinct staged content and mode is written once, to
+//! `.staged/.store/<sha256>-<mode>`, and staged copies are hard links to
+//! it, so files that render the same bytes share one copy on disk and
+//! restaging an unchanged file only relinks it. Deploy's symlinks point at
+//! the staged paths as usual and never see the store.
+//!
+//! Hard links share their data, so a program that edits a staged file in
+//! place edits every file linked to the same blob. [`link`] checks a blob
+//! still matches its name before reusing it, and blobs are only ever
+//! replaced by rename, never rewritten.
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use crate::plan;
+use crate::platform::{Fs, WalkOptions};
+
+/// Directory within the staged directory holding the blobs.
+pub const STORE_DIR: &str = ".store";
+
+/// The blob directory for `staged_dir`.
+pub fn store_dir(staged_dir: &Path) -> PathBuf {
+    staged_dir.join(STORE_DIR)
+}
+
+fn blob_name(content: &[u8], mode: u32) -> String {
+    format!("{:x}-{:o}", Sha256::digest(content), mode & 0o7777)
+}
+
+/// A sibling of `path` to build a file at before renaming it into place.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".janus-tmp");
+    path.with_file_name(name)
+}
+
+/// Stage `content` with `mode` at `dest` as a hard link to its blob,
+/// writing the blob first if there isn't an intact one. Returns whether an
+/// existing blob was reused.
+pub fn link(
+    staged_dir: &Path,
+    dest: &Path,
+    content: &[u8],
+    mode: u32,
+    fs: &impl Fs,
+) -> Result<bool> {
+    let dir = store_dir(staged_dir);
+    fs.create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    let blob = dir.join(blob_name(content, mode));
+
+    let reused = fs.is_file(&blob)
+        && fs.read(&blob).is_ok_and(|b| b == content)
+        && fs
+            .file_mode(&blob)
+            .is_ok_and(|m| m & 0o7777 == mode & 0o7777);
+    if !reused {
+        if fs.exists(&blob) {
+            warn!(
+                "Replacing {}, a staged file sharing it was edited in place",
+                blob.display()
+            );
+        }
+        let tmp = temp_path(&blob);
+        fs.write(&tmp, content)
+            .with_context(|| format!("Failed to write blob: {}", tmp.display()))?;
+        fs.set_file_mode(&tmp, mode)
+            .with_context(|| format!("Failed to set permissions: {}", tmp.display()))?;
+        fs.rename(&tmp, &blob)
+            .with_context(|| format!("Failed to write blob: {}", blob.display()))?;
+    }
+
+    let tmp = temp_path(dest);
+    if fs.is_file(&tmp) {
+        fs.remove_file(&tmp)?;
+    }
+    fs.hard_link(&blob, &tmp)
+        .with_context(|| format!("Failed to link {} to {}", tmp.display(), blob.display()))?;
+    fs.rename(&tmp, dest)
+        .with_context(|| format!("Failed to replace {}", dest.display()))?;
+    // Renaming onto another link to the same blob is a no-op that leaves
+    // the temporary link behind
+    if fs.is_file(&tmp) {
+        fs.remove_file(&tmp)?;
+    }
+    debug!(
+        "Linked {} to {}{}",
+        dest.display(),
+        blob.display(),
+        if reused { " (reused)" } else { "" }
+    );
+    Ok(reused)
+}
+
+/// Remove blobs no file under `staged_dir` has the content and mode of.
+/// Returns how many were (or, with `dry_run`, would be) removed.
+pub fn prune(staged_dir: &Path, dry_run: bool, fs: &impl Fs) -> Result<usize> {
+    let dir = store_dir(staged_dir);
+    if !fs.is_dir(&dir) {
+        return Ok(0);
+    }
+    let opts = WalkOptions {
+        min_depth: 1,
+        ..Default::default()
+    };
+    let mut used = HashSet::new();
+    for entry in fs.walk_dir(staged_dir, &opts)? {
+        if !entry.is_file || entry.is_symlink || entry.path.starts_with(&dir) {
+            continue;
+        }
+        let content = fs
+            .read(&entry.path)
+            .with_context(|| format!("Failed to read staged: {}", entry.path.display()))?;
+        let mode = fs.file_mode(&entry.path)?;
+        used.insert(blob_name(&content, mode));
+    }
+
+    let mut count = 0;
+    for entry in fs.walk_dir(&dir, &opts)? {
+        let name = entry.path.file_name().unwrap_or_default().to_string_lossy();
+        if !entry.is_file || used.contains(name.as_ref()) {
+            continue;
+        }
+        if dry_run {
+            info!("[dry-run] Would remove unused blob: {name}");
+            plan::action("remove", "", &entry.path.to_string_lossy(), "unused blob");
+        } else {
+            fs.remove_file(&entry.path)
+                .with_context(|| format!("Failed to remove blob: {}", entry.path.display()))?;
+            debug!("Removed unused blob: {name}");
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn identical_content_shares_a_blob() {
+        let fs = setup_fs();
+        let staged = Path::new(DOTFILES).join(".staged");
+        let a = staged.join("a.conf");
+        let b = staged.join("b.conf");
+        fs.add_dir(&staged);
+
+        assert!(!link(&staged, &a, b"same\n", 0o644, &fs).unwrap());
+        assert!(link(&staged, &b, b"same\n", 0o644, &fs).unwrap());
+        // A different mode needs its own blob
+        assert!(!link(&staged, &b, b"same\n", 0o600, &fs).unwrap());
+
+        assert_eq!(fs.read(&b).unwrap(), b"same\n");
+        assert_eq!(fs.file_mode(&b).unwrap(), 0o600);
+        assert!(!fs.exists(&temp_path(&b)));
+    }
+
+    #[test]
+    fn edited_blob_is_replaced_not_reused() {
+        let fs = setup_fs();
+        let staged = Path::new(DOTFILES).join(".staged");
+        fs.add_dir(&staged);
+        link(&staged, &staged.join("a.conf"), b"same\n", 0o644, &fs).unwrap();
+        let blob = store_dir(&staged).join(blob_name(b"same\n", 0o644));
+        fs.write(&blob, b"edited\n").unwrap();
+
+        assert!(!link(&staged, &staged.join("b.conf"), b"same\n", 0o644, &fs).unwrap());
+        assert_eq!(fs.read(&blob).unwrap(), b"same\n");
+    }
+
+    #[test]
+    fn prune_keeps_blobs_still_staged() {
+        let fs = setup_fs();
+        let staged = Path::new(DOTFILES).join(".staged");
+        fs.add_dir(&staged);
+        link(&staged, &staged.join("a.conf"), b"kept\n", 0o644, &fs).unwrap();
+        link(&staged, &staged.join("b.conf"), b"old\n", 0o644, &fs).unwrap();
+        link(&staged, &staged.join("b.conf"), b"new\n", 0o644, &fs).unwrap();
+
+        assert_eq!(prune(&staged, true, &fs).unwrap(), 1);
+        assert_eq!(prune(&staged, false, &fs).unwrap(), 1);
+        let dir = store_dir(&staged);
+        assert!(fs.exists(&dir.join(blob_name(b"kept\n", 0o644))));
+        assert!(fs.exists(&dir.join(blob_name(b"new\n", 0o644))));
+        assert!(!fs.exists(&dir.join(blob_name(b"old\n", 0o644))));
+    }
+}
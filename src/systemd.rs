@@ -0,0 +1,320 @@
+//! systemctl calls triggered by deploying or undeploying systemd units.
+//!
+//! Files with `systemd_unit = true` need a `daemon-reload` after their symlink
+//! changes, and `reload_units` lists units to restart after a deploy. Actions
+//! are collected per file and run once at the end of the command, reloads
+//! before restarts. Targets under the home directory use `systemctl --user`;
+//! anything else uses the system manager.
+//!
+//! Program reloads from an entry's `reload` setting (see [`crate::reload`])
+//! are batched the same way and run after the systemctl commands, debounced
+//! hooks last.
+
+use anyhow::{Result, bail};
+use std::path::Path;
+use tracing::{debug, info, warn};
+
+use crate::config::FileEntry;
+use crate::plan;
+use crate::platform::{CommandRunner, Fs};
+use crate::reload::{self, ReloadAction};
+use crate::shell;
+
+/// Batched, deduplicated systemctl and program reload commands for one
+/// deploy/undeploy run.
+#[derive(Debug, Default)]
+pub struct SystemdActions {
+    reloads: Vec<String>,
+    restarts: Vec<String>,
+    programs: Vec<String>,
+    /// Debounced program reloads, run after the others.
+    debounced: Vec<String>,
+    /// Recipes already suggested this run, so each is mentioned once.
+    suggested: Vec<&'static str>,
+}
+
+impl SystemdActions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the actions needed after `entry` was deployed to `target_path`.
+    pub fn record_deploy(&mut self, entry: &FileEntry, target_path: &Path, fs: &impl Fs) {
+        let scope = scope_flag(target_path, fs);
+        if entry.systemd_unit {
+            push_unique(&mut self.reloads, format!("systemctl {scope}daemon-reload"));
+        }
+        for unit in &entry.reload_units {
+            push_unique(
+                &mut self.restarts,
+                format!("systemctl {scope}try-restart {}", shell::quote(unit)),
+            );
+        }
+        match reload::action_for(entry, target_path, fs) {
+            ReloadAction::Run(command) => push_unique(&mut self.programs, command),
+            ReloadAction::Debounce(command) => push_unique(&mut self.debounced, command),
+            ReloadAction::Suggest(recipe) if !self.suggested.contains(&recipe.program) => {
+                self.suggested.push(recipe.program);
+                info!(
+                    "{} can reload {} after deploy; set reload = \"auto\" (or \"never\") on its entry",
+                    recipe.program, entry.src
+                );
+            }
+            ReloadAction::Suggest(_) | ReloadAction::None => {}
+        }
+    }
+
+    /// Record the actions needed after `entry` was removed from `target_path`.
+    ///
+    /// Only reloads — restarting units whose config was just removed is left
+    /// to the user.
+    pub fn record_undeploy(&mut self, entry: &FileEntry, target_path: &Path, fs: &impl Fs) {
+        if entry.systemd_unit {
+            let scope = scope_flag(target_path, fs);
+            push_unique(&mut self.reloads, format!("systemctl {scope}daemon-reload"));
+        }
+    }
+
+    /// Run (or, with `dry_run`, print) the recorded commands.
+    ///
+    /// Attempts every command and reports all failures at the end.
+    pub fn run(&self, dry_run: bool, runner: &impl CommandRunner) -> Result<()> {
+        let systemctl: Vec<&String> = self.reloads.iter().chain(&self.restarts).collect();
+        let programs: Vec<&String> = self
+            .programs
+            .iter()
+            .filter(|c| !self.debounced.contains(c))
+            .chain(&self.debounced)
+            .collect();
+        let mut msg = String::new();
+        for (kind, commands) in [("systemctl", systemctl), ("reload", programs)] {
+            let errors = run_all(&commands, dry_run, runner);
+            if !errors.is_empty() {
+                if !msg.is_empty() {
+                    msg.push('\n');
+                }
+                msg.push_str(&format!(
+                    "Failed to run {} {kind} command(s):",
+                    errors.len()
+                ));
+                for (command, e) in &errors {
+                    msg.push_str(&format!("\n  {command}: {e}"));
+                }
+            }
+        }
+        if !msg.is_empty() {
+            bail!(msg);
+        }
+        Ok(())
+    }
+}
+
+/// Run (or print) each command, returning the failures.
+fn run_all<'a>(
+    commands: &[&'a String],
+    dry_run: bool,
+    runner: &impl CommandRunner,
+) -> Vec<(&'a str, String)> {
+    let mut errors = Vec::new();
+    for command in commands {
+        if dry_run {
+            info!("[dry-run] Would run: {command}");
+            plan::action("run", "", command, "after deploy");
+            continue;
+        }
+        debug!("Running: {command}");
+        match runner.run(command, None) {
+            Ok(output) if output.success => info!("Ran {command}"),
+            Ok(output) => {
+                warn!("{command} failed: {}", output.stderr.trim());
+                errors.push((command.as_str(), output.stderr.trim().to_string()));
+            }
+            Err(e) => {
+                warn!("{command} failed: {e:#}");
+                errors.push((command.as_str(), format!("{e:#}")));
+            }
+        }
+    }
+    errors
+}
+
+/// `--user ` for targets under the home directory, nothing otherwise.
+fn scope_flag(target_path: &Path, fs: &impl Fs) -> &'static str {
+    match fs.home_dir() {
+        Some(home) if target_path.starts_with(&home) => "--user ",
+        _ => "",
+    }
+}
+
+fn push_unique(commands: &mut Vec<String>, command: String) {
+    if !commands.contains(&command) {
+        commands.push(command);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ReloadHook;
+    use crate::platform::{CommandOutput, FakeCommandRunner};
+    use crate::test_helpers::*;
+
+    fn unit_entry(systemd_unit: bool, reload_units: &[&str]) -> FileEntry {
+        FileEntry {
+            src: "systemd/user/waybar.service".to_string(),
+            name: None,
+            target: None,
+            targets: Default::default(),
+            host_target: None,
+            template: false,
+            postprocess: vec![],
+            vars: vec![],
+            secrets: vec![],
+            inject: vec![],
+            newline: Default::default(),
+            encoding: Default::default(),
+            mode: None,
+            direct: false,
+            regions_template: None,
+            exclude_from_all: false,
+            enabled: true,
+            systemd_unit,
+            reload_units: reload_units.iter().map(|s| s.to_string()).collect(),
+            create_parents: None,
+            reload: None,
+            depends_on: vec![],
+            undefined_vars: None,
+        }
+    }
+
+    #[test]
+    fn reloads_before_restarts_and_dedups() {
+        let fs = setup_fs();
+        let target = Path::new("/home/test/.config/systemd/user/waybar.service");
+        let mut actions = SystemdActions::new();
+        actions.record_deploy(&unit_entry(false, &["waybar.service"]), target, &fs);
+        actions.record_deploy(&unit_entry(true, &["waybar.service"]), target, &fs);
+        actions.record_deploy(&unit_entry(true, &[]), target, &fs);
+
+        let runner = FakeCommandRunner::new();
+        actions.run(false, &runner).unwrap();
+        assert_eq!(
+            runner.commands(),
+            vec![
+                "systemctl --user daemon-reload",
+                "systemctl --user try-restart waybar.service",
+            ]
+        );
+    }
+
+    #[test]
+    fn system_scope_outside_home() {
+        let fs = setup_fs();
+        let mut actions = SystemdActions::new();
+        actions.record_deploy(
+            &unit_entry(true, &[]),
+            Path::new("/etc/systemd/system/foo.service"),
+            &fs,
+        );
+        let runner = FakeCommandRunner::new();
+        actions.run(false, &runner).unwrap();
+        assert_eq!(runner.commands(), vec!["systemctl daemon-reload"]);
+    }
+
+    #[test]
+    fn undeploy_only_reloads() {
+        let fs = setup_fs();
+        let target = Path::new("/home/test/.config/systemd/user/waybar.service");
+        let mut actions = SystemdActions::new();
+        actions.record_undeploy(&unit_entry(true, &["waybar.service"]), target, &fs);
+        actions.record_undeploy(&unit_entry(false, &["waybar.service"]), target, &fs);
+        let runner = FakeCommandRunner::new();
+        actions.run(false, &runner).unwrap();
+        assert_eq!(runner.commands(), vec!["systemctl --user daemon-reload"]);
+    }
+
+    #[test]
+    fn dry_run_runs_nothing() {
+        let fs = setup_fs();
+        let target = Path::new("/home/test/.config/systemd/user/waybar.service");
+        let mut actions = SystemdActions::new();
+        actions.record_deploy(&unit_entry(true, &["waybar.service"]), target, &fs);
+        let runner = FakeCommandRunner::new();
+        actions.run(true, &runner).unwrap();
+        assert!(runner.calls().is_empty());
+    }
+
+    #[test]
+    fn failures_collected() {
+        let fs = setup_fs();
+        let target = Path::new("/home/test/.config/systemd/user/waybar.service");
+        let mut actions = SystemdActions::new();
+        actions.record_deploy(&unit_entry(true, &["waybar.service"]), target, &fs);
+        let mut runner = FakeCommandRunner::new();
+        runner.add_response(
+            "systemctl --user daemon-reload",
+            CommandOutput {
+                success: false,
+                stdout: String::new(),
+                stderr: "Failed to connect to bus".to_string(),
+            },
+        );
+        let result = actions.run(false, &runner);
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(msg.contains("1 systemctl command(s)"), "got: {msg}");
+        // The restart was still attempted
+        assert_eq!(runner.calls().len(), 2);
+    }
+
+    #[test]
+    fn program_reloads_run_after_systemctl_once() {
+        let fs = setup_fs();
+        let mut entry = unit_entry(false, &["waybar.service"]);
+        entry.reload = Some("auto".into());
+        let mut actions = SystemdActions::new();
+        for file in ["config", "style.css"] {
+            let target = format!("/home/test/.config/waybar/{file}");
+            actions.record_deploy(&entry, Path::new(&target), &fs);
+        }
+        let runner = FakeCommandRunner::new();
+        actions.run(false, &runner).unwrap();
+        assert_eq!(
+            runner.commands(),
+            vec![
+                "systemctl --user try-restart waybar.service",
+                "pgrep -x waybar >/dev/null 2>&1 || exit 0; pkill -SIGUSR2 -x waybar",
+            ]
+        );
+    }
+
+    #[test]
+    fn debounced_hooks_run_once_per_target_last() {
+        let fs = setup_fs();
+        let mut tmux = unit_entry(false, &[]);
+        tmux.reload = Some(ReloadHook::Table {
+            command: "auto".to_string(),
+            debounce: true,
+        });
+        let mut kitty = unit_entry(false, &[]);
+        kitty.reload = Some("auto".into());
+        let mut actions = SystemdActions::new();
+        actions.record_deploy(&tmux, Path::new("/home/test/.config/tmux/a.conf"), &fs);
+        actions.record_deploy(
+            &kitty,
+            Path::new("/home/test/.config/kitty/kitty.conf"),
+            &fs,
+        );
+        actions.record_deploy(&tmux, Path::new("/home/test/.config/tmux/b.conf"), &fs);
+        actions.record_deploy(&tmux, Path::new("/home/test/.config/tmux/a.conf"), &fs);
+        let runner = FakeCommandRunner::new();
+        actions.run(false, &runner).unwrap();
+        assert_eq!(
+            runner.commands(),
+            vec![
+                "pgrep -x kitty >/dev/null 2>&1 || exit 0; pkill -SIGUSR1 -x kitty",
+                "tmux info >/dev/null 2>&1 || exit 0; tmux source-file /home/test/.config/tmux/a.conf",
+                "tmux info >/dev/null 2>&1 || exit 0; tmux source-file /home/test/.config/tmux/b.conf",
+            ]
+        );
+    }
+}
@@ -0,0 +1,1158 @@
+//! Tera rendering for managed templates.
+//!
+//! Wraps a one-template [`Tera`] instance (autoescaping off, like
+//! `Tera::one_off`) with janus's template functions registered:
+//!
+//! - `throw(message)` fails the render with `message`
+//! - `assert(cond, message)` fails the render with `message` if `cond` is false
+//!
+//! Tera functions take keyword arguments, so in a template these read
+//! `{{ assert(cond=bar_height > 0, message="bar_height must be positive") }}`.
+//! Both render as an empty string when they don't fail. Failures surface as
+//! "Template assertion failed" errors instead of a generic render failure.
+//!
+//! Three more functions ask about the machine the template is rendered on:
+//!
+//! - `has_command(name)` is true if `name` is on `$PATH`
+//! - `pkg_installed(name)` is true if the system package manager (pacman,
+//!   dpkg, rpm, or brew) has `name` installed
+//! - `command_output(command)` is the stdout of `command`, minus trailing
+//!   newlines
+//!
+//! All three run commands, so they only work with `allow_exec = true` in the
+//! config.
+//!
+//! Their arguments must be string literals: [`Probes::collect`] finds the
+//! calls in the template text and runs them through a
+//! [`CommandRunner`] before rendering, since Tera functions can't borrow one.
+//!
+//! Templates also see a `janus` object describing the file being rendered and
+//! every enabled managed file (see [`JanusObject`]), so one template can
+//! refer to the others, e.g. to emit a `source = ...` line for each file in a
+//! directory. Disabled entries are left out, as every command skips them.
+//! Two functions select from the same list:
+//!
+//! - `fileset_files(name)` is the files in fileset `name`
+//! - `managed_files(pattern)` is the files whose `src` matches the glob
+//!   `pattern`
+//!
+//! Both return `janus.files`-style objects in config order, so
+//! `{% for f in fileset_files(name="lua") %}` can require every module of a
+//! fileset. [`Probes::with_managed`] supplies the list.
+//!
+//! `now()` is the current time, as Tera's built-in is, with a few more
+//! arguments: `format` (a strftime string), `timezone` (an IANA name like
+//! `Europe/Berlin`), `utc`, and `timestamp`. A template stamping its
+//! generation time renders differently on every run, so the time can be
+//! frozen with `--frozen-time` or `SOURCE_DATE_EPOCH` (see [`freeze_time`]).
+//!
+//! Errors point at the failing spot with a few lines of the template and a
+//! caret underneath: syntax errors where Tera's parser stopped, and render
+//! errors at the first tag using the missing variable, filter, or function.
+//!
+//! [`TemplateCache`] keeps compiled templates around for callers that render
+//! the same files repeatedly, re-parsing only the ones whose content changed.
+
+use anyhow::{Result, anyhow, bail};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, Utc};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, LazyLock, OnceLock};
+use tera::{Tera, Value};
+use tracing::debug;
+
+use crate::config::{Config, FileEntry};
+use crate::paths::expand_tilde;
+use crate::platform::{CommandRunner, Fs};
+use crate::shell;
+use crate::state::content_hash;
+
+/// Name of the context variable holding the [`JanusObject`].
+const JANUS_VAR: &str = "janus";
+
+/// The `janus` template variable.
+#[derive(Debug, Serialize)]
+pub struct JanusObject {
+    /// Source path of the file being rendered, relative to the dotfiles dir.
+    pub src: String,
+    /// Its target as configured (may start with `~`).
+    pub target: String,
+    /// Its target with `~` expanded.
+    pub target_path: String,
+    /// Names of the filesets it belongs to, sorted.
+    pub filesets: Vec<String>,
+    /// Every enabled managed file, in config order.
+    pub files: Vec<ManagedFile>,
+}
+
+/// One entry of `janus.files`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ManagedFile {
+    pub src: String,
+    pub target: String,
+    pub target_path: String,
+    pub filesets: Vec<String>,
+    pub template: bool,
+    pub direct: bool,
+}
+
+impl ManagedFile {
+    fn new(config: &Config, entry: &FileEntry, fs: &impl Fs) -> Self {
+        let target = entry.target();
+        let mut filesets: Vec<String> = config
+            .matching_filesets_named(&entry.src)
+            .into_iter()
+            .map(|(name, _)| name.to_string())
+            .collect();
+        filesets.sort();
+        Self {
+            src: entry.src.clone(),
+            target_path: expand_tilde(&target, fs).display().to_string(),
+            target,
+            filesets,
+            template: entry.template,
+            direct: entry.direct,
+        }
+    }
+}
+
+impl JanusObject {
+    /// Describe `entry` and the rest of the config.
+    pub fn new(config: &Config, entry: &FileEntry, fs: &impl Fs) -> Self {
+        let current = ManagedFile::new(config, entry, fs);
+        Self {
+            src: current.src,
+            target: current.target,
+            target_path: current.target_path,
+            filesets: current.filesets,
+            files: config
+                .files
+                .iter()
+                .filter(|file| file.enabled)
+                .map(|file| ManagedFile::new(config, file, fs))
+                .collect(),
+        }
+    }
+}
+
+/// Add the `janus` object for `entry` to `context`.
+///
+/// Fails if a user variable or secret is already named `janus`.
+pub fn insert_janus(
+    context: &mut tera::Context,
+    config: &Config,
+    entry: &FileEntry,
+    fs: &impl Fs,
+) -> Result<()> {
+    if context.contains_key(JANUS_VAR) {
+        bail!("`{JANUS_VAR}` is reserved for the janus template object; rename that variable");
+    }
+    context.insert(JANUS_VAR, &JanusObject::new(config, entry, fs));
+    Ok(())
+}
+
+/// Marks errors raised by `throw`/`assert` so they can be told apart from
+/// ordinary render failures.
+const ASSERTION_PREFIX: &str = "janus assertion: ";
+
+/// The time `now()` reports when frozen, set by [`freeze_time`].
+static FROZEN_TIME: OnceLock<i64> = OnceLock::new();
+
+/// Make `now()` report `secs` (since the Unix epoch) for the rest of the
+/// process, e.g. for `--frozen-time`. Only the first call has an effect.
+pub fn freeze_time(secs: i64) {
+    let _ = FROZEN_TIME.set(secs);
+}
+
+/// The frozen time, if any: from [`freeze_time`], or else the
+/// `SOURCE_DATE_EPOCH` environment variable.
+fn frozen_time() -> Option<i64> {
+    FROZEN_TIME.get().copied().or_else(|| {
+        let value = std::env::var("SOURCE_DATE_EPOCH").ok()?;
+        match value.trim().parse() {
+            Ok(secs) => Some(secs),
+            Err(_) => {
+                debug!("Ignoring SOURCE_DATE_EPOCH={value}: not a number of seconds");
+                None
+            }
+        }
+    })
+}
+
+/// Parse a `--frozen-time` value: seconds since the Unix epoch, or an
+/// RFC 3339 timestamp like `2024-01-01T00:00:00Z`.
+pub fn parse_time(value: &str) -> Result<i64, String> {
+    if let Ok(secs) = value.parse() {
+        return Ok(secs);
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.timestamp())
+        .map_err(|_| {
+            format!("invalid time `{value}` (expected seconds since the epoch or RFC 3339)")
+        })
+}
+
+/// Calls to the machine-query functions with a literal argument, e.g.
+/// `has_command(name="wl-copy")`.
+static PROBE_CALL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"\b(has_command|pkg_installed|command_output)\s*\(\s*(?:name|command)\s*=\s*(?:"([^"]*)"|'([^']*)'|`([^`]*)`)\s*\)"#,
+    )
+    .expect("valid regex")
+});
+
+/// Calls listing managed files with a literal argument, e.g.
+/// `fileset_files(name="lua")`.
+static LIST_CALL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"\b(fileset_files|managed_files)\s*\(\s*(?:name|pattern)\s*=\s*(?:"([^"]*)"|'([^']*)'|`([^`]*)`)\s*\)"#,
+    )
+    .expect("valid regex")
+});
+
+/// The managed files `content` lists through `fileset_files` or
+/// `managed_files` calls with literal arguments, each with the call that
+/// lists it, in order of appearance.
+pub fn listed_files<'a>(content: &str, config: &'a Config) -> Vec<(String, &'a FileEntry)> {
+    let mut listed: Vec<(String, &FileEntry)> = Vec::new();
+    for block in template_blocks(content) {
+        for call in LIST_CALL.captures_iter(block) {
+            let Some(arg) = (2..=4).find_map(|i| call.get(i)) else {
+                continue;
+            };
+            let arg = arg.as_str().to_string();
+            let patterns = match &call[1] {
+                "fileset_files" => match config.filesets.get(&arg) {
+                    Some(fileset) => fileset.patterns.clone(),
+                    None => continue,
+                },
+                _ => vec![arg],
+            };
+            for entry in config.filter_files(Some(&patterns)) {
+                if !listed.iter().any(|(_, e)| e.src == entry.src) {
+                    listed.push((call[0].to_string(), entry));
+                }
+            }
+        }
+    }
+    listed
+}
+
+/// Answers to the `has_command`, `pkg_installed`, and `command_output`
+/// calls in a template, gathered before rendering.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Probes {
+    commands: BTreeMap<String, bool>,
+    packages: BTreeMap<String, bool>,
+    outputs: BTreeMap<String, String>,
+    /// Enabled managed files, for `fileset_files` and `managed_files`.
+    files: Vec<ManagedFile>,
+    /// Names of the configured filesets.
+    filesets: Vec<String>,
+    /// What `now()` reports instead of the current time.
+    frozen_time: Option<i64>,
+}
+
+impl Probes {
+    /// Run the machine queries `content` makes inside `{{ }}` or `{% %}`.
+    ///
+    /// Fails if it calls any of them without `allow_exec`, or if a
+    /// `command_output` command fails.
+    pub fn collect(content: &str, allow_exec: bool, runner: &impl CommandRunner) -> Result<Self> {
+        let mut probes = Self {
+            frozen_time: frozen_time(),
+            ..Self::default()
+        };
+        for block in template_blocks(content) {
+            for call in PROBE_CALL.captures_iter(block) {
+                let Some(arg) = (2..=4).find_map(|i| call.get(i)) else {
+                    continue;
+                };
+                let arg = arg.as_str().to_string();
+                if !allow_exec {
+                    let param = if &call[1] == "command_output" {
+                        "command"
+                    } else {
+                        "name"
+                    };
+                    bail!(
+                        "{}({param}=\"{arg}\") needs `allow_exec = true` in the config",
+                        &call[1]
+                    );
+                }
+                match &call[1] {
+                    "has_command" if !probes.commands.contains_key(&arg) => {
+                        let command = format!("command -v {} >/dev/null 2>&1", shell::quote(&arg));
+                        let found = runner.run(&command, None)?.success;
+                        debug!("has_command({arg}): {found}");
+                        probes.commands.insert(arg, found);
+                    }
+                    "pkg_installed" if !probes.packages.contains_key(&arg) => {
+                        let found = runner.run(&pkg_query(&arg), None)?.success;
+                        debug!("pkg_installed({arg}): {found}");
+                        probes.packages.insert(arg, found);
+                    }
+                    "command_output" if !probes.outputs.contains_key(&arg) => {
+                        let output = runner.run(&arg, None)?;
+                        if !output.success {
+                            bail!("command_output: `{arg}` failed: {}", output.stderr.trim());
+                        }
+                        let stdout = output.stdout.trim_end_matches(['\r', '\n']).to_string();
+                        probes.outputs.insert(arg, stdout);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(probes)
+    }
+
+    /// Add the managed files `fileset_files` and `managed_files` select
+    /// from.
+    pub fn with_managed(mut self, config: &Config, fs: &impl Fs) -> Self {
+        self.files = config
+            .files
+            .iter()
+            .filter(|entry| entry.enabled)
+            .map(|entry| ManagedFile::new(config, entry, fs))
+            .collect();
+        self.filesets = config.filesets.keys().cloned().collect();
+        self
+    }
+}
+
+/// A shell line that succeeds if the first package manager found has
+/// `package` installed.
+fn pkg_query(package: &str) -> String {
+    let package = shell::quote(package);
+    format!(
+        "if command -v pacman >/dev/null 2>&1; then pacman -Q -- {package}; \
+         elif command -v dpkg-query >/dev/null 2>&1; then \
+         dpkg-query -W -f='${{Status}}' -- {package} | grep -q 'ok installed'; \
+         elif command -v rpm >/dev/null 2>&1; then rpm -q -- {package}; \
+         elif command -v brew >/dev/null 2>&1; then brew list --versions {package}; \
+         else exit 1; fi >/dev/null 2>&1"
+    )
+}
+
+/// Render `content` as a template named `name` with `context`, answering
+/// machine queries from `probes`.
+///
+/// `name` is only used in error messages.
+pub fn render(
+    name: &str,
+    content: &str,
+    context: &tera::Context,
+    probes: &Probes,
+) -> Result<String> {
+    compile(name, content, probes)?
+        .render(name, context)
+        .map_err(|e| render_error(e, name, content))
+}
+
+/// Parse `content` as the template `name` without rendering it, failing
+/// with an excerpt at the first syntax error.
+pub fn check_syntax(name: &str, content: &str) -> Result<()> {
+    compile(name, content, &Probes::default()).map(|_| ())
+}
+
+/// Parse `content` into a one-template [`Tera`] instance with janus's
+/// functions registered.
+fn compile(name: &str, content: &str, probes: &Probes) -> Result<Tera> {
+    let mut tera = Tera::default();
+    tera.autoescape_on(vec![]);
+    tera.register_function("throw", throw);
+    tera.register_function("assert", assert);
+    let probes = Arc::new(probes.clone());
+    tera.register_function(
+        "has_command",
+        probe_function("has_command", "name", probes.clone(), |p| &p.commands),
+    );
+    tera.register_function(
+        "pkg_installed",
+        probe_function("pkg_installed", "name", probes.clone(), |p| &p.packages),
+    );
+    tera.register_function(
+        "command_output",
+        probe_function("command_output", "command", probes.clone(), |p| &p.outputs),
+    );
+    tera.register_function("fileset_files", fileset_files(probes.clone()));
+    tera.register_function("now", now(probes.frozen_time));
+    tera.register_function("managed_files", managed_files(probes));
+    tera.add_raw_template(name, content)
+        .map_err(|e| render_error(e, name, content))?;
+    Ok(tera)
+}
+
+/// A Tera function answering `function(arg=...)` from the map `pick`
+/// selects in `probes`.
+fn probe_function<T>(
+    function: &'static str,
+    arg: &'static str,
+    probes: Arc<Probes>,
+    pick: fn(&Probes) -> &BTreeMap<String, T>,
+) -> impl tera::Function
+where
+    T: Clone + Into<Value> + Send + Sync + 'static,
+{
+    move |args: &HashMap<String, Value>| {
+        let Some(key) = args.get(arg).and_then(Value::as_str) else {
+            return Err(tera::Error::msg(format!(
+                "{function}() requires a string `{arg}` argument"
+            )));
+        };
+        match pick(&probes).get(key) {
+            Some(value) => Ok(value.clone().into()),
+            None => Err(tera::Error::msg(format!(
+                "{function}() needs a string literal `{arg}`, so it can run before rendering"
+            ))),
+        }
+    }
+}
+
+/// The string argument `arg` of `function`.
+fn string_arg<'a>(
+    function: &str,
+    arg: &str,
+    args: &'a HashMap<String, Value>,
+) -> tera::Result<&'a str> {
+    args.get(arg)
+        .and_then(Value::as_str)
+        .ok_or_else(|| tera::Error::msg(format!("{function}() requires a string `{arg}` argument")))
+}
+
+/// `fileset_files(name)`: the managed files in fileset `name`.
+fn fileset_files(probes: Arc<Probes>) -> impl tera::Function {
+    move |args: &HashMap<String, Value>| {
+        let name = string_arg("fileset_files", "name", args)?;
+        if !probes.filesets.iter().any(|f| f == name) {
+            return Err(tera::Error::msg(format!(
+                "fileset_files(): no fileset named {name}"
+            )));
+        }
+        let files: Vec<&ManagedFile> = probes
+            .files
+            .iter()
+            .filter(|f| f.filesets.iter().any(|s| s == name))
+            .collect();
+        tera::to_value(files).map_err(tera::Error::msg)
+    }
+}
+
+/// `managed_files(pattern)`: the managed files whose `src` matches the glob
+/// `pattern`.
+fn managed_files(probes: Arc<Probes>) -> impl tera::Function {
+    move |args: &HashMap<String, Value>| {
+        let pattern = string_arg("managed_files", "pattern", args)?;
+        let pattern = glob::Pattern::new(pattern).map_err(|e| {
+            tera::Error::msg(format!("managed_files(): bad pattern {pattern}: {e}"))
+        })?;
+        let files: Vec<&ManagedFile> = probes
+            .files
+            .iter()
+            .filter(|f| pattern.matches(&f.src))
+            .collect();
+        tera::to_value(files).map_err(tera::Error::msg)
+    }
+}
+
+/// `now(format, timezone, utc, timestamp)`: the current time, or
+/// `frozen` if set. Without `format` it's RFC 3339, in local time unless
+/// `utc` or `timezone` is given; `timestamp=true` gives epoch seconds.
+/// A frozen time is in UTC unless `timezone` is given, so it renders the
+/// same on every machine.
+fn now(frozen: Option<i64>) -> impl tera::Function {
+    move |args: &HashMap<String, Value>| {
+        let flag = |name: &str| -> tera::Result<bool> {
+            match args.get(name) {
+                None => Ok(false),
+                Some(value) => value
+                    .as_bool()
+                    .ok_or_else(|| tera::Error::msg(format!("now() requires a boolean `{name}`"))),
+            }
+        };
+        let time = match frozen {
+            Some(secs) => DateTime::from_timestamp(secs, 0)
+                .ok_or_else(|| tera::Error::msg(format!("frozen time {secs} is out of range")))?,
+            None => Utc::now(),
+        };
+        if flag("timestamp")? {
+            return Ok(Value::from(time.timestamp()));
+        }
+        let format = match args.get("format") {
+            None => None,
+            Some(_) => {
+                let format = string_arg("now", "format", args)?;
+                if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+                    return Err(tera::Error::msg(format!(
+                        "now(): invalid format `{format}`"
+                    )));
+                }
+                Some(format)
+            }
+        };
+        let text = if let Some(name) = args.get("timezone") {
+            let name = name
+                .as_str()
+                .ok_or_else(|| tera::Error::msg("now() requires a string `timezone`"))?;
+            let tz: chrono_tz::Tz = name
+                .parse()
+                .map_err(|_| tera::Error::msg(format!("now(): unknown timezone {name}")))?;
+            let time = time.with_timezone(&tz);
+            format.map_or_else(|| time.to_rfc3339(), |f| time.format(f).to_string())
+        } else if flag("utc")? || frozen.is_some() {
+            format.map_or_else(|| time.to_rfc3339(), |f| time.format(f).to_string())
+        } else {
+            let time = time.with_timezone(&Local);
+            format.map_or_else(|| time.to_rfc3339(), |f| time.format(f).to_string())
+        };
+        Ok(Value::from(text))
+    }
+}
+
+/// Compiled templates kept between renders, keyed by name.
+///
+/// A template is re-parsed only when its content hash changes. Each one
+/// still gets its own [`Tera`] instance, as with [`render`], so templates
+/// can't include one another.
+#[derive(Default)]
+pub struct TemplateCache {
+    compiled: HashMap<String, (String, Probes, Tera)>,
+}
+
+impl TemplateCache {
+    /// Render `content` as the template `name`, reusing the compiled
+    /// template from an earlier call if neither `content` nor `probes` has
+    /// changed.
+    pub fn render(
+        &mut self,
+        name: &str,
+        content: &str,
+        context: &tera::Context,
+        probes: &Probes,
+    ) -> Result<String> {
+        let hash = content_hash(content.as_bytes());
+        if self
+            .compiled
+            .get(name)
+            .is_none_or(|(cached, cached_probes, _)| *cached != hash || cached_probes != probes)
+        {
+            let tera = compile(name, content, probes)?;
+            self.compiled
+                .insert(name.to_string(), (hash, probes.clone(), tera));
+        }
+        let (_, _, tera) = &self.compiled[name];
+        tera.render(name, context)
+            .map_err(|e| render_error(e, name, content))
+    }
+
+    /// Drop the compiled template `name`, e.g. after its source was removed.
+    pub fn invalidate(&mut self, name: &str) {
+        self.compiled.remove(name);
+    }
+}
+
+/// Turn a Tera error into an anyhow error, surfacing assertion failures by
+/// themselves rather than buried under Tera's call-site wrapping. Other
+/// errors get an excerpt of `content` at the failure when it can be found.
+fn render_error(e: tera::Error, name: &str, content: &str) -> anyhow::Error {
+    let mut messages = Vec::new();
+    let mut source: Option<&dyn std::error::Error> = Some(&e);
+    while let Some(err) = source {
+        messages.push(err.to_string());
+        source = err.source();
+    }
+
+    if let Some(message) = messages
+        .iter()
+        .find_map(|m| m.strip_prefix(ASSERTION_PREFIX))
+    {
+        return anyhow!("Template assertion failed in {name}: {message}");
+    }
+
+    let Some((reason, offset, width)) = locate_error(&messages, content) else {
+        return anyhow::Error::new(e).context(format!("Failed to render template: {name}"));
+    };
+    anyhow!("{reason}\n{}", excerpt(name, content, offset, width))
+        .context(format!("Failed to render template: {name}"))
+}
+
+/// Where in `content` the error described by `messages` (outermost first)
+/// happened: a one-line reason, the byte offset, and how many characters
+/// to underline.
+fn locate_error(messages: &[String], content: &str) -> Option<(String, usize, usize)> {
+    static PARSE_AT: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"-->\s*(\d+):(\d+)").expect("valid regex"));
+    static NAMED: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"(?:Variable|Function|Filter|Test) (?:call )?[`']([\w.]+)[`']")
+            .expect("valid regex")
+    });
+
+    let innermost = messages.last()?;
+    // Syntax errors carry the parser's own position
+    if let Some(captures) = messages.iter().find_map(|m| PARSE_AT.captures(m)) {
+        let line: usize = captures[1].parse().ok()?;
+        let column: usize = captures[2].parse().ok()?;
+        let line_start: usize = content
+            .split_inclusive('\n')
+            .take(line.saturating_sub(1))
+            .map(str::len)
+            .sum();
+        // The parser counts columns in characters, not bytes
+        let line_text = content.get(line_start..)?.split('\n').next()?;
+        let column = line_text
+            .char_indices()
+            .nth(column.saturating_sub(1))
+            .map_or(line_text.len(), |(i, _)| i);
+        let reason = innermost
+            .lines()
+            .find_map(|l| l.trim().strip_prefix("= "))
+            .unwrap_or("syntax error")
+            .to_string();
+        return Some((reason, line_start + column, 1));
+    }
+
+    // Render errors only name what failed; point at its first use
+    let names: Vec<&str> = messages
+        .iter()
+        .flat_map(|m| NAMED.captures_iter(m))
+        .map(|c| c.get(1).map_or("", |m| m.as_str()))
+        .collect();
+    let name = names.last()?;
+    let (offset, width) = find_in_tags(content, name)
+        .or_else(|| find_in_tags(content, name.split('.').next().unwrap_or(name)))?;
+    Some((innermost.trim().to_string(), offset, width))
+}
+
+/// Contents of every `{{ ... }}` and `{% ... %}` block in a template.
+pub(crate) fn template_blocks(template: &str) -> impl Iterator<Item = &str> {
+    let mut rest = template;
+    std::iter::from_fn(move || {
+        loop {
+            let start = rest.find('{')?;
+            let close = match rest[start..].chars().nth(1) {
+                Some('{') => "}}",
+                Some('%') => "%}",
+                _ => {
+                    rest = &rest[start + 1..];
+                    continue;
+                }
+            };
+            let after = &rest[start + 2..];
+            let end = after.find(close)?;
+            rest = &after[end + 2..];
+            return Some(after[..end].trim_matches('-'));
+        }
+    })
+}
+
+/// Byte offset and width of the first whole-word `needle` inside a
+/// `{{ }}` or `{% %}` tag of `content`.
+fn find_in_tags(content: &str, needle: &str) -> Option<(usize, usize)> {
+    let pattern = Regex::new(&format!(r"(?:^|[^\w.]){}(?:$|\W)", regex::escape(needle))).ok()?;
+    template_blocks(content).find_map(|block| {
+        let found = pattern.find(block)?;
+        let start = found.start() + found.as_str().find(needle)?;
+        // Blocks are slices of the content, so their offset gives the position
+        let offset = block.as_ptr() as usize - content.as_ptr() as usize + start;
+        Some((offset, needle.chars().count()))
+    })
+}
+
+/// Up to three lines of `content` around byte `offset`, with `width`
+/// carets under it, headed by `--> name:line:column`.
+fn excerpt(name: &str, content: &str, offset: usize, width: usize) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut offset = offset.min(content.len());
+    while !content.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let before = &content[..offset];
+    let mut index = before.matches('\n').count();
+    let mut column = before[before.rfind('\n').map_or(0, |i| i + 1)..]
+        .chars()
+        .count();
+    // Past the final newline (an unclosed tag): point just after the last line
+    if index >= lines.len() {
+        index = lines.len().saturating_sub(1);
+        column = lines.last().map_or(0, |l| l.chars().count());
+    }
+    let first = index.saturating_sub(1);
+    let last = (index + 1).min(lines.len().saturating_sub(1));
+    let gutter = (last + 1).to_string().len();
+
+    let mut out = format!("{:gutter$}--> {name}:{}:{}\n", "", index + 1, column + 1);
+    out.push_str(&format!("{:gutter$} |\n", ""));
+    for (i, line) in lines.iter().enumerate().take(last + 1).skip(first) {
+        out.push_str(&format!("{:>gutter$} | {line}\n", i + 1));
+        if i == index {
+            out.push_str(&format!(
+                "{:gutter$} | {}{}\n",
+                "",
+                " ".repeat(column),
+                "^".repeat(width.max(1))
+            ));
+        }
+    }
+    out.trim_end().to_string()
+}
+
+/// `throw(message)`: always fail with `message`.
+fn throw(args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let message = args
+        .get("message")
+        .and_then(Value::as_str)
+        .unwrap_or("throw() called");
+    Err(tera::Error::msg(format!("{ASSERTION_PREFIX}{message}")))
+}
+
+/// `assert(cond, message)`: fail with `message` unless `cond` is true.
+fn assert(args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let Some(cond) = args.get("cond").and_then(Value::as_bool) else {
+        return Err(tera::Error::msg(
+            "assert() requires a boolean `cond` argument",
+        ));
+    };
+    if cond {
+        return Ok(Value::String(String::new()));
+    }
+    let message = args
+        .get("message")
+        .and_then(Value::as_str)
+        .unwrap_or("assertion failed");
+    Err(tera::Error::msg(format!("{ASSERTION_PREFIX}{message}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{CommandOutput, FakeCommandRunner};
+
+    fn context(pairs: &[(&str, i64)]) -> tera::Context {
+        let mut context = tera::Context::new();
+        for (k, v) in pairs {
+            context.insert(*k, v);
+        }
+        context
+    }
+
+    #[test]
+    fn renders_vars() {
+        let out = render(
+            "a.conf",
+            "h={{ h }}",
+            &context(&[("h", 3)]),
+            &Probes::default(),
+        )
+        .unwrap();
+        assert_eq!(out, "h=3");
+    }
+
+    #[test]
+    fn no_autoescape_for_html_names() {
+        let mut ctx = tera::Context::new();
+        ctx.insert("x", "<b>");
+        let out = render("page.html", "{{ x }}", &ctx, &Probes::default()).unwrap();
+        assert_eq!(out, "<b>");
+    }
+
+    #[test]
+    fn assert_true_renders_empty() {
+        let out = render(
+            "a.conf",
+            "x{{ assert(cond=h > 0, message=\"h must be positive\") }}y",
+            &context(&[("h", 3)]),
+            &Probes::default(),
+        )
+        .unwrap();
+        assert_eq!(out, "xy");
+    }
+
+    #[test]
+    fn assert_false_reports_message() {
+        let result = render(
+            "bar.conf",
+            "{{ assert(cond=h > 0, message=\"h must be positive\") }}",
+            &context(&[("h", 0)]),
+            &Probes::default(),
+        );
+        let msg = format!("{:#}", result.unwrap_err());
+        assert_eq!(
+            msg,
+            "Template assertion failed in bar.conf: h must be positive"
+        );
+    }
+
+    #[test]
+    fn throw_reports_message() {
+        let result = render(
+            "a.conf",
+            "{% if h > 2 %}{{ throw(message=\"too tall\") }}{% endif %}",
+            &context(&[("h", 3)]),
+            &Probes::default(),
+        );
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(
+            msg.contains("assertion failed in a.conf: too tall"),
+            "got: {msg}"
+        );
+    }
+
+    #[test]
+    fn assert_requires_bool() {
+        let result = render(
+            "a.conf",
+            "{{ assert(message=\"x\") }}",
+            &context(&[]),
+            &Probes::default(),
+        );
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(
+            msg.contains("Failed to render template: a.conf"),
+            "got: {msg}"
+        );
+    }
+
+    #[test]
+    fn janus_object_lists_files() {
+        let fs = crate::test_helpers::setup_fs();
+        let toml = format!(
+            r#"dotfiles_dir = "{}"
+
+[[files]]
+src = "hypr/hypr.conf"
+
+[[files]]
+src = "hypr/conf.d/binds.conf"
+
+[[files]]
+src = "hypr/conf.d/rules.conf"
+target = "~/rules.conf"
+
+[[files]]
+src = "hypr/conf.d/old.conf"
+enabled = false
+
+[filesets.desktop]
+patterns = ["hypr/*"]
+"#,
+            crate::test_helpers::DOTFILES
+        );
+        let config = crate::test_helpers::write_and_load_config(&fs, &toml);
+        let mut ctx = tera::Context::new();
+        insert_janus(&mut ctx, &config, &config.files[0], &fs).unwrap();
+        let template = "{{ janus.src }} [{{ janus.filesets | join(sep=\",\") }}]\n\
+            {% for f in janus.files %}{% if f.src is starting_with(\"hypr/conf.d/\") %}\
+            source = {{ f.target_path }}\n{% endif %}{% endfor %}";
+        let out = render("hypr/hypr.conf", template, &ctx, &Probes::default()).unwrap();
+        assert_eq!(
+            out,
+            "hypr/hypr.conf [desktop]\n\
+             source = /home/test/.config/hypr/conf.d/binds.conf\n\
+             source = /home/test/rules.conf\n"
+        );
+    }
+
+    #[test]
+    fn fileset_and_managed_files() {
+        let fs = crate::test_helpers::setup_fs();
+        let toml = format!(
+            r#"{}enabled = false
+
+[[files]]
+src = "nvim/lua/keys.lua"
+
+[filesets.lua]
+patterns = ["nvim/lua/*"]
+"#,
+            crate::test_helpers::make_config_toml(&[
+                ("nvim/init.lua", None),
+                ("nvim/lua/opts.lua", None),
+                ("nvim/lua/old.lua", None),
+            ])
+        );
+        let config = crate::test_helpers::write_and_load_config(&fs, &toml);
+        let probes = Probes::default().with_managed(&config, &fs);
+        let template = "{% for f in fileset_files(name=\"lua\") %}\
+            require(\"{{ f.src | replace(from=\"nvim/lua/\", to=\"\") }}\")\n{% endfor %}\
+            {{ managed_files(pattern=\"nvim/i*\") | map(attribute=\"target\") | join(sep=\",\") }}";
+        let out = render("nvim/init.lua", template, &tera::Context::new(), &probes).unwrap();
+        assert_eq!(
+            out,
+            "require(\"opts.lua\")\nrequire(\"keys.lua\")\n~/.config/nvim/init.lua"
+        );
+
+        let err = render(
+            "a.conf",
+            "{{ fileset_files(name=\"nope\") }}",
+            &tera::Context::new(),
+            &probes,
+        )
+        .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("no fileset named nope"),
+            "got: {err:#}"
+        );
+    }
+
+    #[test]
+    fn janus_var_is_reserved() {
+        let fs = crate::test_helpers::setup_fs();
+        let config = crate::test_helpers::write_and_load_config(
+            &fs,
+            &crate::test_helpers::make_config_toml(&[("a.conf", None)]),
+        );
+        let mut ctx = tera::Context::new();
+        ctx.insert("janus", "mine");
+        let msg = format!(
+            "{:#}",
+            insert_janus(&mut ctx, &config, &config.files[0], &fs).unwrap_err()
+        );
+        assert!(msg.contains("`janus` is reserved"), "got: {msg}");
+    }
+
+    #[test]
+    fn cache_recompiles_changed_content() {
+        let mut cache = TemplateCache::default();
+        let ctx = context(&[("h", 3)]);
+        assert_eq!(
+            cache
+                .render("a.conf", "h={{ h }}", &ctx, &Probes::default())
+                .unwrap(),
+            "h=3"
+        );
+        assert_eq!(
+            cache
+                .render("a.conf", "h={{ h }}", &ctx, &Probes::default())
+                .unwrap(),
+            "h=3"
+        );
+        assert_eq!(
+            cache
+                .render("a.conf", "height={{ h }}", &ctx, &Probes::default())
+                .unwrap(),
+            "height=3"
+        );
+        assert_eq!(cache.compiled.len(), 1);
+
+        cache.invalidate("a.conf");
+        assert!(cache.compiled.is_empty());
+    }
+
+    #[test]
+    fn cache_keeps_templates_apart() {
+        let mut cache = TemplateCache::default();
+        let ctx = context(&[]);
+        cache
+            .render("a.conf", "A", &ctx, &Probes::default())
+            .unwrap();
+        let result = cache.render(
+            "b.conf",
+            "{% include \"a.conf\" %}",
+            &ctx,
+            &Probes::default(),
+        );
+        assert!(result.is_err());
+    }
+
+    fn failed() -> CommandOutput {
+        CommandOutput {
+            success: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn has_command_answers_from_probes() {
+        let mut runner = FakeCommandRunner::new();
+        runner.add_response("command -v wl-copy >/dev/null 2>&1", failed());
+        let template = "{% if has_command(name=\"wl-copy\") %}wl{% else %}xclip{% endif %} \
+                        {{ has_command(name='kitty') }} {{ has_command(name=\"kitty\") }}";
+        let probes = Probes::collect(template, true, &runner).unwrap();
+        // Each name is looked up once
+        assert_eq!(runner.commands().len(), 2);
+        let out = render("a.conf", template, &context(&[]), &probes).unwrap();
+        assert_eq!(out, "xclip true true");
+    }
+
+    #[test]
+    fn pkg_installed_runs_package_query() {
+        let mut runner = FakeCommandRunner::new();
+        runner.add_response(&pkg_query("pipewire"), failed());
+        let template = "{{ pkg_installed(name=\"pipewire\") }}";
+        let probes = Probes::collect(template, true, &runner).unwrap();
+        let out = render("a.conf", template, &context(&[]), &probes).unwrap();
+        assert_eq!(out, "false");
+    }
+
+    #[test]
+    fn probes_require_allow_exec() {
+        let runner = FakeCommandRunner::new();
+        for template in [
+            "{{ has_command(name=\"kitty\") }}",
+            "{{ pkg_installed(name=\"pipewire\") }}",
+        ] {
+            let err = Probes::collect(template, false, &runner).unwrap_err();
+            assert!(format!("{err:#}").contains("allow_exec"), "got: {err:#}");
+        }
+        assert!(runner.commands().is_empty());
+    }
+
+    #[test]
+    fn command_output_requires_allow_exec() {
+        let mut runner = FakeCommandRunner::new();
+        runner.add_response(
+            "hostname -s",
+            CommandOutput {
+                success: true,
+                stdout: "laptop\n".to_string(),
+                stderr: String::new(),
+            },
+        );
+        let template = "host={{ command_output(command=\"hostname -s\") }}";
+        let err = Probes::collect(template, false, &runner).unwrap_err();
+        assert!(format!("{err:#}").contains("allow_exec"), "got: {err:#}");
+        assert!(runner.commands().is_empty());
+
+        let probes = Probes::collect(template, true, &runner).unwrap();
+        let out = render("a.conf", template, &context(&[]), &probes).unwrap();
+        assert_eq!(out, "host=laptop");
+    }
+
+    #[test]
+    fn probes_ignore_text_outside_tags() {
+        let runner = FakeCommandRunner::new();
+        Probes::collect("# has_command(name=\"x\")", false, &runner).unwrap();
+        assert!(runner.commands().is_empty());
+    }
+
+    #[test]
+    fn probe_with_non_literal_argument_fails() {
+        let mut ctx = context(&[]);
+        ctx.insert("tool", "kitty");
+        let template = "{{ has_command(name=tool) }}";
+        let probes = Probes::collect(template, false, &FakeCommandRunner::new()).unwrap();
+        let err = render("a.conf", template, &ctx, &probes).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("string literal"),
+            "got: {err:#}"
+        );
+    }
+
+    #[test]
+    fn frozen_now_formats_in_zones() {
+        let probes = Probes {
+            frozen_time: Some(1_700_000_000),
+            ..Probes::default()
+        };
+        let render_now = |call: &str| {
+            render(
+                "t",
+                &format!("{{{{ {call} }}}}"),
+                &tera::Context::new(),
+                &probes,
+            )
+        };
+        assert_eq!(
+            render_now("now(utc=true)").unwrap(),
+            "2023-11-14T22:13:20+00:00"
+        );
+        // Frozen times don't depend on the machine's zone
+        assert_eq!(render_now("now()").unwrap(), "2023-11-14T22:13:20+00:00");
+        assert_eq!(render_now("now(timestamp=true)").unwrap(), "1700000000");
+        assert_eq!(
+            render_now(r#"now(format="%Y-%m-%d %H:%M", timezone="Asia/Tokyo")"#).unwrap(),
+            "2023-11-15 07:13"
+        );
+        let err = render_now(r#"now(format="%Q")"#).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("invalid format"),
+            "got: {err:#}"
+        );
+        assert_eq!(parse_time("2023-11-14T22:13:20Z"), Ok(1_700_000_000));
+    }
+
+    #[test]
+    fn listed_files_follow_literal_calls() {
+        let fs = crate::test_helpers::setup_fs();
+        let config = crate::test_helpers::write_and_load_config(
+            &fs,
+            &format!(
+                "{}\n[filesets.lua]\npatterns = [\"nvim/lua/*\"]\n",
+                crate::test_helpers::make_config_toml(&[
+                    ("nvim/init.lua", None),
+                    ("nvim/lua/a.lua", None),
+                    ("nvim/lua/b.lua", None),
+                    ("hypr/hypr.conf", None),
+                ])
+            ),
+        );
+        let content = "{% for f in fileset_files(name=\"lua\") %}{{ f.src }}{% endfor %}\n\
+                       {{ managed_files(pattern='hypr/*') | length }} fileset_files(name=\"x\")";
+        let listed: Vec<(String, &str)> = listed_files(content, &config)
+            .into_iter()
+            .map(|(call, e)| (call, e.src.as_str()))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![
+                ("fileset_files(name=\"lua\")".to_string(), "nvim/lua/a.lua"),
+                ("fileset_files(name=\"lua\")".to_string(), "nvim/lua/b.lua"),
+                (
+                    "managed_files(pattern='hypr/*')".to_string(),
+                    "hypr/hypr.conf"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn undefined_var_is_render_failure() {
+        let result = render("a.conf", "{{ nope }}", &context(&[]), &Probes::default());
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(
+            msg.contains("Failed to render template: a.conf"),
+            "got: {msg}"
+        );
+    }
+
+    #[test]
+    fn render_error_points_at_use() {
+        let template = "a = 1\nb = 2\ncolor = {{ theme.colr }}\nd = 4\ne = 5\n";
+        let result = render("a.conf", template, &context(&[]), &Probes::default());
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(msg.contains("not found"), "got: {msg}");
+        assert!(
+            msg.contains(
+                " --> a.conf:3:12\n  |\n2 | b = 2\n3 | color = {{ theme.colr }}\n  |            ^^^^^^^^^^\n4 | d = 4"
+            ),
+            "got: {msg}"
+        );
+    }
+
+    #[test]
+    fn syntax_error_points_at_parser_position() {
+        let template = "one\n{% if x %}\nthree\n";
+        let result = render("a.conf", template, &context(&[]), &Probes::default());
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(msg.contains("--> a.conf:"), "got: {msg}");
+        assert!(msg.contains("^"), "got: {msg}");
+    }
+
+    #[test]
+    fn syntax_error_column_counts_characters() {
+        // Column 7 is the `{`, after six characters but nine bytes
+        let content = "ééé = {{ x y }}\n";
+        let messages = vec![" --> 1:7\n  = expected `}}`".to_string()];
+        let (_, offset, _) = locate_error(&messages, content).unwrap();
+        assert_eq!(&content[offset..offset + 2], "{{");
+        assert!(excerpt("a.conf", content, offset, 1).contains("--> a.conf:1:7"));
+
+        let result = render("a.conf", content, &context(&[]), &Probes::default());
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(msg.contains("--> a.conf:1:"), "got: {msg}");
+        assert!(msg.contains("ééé"), "got: {msg}");
+    }
+}
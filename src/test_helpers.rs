@@ -4,9 +4,7 @@ use crate::config::Config;
 use crate::platform::FakeFs;
 use std::path::Path;
 
-pub const HOME: &str = "/home/test";
-pub const DOTFILES: &str = "/home/test/dotfiles";
-pub const CONFIG_PATH: &str = "/home/test/.config/janus/config.toml";
+pub use crate::testing::{CONFIG_PATH, DOTFILES, HOME};
 
 /// Create a `FakeFs` seeded with the standard dotfiles directory structure
 /// and an empty state file.
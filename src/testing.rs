@@ -0,0 +1,151 @@
+//! Fixtures for testing tools built on janus (the `test-util` feature).
+//!
+//! [`Fixture`] lays out a dotfiles directory on a
+//! [`FakeFs`], writes a config managing its files,
+//! and loads it, so a test can start from any point in the pipeline in one
+//! call:
+//!
+//! ```ignore
+//! let (fs, config) = Fixture::new()
+//!     .source("git/config", "[user]\n")
+//!     .deployed("hypr/hypr.conf", "gaps = 4\n")
+//!     .build();
+//! janus::ops::generate::run(&config, None, false, &fs, &engine, &runner)?;
+//! ```
+//!
+//! Pair it with the other fakes in [`platform`](crate::platform).
+
+use std::path::Path;
+
+use crate::config::Config;
+use crate::paths::expand_tilde;
+use crate::platform::FakeFs;
+use crate::state::State;
+
+/// Home directory of the fake filesystem.
+pub const HOME: &str = "/home/test";
+/// The fixture's `dotfiles_dir`.
+pub const DOTFILES: &str = "/home/test/dotfiles";
+/// Where the fixture writes its config.
+pub const CONFIG_PATH: &str = "/home/test/.config/janus/config.toml";
+
+/// How far through the pipeline a fixture file has gone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Source,
+    Staged,
+    Deployed,
+}
+
+/// Builder for an in-memory dotfiles directory and its loaded config.
+#[derive(Debug, Default)]
+pub struct Fixture {
+    config: String,
+    files: Vec<(String, String, Stage)>,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add config TOML, keys before tables (e.g. `vars = ["vars.toml"]` or
+    /// `[filesets.x]`). It goes after `dotfiles_dir` and before the
+    /// `[[files]]` entries.
+    pub fn config(mut self, toml: &str) -> Self {
+        self.config.push_str(toml);
+        self.config.push('\n');
+        self
+    }
+
+    /// Manage `src` with only its source file written.
+    pub fn source(mut self, src: &str, content: &str) -> Self {
+        self.files
+            .push((src.to_string(), content.to_string(), Stage::Source));
+        self
+    }
+
+    /// Manage `src` with matching source, generated, and staged copies.
+    pub fn staged(mut self, src: &str, content: &str) -> Self {
+        self.files
+            .push((src.to_string(), content.to_string(), Stage::Staged));
+        self
+    }
+
+    /// Like [`staged`](Self::staged), and also symlinked from its default
+    /// target (`~/.config/{src}`) and recorded as deployed in state.
+    pub fn deployed(mut self, src: &str, content: &str) -> Self {
+        self.files
+            .push((src.to_string(), content.to_string(), Stage::Deployed));
+        self
+    }
+
+    /// Write everything to a new [`FakeFs`] and load the config.
+    ///
+    /// Panics if the config doesn't load, since that's a bug in the test.
+    pub fn build(self) -> (FakeFs, Config) {
+        let fs = FakeFs::new(HOME);
+        fs.add_dir(DOTFILES);
+        fs.add_dir(format!("{DOTFILES}/.generated"));
+        fs.add_dir(format!("{DOTFILES}/.staged"));
+        fs.add_file(format!("{DOTFILES}/.janus_state.toml"), "");
+
+        // `[[files]]` go last, so `config` can hold both keys and tables
+        let mut toml = format!("dotfiles_dir = \"{DOTFILES}\"\n{}", self.config);
+        for (src, content, stage) in &self.files {
+            toml.push_str(&format!("\n[[files]]\nsrc = \"{src}\"\n"));
+            fs.add_file(format!("{DOTFILES}/{src}"), content.as_str());
+            if *stage != Stage::Source {
+                fs.add_file(format!("{DOTFILES}/.generated/{src}"), content.as_str());
+                fs.add_file(format!("{DOTFILES}/.staged/{src}"), content.as_str());
+            }
+        }
+        fs.add_file(CONFIG_PATH, toml);
+        let config = Config::load(Path::new(CONFIG_PATH), &fs).expect("fixture config should load");
+
+        let mut state = State::load(Path::new(DOTFILES), &fs).expect("fixture state should load");
+        for (src, _, stage) in &self.files {
+            if *stage != Stage::Deployed {
+                continue;
+            }
+            let entry = config
+                .files
+                .iter()
+                .find(|e| e.src == *src)
+                .expect("fixture file");
+            let target = entry.target();
+            fs.add_symlink(expand_tilde(&target, &fs), config.staged_dir(&fs).join(src));
+            state.add_deployed(src.clone(), target);
+        }
+        state.save(&fs).expect("fixture state should save");
+        (fs, config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Fs;
+
+    #[test]
+    fn builds_each_pipeline_stage() {
+        let (fs, config) = Fixture::new()
+            .config("vars = [\"vars.toml\"]\n\n[filesets.desktop]\npatterns = [\"hypr/*\"]")
+            .source("a.conf", "a")
+            .staged("b.conf", "b")
+            .deployed("hypr/hypr.conf", "h")
+            .build();
+
+        assert_eq!(config.files.len(), 3);
+        assert_eq!(config.vars, ["vars.toml"]);
+        assert!(config.filesets.contains_key("desktop"));
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/.staged/a.conf"))));
+        assert!(fs.exists(Path::new(&format!("{DOTFILES}/.staged/b.conf"))));
+
+        let target = Path::new("/home/test/.config/hypr/hypr.conf");
+        assert_eq!(fs.read_to_string(target).unwrap(), "h");
+        let state = State::load(Path::new(DOTFILES), &fs).unwrap();
+        assert!(state.is_deployed("hypr/hypr.conf"));
+        assert!(!state.is_deployed("b.conf"));
+    }
+}
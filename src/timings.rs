@@ -0,0 +1,345 @@
+//! `--timings`: how long each phase of a command took.
+//!
+//! Slow work runs inside a `timing` span (see [`phase`]) naming the phase
+//! and what it worked on, e.g. `render` and a file's `src`, or `secret` and
+//! an engine. With `--timings`, [`layer`] records each span's wall time and
+//! [`Recorder::finish`] sums them into a [`RunTimings`], which is printed and
+//! kept in the state file for `janus stats`.
+//!
+//! The spans are at `trace` level, so the console only shows them at `-vv`.
+//! A phase's time includes any phase nested inside it.
+
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+use tracing::Subscriber;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, EnteredSpan, Id};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::state::unix_now;
+
+/// Name of the spans [`TimingsLayer`] records.
+const SPAN_NAME: &str = "timing";
+
+/// How many of a phase's slowest items to keep.
+const SLOWEST: usize = 5;
+
+/// Time the rest of the enclosing scope as `phase`, working on `detail`.
+pub fn phase(phase: &'static str, detail: &str) -> EnteredSpan {
+    tracing::trace_span!("timing", phase, detail).entered()
+}
+
+/// Timings for one run of a command.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct RunTimings {
+    /// The command that ran, e.g. `generate`.
+    pub command: String,
+    /// When it finished, in seconds since the Unix epoch.
+    pub at: u64,
+    /// Wall time of the whole command, in microseconds.
+    pub total_us: u64,
+    /// Each phase, in the order it first finished.
+    #[serde(default)]
+    pub phases: Vec<PhaseTiming>,
+}
+
+/// Total time spent in one phase.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct PhaseTiming {
+    /// Phase name, e.g. `render`.
+    pub phase: String,
+    /// Summed time, in microseconds.
+    pub total_us: u64,
+    /// How many times the phase ran.
+    pub count: usize,
+    /// The items that took longest, slowest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub slowest: Vec<ItemTiming>,
+}
+
+/// Summed time for one item of a phase, such as one file's render.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ItemTiming {
+    pub item: String,
+    pub total_us: u64,
+}
+
+impl RunTimings {
+    /// Write a report, noting each phase's time in `previous` if it was the
+    /// same command.
+    pub fn write(&self, previous: Option<&RunTimings>, out: &mut impl Write) -> io::Result<()> {
+        let previous = previous.filter(|p| p.command == self.command);
+        writeln!(
+            out,
+            "Timings for `{}`: {}{}",
+            self.command,
+            format_us(self.total_us),
+            last(previous.map(|p| p.total_us))
+        )?;
+        let width = self
+            .phases
+            .iter()
+            .flat_map(|p| {
+                std::iter::once(p.phase.len()).chain(p.slowest.iter().map(|i| i.item.len() + 2))
+            })
+            .max()
+            .unwrap_or(0);
+        for phase in &self.phases {
+            let before = previous
+                .and_then(|p| p.phases.iter().find(|q| q.phase == phase.phase))
+                .map(|p| p.total_us);
+            writeln!(
+                out,
+                "  {:<width$}  {:>8}  {}x{}",
+                phase.phase,
+                format_us(phase.total_us),
+                phase.count,
+                last(before)
+            )?;
+            // A lone item says nothing the phase line doesn't
+            if phase.slowest.len() > 1 || phase.count > 1 {
+                for item in &phase.slowest {
+                    let name = format!("  {}", item.item);
+                    writeln!(out, "  {name:<width$}  {:>8}", format_us(item.total_us))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `"  (last run: 1.20s)"`, or nothing without a previous time.
+fn last(previous: Option<u64>) -> String {
+    previous
+        .map(|us| format!("  (last run: {})", format_us(us)))
+        .unwrap_or_default()
+}
+
+/// Microseconds in the largest unit that keeps the number readable.
+pub fn format_us(us: u64) -> String {
+    if us < 1_000 {
+        format!("{us}µs")
+    } else if us < 1_000_000 {
+        format!("{:.1}ms", us as f64 / 1_000.0)
+    } else {
+        format!("{:.2}s", us as f64 / 1_000_000.0)
+    }
+}
+
+/// Install [`TimingsLayer`] in the subscriber, then call
+/// [`Recorder::finish`] when the command is done.
+pub fn layer() -> (TimingsLayer, Recorder) {
+    let samples = Arc::new(Mutex::new(Vec::new()));
+    (
+        TimingsLayer {
+            samples: samples.clone(),
+        },
+        Recorder {
+            samples,
+            started: Instant::now(),
+        },
+    )
+}
+
+/// One closed `timing` span.
+struct Sample {
+    phase: String,
+    detail: String,
+    us: u64,
+}
+
+/// A [`Layer`] recording the wall time of every `timing` span.
+pub struct TimingsLayer {
+    samples: Arc<Mutex<Vec<Sample>>>,
+}
+
+/// Stored in a span's extensions from creation until it closes.
+#[derive(Default)]
+struct Started {
+    phase: String,
+    detail: String,
+    at: Option<Instant>,
+}
+
+impl Visit for Started {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "phase" => self.phase = value.to_string(),
+            "detail" => self.detail = value.to_string(),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.record_str(field, &format!("{value:?}"));
+    }
+}
+
+impl<S> Layer<S> for TimingsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if attrs.metadata().name() != SPAN_NAME {
+            return;
+        }
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut started = Started::default();
+        attrs.record(&mut started);
+        started.at = Some(Instant::now());
+        span.extensions_mut().insert(started);
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(started) = span.extensions_mut().remove::<Started>() else {
+            return;
+        };
+        let us = started.at.map_or(0, |at| at.elapsed().as_micros() as u64);
+        self.samples
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Sample {
+                phase: started.phase,
+                detail: started.detail,
+                us,
+            });
+    }
+}
+
+/// Reads back what [`TimingsLayer`] recorded.
+pub struct Recorder {
+    samples: Arc<Mutex<Vec<Sample>>>,
+    started: Instant,
+}
+
+impl Recorder {
+    /// Sum everything recorded so far into timings for `command`.
+    pub fn finish(&self, command: &str) -> RunTimings {
+        let samples = self.samples.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut phases: Vec<(PhaseTiming, Vec<ItemTiming>)> = Vec::new();
+        for sample in samples.iter() {
+            let index = match phases.iter().position(|(p, _)| p.phase == sample.phase) {
+                Some(index) => index,
+                None => {
+                    let phase = PhaseTiming {
+                        phase: sample.phase.clone(),
+                        ..PhaseTiming::default()
+                    };
+                    phases.push((phase, Vec::new()));
+                    phases.len() - 1
+                }
+            };
+            let (phase, items) = &mut phases[index];
+            phase.total_us += sample.us;
+            phase.count += 1;
+            if sample.detail.is_empty() {
+                continue;
+            }
+            match items.iter_mut().find(|i| i.item == sample.detail) {
+                Some(item) => item.total_us += sample.us,
+                None => items.push(ItemTiming {
+                    item: sample.detail.clone(),
+                    total_us: sample.us,
+                }),
+            }
+        }
+        RunTimings {
+            command: command.to_string(),
+            at: unix_now(),
+            total_us: self.started.elapsed().as_micros() as u64,
+            phases: phases
+                .into_iter()
+                .map(|(mut phase, mut items)| {
+                    items.sort_by(|a, b| b.total_us.cmp(&a.total_us).then(a.item.cmp(&b.item)));
+                    items.truncate(SLOWEST);
+                    phase.slowest = items;
+                    phase
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn records_phases_in_order_with_slowest_items() {
+        let (layer, recorder) = layer();
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let _outer = phase("vars", "global");
+            for src in ["a.conf", "b.conf", "a.conf"] {
+                let _timing = phase("render", src);
+            }
+        });
+        let timings = recorder.finish("generate");
+        assert_eq!(timings.command, "generate");
+        let phases: Vec<(&str, usize)> = timings
+            .phases
+            .iter()
+            .map(|p| (p.phase.as_str(), p.count))
+            .collect();
+        assert_eq!(phases, [("render", 3), ("vars", 1)]);
+        let items: Vec<&str> = timings.phases[0]
+            .slowest
+            .iter()
+            .map(|i| i.item.as_str())
+            .collect();
+        assert_eq!(items.len(), 2);
+        assert!(items.contains(&"a.conf") && items.contains(&"b.conf"));
+    }
+
+    #[test]
+    fn report_compares_with_same_command_only() {
+        let timings = |command: &str, us: u64| RunTimings {
+            command: command.to_string(),
+            at: 0,
+            total_us: us,
+            phases: vec![PhaseTiming {
+                phase: "secret".to_string(),
+                total_us: us,
+                count: 1,
+                slowest: vec![ItemTiming {
+                    item: "1password".to_string(),
+                    total_us: us,
+                }],
+            }],
+        };
+        let current = timings("generate", 2_500_000);
+
+        let mut out = Vec::new();
+        current
+            .write(Some(&timings("generate", 1_500)), &mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.starts_with("Timings for `generate`: 2.50s  (last run: 1.5ms)\n"),
+            "got: {out}"
+        );
+        assert!(out.contains("secret"), "got: {out}");
+
+        let mut out = Vec::new();
+        current
+            .write(Some(&timings("deploy", 1_500)), &mut out)
+            .unwrap();
+        assert!(!String::from_utf8(out).unwrap().contains("last run"));
+    }
+
+    #[test]
+    fn durations_pick_readable_units() {
+        assert_eq!(format_us(250), "250µs");
+        assert_eq!(format_us(12_345), "12.3ms");
+        assert_eq!(format_us(19_120_000), "19.12s");
+    }
+}
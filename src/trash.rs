@@ -0,0 +1,114 @@
+//! Removals routed through the trash when `use_trash = true`.
+//!
+//! [`remove_file`] is what `clean` and `unimport` call instead of
+//! [`Fs::remove_file`]. With `use_trash` set, the file goes to the trash via
+//! [`Fs::trash`] and is logged in `.janus_trash.toml` in the state
+//! directory, which `janus trash list` and `janus trash restore` read.
+//! Without it, the file is deleted as before.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+use crate::config::Config;
+use crate::platform::Fs;
+
+/// File within the state directory logging what janus trashed.
+pub const TRASH_LOG: &str = ".janus_trash.toml";
+
+/// One file janus moved to the trash.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Trashed {
+    /// Where the file was.
+    pub original: PathBuf,
+    /// Where it is now, under the trash's `files/` directory.
+    pub trashed: PathBuf,
+    /// When it was trashed, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// Everything janus has trashed and not yet restored, oldest first.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct TrashLog {
+    #[serde(default)]
+    pub trashed: Vec<Trashed>,
+}
+
+impl TrashLog {
+    /// Load the log from the state directory. A missing log is empty.
+    pub fn load(state_dir: &Path, fs: &impl Fs) -> Result<Self> {
+        let path = state_dir.join(TRASH_LOG);
+        if !fs.exists(&path) {
+            return Ok(Self::default());
+        }
+        let contents = fs
+            .read_to_string(&path)
+            .with_context(|| format!("Failed to read trash log: {}", path.display()))?;
+        toml::from_str(&contents).context("Failed to parse trash log")
+    }
+
+    /// Write the log back to the state directory.
+    pub fn save(&self, state_dir: &Path, fs: &impl Fs) -> Result<()> {
+        let path = state_dir.join(TRASH_LOG);
+        let contents = toml::to_string_pretty(self).context("Failed to serialize trash log")?;
+        fs.write(&path, contents.as_bytes())
+            .with_context(|| format!("Failed to write trash log: {}", path.display()))
+    }
+}
+
+/// Remove a file, moving it to the trash instead if `use_trash` is set.
+pub fn remove_file(config: &Config, path: &Path, fs: &impl Fs) -> Result<()> {
+    if !config.use_trash {
+        return fs.remove_file(path);
+    }
+    let trashed = fs.trash(path)?;
+    debug!("Trashed {} to {}", path.display(), trashed.display());
+
+    let state_dir = config.state_dir(fs);
+    let mut log = TrashLog::load(&state_dir, fs)?;
+    log.trashed.push(Trashed {
+        original: path.to_path_buf(),
+        trashed,
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    });
+    log.save(&state_dir, fs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn deletes_without_use_trash() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.conf"), "a");
+        let config = write_and_load_config(&fs, &make_config_toml(&[]));
+        let path = PathBuf::from(format!("{DOTFILES}/a.conf"));
+
+        remove_file(&config, &path, &fs).unwrap();
+        assert!(!fs.exists(&path));
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/{TRASH_LOG}"))));
+    }
+
+    #[test]
+    fn trashes_and_logs_with_use_trash() {
+        let fs = setup_fs();
+        fs.add_file(format!("{DOTFILES}/a.conf"), "a");
+        let toml = format!("use_trash = true\n{}", make_config_toml(&[]));
+        let config = write_and_load_config(&fs, &toml);
+        let path = PathBuf::from(format!("{DOTFILES}/a.conf"));
+
+        remove_file(&config, &path, &fs).unwrap();
+        assert!(!fs.exists(&path));
+        let log = TrashLog::load(Path::new(DOTFILES), &fs).unwrap();
+        assert_eq!(log.trashed.len(), 1);
+        assert_eq!(log.trashed[0].original, path);
+        assert_eq!(fs.read_to_string(&log.trashed[0].trashed).unwrap(), "a");
+    }
+}
@@ -0,0 +1,508 @@
+//! Recording of filesystem changes so the last mutating command can be undone.
+//!
+//! [`RecordingFs`] wraps the real filesystem for the duration of a command.
+//! The first time the command is about to change a path (write, copy, remove,
+//! rename, symlink, chmod), the path's current state is snapshotted: file
+//! contents and mode, symlink target, directory, or absence. Afterwards the
+//! snapshots are saved to `.janus_undo/` in the state directory as an
+//! [`UndoRecord`], replacing the previous one, so only the most recent
+//! command can be undone. File contents are copied to `.janus_undo/pending/`
+//! as they're snapshotted rather than held in memory, and moved in beside
+//! the record when it's saved. Config and state files are ordinary writes, so they
+//! are covered too.
+//!
+//! Each change also records a fingerprint of the path as the command left it,
+//! so `janus undo` can tell when a path was modified again afterwards.
+//!
+//! Not recorded: directories created along the way (they're left in place),
+//! the contents of a directory that was trashed (undo recreates it empty;
+//! `janus trash restore` brings the contents back), and anything outside the
+//! filesystem, such as reload commands or systemd units that were started.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+use crate::platform::{DirEntry, FileStamp, Fs, WalkOptions, Xattrs};
+use crate::state::content_hash;
+
+/// Directory within the state directory holding the undo record.
+pub const UNDO_DIR: &str = ".janus_undo";
+
+/// File name of the record within [`UNDO_DIR`]. Snapshotted file contents
+/// sit next to it, named by index.
+const RECORD_FILE: &str = "record.toml";
+
+/// Directory within [`UNDO_DIR`] holding the snapshots of a command still
+/// running, until [`RecordingFs::save`] moves them next to the record.
+const PENDING_DIR: &str = "pending";
+
+/// What a path looked like before the command changed it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Before {
+    /// Nothing existed at the path.
+    Absent,
+    /// A directory.
+    Dir,
+    /// A symlink pointing at `target`.
+    Symlink { target: PathBuf },
+    /// A regular file, whose contents are saved as `blob` in the undo dir.
+    File { blob: String, mode: u32 },
+}
+
+/// One path changed by the recorded command.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Change {
+    pub path: PathBuf,
+    pub before: Before,
+    /// [`fingerprint`] of the path right after the command finished.
+    pub after: String,
+}
+
+/// Everything needed to revert the most recent mutating command.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UndoRecord {
+    /// The command line that made the changes (e.g. `janus deploy --all`).
+    pub command: String,
+    /// When the command finished, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Changed paths, in the order they were first touched.
+    #[serde(default)]
+    pub changes: Vec<Change>,
+}
+
+impl UndoRecord {
+    /// Load the record from the state directory, if there is one.
+    pub fn load(state_dir: &Path, fs: &impl Fs) -> Result<Option<Self>> {
+        let path = state_dir.join(UNDO_DIR).join(RECORD_FILE);
+        if !fs.exists(&path) {
+            return Ok(None);
+        }
+        let contents = fs
+            .read_to_string(&path)
+            .with_context(|| format!("Failed to read undo record: {}", path.display()))?;
+        let record = toml::from_str(&contents).context("Failed to parse undo record")?;
+        Ok(Some(record))
+    }
+
+    /// Read a snapshotted file's contents from the undo dir.
+    pub fn blob(state_dir: &Path, blob: &str, fs: &impl Fs) -> Result<Vec<u8>> {
+        let path = state_dir.join(UNDO_DIR).join(blob);
+        fs.read(&path)
+            .with_context(|| format!("Failed to read undo snapshot: {}", path.display()))
+    }
+
+    /// Delete the record and its snapshots, including any left pending by
+    /// a command that stopped before saving.
+    pub fn clear(state_dir: &Path, fs: &impl Fs) -> Result<()> {
+        let undo_dir = state_dir.join(UNDO_DIR);
+        remove_pending(&undo_dir, fs)?;
+        remove_files(&undo_dir, fs)
+    }
+}
+
+/// Remove the regular files directly inside `dir`, if it exists.
+fn remove_files(dir: &Path, fs: &impl Fs) -> Result<()> {
+    if !fs.exists(dir) {
+        return Ok(());
+    }
+    let opts = WalkOptions {
+        min_depth: 1,
+        max_depth: Some(1),
+        ..Default::default()
+    };
+    for DirEntry { path, is_dir, .. } in fs.walk_dir(dir, &opts)? {
+        if !is_dir {
+            fs.remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove the pending snapshots in `undo_dir` and their directory.
+fn remove_pending(undo_dir: &Path, fs: &impl Fs) -> Result<()> {
+    let pending = undo_dir.join(PENDING_DIR);
+    if !fs.exists(&pending) {
+        return Ok(());
+    }
+    remove_files(&pending, fs)?;
+    fs.remove_dir(&pending)
+        .with_context(|| format!("Failed to remove {}", pending.display()))
+}
+
+/// Summarize a path's current state: `absent`, `dir`, `symlink:<target>`, or
+/// `file:<content hash>`.
+pub fn fingerprint(path: &Path, fs: &impl Fs) -> String {
+    if fs.is_symlink(path) {
+        return match fs.read_link(path) {
+            Ok(target) => format!("symlink:{}", target.display()),
+            Err(_) => "symlink:?".to_string(),
+        };
+    }
+    if fs.is_dir(path) {
+        return "dir".to_string();
+    }
+    if !fs.exists(path) {
+        return "absent".to_string();
+    }
+    match fs.read(path) {
+        Ok(content) => format!("file:{}", content_hash(&content)),
+        Err(_) => "file:?".to_string(),
+    }
+}
+
+/// An [`Fs`] that snapshots each path before its first modification.
+///
+/// Reads pass straight through. Paths inside the undo dir itself are never
+/// recorded.
+pub struct RecordingFs<'a, F: Fs> {
+    inner: &'a F,
+    undo_dir: PathBuf,
+    seen: RefCell<HashSet<PathBuf>>,
+    changes: RefCell<Vec<(PathBuf, Before)>>,
+    /// Number of file snapshots copied to the pending dir so far.
+    blobs: Cell<usize>,
+}
+
+impl<'a, F: Fs> RecordingFs<'a, F> {
+    pub fn new(inner: &'a F, state_dir: &Path) -> Self {
+        Self {
+            inner,
+            undo_dir: state_dir.join(UNDO_DIR),
+            seen: RefCell::new(HashSet::new()),
+            changes: RefCell::new(Vec::new()),
+            blobs: Cell::new(0),
+        }
+    }
+
+    /// Snapshot `path` unless it was already snapshotted by this command.
+    fn snapshot(&self, path: &Path) {
+        if path.starts_with(&self.undo_dir) || !self.seen.borrow_mut().insert(path.to_path_buf()) {
+            return;
+        }
+        let fs = self.inner;
+        let before = if fs.is_symlink(path) {
+            match fs.read_link(path) {
+                Ok(target) => Before::Symlink { target },
+                Err(e) => {
+                    warn!("Can't record {} for undo: {e:#}", path.display());
+                    return;
+                }
+            }
+        } else if fs.is_dir(path) {
+            Before::Dir
+        } else if fs.exists(path) {
+            match self.copy_blob(path) {
+                Ok(blob) => Before::File {
+                    blob,
+                    mode: fs.file_mode(path).unwrap_or(0o644),
+                },
+                Err(e) => {
+                    warn!("Can't record {} for undo: {e:#}", path.display());
+                    return;
+                }
+            }
+        } else {
+            Before::Absent
+        };
+        self.changes.borrow_mut().push((path.to_path_buf(), before));
+    }
+
+    /// Copy the file at `path` into the pending dir, returning its blob name.
+    fn copy_blob(&self, path: &Path) -> Result<String> {
+        let fs = self.inner;
+        let pending = self.undo_dir.join(PENDING_DIR);
+        let index = self.blobs.get();
+        if index == 0 {
+            // Whatever is there was left by a command that never saved
+            remove_pending(&self.undo_dir, fs)?;
+            fs.create_dir_all(&pending)
+                .with_context(|| format!("Failed to create {}", pending.display()))?;
+        }
+        let blob = index.to_string();
+        fs.copy_streaming(path, &pending.join(&blob))
+            .context("Failed to save undo snapshot")?;
+        self.blobs.set(index + 1);
+        Ok(blob)
+    }
+
+    /// Save the recorded changes as the undo record for `command`, replacing
+    /// the previous record. A command that changed nothing leaves the
+    /// previous record in place.
+    pub fn save(&self, command: &str) -> Result<()> {
+        let changes = self.changes.borrow();
+        let fs = self.inner;
+        if changes.is_empty() {
+            debug!("No changes to record for undo");
+            return remove_pending(&self.undo_dir, fs);
+        }
+        fs.create_dir_all(&self.undo_dir)
+            .with_context(|| format!("Failed to create {}", self.undo_dir.display()))?;
+        // The previous record's files only; this command's are still pending
+        remove_files(&self.undo_dir, fs)?;
+        let pending = self.undo_dir.join(PENDING_DIR);
+        for i in 0..self.blobs.get() {
+            let blob = i.to_string();
+            fs.rename(&pending.join(&blob), &self.undo_dir.join(&blob))
+                .context("Failed to save undo snapshot")?;
+        }
+        remove_pending(&self.undo_dir, fs)?;
+        let record = UndoRecord {
+            command: command.to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            changes: changes
+                .iter()
+                .map(|(path, before)| Change {
+                    path: path.clone(),
+                    before: before.clone(),
+                    after: fingerprint(path, fs),
+                })
+                .collect(),
+        };
+        let contents =
+            toml::to_string_pretty(&record).context("Failed to serialize undo record")?;
+        fs.write(&self.undo_dir.join(RECORD_FILE), contents.as_bytes())
+            .context("Failed to write undo record")
+    }
+}
+
+impl<F: Fs> Fs for RecordingFs<'_, F> {
+    fn read_to_string(&self, path: &Path) -> Result<String> {
+        self.inner.read_to_string(path)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.inner.read(path)
+    }
+
+    fn hash_file(&self, path: &Path) -> Result<String> {
+        self.inner.hash_file(path)
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        self.snapshot(path);
+        self.inner.write(path, contents)
+    }
+
+    fn append(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        self.snapshot(path);
+        self.inner.append(path, contents)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.snapshot(to);
+        self.inner.copy(from, to)
+    }
+
+    fn copy_streaming(&self, from: &Path, to: &Path) -> Result<()> {
+        self.snapshot(to);
+        self.inner.copy_streaming(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        self.snapshot(path);
+        self.inner.remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<()> {
+        self.snapshot(path);
+        self.inner.remove_dir(path)
+    }
+
+    fn trash(&self, path: &Path) -> Result<PathBuf> {
+        self.snapshot(path);
+        self.inner.trash(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.snapshot(from);
+        self.snapshot(to);
+        self.inner.rename(from, to)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        self.inner.create_dir_all(path)
+    }
+
+    fn file_mode(&self, path: &Path) -> Result<u32> {
+        self.inner.file_mode(path)
+    }
+
+    fn set_file_mode(&self, path: &Path, mode: u32) -> Result<()> {
+        self.snapshot(path);
+        self.inner.set_file_mode(path, mode)
+    }
+
+    fn stamp(&self, path: &Path) -> Result<FileStamp> {
+        self.inner.stamp(path)
+    }
+
+    fn xattrs(&self, path: &Path) -> Result<Xattrs> {
+        self.inner.xattrs(path)
+    }
+
+    fn set_xattrs(&self, path: &Path, attrs: &Xattrs) -> Result<()> {
+        self.snapshot(path);
+        self.inner.set_xattrs(path, attrs)
+    }
+
+    fn symlink(&self, original: &Path, link: &Path) -> Result<()> {
+        self.snapshot(link);
+        self.inner.symlink(original, link)
+    }
+
+    fn hard_link(&self, original: &Path, link: &Path) -> Result<()> {
+        self.snapshot(link);
+        self.inner.hard_link(original, link)
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf> {
+        self.inner.read_link(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        self.inner.canonicalize(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.inner.exists(path)
+    }
+
+    fn is_symlink(&self, path: &Path) -> bool {
+        self.inner.is_symlink(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.inner.is_file(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.inner.is_dir(path)
+    }
+
+    fn walk_dir(&self, path: &Path, opts: &WalkOptions) -> Result<Vec<DirEntry>> {
+        self.inner.walk_dir(path, opts)
+    }
+
+    fn home_dir(&self) -> Option<PathBuf> {
+        self.inner.home_dir()
+    }
+
+    fn config_dir(&self) -> Option<PathBuf> {
+        self.inner.config_dir()
+    }
+
+    fn state_dir(&self) -> Option<PathBuf> {
+        self.inner.state_dir()
+    }
+
+    fn cache_dir(&self) -> Option<PathBuf> {
+        self.inner.cache_dir()
+    }
+
+    fn hostname(&self) -> Option<String> {
+        self.inner.hostname()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn records_first_state_only() {
+        let fs = setup_fs();
+        fs.add_file(format!("{HOME}/a"), "original");
+        let recording = RecordingFs::new(&fs, Path::new(DOTFILES));
+        recording
+            .write(Path::new(&format!("{HOME}/a")), b"one")
+            .unwrap();
+        recording
+            .write(Path::new(&format!("{HOME}/a")), b"two")
+            .unwrap();
+        recording
+            .write(Path::new(&format!("{HOME}/b")), b"new")
+            .unwrap();
+        recording.save("janus test").unwrap();
+
+        let record = UndoRecord::load(Path::new(DOTFILES), &fs).unwrap().unwrap();
+        assert_eq!(record.command, "janus test");
+        assert_eq!(record.changes.len(), 2);
+        let Before::File { blob, .. } = &record.changes[0].before else {
+            panic!("expected file, got {:?}", record.changes[0].before);
+        };
+        assert_eq!(
+            UndoRecord::blob(Path::new(DOTFILES), blob, &fs).unwrap(),
+            b"original"
+        );
+        assert_eq!(
+            record.changes[0].after,
+            format!("file:{}", content_hash(b"two"))
+        );
+        assert_eq!(record.changes[1].before, Before::Absent);
+    }
+
+    #[test]
+    fn snapshots_are_written_out_as_taken() {
+        let fs = setup_fs();
+        fs.add_file(format!("{HOME}/a"), "original");
+        let recording = RecordingFs::new(&fs, Path::new(DOTFILES));
+        recording
+            .write(Path::new(&format!("{HOME}/a")), b"changed")
+            .unwrap();
+        let pending = format!("{DOTFILES}/{UNDO_DIR}/{PENDING_DIR}/0");
+        assert_eq!(fs.read_to_string(Path::new(&pending)).unwrap(), "original");
+
+        recording.save("janus test").unwrap();
+        assert!(!fs.exists(Path::new(&format!("{DOTFILES}/{UNDO_DIR}/{PENDING_DIR}"))));
+        assert_eq!(
+            UndoRecord::blob(Path::new(DOTFILES), "0", &fs).unwrap(),
+            b"original"
+        );
+    }
+
+    #[test]
+    fn records_symlink_target() {
+        let fs = setup_fs();
+        fs.add_symlink(format!("{HOME}/link"), "/somewhere");
+        let recording = RecordingFs::new(&fs, Path::new(DOTFILES));
+        recording
+            .remove_file(Path::new(&format!("{HOME}/link")))
+            .unwrap();
+        recording.save("janus test").unwrap();
+
+        let record = UndoRecord::load(Path::new(DOTFILES), &fs).unwrap().unwrap();
+        assert_eq!(
+            record.changes[0].before,
+            Before::Symlink {
+                target: PathBuf::from("/somewhere")
+            }
+        );
+        assert_eq!(record.changes[0].after, "absent");
+    }
+
+    #[test]
+    fn no_changes_keeps_previous_record() {
+        let fs = setup_fs();
+        let recording = RecordingFs::new(&fs, Path::new(DOTFILES));
+        recording
+            .write(Path::new(&format!("{HOME}/a")), b"x")
+            .unwrap();
+        recording.save("janus first").unwrap();
+
+        let recording = RecordingFs::new(&fs, Path::new(DOTFILES));
+        recording.save("janus second").unwrap();
+        let record = UndoRecord::load(Path::new(DOTFILES), &fs).unwrap().unwrap();
+        assert_eq!(record.command, "janus first");
+    }
+}
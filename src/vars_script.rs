@@ -0,0 +1,161 @@
+//! Derived template variables computed by a Rhai script.
+//!
+//! `vars_script = "vars.rhai"` runs once per generate, after the global
+//! `vars` files are loaded. The script sees those vars as a read-only `vars`
+//! map and must evaluate to a map (`#{ ... }`), which is merged on top of
+//! them. Fileset and per-file vars still override the result. `env(name)`
+//! returns an environment variable as a string, or `()` if it's unset
+//! (`env(name, default)` returns `default` instead), so values such as
+//! `xrandr` output can be passed in from the shell.
+//!
+//! Scripts can't run commands or touch files, and are capped at
+//! `MAX_OPERATIONS` so a runaway loop fails instead of hanging generate.
+//! Requires the `vars-script` feature (on by default).
+
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Upper bound on script operations before evaluation is aborted.
+#[cfg(feature = "vars-script")]
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Evaluate `source` with `vars` in scope and return the variables it
+/// produces. `name` is only used in error messages.
+#[cfg(feature = "vars-script")]
+pub fn eval(
+    name: &str,
+    source: &str,
+    vars: &HashMap<String, toml::Value>,
+) -> Result<HashMap<String, toml::Value>> {
+    use anyhow::{Context, anyhow, bail};
+    use rhai::{Dynamic, Engine, Scope};
+
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    // Constant folding inlines `vars`, and rhai then panics on assignments
+    // to it instead of reporting them
+    engine.set_optimization_level(rhai::OptimizationLevel::None);
+    engine.register_fn("env", |key: &str| -> Dynamic {
+        std::env::var(key)
+            .map(Dynamic::from)
+            .unwrap_or(Dynamic::UNIT)
+    });
+    engine.register_fn("env", |key: &str, default: &str| -> String {
+        std::env::var(key).unwrap_or_else(|_| default.to_string())
+    });
+
+    let vars_map: Dynamic = rhai::serde::to_dynamic(vars)
+        .map_err(|e| anyhow!("{e}"))
+        .with_context(|| format!("Failed to pass vars to {name}"))?;
+    let mut scope = Scope::new();
+    scope.push_constant("vars", vars_map);
+
+    let result: Dynamic = engine
+        .eval_with_scope(&mut scope, source)
+        .map_err(|e| anyhow!("{e}"))
+        .with_context(|| format!("Failed to run vars script: {name}"))?;
+    if !result.is_map() {
+        bail!(
+            "Vars script {name} must evaluate to a map (#{{ ... }}), got {}",
+            result.type_name()
+        );
+    }
+    rhai::serde::from_dynamic(&result)
+        .map_err(|e| anyhow!("{e}"))
+        .with_context(|| format!("Vars script {name} returned a value TOML can't represent"))
+}
+
+/// Without the `vars-script` feature, configuring a script is an error.
+#[cfg(not(feature = "vars-script"))]
+pub fn eval(
+    name: &str,
+    _source: &str,
+    _vars: &HashMap<String, toml::Value>,
+) -> Result<HashMap<String, toml::Value>> {
+    anyhow::bail!("vars_script {name} requires janus built with the `vars-script` feature")
+}
+
+#[cfg(all(test, feature = "vars-script"))]
+mod tests {
+    use super::*;
+
+    fn vars(toml_str: &str) -> HashMap<String, toml::Value> {
+        toml::from_str(toml_str).unwrap()
+    }
+
+    #[test]
+    fn derives_from_vars() {
+        let input = vars("dpi = 192\nbase_font = 11");
+        let source = r#"
+            let scale = if vars.dpi > 144 { 2 } else { 1 };
+            #{ scale: scale, font_size: vars.base_font * scale, name: "hidpi" }
+        "#;
+        let out = eval("vars.rhai", source, &input).unwrap();
+        assert_eq!(out["scale"], toml::Value::Integer(2));
+        assert_eq!(out["font_size"], toml::Value::Integer(22));
+        assert_eq!(out["name"], toml::Value::String("hidpi".to_string()));
+    }
+
+    #[test]
+    fn nested_values_roundtrip() {
+        let out = eval(
+            "vars.rhai",
+            "#{ sizes: [1, 2], bar: #{ height: 24 } }",
+            &vars(""),
+        )
+        .unwrap();
+        let expected = vars("sizes = [1, 2]\nbar = { height = 24 }");
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn non_map_result_errors() {
+        let result = eval("vars.rhai", "42", &vars(""));
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(msg.contains("must evaluate to a map"), "got: {msg}");
+    }
+
+    #[test]
+    fn script_error_names_script() {
+        let result = eval("vars.rhai", "vars.missing.nope", &vars(""));
+        let msg = format!("{:#}", result.unwrap_err());
+        assert!(
+            msg.contains("Failed to run vars script: vars.rhai"),
+            "got: {msg}"
+        );
+    }
+
+    #[test]
+    fn runaway_loop_aborted() {
+        let result = eval("vars.rhai", "loop {}", &vars(""));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn vars_are_read_only() {
+        let result = eval("vars.rhai", "vars.dpi = 1; #{}", &vars("dpi = 2"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn env_unset_is_unit() {
+        let out = eval(
+            "vars.rhai",
+            r#"#{ missing: type_of(env("JANUS_TEST_SURELY_UNSET")) == "()" }"#,
+            &vars(""),
+        )
+        .unwrap();
+        assert_eq!(out["missing"], toml::Value::Boolean(true));
+    }
+
+    #[test]
+    fn env_default() {
+        let out = eval(
+            "vars.rhai",
+            r#"#{ monitors: parse_int(env("JANUS_TEST_SURELY_UNSET", "1")) }"#,
+            &vars(""),
+        )
+        .unwrap();
+        assert_eq!(out["monitors"], toml::Value::Integer(1));
+    }
+}
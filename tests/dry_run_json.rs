@@ -0,0 +1,85 @@
+//! `--dry-run --format json` run as a real process: stdout must hold the
+//! plan and nothing else, whatever the op prints along the way.
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// A temporary home directory, removed on drop.
+struct TempHome(PathBuf);
+
+impl TempHome {
+    fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("janus-dry-run-json-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("create temp dir");
+        Self(std::fs::canonicalize(&path).expect("resolve temp dir"))
+    }
+
+    fn write(&self, relative: &str, contents: &str) {
+        let path = self.0.join(relative);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    fn janus(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_janus"))
+            .args(args)
+            .env("HOME", &self.0)
+            .env("XDG_CONFIG_HOME", self.0.join(".config"))
+            .env_remove("JANUS_READONLY")
+            .output()
+            .expect("run janus")
+    }
+}
+
+impl Drop for TempHome {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn setup(home: &TempHome) {
+    let dotfiles = home.0.join("dotfiles");
+    home.write(
+        ".config/janus/config.toml",
+        &format!(
+            "dotfiles_dir = \"{}\"\nvars = [\"vars.toml\"]\n\n[[files]]\nsrc = \"a.conf\"\n",
+            dotfiles.display()
+        ),
+    );
+    home.write("dotfiles/vars.toml", "x = 1\n");
+    home.write("dotfiles/a.conf", "hello {{ x }}\n");
+}
+
+fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "janus failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn apply_plan_is_the_whole_of_stdout() {
+    let home = TempHome::new("apply");
+    setup(&home);
+    assert_success(&home.janus(&["-q", "apply", "--all"]));
+    home.write("dotfiles/a.conf", "bye {{ x }}\n");
+
+    let output = home.janus(&["--dry-run", "--format", "json", "apply", "--all"]);
+    assert_success(&output);
+    let plan: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap_or_else(|e| {
+        panic!(
+            "stdout is not one JSON document ({e}): {}",
+            String::from_utf8_lossy(&output.stdout)
+        )
+    });
+    assert_eq!(plan["dry_run"], true);
+    assert_eq!(plan["actions"][0]["type"], "generate");
+    // The human-readable summary still reaches the user
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Apply summary (dry run)"), "got: {stderr}");
+    let generated = std::fs::read_to_string(home.0.join("dotfiles/.generated/a.conf")).unwrap();
+    assert_eq!(generated, "hello 1\n");
+}